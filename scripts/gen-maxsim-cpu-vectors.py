@@ -0,0 +1,99 @@
+#!/usr/bin/env python3
+"""
+Generate the MaxSim vectors shared with maxsim-cpu (src/rust/testdata/maxsim_cpu_vectors.txt)
+
+Every embedding value is an integer k in [-64, 64] standing for k / 64, in dimension 8,
+with at most 6 query tokens. Every product, dot product, maximum and query-token sum
+is then a multiple of 1/4096 below 2^6 in magnitude, exactly representable in f32:
+any correct f32 MaxSim - maxsim-cpu's BLAS path, the browser's SIMD kernels - returns
+these scores bit for bit, whatever its summation order or length grouping.
+
+    python3 scripts/gen-maxsim-cpu-vectors.py          # rewrite the vectors
+    python3 scripts/gen-maxsim-cpu-vectors.py --check  # compare with maxsim_cpu
+"""
+
+import sys
+from pathlib import Path
+
+OUT = Path(__file__).resolve().parent.parent / "src" / "rust" / "testdata" / "maxsim_cpu_vectors.txt"
+SCALE = 64
+DIM = 8
+
+
+class Lcg:
+    """64-bit LCG (Knuth's MMIX constants), so the vectors don't depend on Python's RNG"""
+
+    def __init__(self, seed):
+        self.state = seed
+
+    def next(self, n):
+        self.state = (self.state * 6364136223846793005 + 1442695040888963407) % 2**64
+        return (self.state >> 33) % n
+
+    def values(self, count):
+        return [self.next(2 * SCALE + 1) - SCALE for _ in range(count)]
+
+
+def exact_scores(query, docs, doc_lens):
+    """Σ_q max_d ⟨q, d⟩ in integer units of 1/SCALE², as floats"""
+    tokens = [query[i:i + DIM] for i in range(0, len(query), DIM)]
+    scores, offset = [], 0
+    for length in doc_lens:
+        doc = [docs[(offset + t) * DIM:(offset + t + 1) * DIM] for t in range(length)]
+        offset += length
+        total = sum(max(sum(a * b for a, b in zip(q, d)) for d in doc) for q in tokens)
+        scores.append(total / SCALE**2)
+    return scores
+
+
+def cases():
+    rng = Lcg(2035)
+    # (name, maxsim-cpu function, query tokens, document lengths)
+    specs = [
+        ("uniform", "maxsim_scores", 6, [10] * 50),
+        ("near_uniform", "maxsim_scores_variable", 5, [10 + rng.next(3) for _ in range(60)]),
+        ("grouped", "maxsim_scores_variable", 4, [1 + rng.next(40) for _ in range(30)]),
+    ]
+    for name, function, q_len, doc_lens in specs:
+        query = rng.values(q_len * DIM)
+        docs = rng.values(sum(doc_lens) * DIM)
+        yield name, function, q_len, doc_lens, query, docs, exact_scores(query, docs, doc_lens)
+
+
+def write():
+    lines = [
+        "# MaxSim vectors shared with maxsim-cpu, generated by scripts/gen-maxsim-cpu-vectors.py",
+        f"# Embedding values are integers k standing for k / {SCALE}; scores are exact",
+    ]
+    for name, function, q_len, doc_lens, query, docs, scores in cases():
+        lines += [
+            f"case {name} {function} dim={DIM} q_len={q_len}",
+            "doc_lens " + " ".join(map(str, doc_lens)),
+            "query " + " ".join(map(str, query)),
+            "docs " + " ".join(map(str, docs)),
+            "scores " + " ".join(repr(score) for score in scores),
+        ]
+    OUT.parent.mkdir(parents=True, exist_ok=True)
+    OUT.write_text("\n".join(lines) + "\n")
+    print(f"Wrote {OUT}")
+
+
+def check():
+    import numpy as np
+    import maxsim_cpu
+
+    for name, function, q_len, doc_lens, query, docs, scores in cases():
+        q = np.array(query, dtype=np.float32).reshape(q_len, DIM) / SCALE
+        flat = np.array(docs, dtype=np.float32).reshape(-1, DIM) / SCALE
+        if function == "maxsim_scores":
+            got = maxsim_cpu.maxsim_scores(q, flat.reshape(len(doc_lens), doc_lens[0], DIM))
+        else:
+            splits = np.cumsum(doc_lens)[:-1]
+            got = maxsim_cpu.maxsim_scores_variable(q, list(np.split(flat, splits)))
+        if list(np.asarray(got, dtype=np.float32)) != [np.float32(s) for s in scores]:
+            sys.exit(f"{name}: maxsim_cpu disagrees with the shared vectors")
+        print(f"{name}: {len(scores)} scores match maxsim_cpu")
+
+
+if __name__ == "__main__":
+    check() if "--check" in sys.argv else write()
@@ -0,0 +1,55 @@
+#!/usr/bin/env node
+
+/**
+ * WASM Kernel Test Script
+ *
+ * Runs the maxsim-core unit tests compiled for wasm32 (+simd128), so the SIMD
+ * kernels that `cargo test` never reaches natively (specialized dot products, the
+ * generic tail loops, packed and int4 kernels) are exercised under Node.js.
+ *
+ * maxsim-core is no_std without imports, so the libtest binary instantiates with
+ * an empty import object. Test output is discarded on wasm32-unknown-unknown: a
+ * failing test aborts, which surfaces here as a trap.
+ */
+
+import { execFileSync } from 'child_process';
+import { readFileSync } from 'fs';
+import { join, dirname } from 'path';
+import { fileURLToPath } from 'url';
+
+const __dirname = dirname(fileURLToPath(import.meta.url));
+const crateRoot = join(__dirname, '..', 'src', 'rust');
+
+const output = execFileSync(
+  'cargo',
+  ['test', '-p', 'maxsim-core', '--target', 'wasm32-unknown-unknown', '--no-run', '--message-format=json'],
+  {
+    cwd: crateRoot,
+    env: { ...process.env, RUSTFLAGS: '-C target-feature=+simd128' },
+    encoding: 'utf8',
+    stdio: ['ignore', 'pipe', 'inherit'],
+  }
+);
+
+const binaries = output
+  .split('\n')
+  .filter(Boolean)
+  .map((line) => JSON.parse(line))
+  .filter((message) => message.reason === 'compiler-artifact' && message.profile?.test && message.executable)
+  .map((message) => message.executable);
+
+if (binaries.length === 0) {
+  console.error('❌ No maxsim-core test binary was built');
+  process.exit(1);
+}
+
+for (const binary of binaries) {
+  const { instance } = await WebAssembly.instantiate(readFileSync(binary), {});
+  try {
+    instance.exports.main(0, 0);
+  } catch (error) {
+    console.error(`❌ ${binary}: a test failed (${error.message})`);
+    process.exit(1);
+  }
+  console.log(`✅ ${binary}`);
+}
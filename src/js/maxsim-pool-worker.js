@@ -0,0 +1,27 @@
+/**
+ * MaxSim pool worker
+ *
+ * Worker side of the `MaxSimPool` exported by the WASM module: holds one shard
+ * of the corpus and answers the pool's load/search messages.
+ *
+ * Usage (main thread):
+ *   const workers = Array.from({ length: 4 },
+ *     () => new Worker(new URL('./maxsim-pool-worker.js', import.meta.url), { type: 'module' }));
+ *   const pool = new MaxSimPool(workers);
+ */
+
+import init, { MaxSimWasm, handle_pool_message } from '../../dist/wasm/maxsim_web_wasm.js';
+
+const ready = init().then(() => new MaxSimWasm());
+
+self.onmessage = async function(e) {
+  let engine;
+  try {
+    engine = await ready;
+  } catch (error) {
+    // The pool matches replies by id; an unanswered call would never settle
+    self.postMessage({ id: e.data?.id, error: String(error) });
+    return;
+  }
+  self.postMessage(handle_pool_message(engine, e.data));
+};
@@ -412,8 +412,9 @@ export class MaxSimWasm {
     }
 
     /**
-     * ULTIMATE PERFORMANCE: Direct memory access with zero-copy operations
-     * This is the fastest possible implementation
+     * ULTIMATE PERFORMANCE: Scores from buffers owned by WASM
+     * Query and documents are copied into persistent WASM buffers
+     * (alloc_buffer/write_buffer), so scoring reads them in place
      */
     maxsimBatchHyperOptimized(queryEmbedding, docEmbeddings) {
         if (!this.isInitialized) {
@@ -422,67 +423,54 @@ export class MaxSimWasm {
 
         const queryTokens = queryEmbedding.length;
         const embeddingDim = queryEmbedding[0].length;
-        const numDocs = docEmbeddings.length;
 
-        // Calculate total memory needed
-        const querySize = queryTokens * embeddingDim;
         const docTokenCounts = docEmbeddings.map(doc => doc.length);
         const totalDocSize = docTokenCounts.reduce((sum, count) => sum + count * embeddingDim, 0);
 
-        // Allocate WASM linear memory directly
-        const totalFloats = querySize + totalDocSize;
-        const memory = new Float32Array(totalFloats);
-        const docTokensArray = new Uint32Array(docTokenCounts);
+        const queryBuffer = this.ensureWasmBuffer('query', queryTokens * embeddingDim);
+        const docBuffer = this.ensureWasmBuffer('docs', totalDocSize);
 
-        // Pack data directly into WASM memory with optimal layout
+        // Pack query and documents, then copy each into its WASM buffer in one call
+        const { queryFlat } = this.flattenEmbedding(queryEmbedding);
+        const docFlat = new Float32Array(totalDocSize);
         let offset = 0;
-
-        // Pack query with cache-friendly layout
-        for (let q = 0; q < queryTokens; q++) {
-            const token = queryEmbedding[q];
-            if (token instanceof Float32Array) {
-                memory.set(token, offset);
-            } else {
-                for (let d = 0; d < embeddingDim; d++) {
-                    memory[offset + d] = token[d];
-                }
-            }
-            offset += embeddingDim;
-        }
-
-        const docStartOffset = offset;
-
-        // Pack documents with optimal memory layout
         for (const doc of docEmbeddings) {
             for (const token of doc) {
-                if (token instanceof Float32Array) {
-                    memory.set(token, offset);
-                } else {
-                    for (let d = 0; d < embeddingDim; d++) {
-                        memory[offset + d] = token[d];
-                    }
-                }
+                docFlat.set(token, offset);
                 offset += embeddingDim;
             }
         }
+        this.wasmInstance.write_buffer(queryBuffer, 0, queryFlat);
+        this.wasmInstance.write_buffer(docBuffer, 0, docFlat);
 
-        // Get raw pointers to WASM linear memory
-        const queryPtr = memory.subarray(0, querySize);
-        const docPtr = memory.subarray(docStartOffset);
-
-        // Call hyper-optimized WASM function with direct memory access
-        const scores = this.wasmInstance.maxsim_batch_zero_copy(
-            queryPtr.byteOffset,
+        const scores = this.wasmInstance.maxsim_batch_buffers(
+            queryBuffer,
             queryTokens,
-            docPtr.byteOffset,
-            docTokensArray.byteOffset,
-            numDocs,
+            docBuffer,
+            new Uint32Array(docTokenCounts),
             embeddingDim
         );
 
         return new Float32Array(scores);
     }
 
+    /**
+     * Persistent WASM buffer handle with room for `floats` floats (grown as needed)
+     */
+    ensureWasmBuffer(name, floats) {
+        this.wasmBuffers = this.wasmBuffers || {};
+        const current = this.wasmBuffers[name];
+        if (current && current.floats >= floats) {
+            return current.handle;
+        }
+        if (current) {
+            this.wasmInstance.free_buffer(current.handle);
+        }
+        const capacity = Math.max(floats, current ? current.floats * 2 : 0);
+        this.wasmBuffers[name] = { handle: this.wasmInstance.alloc_buffer(capacity), floats: capacity };
+        return this.wasmBuffers[name].handle;
+    }
+
     /**
      * Ultra-fast batch processing with zero-allocation persistent buffers
      * This eliminates all memory allocation overhead
@@ -0,0 +1,56 @@
+//! Adaptive batching must score every document like `maxsim_single`
+//!
+//! Inputs pick the shape (dimension, query length, document lengths including empty
+//! ones) and raw f32 bit patterns for the values, so zeros, subnormals and extreme
+//! magnitudes all show up. Non-finite inputs are skipped: NaN propagation is allowed
+//! to depend on the reduction order.
+
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use maxsim_web_wasm::MaxSimWasm;
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    dim: u8,
+    query_tokens: u8,
+    doc_tokens: Vec<u8>,
+    values: Vec<u32>,
+}
+
+fuzz_target!(|input: Input| {
+    let dim = 1 + input.dim as usize % 130;
+    let query_tokens = 1 + input.query_tokens as usize % 32;
+    let doc_tokens: Vec<usize> = input.doc_tokens.iter().take(256).map(|&len| len as usize).collect();
+    let total = (query_tokens + doc_tokens.iter().sum::<usize>()) * dim;
+    if input.values.is_empty() {
+        return;
+    }
+    // Values repeat when the input is shorter than the batch
+    let values: Vec<f32> = input.values.iter().cycle().take(total).map(|&bits| f32::from_bits(bits)).collect();
+    if values.iter().any(|v| !v.is_finite()) {
+        return;
+    }
+    let (query, docs) = values.split_at(query_tokens * dim);
+
+    let maxsim = MaxSimWasm::new();
+    let scores = maxsim.maxsim_batch(query, query_tokens, docs, &doc_tokens, dim).unwrap();
+    let mut offset = 0;
+    for (&score, &len) in scores.iter().zip(&doc_tokens) {
+        let doc = &docs[offset * dim..(offset + len) * dim];
+        let single = maxsim.maxsim_single(query, query_tokens, doc, len, dim).unwrap();
+        offset += len;
+        // Overflow to ±inf or inf - inf = NaN can hit one summation order and not the other
+        if !score.is_finite() || !single.is_finite() {
+            continue;
+        }
+        // Error bound of f32 dot products in any order: dim · ε · Σ|q||d| per query token
+        let magnitude: f32 = query
+            .chunks_exact(dim)
+            .map(|q| doc.chunks_exact(dim).map(|d| q.iter().zip(d).map(|(a, b)| (a * b).abs()).sum::<f32>()).fold(0.0, f32::max))
+            .sum();
+        let tolerance = 2.0 * dim as f32 * f32::EPSILON * magnitude + f32::MIN_POSITIVE;
+        assert!((score - single).abs() <= tolerance, "batch {} vs single {} (len {}, dim {})", score, single, len, dim);
+    }
+});
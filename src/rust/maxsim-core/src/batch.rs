@@ -0,0 +1,249 @@
+/*!
+ * Length-grouped batch scoring
+ *
+ * Documents are scored in order of length so similar lengths share one padded
+ * buffer (matches the official maxsim-cpu grouping):
+ * 1. Uniform corpora (≥ 50 documents within 20% of each other) are scored one
+ *    document at a time with no grouping at all
+ * 2. Otherwise documents within 20% of a group's shortest length form a group of at
+ *    most 128; groups under 4 documents are scored individually, larger ones in
+ *    padded sub-batches whose similarities are computed four documents at a time
+ *
+ * Grouping only changes speed, never a score. `maxsim_batch` is the plain
+ * pipeline (dot product, Σ max); the wasm bindings drive the same steps with
+ * their own reduction (metrics, aggregations, pruning) and instrumentation.
+ */
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::kernels::{default_d_block, dot_product, matrix_multiply, simd_max};
+
+/// Documents within this ratio of a group's shortest length share a group
+pub const LENGTH_TOLERANCE: f32 = 1.2;
+/// Largest length group
+pub const TARGET_BATCH_SIZE: usize = 128;
+/// Groups smaller than this are scored document by document
+pub const MIN_PADDED_GROUP: usize = 4;
+/// Smallest corpus that takes the uniform path
+pub const UNIFORM_MIN_DOCS: usize = 50;
+/// Documents per padded sub-batch (sized for L2, see the calibration of the bindings)
+pub const DEFAULT_SUB_BATCH_SIZE: usize = 16;
+
+/// Document indices in ascending order of length (stable: ties keep index order)
+pub fn length_order(doc_tokens: &[usize]) -> Vec<usize> {
+    let mut indices: Vec<usize> = (0..doc_tokens.len()).collect();
+    indices.sort_by_key(|&i| doc_tokens[i]);
+    indices
+}
+
+/// Whether documents of `min_len`..=`max_len` tokens take the uniform path
+pub fn is_uniform(min_len: usize, max_len: usize, num_docs: usize) -> bool {
+    min_len > 0 && max_len as f32 / min_len as f32 <= LENGTH_TOLERANCE && num_docs >= UNIFORM_MIN_DOCS
+}
+
+/// End (exclusive) of the length group starting at `start` of ascending `sorted_lens`
+pub fn group_end(sorted_lens: &[usize], start: usize) -> usize {
+    let max_allowed_len = (sorted_lens[start] as f32 * LENGTH_TOLERANCE) as usize;
+    let limit = sorted_lens.len().min(start + TARGET_BATCH_SIZE);
+    let mut end = start + 1;
+    while end < limit && sorted_lens[end] <= max_allowed_len {
+        end += 1;
+    }
+    end
+}
+
+/// Copy documents into consecutive `max_len`-token slots of `buffer`, zeroing the
+/// unused tail of each slot; returns the number of padding floats written
+pub fn pad_documents<'a>(
+    docs: impl IntoIterator<Item = &'a [f32]>,
+    max_len: usize,
+    embedding_dim: usize,
+    buffer: &mut [f32],
+) -> usize {
+    let slot = max_len * embedding_dim;
+    let mut padding = 0;
+    for (doc, dst) in docs.into_iter().zip(buffer.chunks_exact_mut(slot)) {
+        dst[..doc.len()].copy_from_slice(doc);
+        dst[doc.len()..].fill(0.0);
+        padding += slot - doc.len();
+    }
+    padding
+}
+
+/// Similarities of every query token with the documents of a padded sub-batch
+///
+/// `buffer` holds `lens.len()` slots of `max_len` tokens (see `pad_documents`).
+/// Layout of `similarities`: `query_tokens × (lens.len() × max_len)`; only the first
+/// `lens[doc]` entries of each document's row are written. Four documents are
+/// processed together so their dot products pipeline.
+pub fn batch_similarities(
+    query_flat: &[f32],
+    query_tokens: usize,
+    buffer: &[f32],
+    lens: &[usize],
+    max_len: usize,
+    embedding_dim: usize,
+    similarities: &mut [f32],
+) {
+    let batch_size = lens.len();
+    let row_len = batch_size * max_len;
+    let token = |doc_idx: usize, tok_idx: usize| {
+        let start = (doc_idx * max_len + tok_idx) * embedding_dim;
+        &buffer[start..start + embedding_dim]
+    };
+
+    for q_idx in 0..query_tokens {
+        let query_token = &query_flat[q_idx * embedding_dim..(q_idx + 1) * embedding_dim];
+        let row = &mut similarities[q_idx * row_len..(q_idx + 1) * row_len];
+
+        // Groups of 4 documents: common tokens together, then each document's tail
+        let num_full_groups = batch_size / 4;
+        for group_idx in 0..num_full_groups {
+            let base = group_idx * 4;
+            let min_len = lens[base..base + 4].iter().copied().min().unwrap_or(0);
+            for tok_idx in 0..min_len {
+                let sim0 = dot_product(query_token, token(base, tok_idx));
+                let sim1 = dot_product(query_token, token(base + 1, tok_idx));
+                let sim2 = dot_product(query_token, token(base + 2, tok_idx));
+                let sim3 = dot_product(query_token, token(base + 3, tok_idx));
+                row[base * max_len + tok_idx] = sim0;
+                row[(base + 1) * max_len + tok_idx] = sim1;
+                row[(base + 2) * max_len + tok_idx] = sim2;
+                row[(base + 3) * max_len + tok_idx] = sim3;
+            }
+            for doc_idx in base..base + 4 {
+                for tok_idx in min_len..lens[doc_idx] {
+                    row[doc_idx * max_len + tok_idx] = dot_product(query_token, token(doc_idx, tok_idx));
+                }
+            }
+        }
+
+        // Remainder documents (< 4)
+        for doc_idx in num_full_groups * 4..batch_size {
+            for tok_idx in 0..lens[doc_idx] {
+                row[doc_idx * max_len + tok_idx] = dot_product(query_token, token(doc_idx, tok_idx));
+            }
+        }
+    }
+}
+
+/// Official MaxSim (Σ over query tokens of the max dot product) of documents stored
+/// back to back with `doc_tokens` tokens each; empty documents score 0
+pub fn maxsim_batch(
+    query_flat: &[f32],
+    query_tokens: usize,
+    doc_flat: &[f32],
+    doc_tokens: &[usize],
+    embedding_dim: usize,
+) -> Vec<f32> {
+    let num_docs = doc_tokens.len();
+    let mut scores = vec![0.0; num_docs];
+    if num_docs == 0 || query_tokens == 0 {
+        return scores;
+    }
+
+    let mut offsets = Vec::with_capacity(num_docs);
+    let mut offset = 0;
+    for &len in doc_tokens {
+        offsets.push(offset);
+        offset += len * embedding_dim;
+    }
+    let doc = |idx: usize| &doc_flat[offsets[idx]..offsets[idx] + doc_tokens[idx] * embedding_dim];
+    let sum_of_maxima = |similarities: &[f32], row_start: &dyn Fn(usize) -> usize, len: usize| {
+        (0..query_tokens).map(|q_idx| simd_max(&similarities[row_start(q_idx)..row_start(q_idx) + len])).sum()
+    };
+
+    let order = length_order(doc_tokens);
+    let sorted_lens: Vec<usize> = order.iter().map(|&idx| doc_tokens[idx]).collect();
+    let mut similarities = Vec::new();
+    let score_individually = |idx: usize, similarities: &mut Vec<f32>| {
+        let len = doc_tokens[idx];
+        similarities.resize(query_tokens * len, 0.0);
+        matrix_multiply(query_flat, doc(idx), similarities, query_tokens, len, embedding_dim, default_d_block(len));
+        sum_of_maxima(similarities, &|q_idx| q_idx * len, len)
+    };
+
+    if is_uniform(sorted_lens[0], sorted_lens[num_docs - 1], num_docs) {
+        for &idx in &order {
+            scores[idx] = score_individually(idx, &mut similarities);
+        }
+        return scores;
+    }
+
+    let mut buffer = Vec::new();
+    let mut start = 0;
+    while start < num_docs {
+        if sorted_lens[start] == 0 {
+            start += 1;
+            continue;
+        }
+        let end = group_end(&sorted_lens, start);
+        if end - start < MIN_PADDED_GROUP {
+            for &idx in &order[start..end] {
+                scores[idx] = score_individually(idx, &mut similarities);
+            }
+        } else {
+            let max_len = sorted_lens[end - 1];
+            for (sub_order, lens) in order[start..end]
+                .chunks(DEFAULT_SUB_BATCH_SIZE)
+                .zip(sorted_lens[start..end].chunks(DEFAULT_SUB_BATCH_SIZE))
+            {
+                buffer.resize(sub_order.len() * max_len * embedding_dim, 0.0);
+                pad_documents(sub_order.iter().map(|&idx| doc(idx)), max_len, embedding_dim, &mut buffer);
+                let row_len = sub_order.len() * max_len;
+                similarities.resize(query_tokens * row_len, 0.0);
+                batch_similarities(query_flat, query_tokens, &buffer, lens, max_len, embedding_dim, &mut similarities);
+                for (slot, &idx) in sub_order.iter().enumerate() {
+                    scores[idx] = sum_of_maxima(&similarities, &|q_idx| q_idx * row_len + slot * max_len, lens[slot]);
+                }
+            }
+        }
+        start = end;
+    }
+    scores
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_groups_follow_the_length_tolerance() {
+        let sorted_lens = [10, 11, 12, 13, 20, 24, 25];
+        assert_eq!(group_end(&sorted_lens, 0), 3); // 13 > 10 × 1.2
+        assert_eq!(group_end(&sorted_lens, 4), 6);
+        assert!(is_uniform(100, 120, 50) && !is_uniform(100, 121, 50) && !is_uniform(100, 100, 49));
+    }
+
+    #[test]
+    fn test_every_path_matches_plain_loops() {
+        let dim = 3;
+        let value = |i: usize| ((i * 37 % 23) as f32 - 11.0) / 8.0;
+        let corpora: [Vec<usize>; 3] = [
+            vec![4; 60],                             // Uniform
+            (0..40).map(|i| 5 + i % 3).collect(),     // Padded groups and sub-batches
+            vec![3, 0, 9, 1, 4, 30, 4],               // Individual documents and an empty one
+        ];
+        let query: Vec<f32> = (0..2 * dim).map(value).collect();
+        for doc_tokens in corpora {
+            let docs: Vec<f32> = (0..doc_tokens.iter().sum::<usize>() * dim).map(|i| value(i + 5)).collect();
+            let scores = maxsim_batch(&query, 2, &docs, &doc_tokens, dim);
+
+            let mut offset = 0;
+            for (&len, &score) in doc_tokens.iter().zip(&scores) {
+                let doc = &docs[offset..offset + len * dim];
+                offset += len * dim;
+                let expected: f32 = if len == 0 {
+                    0.0
+                } else {
+                    query
+                        .chunks_exact(dim)
+                        .map(|q| doc.chunks_exact(dim).map(|d| dot_product(q, d)).fold(f32::NEG_INFINITY, f32::max))
+                        .sum()
+                };
+                assert_eq!(score, expected);
+            }
+        }
+    }
+}
@@ -0,0 +1,177 @@
+/*!
+ * Index deltas
+ *
+ * When a corpus changes by a few documents, clients holding yesterday's index
+ * download a delta instead of the whole new index. `create(base, updated)` diffs two
+ * serialized indexes; applying the delta to the base yields the updated index's
+ * documents: the base documents not removed, in base order, followed by the added
+ * ones.
+ *
+ * Documents are matched by their encoded bytes, walking both indexes in order. An
+ * updated index that appends and drops documents gives the smallest delta; a
+ * document inserted in the middle (or a reordering) turns the base documents after
+ * it into removals and additions, which is still correct, only larger.
+ *
+ * Layout (little-endian):
+ *
+ * ```text
+ * magic          4 bytes   "MXSD"
+ * version        u32       DELTA_VERSION
+ * base           u32       fingerprint of the base index (`Header::fingerprint`)
+ * result         u32       fingerprint of the updated index
+ * num_removed    u64
+ * removed        u32 × num_removed   (base document indices, ascending)
+ * checksum       u32       CRC-32 of everything above
+ * added          the added documents as a standalone index (same encoding)
+ * ```
+ */
+
+use alloc::vec::Vec;
+
+use crate::index::{crc32, read_u32, read_u64, Header, IndexError};
+
+pub const DELTA_MAGIC: &[u8; 4] = b"MXSD";
+pub const DELTA_VERSION: u32 = 1;
+
+/// A parsed delta, borrowing its added documents from the input
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Delta<'a> {
+    pub base: u32,
+    pub result: u32,
+    pub removed: Vec<usize>,
+    /// Serialized index of the added documents
+    pub added: &'a [u8],
+}
+
+impl<'a> Delta<'a> {
+    /// Parse and validate everything but the added index (parsed by its reader)
+    pub fn parse(bytes: &'a [u8]) -> Result<Self, IndexError> {
+        let corrupt = |reason: &str| IndexError::CorruptHeader(reason.into());
+        if bytes.len() < 4 || &bytes[..4] != DELTA_MAGIC {
+            return Err(IndexError::NotAnIndex);
+        }
+        if bytes.len() < 24 {
+            return Err(corrupt("delta too short for header"));
+        }
+        let version = read_u32(bytes, 4);
+        if version != DELTA_VERSION {
+            return Err(IndexError::UnsupportedVersion { found: version });
+        }
+
+        let num_removed = read_u64(bytes, 16) as usize;
+        let checksum_at = num_removed
+            .checked_mul(4)
+            .and_then(|n| n.checked_add(24))
+            .filter(|&end| end.checked_add(4).is_some_and(|checksum_end| checksum_end <= bytes.len()))
+            .ok_or_else(|| corrupt("delta removals exceed its size"))?;
+        if crc32(&bytes[..checksum_at]) != read_u32(bytes, checksum_at) {
+            return Err(IndexError::ChecksumMismatch { block: 0, blocks: 1 });
+        }
+
+        let removed: Vec<usize> = (0..num_removed).map(|i| read_u32(bytes, 24 + i * 4) as usize).collect();
+        if removed.windows(2).any(|pair| pair[0] >= pair[1]) {
+            return Err(corrupt("delta removals are not ascending"));
+        }
+        Ok(Delta { base: read_u32(bytes, 8), result: read_u32(bytes, 12), removed, added: &bytes[checksum_at + 4..] })
+    }
+}
+
+/// Delta turning the `base` index into the `updated` one (both must share their
+/// encoding and dimension)
+pub fn create(base: &[u8], updated: &[u8]) -> Result<Vec<u8>, IndexError> {
+    let (base_header, updated_header) = (Header::parse(base)?, Header::parse(updated)?);
+    base_header.verify_checksums(base)?;
+    updated_header.verify_checksums(updated)?;
+    updated_header.expect_dim(base_header.embedding_dim)?;
+    if updated_header.encoding != base_header.encoding {
+        return Err(IndexError::CorruptHeader("base and updated indexes use different encodings".into()));
+    }
+
+    let (base_docs, updated_docs) = (documents(&base_header, base), documents(&updated_header, updated));
+    let mut removed = Vec::new();
+    let mut kept = 0;
+    for (doc_idx, doc) in base_docs.iter().enumerate() {
+        if updated_docs.get(kept) == Some(doc) {
+            kept += 1;
+        } else {
+            removed.push(doc_idx);
+        }
+    }
+
+    let mut out = DELTA_MAGIC.to_vec();
+    out.extend_from_slice(&DELTA_VERSION.to_le_bytes());
+    out.extend_from_slice(&base_header.fingerprint(base).to_le_bytes());
+    out.extend_from_slice(&updated_header.fingerprint(updated).to_le_bytes());
+    out.extend_from_slice(&(removed.len() as u64).to_le_bytes());
+    for &doc_idx in &removed {
+        out.extend_from_slice(&(doc_idx as u32).to_le_bytes());
+    }
+    out.extend_from_slice(&crc32(&out).to_le_bytes());
+
+    let added = Header {
+        encoding: updated_header.encoding,
+        embedding_dim: updated_header.embedding_dim,
+        doc_tokens: updated_header.doc_tokens[kept..].to_vec(),
+    };
+    let start = out.len();
+    added.write(&mut out);
+    for doc in &updated_docs[kept..] {
+        out.extend_from_slice(doc);
+    }
+    let mut added_blob = out.split_off(start);
+    added.write_checksums(&mut added_blob);
+    out.extend_from_slice(&added_blob);
+    Ok(out)
+}
+
+// Encoded bytes of every document of a parsed blob
+fn documents<'a>(header: &Header, bytes: &'a [u8]) -> Vec<&'a [u8]> {
+    let token_bytes = header.encoding.token_bytes(header.embedding_dim);
+    let mut offset = header.embeddings_start();
+    header
+        .doc_tokens
+        .iter()
+        .map(|&tokens| {
+            let doc = &bytes[offset..offset + tokens * token_bytes];
+            offset += doc.len();
+            doc
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::{deserialize, serialize, Encoding};
+    use alloc::vec;
+
+    #[test]
+    fn test_delta_keeps_base_order_and_appends() {
+        let doc = |value: f32, tokens: usize| vec![value; tokens * 2];
+        let docs = [doc(1.0, 2), doc(2.0, 1), doc(3.0, 3), doc(4.0, 1)];
+        let base = serialize(&docs.concat(), &[2, 1, 3, 1], 2, Encoding::F32);
+        // Doc 1 dropped, two documents appended
+        let updated_docs = [&docs[0], &docs[2], &docs[3], &doc(5.0, 2), &doc(6.0, 1)];
+        let updated = serialize(&updated_docs.map(|d| d.as_slice()).concat(), &[2, 3, 1, 2, 1], 2, Encoding::F32);
+
+        let bytes = create(&base, &updated).unwrap();
+        let delta = Delta::parse(&bytes).unwrap();
+        assert_eq!(delta.base, Header::parse(&base).unwrap().fingerprint(&base));
+        assert_eq!(delta.result, Header::parse(&updated).unwrap().fingerprint(&updated));
+        assert_eq!(delta.removed, [1]);
+        let (added, flat) = deserialize(delta.added).unwrap();
+        assert_eq!((added.doc_tokens, flat), (vec![2, 1], [doc(5.0, 2), doc(6.0, 1)].concat()));
+
+        let mut damaged = bytes.clone();
+        damaged[24] ^= 1;
+        assert_eq!(Delta::parse(&damaged).unwrap_err().code(), "ChecksumMismatch");
+        assert_eq!(Delta::parse(&base).unwrap_err(), IndexError::NotAnIndex);
+        let other_dim = serialize(&[0.0; 3], &[1], 3, Encoding::F32);
+        assert_eq!(create(&base, &other_dim).unwrap_err().code(), "DimMismatch");
+
+        // A removal count whose list ends 4 bytes short of the address space
+        let mut oversized = bytes.clone();
+        oversized[16..24].copy_from_slice(&((u64::MAX - 27) / 4).to_le_bytes());
+        assert_eq!(Delta::parse(&oversized).unwrap_err().code(), "CorruptHeader");
+    }
+}
@@ -0,0 +1,491 @@
+/*!
+ * Serialized index encoding
+ *
+ * The header, layout and int8 quantization of the `MXSI` index blob, shared by the
+ * WASM `serialize_index`/`load_index` and the offline builders (maxsim-py), so an
+ * index written anywhere decodes to the same floats in the browser.
+ *
+ * Layout (all integers and floats little-endian):
+ *
+ * ```text
+ * magic          4 bytes   "MXSI"
+ * version        u32       FORMAT_VERSION
+ * encoding       u32       0 = f32, 1 = int8 (per-token scale)
+ * embedding_dim  u32
+ * num_docs       u64
+ * total_tokens   u64
+ * doc_tokens     u32 × num_docs
+ * embeddings     f32:  f32 × total_tokens × embedding_dim
+ *                int8: per token, f32 scale followed by embedding_dim × i8
+ * checksums      u32 × num_blocks   (version 2 and later)
+ * ```
+ *
+ * The magic and version are the first 8 bytes in every version, and a reader checks
+ * them before anything else: a blob from a newer writer fails with
+ * `IndexError::UnsupportedVersion` instead of being decoded under the wrong layout.
+ * Any change to the layout bumps `FORMAT_VERSION`; older versions down to
+ * `OLDEST_READABLE_VERSION` still load.
+ *
+ * Checksums are CRC-32 (IEEE) of consecutive blocks: block 0 is the header with the
+ * token counts, then the embeddings in blocks of `CHECKSUM_BLOCK_BYTES`. A blob cut
+ * short fails the size check; one of the right size with damaged bytes (a partial
+ * storage write, a bad cache entry) fails with `ChecksumMismatch` naming the block,
+ * before any token is decoded. Version 1 blobs carry no checksums.
+ *
+ * A blob may be shipped zstd-compressed as a whole (`zstd -19 corpus.mxsi`); the
+ * WASM build decodes it when compiled with the `zstd` feature. Here a compressed blob
+ * is recognized by the zstd frame magic and reported as `Compressed`.
+ */
+
+use core::fmt;
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+pub const MAGIC: &[u8; 4] = b"MXSI";
+pub const FORMAT_VERSION: u32 = 2;
+/// Oldest version still read (version 1 has no checksums)
+pub const OLDEST_READABLE_VERSION: u32 = 1;
+pub const HEADER_LEN: usize = 4 + 4 + 4 + 4 + 8 + 8;
+/// Embedding bytes covered by one checksum
+pub const CHECKSUM_BLOCK_BYTES: usize = 1 << 20;
+/// First bytes of a zstd frame
+pub const ZSTD_MAGIC: &[u8; 4] = &[0x28, 0xB5, 0x2F, 0xFD];
+
+/// Why a blob could not be read as an index
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum IndexError {
+    /// Not a MaxSim index at all (bad magic)
+    NotAnIndex,
+    /// zstd-compressed blob, and this build has no decompressor
+    Compressed,
+    /// zstd-compressed blob that failed to decompress
+    CorruptCompression(String),
+    /// Written with a format version this build does not read
+    UnsupportedVersion { found: u32 },
+    /// Embedding encoding unknown to this build
+    UnsupportedEncoding(u32),
+    /// Header inconsistent with itself or with the blob's length
+    CorruptHeader(String),
+    /// Bytes of checksum block `block` (0 = header) were damaged
+    ChecksumMismatch { block: usize, blocks: usize },
+    /// Valid index of another embedding dimension than required
+    DimMismatch { expected: usize, found: usize },
+    /// Index delta for another base than the loaded index (fingerprints, see `delta`)
+    BaseMismatch { base: u32, loaded: Option<u32> },
+}
+
+impl IndexError {
+    /// Stable name of the variant, for callers that branch on the kind of failure
+    pub fn code(&self) -> &'static str {
+        match self {
+            IndexError::NotAnIndex => "NotAnIndex",
+            IndexError::Compressed => "Compressed",
+            IndexError::CorruptCompression(_) => "CorruptCompression",
+            IndexError::UnsupportedVersion { .. } => "UnsupportedVersion",
+            IndexError::UnsupportedEncoding(_) => "UnsupportedEncoding",
+            IndexError::CorruptHeader(_) => "CorruptHeader",
+            IndexError::ChecksumMismatch { .. } => "ChecksumMismatch",
+            IndexError::DimMismatch { .. } => "DimMismatch",
+            IndexError::BaseMismatch { .. } => "BaseMismatch",
+        }
+    }
+}
+
+impl fmt::Display for IndexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IndexError::NotAnIndex => write!(f, "Not a MaxSim index (bad magic)"),
+            IndexError::Compressed => {
+                write!(f, "Index is zstd-compressed; decompress it first or use a build with the `zstd` feature")
+            }
+            IndexError::CorruptCompression(reason) => write!(f, "Compressed index is corrupt: {}", reason),
+            IndexError::UnsupportedVersion { found } if *found > FORMAT_VERSION => write!(
+                f,
+                "Index format version {} is newer than this build reads ({}); upgrade maxsim-web",
+                found, FORMAT_VERSION
+            ),
+            IndexError::UnsupportedVersion { found } => write!(
+                f,
+                "Unsupported index version {} (this build reads {} to {})",
+                found, OLDEST_READABLE_VERSION, FORMAT_VERSION
+            ),
+            IndexError::UnsupportedEncoding(encoding) => write!(f, "Unknown index encoding {}", encoding),
+            IndexError::CorruptHeader(reason) => write!(f, "Corrupt index header: {}", reason),
+            IndexError::ChecksumMismatch { block: 0, .. } => write!(f, "Index header is corrupt (checksum mismatch)"),
+            IndexError::ChecksumMismatch { block, blocks } => {
+                write!(f, "Index embeddings are corrupt in block {} of {} (checksum mismatch)", block, blocks - 1)
+            }
+            IndexError::DimMismatch { expected, found } => {
+                write!(f, "Index has embedding dimension {}, expected {}", found, expected)
+            }
+            IndexError::BaseMismatch { base, loaded: Some(loaded) } => {
+                write!(f, "Delta applies to index {:08x}, but index {:08x} is loaded", base, loaded)
+            }
+            IndexError::BaseMismatch { base, loaded: None } => {
+                write!(f, "Delta applies to index {:08x}, but no index is loaded (load it with load_index())", base)
+            }
+        }
+    }
+}
+
+impl From<IndexError> for String {
+    fn from(error: IndexError) -> String {
+        error.to_string()
+    }
+}
+
+/// How token embeddings are encoded in the blob
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    F32 = 0,
+    /// Symmetric int8 with one f32 scale per token (~4× smaller, dequantized on load)
+    Int8 = 1,
+}
+
+impl Encoding {
+    pub fn from_u32(value: u32) -> Option<Self> {
+        match value {
+            0 => Some(Encoding::F32),
+            1 => Some(Encoding::Int8),
+            _ => None,
+        }
+    }
+
+    /// Encoded size of one token embedding
+    pub fn token_bytes(self, embedding_dim: usize) -> usize {
+        match self {
+            Encoding::F32 => embedding_dim * 4,
+            Encoding::Int8 => 4 + embedding_dim,
+        }
+    }
+}
+
+/// Validated header of a serialized index
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Header {
+    pub encoding: Encoding,
+    pub embedding_dim: usize,
+    pub doc_tokens: Vec<usize>,
+}
+
+impl Header {
+    /// Byte offset of the first encoded token
+    pub fn embeddings_start(&self) -> usize {
+        HEADER_LEN + self.doc_tokens.len() * 4
+    }
+
+    /// Bytes of encoded tokens
+    pub fn embeddings_len(&self) -> usize {
+        self.doc_tokens.iter().sum::<usize>() * self.encoding.token_bytes(self.embedding_dim)
+    }
+
+    /// Checksummed blocks: the header, then every `CHECKSUM_BLOCK_BYTES` of embeddings
+    pub fn checksum_blocks(&self) -> usize {
+        1 + self.embeddings_len().div_ceil(CHECKSUM_BLOCK_BYTES)
+    }
+
+    /// Append the checksums to `out`, a blob holding the header and every token
+    pub fn write_checksums(&self, out: &mut Vec<u8>) {
+        let checksums: Vec<u32> = self.blocks(out).map(crc32).collect();
+        for checksum in checksums {
+            out.extend_from_slice(&checksum.to_le_bytes());
+        }
+    }
+
+    /// Check every block of a blob this header was parsed from against its checksum
+    /// (nothing to check before version 2)
+    pub fn verify_checksums(&self, bytes: &[u8]) -> Result<(), IndexError> {
+        if read_u32(bytes, 4) < 2 {
+            return Ok(());
+        }
+        let blocks = self.checksum_blocks();
+        let trailer = self.embeddings_start() + self.embeddings_len();
+        for (block, data) in self.blocks(bytes).enumerate() {
+            if crc32(data) != read_u32(bytes, trailer + block * 4) {
+                return Err(IndexError::ChecksumMismatch { block, blocks });
+            }
+        }
+        Ok(())
+    }
+
+    /// Identity of the corpus in a blob this header was parsed from: the CRC-32 of its
+    /// checksums (of the whole blob before version 2), so it costs no pass over the
+    /// embeddings. Index deltas name their base and result by fingerprint.
+    pub fn fingerprint(&self, bytes: &[u8]) -> u32 {
+        if read_u32(bytes, 4) < 2 {
+            return crc32(bytes);
+        }
+        crc32(&bytes[self.embeddings_start() + self.embeddings_len()..])
+    }
+
+    // The header block, then the embeddings in CHECKSUM_BLOCK_BYTES blocks
+    fn blocks<'a>(&self, bytes: &'a [u8]) -> impl Iterator<Item = &'a [u8]> {
+        let (start, end) = (self.embeddings_start(), self.embeddings_start() + self.embeddings_len());
+        core::iter::once(&bytes[..start]).chain(bytes[start..end].chunks(CHECKSUM_BLOCK_BYTES))
+    }
+
+    /// Append the header and token counts to `out` (the tokens and then
+    /// `write_checksums()` complete the blob)
+    pub fn write(&self, out: &mut Vec<u8>) {
+        let total_tokens: usize = self.doc_tokens.iter().sum();
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        out.extend_from_slice(&(self.encoding as u32).to_le_bytes());
+        out.extend_from_slice(&(self.embedding_dim as u32).to_le_bytes());
+        out.extend_from_slice(&(self.doc_tokens.len() as u64).to_le_bytes());
+        out.extend_from_slice(&(total_tokens as u64).to_le_bytes());
+        for &tokens in &self.doc_tokens {
+            out.extend_from_slice(&(tokens as u32).to_le_bytes());
+        }
+    }
+
+    /// Parse and validate the header of a complete blob (the length must match exactly)
+    pub fn parse(bytes: &[u8]) -> Result<Header, IndexError> {
+        let corrupt = |reason: &str| IndexError::CorruptHeader(reason.to_string());
+        if bytes.starts_with(ZSTD_MAGIC) {
+            return Err(IndexError::Compressed);
+        }
+        if bytes.len() < 4 || &bytes[0..4] != MAGIC {
+            return Err(IndexError::NotAnIndex);
+        }
+        if bytes.len() < 8 {
+            return Err(corrupt("too short for header"));
+        }
+
+        // Before anything else: later versions may lay out the rest differently
+        let version = read_u32(bytes, 4);
+        if !(OLDEST_READABLE_VERSION..=FORMAT_VERSION).contains(&version) {
+            return Err(IndexError::UnsupportedVersion { found: version });
+        }
+        if bytes.len() < HEADER_LEN {
+            return Err(corrupt("too short for header"));
+        }
+
+        let encoding = Encoding::from_u32(read_u32(bytes, 8)).ok_or(IndexError::UnsupportedEncoding(read_u32(bytes, 8)))?;
+        let dim = read_u32(bytes, 12) as usize;
+        let num_docs = read_u64(bytes, 16) as usize;
+        let total_tokens = read_u64(bytes, 24) as usize;
+
+        if dim == 0 {
+            return Err(corrupt("embedding dimension must be > 0"));
+        }
+
+        let embeddings_start = num_docs
+            .checked_mul(4)
+            .and_then(|n| n.checked_add(HEADER_LEN))
+            .ok_or_else(|| corrupt("document count overflows"))?;
+        let embeddings_len = total_tokens
+            .checked_mul(encoding.token_bytes(dim))
+            .ok_or_else(|| corrupt("token count overflows"))?;
+        let checksums_len = if version >= 2 { (1 + embeddings_len.div_ceil(CHECKSUM_BLOCK_BYTES)) * 4 } else { 0 };
+        let expected_len = embeddings_start
+            .checked_add(embeddings_len)
+            .and_then(|n| n.checked_add(checksums_len))
+            .ok_or_else(|| corrupt("token count overflows"))?;
+        if bytes.len() != expected_len {
+            return Err(IndexError::CorruptHeader(format!(
+                "size mismatch: expected {} bytes, got {}",
+                expected_len,
+                bytes.len()
+            )));
+        }
+
+        let doc_tokens: Vec<usize> = (0..num_docs)
+            .map(|i| read_u32(bytes, HEADER_LEN + i * 4) as usize)
+            .collect();
+        if doc_tokens.iter().sum::<usize>() != total_tokens {
+            return Err(corrupt("document token counts do not match total_tokens"));
+        }
+
+        Ok(Header { encoding, embedding_dim: dim, doc_tokens })
+    }
+
+    /// Fail with `DimMismatch` unless the index has `embedding_dim` dimensions
+    pub fn expect_dim(&self, embedding_dim: usize) -> Result<(), IndexError> {
+        if self.embedding_dim != embedding_dim {
+            return Err(IndexError::DimMismatch { expected: embedding_dim, found: self.embedding_dim });
+        }
+        Ok(())
+    }
+}
+
+/// Append one encoded token embedding to `out`
+pub fn encode_token(token: &[f32], encoding: Encoding, out: &mut Vec<u8>) {
+    match encoding {
+        Encoding::F32 => {
+            for value in token {
+                out.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        Encoding::Int8 => {
+            let max_abs = token.iter().fold(0.0f32, |m, x| m.max(x.abs()));
+            let scale = if max_abs > 0.0 { max_abs / 127.0 } else { 1.0 };
+            out.extend_from_slice(&scale.to_le_bytes());
+            out.extend(token.iter().map(|&x| round(x / scale).clamp(-127.0, 127.0) as i8 as u8));
+        }
+    }
+}
+
+/// Append the floats of one encoded token (`encoding.token_bytes(dim)` bytes) to `out`
+pub fn decode_token(token: &[u8], encoding: Encoding, out: &mut Vec<f32>) {
+    match encoding {
+        Encoding::F32 => {
+            out.extend(token.chunks_exact(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])));
+        }
+        Encoding::Int8 => {
+            let scale = f32::from_le_bytes([token[0], token[1], token[2], token[3]]);
+            out.extend(token[4..].iter().map(|&q| q as i8 as f32 * scale));
+        }
+    }
+}
+
+/// Serialize documents stored back to back (`doc_tokens` tokens each)
+pub fn serialize(doc_flat: &[f32], doc_tokens: &[usize], embedding_dim: usize, encoding: Encoding) -> Vec<u8> {
+    let header = Header { encoding, embedding_dim, doc_tokens: doc_tokens.to_vec() };
+    let total_tokens: usize = doc_tokens.iter().sum();
+    let mut out = Vec::with_capacity(header.embeddings_start() + total_tokens * encoding.token_bytes(embedding_dim) + header.checksum_blocks() * 4);
+    header.write(&mut out);
+    for token in doc_flat[..total_tokens * embedding_dim].chunks_exact(embedding_dim) {
+        encode_token(token, encoding, &mut out);
+    }
+    header.write_checksums(&mut out);
+    out
+}
+
+/// Decode a serialized index into its header and flat embeddings (documents back to back)
+pub fn deserialize(bytes: &[u8]) -> Result<(Header, Vec<f32>), IndexError> {
+    let header = Header::parse(bytes)?;
+    header.verify_checksums(bytes)?;
+    let token_bytes = header.encoding.token_bytes(header.embedding_dim);
+    let start = header.embeddings_start();
+    let mut embeddings = Vec::with_capacity(header.embeddings_len() / token_bytes * header.embedding_dim);
+    for token in bytes[start..start + header.embeddings_len()].chunks_exact(token_bytes) {
+        decode_token(token, header.encoding, &mut embeddings);
+    }
+    Ok((header, embeddings))
+}
+
+/// CRC-32 (IEEE 802.3, the zlib/PNG polynomial) of `bytes`
+pub fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0u32, |crc, &byte| CRC_TABLE[((crc ^ byte as u32) & 0xFF) as usize] ^ (crc >> 8))
+}
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 { 0xEDB8_8320 ^ (crc >> 1) } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+// f32::round (half away from zero) is not available without std
+fn round(x: f32) -> f32 {
+    let truncated = x as i32 as f32;
+    let fraction = x - truncated;
+    if fraction >= 0.5 {
+        truncated + 1.0
+    } else if fraction <= -0.5 {
+        truncated - 1.0
+    } else {
+        truncated
+    }
+}
+
+pub(crate) fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+pub(crate) fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn test_flat_round_trip_matches_the_blob_layout() {
+        let flat = vec![0.6, 0.8, 1.0, 0.0, -0.5, 0.25];
+        let bytes = serialize(&flat, &[2, 1], 2, Encoding::Int8);
+        assert_eq!(bytes.len(), HEADER_LEN + 2 * 4 + 3 * (4 + 2) + 2 * 4);
+
+        let (header, restored) = deserialize(&bytes).unwrap();
+        assert_eq!(header, Header { encoding: Encoding::Int8, embedding_dim: 2, doc_tokens: vec![2, 1] });
+        for (a, b) in restored.iter().zip(&flat) {
+            assert!((a - b).abs() < 0.01);
+        }
+        assert_eq!(deserialize(&serialize(&flat, &[2, 1], 2, Encoding::F32)).unwrap().1, flat);
+        // Ties round away from zero like f32::round
+        assert_eq!((round(2.5), round(-2.5), round(0.49999997), round(-126.6)), (3.0, -3.0, 0.0, -127.0));
+    }
+
+    #[test]
+    fn test_header_errors_are_typed() {
+        let bytes = serialize(&[1.0, 0.0], &[1], 2, Encoding::F32);
+        assert_eq!(Header::parse(b"MXS"), Err(IndexError::NotAnIndex));
+        assert_eq!(Header::parse(b"NOPE and more bytes than the header").unwrap_err().code(), "NotAnIndex");
+        assert_eq!(Header::parse(&[0x28, 0xB5, 0x2F, 0xFD, 0, 0]), Err(IndexError::Compressed));
+
+        // A newer blob is reported as such even when its layout is unreadable
+        let mut newer = b"MXSI".to_vec();
+        newer.extend_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        let error = Header::parse(&newer).unwrap_err();
+        assert_eq!(error, IndexError::UnsupportedVersion { found: FORMAT_VERSION + 1 });
+        assert!(error.to_string().contains("newer"));
+
+        let mut encoding = bytes.clone();
+        encoding[8] = 7;
+        assert_eq!(Header::parse(&encoding), Err(IndexError::UnsupportedEncoding(7)));
+        assert_eq!(Header::parse(&bytes[..bytes.len() - 1]).unwrap_err().code(), "CorruptHeader");
+        assert_eq!(Header::parse(&bytes[..12]).unwrap_err().code(), "CorruptHeader");
+
+        let header = Header::parse(&bytes).unwrap();
+        assert_eq!(header.expect_dim(2), Ok(()));
+        assert_eq!(Header::parse(&[&bytes[..4], &0u32.to_le_bytes()].concat()), Err(IndexError::UnsupportedVersion { found: 0 }));
+        assert_eq!(header.expect_dim(3), Err(IndexError::DimMismatch { expected: 3, found: 2 }));
+    }
+
+    #[test]
+    fn test_checksums_locate_damaged_blocks() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+
+        // Two embedding blocks: 2 docs × 1024 tokens × 1 KB
+        let dim = 256;
+        let flat: Vec<f32> = (0..2048 * dim).map(|i| (i % 97) as f32).collect();
+        let bytes = serialize(&flat, &[1024, 1024], dim, Encoding::F32);
+        let header = Header::parse(&bytes).unwrap();
+        assert_eq!(header.checksum_blocks(), 3);
+        assert_eq!(deserialize(&bytes).unwrap().1, flat);
+
+        let flipped = |offset: usize| {
+            let mut damaged = bytes.clone();
+            damaged[offset] ^= 1;
+            deserialize(&damaged).unwrap_err()
+        };
+        assert_eq!(flipped(bytes.len() - 100 * 4 * dim), IndexError::ChecksumMismatch { block: 2, blocks: 3 });
+        assert_eq!(flipped(header.embeddings_start()), IndexError::ChecksumMismatch { block: 1, blocks: 3 });
+        // Swapped token counts keep every size consistent
+        let mut swapped = serialize(&flat[..4 * dim], &[1, 3], dim, Encoding::F32);
+        swapped[HEADER_LEN..HEADER_LEN + 8].rotate_left(4);
+        assert_eq!(deserialize(&swapped).unwrap_err().code(), "ChecksumMismatch");
+
+        // Version 1 blobs (no checksums) still load
+        let mut v1 = serialize(&flat[..4 * dim], &[1, 3], dim, Encoding::F32);
+        v1.truncate(v1.len() - 8);
+        v1[4] = 1;
+        assert_eq!(deserialize(&v1).unwrap().1, &flat[..4 * dim]);
+    }
+}
@@ -0,0 +1,447 @@
+/*!
+ * Scoring kernels: SIMD dot products, blocked similarity matrices, max and clamp
+ *
+ * On wasm32 every kernel uses 128-bit SIMD (build with `+simd128`); other targets
+ * use the native AVX2/SSE2/NEON kernels of native.rs for `dot_product` and
+ * `simd_max`, with the same results up to float summation order.
+ *
+ * Every embedding dimension is supported: dimensions that are not a multiple of the
+ * vector width (e.g. 96 or 250) finish with 4-wide steps and a scalar tail, and
+ * vectors of different lengths are multiplied over their common prefix, so no
+ * kernel ever reads past the end of a slice.
+ */
+
+#[cfg(target_arch = "wasm32")]
+use core::arch::wasm32::*;
+
+/// Upper document length (inclusive) of each cache blocking class
+pub const LENGTH_CLASSES: [usize; 5] = [128, 256, 512, 1024, usize::MAX];
+/// Default document-token block size of `matrix_multiply` per length class
+pub const DEFAULT_D_BLOCKS: [usize; 5] = [16, 12, 8, 6, 4];
+
+/// Default `matrix_multiply` block size for documents of `doc_tokens` tokens
+#[inline]
+pub fn default_d_block(doc_tokens: usize) -> usize {
+    DEFAULT_D_BLOCKS[length_class(doc_tokens)]
+}
+
+/// Index of the blocking class of documents of `doc_tokens` tokens
+#[inline]
+pub fn length_class(doc_tokens: usize) -> usize {
+    LENGTH_CLASSES.iter().position(|&max_len| doc_tokens <= max_len).unwrap_or(LENGTH_CLASSES.len() - 1)
+}
+
+// ============================================================================
+// SIMD DOT PRODUCT - Macro-generated specialized versions
+// ============================================================================
+
+// Specialized kernels have no tail and no bounds check: `dot_product` only dispatches
+// slices of exactly `$dim` floats to them (the caller's safety contract)
+macro_rules! generate_simd_dot {
+    ($name:ident, $dim:expr) => {
+        #[cfg(target_arch = "wasm32")]
+        #[inline]
+        unsafe fn $name(a: &[f32], b: &[f32]) -> f32 {
+            const _: () = assert!($dim % 4 == 0);
+            debug_assert!(a.len() == $dim && b.len() == $dim);
+            unsafe {
+                let mut sum = f32x4_splat(0.0);
+                for i in (0..$dim).step_by(4) {
+                    let va = v128_load(a.as_ptr().add(i) as *const v128);
+                    let vb = v128_load(b.as_ptr().add(i) as *const v128);
+                    sum = f32x4_add(sum, f32x4_mul(va, vb));
+                }
+                f32x4_extract_lane::<0>(sum) + f32x4_extract_lane::<1>(sum) + 
+                f32x4_extract_lane::<2>(sum) + f32x4_extract_lane::<3>(sum)
+            }
+        }
+    };
+}
+
+// Small late-interaction models: ColBERT-small (48), answerai-colbert (96), JaColBERT
+// and others (64)
+generate_simd_dot!(simd_dot_48, 48);
+generate_simd_dot!(simd_dot_64, 64);
+generate_simd_dot!(simd_dot_96, 96);
+generate_simd_dot!(simd_dot_128, 128);
+generate_simd_dot!(simd_dot_256, 256);
+generate_simd_dot!(simd_dot_384, 384);
+generate_simd_dot!(simd_dot_512, 512);
+generate_simd_dot!(simd_dot_768, 768);
+generate_simd_dot!(simd_dot_1024, 1024);
+
+#[cfg(target_arch = "wasm32")]
+#[inline]
+fn simd_dot_generic(a: &[f32], b: &[f32]) -> f32 {
+    let len = a.len().min(b.len());
+    let simd_len = len - (len % 16);
+    let quad_len = len - (len % 4);
+
+    unsafe {
+        let mut sum0 = f32x4_splat(0.0);
+        let mut sum1 = f32x4_splat(0.0);
+        let mut sum2 = f32x4_splat(0.0);
+        let mut sum3 = f32x4_splat(0.0);
+
+        let mut i = 0;
+        while i < simd_len {
+            let va0 = v128_load(a.as_ptr().add(i) as *const v128);
+            let vb0 = v128_load(b.as_ptr().add(i) as *const v128);
+            sum0 = f32x4_add(sum0, f32x4_mul(va0, vb0));
+
+            let va1 = v128_load(a.as_ptr().add(i + 4) as *const v128);
+            let vb1 = v128_load(b.as_ptr().add(i + 4) as *const v128);
+            sum1 = f32x4_add(sum1, f32x4_mul(va1, vb1));
+
+            let va2 = v128_load(a.as_ptr().add(i + 8) as *const v128);
+            let vb2 = v128_load(b.as_ptr().add(i + 8) as *const v128);
+            sum2 = f32x4_add(sum2, f32x4_mul(va2, vb2));
+
+            let va3 = v128_load(a.as_ptr().add(i + 12) as *const v128);
+            let vb3 = v128_load(b.as_ptr().add(i + 12) as *const v128);
+            sum3 = f32x4_add(sum3, f32x4_mul(va3, vb3));
+
+            i += 16;
+        }
+
+        // Remaining multiples of 4 (e.g. 8 of the last 10 dimensions at dim 250)
+        while i < quad_len {
+            let va = v128_load(a.as_ptr().add(i) as *const v128);
+            let vb = v128_load(b.as_ptr().add(i) as *const v128);
+            sum0 = f32x4_add(sum0, f32x4_mul(va, vb));
+            i += 4;
+        }
+
+        let sum_ab = f32x4_add(f32x4_add(sum0, sum1), f32x4_add(sum2, sum3));
+        let mut result = f32x4_extract_lane::<0>(sum_ab)
+            + f32x4_extract_lane::<1>(sum_ab)
+            + f32x4_extract_lane::<2>(sum_ab)
+            + f32x4_extract_lane::<3>(sum_ab);
+
+        for j in quad_len..len {
+            result += a[j] * b[j];
+        }
+
+        result
+    }
+}
+
+/// Name of the kernel variant `dot_product` dispatches to for `embedding_dim` (for traces)
+pub fn dot_kernel_name(embedding_dim: usize) -> &'static str {
+    if cfg!(target_arch = "wasm32") {
+        match embedding_dim {
+            48 => "simd_dot_48",
+            64 => "simd_dot_64",
+            96 => "simd_dot_96",
+            128 => "simd_dot_128",
+            256 => "simd_dot_256",
+            384 => "simd_dot_384",
+            512 => "simd_dot_512",
+            768 => "simd_dot_768",
+            1024 => "simd_dot_1024",
+            _ => "simd_dot_generic",
+        }
+    } else {
+        crate::native::KERNEL_NAME
+    }
+}
+
+/// Dot product of two equal-length vectors (SIMD on wasm32, specialized for common dims)
+///
+/// Vectors of different lengths are multiplied over their common prefix.
+#[inline]
+pub fn dot_product(a: &[f32], b: &[f32]) -> f32 {
+    #[cfg(target_arch = "wasm32")]
+    {
+        let len = a.len().min(b.len());
+        let (a, b) = (&a[..len], &b[..len]);
+        // SAFETY: both slices hold exactly `len` floats, the dimension of the kernel
+        unsafe {
+            match len {
+                48 => simd_dot_48(a, b),
+                64 => simd_dot_64(a, b),
+                96 => simd_dot_96(a, b),
+                128 => simd_dot_128(a, b),
+                256 => simd_dot_256(a, b),
+                384 => simd_dot_384(a, b),
+                512 => simd_dot_512(a, b),
+                768 => simd_dot_768(a, b),
+                1024 => simd_dot_1024(a, b),
+                _ => simd_dot_generic(a, b),
+            }
+        }
+    }
+    
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        crate::native::dot(a, b)
+    }
+}
+
+// ============================================================================
+// MATRIX MULTIPLICATION with Adaptive Cache Blocking
+// ============================================================================
+
+/// Dot products of every query token with every document token, written row-major
+/// (`query_tokens × doc_tokens`) into `similarities`
+///
+/// Cache blocking: `d_block_size` document tokens at a time, chosen by document length
+/// (`default_d_block`, or a per-device calibration).
+#[inline]
+pub fn matrix_multiply(
+    query_flat: &[f32],
+    doc_flat: &[f32],
+    similarities: &mut [f32],
+    query_tokens: usize,
+    doc_tokens: usize,
+    embedding_dim: usize,
+    d_block_size: usize,
+) {
+    let q_block_size = 8;
+
+    for q_block in (0..query_tokens).step_by(q_block_size) {
+        let q_end = (q_block + q_block_size).min(query_tokens);
+        
+        for d_block in (0..doc_tokens).step_by(d_block_size) {
+            let d_end = (d_block + d_block_size).min(doc_tokens);
+            
+            for q_idx in q_block..q_end {
+                let query_start = q_idx * embedding_dim;
+                let query_token = &query_flat[query_start..query_start + embedding_dim];
+                
+                for d_idx in d_block..d_end {
+                    let doc_start = d_idx * embedding_dim;
+                    let doc_token = &doc_flat[doc_start..doc_start + embedding_dim];
+                    
+                    let similarity = dot_product(query_token, doc_token);
+                    
+                    similarities[q_idx * doc_tokens + d_idx] = similarity;
+                }
+            }
+        }
+    }
+}
+
+/// Dot product accumulated in f64 (accuracy mode; scalar, several times slower)
+///
+/// Long f32 sums drift by up to `dim · ε` relative to the exact value; f64
+/// accumulation leaves a single rounding to f32 at the end, matching references
+/// that compute in f64 or with pairwise/Kahan summation.
+#[inline]
+pub fn dot_product_f64(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(&x, &y)| x as f64 * y as f64).sum::<f64>() as f32
+}
+
+/// `matrix_multiply` with `dot_product_f64` (no blocking: the scalar kernel dominates)
+#[inline]
+pub fn matrix_multiply_f64(
+    query_flat: &[f32],
+    doc_flat: &[f32],
+    similarities: &mut [f32],
+    query_tokens: usize,
+    doc_tokens: usize,
+    embedding_dim: usize,
+) {
+    for (q_idx, query_token) in query_flat.chunks_exact(embedding_dim).take(query_tokens).enumerate() {
+        let row = &mut similarities[q_idx * doc_tokens..(q_idx + 1) * doc_tokens];
+        for (similarity, doc_token) in row.iter_mut().zip(doc_flat.chunks_exact(embedding_dim)) {
+            *similarity = dot_product_f64(query_token, doc_token);
+        }
+    }
+}
+
+/// `matrix_multiply` over the first `search_dim` dimensions only (Matryoshka search)
+///
+/// `query_prefix` holds the already truncated query (`query_tokens × search_dim`);
+/// document tokens are read in place with stride `embedding_dim`, so truncated
+/// scoring never copies the documents.
+#[inline]
+pub fn prefix_similarities(
+    query_prefix: &[f32],
+    doc_flat: &[f32],
+    similarities: &mut [f32],
+    query_tokens: usize,
+    doc_tokens: usize,
+    embedding_dim: usize,
+    search_dim: usize,
+) {
+    for q_idx in 0..query_tokens {
+        let query_token = &query_prefix[q_idx * search_dim..(q_idx + 1) * search_dim];
+        let row = &mut similarities[q_idx * doc_tokens..(q_idx + 1) * doc_tokens];
+        for (similarity, doc_token) in row.iter_mut().zip(doc_flat.chunks_exact(embedding_dim)) {
+            *similarity = dot_product(query_token, &doc_token[..search_dim]);
+        }
+    }
+}
+
+// ============================================================================
+// SIMD MAX FINDING
+// ============================================================================
+
+/// Largest value of `slice` (`f32::NEG_INFINITY` when empty)
+#[cfg(target_arch = "wasm32")]
+#[inline]
+pub fn simd_max(slice: &[f32]) -> f32 {
+    let len = slice.len();
+    
+    if len < 32 {
+        return slice.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    }
+
+    let simd_len = len - (len % 32);
+
+    unsafe {
+        let mut max0 = f32x4_splat(f32::NEG_INFINITY);
+        let mut max1 = f32x4_splat(f32::NEG_INFINITY);
+        let mut max2 = f32x4_splat(f32::NEG_INFINITY);
+        let mut max3 = f32x4_splat(f32::NEG_INFINITY);
+        let mut max4 = f32x4_splat(f32::NEG_INFINITY);
+        let mut max5 = f32x4_splat(f32::NEG_INFINITY);
+        let mut max6 = f32x4_splat(f32::NEG_INFINITY);
+        let mut max7 = f32x4_splat(f32::NEG_INFINITY);
+
+        let mut i = 0;
+        while i < simd_len {
+            let data0 = v128_load(slice.as_ptr().add(i) as *const v128);
+            let data1 = v128_load(slice.as_ptr().add(i + 4) as *const v128);
+            let data2 = v128_load(slice.as_ptr().add(i + 8) as *const v128);
+            let data3 = v128_load(slice.as_ptr().add(i + 12) as *const v128);
+            let data4 = v128_load(slice.as_ptr().add(i + 16) as *const v128);
+            let data5 = v128_load(slice.as_ptr().add(i + 20) as *const v128);
+            let data6 = v128_load(slice.as_ptr().add(i + 24) as *const v128);
+            let data7 = v128_load(slice.as_ptr().add(i + 28) as *const v128);
+
+            max0 = f32x4_pmax(max0, data0);
+            max1 = f32x4_pmax(max1, data1);
+            max2 = f32x4_pmax(max2, data2);
+            max3 = f32x4_pmax(max3, data3);
+            max4 = f32x4_pmax(max4, data4);
+            max5 = f32x4_pmax(max5, data5);
+            max6 = f32x4_pmax(max6, data6);
+            max7 = f32x4_pmax(max7, data7);
+
+            i += 32;
+        }
+
+        let max_ab = f32x4_pmax(f32x4_pmax(max0, max1), f32x4_pmax(max2, max3));
+        let max_cd = f32x4_pmax(f32x4_pmax(max4, max5), f32x4_pmax(max6, max7));
+        let final_max = f32x4_pmax(max_ab, max_cd);
+
+        let mut result = f32x4_extract_lane::<0>(final_max)
+            .max(f32x4_extract_lane::<1>(final_max))
+            .max(f32x4_extract_lane::<2>(final_max))
+            .max(f32x4_extract_lane::<3>(final_max));
+
+        for &value in &slice[simd_len..] {
+            result = result.max(value);
+        }
+
+        result
+    }
+}
+
+/// Largest value of `slice` (`f32::NEG_INFINITY` when empty)
+#[cfg(not(target_arch = "wasm32"))]
+#[inline]
+pub fn simd_max(slice: &[f32]) -> f32 {
+    crate::native::max(slice)
+}
+
+// ============================================================================
+// SIMD CLAMPING
+// ============================================================================
+
+/// Clamp every similarity to [-1, 1] in place, returning how many were out of range
+#[cfg(target_arch = "wasm32")]
+#[inline]
+pub fn simd_clamp_unit(slice: &mut [f32]) -> usize {
+    let simd_len = slice.len() - (slice.len() % 4);
+    let mut clamped = 0;
+
+    unsafe {
+        let one = f32x4_splat(1.0);
+        let neg_one = f32x4_splat(-1.0);
+        let mut i = 0;
+        while i < simd_len {
+            let ptr = slice.as_mut_ptr().add(i) as *mut v128;
+            let v = v128_load(ptr);
+            let outside = v128_or(f32x4_gt(v, one), f32x4_lt(v, neg_one));
+            clamped += i32x4_bitmask(outside).count_ones() as usize;
+            v128_store(ptr, f32x4_pmin(f32x4_pmax(v, neg_one), one));
+            i += 4;
+        }
+    }
+
+    for value in &mut slice[simd_len..] {
+        if value.abs() > 1.0 {
+            *value = value.clamp(-1.0, 1.0);
+            clamped += 1;
+        }
+    }
+    clamped
+}
+
+/// Clamp every similarity to [-1, 1] in place, returning how many were out of range
+#[cfg(not(target_arch = "wasm32"))]
+#[inline]
+pub fn simd_clamp_unit(slice: &mut [f32]) -> usize {
+    let mut clamped = 0;
+    for value in slice.iter_mut() {
+        if value.abs() > 1.0 {
+            *value = value.clamp(-1.0, 1.0);
+            clamped += 1;
+        }
+    }
+    clamped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn test_dot_product() {
+        let a = [1.0, 2.0, 3.0, 4.0];
+        let b = [2.0, 3.0, 4.0, 5.0];
+        assert_eq!(dot_product(&a, &b), 40.0);
+
+        // Every tail length of the 16-wide generic kernel, specialized dims and odd ones
+        for dim in (1..=40).chain([48, 64, 96, 127, 128, 250, 383, 384, 768, 1023]) {
+            let a: Vec<f32> = (0..dim).map(|i| ((i * 7 % 13) as f32 - 6.0) / 8.0).collect();
+            let b: Vec<f32> = (0..dim).map(|i| ((i * 5 % 11) as f32 - 5.0) / 4.0).collect();
+            let expected: f64 = a.iter().zip(&b).map(|(&x, &y)| x as f64 * y as f64).sum();
+            assert!((dot_product(&a, &b) as f64 - expected).abs() < 1e-4, "dim {}", dim);
+
+            // Different lengths multiply over the common prefix
+            let longer: Vec<f32> = b.iter().copied().chain([3.0; 5]).collect();
+            assert_eq!(dot_product(&a, &longer), dot_product(&a, &b), "dim {}", dim);
+            assert_eq!(dot_product(&longer, &a), dot_product(&a, &b), "dim {}", dim);
+        }
+    }
+
+    #[test]
+    fn test_matrix_multiply_is_independent_of_blocking() {
+        let query = [1.0, 0.0, 0.5, 0.5, -1.0, 2.0];
+        let doc = [0.5, 0.25, 1.0, -1.0, 0.0, 2.0, 3.0, 1.0];
+        let mut blocked = [0.0; 12];
+        let mut unblocked = [0.0; 12];
+        matrix_multiply(&query, &doc, &mut blocked, 3, 4, 2, 1);
+        matrix_multiply(&query, &doc, &mut unblocked, 3, 4, 2, default_d_block(4));
+        assert_eq!(blocked, unblocked);
+        assert_eq!(&blocked[..4], &[0.5, 1.0, 0.0, 3.0]);
+        assert_eq!(simd_max(&blocked[8..]), 4.0);
+        assert_eq!(simd_max(&[]), f32::NEG_INFINITY);
+
+        // The first dimension only: query (1, 0.5, -1) -> 1, 0.5, -1 against doc dim 0
+        let mut prefix = [0.0; 6];
+        prefix_similarities(&[1.0, 0.5, -1.0], &doc, &mut prefix, 3, 2, 4, 1);
+        assert_eq!(prefix, [0.5, 0.0, 0.25, 0.0, -0.5, 0.0]);
+
+        let mut precise = [0.0; 12];
+        matrix_multiply_f64(&query, &doc, &mut precise, 3, 4, 2);
+        assert_eq!(precise, blocked);
+        // 1 + 1e8 - 1e8 loses the 1 in f32 but not in f64
+        assert_eq!(dot_product_f64(&[1.0, 1e8, -1e8], &[1.0, 1.0, 1.0]), 1.0);
+    }
+}
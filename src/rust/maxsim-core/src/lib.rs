@@ -0,0 +1,34 @@
+/*!
+ * MaxSim Core - scoring kernels without a WASM runtime
+ *
+ * The dot-product, similarity-matrix, max and batching code behind `maxsim_web_wasm`,
+ * with no wasm-bindgen or std dependency (`alloc` only), so the same scores can be
+ * computed in a native backend (e.g. Tauri) or in plain `cargo test`.
+ *
+ * - `kernels`: SIMD dot products, cache-blocked `matrix_multiply`, `simd_max`, clamping
+ * - `native`:  AVX2/SSE2/NEON `dot_product` and `simd_max` for non-wasm targets
+ * - `packed`:  block-transposed document layout and its kernel
+ * - `batch`:   length grouping, padded sub-batches and the plain `maxsim_batch` pipeline
+ * - `index`:   the serialized index blob (header, layout, int8 quantization)
+ * - `delta`:   index deltas (documents removed and added since a base index)
+ * - `int4`:    4-bit grouped quantization and its fused dot product
+ *
+ * Inputs are flat row-major f32 arrays (`tokens × embedding_dim`). Build wasm32
+ * targets with `-C target-feature=+simd128`, and x86_64 servers with
+ * `-C target-cpu=native` (or `+avx2,+fma`) for the AVX2 kernels.
+ */
+
+#![no_std]
+
+extern crate alloc;
+
+pub mod batch;
+pub mod delta;
+pub mod index;
+pub mod int4;
+pub mod kernels;
+pub mod native;
+pub mod packed;
+
+pub use batch::maxsim_batch;
+pub use kernels::{dot_product, matrix_multiply, simd_max};
@@ -0,0 +1,209 @@
+/*!
+ * Native SIMD kernels (non-wasm targets)
+ *
+ * `dot_product` and `simd_max` dispatch here outside the browser, so the crate is a
+ * fast native library (e.g. server-side rerank) with the same API. The instruction
+ * set is fixed at compile time, which keeps the crate `no_std`:
+ * - x86_64 with `avx2` and `fma` enabled (`-C target-cpu=native` or
+ *   `-C target-feature=+avx2,+fma`): 256-bit FMA kernels
+ * - other x86_64: SSE2 (part of the x86_64 baseline)
+ * - aarch64: NEON (part of the aarch64 baseline)
+ * - anything else (wasm32 included, which has its own kernels): scalar loops
+ *
+ * Every variant keeps several independent accumulators, so results equal the scalar
+ * loop up to float summation order.
+ */
+
+#[cfg(target_arch = "aarch64")]
+use core::arch::aarch64::*;
+#[cfg(target_arch = "x86_64")]
+use core::arch::x86_64::*;
+
+/// Name of the native kernel family (for traces)
+pub const KERNEL_NAME: &str = if cfg!(all(target_arch = "x86_64", target_feature = "avx2", target_feature = "fma")) {
+    "avx2"
+} else if cfg!(target_arch = "x86_64") {
+    "sse2"
+} else if cfg!(target_arch = "aarch64") {
+    "neon"
+} else {
+    "scalar"
+};
+
+#[cfg(all(target_arch = "x86_64", target_feature = "avx2", target_feature = "fma"))]
+#[inline]
+pub fn dot(a: &[f32], b: &[f32]) -> f32 {
+    let len = a.len().min(b.len());
+    let simd_len = len - len % 16;
+    let mut result = unsafe {
+        let mut sum0 = _mm256_setzero_ps();
+        let mut sum1 = _mm256_setzero_ps();
+        let mut i = 0;
+        while i < simd_len {
+            sum0 = _mm256_fmadd_ps(_mm256_loadu_ps(a.as_ptr().add(i)), _mm256_loadu_ps(b.as_ptr().add(i)), sum0);
+            sum1 = _mm256_fmadd_ps(_mm256_loadu_ps(a.as_ptr().add(i + 8)), _mm256_loadu_ps(b.as_ptr().add(i + 8)), sum1);
+            i += 16;
+        }
+        let sum = _mm256_add_ps(sum0, sum1);
+        let quad = _mm_add_ps(_mm256_castps256_ps128(sum), _mm256_extractf128_ps::<1>(sum));
+        horizontal_sum_sse(quad)
+    };
+    for j in simd_len..len {
+        result += a[j] * b[j];
+    }
+    result
+}
+
+#[cfg(all(target_arch = "x86_64", not(all(target_feature = "avx2", target_feature = "fma"))))]
+#[inline]
+pub fn dot(a: &[f32], b: &[f32]) -> f32 {
+    let len = a.len().min(b.len());
+    let simd_len = len - len % 8;
+    let mut result = unsafe {
+        let mut sum0 = _mm_setzero_ps();
+        let mut sum1 = _mm_setzero_ps();
+        let mut i = 0;
+        while i < simd_len {
+            sum0 = _mm_add_ps(sum0, _mm_mul_ps(_mm_loadu_ps(a.as_ptr().add(i)), _mm_loadu_ps(b.as_ptr().add(i))));
+            sum1 = _mm_add_ps(sum1, _mm_mul_ps(_mm_loadu_ps(a.as_ptr().add(i + 4)), _mm_loadu_ps(b.as_ptr().add(i + 4))));
+            i += 8;
+        }
+        horizontal_sum_sse(_mm_add_ps(sum0, sum1))
+    };
+    for j in simd_len..len {
+        result += a[j] * b[j];
+    }
+    result
+}
+
+#[cfg(target_arch = "x86_64")]
+#[inline]
+fn horizontal_sum_sse(v: __m128) -> f32 {
+    let mut lanes = [0.0f32; 4];
+    unsafe { _mm_storeu_ps(lanes.as_mut_ptr(), v) };
+    (lanes[0] + lanes[1]) + (lanes[2] + lanes[3])
+}
+
+#[cfg(target_arch = "aarch64")]
+#[inline]
+pub fn dot(a: &[f32], b: &[f32]) -> f32 {
+    let len = a.len().min(b.len());
+    let simd_len = len - len % 8;
+    let mut result = unsafe {
+        let mut sum0 = vdupq_n_f32(0.0);
+        let mut sum1 = vdupq_n_f32(0.0);
+        let mut i = 0;
+        while i < simd_len {
+            sum0 = vfmaq_f32(sum0, vld1q_f32(a.as_ptr().add(i)), vld1q_f32(b.as_ptr().add(i)));
+            sum1 = vfmaq_f32(sum1, vld1q_f32(a.as_ptr().add(i + 4)), vld1q_f32(b.as_ptr().add(i + 4)));
+            i += 8;
+        }
+        vaddvq_f32(vaddq_f32(sum0, sum1))
+    };
+    for j in simd_len..len {
+        result += a[j] * b[j];
+    }
+    result
+}
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+#[inline]
+pub fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b.iter()).map(|(x, y)| x * y).sum()
+}
+
+#[cfg(all(target_arch = "x86_64", target_feature = "avx2"))]
+#[inline]
+pub fn max(slice: &[f32]) -> f32 {
+    let simd_len = slice.len() - slice.len() % 16;
+    let mut result = f32::NEG_INFINITY;
+    if simd_len > 0 {
+        unsafe {
+            let mut max0 = _mm256_set1_ps(f32::NEG_INFINITY);
+            let mut max1 = _mm256_set1_ps(f32::NEG_INFINITY);
+            let mut i = 0;
+            while i < simd_len {
+                max0 = _mm256_max_ps(max0, _mm256_loadu_ps(slice.as_ptr().add(i)));
+                max1 = _mm256_max_ps(max1, _mm256_loadu_ps(slice.as_ptr().add(i + 8)));
+                i += 16;
+            }
+            let wide = _mm256_max_ps(max0, max1);
+            result = horizontal_max_sse(_mm_max_ps(_mm256_castps256_ps128(wide), _mm256_extractf128_ps::<1>(wide)));
+        }
+    }
+    slice[simd_len..].iter().copied().fold(result, f32::max)
+}
+
+#[cfg(all(target_arch = "x86_64", not(target_feature = "avx2")))]
+#[inline]
+pub fn max(slice: &[f32]) -> f32 {
+    let simd_len = slice.len() - slice.len() % 8;
+    let mut result = f32::NEG_INFINITY;
+    if simd_len > 0 {
+        unsafe {
+            let mut max0 = _mm_set1_ps(f32::NEG_INFINITY);
+            let mut max1 = _mm_set1_ps(f32::NEG_INFINITY);
+            let mut i = 0;
+            while i < simd_len {
+                max0 = _mm_max_ps(max0, _mm_loadu_ps(slice.as_ptr().add(i)));
+                max1 = _mm_max_ps(max1, _mm_loadu_ps(slice.as_ptr().add(i + 4)));
+                i += 8;
+            }
+            result = horizontal_max_sse(_mm_max_ps(max0, max1));
+        }
+    }
+    slice[simd_len..].iter().copied().fold(result, f32::max)
+}
+
+#[cfg(target_arch = "x86_64")]
+#[inline]
+fn horizontal_max_sse(v: __m128) -> f32 {
+    let mut lanes = [0.0f32; 4];
+    unsafe { _mm_storeu_ps(lanes.as_mut_ptr(), v) };
+    lanes[0].max(lanes[1]).max(lanes[2]).max(lanes[3])
+}
+
+#[cfg(target_arch = "aarch64")]
+#[inline]
+pub fn max(slice: &[f32]) -> f32 {
+    let simd_len = slice.len() - slice.len() % 8;
+    let mut result = f32::NEG_INFINITY;
+    if simd_len > 0 {
+        unsafe {
+            let mut max0 = vdupq_n_f32(f32::NEG_INFINITY);
+            let mut max1 = vdupq_n_f32(f32::NEG_INFINITY);
+            let mut i = 0;
+            while i < simd_len {
+                max0 = vmaxnmq_f32(max0, vld1q_f32(slice.as_ptr().add(i)));
+                max1 = vmaxnmq_f32(max1, vld1q_f32(slice.as_ptr().add(i + 4)));
+                i += 8;
+            }
+            result = vmaxnmvq_f32(vmaxnmq_f32(max0, max1));
+        }
+    }
+    slice[simd_len..].iter().copied().fold(result, f32::max)
+}
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+#[inline]
+pub fn max(slice: &[f32]) -> f32 {
+    slice.iter().copied().fold(f32::NEG_INFINITY, f32::max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn test_native_kernels_match_scalar_loops() {
+        // Lengths around every vector width and unroll factor, tails included
+        for len in [0, 1, 3, 4, 7, 8, 15, 16, 17, 31, 33, 128, 129] {
+            let a: Vec<f32> = (0..len).map(|i| ((i * 7 % 13) as f32 - 6.0) / 4.0).collect();
+            let b: Vec<f32> = (0..len).map(|i| ((i * 5 % 11) as f32 - 5.0) / 8.0).collect();
+            let expected: f32 = a.iter().zip(&b).map(|(x, y)| x * y).sum();
+            assert!((dot(&a, &b) - expected).abs() <= 1e-5 * expected.abs().max(1.0), "len {}", len);
+            assert_eq!(max(&a), a.iter().copied().fold(f32::NEG_INFINITY, f32::max), "len {}", len);
+        }
+    }
+}
@@ -0,0 +1,133 @@
+/*!
+ * Block-transposed ("packed") document layout
+ *
+ * Row-major documents make every similarity a dot product that ends in a horizontal
+ * lane reduction. The packed layout stores each document in blocks of
+ * `BLOCK_TOKENS` tokens, dimension-major within a block:
+ *
+ * ```text
+ * block b: t0[0] t1[0] t2[0] t3[0] | t0[1] t1[1] t2[1] t3[1] | ... | t0[dim-1] .. t3[dim-1]
+ * ```
+ *
+ * so one f32x4 load fetches the same dimension of four document tokens, and a
+ * broadcast query value times that vector accumulates four similarities at once
+ * with no reduction at the end. The last block of a document is zero-padded; its
+ * padding lanes are never written to the similarity rows.
+ *
+ * Similarities equal the row-major kernel's up to float summation order.
+ */
+
+#[cfg(target_arch = "wasm32")]
+use core::arch::wasm32::*;
+
+use alloc::vec::Vec;
+
+/// Document tokens per packed block (one f32x4 vector)
+pub const BLOCK_TOKENS: usize = 4;
+
+/// Floats taken by a document of `tokens` tokens in the packed layout
+pub fn packed_len(tokens: usize, embedding_dim: usize) -> usize {
+    tokens.div_ceil(BLOCK_TOKENS) * BLOCK_TOKENS * embedding_dim
+}
+
+/// Append a row-major document to `out` in the packed layout
+pub fn pack_tokens(doc_flat: &[f32], embedding_dim: usize, out: &mut Vec<f32>) {
+    for block in doc_flat.chunks(BLOCK_TOKENS * embedding_dim) {
+        let start = out.len();
+        out.resize(start + BLOCK_TOKENS * embedding_dim, 0.0);
+        for (t, token) in block.chunks_exact(embedding_dim).enumerate() {
+            for (d, &value) in token.iter().enumerate() {
+                out[start + d * BLOCK_TOKENS + t] = value;
+            }
+        }
+    }
+}
+
+/// Dot products of every query token with every token of one packed document,
+/// written row-major (`query_tokens × doc_tokens`) like `matrix_multiply`
+pub fn packed_similarities(
+    query_flat: &[f32],
+    packed_doc: &[f32],
+    similarities: &mut [f32],
+    query_tokens: usize,
+    doc_tokens: usize,
+    embedding_dim: usize,
+) {
+    let block_floats = BLOCK_TOKENS * embedding_dim;
+    for q_idx in 0..query_tokens {
+        let query = &query_flat[q_idx * embedding_dim..(q_idx + 1) * embedding_dim];
+        let row = &mut similarities[q_idx * doc_tokens..(q_idx + 1) * doc_tokens];
+        for (b, block) in packed_doc.chunks_exact(block_floats).enumerate() {
+            let sums = block_dot(query, block);
+            let start = b * BLOCK_TOKENS;
+            let lanes = (doc_tokens - start).min(BLOCK_TOKENS);
+            row[start..start + lanes].copy_from_slice(&sums[..lanes]);
+        }
+    }
+}
+
+// Similarities of one query token with the four tokens of a packed block
+#[cfg(target_arch = "wasm32")]
+#[inline]
+fn block_dot(query: &[f32], block: &[f32]) -> [f32; BLOCK_TOKENS] {
+    let dim = query.len();
+    let mut sums = [0.0f32; BLOCK_TOKENS];
+    unsafe {
+        // Two accumulators (even and odd dimensions) hide the add latency
+        let mut acc0 = f32x4_splat(0.0);
+        let mut acc1 = f32x4_splat(0.0);
+        let mut d = 0;
+        while d + 1 < dim {
+            let v0 = v128_load(block.as_ptr().add(d * BLOCK_TOKENS) as *const v128);
+            let v1 = v128_load(block.as_ptr().add((d + 1) * BLOCK_TOKENS) as *const v128);
+            acc0 = f32x4_add(acc0, f32x4_mul(f32x4_splat(query[d]), v0));
+            acc1 = f32x4_add(acc1, f32x4_mul(f32x4_splat(query[d + 1]), v1));
+            d += 2;
+        }
+        if d < dim {
+            let v0 = v128_load(block.as_ptr().add(d * BLOCK_TOKENS) as *const v128);
+            acc0 = f32x4_add(acc0, f32x4_mul(f32x4_splat(query[d]), v0));
+        }
+        v128_store(sums.as_mut_ptr() as *mut v128, f32x4_add(acc0, acc1));
+    }
+    sums
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[inline]
+fn block_dot(query: &[f32], block: &[f32]) -> [f32; BLOCK_TOKENS] {
+    let mut sums = [0.0f32; BLOCK_TOKENS];
+    for (&q, lanes) in query.iter().zip(block.chunks_exact(BLOCK_TOKENS)) {
+        for (sum, &value) in sums.iter_mut().zip(lanes) {
+            *sum += q * value;
+        }
+    }
+    sums
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn test_packed_similarities_match_row_major() {
+        let dim = 3;
+        let doc: Vec<f32> = (0..6 * dim).map(|v| v as f32 * 0.25 - 1.0).collect(); // 6 tokens
+        let query = [1.0, -0.5, 2.0, 0.0, 1.0, 0.5];
+
+        let mut packed = Vec::new();
+        pack_tokens(&doc, dim, &mut packed);
+        assert_eq!(packed.len(), packed_len(6, dim));
+        assert_eq!(&packed[..4], &[doc[0], doc[3], doc[6], doc[9]]); // Dimension 0 of tokens 0-3
+
+        let mut similarities = vec![0.0; 2 * 6];
+        packed_similarities(&query, &packed, &mut similarities, 2, 6, dim);
+        for q in 0..2 {
+            for t in 0..6 {
+                let expected: f32 = (0..dim).map(|d| query[q * dim + d] * doc[t * dim + d]).sum();
+                assert!((similarities[q * 6 + t] - expected).abs() < 1e-6);
+            }
+        }
+    }
+}
@@ -0,0 +1,47 @@
+/*
+ * maxsim.h - C ABI of the maxsim-core scoring functions
+ *
+ * Link against libmaxsim.a (iOS) or libmaxsim.so (Android, desktop), built with
+ * `cargo build --release -p maxsim-ffi` for the target triple.
+ *
+ * Embeddings are flat row-major float arrays (tokens x dim); documents are stored
+ * back to back with doc_tokens[i] tokens each. Output buffers are caller-owned.
+ */
+
+#ifndef MAXSIM_H
+#define MAXSIM_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define MAXSIM_OK 0
+#define MAXSIM_NULL_POINTER (-1)
+#define MAXSIM_INVALID_SIZE (-2)
+
+/* Official MaxSim of one document (raw sum of per-query-token maxima); NaN on invalid input */
+float maxsim_score(const float *query, size_t query_tokens,
+                   const float *doc, size_t doc_tokens, size_t dim);
+
+/* maxsim_score averaged over query tokens */
+float maxsim_score_normalized(const float *query, size_t query_tokens,
+                              const float *doc, size_t doc_tokens, size_t dim);
+
+/* Official MaxSim of num_docs documents into out_scores[num_docs]; returns a MAXSIM_* status */
+int32_t maxsim_batch(const float *query, size_t query_tokens,
+                     const float *docs, const size_t *doc_tokens, size_t num_docs,
+                     size_t dim, float *out_scores);
+
+/* maxsim_batch averaged over query tokens */
+int32_t maxsim_batch_normalized(const float *query, size_t query_tokens,
+                                const float *docs, const size_t *doc_tokens, size_t num_docs,
+                                size_t dim, float *out_scores);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* MAXSIM_H */
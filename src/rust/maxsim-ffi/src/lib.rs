@@ -0,0 +1,153 @@
+/*!
+ * MaxSim FFI - C ABI over maxsim-core
+ *
+ * `extern "C"` entry points for embedding the scoring code in native apps (Swift on
+ * iOS via the staticlib, Kotlin/JNI on Android via the cdylib). The algorithm is
+ * `maxsim_core::maxsim_batch`, so scores equal the WASM build's up to float
+ * summation order. The declarations are in `include/maxsim.h`.
+ *
+ * Conventions:
+ * - Embeddings are flat row-major float arrays (`tokens × dim`), documents back to back
+ * - Status codes: `MAXSIM_OK` (0), `MAXSIM_NULL_POINTER` (-1), `MAXSIM_INVALID_SIZE` (-2)
+ * - `maxsim_score*` return NaN instead of a status on invalid input
+ * - Nothing is allocated for the caller; output buffers are caller-owned
+ */
+
+use std::slice;
+
+pub const MAXSIM_OK: i32 = 0;
+pub const MAXSIM_NULL_POINTER: i32 = -1;
+pub const MAXSIM_INVALID_SIZE: i32 = -2;
+
+/// Official MaxSim of one document (raw sum of per-query-token maxima), NaN on invalid input
+///
+/// # Safety
+/// `query` must point to `query_tokens × dim` floats and `doc` to `doc_tokens × dim` floats.
+#[no_mangle]
+pub unsafe extern "C" fn maxsim_score(
+    query: *const f32,
+    query_tokens: usize,
+    doc: *const f32,
+    doc_tokens: usize,
+    dim: usize,
+) -> f32 {
+    score_impl(query, query_tokens, doc, doc_tokens, dim, false)
+}
+
+/// Normalized variant of `maxsim_score` (averaged over query tokens)
+///
+/// # Safety
+/// Same as `maxsim_score`.
+#[no_mangle]
+pub unsafe extern "C" fn maxsim_score_normalized(
+    query: *const f32,
+    query_tokens: usize,
+    doc: *const f32,
+    doc_tokens: usize,
+    dim: usize,
+) -> f32 {
+    score_impl(query, query_tokens, doc, doc_tokens, dim, true)
+}
+
+/// Official MaxSim of `num_docs` documents, written to `out_scores` in document order
+///
+/// # Safety
+/// `query` must point to `query_tokens × dim` floats, `doc_tokens` and `out_scores` to
+/// `num_docs` elements, and `docs` to `Σ doc_tokens × dim` floats.
+#[no_mangle]
+pub unsafe extern "C" fn maxsim_batch(
+    query: *const f32,
+    query_tokens: usize,
+    docs: *const f32,
+    doc_tokens: *const usize,
+    num_docs: usize,
+    dim: usize,
+    out_scores: *mut f32,
+) -> i32 {
+    batch_impl(query, query_tokens, docs, doc_tokens, num_docs, dim, out_scores, false)
+}
+
+/// Normalized variant of `maxsim_batch`
+///
+/// # Safety
+/// Same as `maxsim_batch`.
+#[no_mangle]
+pub unsafe extern "C" fn maxsim_batch_normalized(
+    query: *const f32,
+    query_tokens: usize,
+    docs: *const f32,
+    doc_tokens: *const usize,
+    num_docs: usize,
+    dim: usize,
+    out_scores: *mut f32,
+) -> i32 {
+    batch_impl(query, query_tokens, docs, doc_tokens, num_docs, dim, out_scores, true)
+}
+
+unsafe fn score_impl(query: *const f32, query_tokens: usize, doc: *const f32, doc_tokens: usize, dim: usize, normalized: bool) -> f32 {
+    let mut score = f32::NAN;
+    match batch_impl(query, query_tokens, doc, &doc_tokens, 1, dim, &mut score, normalized) {
+        MAXSIM_OK => score,
+        _ => f32::NAN,
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+unsafe fn batch_impl(
+    query: *const f32,
+    query_tokens: usize,
+    docs: *const f32,
+    doc_tokens: *const usize,
+    num_docs: usize,
+    dim: usize,
+    out_scores: *mut f32,
+    normalized: bool,
+) -> i32 {
+    if query.is_null() || doc_tokens.is_null() || out_scores.is_null() || (docs.is_null() && num_docs > 0) {
+        return MAXSIM_NULL_POINTER;
+    }
+    let Some(query_floats) = query_tokens.checked_mul(dim).filter(|&floats| floats > 0) else {
+        return MAXSIM_INVALID_SIZE;
+    };
+    let doc_tokens = slice::from_raw_parts(doc_tokens, num_docs);
+    let Some(total_floats) = doc_tokens
+        .iter()
+        .try_fold(0usize, |total, &tokens| total.checked_add(tokens))
+        .and_then(|tokens| tokens.checked_mul(dim))
+    else {
+        return MAXSIM_INVALID_SIZE;
+    };
+    let query = slice::from_raw_parts(query, query_floats);
+    let docs = if total_floats == 0 { &[][..] } else { slice::from_raw_parts(docs, total_floats) };
+    let out_scores = slice::from_raw_parts_mut(out_scores, num_docs);
+
+    let scores = maxsim_core::maxsim_batch(query, query_tokens, docs, doc_tokens, dim);
+    for (out, score) in out_scores.iter_mut().zip(scores) {
+        *out = if normalized { score / query_tokens as f32 } else { score };
+    }
+    MAXSIM_OK
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_c_entry_points_match_the_core() {
+        let query = [1.0, 0.0, 0.5, 0.5];
+        let docs = [0.5, 0.25, 0.0, 1.0, -1.0, 0.0, 0.0, 0.0];
+        let doc_tokens = [3usize, 1];
+        let mut scores = [0.0f32; 2];
+        unsafe {
+            assert_eq!(maxsim_batch(query.as_ptr(), 2, docs.as_ptr(), doc_tokens.as_ptr(), 2, 2, scores.as_mut_ptr()), MAXSIM_OK);
+            assert_eq!(scores.to_vec(), maxsim_core::maxsim_batch(&query, 2, &docs, &doc_tokens, 2));
+            assert_eq!(maxsim_score(query.as_ptr(), 2, docs.as_ptr(), 3, 2), scores[0]);
+            assert_eq!(maxsim_score_normalized(query.as_ptr(), 2, docs.as_ptr(), 3, 2), scores[0] / 2.0);
+
+            let null = std::ptr::null();
+            assert_eq!(maxsim_batch(null, 2, docs.as_ptr(), doc_tokens.as_ptr(), 2, 2, scores.as_mut_ptr()), MAXSIM_NULL_POINTER);
+            assert_eq!(maxsim_batch(query.as_ptr(), 0, docs.as_ptr(), doc_tokens.as_ptr(), 2, 2, scores.as_mut_ptr()), MAXSIM_INVALID_SIZE);
+            assert!(maxsim_score(null, 2, docs.as_ptr(), 3, 2).is_nan());
+        }
+    }
+}
@@ -0,0 +1,215 @@
+/*!
+ * MaxSim Node - native N-API addon
+ *
+ * Server-side Node deployments load this addon instead of the WASM module: the same
+ * `maxsim-core` kernels run natively (AVX2/SSE2/NEON, see maxsim-core's native.rs)
+ * without the WASM sandbox. N-API and wasm-bindgen exports cannot share one cdylib,
+ * so the bindings are a separate crate built with `npm run build` (napi-rs CLI).
+ *
+ * The class is exported as `MaxSimNative`; its methods carry the same names and
+ * arguments as the core of `MaxSimWasm`, so JS callers swap the constructor only:
+ * - `maxsim_single` / `maxsim_batch`: official MaxSim (raw sum)
+ * - `*_normalized`: averaged over query tokens
+ * - `load_documents` + `search_preloaded*`: keep a corpus in native memory
+ *
+ * Arrays are flat row-major Float32Arrays; token counts are Uint32Arrays. Scores are
+ * identical to the WASM build up to float summation order of the SIMD kernels.
+ *
+ * Not exposed (WASM build only): metrics other than dot product, instance
+ * configuration (aggregations, score norms, query limits, stopmasks, token masks,
+ * auto-normalization), top-k hits and thresholds, approximate indexes (ANN, HNSW,
+ * PQ, int4, residual, pooled, token centroids), the MXSI index and snapshot
+ * formats, async and cancellable searches, traces and profiles. `tests/` runs a
+ * smoke test of the built addon (`npm test`).
+ */
+
+use napi::bindgen_prelude::*;
+use napi_derive::napi;
+
+/// Native MaxSim engine
+#[napi]
+#[derive(Default)]
+pub struct MaxSimNative {
+    documents: Vec<f32>,
+    doc_tokens: Vec<usize>,
+    embedding_dim: usize,
+}
+
+#[napi]
+impl MaxSimNative {
+    #[napi(constructor)]
+    pub fn new() -> Self {
+        MaxSimNative::default()
+    }
+
+    /// Official MaxSim of one document: raw sum of per-query-token maxima
+    #[napi(js_name = "maxsim_single")]
+    pub fn maxsim_single(
+        &self,
+        query_flat: Float32Array,
+        query_tokens: u32,
+        doc_flat: Float32Array,
+        doc_tokens: u32,
+        embedding_dim: u32,
+    ) -> Result<f64> {
+        let scores = batch_impl(&query_flat, query_tokens, &doc_flat, &[doc_tokens], embedding_dim, false)
+            .map_err(invalid_arg)?;
+        Ok(scores[0] as f64)
+    }
+
+    /// Normalized variant of `maxsim_single`
+    #[napi(js_name = "maxsim_single_normalized")]
+    pub fn maxsim_single_normalized(
+        &self,
+        query_flat: Float32Array,
+        query_tokens: u32,
+        doc_flat: Float32Array,
+        doc_tokens: u32,
+        embedding_dim: u32,
+    ) -> Result<f64> {
+        let scores = batch_impl(&query_flat, query_tokens, &doc_flat, &[doc_tokens], embedding_dim, true)
+            .map_err(invalid_arg)?;
+        Ok(scores[0] as f64)
+    }
+
+    /// Official MaxSim of documents stored back to back (`doc_tokens` tokens each)
+    #[napi(js_name = "maxsim_batch")]
+    pub fn maxsim_batch(
+        &self,
+        query_flat: Float32Array,
+        query_tokens: u32,
+        doc_flat: Float32Array,
+        doc_tokens: Uint32Array,
+        embedding_dim: u32,
+    ) -> Result<Float32Array> {
+        batch_impl(&query_flat, query_tokens, &doc_flat, &doc_tokens, embedding_dim, false)
+            .map(Float32Array::new)
+            .map_err(invalid_arg)
+    }
+
+    /// Normalized variant of `maxsim_batch`
+    #[napi(js_name = "maxsim_batch_normalized")]
+    pub fn maxsim_batch_normalized(
+        &self,
+        query_flat: Float32Array,
+        query_tokens: u32,
+        doc_flat: Float32Array,
+        doc_tokens: Uint32Array,
+        embedding_dim: u32,
+    ) -> Result<Float32Array> {
+        batch_impl(&query_flat, query_tokens, &doc_flat, &doc_tokens, embedding_dim, true)
+            .map(Float32Array::new)
+            .map_err(invalid_arg)
+    }
+
+    /// Copy a corpus into native memory for `search_preloaded`
+    #[napi(js_name = "load_documents")]
+    pub fn load_documents(&mut self, embeddings_data: Float32Array, doc_tokens: Uint32Array, embedding_dim: u32) -> Result<()> {
+        let doc_tokens: Vec<usize> = doc_tokens.iter().map(|&tokens| tokens as usize).collect();
+        let embedding_dim = embedding_dim as usize;
+        if doc_tokens.is_empty() {
+            return Err(invalid_arg("No documents to load".to_string()));
+        }
+        check_documents(&embeddings_data, &doc_tokens, embedding_dim).map_err(invalid_arg)?;
+        self.documents = embeddings_data.to_vec();
+        self.doc_tokens = doc_tokens;
+        self.embedding_dim = embedding_dim;
+        Ok(())
+    }
+
+    /// Official MaxSim of the loaded documents (one score per document, load order)
+    #[napi(js_name = "search_preloaded")]
+    pub fn search_preloaded(&self, query_flat: Float32Array, query_tokens: u32) -> Result<Float32Array> {
+        self.search_impl(&query_flat, query_tokens as usize, false).map(Float32Array::new).map_err(invalid_arg)
+    }
+
+    /// Normalized variant of `search_preloaded`
+    #[napi(js_name = "search_preloaded_normalized")]
+    pub fn search_preloaded_normalized(&self, query_flat: Float32Array, query_tokens: u32) -> Result<Float32Array> {
+        self.search_impl(&query_flat, query_tokens as usize, true).map(Float32Array::new).map_err(invalid_arg)
+    }
+
+    #[napi(js_name = "num_docs")]
+    pub fn num_docs(&self) -> u32 {
+        self.doc_tokens.len() as u32
+    }
+
+    #[napi(js_name = "get_info")]
+    pub fn get_info(&self) -> String {
+        format!(
+            "MaxSim Node v{} (native kernels: {}, methods: maxsim + maxsim_normalized + preloading)",
+            env!("CARGO_PKG_VERSION"),
+            maxsim_core::native::KERNEL_NAME
+        )
+    }
+}
+
+impl MaxSimNative {
+    fn search_impl(&self, query_flat: &[f32], query_tokens: usize, normalized: bool) -> std::result::Result<Vec<f32>, String> {
+        if self.doc_tokens.is_empty() {
+            return Err("No documents loaded. Call load_documents() first.".to_string());
+        }
+        check_query(query_flat, query_tokens, self.embedding_dim)?;
+        Ok(scores(query_flat, query_tokens, &self.documents, &self.doc_tokens, self.embedding_dim, normalized))
+    }
+}
+
+// Validate and score one call's documents
+fn batch_impl(
+    query_flat: &[f32],
+    query_tokens: u32,
+    doc_flat: &[f32],
+    doc_tokens: &[u32],
+    embedding_dim: u32,
+    normalized: bool,
+) -> std::result::Result<Vec<f32>, String> {
+    let doc_tokens: Vec<usize> = doc_tokens.iter().map(|&tokens| tokens as usize).collect();
+    let (query_tokens, embedding_dim) = (query_tokens as usize, embedding_dim as usize);
+    check_query(query_flat, query_tokens, embedding_dim)?;
+    check_documents(doc_flat, &doc_tokens, embedding_dim)?;
+    Ok(scores(query_flat, query_tokens, doc_flat, &doc_tokens, embedding_dim, normalized))
+}
+
+fn scores(
+    query_flat: &[f32],
+    query_tokens: usize,
+    doc_flat: &[f32],
+    doc_tokens: &[usize],
+    embedding_dim: usize,
+    normalized: bool,
+) -> Vec<f32> {
+    let mut scores = maxsim_core::maxsim_batch(query_flat, query_tokens, doc_flat, doc_tokens, embedding_dim);
+    if normalized {
+        scores.iter_mut().for_each(|score| *score /= query_tokens as f32);
+    }
+    scores
+}
+
+// Validate flat document embeddings against their token counts
+fn check_documents(embeddings_data: &[f32], doc_tokens: &[usize], embedding_dim: usize) -> std::result::Result<(), String> {
+    if embedding_dim == 0 {
+        return Err("Embedding dimension must be > 0".to_string());
+    }
+    let expected_size = doc_tokens
+        .iter()
+        .try_fold(0usize, |size, &count| count.checked_mul(embedding_dim).and_then(|n| size.checked_add(n)));
+    if expected_size != Some(embeddings_data.len()) {
+        return Err("Embeddings data size mismatch".to_string());
+    }
+    Ok(())
+}
+
+// Validate a query against the embedding dimension
+fn check_query(query_flat: &[f32], query_tokens: usize, embedding_dim: usize) -> std::result::Result<(), String> {
+    if query_tokens == 0 {
+        return Err("Query cannot be empty".to_string());
+    }
+    if query_tokens.checked_mul(embedding_dim) != Some(query_flat.len()) {
+        return Err("Query size mismatch".to_string());
+    }
+    Ok(())
+}
+
+fn invalid_arg(message: String) -> Error {
+    Error::new(Status::InvalidArg, message)
+}
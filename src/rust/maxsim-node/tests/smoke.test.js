@@ -0,0 +1,66 @@
+// Smoke test of the built addon against a plain JS reference (run with `npm test` after `npm run build`)
+
+const test = require('node:test');
+const assert = require('node:assert');
+const { MaxSimNative } = require('../index.js');
+
+function referenceMaxsim(query, queryTokens, docs, docTokens, dim) {
+  const scores = [];
+  let offset = 0;
+  for (const tokens of docTokens) {
+    let score = 0;
+    for (let q = 0; q < queryTokens; q++) {
+      let best = -Infinity;
+      for (let d = 0; d < tokens; d++) {
+        let dot = 0;
+        for (let i = 0; i < dim; i++) dot += query[q * dim + i] * docs[(offset + d) * dim + i];
+        best = Math.max(best, dot);
+      }
+      score += best;
+    }
+    scores.push(score);
+    offset += tokens;
+  }
+  return scores;
+}
+
+function sample(dim = 48) {
+  // Values k / 64: dot products are exact in f32, so scores compare exactly
+  let state = 2048;
+  const value = () => ((state = (Math.imul(state, 1103515245) + 12345) >>> 0) >>> 16) % 129 / 64 - 1;
+  const docTokens = Uint32Array.from([3, 17, 17, 18, 20, 64, 1]);
+  const total = docTokens.reduce((sum, tokens) => sum + tokens, 0);
+  const query = Float32Array.from({ length: 8 * dim }, value);
+  const docs = Float32Array.from({ length: total * dim }, value);
+  return { query, docs, docTokens, dim };
+}
+
+test('batch and single scores match the reference', () => {
+  const { query, docs, docTokens, dim } = sample();
+  const engine = new MaxSimNative();
+  const expected = referenceMaxsim(query, 8, docs, docTokens, dim);
+  assert.deepStrictEqual(Array.from(engine.maxsim_batch(query, 8, docs, docTokens, dim)), expected);
+  assert.deepStrictEqual(
+    Array.from(engine.maxsim_batch_normalized(query, 8, docs, docTokens, dim)),
+    expected.map((score) => Math.fround(score / 8))
+  );
+  assert.strictEqual(engine.maxsim_single(query, 8, docs.subarray(0, 3 * dim), 3, dim), expected[0]);
+});
+
+test('preloaded search matches the batch scores', () => {
+  const { query, docs, docTokens, dim } = sample();
+  const engine = new MaxSimNative();
+  assert.throws(() => engine.search_preloaded(query, 8), /No documents loaded/);
+  engine.load_documents(docs, docTokens, dim);
+  assert.strictEqual(engine.num_docs(), docTokens.length);
+  assert.deepStrictEqual(engine.search_preloaded(query, 8), engine.maxsim_batch(query, 8, docs, docTokens, dim));
+  assert.match(engine.get_info(), /^MaxSim Node v/);
+});
+
+test('shape errors are rejected', () => {
+  const { query, docs, docTokens, dim } = sample();
+  const engine = new MaxSimNative();
+  assert.throws(() => engine.maxsim_batch(query, 7, docs, docTokens, dim), /Query size mismatch/);
+  assert.throws(() => engine.maxsim_batch(query, 8, docs.subarray(1), docTokens, dim), /size mismatch/);
+  assert.throws(() => engine.load_documents(docs, new Uint32Array(), dim), /No documents to load/);
+});
@@ -0,0 +1,163 @@
+/*!
+ * MaxSim Python - pyo3 bindings over maxsim-core
+ *
+ * Build indexes offline in Python and check score parity with the browser. Indexes
+ * are written by `maxsim_core::index`, the same code behind `MaxSimWasm.serialize_index`,
+ * so a blob from `serialize_index` is byte-identical to the WASM build's for the same
+ * corpus and decodes (int8 included) to exactly the floats `load_index` sees in the
+ * browser. Scores use the same `maxsim_batch` pipeline; the native SIMD kernels only
+ * differ from the wasm32 ones in float summation order (~1e-6 relative).
+ *
+ * Documents are lists of `(tokens, dim)` float32 arrays; any other float dtype or
+ * memory order must be converted by the caller (`np.ascontiguousarray(x, np.float32)`).
+ * Built with maturin (`maturin build --release`), outside the cargo workspace.
+ */
+
+use maxsim_core::index::{self, Encoding};
+use numpy::{PyArray1, PyArray2, PyArrayMethods, PyReadonlyArray2, PyUntypedArrayMethods};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+/// Official MaxSim of each document (raw sum of per-query-token maxima, or averaged
+/// over query tokens with `normalized=True`)
+#[pyfunction]
+#[pyo3(signature = (query, docs, normalized = false))]
+fn maxsim_batch<'py>(
+    py: Python<'py>,
+    query: PyReadonlyArray2<'py, f32>,
+    docs: Vec<PyReadonlyArray2<'py, f32>>,
+    normalized: bool,
+) -> PyResult<Bound<'py, PyArray1<f32>>> {
+    let corpus = Corpus::from_arrays(&docs)?;
+    let scores = corpus.scores(&query, normalized)?;
+    Ok(PyArray1::from_vec_bound(py, scores))
+}
+
+/// Serialize documents into the `MXSI` index blob read by `MaxSimWasm.load_index()`
+///
+/// `quantized=True` writes int8 embeddings with a per-token scale (same as
+/// `serialize_index_quantized()`).
+#[pyfunction]
+#[pyo3(signature = (docs, quantized = false))]
+fn serialize_index<'py>(
+    py: Python<'py>,
+    docs: Vec<PyReadonlyArray2<'py, f32>>,
+    quantized: bool,
+) -> PyResult<Bound<'py, PyBytes>> {
+    let corpus = Corpus::from_arrays(&docs)?;
+    let encoding = if quantized { Encoding::Int8 } else { Encoding::F32 };
+    let bytes = index::serialize(&corpus.flat, &corpus.doc_tokens, corpus.embedding_dim, encoding);
+    Ok(PyBytes::new_bound(py, &bytes))
+}
+
+/// Delta turning the `base` index blob into the `updated` one, applied in the browser
+/// with `MaxSimWasm.apply_index_delta()` (see `maxsim_core::delta`)
+#[pyfunction]
+fn index_delta<'py>(py: Python<'py>, base: &[u8], updated: &[u8]) -> PyResult<Bound<'py, PyBytes>> {
+    let delta = maxsim_core::delta::create(base, updated).map_err(|e| PyValueError::new_err(e.to_string()))?;
+    Ok(PyBytes::new_bound(py, &delta))
+}
+
+/// Decode an index blob into one `(tokens, dim)` array per document, exactly as the
+/// browser holds it after `load_index()` (int8 indexes come back dequantized)
+#[pyfunction]
+fn load_index<'py>(py: Python<'py>, data: &[u8]) -> PyResult<Vec<Bound<'py, PyArray2<f32>>>> {
+    let corpus = Corpus::from_index(data)?;
+    let mut offset = 0;
+    corpus
+        .doc_tokens
+        .iter()
+        .map(|&tokens| {
+            let doc = &corpus.flat[offset..offset + tokens * corpus.embedding_dim];
+            offset += doc.len();
+            PyArray1::from_slice_bound(py, doc).reshape([tokens, corpus.embedding_dim])
+        })
+        .collect()
+}
+
+/// Score a query against an index blob; the reference for `search_preloaded()` after
+/// `load_index()` in the browser
+#[pyfunction]
+#[pyo3(signature = (data, query, normalized = false))]
+fn search_index<'py>(
+    py: Python<'py>,
+    data: &[u8],
+    query: PyReadonlyArray2<'py, f32>,
+    normalized: bool,
+) -> PyResult<Bound<'py, PyArray1<f32>>> {
+    let corpus = Corpus::from_index(data)?;
+    let scores = corpus.scores(&query, normalized)?;
+    Ok(PyArray1::from_vec_bound(py, scores))
+}
+
+/// Native kernel family in use ("avx2", "sse2", "neon" or "scalar")
+#[pyfunction]
+fn kernel_name() -> &'static str {
+    maxsim_core::native::KERNEL_NAME
+}
+
+#[pymodule]
+fn maxsim_web(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add("__version__", env!("CARGO_PKG_VERSION"))?;
+    m.add("INDEX_FORMAT_VERSION", index::FORMAT_VERSION)?;
+    m.add_function(wrap_pyfunction!(maxsim_batch, m)?)?;
+    m.add_function(wrap_pyfunction!(serialize_index, m)?)?;
+    m.add_function(wrap_pyfunction!(load_index, m)?)?;
+    m.add_function(wrap_pyfunction!(index_delta, m)?)?;
+    m.add_function(wrap_pyfunction!(search_index, m)?)?;
+    m.add_function(wrap_pyfunction!(kernel_name, m)?)?;
+    Ok(())
+}
+
+/// Documents stored back to back, as the WASM build keeps them
+struct Corpus {
+    flat: Vec<f32>,
+    doc_tokens: Vec<usize>,
+    embedding_dim: usize,
+}
+
+impl Corpus {
+    fn from_arrays(docs: &[PyReadonlyArray2<'_, f32>]) -> PyResult<Self> {
+        let embedding_dim = docs
+            .first()
+            .map(|doc| doc.shape()[1])
+            .ok_or_else(|| PyValueError::new_err("No documents"))?;
+        if embedding_dim == 0 {
+            return Err(PyValueError::new_err("Embedding dimension must be > 0"));
+        }
+
+        let mut corpus = Corpus { flat: Vec::new(), doc_tokens: Vec::with_capacity(docs.len()), embedding_dim };
+        for doc in docs {
+            if doc.shape()[1] != embedding_dim {
+                return Err(PyValueError::new_err("Embedding dimension mismatch"));
+            }
+            corpus.doc_tokens.push(doc.shape()[0]);
+            // Logical (row-major) order whatever the array's strides
+            corpus.flat.extend(doc.as_array().iter().copied());
+        }
+        Ok(corpus)
+    }
+
+    fn from_index(data: &[u8]) -> PyResult<Self> {
+        let (header, flat) = index::deserialize(data).map_err(|e| PyValueError::new_err(e.to_string()))?;
+        Ok(Corpus { flat, doc_tokens: header.doc_tokens, embedding_dim: header.embedding_dim })
+    }
+
+    fn scores(&self, query: &PyReadonlyArray2<'_, f32>, normalized: bool) -> PyResult<Vec<f32>> {
+        let query_tokens = query.shape()[0];
+        if query_tokens == 0 {
+            return Err(PyValueError::new_err("Query cannot be empty"));
+        }
+        if query.shape()[1] != self.embedding_dim {
+            return Err(PyValueError::new_err("Query size mismatch"));
+        }
+
+        let query_flat: Vec<f32> = query.as_array().iter().copied().collect();
+        let mut scores = maxsim_core::maxsim_batch(&query_flat, query_tokens, &self.flat, &self.doc_tokens, self.embedding_dim);
+        if normalized {
+            scores.iter_mut().for_each(|score| *score /= query_tokens as f32);
+        }
+        Ok(scores)
+    }
+}
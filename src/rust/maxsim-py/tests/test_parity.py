@@ -0,0 +1,37 @@
+"""Parity of the Python bindings with a NumPy reference (run with pytest after `maturin develop`)."""
+
+import numpy as np
+import maxsim_web
+
+
+def reference_maxsim(query, docs):
+    return np.array([(query @ doc.T).max(axis=1).sum() for doc in docs], dtype=np.float32)
+
+
+def sample(seed=0, dim=48):
+    rng = np.random.default_rng(seed)
+    query = rng.standard_normal((8, dim), dtype=np.float32)
+    docs = [rng.standard_normal((n, dim), dtype=np.float32) for n in (3, 17, 17, 18, 20, 64, 1)]
+    return query, docs
+
+
+def test_scores_match_numpy():
+    query, docs = sample()
+    np.testing.assert_allclose(maxsim_web.maxsim_batch(query, docs), reference_maxsim(query, docs), rtol=1e-5)
+    np.testing.assert_allclose(
+        maxsim_web.maxsim_batch(query, docs, normalized=True), reference_maxsim(query, docs) / 8, rtol=1e-5
+    )
+
+
+def test_index_round_trip():
+    query, docs = sample(seed=1)
+    blob = maxsim_web.serialize_index(docs)
+    assert blob[:4] == b"MXSI"
+    for restored, doc in zip(maxsim_web.load_index(blob), docs):
+        np.testing.assert_array_equal(restored, doc)
+
+    quantized = maxsim_web.serialize_index(docs, quantized=True)
+    dequantized = maxsim_web.load_index(quantized)
+    np.testing.assert_allclose(
+        maxsim_web.search_index(quantized, query), reference_maxsim(query, dequantized), rtol=1e-5
+    )
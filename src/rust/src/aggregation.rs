@@ -0,0 +1,122 @@
+/*!
+ * Per-query-token aggregation over document tokens
+ *
+ * MaxSim keeps the best-matching document token for every query token. Softer
+ * aggregations are less sensitive to a single spurious token match:
+ * - Max:       max_j s_j (standard MaxSim - the default)
+ * - Softmax:   Σ_j softmax(s / τ)_j · s_j, approaches Max as the temperature τ → 0
+ * - TopK:      mean of the k largest s_j (Max when k = 1)
+ * - LogSumExp: τ · ln(mean_j exp(s_j / τ)), between the mean (τ → ∞) and Max (τ → 0)
+ * - TopP:      mean of the largest ⌈p · n⌉ of the n document tokens' s_j
+ *
+ * LogSumExp averages instead of summing inside the log, which keeps it at or below
+ * Max (the plain sum exceeds Max by up to τ · ln n and favors long documents). All
+ * aggregations are therefore bounded above by Max, so score-threshold early
+ * termination stays exact.
+ */
+
+use wasm_bindgen::prelude::*;
+
+use crate::simd_max;
+
+/// How each query token's similarities to the document tokens are reduced
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Aggregation {
+    #[default]
+    Max = 0,
+    Softmax = 1,
+    TopK = 2,
+    LogSumExp = 3,
+    TopP = 4,
+}
+
+/// An aggregation together with its parameters, resolved once per call
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct RowReduction {
+    pub(crate) aggregation: Aggregation,
+    pub(crate) temperature: f32, // Softmax and LogSumExp (> 0)
+    pub(crate) top_k: usize,     // TopK only (≥ 1)
+    pub(crate) top_p: f32,       // TopP only, fraction of document tokens in (0, 1]
+}
+
+impl Default for RowReduction {
+    fn default() -> Self {
+        RowReduction { aggregation: Aggregation::Max, temperature: 1.0, top_k: 1, top_p: 1.0 }
+    }
+}
+
+impl RowReduction {
+    /// Reduce one query token's similarities (the row may be reordered)
+    #[inline]
+    pub(crate) fn reduce(&self, row: &mut [f32]) -> f32 {
+        if row.is_empty() {
+            return simd_max(row);
+        }
+
+        match self.aggregation {
+            Aggregation::Max => simd_max(row),
+            Aggregation::Softmax => {
+                // Shift by the max for numerical stability
+                let max = simd_max(row);
+                let (mut weighted, mut total) = (0.0, 0.0);
+                for &sim in row.iter() {
+                    let weight = ((sim - max) / self.temperature).exp();
+                    weighted += weight * sim;
+                    total += weight;
+                }
+                weighted / total
+            }
+            Aggregation::TopK => top_mean(row, self.top_k),
+            Aggregation::LogSumExp => {
+                // Shift by the max for numerical stability
+                let max = simd_max(row);
+                let total: f32 = row.iter().map(|&sim| ((sim - max) / self.temperature).exp()).sum();
+                max + self.temperature * (total / row.len() as f32).ln()
+            }
+            Aggregation::TopP => top_mean(row, (self.top_p * row.len() as f32).ceil() as usize),
+        }
+    }
+}
+
+// Mean of the `k` largest values (k clamped to 1..=len; the row is reordered)
+#[inline]
+fn top_mean(row: &mut [f32], k: usize) -> f32 {
+    let k = k.clamp(1, row.len());
+    if k == 1 {
+        return simd_max(row);
+    }
+    row.select_nth_unstable_by(k - 1, |a, b| b.total_cmp(a));
+    row[..k].iter().sum::<f32>() / k as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_aggregations_are_bounded_by_max() {
+        let row = [0.9, 0.1, 0.5, 0.7];
+        let reduce = |aggregation, temperature, top_k| {
+            RowReduction { aggregation, temperature, top_k, top_p: 0.5 }.reduce(&mut row.clone())
+        };
+
+        assert_eq!(reduce(Aggregation::Max, 1.0, 1), 0.9);
+        assert!((reduce(Aggregation::TopK, 1.0, 2) - 0.8).abs() < 1e-6);
+        assert!((reduce(Aggregation::TopK, 1.0, 10) - 0.55).abs() < 1e-6); // k clamped to the row length
+
+        let sharp = reduce(Aggregation::Softmax, 0.01, 1);
+        let soft = reduce(Aggregation::Softmax, 10.0, 1);
+        assert!((sharp - 0.9).abs() < 1e-3);
+        assert!(soft < sharp && soft > 0.5);
+
+        // LogSumExp stays between the mean (0.55) and Max
+        let sharp = reduce(Aggregation::LogSumExp, 0.01, 1);
+        let soft = reduce(Aggregation::LogSumExp, 100.0, 1);
+        assert!((sharp - 0.9).abs() < 0.02 && sharp <= 0.9);
+        assert!((soft - 0.55).abs() < 1e-3);
+
+        // TopP = 0.5 of 4 tokens averages the best 2
+        assert!((reduce(Aggregation::TopP, 1.0, 1) - 0.8).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,161 @@
+/*!
+ * Centroid-based candidate pruning (IVF-style approximate search)
+ *
+ * Every document is summarized by its mean-pooled, L2-normalized token embedding.
+ * Spherical k-means over these pooled vectors partitions the corpus into clusters;
+ * an approximate search ranks the centroids against the pooled query and runs exact
+ * MaxSim only over the documents of the `nprobe` nearest clusters.
+ */
+
+use crate::results::rank_cmp;
+use crate::store::PreloadedDocuments;
+use crate::{dot_product, normalize_tokens};
+
+/// Default number of k-means refinement iterations
+pub(crate) const DEFAULT_ITERATIONS: usize = 10;
+
+pub(crate) struct AnnIndex {
+    embedding_dim: usize,
+    centroids: Vec<f32>,      // num_clusters × embedding_dim, L2-normalized
+    members: Vec<Vec<usize>>, // Original document indices assigned to each cluster
+}
+
+impl AnnIndex {
+    /// Cluster the documents into (at most) `num_clusters` clusters
+    pub(crate) fn build(docs: &PreloadedDocuments, num_clusters: usize, iterations: usize) -> Self {
+        let dim = docs.embedding_dim;
+        let pooled = pooled_vectors(docs);
+        let num_docs = docs.num_docs();
+        let k = num_clusters.clamp(1, num_docs.max(1));
+
+        // Deterministic initialization: evenly spaced documents
+        let mut centroids = Vec::with_capacity(k * dim);
+        for c in 0..k {
+            let doc = c * num_docs / k;
+            centroids.extend_from_slice(pooled.get(doc * dim..(doc + 1) * dim).unwrap_or(&vec![0.0; dim]));
+        }
+
+        let mut assignments = vec![0usize; num_docs];
+        for _ in 0..iterations.max(1) {
+            let mut changed = false;
+            for (doc, assignment) in assignments.iter_mut().enumerate() {
+                let nearest = nearest_centroid(&centroids, &pooled[doc * dim..(doc + 1) * dim], dim);
+                changed |= nearest != *assignment;
+                *assignment = nearest;
+            }
+
+            // Recompute centroids (empty clusters keep their previous centroid)
+            let mut sums = vec![0.0f32; k * dim];
+            let mut counts = vec![0usize; k];
+            for (doc, &cluster) in assignments.iter().enumerate() {
+                counts[cluster] += 1;
+                for (sum, &value) in sums[cluster * dim..(cluster + 1) * dim].iter_mut().zip(&pooled[doc * dim..]) {
+                    *sum += value;
+                }
+            }
+            for cluster in (0..k).filter(|&c| counts[c] > 0) {
+                centroids[cluster * dim..(cluster + 1) * dim].copy_from_slice(&sums[cluster * dim..(cluster + 1) * dim]);
+            }
+            normalize_tokens(&mut centroids, dim);
+
+            if !changed {
+                break;
+            }
+        }
+
+        let mut members = vec![Vec::new(); k];
+        for (doc, &cluster) in assignments.iter().enumerate() {
+            members[cluster].push(doc);
+        }
+
+        AnnIndex { embedding_dim: dim, centroids, members }
+    }
+
+    pub(crate) fn num_clusters(&self) -> usize {
+        self.members.len()
+    }
+
+    /// Heap size of the centroids and cluster member lists
+    pub(crate) fn memory_bytes(&self) -> usize {
+        self.centroids.len() * std::mem::size_of::<f32>()
+            + self.members.iter().map(|m| m.len() * std::mem::size_of::<usize>()).sum::<usize>()
+    }
+
+    /// Documents of the `nprobe` clusters nearest to the (prepared) query, ascending
+    pub(crate) fn candidates(&self, query_flat: &[f32], nprobe: usize) -> Vec<usize> {
+        self.candidates_pooled(&mean_pool(query_flat, self.embedding_dim), nprobe)
+    }
+
+    /// Candidates for an already pooled query (any positive multiple of the mean-pooled
+    /// query, e.g. the running token sum of a query still being encoded)
+    pub(crate) fn candidates_pooled(&self, query_pooled: &[f32], nprobe: usize) -> Vec<usize> {
+        let mut ranked: Vec<(usize, f32)> = self
+            .centroids
+            .chunks_exact(self.embedding_dim)
+            .map(|centroid| dot_product(query_pooled, centroid))
+            .enumerate()
+            .collect();
+        ranked.sort_by(|&a, &b| rank_cmp(a, b));
+
+        let mut candidates: Vec<usize> = ranked
+            .iter()
+            .take(nprobe.max(1))
+            .flat_map(|&(cluster, _)| self.members[cluster].iter().copied())
+            .collect();
+        candidates.sort_unstable();
+        candidates
+    }
+}
+
+pub(crate) fn nearest_centroid(centroids: &[f32], vector: &[f32], dim: usize) -> usize {
+    centroids
+        .chunks_exact(dim)
+        .map(|centroid| dot_product(vector, centroid))
+        .enumerate()
+        .fold((0, f32::NEG_INFINITY), |best, (idx, sim)| if sim > best.1 { (idx, sim) } else { best })
+        .0
+}
+
+/// L2-normalized mean of a run of token embeddings (zeros for an empty run)
+pub(crate) fn mean_pool(tokens: &[f32], dim: usize) -> Vec<f32> {
+    let mut pooled = vec![0.0f32; dim];
+    for token in tokens.chunks_exact(dim) {
+        for (sum, &value) in pooled.iter_mut().zip(token) {
+            *sum += value;
+        }
+    }
+    normalize_tokens(&mut pooled, dim);
+    pooled
+}
+
+/// Mean-pooled vector of every document, flat (num_docs × embedding_dim)
+pub(crate) fn pooled_vectors(docs: &PreloadedDocuments) -> Vec<f32> {
+    let dim = docs.embedding_dim;
+    let mut pooled = Vec::with_capacity(docs.num_docs() * dim);
+    for page in docs.pages() {
+        let mut offset = 0;
+        for &tokens in &page.doc_tokens {
+            pooled.extend(mean_pool(&page.embeddings[offset..offset + tokens * dim], dim));
+            offset += tokens * dim;
+        }
+    }
+    pooled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clusters_separate_distinct_topics() {
+        // Two documents near (1, 0) and two near (0, 1)
+        let embeddings = [1.0, 0.1, 0.9, 0.0, 0.0, 1.0, 0.1, 0.9, 1.0, 0.0, 0.2, 1.0];
+        let docs = PreloadedDocuments::from_flat(&embeddings, &[2, 2, 1, 1], 2);
+        let index = AnnIndex::build(&docs, 2, DEFAULT_ITERATIONS);
+
+        assert_eq!(index.num_clusters(), 2);
+        assert_eq!(index.candidates(&[1.0, 0.0], 1), vec![0, 2]);
+        assert_eq!(index.candidates(&[0.0, 1.0], 1), vec![1, 3]);
+        assert_eq!(index.candidates(&[0.0, 1.0], 2), vec![0, 1, 2, 3]);
+    }
+}
@@ -0,0 +1,221 @@
+/*!
+ * Device benchmark over a synthetic corpus
+ *
+ * `MaxSimWasm.benchmark()` generates random documents inside WASM, loads them into a
+ * scoring snapshot of the instance (same tuning, metric, stopmask, auto-normalization
+ * and packed layout) and times every stage of a search:
+ * - generate: filling the synthetic embeddings
+ * - load:     building the paged store (sorting, norms, packed copy)
+ * - ann:      clustering for approximate search (√num_docs clusters)
+ * - exact:    `search_preloaded` over the whole corpus, mean per query
+ * - approx:   `search_preloaded_approx` probing a quarter of the clusters, mean per query
+ *
+ * The loaded corpus of the instance is never touched. Throughput is reported in
+ * corpus documents per second, so `exact_docs_per_sec` and `approx_docs_per_sec`
+ * compare directly when choosing a search path for a given corpus size.
+ */
+
+use wasm_bindgen::prelude::*;
+
+use crate::ann::{self, AnnIndex};
+use crate::clock::now_ms;
+use crate::store::PreloadedDocuments;
+use crate::tuning::synthetic;
+use crate::MaxSimWasm;
+
+// Benchmark queries: a typical ColBERT query length
+const QUERY_TOKENS: usize = 32;
+// Hits kept by the approximate search
+const APPROX_K: usize = 10;
+
+/// Stage timings and throughput of one `MaxSimWasm.benchmark()` run
+#[wasm_bindgen]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BenchmarkReport {
+    num_docs: usize,
+    doc_tokens: usize,
+    embedding_dim: usize,
+    iterations: usize,
+    generate_ms: f64,
+    load_ms: f64,
+    ann_build_ms: f64,
+    exact_ms: f64,
+    approx_ms: f64,
+}
+
+pub(crate) fn run(
+    engine: &MaxSimWasm,
+    num_docs: usize,
+    doc_tokens: usize,
+    embedding_dim: usize,
+    iterations: usize,
+) -> Result<BenchmarkReport, String> {
+    if num_docs == 0 || doc_tokens == 0 || embedding_dim == 0 || iterations == 0 {
+        return Err("num_docs, doc_tokens, embedding_dim and iterations must be positive".to_string());
+    }
+    let total_floats = num_docs
+        .checked_mul(doc_tokens)
+        .and_then(|tokens| tokens.checked_mul(embedding_dim))
+        .ok_or_else(|| "Benchmark corpus is too large".to_string())?;
+    let mut report = BenchmarkReport { num_docs, doc_tokens, embedding_dim, iterations, ..BenchmarkReport::default() };
+
+    let start = now_ms();
+    let embeddings = synthetic(total_floats, 1);
+    let queries: Vec<Vec<f32>> = (0..iterations as u64).map(|i| synthetic(QUERY_TOKENS * embedding_dim, 2 + i)).collect();
+    report.generate_ms = now_ms() - start;
+
+    let mut bench = engine.scoring_snapshot();
+    bench.packed_layout = engine.packed_layout;
+    let start = now_ms();
+    bench.install_documents(PreloadedDocuments::from_vec(embeddings, &vec![doc_tokens; num_docs], embedding_dim));
+    report.load_ms = now_ms() - start;
+
+    let docs_ref = bench.documents.borrow();
+    let docs = docs_ref.as_ref().ok_or_else(|| "Benchmark corpus failed to load".to_string())?;
+    let num_clusters = ((num_docs as f64).sqrt().round() as usize).max(1);
+    let start = now_ms();
+    let ann = AnnIndex::build(docs, num_clusters, ann::DEFAULT_ITERATIONS);
+    report.ann_build_ms = now_ms() - start;
+
+    // One warm-up query per path, then the mean over `iterations` queries
+    let metric = bench.config.metric();
+    let normalized = bench.config.normalized();
+    let nprobe = num_clusters.div_ceil(4);
+    bench.search_store(docs, &queries[0], QUERY_TOKENS, &[], normalized, metric, None)?;
+    let start = now_ms();
+    for query in &queries {
+        bench.search_store(docs, query, QUERY_TOKENS, &[], normalized, metric, None)?;
+    }
+    report.exact_ms = (now_ms() - start) / iterations as f64;
+
+    bench.approx_hits(docs, &ann, &queries[0], QUERY_TOKENS, nprobe, APPROX_K)?;
+    let start = now_ms();
+    for query in &queries {
+        bench.approx_hits(docs, &ann, query, QUERY_TOKENS, nprobe, APPROX_K)?;
+    }
+    report.approx_ms = (now_ms() - start) / iterations as f64;
+    Ok(report)
+}
+
+// Operations per second of one `per_op_ms` millisecond operation
+fn per_second(per_op_ms: f64) -> f64 {
+    1000.0 / per_op_ms.max(1e-3)
+}
+
+#[wasm_bindgen]
+impl BenchmarkReport {
+    #[wasm_bindgen(getter)]
+    pub fn num_docs(&self) -> usize {
+        self.num_docs
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn doc_tokens(&self) -> usize {
+        self.doc_tokens
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn embedding_dim(&self) -> usize {
+        self.embedding_dim
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn query_tokens(&self) -> usize {
+        QUERY_TOKENS
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn iterations(&self) -> usize {
+        self.iterations
+    }
+
+    /// Milliseconds spent generating the synthetic corpus and queries
+    #[wasm_bindgen(getter)]
+    pub fn generate_ms(&self) -> f64 {
+        self.generate_ms
+    }
+
+    /// Milliseconds spent building the paged store
+    #[wasm_bindgen(getter)]
+    pub fn load_ms(&self) -> f64 {
+        self.load_ms
+    }
+
+    /// Milliseconds spent clustering the corpus for approximate search
+    #[wasm_bindgen(getter)]
+    pub fn ann_build_ms(&self) -> f64 {
+        self.ann_build_ms
+    }
+
+    /// Mean milliseconds per exact full-corpus query
+    #[wasm_bindgen(getter)]
+    pub fn exact_ms(&self) -> f64 {
+        self.exact_ms
+    }
+
+    /// Mean milliseconds per approximate query
+    #[wasm_bindgen(getter)]
+    pub fn approx_ms(&self) -> f64 {
+        self.approx_ms
+    }
+
+    /// Corpus documents searched per second by exact search
+    #[wasm_bindgen(getter)]
+    pub fn exact_docs_per_sec(&self) -> f64 {
+        self.num_docs as f64 * per_second(self.exact_ms)
+    }
+
+    /// Corpus documents searched per second by approximate search
+    #[wasm_bindgen(getter)]
+    pub fn approx_docs_per_sec(&self) -> f64 {
+        self.num_docs as f64 * per_second(self.approx_ms)
+    }
+
+    /// Similarity throughput of exact search (2 flops per multiply-add)
+    #[wasm_bindgen(getter)]
+    pub fn gflops(&self) -> f64 {
+        let flops = 2.0 * QUERY_TOKENS as f64 * self.num_docs as f64 * self.doc_tokens as f64 * self.embedding_dim as f64;
+        flops * per_second(self.exact_ms) / 1e9
+    }
+
+    /// Human-readable summary, e.g. "1000 docs × 128 tokens × 128 dim: exact 12.300 ms ..."
+    #[wasm_bindgen]
+    pub fn describe(&self) -> String {
+        format!(
+            "{} docs × {} tokens × {} dim: exact {:.3} ms ({:.0} docs/s, {:.2} GFLOP/s), approx {:.3} ms ({:.0} docs/s), load {:.1} ms, ann {:.1} ms",
+            self.num_docs, self.doc_tokens, self.embedding_dim,
+            self.exact_ms, self.exact_docs_per_sec(), self.gflops(),
+            self.approx_ms, self.approx_docs_per_sec(), self.load_ms, self.ann_build_ms
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_benchmark_reports_every_stage() {
+        let engine = MaxSimWasm::new();
+        let report = run(&engine, 40, 8, 16, 2).unwrap();
+        assert_eq!((report.num_docs(), report.doc_tokens(), report.iterations()), (40, 8, 2));
+        assert!(report.exact_ms() >= 0.0 && report.approx_ms() >= 0.0);
+        assert!(report.gflops() > 0.0 && report.exact_docs_per_sec() > 0.0);
+        assert!(engine.documents.borrow().is_none()); // The instance corpus is untouched
+
+        assert!(run(&engine, 40, 8, 16, 0).is_err());
+    }
+
+    #[test]
+    fn test_gflops_past_the_wasm32_usize_range() {
+        // 2 · 32 · 100k · 512 · 1024 flops overflow a 32-bit usize
+        let report = BenchmarkReport {
+            num_docs: 100_000,
+            doc_tokens: 512,
+            embedding_dim: 1024,
+            exact_ms: 1000.0,
+            ..Default::default()
+        };
+        assert!((report.gflops() - 3355.4432).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,17 @@
+//! Offline index builder (see `src/cli.rs`)
+
+#[cfg(not(target_arch = "wasm32"))]
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match maxsim_web_wasm::cli::run(&args) {
+        Ok(report) => println!("{}", report),
+        Err(message) => {
+            eprintln!("{}", message);
+            std::process::exit(1);
+        }
+    }
+}
+
+// The builder reads and writes files, which the wasm32 library doesn't include
+#[cfg(target_arch = "wasm32")]
+fn main() {}
@@ -0,0 +1,99 @@
+/*!
+ * Per-document score bounds for exact top-k search
+ *
+ * Every document is summarized by the bounding box of its token embeddings: the
+ * smallest and largest value of each dimension over its tokens. For any query token
+ * `q` and document token `d`, `q·d <= Σ_j max(q_j·lo_j, q_j·hi_j)`, so one pass over
+ * the box (`embedding_dim` multiply-adds per query token, independent of document
+ * length) bounds the dot-product MaxSim contribution of the whole document.
+ *
+ * `search_preloaded_top_k()` visits documents by decreasing bound and stops as soon
+ * as the next bound falls below the k-th best exact score: the result is exactly the
+ * top k of a full scan, and every document left unvisited is skipped entirely. The
+ * boxes cost `2 × embedding_dim` floats per document. They are tightest for
+ * clustered or short documents; long documents spanning the whole space yield loose
+ * bounds and little pruning.
+ */
+
+use crate::store::PreloadedDocuments;
+
+/// Relative slack added to every bound so that summation order in the SIMD kernels
+/// can never make an exact score exceed its bound
+const ROUNDING_SLACK: f32 = 1e-5;
+
+/// Bounding box of every preloaded document, in original order
+pub(crate) struct DocBounds {
+    embedding_dim: usize,
+    lower: Vec<f32>, // num_docs × embedding_dim, per-dimension minimum
+    upper: Vec<f32>, // num_docs × embedding_dim, per-dimension maximum
+}
+
+impl DocBounds {
+    pub(crate) fn build(docs: &PreloadedDocuments) -> Self {
+        let dim = docs.embedding_dim;
+        let mut lower = Vec::with_capacity(docs.num_docs() * dim);
+        let mut upper = Vec::with_capacity(docs.num_docs() * dim);
+
+        for doc_idx in 0..docs.num_docs() {
+            let (embeddings, _) = docs.document(doc_idx);
+            if embeddings.is_empty() {
+                // Empty documents score 0, which a zero box bounds exactly
+                lower.resize(lower.len() + dim, 0.0);
+                upper.resize(upper.len() + dim, 0.0);
+                continue;
+            }
+            let (lo, hi) = (lower.len(), upper.len());
+            lower.extend_from_slice(&embeddings[..dim]);
+            upper.extend_from_slice(&embeddings[..dim]);
+            for token in embeddings.chunks_exact(dim).skip(1) {
+                for (j, &value) in token.iter().enumerate() {
+                    lower[lo + j] = lower[lo + j].min(value);
+                    upper[hi + j] = upper[hi + j].max(value);
+                }
+            }
+        }
+
+        DocBounds { embedding_dim: dim, lower, upper }
+    }
+
+    /// Upper bound of each query token's dot product with any token of `doc_idx`
+    pub(crate) fn token_bounds<'a>(&'a self, doc_idx: usize, query_flat: &'a [f32]) -> impl Iterator<Item = f32> + 'a {
+        let dim = self.embedding_dim;
+        let lower = &self.lower[doc_idx * dim..(doc_idx + 1) * dim];
+        let upper = &self.upper[doc_idx * dim..(doc_idx + 1) * dim];
+        query_flat.chunks_exact(dim).map(move |query_token| {
+            let (mut bound, mut magnitude) = (0.0f32, 0.0f32);
+            for ((&q, &lo), &hi) in query_token.iter().zip(lower).zip(upper) {
+                let term = (q * lo).max(q * hi);
+                bound += term;
+                magnitude += term.abs();
+            }
+            bound + magnitude * ROUNDING_SLACK
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dot_product;
+
+    #[test]
+    fn test_box_bounds_every_token_pair() {
+        let docs_flat = vec![0.6, 0.8, -0.8, 0.6, 1.0, 0.0, 0.0, -1.0];
+        let docs = PreloadedDocuments::from_flat(&docs_flat, &[2, 0, 2], 2);
+        let bounds = DocBounds::build(&docs);
+
+        let query = vec![0.5, -0.5, -1.0, 0.2];
+        for (doc_idx, doc) in [(0, &docs_flat[..4]), (2, &docs_flat[4..])] {
+            for (q_idx, bound) in bounds.token_bounds(doc_idx, &query).enumerate() {
+                let best = doc
+                    .chunks_exact(2)
+                    .map(|d| dot_product(&query[q_idx * 2..(q_idx + 1) * 2], d))
+                    .fold(f32::NEG_INFINITY, f32::max);
+                assert!(bound >= best && bound <= best + 1.0);
+            }
+        }
+        assert!(bounds.token_bounds(1, &query).all(|bound| bound == 0.0));
+    }
+}
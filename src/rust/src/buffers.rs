@@ -0,0 +1,81 @@
+/*!
+ * WASM-owned scoring buffers
+ *
+ * Zero-copy scoring used to take raw `*const f32` addresses from JS, which is
+ * undefined behavior as soon as JS passes a stale or foreign address (a view taken
+ * before memory growth, or a plain JS `Float32Array` that never lived in WASM
+ * memory). Buffers are now allocated here and named by a handle: JS writes into
+ * them (`write_buffer()`, or a view at `buffer_ptr()`), and scoring looks the handle
+ * up, so an unknown handle is an error instead of a wild read.
+ */
+
+use std::collections::BTreeMap;
+
+/// Buffers allocated with `MaxSimWasm.alloc_buffer()`, by handle
+#[derive(Default)]
+pub(crate) struct BufferRegistry {
+    buffers: BTreeMap<u32, Vec<f32>>,
+    next_handle: u32,
+}
+
+impl BufferRegistry {
+    /// Allocate a zeroed buffer of `floats` floats and return its handle
+    pub(crate) fn alloc(&mut self, floats: usize) -> u32 {
+        self.next_handle = self.next_handle.wrapping_add(1);
+        while self.next_handle == 0 || self.buffers.contains_key(&self.next_handle) {
+            self.next_handle = self.next_handle.wrapping_add(1);
+        }
+        self.buffers.insert(self.next_handle, vec![0.0; floats]);
+        self.next_handle
+    }
+
+    pub(crate) fn get(&self, handle: u32) -> Result<&[f32], String> {
+        self.buffers.get(&handle).map(Vec::as_slice).ok_or_else(|| unknown(handle))
+    }
+
+    pub(crate) fn get_mut(&mut self, handle: u32) -> Result<&mut Vec<f32>, String> {
+        self.buffers.get_mut(&handle).ok_or_else(|| unknown(handle))
+    }
+
+    /// Copy `data` into the buffer starting at float `offset`
+    pub(crate) fn write(&mut self, handle: u32, offset: usize, data: &[f32]) -> Result<(), String> {
+        let buffer = self.get_mut(handle)?;
+        let end = offset.checked_add(data.len()).filter(|&end| end <= buffer.len());
+        let end = end.ok_or_else(|| {
+            format!("Write of {} floats at {} overflows buffer {} ({} floats)", data.len(), offset, handle, buffer.len())
+        })?;
+        buffer[offset..end].copy_from_slice(data);
+        Ok(())
+    }
+
+    /// Release a buffer; false if the handle was unknown
+    pub(crate) fn free(&mut self, handle: u32) -> bool {
+        self.buffers.remove(&handle).is_some()
+    }
+}
+
+fn unknown(handle: u32) -> String {
+    format!("Unknown buffer handle {} (never allocated or already freed)", handle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_handles_guard_every_access() {
+        let mut buffers = BufferRegistry::default();
+        let a = buffers.alloc(4);
+        let b = buffers.alloc(2);
+        assert_ne!(a, b);
+
+        buffers.write(a, 1, &[1.0, 2.0]).unwrap();
+        assert_eq!(buffers.get(a).unwrap(), &[0.0, 1.0, 2.0, 0.0]);
+        assert!(buffers.write(b, 1, &[1.0, 2.0]).unwrap_err().contains("overflows"));
+        assert!(buffers.write(b, usize::MAX, &[1.0]).is_err());
+
+        assert!(buffers.free(a));
+        assert!(!buffers.free(a));
+        assert!(buffers.get(a).unwrap_err().contains("Unknown buffer handle"));
+    }
+}
@@ -0,0 +1,111 @@
+/*!
+ * Calibration of MaxSim scores to a 0–100 display scale
+ *
+ * Raw MaxSim is a sum over query tokens, so its range grows with query length and
+ * shifts with the corpus: a fixed threshold or a "relevance bar" means something
+ * different for every query. Calibration first divides a raw score by the query's
+ * token count (the `*_normalized` score, a mean similarity per query token), then
+ * maps a per-corpus range `[min, max]` linearly onto 0–100, clamping outside it:
+ *
+ * ```text
+ * relevance = 100 · clamp((score / query_tokens − min) / (max − min), 0, 1)
+ * ```
+ *
+ * The range is set by the app (`set_calibration()`, e.g. from labelled queries) or
+ * learned from the corpus at load (`set_auto_calibration()`): sampled documents are
+ * used as queries against each other, `min` is the median score of a pair (what an
+ * unrelated document scores, mapped to 0) and `max` is the median score of each
+ * document's closest sampled neighbour (a strong match, mapped to 100). Learning
+ * uses the dot product on the stored (possibly auto-normalized) tokens, whatever the
+ * instance metric, and draws its sample from the seeded stream, so it is
+ * reproducible. Length penalties (`ScoreNorm`) are not undone.
+ */
+
+use crate::dot_product;
+use crate::rng::SeededRng;
+use crate::store::PreloadedDocuments;
+
+/// Documents sampled to learn a range
+const SAMPLE_DOCS: usize = 32;
+
+/// Per-query-token score range mapped onto 0–100
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Calibration {
+    pub(crate) min: f32,
+    pub(crate) max: f32,
+}
+
+impl Calibration {
+    pub(crate) fn new(min: f32, max: f32) -> Result<Self, String> {
+        if !(min.is_finite() && max.is_finite() && max > min) {
+            return Err(format!("Calibration range must be finite with max > min (got {}, {})", min, max));
+        }
+        Ok(Calibration { min, max })
+    }
+
+    /// Range learned from a sample of the documents (None with fewer than two
+    /// non-empty sampled documents, or when their scores don't spread)
+    pub(crate) fn learn(docs: &PreloadedDocuments, rng: &mut SeededRng) -> Option<Self> {
+        let sample: Vec<usize> =
+            rng.sample(docs.num_docs(), SAMPLE_DOCS).into_iter().filter(|&doc| docs.doc_tokens()[doc] > 0).collect();
+        if sample.len() < 2 {
+            return None;
+        }
+        let dim = docs.embedding_dim;
+        let (mut pairs, mut nearest) = (Vec::new(), Vec::new());
+        for &query_doc in &sample {
+            let query = docs.document(query_doc).0;
+            let mut best = f32::NEG_INFINITY;
+            for &doc in sample.iter().filter(|&&doc| doc != query_doc) {
+                let doc = docs.document(doc).0;
+                let score = query
+                    .chunks_exact(dim)
+                    .map(|q| doc.chunks_exact(dim).map(|d| dot_product(q, d)).fold(f32::NEG_INFINITY, f32::max))
+                    .sum::<f32>()
+                    / (query.len() / dim) as f32;
+                pairs.push(score);
+                best = best.max(score);
+            }
+            nearest.push(best);
+        }
+        Calibration::new(median(&mut pairs), median(&mut nearest)).ok()
+    }
+
+    /// 0–100 relevance of a per-query-token score
+    pub(crate) fn scale(&self, score: f32) -> f32 {
+        100.0 * ((score - self.min) / (self.max - self.min)).clamp(0.0, 1.0)
+    }
+}
+
+fn median(values: &mut [f32]) -> f32 {
+    values.sort_unstable_by(f32::total_cmp);
+    values[values.len() / 2]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_learned_range_maps_unrelated_to_zero_and_neighbours_to_100() {
+        // Eight pairs of near-identical documents along different axes
+        let dim = 8;
+        let mut flat = Vec::new();
+        for axis in 0..dim {
+            for twist in [0.0, 0.1] {
+                let mut token = vec![0.0; dim];
+                token[axis] = 1.0;
+                token[(axis + 1) % dim] = twist;
+                flat.extend(token);
+            }
+        }
+        let docs = PreloadedDocuments::from_flat(&flat, &[1; 16], dim);
+        let calibration = Calibration::learn(&docs, &mut SeededRng::new(0, 4)).unwrap();
+        assert_eq!(calibration.min, 0.0);
+        assert!(calibration.max >= 1.0);
+        assert_eq!((calibration.scale(0.0), calibration.scale(1.0), calibration.scale(-1.0)), (0.0, 100.0, 0.0));
+
+        assert!(Calibration::learn(&PreloadedDocuments::from_flat(&flat[..dim], &[1], dim), &mut SeededRng::new(0, 4)).is_none());
+        assert!(Calibration::new(1.0, 1.0).is_err());
+    }
+}
@@ -0,0 +1,44 @@
+/*!
+ * Cancellation of in-flight searches
+ *
+ * Search-as-you-type constantly abandons stale queries. JS keeps an `AbortFlag` per
+ * search and calls `abort()` when the query is superseded; the batch pipeline checks
+ * it between sub-batches (and cooperative searches between chunks) and stops early.
+ */
+
+use std::cell::Cell;
+use std::rc::Rc;
+
+use wasm_bindgen::prelude::*;
+
+/// Cancellation flag shared between JS and the searches it was passed to
+#[wasm_bindgen]
+#[derive(Clone, Debug, Default)]
+pub struct AbortFlag {
+    aborted: Rc<Cell<bool>>,
+}
+
+#[wasm_bindgen]
+impl AbortFlag {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> AbortFlag {
+        AbortFlag::default()
+    }
+
+    /// Request cancellation of every search using this flag
+    #[wasm_bindgen]
+    pub fn abort(&self) {
+        self.aborted.set(true);
+    }
+
+    /// Clear the flag so it can be reused for a new search
+    #[wasm_bindgen]
+    pub fn reset(&self) {
+        self.aborted.set(false);
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn aborted(&self) -> bool {
+        self.aborted.get()
+    }
+}
@@ -0,0 +1,113 @@
+/*!
+ * Per-document token centroids for approximate MaxSim
+ *
+ * Every document's tokens are clustered (a few rounds of k-means inside the
+ * document) into at most `per_doc` centroids, the mean of each cluster's tokens.
+ * The per-query-token maximum over a document is then estimated as the maximum over
+ * its centroids: `per_doc` dot products per query token instead of one per document
+ * token. Estimates rank the corpus cheaply; `search_preloaded_centroid()` rescores
+ * exactly only the documents whose estimate comes within a margin of the current
+ * k-th best exact score.
+ *
+ * A centroid is an average, so estimates usually undershoot the exact score (more
+ * so for spread-out documents); the margin absorbs that error. Unlike the bounds of
+ * bounds.rs nothing is guaranteed: a larger margin trades speed for recall.
+ */
+
+use crate::ann::nearest_centroid;
+use crate::dot_product;
+use crate::store::PreloadedDocuments;
+
+/// k-means rounds per document (tokens rarely move after a few)
+const ITERATIONS: usize = 4;
+
+/// Token centroids of every preloaded document, in original order
+pub(crate) struct TokenCentroids {
+    embedding_dim: usize,
+    centroids: Vec<f32>,       // All centroids back to back
+    doc_offsets: Vec<usize>,   // Index of each document's first centroid (num_docs + 1 entries)
+}
+
+impl TokenCentroids {
+    pub(crate) fn build(docs: &PreloadedDocuments, per_doc: usize) -> Self {
+        let dim = docs.embedding_dim;
+        let mut centroids = Vec::new();
+        let mut doc_offsets = vec![0];
+
+        for doc_idx in 0..docs.num_docs() {
+            let (embeddings, _) = docs.document(doc_idx);
+            centroids.extend(cluster_tokens(embeddings, dim, per_doc));
+            doc_offsets.push(centroids.len() / dim);
+        }
+
+        TokenCentroids { embedding_dim: dim, centroids, doc_offsets }
+    }
+
+    /// Centroids kept per document on average
+    pub(crate) fn mean_per_doc(&self) -> f32 {
+        let num_docs = self.doc_offsets.len() - 1;
+        if num_docs == 0 { 0.0 } else { self.doc_offsets[num_docs] as f32 / num_docs as f32 }
+    }
+
+    /// Estimated maximum similarity of each query token over the tokens of `doc_idx`
+    /// (0 for an empty document, which scores 0)
+    pub(crate) fn token_estimates<'a>(&'a self, doc_idx: usize, query_flat: &'a [f32]) -> impl Iterator<Item = f32> + 'a {
+        let dim = self.embedding_dim;
+        let doc = &self.centroids[self.doc_offsets[doc_idx] * dim..self.doc_offsets[doc_idx + 1] * dim];
+        query_flat.chunks_exact(dim).map(move |query_token| {
+            let best = doc.chunks_exact(dim).map(|centroid| dot_product(query_token, centroid)).fold(f32::NEG_INFINITY, f32::max);
+            if doc.is_empty() { 0.0 } else { best }
+        })
+    }
+}
+
+// Cluster one document's tokens into at most `k` mean centroids (all tokens when it has
+// no more than `k`)
+fn cluster_tokens(tokens: &[f32], dim: usize, k: usize) -> Vec<f32> {
+    let num_tokens = tokens.len() / dim;
+    if num_tokens <= k {
+        return tokens.to_vec();
+    }
+
+    // Deterministic initialization: evenly spaced tokens
+    let mut centroids: Vec<f32> = (0..k)
+        .flat_map(|c| tokens[c * num_tokens / k * dim..(c * num_tokens / k + 1) * dim].iter().copied())
+        .collect();
+    for _ in 0..ITERATIONS {
+        let mut sums = vec![0.0f32; k * dim];
+        let mut counts = vec![0usize; k];
+        for token in tokens.chunks_exact(dim) {
+            let cluster = nearest_centroid(&centroids, token, dim);
+            counts[cluster] += 1;
+            for (sum, &value) in sums[cluster * dim..(cluster + 1) * dim].iter_mut().zip(token) {
+                *sum += value;
+            }
+        }
+        // Empty clusters keep their previous centroid
+        for cluster in (0..k).filter(|&c| counts[c] > 0) {
+            for (centroid, &sum) in centroids[cluster * dim..(cluster + 1) * dim].iter_mut().zip(&sums[cluster * dim..]) {
+                *centroid = sum / counts[cluster] as f32;
+            }
+        }
+    }
+    centroids
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_short_documents_keep_exact_tokens() {
+        // Doc 0 has two tight clusters of tokens, doc 1 fits in the budget
+        let docs_flat = vec![1.0, 0.0, 0.9, 0.1, 0.0, 1.0, 0.1, 0.9, 0.6, 0.8];
+        let docs = PreloadedDocuments::from_flat(&docs_flat, &[4, 1], 2);
+        let centroids = TokenCentroids::build(&docs, 2);
+        assert_eq!(centroids.mean_per_doc(), 1.5);
+
+        let query = vec![1.0, 0.0];
+        let estimate: Vec<f32> = centroids.token_estimates(0, &query).collect();
+        assert!((estimate[0] - 0.95).abs() < 1e-6);
+        assert_eq!(centroids.token_estimates(1, &query).collect::<Vec<_>>(), vec![0.6]);
+    }
+}
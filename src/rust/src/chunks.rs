@@ -0,0 +1,118 @@
+/*!
+ * MaxSim over documents stored as several chunks
+ *
+ * Long documents are usually split into chunks that fit the encoder's context.
+ * With the chunk counts of every document (`MaxSimWasm.load_documents_chunked()`),
+ * `search_chunked()` scores each query token against every chunk once and merges
+ * the per-query-token maxima `m[q][c]` of a document's chunks by a `ChunkMerge`:
+ * - MaxChunk:   max_c Σ_q m[q][c], the best single chunk
+ * - BestWindow: max over runs W of `window` consecutive chunks of Σ_q max_{c ∈ W} m[q][c],
+ *   i.e. MaxSim against the best window of concatenated chunks
+ * - Full:       Σ_q max_c m[q][c], MaxSim against all chunks concatenated
+ *
+ * With the default Max aggregation, BestWindow and Full equal MaxSim over the
+ * concatenated chunks exactly (a query token's best match in the concatenation is
+ * its best match in one of the chunks). Other aggregations merge their per-chunk
+ * row scores by the same max. Empty chunks (no tokens) never match, so they are
+ * skipped rather than scoring 0 against chunks with negative scores; documents
+ * without non-empty chunks score 0. Length penalties (`ScoreNorm`) don't apply.
+ */
+
+use wasm_bindgen::prelude::*;
+
+/// How the per-chunk scores of a document are merged into its score
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ChunkMerge {
+    #[default]
+    MaxChunk = 0,
+    BestWindow = 1,
+    Full = 2,
+}
+
+/// Score of every document from the k-major per-query-token maxima of all chunks
+/// (`maxima[q * num_chunks + chunk]`); `chunk_tokens` are the token counts per chunk
+/// and `doc_chunks` the chunk counts per document
+pub(crate) fn merge_chunks(
+    maxima: &[f32],
+    query_tokens: usize,
+    chunk_tokens: &[usize],
+    doc_chunks: &[usize],
+    merge: ChunkMerge,
+    window: usize,
+) -> Vec<f32> {
+    let num_chunks = chunk_tokens.len();
+    // Σ_q max over the non-empty chunks first..first + len (-∞ when all are empty)
+    let run_score = |first: usize, len: usize| -> f32 {
+        if chunk_tokens[first..first + len].iter().all(|&tokens| tokens == 0) {
+            return f32::NEG_INFINITY;
+        }
+        (0..query_tokens)
+            .map(|q| {
+                (first..first + len)
+                    .filter(|&chunk| chunk_tokens[chunk] > 0)
+                    .map(|chunk| maxima[q * num_chunks + chunk])
+                    .fold(f32::NEG_INFINITY, f32::max)
+            })
+            .sum()
+    };
+
+    let mut first = 0;
+    doc_chunks
+        .iter()
+        .map(|&chunks| {
+            let width = match merge {
+                _ if chunks == 0 => 0,
+                ChunkMerge::MaxChunk => 1,
+                ChunkMerge::BestWindow => window.clamp(1, chunks),
+                ChunkMerge::Full => chunks,
+            };
+            let score = if width == 0 {
+                0.0
+            } else {
+                (first..=first + chunks - width).map(|start| run_score(start, width)).fold(f32::NEG_INFINITY, f32::max)
+            };
+            let score = if score == f32::NEG_INFINITY { 0.0 } else { score };
+            first += chunks;
+            score
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_policies_over_chunk_maxima() {
+        // Two query tokens; document 0 has chunks 0..3, document 1 has none, document 2 has chunk 3
+        let maxima = [
+            0.9, 0.1, 0.2, 0.4, // q0 per chunk
+            0.1, 0.3, 0.8, 0.5, // q1 per chunk
+        ];
+        let chunk_tokens = [2; 4];
+        let doc_chunks = [3, 0, 1];
+        let round = |scores: Vec<f32>| scores.iter().map(|s| (s * 100.0).round() / 100.0).collect::<Vec<_>>();
+
+        assert_eq!(round(merge_chunks(&maxima, 2, &chunk_tokens, &doc_chunks, ChunkMerge::MaxChunk, 0)), [1.0, 0.0, 0.9]);
+        assert_eq!(round(merge_chunks(&maxima, 2, &chunk_tokens, &doc_chunks, ChunkMerge::BestWindow, 2)), [1.2, 0.0, 0.9]);
+        assert_eq!(round(merge_chunks(&maxima, 2, &chunk_tokens, &doc_chunks, ChunkMerge::Full, 0)), [1.7, 0.0, 0.9]);
+        // Windows wider than the document cover all of it
+        assert_eq!(
+            merge_chunks(&maxima, 2, &chunk_tokens, &doc_chunks, ChunkMerge::BestWindow, 9),
+            merge_chunks(&maxima, 2, &chunk_tokens, &doc_chunks, ChunkMerge::Full, 0)
+        );
+    }
+
+    #[test]
+    fn test_empty_chunks_never_win() {
+        // Document 0: an empty chunk (maxima 0) before a chunk with negative maxima;
+        // document 1 has only an empty chunk
+        let maxima = [0.0, -0.5, 0.0, 0.0, -0.25, 0.0];
+        let chunk_tokens = [0, 3, 0];
+        let doc_chunks = [2, 1];
+        for merge in [ChunkMerge::MaxChunk, ChunkMerge::BestWindow, ChunkMerge::Full] {
+            assert_eq!(merge_chunks(&maxima, 2, &chunk_tokens, &doc_chunks, merge, 1), [-0.75, 0.0]);
+        }
+    }
+}
@@ -0,0 +1,264 @@
+/*!
+ * `maxsim-cli`: offline index building and inspection
+ *
+ * Index construction belongs in a build step, not in the browser: the CLI turns raw
+ * embeddings into the serialized index format (see formats/index.rs) so the app only
+ * fetches the artifact and calls `load_index()`. It uses the same parsers and encoder
+ * as the WASM build, so its indexes load byte for byte like `serialize_index()` output.
+ *
+ * ```text
+ * maxsim-cli build-index <embeddings> -o <index> [--doclens <lengths>] [--tensor <name>] [--quantize]
+ * maxsim-cli inspect <index>
+ * maxsim-cli quantize <index> -o <index>
+ * maxsim-cli validate <index>
+ * maxsim-cli delta <updated index> --base <index> -o <delta>
+ * ```
+ *
+ * Embeddings are `.npy`, `.safetensors` or `.npz` files, flat or padded as in
+ * `load_documents_npy()`. `--doclens` is a `.npy` integer array or a comma-separated
+ * list (required for `.npy` and `.safetensors`; for `.npz` it names the lengths array,
+ * default `doclens`). `--tensor` names the embeddings tensor (default `embeddings`).
+ * `delta` writes what `apply_index_delta()` needs to turn the base index into the
+ * updated one (see maxsim_core::delta).
+ */
+
+use std::path::Path;
+
+use maxsim_core::index::Header;
+
+use crate::formats::{self, index::Encoding};
+use crate::store::PreloadedDocuments;
+
+const USAGE: &str = "usage:
+  maxsim-cli build-index <embeddings> -o <index> [--doclens <lengths>] [--tensor <name>] [--quantize]
+  maxsim-cli inspect <index>
+  maxsim-cli quantize <index> -o <index>
+  maxsim-cli validate <index>
+  maxsim-cli delta <updated index> --base <index> -o <delta>";
+
+/// Run one command; the report goes to stdout, errors to stderr with a failing exit code
+pub fn run(args: &[String]) -> Result<String, String> {
+    let (command, rest) = args.split_first().ok_or(USAGE)?;
+    let options = Options::parse(rest)?;
+    match command.as_str() {
+        "build-index" => build_index(&options),
+        "inspect" => inspect(&options),
+        "quantize" => quantize(&options),
+        "validate" => validate(&options),
+        "delta" => delta(&options),
+        "help" | "--help" | "-h" => Ok(USAGE.to_string()),
+        _ => Err(format!("Unknown command {:?}\n{}", command, USAGE)),
+    }
+}
+
+#[derive(Default)]
+struct Options {
+    input: Option<String>,
+    output: Option<String>,
+    doclens: Option<String>,
+    tensor: Option<String>,
+    base: Option<String>,
+    quantize: bool,
+}
+
+impl Options {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut options = Options::default();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().cloned().ok_or_else(|| format!("{} needs a value", arg));
+            match arg.as_str() {
+                "-o" | "--output" => options.output = Some(value()?),
+                "--doclens" => options.doclens = Some(value()?),
+                "--tensor" => options.tensor = Some(value()?),
+                "--base" => options.base = Some(value()?),
+                "--quantize" => options.quantize = true,
+                flag if flag.starts_with('-') => return Err(format!("Unknown option {:?}\n{}", flag, USAGE)),
+                _ if options.input.is_none() => options.input = Some(arg.clone()),
+                _ => return Err(format!("Unexpected argument {:?}\n{}", arg, USAGE)),
+            }
+        }
+        Ok(options)
+    }
+
+    fn input(&self) -> Result<&str, String> {
+        self.input.as_deref().ok_or_else(|| format!("Missing input file\n{}", USAGE))
+    }
+
+    fn output(&self) -> Result<&str, String> {
+        self.output.as_deref().ok_or_else(|| format!("Missing -o <index>\n{}", USAGE))
+    }
+}
+
+fn read(path: &str) -> Result<Vec<u8>, String> {
+    std::fs::read(path).map_err(|e| format!("Cannot read {}: {}", path, e))
+}
+
+fn write(path: &str, bytes: &[u8]) -> Result<(), String> {
+    std::fs::write(path, bytes).map_err(|e| format!("Cannot write {}: {}", path, e))
+}
+
+fn build_index(options: &Options) -> Result<String, String> {
+    let input = options.input()?;
+    let bytes = read(input)?;
+    let tensor = options.tensor.as_deref().unwrap_or("embeddings");
+    let extension = Path::new(input).extension().and_then(|e| e.to_str()).unwrap_or("");
+
+    let docs = if extension == "npz" {
+        formats::numpy::read_npz_documents(&bytes, tensor, options.doclens.as_deref().unwrap_or("doclens"))?
+    } else {
+        let doc_tokens = read_doclens(options.doclens.as_deref().ok_or("--doclens is required for this input")?)?;
+        if doc_tokens.is_empty() {
+            return Err("No documents to load".to_string());
+        }
+        match extension {
+            "npy" => formats::numpy::read_documents(&bytes, &doc_tokens)?,
+            "safetensors" => formats::safetensors::read_documents(&bytes, tensor, &doc_tokens)?,
+            _ => return Err(format!("Unsupported input {:?} (expected .npy, .safetensors or .npz)", input)),
+        }
+    };
+
+    let encoding = if options.quantize { Encoding::Int8 } else { Encoding::F32 };
+    let index = formats::index::serialize(&docs, encoding);
+    write(options.output()?, &index)?;
+    Ok(format!("Wrote {}\n{}", options.output()?, describe(&Header::parse(&index)?, &index)))
+}
+
+// A `.npy` integer array, or an inline comma-separated list
+fn read_doclens(doclens: &str) -> Result<Vec<usize>, String> {
+    if doclens.ends_with(".npy") {
+        return formats::numpy::read_lengths(&read(doclens)?);
+    }
+    doclens
+        .split(',')
+        .map(|len| len.trim().parse().map_err(|_| format!("Invalid document length {:?}", len)))
+        .collect()
+}
+
+fn inspect(options: &Options) -> Result<String, String> {
+    let bytes = read(options.input()?)?;
+    Ok(describe(&Header::parse(&bytes)?, &bytes))
+}
+
+fn quantize(options: &Options) -> Result<String, String> {
+    let docs = load(options.input()?)?;
+    let index = formats::index::serialize(&docs, Encoding::Int8);
+    let error = max_abs_diff(&docs, &formats::index::deserialize(&index)?);
+    write(options.output()?, &index)?;
+    Ok(format!(
+        "Wrote {}\n{}\nmax dequantization error: {:.6}",
+        options.output()?,
+        describe(&Header::parse(&index)?, &index),
+        error
+    ))
+}
+
+fn validate(options: &Options) -> Result<String, String> {
+    let bytes = read(options.input()?)?;
+    let header = Header::parse(&bytes)?;
+    let docs = formats::index::deserialize(&bytes)?;
+    let dim = docs.embedding_dim;
+
+    let tokens = docs.pages().iter().flat_map(|page| page.embeddings.chunks_exact(dim));
+    let (mut non_finite, mut zero) = (0, 0);
+    for token in tokens {
+        non_finite += token.iter().any(|x| !x.is_finite()) as usize;
+        zero += token.iter().all(|&x| x == 0.0) as usize;
+    }
+    if non_finite > 0 {
+        return Err(format!("{} tokens contain NaN or infinite values", non_finite));
+    }
+
+    let mut report = format!("OK\n{}", describe(&header, &bytes));
+    if zero > 0 {
+        report.push_str(&format!("\nwarning: {} all-zero tokens (usually a padding or export bug)", zero));
+    }
+    let empty = header.doc_tokens.iter().filter(|&&tokens| tokens == 0).count();
+    if empty > 0 {
+        report.push_str(&format!("\nwarning: {} empty documents (they score 0)", empty));
+    }
+    Ok(report)
+}
+
+fn delta(options: &Options) -> Result<String, String> {
+    let base = read(options.base.as_deref().ok_or_else(|| format!("Missing --base <index>\n{}", USAGE))?)?;
+    let updated = read(options.input()?)?;
+    let delta = maxsim_core::delta::create(&base, &updated)?;
+    let parsed = maxsim_core::delta::Delta::parse(&delta)?;
+    write(options.output()?, &delta)?;
+    Ok(format!(
+        "Wrote {}\nremoved:    {} documents\nadded:      {} documents\nsize:       {} bytes ({} for the updated index)",
+        options.output()?,
+        parsed.removed.len(),
+        Header::parse(parsed.added)?.doc_tokens.len(),
+        delta.len(),
+        updated.len()
+    ))
+}
+
+fn load(path: &str) -> Result<PreloadedDocuments, String> {
+    Ok(formats::index::deserialize(&read(path)?)?)
+}
+
+fn max_abs_diff(a: &PreloadedDocuments, b: &PreloadedDocuments) -> f32 {
+    let floats_a = a.pages().iter().flat_map(|page| &page.embeddings);
+    let floats_b = b.pages().iter().flat_map(|page| &page.embeddings);
+    floats_a.zip(floats_b).fold(0.0, |m, (x, y)| m.max((x - y).abs()))
+}
+
+fn describe(header: &Header, bytes: &[u8]) -> String {
+    let doc_tokens = &header.doc_tokens;
+    let total_tokens: usize = doc_tokens.iter().sum();
+    let encoding = match header.encoding {
+        Encoding::F32 => "f32",
+        Encoding::Int8 => "int8 (per-token scale)",
+    };
+    format!(
+        "format:     MXSI v{}\nencoding:   {}\ndim:        {}\ndocuments:  {}\ntokens:     {} (per document: min {}, mean {:.1}, max {})\nsize:       {} bytes ({} bytes as f32)",
+        formats::read_u32(bytes, 4),
+        encoding,
+        header.embedding_dim,
+        doc_tokens.len(),
+        total_tokens,
+        doc_tokens.iter().min().unwrap_or(&0),
+        total_tokens as f64 / doc_tokens.len().max(1) as f64,
+        doc_tokens.iter().max().unwrap_or(&0),
+        bytes.len(),
+        total_tokens * header.embedding_dim * 4,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_quantize_and_validate_an_index() {
+        let dir = std::env::temp_dir().join(format!("maxsim-cli-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+        let args = |line: &str| line.split(' ').map(|arg| arg.replace('@', &path(""))).collect::<Vec<_>>();
+
+        let values: Vec<f32> = (0..12).map(|i| (i as f32 - 5.0) / 4.0).collect();
+        let data: Vec<u8> = values.iter().flat_map(|v| v.to_le_bytes()).collect();
+        std::fs::write(path("emb.npy"), formats::numpy::write_npy("<f4", &[6, 2], &data)).unwrap();
+
+        let built = run(&args("build-index @emb.npy --doclens 2,3,1 -o @index.mxsi")).unwrap();
+        assert!(built.contains("documents:  3") && built.contains("encoding:   f32"));
+        let restored = load(&path("index.mxsi")).unwrap();
+        assert_eq!(restored.doc_tokens(), &[2, 3, 1]);
+        assert_eq!(restored.pages()[0].embeddings, values);
+
+        let quantized = run(&args("quantize @index.mxsi -o @int8.mxsi")).unwrap();
+        assert!(quantized.contains("int8") && quantized.contains("max dequantization error: 0.00"));
+        assert!(run(&args("validate @int8.mxsi")).unwrap().starts_with("OK"));
+        assert!(run(&args("inspect @int8.mxsi")).unwrap().contains("tokens:     6 (per document: min 1, mean 2.0, max 3)"));
+        let delta = run(&args("delta @index.mxsi --base @index.mxsi -o @same.mxsd")).unwrap();
+        assert!(delta.contains("removed:    0 documents") && delta.contains("added:      0 documents"));
+        assert!(run(&args("delta @int8.mxsi --base @index.mxsi -o @x.mxsd")).is_err());
+
+        assert!(run(&args("build-index @emb.npy -o @x.mxsi")).unwrap_err().contains("--doclens"));
+        assert!(run(&args("validate @emb.npy")).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,22 @@
+/*!
+ * Millisecond wall clock for time-budgeted work
+ *
+ * `std::time::Instant` is unavailable on wasm32-unknown-unknown, so the browser
+ * clock (`Date.now()`) is used there and `Instant` everywhere else.
+ */
+
+/// Milliseconds since an arbitrary, fixed origin
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn now_ms() -> f64 {
+    js_sys::Date::now()
+}
+
+/// Milliseconds since an arbitrary, fixed origin
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn now_ms() -> f64 {
+    use std::sync::OnceLock;
+    use std::time::Instant;
+
+    static ORIGIN: OnceLock<Instant> = OnceLock::new();
+    ORIGIN.get_or_init(Instant::now).elapsed().as_secs_f64() * 1000.0
+}
@@ -0,0 +1,131 @@
+/*!
+ * maxsim-cpu compatible entry points
+ *
+ * Free functions with the parameter conventions of the `maxsim-cpu` crate, so a
+ * server pipeline built on it and the browser can be swapped without re-validating
+ * rankings. Row-major f32 throughout:
+ * - `maxsim_scores`:          query [q_len, dim], docs one dense [n_docs, d_len, dim]
+ *   array; every row is a document token (zero padding rows included)
+ * - `maxsim_scores_variable`: query [q_len, dim], docs back to back with `doc_lens`
+ *
+ * Score parity, independent of any instance configuration (no stopmask,
+ * auto-normalization, clamping or alternative aggregation):
+ * - score = Σ_q max_d ⟨q, d⟩, the raw sum over query tokens with dot product
+ * - per-query-token maxima are summed in query-token order
+ * - documents are grouped by length with the same 20% tolerance and groups of at
+ *   most 128; grouping only changes the speed, never a score
+ * - dot products accumulate in SIMD lanes rather than BLAS order, so scores agree to
+ *   float rounding (~1e-6 relative), not bit for bit; rankings only differ on ties
+ *   within that rounding
+ *
+ * testdata/maxsim_cpu_vectors.txt holds vectors on which every f32 implementation
+ * is exact (see scripts/gen-maxsim-cpu-vectors.py, whose `--check` runs them
+ * through maxsim_cpu): both sides must reproduce those scores bit for bit.
+ */
+
+use wasm_bindgen::prelude::*;
+
+use crate::metric::Metric;
+use crate::MaxSimWasm;
+
+/// MaxSim of a dense, uniform-length document array (maxsim-cpu `maxsim_scores`)
+#[wasm_bindgen]
+pub fn maxsim_scores(
+    query: &[f32],
+    q_len: usize,
+    docs: &[f32],
+    n_docs: usize,
+    d_len: usize,
+    dim: usize,
+) -> Result<Vec<f32>, JsValue> {
+    scores_impl(query, q_len, docs, &vec![d_len; n_docs], dim).map_err(|e| JsValue::from_str(&e))
+}
+
+/// MaxSim of variable-length documents (maxsim-cpu `maxsim_scores_variable`)
+#[wasm_bindgen]
+pub fn maxsim_scores_variable(
+    query: &[f32],
+    q_len: usize,
+    docs: &[f32],
+    doc_lens: &[usize],
+    dim: usize,
+) -> Result<Vec<f32>, JsValue> {
+    scores_impl(query, q_len, docs, doc_lens, dim).map_err(|e| JsValue::from_str(&e))
+}
+
+fn scores_impl(query: &[f32], q_len: usize, docs: &[f32], doc_lens: &[usize], dim: usize) -> Result<Vec<f32>, String> {
+    if q_len == 0 || dim == 0 || query.len() != q_len * dim {
+        return Err(format!("Query must be a non-empty [{}, {}] array", q_len, dim));
+    }
+    if docs.len() != doc_lens.iter().sum::<usize>() * dim {
+        return Err("Document array size does not match doc_lens and dim".to_string());
+    }
+
+    // A default engine: official MaxSim with nothing applied on top
+    let engine = MaxSimWasm::new();
+    Ok(engine.score_batch_prepared(query, q_len, docs, doc_lens, &[], dim, false, Metric::DotProduct, None))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conformance::reference_scores;
+    use crate::tuning::synthetic;
+
+    // Vectors shared with maxsim-cpu (scripts/gen-maxsim-cpu-vectors.py): values k / 64
+    // in dimension 8, so every f32 implementation produces the exact scores
+    const MAXSIM_CPU_VECTORS: &str = include_str!("../testdata/maxsim_cpu_vectors.txt");
+
+    #[test]
+    fn test_matches_maxsim_cpu_vectors() {
+        let mut cases = 0;
+        let mut lines = MAXSIM_CPU_VECTORS.lines().filter(|line| !line.starts_with('#'));
+        while let Some(header) = lines.next() {
+            let fields: Vec<&str> = header.split(' ').collect();
+            let field = |key: &str| fields.iter().find_map(|f| f.strip_prefix(key)).unwrap().parse::<usize>().unwrap();
+            let (dim, q_len) = (field("dim="), field("q_len="));
+            let mut row = |key: &str| {
+                let line = lines.next().unwrap().strip_prefix(key).unwrap();
+                line.split(' ').map(|v| v.parse::<f32>().unwrap()).collect::<Vec<f32>>()
+            };
+            let doc_lens: Vec<usize> = row("doc_lens ").iter().map(|&len| len as usize).collect();
+            let query: Vec<f32> = row("query ").iter().map(|k| k / 64.0).collect();
+            let docs: Vec<f32> = row("docs ").iter().map(|k| k / 64.0).collect();
+            let expected = row("scores ");
+
+            let scores = match fields[2] {
+                "maxsim_scores" => maxsim_scores(&query, q_len, &docs, doc_lens.len(), doc_lens[0], dim),
+                _ => maxsim_scores_variable(&query, q_len, &docs, &doc_lens, dim),
+            };
+            assert_eq!(scores.unwrap(), expected, "case {}", fields[1]);
+            cases += 1;
+        }
+        assert_eq!(cases, 3);
+    }
+
+    #[test]
+    fn test_matches_the_f64_reference() {
+        // Exactly representable vector: scores must match bit for bit
+        let query = [1.0, 0.0, 0.5, 0.5];
+        let docs = [0.5, 0.25, 0.0, 1.0, -1.0, 0.0, 0.0, 0.0];
+        assert_eq!(maxsim_scores(&query, 2, &docs, 2, 2, 2).unwrap(), vec![0.5 + 0.5, 0.0 + 0.0]);
+        assert_eq!(maxsim_scores_variable(&query, 2, &docs, &[3, 1], 2).unwrap(), vec![0.5 + 0.5, 0.0]);
+
+        // Seeded random corpora covering the uniform path (≥ 50 docs, equal lengths or
+        // within 20%) and length-grouped batches
+        let dim = 48;
+        let query = synthetic(8 * dim, 2035);
+        let corpora: [Vec<usize>; 3] = [
+            vec![20; 60],
+            (0..60).map(|i| 20 + i % 4).collect(),
+            (0..90).map(|i| 1 + i * 7 % 61).collect(),
+        ];
+        for (seed, doc_lens) in (2036..).zip(corpora) {
+            let docs = synthetic(doc_lens.iter().sum::<usize>() * dim, seed);
+            let scores = maxsim_scores_variable(&query, 8, &docs, &doc_lens, dim).unwrap();
+            for (score, expected) in scores.iter().zip(reference_scores(&query, &docs, &doc_lens, dim)) {
+                assert!((*score as f64 - expected).abs() <= 1e-5 * expected.abs().max(1.0), "{} vs {}", score, expected);
+            }
+        }
+    }
+}
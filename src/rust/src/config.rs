@@ -0,0 +1,209 @@
+/*!
+ * Instance-level scoring defaults
+ *
+ * A `MaxSimConfig` fixes the metric, aggregation and score normalization once, at
+ * construction (`MaxSimWasm.with_config`), so call sites don't thread options
+ * through every search. Explicit per-call variants (`*_normalized`, `*_with_metric`)
+ * still override the instance defaults.
+ *
+ * The official entry points (`maxsim_single`, `maxsim_batch` and their `_normalized`
+ * variants) ignore the configuration: they always score the dot product with Max
+ * aggregation, no length penalty, no clamping and f32 accumulation, so they keep
+ * matching the reference implementations. The configuration applies to every other
+ * search.
+ *
+ * Nothing in the stored corpus depends on the configuration: documents keep raw
+ * embeddings and squared token norms, which serve every metric and aggregation. A
+ * configuration can therefore be replaced at any time (`MaxSimWasm.set_config`)
+ * without reloading documents; in-flight async searches keep the configuration they
+ * started with.
+ */
+
+use wasm_bindgen::prelude::*;
+
+use crate::aggregation::{Aggregation, RowReduction};
+use crate::metric::Metric;
+use crate::rng::SeededRng;
+use crate::score_norm::ScoreNorm;
+
+/// Default scoring options of a `MaxSimWasm` instance
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MaxSimConfig {
+    metric: Metric,
+    aggregation: Aggregation,
+    normalized: bool,
+    score_norm: ScoreNorm,
+    softmax_temperature: f32,
+    top_k: usize,
+    top_p: f32,
+    clamp_similarities: bool,
+    precise_accumulation: bool,
+    seed: u32,
+}
+
+impl Default for MaxSimConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[wasm_bindgen]
+impl MaxSimConfig {
+    /// Official MaxSim defaults: DotProduct, Max aggregation, raw (unnormalized) sum
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> MaxSimConfig {
+        MaxSimConfig {
+            metric: Metric::DotProduct,
+            aggregation: Aggregation::Max,
+            normalized: false,
+            score_norm: ScoreNorm::None,
+            softmax_temperature: 1.0,
+            top_k: 1,
+            top_p: 0.1,
+            clamp_similarities: false,
+            precise_accumulation: false,
+            seed: 0,
+        }
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn metric(&self) -> Metric {
+        self.metric
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_metric(&mut self, metric: Metric) {
+        self.metric = metric;
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn aggregation(&self) -> Aggregation {
+        self.aggregation
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_aggregation(&mut self, aggregation: Aggregation) {
+        self.aggregation = aggregation;
+    }
+
+    /// Whether the `*_default` APIs average over query tokens
+    #[wasm_bindgen(getter)]
+    pub fn normalized(&self) -> bool {
+        self.normalized
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_normalized(&mut self, normalized: bool) {
+        self.normalized = normalized;
+    }
+
+    /// Length penalty dividing every document score (see `ScoreNorm`)
+    ///
+    /// Applies to the preloaded and collection searches and to the `*_with_metric`
+    /// and `*_default` batch calls, on top of the per-call raw/normalized choice.
+    /// The official entry points never apply it, and no per-call override exists.
+    #[wasm_bindgen(getter)]
+    pub fn score_norm(&self) -> ScoreNorm {
+        self.score_norm
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_score_norm(&mut self, score_norm: ScoreNorm) {
+        self.score_norm = score_norm;
+    }
+
+    /// Temperature of the Softmax and LogSumExp aggregations (lower is closer to Max)
+    #[wasm_bindgen(getter)]
+    pub fn softmax_temperature(&self) -> f32 {
+        self.softmax_temperature
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_softmax_temperature(&mut self, temperature: f32) {
+        self.softmax_temperature = temperature.max(f32::MIN_POSITIVE);
+    }
+
+    /// Number of best document tokens averaged by the TopK aggregation
+    #[wasm_bindgen(getter)]
+    pub fn top_k(&self) -> usize {
+        self.top_k
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_top_k(&mut self, top_k: usize) {
+        self.top_k = top_k.max(1);
+    }
+
+    /// Fraction of document tokens averaged by the TopP aggregation, in (0, 1]
+    /// (default 0.1; at least one token is always kept)
+    #[wasm_bindgen(getter)]
+    pub fn top_p(&self) -> f32 {
+        self.top_p
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_top_p(&mut self, top_p: f32) {
+        self.top_p = if top_p.is_nan() { 1.0 } else { top_p.clamp(f32::MIN_POSITIVE, 1.0) };
+    }
+
+    /// Robustness mode: clamp token similarities to [-1, 1] before aggregation
+    ///
+    /// Slightly de-normalized embeddings can produce similarities just outside
+    /// [-1, 1] that skew Softmax aggregation and calibrated outputs. The number of
+    /// clamped values is reported in the score trace. Ignored for NegativeL2.
+    #[wasm_bindgen(getter)]
+    pub fn clamp_similarities(&self) -> bool {
+        self.clamp_similarities
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_clamp_similarities(&mut self, clamp: bool) {
+        self.clamp_similarities = clamp;
+    }
+
+    /// Accuracy mode: accumulate dot products and per-document sums in f64
+    ///
+    /// f32 accumulation over 1024-dim tokens drifts enough to flip near-tied rankings
+    /// against an f64 (e.g. PyTorch double) reference. Precise scoring runs a scalar
+    /// kernel per document, several times slower than the SIMD paths. Applies to the
+    /// f32 corpora (per-call batches, preloaded documents, collections); the
+    /// compressed corpora keep their own kernels.
+    #[wasm_bindgen(getter)]
+    pub fn precise_accumulation(&self) -> bool {
+        self.precise_accumulation
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_precise_accumulation(&mut self, precise: bool) {
+        self.precise_accumulation = precise;
+    }
+
+    /// Seed of every stochastic feature (sampling, estimation), for reproducible runs
+    #[wasm_bindgen(getter)]
+    pub fn seed(&self) -> u32 {
+        self.seed
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_seed(&mut self, seed: u32) {
+        self.seed = seed;
+    }
+}
+
+impl MaxSimConfig {
+    /// Row reduction selected by this configuration
+    pub(crate) fn reduction(&self) -> RowReduction {
+        RowReduction {
+            aggregation: self.aggregation,
+            temperature: self.softmax_temperature,
+            top_k: self.top_k,
+            top_p: self.top_p,
+        }
+    }
+
+    /// Generator of the feature using `stream` (see rng.rs), seeded from `seed`
+    pub(crate) fn rng(&self, stream: u64) -> SeededRng {
+        SeededRng::new(self.seed as u64, stream)
+    }
+}
@@ -0,0 +1,171 @@
+/*!
+ * Score parity against a reference implementation
+ *
+ * The batch path dispatches to several optimized strategies (uniform-length fast
+ * path, length groups scored individually, padded groups split into sub-batches)
+ * that must all agree with plain MaxSim. `verify()` scores a batch with a default
+ * engine and compares every score with a straightforward f64 implementation:
+ *
+ * ```text
+ * reference = Σ_q max_d Σ_j q_j · d_j    (nested loops, f64 accumulation)
+ * ```
+ *
+ * A score passes when it is within `TOLERANCE` of the reference, relative to the
+ * larger of its magnitude and 1 (SIMD lanes accumulate in f32, so scores agree to
+ * rounding, not bit for bit). The report names the strategies the batch took, so a
+ * divergence can be pinned to one path.
+ */
+
+use wasm_bindgen::prelude::*;
+
+use crate::metric::Metric;
+use crate::MaxSimWasm;
+
+/// Relative error allowed between a batch score and the reference
+const TOLERANCE: f64 = 1e-5;
+
+/// Comparison of one batch with the reference implementation
+#[wasm_bindgen]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ConformanceReport {
+    num_docs: usize,
+    max_error: f64,
+    worst_doc: usize,
+    failures: usize,
+    paths: String,
+}
+
+#[wasm_bindgen]
+impl ConformanceReport {
+    #[wasm_bindgen(getter)]
+    pub fn num_docs(&self) -> usize {
+        self.num_docs
+    }
+
+    /// Largest relative error over the batch
+    #[wasm_bindgen(getter)]
+    pub fn max_error(&self) -> f64 {
+        self.max_error
+    }
+
+    /// Document with the largest error
+    #[wasm_bindgen(getter)]
+    pub fn worst_doc(&self) -> usize {
+        self.worst_doc
+    }
+
+    /// Documents outside the tolerance
+    #[wasm_bindgen(getter)]
+    pub fn failures(&self) -> usize {
+        self.failures
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn passed(&self) -> bool {
+        self.failures == 0
+    }
+
+    /// Strategies taken by the batch: "uniform", or "variable" followed by the group
+    /// modes that ran ("variable:individual+batched")
+    #[wasm_bindgen(getter)]
+    pub fn paths(&self) -> String {
+        self.paths.clone()
+    }
+}
+
+/// Compare the batch scores of variable-length documents (back to back with
+/// `doc_lens`) with the reference implementation
+#[wasm_bindgen]
+pub fn verify(query: &[f32], q_len: usize, docs: &[f32], doc_lens: &[usize], dim: usize) -> Result<ConformanceReport, JsValue> {
+    verify_impl(query, q_len, docs, doc_lens, dim).map_err(|e| JsValue::from_str(&e))
+}
+
+fn verify_impl(query: &[f32], q_len: usize, docs: &[f32], doc_lens: &[usize], dim: usize) -> Result<ConformanceReport, String> {
+    if q_len == 0 || dim == 0 || query.len() != q_len * dim {
+        return Err(format!("Query must be a non-empty [{}, {}] array", q_len, dim));
+    }
+    if docs.len() != doc_lens.iter().sum::<usize>() * dim {
+        return Err("Document array size does not match doc_lens and dim".to_string());
+    }
+
+    // A default engine traced to record the strategies taken
+    let mut engine = MaxSimWasm::new();
+    engine.set_trace_enabled(true);
+    let scores = engine.score_batch_prepared(query, q_len, docs, doc_lens, &[], dim, false, Metric::DotProduct, None);
+
+    let mut report = ConformanceReport { num_docs: doc_lens.len(), paths: batch_paths(&engine.last_trace()), ..Default::default() };
+    for (doc_idx, (&score, expected)) in scores.iter().zip(reference_scores(query, docs, doc_lens, dim)).enumerate() {
+        let error = (score as f64 - expected).abs() / expected.abs().max(1.0);
+        if error > TOLERANCE {
+            report.failures += 1;
+        }
+        if error > report.max_error {
+            report.max_error = error;
+            report.worst_doc = doc_idx;
+        }
+    }
+    Ok(report)
+}
+
+/// Raw-sum dot-product MaxSim in f64, plain loops
+pub(crate) fn reference_scores(query: &[f32], docs: &[f32], doc_lens: &[usize], dim: usize) -> Vec<f64> {
+    let mut offset = 0;
+    doc_lens
+        .iter()
+        .map(|&len| {
+            let doc = &docs[offset * dim..(offset + len) * dim];
+            offset += len;
+            if doc.is_empty() {
+                return 0.0;
+            }
+            query
+                .chunks_exact(dim)
+                .map(|q| {
+                    doc.chunks_exact(dim)
+                        .map(|d| q.iter().zip(d).map(|(&a, &b)| a as f64 * b as f64).sum::<f64>())
+                        .fold(f64::NEG_INFINITY, f64::max)
+                })
+                .sum()
+        })
+        .collect()
+}
+
+// Strategies named by the "path=" and group "mode=" events of a batch trace
+fn batch_paths(trace: &str) -> String {
+    let value = |line: &str, key: &str| line.split(' ').find_map(|field| field.strip_prefix(key)).map(str::to_string);
+    let path = trace.lines().find_map(|line| value(line, "path=")).unwrap_or_default();
+    let mut modes: Vec<String> = Vec::new();
+    for mode in trace.lines().filter_map(|line| value(line, "mode=")) {
+        if !modes.contains(&mode) {
+            modes.push(mode);
+        }
+    }
+    if modes.is_empty() { path } else { format!("{}:{}", path, modes.join("+")) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tuning::synthetic;
+
+    #[test]
+    fn test_every_batch_path_matches_reference() {
+        let dim = 96;
+        let query = synthetic(12 * dim, 2073);
+        // Uniform fast path; small groups scored individually; one length group of 40
+        // documents split into sub-batches, next to a lone long document
+        let corpora: [(Vec<usize>, &str); 3] = [
+            ((0..64).map(|i| 30 + i % 5).collect(), "uniform"),
+            (vec![3, 9, 27, 81, 0], "variable:individual"),
+            ((0..41).map(|i| if i == 40 { 200 } else { 50 + i % 3 }).collect(), "variable:batched+individual"),
+        ];
+        for (seed, (doc_lens, paths)) in (2074..).zip(corpora) {
+            let docs = synthetic(doc_lens.iter().sum::<usize>() * dim, seed);
+            let report = verify(&query, 12, &docs, &doc_lens, dim).unwrap();
+            assert_eq!(report.paths(), paths);
+            assert!(report.passed(), "{} docs off, worst {} by {}", report.failures(), report.worst_doc(), report.max_error());
+        }
+
+        assert!(verify_impl(&query, 12, &query, &[5], dim).is_err());
+    }
+}
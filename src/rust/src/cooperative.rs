@@ -0,0 +1,173 @@
+/*!
+ * Cooperative (chunked) search over the preloaded corpus
+ *
+ * A long synchronous search blocks the UI thread for its whole duration. A
+ * `ChunkedSearch` scores at most `chunk_docs` documents per step; the async API
+ * yields to the event loop between steps so rendering and input handling can run.
+ * The search holds its own snapshot of the corpus (an `Rc`), so documents reloaded
+ * while it is in flight don't affect its results.
+ */
+
+use std::rc::Rc;
+
+use js_sys::{Function, Promise, Reflect};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+
+use crate::metric::Metric;
+use crate::scoring::DocIds;
+use crate::store::PreloadedDocuments;
+use crate::watchdog::Group;
+use crate::MaxSimWasm;
+
+pub(crate) struct ChunkedSearch {
+    engine: MaxSimWasm, // Scoring snapshot of the owning instance (settings, own buffers)
+    docs: Rc<PreloadedDocuments>,
+    query: Vec<f32>, // Prepared query (masked, normalized)
+    query_tokens: usize,
+    normalized: bool,
+    metric: Metric,
+    chunk_docs: usize,
+    page: usize,       // Current page
+    next_doc: usize,   // Next page-local document to score
+    next_token: usize, // Page-local token offset of `next_doc`
+    scores: Vec<f32>,
+    watched: Vec<Group>, // Length groups of every step, inspected once at the end
+}
+
+impl ChunkedSearch {
+    pub(crate) fn new(
+        engine: MaxSimWasm,
+        docs: Rc<PreloadedDocuments>,
+        query_flat: &[f32],
+        query_tokens: usize,
+        normalized: bool,
+        metric: Metric,
+        chunk_docs: usize,
+    ) -> Result<Self, String> {
+        if query_tokens == 0 {
+            return Err("Query cannot be empty".to_string());
+        }
+        if query_flat.len() != query_tokens * docs.embedding_dim {
+            return Err("Query size mismatch".to_string());
+        }
+
+        let (query, query_tokens) = engine.prepare_query_tokens(query_flat, query_tokens, &[], docs.embedding_dim)?;
+        Ok(ChunkedSearch {
+            query: query.into_owned(),
+            query_tokens,
+            scores: vec![0.0; docs.num_docs()],
+            watched: Vec::new(),
+            engine,
+            docs,
+            normalized,
+            metric,
+            chunk_docs: chunk_docs.max(1),
+            page: 0,
+            next_doc: 0,
+            next_token: 0,
+        })
+    }
+
+    /// Score the next chunk of documents; returns false once every document is scored
+    pub(crate) fn step(&mut self) -> bool {
+        let dim = self.docs.embedding_dim;
+        let Some(page) = self.docs.pages().get(self.page) else {
+            return false;
+        };
+
+        let start = self.next_doc;
+        let end = (start + self.chunk_docs).min(page.doc_tokens.len());
+        let doc_tokens = &page.doc_tokens[start..end];
+        let token_end = self.next_token + doc_tokens.iter().sum::<usize>();
+
+        let ctx = self.engine.score_context(
+            self.normalized,
+            self.metric,
+            &self.query,
+            Some(&page.token_norms[self.next_token..token_end]),
+            dim,
+        )
+        .with_doc_ids(DocIds::From(page.first_doc + start));
+        // The precomputed length order only applies when the chunk is the whole page
+        let length_order = if start == 0 && end == page.doc_tokens.len() {
+            page.length_order.as_deref()
+        } else {
+            None
+        };
+        let chunk_scores = self.engine.maxsim_batch_impl(
+            &self.query,
+            self.query_tokens,
+            &page.embeddings[self.next_token * dim..token_end * dim],
+            doc_tokens,
+            dim,
+            &ctx,
+            length_order,
+        );
+        let first = page.first_doc + start;
+        self.scores[first..first + chunk_scores.len()].copy_from_slice(&chunk_scores);
+        self.watched.extend(ctx.take_watched().unwrap_or_default());
+
+        if end == page.doc_tokens.len() {
+            self.page += 1;
+            self.next_doc = 0;
+            self.next_token = 0;
+        } else {
+            self.next_doc = end;
+            self.next_token = token_end;
+        }
+        self.page < self.docs.pages().len()
+    }
+
+    /// Scores of every document, after reporting the watchdog findings of the whole search
+    pub(crate) fn into_scores(self) -> Vec<f32> {
+        self.engine.report_watchdog(&self.watched);
+        self.scores
+    }
+}
+
+/// Resolve on the next macrotask (`setTimeout(0)`), letting the browser render and
+/// handle input; a resolved-promise microtask would not yield to the event loop
+pub(crate) fn yield_to_event_loop() -> JsFuture {
+    let promise = Promise::new(&mut |resolve, reject| {
+        let global = js_sys::global();
+        let scheduled = Reflect::get(&global, &JsValue::from_str("setTimeout"))
+            .and_then(|f| f.dyn_into::<Function>())
+            .and_then(|set_timeout| set_timeout.call2(&global, &resolve, &JsValue::from_f64(0.0)));
+        if let Err(err) = scheduled {
+            let _ = reject.call1(&JsValue::UNDEFINED, &err);
+        }
+    });
+    JsFuture::from(promise)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunked_search_matches_one_shot() {
+        // Two pages of 2-dim documents with 2, 1, 3 | 2 tokens
+        let mut docs = PreloadedDocuments::with_page_floats(2, 12);
+        let embeddings = [0.6, 0.8, 1.0, 0.0, 0.0, 1.0, 0.8, 0.6, 1.0, 0.0, 0.6, 0.8, 0.0, 1.0, 1.0, 0.0];
+        let mut offset = 0;
+        for tokens in [2, 1, 3, 2] {
+            docs.push_document(&embeddings[offset..offset + tokens * 2]);
+            offset += tokens * 2;
+        }
+        assert_eq!(docs.pages().len(), 2);
+
+        let query = [1.0, 0.0, 0.0, 1.0];
+        let expected = MaxSimWasm::new().maxsim_batch(&query, 2, &embeddings, &[2, 1, 3, 2], 2).unwrap();
+
+        let mut search =
+            ChunkedSearch::new(MaxSimWasm::new(), Rc::new(docs), &query, 2, false, Metric::DotProduct, 2).unwrap();
+        let mut steps = 1;
+        while search.step() {
+            steps += 1;
+        }
+        assert_eq!(steps, 3); // [0, 1], [2], [3]
+        assert_eq!(search.into_scores(), expected);
+    }
+}
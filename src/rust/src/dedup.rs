@@ -0,0 +1,189 @@
+/*!
+ * Duplicate-document detection at load time
+ *
+ * `MaxSimWasm.load_documents_dedup()` maps every document to the first earlier
+ * document it duplicates. Two checks run per document, against the first documents
+ * of their kind only:
+ *
+ * - Exact copies (same token count and identical floats) are found by a content
+ *   hash and confirmed by comparing the floats; they map to whatever their first
+ *   copy maps to, so every entry points at a first document of its kind.
+ * - Near-duplicates are proposed by locality-sensitive hashing of the pooled
+ *   vector (mean of the L2-normalized tokens, scaled to unit length and centred on
+ *   the corpus mean, its signs against `TABLES × BITS` random hyperplanes, `BITS`
+ *   per table) and confirmed by symmetric cosine MaxSim:
+ *
+ * ```text
+ * similarity(a, b) = (mean_i max_j cos(a_i, b_j) + mean_j max_i cos(a_i, b_j)) / 2
+ * ```
+ *
+ * Documents sharing a bucket in any table are candidates; a candidate at or above
+ * the threshold is a duplicate. Centring matters: ColBERT embeddings are
+ * anisotropic, so uncentred pooled vectors sit in a narrow cone and most documents
+ * would share every bucket. Candidates sharing the most tables are compared first,
+ * at most `MAX_CANDIDATES` per document, which bounds the pass to linear time in
+ * full MaxSims. Hashing only bounds the comparisons: a near-duplicate whose pooled
+ * vector lands in other buckets in every table, or behind `MAX_CANDIDATES` closer
+ * collisions, is missed (rare above similarity 0.95), never a false positive.
+ */
+
+use std::collections::HashMap;
+
+use crate::dot_product;
+use crate::rng::SeededRng;
+
+/// Hash tables of the LSH index
+const TABLES: usize = 4;
+
+/// Hyperplanes (signature bits) per table
+const BITS: usize = 12;
+
+/// Near-duplicate candidates compared per document
+const MAX_CANDIDATES: usize = 32;
+
+/// Input index of the first document each document duplicates (its own index for
+/// the first of its kind); near-duplicates only when `threshold < 1`
+pub(crate) fn find_duplicates(flat: &[f32], doc_tokens: &[usize], dim: usize, threshold: f32, rng: &mut SeededRng) -> Vec<usize> {
+    let hyperplanes: Vec<f32> = (0..TABLES * BITS * dim).map(|_| (rng.next_u64() >> 40) as f32 / (1u32 << 23) as f32 - 1.0).collect();
+    let token_norms: Vec<f32> = flat.chunks_exact(dim).map(|token| dot_product(token, token).sqrt()).collect();
+    let offsets: Vec<usize> = doc_tokens
+        .iter()
+        .scan(0, |offset, &tokens| {
+            let start = *offset;
+            *offset += tokens;
+            Some(start)
+        })
+        .collect();
+    let doc = |i: usize| (offsets[i]..offsets[i] + doc_tokens[i], &flat[offsets[i] * dim..(offsets[i] + doc_tokens[i]) * dim]);
+
+    // Mean of the unit pooled vectors, the centre of the hyperplanes (near-duplicates only)
+    let mut center = vec![0.0; dim];
+    if threshold < 1.0 {
+        for i in 0..doc_tokens.len() {
+            let (tokens, embeddings) = doc(i);
+            let vector = pooled(embeddings, &token_norms[tokens], dim);
+            center.iter_mut().zip(vector).for_each(|(sum, value)| *sum += value / doc_tokens.len() as f32);
+        }
+    }
+
+    let mut exact: HashMap<u64, Vec<usize>> = HashMap::new();
+    let mut buckets: HashMap<(usize, u32), Vec<usize>> = HashMap::new();
+    let mut canonical = Vec::with_capacity(doc_tokens.len());
+    let mut candidates = Vec::new();
+    for i in 0..doc_tokens.len() {
+        let (tokens, embeddings) = doc(i);
+        let first_copies = exact.entry(content_hash(embeddings)).or_default();
+        if let Some(&first) = first_copies.iter().find(|&&j| doc(j).1 == embeddings) {
+            canonical.push(canonical[first]);
+            continue;
+        }
+        first_copies.push(i);
+        if threshold >= 1.0 || tokens.is_empty() {
+            canonical.push(i);
+            continue;
+        }
+
+        let mut vector = pooled(embeddings, &token_norms[tokens.clone()], dim);
+        vector.iter_mut().zip(&center).for_each(|(value, &mean)| *value -= mean);
+        let keys = signature(&vector, &hyperplanes, dim);
+        candidates.clear();
+        for (table, &key) in keys.iter().enumerate() {
+            candidates.extend(buckets.get(&(table, key)).into_iter().flatten().copied());
+        }
+        let found = ranked_candidates(&mut candidates).into_iter().find(|&j| {
+            let (other_tokens, other) = doc(j);
+            symmetric_cosine(embeddings, &token_norms[tokens.clone()], other, &token_norms[other_tokens], dim) >= threshold
+        });
+        canonical.push(found.unwrap_or(i));
+        if found.is_none() {
+            for (table, &key) in keys.iter().enumerate() {
+                buckets.entry((table, key)).or_default().push(i);
+            }
+        }
+    }
+    canonical
+}
+
+// Distinct candidates, sharing the most tables first (then earliest), at most MAX_CANDIDATES
+fn ranked_candidates(candidates: &mut [usize]) -> Vec<usize> {
+    candidates.sort_unstable();
+    let mut shared: Vec<(usize, usize)> = candidates
+        .chunk_by(|a, b| a == b)
+        .map(|run| (run.len(), run[0]))
+        .collect();
+    shared.sort_unstable_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+    shared.into_iter().take(MAX_CANDIDATES).map(|(_, doc)| doc).collect()
+}
+
+// FNV-1a over the bits of every float
+fn content_hash(embeddings: &[f32]) -> u64 {
+    embeddings.iter().fold(0xcbf2_9ce4_8422_2325, |hash, value| {
+        (hash ^ value.to_bits() as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+// Mean of the L2-normalized tokens (zero tokens left out), scaled to unit length
+fn pooled(embeddings: &[f32], norms: &[f32], dim: usize) -> Vec<f32> {
+    let mut pooled = vec![0.0; dim];
+    for (token, &norm) in embeddings.chunks_exact(dim).zip(norms).filter(|(_, &norm)| norm > 0.0) {
+        for (sum, &value) in pooled.iter_mut().zip(token) {
+            *sum += value / norm;
+        }
+    }
+    let norm = dot_product(&pooled, &pooled).sqrt();
+    if norm > 0.0 {
+        pooled.iter_mut().for_each(|value| *value /= norm);
+    }
+    pooled
+}
+
+// BITS sign bits per table
+fn signature(pooled: &[f32], hyperplanes: &[f32], dim: usize) -> [u32; TABLES] {
+    let mut keys = [0u32; TABLES];
+    for (bit, plane) in hyperplanes.chunks_exact(dim).enumerate() {
+        if dot_product(pooled, plane) >= 0.0 {
+            keys[bit / BITS] |= 1 << (bit % BITS);
+        }
+    }
+    keys
+}
+
+// Symmetric cosine MaxSim from one similarity matrix (zero tokens match nothing)
+fn symmetric_cosine(a: &[f32], a_norms: &[f32], b: &[f32], b_norms: &[f32], dim: usize) -> f32 {
+    let (mut row_max, mut col_max) = (vec![0.0f32; a_norms.len()], vec![0.0f32; b_norms.len()]);
+    for (i, (a_token, &a_norm)) in a.chunks_exact(dim).zip(a_norms).enumerate() {
+        for (j, (b_token, &b_norm)) in b.chunks_exact(dim).zip(b_norms).enumerate() {
+            if a_norm > 0.0 && b_norm > 0.0 {
+                let cosine = dot_product(a_token, b_token) / (a_norm * b_norm);
+                row_max[i] = row_max[i].max(cosine);
+                col_max[j] = col_max[j].max(cosine);
+            }
+        }
+    }
+    let mean = |maxima: &[f32]| maxima.iter().sum::<f32>() / maxima.len() as f32;
+    (mean(&row_max) + mean(&col_max)) / 2.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exact_and_near_duplicates_map_to_the_first_copy() {
+        let doc_a = [1.0, 0.0, 0.0, 0.0, 1.0, 0.0];
+        let doc_b = [0.0, 0.0, 1.0];
+        let near_a = [1.0, 0.02, 0.0, 0.0, 1.0, 0.01]; // doc_a with noise
+        let flat: Vec<f32> = [&doc_a[..], &doc_b, &doc_a, &near_a, &doc_b].concat();
+        let doc_tokens = [2, 1, 2, 2, 1];
+
+        let mut rng = SeededRng::new(0, 0);
+        assert_eq!(find_duplicates(&flat, &doc_tokens, 3, 1.0, &mut rng), [0, 1, 0, 3, 1]);
+        assert_eq!(find_duplicates(&flat, &doc_tokens, 3, 0.99, &mut rng), [0, 1, 0, 0, 1]);
+        // An exact copy of a near-duplicate maps to the document the near-duplicate maps to
+        let chain: Vec<f32> = [&doc_a[..], &near_a, &near_a, &doc_b].concat();
+        assert_eq!(find_duplicates(&chain, &[2, 2, 2, 1], 3, 0.99, &mut rng), [0, 0, 0, 3]);
+        assert_eq!(ranked_candidates(&mut [4, 2, 4, 7, 2, 4]), [4, 2, 7]);
+        // Far below the threshold, the pooled vectors collide but MaxSim rejects them
+        assert_eq!(symmetric_cosine(&doc_a, &[1.0, 1.0], &doc_b, &[1.0], 3), 0.0);
+    }
+}
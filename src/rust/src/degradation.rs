@@ -0,0 +1,119 @@
+/*!
+ * Graceful degradation of oversized queries
+ *
+ * Scratch memory grows with the query length (similarity rows, prepared query
+ * copies), so an instance can cap the number of query tokens it scores
+ * (`MaxSimWasm.set_query_limit`). Longer queries are reduced rather than rejected:
+ * - Truncate:    keep the first `max_tokens` tokens
+ * - PruneByNorm: keep the `max_tokens` tokens with the largest L2 norm (ColBERT-style
+ *   models give low-information tokens smaller norms), in their original order
+ *
+ * The applied reduction is reported as a `QueryDegradation` on the search results
+ * (and by `MaxSimWasm.last_query_degradation`) so the UI can surface it.
+ */
+
+use wasm_bindgen::prelude::*;
+
+/// How a query over the token limit is reduced
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum QueryPruning {
+    #[default]
+    Truncate = 0,
+    PruneByNorm = 1,
+}
+
+impl QueryPruning {
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            QueryPruning::Truncate => "truncate",
+            QueryPruning::PruneByNorm => "prune_by_norm",
+        }
+    }
+
+    /// Keep mask (non-zero = keep) retaining `max_tokens` of the `query_tokens` tokens
+    pub(crate) fn keep_mask(self, query_flat: &[f32], query_tokens: usize, max_tokens: usize, embedding_dim: usize) -> Vec<u8> {
+        let mut keep = vec![0u8; query_tokens];
+        match self {
+            QueryPruning::Truncate => keep[..max_tokens.min(query_tokens)].fill(1),
+            QueryPruning::PruneByNorm => {
+                let norms: Vec<f32> = query_flat
+                    .chunks_exact(embedding_dim)
+                    .map(|token| token.iter().map(|v| v * v).sum())
+                    .collect();
+                let mut order: Vec<usize> = (0..query_tokens).collect();
+                // Largest norms first; ties keep the earlier token
+                order.sort_by(|&a, &b| norms[b].total_cmp(&norms[a]));
+                for &token in order.iter().take(max_tokens) {
+                    keep[token] = 1;
+                }
+            }
+        }
+        keep
+    }
+}
+
+/// Reduction applied to one query
+#[wasm_bindgen]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct QueryDegradation {
+    original_tokens: usize,
+    kept_tokens: usize,
+    strategy: QueryPruning,
+}
+
+impl QueryDegradation {
+    pub(crate) fn new(original_tokens: usize, kept_tokens: usize, strategy: QueryPruning) -> Self {
+        QueryDegradation { original_tokens, kept_tokens, strategy }
+    }
+}
+
+#[wasm_bindgen]
+impl QueryDegradation {
+    /// Active query tokens before the limit was applied (after masks and stopmask)
+    #[wasm_bindgen(getter)]
+    pub fn original_tokens(&self) -> usize {
+        self.original_tokens
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn kept_tokens(&self) -> usize {
+        self.kept_tokens
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn tokens_dropped(&self) -> usize {
+        self.original_tokens - self.kept_tokens
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn strategy(&self) -> QueryPruning {
+        self.strategy
+    }
+
+    /// Human-readable summary, e.g. "dropped 8 of 40 query tokens (truncate)"
+    #[wasm_bindgen]
+    pub fn describe(&self) -> String {
+        format!(
+            "dropped {} of {} query tokens ({})",
+            self.tokens_dropped(), self.original_tokens, self.strategy.name()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keep_masks_retain_max_tokens() {
+        // Norms² 1, 4, 0.25, 4 (tie between tokens 1 and 3)
+        let query = [1.0, 0.0, 0.0, 2.0, 0.5, 0.0, 2.0, 0.0];
+        assert_eq!(QueryPruning::Truncate.keep_mask(&query, 4, 2, 2), vec![1, 1, 0, 0]);
+        assert_eq!(QueryPruning::PruneByNorm.keep_mask(&query, 4, 2, 2), vec![0, 1, 0, 1]);
+        assert_eq!(QueryPruning::PruneByNorm.keep_mask(&query, 4, 3, 2), vec![1, 1, 0, 1]);
+
+        let degradation = QueryDegradation::new(4, 3, QueryPruning::PruneByNorm);
+        assert_eq!(degradation.describe(), "dropped 1 of 4 query tokens (prune_by_norm)");
+    }
+}
@@ -0,0 +1,489 @@
+/*!
+ * Evaluation sweeps over the preloaded corpus
+ *
+ * An `ExperimentRunner` holds a query set and a list of variants (storage precision,
+ * aggregation, ANN `nprobe`) and runs every variant against the loaded documents in
+ * one call. The report compares each variant with exact search (f32, Max aggregation,
+ * no pruning) using the instance metric and stopmask:
+ * - latency:  mean milliseconds per query
+ * - memory:   document storage in the variant's encoding, plus the ANN index if probed
+ * - overlap:  mean fraction of the exact top-k that the variant also returns
+ *
+ * `quantization_impact` is the precision-only counterpart: it rescores a query sample
+ * with the documents round-tripped through f16, int8, int4 and binary storage and reports
+ * how far each ranking drifts from f32 (Spearman correlation and overlap@k).
+ */
+
+use std::fmt::Write as _;
+
+use wasm_bindgen::prelude::*;
+
+use crate::aggregation::Aggregation;
+use crate::ann::AnnIndex;
+use crate::clock::now_ms;
+use crate::formats::index::{self, Encoding};
+use crate::formats::{f16_to_f32, f32_to_f16};
+use maxsim_core::int4;
+use crate::results::SearchHits;
+use crate::store::PreloadedDocuments;
+use crate::MaxSimWasm;
+
+/// One configuration of a sweep
+#[derive(Clone, Debug)]
+struct Variant {
+    label: String,
+    aggregation: Aggregation,
+    quantized: bool, // Score int8-dequantized embeddings (the `serialize_index_quantized` encoding)
+    nprobe: usize,   // 0 = exact scan, otherwise ANN with this many probed clusters
+}
+
+/// Query set and variants of an evaluation sweep
+#[wasm_bindgen]
+pub struct ExperimentRunner {
+    k: usize,
+    queries: Vec<(Vec<f32>, usize)>, // Flat query embeddings and token count
+    variants: Vec<Variant>,
+}
+
+#[wasm_bindgen]
+impl ExperimentRunner {
+    /// Sweep comparing the top `k` hits of every variant
+    #[wasm_bindgen(constructor)]
+    pub fn new(k: usize) -> ExperimentRunner {
+        ExperimentRunner { k: k.max(1), queries: Vec::new(), variants: Vec::new() }
+    }
+
+    /// Add a query (flat embeddings, `query_tokens` tokens) to the query set
+    #[wasm_bindgen]
+    pub fn add_query(&mut self, query_flat: Vec<f32>, query_tokens: usize) {
+        self.queries.push((query_flat, query_tokens));
+    }
+
+    /// Add a variant
+    ///
+    /// * `quantized` - score int8-quantized document embeddings instead of f32
+    /// * `nprobe` - probe this many ANN clusters (needs `set_ann_clusters()`), 0 = exact scan
+    #[wasm_bindgen]
+    pub fn add_variant(&mut self, label: String, aggregation: Aggregation, quantized: bool, nprobe: usize) {
+        self.variants.push(Variant { label, aggregation, quantized, nprobe });
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn num_queries(&self) -> usize {
+        self.queries.len()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn num_variants(&self) -> usize {
+        self.variants.len()
+    }
+
+    /// Run every variant over the query set against the documents loaded in `maxsim`
+    #[wasm_bindgen]
+    pub fn run(&self, maxsim: &MaxSimWasm) -> Result<ExperimentReport, JsValue> {
+        self.run_impl(maxsim).map_err(|e| JsValue::from_str(&e))
+    }
+}
+
+impl ExperimentRunner {
+    fn run_impl(&self, maxsim: &MaxSimWasm) -> Result<ExperimentReport, String> {
+        if self.queries.is_empty() {
+            return Err("No queries. Call add_query() first.".to_string());
+        }
+        let docs_ref = maxsim.documents.borrow();
+        let docs = docs_ref.as_ref()
+            .ok_or_else(|| "No documents loaded. Call load_documents() first.".to_string())?;
+
+        // Reference ranking: exact f32 search with Max aggregation
+        let exact_engine = variant_engine(maxsim, Aggregation::Max);
+        let exact = self.queries
+            .iter()
+            .map(|(query, tokens)| self.exact_hits(&exact_engine, docs, query, *tokens))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let quantized = if self.variants.iter().any(|v| v.quantized) {
+            Some(index::deserialize(&index::serialize(docs, Encoding::Int8))?)
+        } else {
+            None
+        };
+
+        let mut report = ExperimentReport::default();
+        for variant in &self.variants {
+            let engine = variant_engine(maxsim, variant.aggregation);
+            let (variant_docs, encoding) = match &quantized {
+                Some(quantized_docs) if variant.quantized => (quantized_docs, Encoding::Int8),
+                _ => (&**docs, Encoding::F32),
+            };
+            let ann = match variant.nprobe {
+                0 => None,
+                _ => Some(maxsim.ann.as_ref().ok_or_else(|| "No ANN index. Call set_ann_clusters() first.".to_string())?),
+            };
+
+            let start = now_ms();
+            let mut hits = Vec::with_capacity(self.queries.len());
+            for (query, tokens) in &self.queries {
+                hits.push(match ann {
+                    Some(ann) => engine.approx_hits(variant_docs, ann, query, *tokens, variant.nprobe, self.k)?,
+                    None => self.exact_hits(&engine, variant_docs, query, *tokens)?,
+                });
+            }
+            let latency = (now_ms() - start) / self.queries.len() as f64;
+
+            let overlap = hits.iter().zip(&exact).map(|(h, e)| overlap_at_k(h, e)).sum::<f32>() / hits.len() as f32;
+            report.labels.push(variant.label.clone());
+            report.latency_ms.push(latency);
+            report.memory_bytes.push(storage_bytes(variant_docs, encoding) + ann.map_or(0, AnnIndex::memory_bytes));
+            report.overlap.push(overlap);
+        }
+
+        Ok(report)
+    }
+
+    fn exact_hits(&self, engine: &MaxSimWasm, docs: &PreloadedDocuments, query: &[f32], tokens: usize) -> Result<SearchHits, String> {
+        let scores = engine.search_store(docs, query, tokens, &[], false, engine.config.metric(), None)?;
+        let all: Vec<usize> = (0..scores.len()).collect();
+        Ok(SearchHits::top_k(&all, &scores, self.k))
+    }
+}
+
+// Scoring snapshot of `maxsim` with the variant's aggregation
+fn variant_engine(maxsim: &MaxSimWasm, aggregation: Aggregation) -> MaxSimWasm {
+    let mut engine = maxsim.scoring_snapshot();
+    engine.config.set_aggregation(aggregation);
+    engine
+}
+
+// Bytes of the document embeddings stored in `encoding`
+fn storage_bytes(docs: &PreloadedDocuments, encoding: Encoding) -> usize {
+    let tokens: usize = docs.pages().iter().flat_map(|p| &p.doc_tokens).sum();
+    tokens * encoding.token_bytes(docs.embedding_dim)
+}
+
+// Fraction of the reference hits also present in `hits`
+fn overlap_at_k(hits: &SearchHits, reference: &SearchHits) -> f32 {
+    let reference = reference.indices();
+    if reference.is_empty() {
+        return 1.0;
+    }
+    let shared = hits.indices().iter().filter(|idx| reference.contains(idx)).count();
+    shared as f32 / reference.len() as f32
+}
+
+/// Storage precision compared by `quantization_impact`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Precision {
+    F32,
+    F16,
+    Int8,   // The `serialize_index_quantized` encoding (per-token scale)
+    Int4,   // The `load_documents_int4` encoding with the default group size
+    Binary, // Sign bits, scored as ±1/√dim unit vectors
+}
+
+impl Precision {
+    const ALL: [Precision; 5] = [Precision::F32, Precision::F16, Precision::Int8, Precision::Int4, Precision::Binary];
+
+    fn label(self) -> &'static str {
+        match self {
+            Precision::F32 => "f32",
+            Precision::F16 => "f16",
+            Precision::Int8 => "int8",
+            Precision::Int4 => "int4",
+            Precision::Binary => "binary",
+        }
+    }
+
+    fn token_bytes(self, dim: usize) -> usize {
+        match self {
+            Precision::F32 => Encoding::F32.token_bytes(dim),
+            Precision::F16 => dim * 2,
+            Precision::Int8 => Encoding::Int8.token_bytes(dim),
+            Precision::Int4 => int4::code_bytes(dim) + dim.div_ceil(int4_group_size(dim)) * 8,
+            Precision::Binary => dim.div_ceil(8),
+        }
+    }
+
+    // The documents as they score after a round trip through this precision
+    fn round_trip(self, docs: &PreloadedDocuments) -> Result<PreloadedDocuments, String> {
+        let dim = docs.embedding_dim;
+        let sign_value = 1.0 / (dim as f32).sqrt();
+        let quantize: &dyn Fn(f32) -> f32 = match self {
+            Precision::F32 => &|value| value,
+            Precision::F16 => &|value| f16_to_f32(f32_to_f16(value)),
+            Precision::Int8 => return Ok(index::deserialize(&index::serialize(docs, Encoding::Int8))?),
+            Precision::Int4 => return Ok(int4_round_trip(docs)),
+            Precision::Binary => &|value| if value < 0.0 { -sign_value } else { sign_value },
+        };
+
+        let mut out = PreloadedDocuments::new(dim);
+        let mut scratch = Vec::new();
+        for doc in 0..docs.num_docs() {
+            scratch.clear();
+            scratch.extend(docs.document(doc).0.iter().map(|&value| quantize(value)));
+            out.push_document(&scratch);
+        }
+        out.finish();
+        Ok(out)
+    }
+}
+
+// Group size `load_documents_int4` users get by default: the largest of 64, 32, ...
+// dividing `dim`, or 64 with a shorter last group for odd dims (groups must be even)
+pub(crate) fn int4_group_size(dim: usize) -> usize {
+    [64, 32, 16, 8, 4, 2].into_iter().find(|&g| dim.is_multiple_of(g)).unwrap_or(64)
+}
+
+fn int4_round_trip(docs: &PreloadedDocuments) -> PreloadedDocuments {
+    let dim = docs.embedding_dim;
+    let group_size = int4_group_size(dim);
+    let mut out = PreloadedDocuments::new(dim);
+    let (mut codes, mut scales, mut zeros, mut restored) = (Vec::new(), Vec::new(), Vec::new(), Vec::new());
+    for doc in 0..docs.num_docs() {
+        restored.clear();
+        for token in docs.document(doc).0.chunks_exact(dim) {
+            codes.clear();
+            scales.clear();
+            zeros.clear();
+            int4::quantize_token(token, group_size, &mut codes, &mut scales, &mut zeros);
+            int4::dequantize_token(&codes, &scales, &zeros, dim, group_size, &mut restored);
+        }
+        out.push_document(&restored);
+    }
+    out.finish();
+    out
+}
+
+/// Score `queries` (flat, `query_tokens` tokens each) at every precision and compare
+/// each ranking with f32 using the instance metric, aggregation and stopmask
+pub(crate) fn quantization_impact(
+    maxsim: &MaxSimWasm,
+    queries_flat: &[f32],
+    query_tokens: &[usize],
+    k: usize,
+) -> Result<QuantizationReport, String> {
+    if query_tokens.is_empty() {
+        return Err("No sample queries".to_string());
+    }
+    let docs_ref = maxsim.documents.borrow();
+    let docs = docs_ref.as_ref()
+        .ok_or_else(|| "No documents loaded. Call load_documents() first.".to_string())?;
+    let dim = docs.embedding_dim;
+    if query_tokens.iter().sum::<usize>() * dim != queries_flat.len() {
+        return Err("Sample queries size mismatch".to_string());
+    }
+
+    let total_tokens: usize = docs.pages().iter().flat_map(|p| &p.doc_tokens).sum();
+    let all: Vec<usize> = (0..docs.num_docs()).collect();
+    let score_all = |level_docs: &PreloadedDocuments| {
+        let mut offset = 0;
+        query_tokens
+            .iter()
+            .map(|&tokens| {
+                let query = &queries_flat[offset..offset + tokens * dim];
+                offset += tokens * dim;
+                maxsim.search_store(level_docs, query, tokens, &[], false, maxsim.config.metric(), None)
+            })
+            .collect::<Result<Vec<_>, _>>()
+    };
+    let reference = score_all(docs)?;
+    let reference_hits: Vec<SearchHits> = reference.iter().map(|scores| SearchHits::top_k(&all, scores, k)).collect();
+
+    let mut report = QuantizationReport::default();
+    for precision in Precision::ALL {
+        let scores = match precision {
+            Precision::F32 => reference.clone(),
+            _ => score_all(&precision.round_trip(docs)?)?,
+        };
+        let (mut correlation, mut overlap) = (0.0, 0.0);
+        for ((level, exact), exact_hits) in scores.iter().zip(&reference).zip(&reference_hits) {
+            correlation += spearman(level, exact);
+            overlap += overlap_at_k(&SearchHits::top_k(&all, level, k), exact_hits);
+        }
+        report.labels.push(precision.label().to_string());
+        report.memory_bytes.push(total_tokens * precision.token_bytes(dim));
+        report.rank_correlation.push(correlation / scores.len() as f32);
+        report.overlap.push(overlap / scores.len() as f32);
+    }
+    Ok(report)
+}
+
+// Spearman rank correlation (average ranks for ties; 1 when both inputs are constant)
+fn spearman(a: &[f32], b: &[f32]) -> f32 {
+    let (ra, rb) = (ranks(a), ranks(b));
+    let mean = (a.len() as f32 - 1.0) / 2.0;
+    let (mut cov, mut var_a, mut var_b) = (0.0, 0.0, 0.0);
+    for (x, y) in ra.iter().zip(&rb) {
+        cov += (x - mean) * (y - mean);
+        var_a += (x - mean) * (x - mean);
+        var_b += (y - mean) * (y - mean);
+    }
+    match (var_a > 0.0, var_b > 0.0) {
+        (true, true) => cov / (var_a * var_b).sqrt(),
+        (false, false) => 1.0,
+        _ => 0.0,
+    }
+}
+
+fn ranks(values: &[f32]) -> Vec<f32> {
+    let mut order: Vec<usize> = (0..values.len()).collect();
+    order.sort_by(|&a, &b| values[a].total_cmp(&values[b]));
+    let mut ranks = vec![0.0; values.len()];
+    let mut start = 0;
+    while start < order.len() {
+        let end = (start..order.len()).find(|&i| values[order[i]] != values[order[start]]).unwrap_or(order.len());
+        for &idx in &order[start..end] {
+            ranks[idx] = (start + end - 1) as f32 / 2.0;
+        }
+        start = end;
+    }
+    ranks
+}
+
+/// Per-precision results of `quantization_impact_report` (parallel arrays: f32, f16, int8, int4, binary)
+#[wasm_bindgen]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct QuantizationReport {
+    labels: Vec<String>,
+    memory_bytes: Vec<usize>,
+    rank_correlation: Vec<f32>,
+    overlap: Vec<f32>,
+}
+
+#[wasm_bindgen]
+impl QuantizationReport {
+    #[wasm_bindgen(getter)]
+    pub fn labels(&self) -> Vec<String> {
+        self.labels.clone()
+    }
+
+    /// Document storage at each precision in bytes (Uint32Array)
+    #[wasm_bindgen(getter)]
+    pub fn memory_bytes(&self) -> Vec<usize> {
+        self.memory_bytes.clone()
+    }
+
+    /// Mean Spearman correlation of all document scores with f32, in [-1, 1] (Float32Array)
+    #[wasm_bindgen(getter)]
+    pub fn rank_correlation(&self) -> Vec<f32> {
+        self.rank_correlation.clone()
+    }
+
+    /// Mean overlap@k with the f32 top-k, in [0, 1] (Float32Array)
+    #[wasm_bindgen(getter)]
+    pub fn overlap(&self) -> Vec<f32> {
+        self.overlap.clone()
+    }
+
+    /// Plain-text comparison table, one row per precision
+    #[wasm_bindgen]
+    pub fn to_table(&self) -> String {
+        let mut table = String::from("precision\tmemory_bytes\trank_correlation\toverlap\n");
+        for i in 0..self.labels.len() {
+            let _ = writeln!(
+                table,
+                "{}\t{}\t{:.3}\t{:.3}",
+                self.labels[i], self.memory_bytes[i], self.rank_correlation[i], self.overlap[i]
+            );
+        }
+        table
+    }
+}
+
+/// Per-variant results of an `ExperimentRunner` sweep (parallel arrays, variant order)
+#[wasm_bindgen]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ExperimentReport {
+    labels: Vec<String>,
+    latency_ms: Vec<f64>,
+    memory_bytes: Vec<usize>,
+    overlap: Vec<f32>,
+}
+
+#[wasm_bindgen]
+impl ExperimentReport {
+    #[wasm_bindgen(getter)]
+    pub fn labels(&self) -> Vec<String> {
+        self.labels.clone()
+    }
+
+    /// Mean latency per query in milliseconds (Float64Array)
+    #[wasm_bindgen(getter)]
+    pub fn latency_ms(&self) -> Vec<f64> {
+        self.latency_ms.clone()
+    }
+
+    /// Document storage (plus ANN index) in bytes (Uint32Array)
+    #[wasm_bindgen(getter)]
+    pub fn memory_bytes(&self) -> Vec<usize> {
+        self.memory_bytes.clone()
+    }
+
+    /// Mean overlap@k with exact search, in [0, 1] (Float32Array)
+    #[wasm_bindgen(getter)]
+    pub fn overlap(&self) -> Vec<f32> {
+        self.overlap.clone()
+    }
+
+    /// Plain-text comparison table, one row per variant
+    #[wasm_bindgen]
+    pub fn to_table(&self) -> String {
+        let mut table = String::from("variant\tlatency_ms\tmemory_bytes\toverlap\n");
+        for i in 0..self.labels.len() {
+            let _ = writeln!(
+                table,
+                "{}\t{:.3}\t{}\t{:.3}",
+                self.labels[i], self.latency_ms[i], self.memory_bytes[i], self.overlap[i]
+            );
+        }
+        table
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sweep_reports_overlap_and_memory() {
+        let mut maxsim = MaxSimWasm::new();
+        let embeddings = [1.0, 0.0, 0.6, 0.8, 0.0, 1.0, 0.8, 0.6, 0.0, 1.0];
+        maxsim.load_documents(&embeddings, &[2, 1, 1, 1], 2).unwrap();
+        maxsim.set_ann_clusters(2);
+
+        let mut runner = ExperimentRunner::new(2);
+        runner.add_query(vec![1.0, 0.0], 1);
+        runner.add_query(vec![0.0, 1.0], 1);
+        runner.add_variant("exact".to_string(), Aggregation::Max, false, 0);
+        runner.add_variant("int8".to_string(), Aggregation::Max, true, 0);
+        runner.add_variant("ann".to_string(), Aggregation::Max, false, 2);
+
+        let report = runner.run(&maxsim).unwrap();
+        assert_eq!(report.labels(), vec!["exact", "int8", "ann"]);
+        assert_eq!(report.overlap()[0], 1.0);
+        assert_eq!(report.overlap()[2], 1.0); // Probing every cluster is exact
+        assert_eq!(report.memory_bytes()[0], 5 * 2 * 4);
+        assert_eq!(report.memory_bytes()[1], 5 * (4 + 2));
+        assert!(report.to_table().starts_with("variant\t"));
+    }
+
+    #[test]
+    fn test_quantization_impact_compares_with_f32() {
+        let mut maxsim = MaxSimWasm::new();
+        let embeddings = [1.0, 0.0, 0.6, 0.8, 0.0, 1.0, 0.8, 0.6, 0.28, 0.96];
+        maxsim.load_documents(&embeddings, &[2, 1, 1, 1], 2).unwrap();
+
+        let report = quantization_impact(&maxsim, &[0.9, 0.1, 0.3, 0.7], &[1, 1], 2).unwrap();
+        assert_eq!(report.labels(), vec!["f32", "f16", "int8", "int4", "binary"]);
+        assert_eq!(report.memory_bytes(), vec![5 * 8, 5 * 4, 5 * 6, 5 * 9, 5]);
+        assert_eq!(report.rank_correlation()[0], 1.0);
+        assert_eq!(report.overlap()[1], 1.0);
+        // Binary collapses documents 1, 3 and 4 onto (+, +), tying them
+        assert!(report.rank_correlation()[4] < 1.0);
+        let table = report.to_table();
+        assert_eq!(table.lines().count(), 6);
+        assert!(table.starts_with("precision\tmemory_bytes\trank_correlation\toverlap\nf32\t40\t1.000\t1.000\n"));
+
+        assert_eq!(ranks(&[0.5, 0.1, 0.5, 0.9]), vec![1.5, 0.0, 1.5, 3.0]);
+        assert_eq!(f16_to_f32(f32_to_f16(0.1)), 0.099975586);
+        assert_eq!(f16_to_f32(f32_to_f16(1e-7)), 1.1920929e-7);
+    }
+}
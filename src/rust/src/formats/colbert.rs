@@ -0,0 +1,80 @@
+/*!
+ * Index files written by the `colbert-ai` Python package (ColBERTv2 / PLAID)
+ *
+ * An index directory holds the shared codec and one set of files per chunk:
+ * - `centroids.pt`:          codebook tensor (num_centroids × dim, usually f16)
+ * - `buckets.pt`:            tuple (bucket_cutoffs, bucket_weights), only the weights are used
+ * - `{chunk}.codes.pt`:      centroid index of every token (int32)
+ * - `{chunk}.residuals.pt`:  packed residual bucket codes (uint8, dim · nbits / 8 per token)
+ * - `doclens.{chunk}.json`:  token count of every document of the chunk
+ */
+
+use super::json::Json;
+use super::torch::TorchArchive;
+
+/// Codebook from `centroids.pt` (flat f32)
+pub(crate) fn read_centroids(bytes: &[u8]) -> Result<Vec<f32>, String> {
+    let archive = TorchArchive::parse(bytes)?;
+    if archive.num_storages() != 1 {
+        return Err("centroids.pt should hold a single tensor".to_string());
+    }
+    archive.to_f32(0)
+}
+
+/// Bucket weights and nbits from `buckets.pt`
+pub(crate) fn read_bucket_weights(bytes: &[u8]) -> Result<(Vec<f32>, usize), String> {
+    let archive = TorchArchive::parse(bytes)?;
+    // The weights (2^nbits values) are the longer of the two tensors (cutoffs: 2^nbits - 1)
+    let weights = (0..archive.num_storages())
+        .max_by_key(|&key| archive.len(key))
+        .ok_or("buckets.pt holds no tensors")?;
+    let len = archive.len(weights);
+    if len < 2 || !len.is_power_of_two() {
+        return Err(format!("Expected 2^nbits bucket weights, found {}", len));
+    }
+    Ok((archive.to_f32(weights)?, len.trailing_zeros() as usize))
+}
+
+/// Centroid codes from `{chunk}.codes.pt`
+pub(crate) fn read_codes(bytes: &[u8]) -> Result<Vec<u32>, String> {
+    let archive = TorchArchive::parse(bytes)?;
+    if archive.num_storages() != 1 {
+        return Err("codes.pt should hold a single tensor".to_string());
+    }
+    archive.to_u32(0)
+}
+
+/// Packed residuals from `{chunk}.residuals.pt` (borrowed from the archive bytes)
+pub(crate) fn read_residuals(bytes: &[u8]) -> Result<&[u8], String> {
+    let archive = TorchArchive::parse(bytes)?;
+    if archive.num_storages() != 1 {
+        return Err("residuals.pt should hold a single tensor".to_string());
+    }
+    archive.bytes(0)
+}
+
+/// Document lengths from `doclens.{chunk}.json` (a flat JSON array of integers)
+pub(crate) fn read_doclens(json: &str) -> Result<Vec<usize>, String> {
+    Json::parse(json)?
+        .as_usize_array()
+        .ok_or_else(|| "doclens should be a JSON array of document lengths".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formats::torch::write_archive;
+
+    #[test]
+    fn test_reads_bucket_weights_and_doclens() {
+        let floats = |values: &[f32]| values.iter().flat_map(|v| v.to_le_bytes()).collect::<Vec<u8>>();
+        let buckets = write_archive("FloatStorage", &[floats(&[-0.1, 0.0, 0.1]), floats(&[-0.2, -0.05, 0.05, 0.2])]);
+        let (weights, nbits) = read_bucket_weights(&buckets).unwrap();
+        assert_eq!(weights, vec![-0.2, -0.05, 0.05, 0.2]);
+        assert_eq!(nbits, 2);
+
+        assert_eq!(read_doclens(" [3, 1,2]\n").unwrap(), vec![3, 1, 2]);
+        assert_eq!(read_doclens("[]").unwrap(), Vec::<usize>::new());
+        assert!(read_doclens("{\"a\": 1}").is_err());
+    }
+}
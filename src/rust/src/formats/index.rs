@@ -0,0 +1,214 @@
+/*!
+ * Serialized index format
+ *
+ * A compact, versioned binary blob holding a preloaded corpus, so an index built once
+ * can be cached (IndexedDB, Cache API) or served as a static file and restored with a
+ * single `load_index` call instead of re-uploading raw Float32Arrays.
+ *
+ * The encoding itself (header, layout, int8 quantization) lives in
+ * `maxsim_core::index`, so offline builders produce byte-identical blobs; this module
+ * maps it onto paged storage.
+ *
+ * Failures are `IndexError`s; at the JS boundary they become `Error` objects whose
+ * `name` is the variant (`UnsupportedVersion`, `CorruptHeader`, `DimMismatch`, ...),
+ * so a client can tell an index written by a newer release from a damaged one. The
+ * other blobs the crate writes (index deltas, engine state snapshots, HNSW graphs,
+ * shard manifests) carry their own magic and version and fail with the same
+ * variants, worded for the artifact by `artifact_js_error()`.
+ *
+ * With the `zstd` feature, a blob compressed as a whole with zstd is decompressed in
+ * WASM before parsing (pure-Rust decoder, no JS round trip), wherever an index is
+ * read: `load_index`, IndexedDB shards and snapshots.
+ */
+
+use std::borrow::Cow;
+
+use wasm_bindgen::JsValue;
+
+use maxsim_core::index::{decode_token, encode_token, Header};
+pub(crate) use maxsim_core::index::{Encoding, IndexError};
+
+use crate::store::{DocPage, PreloadedDocuments};
+
+/// Serialize a preloaded corpus
+pub(crate) fn serialize(docs: &PreloadedDocuments, encoding: Encoding) -> Vec<u8> {
+    serialize_pages(docs.pages(), docs.embedding_dim, encoding)
+}
+
+/// Serialize a run of pages as a standalone index (e.g. one shard of a larger corpus)
+pub(crate) fn serialize_pages(pages: &[DocPage], dim: usize, encoding: Encoding) -> Vec<u8> {
+    let header = Header {
+        encoding,
+        embedding_dim: dim,
+        doc_tokens: pages.iter().flat_map(|p| p.doc_tokens.iter().copied()).collect(),
+    };
+    let total_tokens: usize = header.doc_tokens.iter().sum();
+
+    let mut out = Vec::with_capacity(header.embeddings_start() + total_tokens * encoding.token_bytes(dim) + header.checksum_blocks() * 4);
+    header.write(&mut out);
+    for page in pages {
+        for token in page.embeddings.chunks_exact(dim) {
+            encode_token(token, encoding, &mut out);
+        }
+    }
+    header.write_checksums(&mut out);
+    out
+}
+
+/// Parse a serialized corpus back into paged storage
+pub(crate) fn deserialize(bytes: &[u8]) -> Result<PreloadedDocuments, IndexError> {
+    read_index(bytes, None).map(|(docs, _)| docs)
+}
+
+/// `deserialize()` with the index's fingerprint (see `Header::fingerprint`), failing
+/// with `DimMismatch` unless the index has `embedding_dim` dimensions (checked on the
+/// header, before decoding any token)
+pub(crate) fn read_index(bytes: &[u8], embedding_dim: Option<usize>) -> Result<(PreloadedDocuments, u32), IndexError> {
+    let bytes = decompress(bytes)?;
+    let header = Header::parse(&bytes)?;
+    if let Some(dim) = embedding_dim {
+        header.expect_dim(dim)?;
+    }
+    header.verify_checksums(&bytes)?;
+    let token_bytes = header.encoding.token_bytes(header.embedding_dim);
+    let mut docs = PreloadedDocuments::new(header.embedding_dim);
+    let mut doc_buffer = Vec::new();
+    let mut offset = header.embeddings_start();
+    for &tokens in &header.doc_tokens {
+        doc_buffer.clear();
+        for token in bytes[offset..offset + tokens * token_bytes].chunks_exact(token_bytes) {
+            decode_token(token, header.encoding, &mut doc_buffer);
+        }
+        docs.push_document(&doc_buffer);
+        offset += tokens * token_bytes;
+    }
+    docs.finish();
+
+    Ok((docs, header.fingerprint(&bytes)))
+}
+
+/// The blob with its zstd compression removed, if it has any
+#[cfg(feature = "zstd")]
+fn decompress(bytes: &[u8]) -> Result<Cow<'_, [u8]>, IndexError> {
+    use std::io::Read;
+
+    if !bytes.starts_with(maxsim_core::index::ZSTD_MAGIC) {
+        return Ok(Cow::Borrowed(bytes));
+    }
+    let corrupt = |e: &dyn std::fmt::Display| IndexError::CorruptCompression(e.to_string());
+    let mut input = bytes;
+    let mut decoder = ruzstd::decoding::StreamingDecoder::new(&mut input).map_err(|e| corrupt(&e))?;
+    let mut out = Vec::new();
+    decoder.read_to_end(&mut out).map_err(|e| corrupt(&e))?;
+    Ok(Cow::Owned(out))
+}
+
+/// The blob unchanged (compressed blobs fail to parse with `IndexError::Compressed`)
+#[cfg(not(feature = "zstd"))]
+fn decompress(bytes: &[u8]) -> Result<Cow<'_, [u8]>, IndexError> {
+    Ok(Cow::Borrowed(bytes))
+}
+
+/// JS `Error` named after the error's variant
+pub(crate) fn js_error(error: IndexError) -> JsValue {
+    named_error(&error, &error.to_string())
+}
+
+/// `js_error()` for another serialized artifact (`artifact` names it in the message,
+/// `version` is the one this build reads); the `name` is still the variant
+pub(crate) fn artifact_js_error(artifact: &str, version: u32, error: IndexError) -> JsValue {
+    let message = match &error {
+        IndexError::NotAnIndex => format!("Not a MaxSim {} (bad magic)", artifact),
+        IndexError::UnsupportedVersion { found } => {
+            format!("Unsupported {} version {} (this build reads {})", artifact, found, version)
+        }
+        IndexError::CorruptHeader(reason) => format!("Corrupt {}: {}", artifact, reason),
+        IndexError::ChecksumMismatch { .. } => format!("Corrupt {} (checksum mismatch)", artifact),
+        other => other.to_string(),
+    };
+    named_error(&error, &message)
+}
+
+/// `js_error()` for one shard of a sharded corpus, numbered in the message
+#[cfg(feature = "idb")]
+pub(crate) fn shard_js_error(shard: usize, error: IndexError) -> JsValue {
+    named_error(&error, &format!("Shard {}: {}", shard, error))
+}
+
+fn named_error(error: &IndexError, message: &str) -> JsValue {
+    let js = js_sys::Error::new(message);
+    js.set_name(error.code());
+    js.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use maxsim_core::index::HEADER_LEN;
+
+    fn sample_docs() -> PreloadedDocuments {
+        let flat = vec![0.6, 0.8, 1.0, 0.0, -0.5, 0.25];
+        PreloadedDocuments::from_flat(&flat, &[2, 1], 2)
+    }
+
+    #[test]
+    fn test_f32_round_trip_is_exact() {
+        let docs = sample_docs();
+        let restored = deserialize(&serialize(&docs, Encoding::F32)).unwrap();
+        assert_eq!(restored.num_docs(), 2);
+        assert_eq!(restored.pages()[0].embeddings, docs.pages()[0].embeddings);
+        assert_eq!(restored.pages()[0].doc_tokens, vec![2, 1]);
+    }
+
+    #[test]
+    fn test_int8_round_trip_is_close() {
+        let docs = sample_docs();
+        let bytes = serialize(&docs, Encoding::Int8);
+        assert_eq!(bytes.len(), HEADER_LEN + 2 * 4 + 3 * (4 + 2) + 2 * 4);
+
+        let restored = deserialize(&bytes).unwrap();
+        for (a, b) in restored.pages()[0].embeddings.iter().zip(&docs.pages()[0].embeddings) {
+            assert!((a - b).abs() < 0.01);
+        }
+    }
+
+    #[test]
+    fn test_rejects_truncated_and_foreign_blobs() {
+        let bytes = serialize(&sample_docs(), Encoding::F32);
+        assert!(deserialize(&bytes[..bytes.len() - 1]).is_err());
+        assert!(deserialize(b"NOPE").is_err());
+
+        let mut wrong_version = bytes.clone();
+        wrong_version[4] = 99;
+        assert_eq!(deserialize(&wrong_version).err(), Some(IndexError::UnsupportedVersion { found: 99 }));
+        assert_eq!(read_index(&bytes, Some(2)).unwrap().0.num_docs(), 2);
+        assert_eq!(read_index(&bytes, Some(4)).err(), Some(IndexError::DimMismatch { expected: 4, found: 2 }));
+
+        let mut damaged = bytes.clone();
+        damaged[HEADER_LEN + 2 * 4] ^= 0x40;
+        assert_eq!(deserialize(&damaged).err(), Some(IndexError::ChecksumMismatch { block: 1, blocks: 2 }));
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_zstd_blobs_decompress_on_load() {
+        use ruzstd::encoding::{compress_to_vec, CompressionLevel};
+
+        let bytes = serialize(&sample_docs(), Encoding::Int8);
+        let compressed = compress_to_vec(&bytes[..], CompressionLevel::Fastest);
+        let restored = deserialize(&compressed).unwrap();
+        assert_eq!(restored.pages()[0].embeddings, deserialize(&bytes).unwrap().pages()[0].embeddings);
+        assert_eq!(deserialize(&compressed[..compressed.len() - 2]).err().map(|e| e.code()), Some("CorruptCompression"));
+    }
+
+    #[test]
+    fn test_pages_encode_like_the_core_blob() {
+        // Offline builders (maxsim-py) serialize flat arrays; the blobs must be identical
+        let flat: Vec<f32> = (0..40).map(|i| ((i * 13 % 17) as f32 - 8.0) / 3.0).collect();
+        let doc_tokens = [3, 5, 2, 10];
+        let docs = PreloadedDocuments::from_flat(&flat, &doc_tokens, 2);
+        for encoding in [Encoding::F32, Encoding::Int8] {
+            assert_eq!(serialize(&docs, encoding), maxsim_core::index::serialize(&flat, &doc_tokens, 2, encoding));
+        }
+    }
+}
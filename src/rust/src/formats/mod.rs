@@ -0,0 +1,126 @@
+/*!
+ * Binary formats read and written by the crate
+ */
+
+pub(crate) mod colbert;
+pub(crate) mod index;
+pub(crate) mod json;
+pub(crate) mod numpy;
+pub(crate) mod safetensors;
+pub(crate) mod torch;
+pub(crate) mod zip;
+
+use crate::store::PreloadedDocuments;
+
+// Little-endian readers (callers check bounds first)
+
+pub(crate) fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(bytes[offset..offset + 2].try_into().unwrap())
+}
+
+pub(crate) fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+pub(crate) fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
+
+/// IEEE 754 half precision to f32 (exact)
+pub(crate) fn f16_to_f32(bits: u16) -> f32 {
+    let sign = (bits as u32 & 0x8000) << 16;
+    let exponent = (bits >> 10) as u32 & 0x1f;
+    let mantissa = bits as u32 & 0x3ff;
+    match exponent {
+        // Zero and subnormals: mantissa · 2^-24
+        0 => {
+            let magnitude = mantissa as f32 / (1u32 << 24) as f32;
+            if sign != 0 { -magnitude } else { magnitude }
+        }
+        // Infinity and NaN
+        0x1f => f32::from_bits(sign | 0x7f80_0000 | (mantissa << 13)),
+        _ => f32::from_bits(sign | ((exponent + 112) << 23) | (mantissa << 13)),
+    }
+}
+
+/// f32 to IEEE 754 half precision (round to nearest even, overflow to infinity)
+pub(crate) fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+    if exponent == 0xff {
+        return sign | 0x7c00 | if mantissa != 0 { 0x200 } else { 0 };
+    }
+
+    // Round away the low `shift` bits of `mantissa`, ties to even
+    let round = |mantissa: u32, shift: u32| {
+        let (kept, rest, half) = (mantissa >> shift, mantissa & ((1 << shift) - 1), 1 << (shift - 1));
+        kept + (rest > half || (rest == half && kept & 1 == 1)) as u32
+    };
+    let half_exponent = exponent - 127 + 15;
+    match half_exponent {
+        0x1f.. => sign | 0x7c00,
+        // Subnormal: mantissa (with its implicit bit) · 2^-24
+        -10..=0 => sign | round(mantissa | 0x80_0000, (14 - half_exponent) as u32) as u16,
+        ..=-11 => sign,
+        // A mantissa carry correctly bumps the exponent (up to infinity)
+        _ => sign | round(((half_exponent as u32) << 23) | mantissa, 13) as u16,
+    }
+}
+
+/// bfloat16 to f32 (exact: the upper half of an f32)
+pub(crate) fn bf16_to_f32(bits: u16) -> f32 {
+    f32::from_bits((bits as u32) << 16)
+}
+
+/// Copy a row-major embeddings tensor into paged storage, one document at a time
+///
+/// The tensor is either flat, `[total_tokens, dim]` with documents back to back, or
+/// padded, `[num_docs, max_tokens, dim]`, keeping the first `doc_tokens[i]` rows of
+/// each document. `decode` appends raw elements (`element_bytes` each) as f32.
+pub(crate) fn documents_from_tensor(
+    data: &[u8],
+    shape: &[usize],
+    element_bytes: usize,
+    decode: impl Fn(&[u8], &mut Vec<f32>),
+    doc_tokens: &[usize],
+) -> Result<PreloadedDocuments, String> {
+    if Some(data.len()) != shape.iter().try_fold(element_bytes, |n, &d| n.checked_mul(d)) {
+        return Err("Tensor data size does not match its shape".to_string());
+    }
+
+    // Token stride between documents: max_tokens rows when padded, else back to back
+    let (dim, padded_tokens) = match shape[..] {
+        [total_tokens, dim] => {
+            if doc_tokens.iter().sum::<usize>() != total_tokens {
+                return Err(format!("doc_tokens sum to {} but the tensor has {} tokens",
+                    doc_tokens.iter().sum::<usize>(), total_tokens));
+            }
+            (dim, None)
+        }
+        [num_docs, max_tokens, dim] => {
+            if num_docs != doc_tokens.len() || doc_tokens.iter().any(|&t| t > max_tokens) {
+                return Err("doc_tokens do not fit the padded [num_docs, max_tokens, dim] tensor".to_string());
+            }
+            (dim, Some(max_tokens))
+        }
+        _ => return Err(format!("Expected a 2-D or 3-D tensor, found shape {:?}", shape)),
+    };
+    if dim == 0 {
+        return Err("Embedding dimension must be > 0".to_string());
+    }
+
+    let row_bytes = dim * element_bytes;
+    let mut docs = PreloadedDocuments::new(dim);
+    let mut scratch = Vec::new();
+    let mut row = 0;
+    for &tokens in doc_tokens {
+        scratch.clear();
+        decode(&data[row * row_bytes..(row + tokens) * row_bytes], &mut scratch);
+        docs.push_document(&scratch);
+        row += padded_tokens.unwrap_or(tokens);
+    }
+    docs.finish();
+    Ok(docs)
+}
@@ -0,0 +1,240 @@
+/*!
+ * NumPy `.npy` arrays and `.npz` archives
+ *
+ * `.npy`: the magic `\x93NUMPY`, a version byte pair, the header length (u16 for
+ * version 1, u32 for versions 2 and 3), then a Python dict literal such as
+ * `{'descr': '<f4', 'fortran_order': False, 'shape': (1200, 128), }` and the raw
+ * array data. `.npz` (`numpy.savez`) is a stored ZIP archive with one `<name>.npy`
+ * entry per array; `numpy.savez_compressed` archives are not supported.
+ *
+ * Embeddings are f16, f32 or f64 arrays, flat `[total_tokens, dim]` or padded
+ * `[num_docs, max_tokens, dim]` (see `documents_from_tensor`); document lengths are
+ * 1-D integer arrays.
+ */
+
+use super::{documents_from_tensor, f16_to_f32, read_u16, read_u32, zip};
+use crate::store::PreloadedDocuments;
+
+const MAGIC: &[u8] = b"\x93NUMPY";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Dtype {
+    F16,
+    F32,
+    F64,
+    Int { bytes: usize, signed: bool },
+}
+
+impl Dtype {
+    // Array-protocol type string, e.g. `<f4`; only little-endian data is accepted
+    fn parse(descr: &str) -> Result<Self, String> {
+        let unsupported = || format!("Unsupported .npy dtype {:?}", descr);
+        let (order, code) = descr.split_at_checked(1).ok_or_else(unsupported)?;
+        if order == ">" {
+            return Err(format!("Big-endian .npy data ({}) is not supported", descr));
+        }
+        if !matches!(order, "<" | "|" | "=") {
+            return Err(unsupported());
+        }
+        match code {
+            "f2" => Ok(Dtype::F16),
+            "f4" => Ok(Dtype::F32),
+            "f8" => Ok(Dtype::F64),
+            "i1" | "i2" | "i4" | "i8" => Ok(Dtype::Int { bytes: code[1..].parse().unwrap(), signed: true }),
+            "u1" | "u2" | "u4" | "u8" => Ok(Dtype::Int { bytes: code[1..].parse().unwrap(), signed: false }),
+            _ => Err(unsupported()),
+        }
+    }
+
+    fn element_bytes(self) -> usize {
+        match self {
+            Dtype::F16 => 2,
+            Dtype::F32 => 4,
+            Dtype::F64 => 8,
+            Dtype::Int { bytes, .. } => bytes,
+        }
+    }
+}
+
+/// One array, borrowed from its `.npy` bytes
+struct Array<'a> {
+    dtype: Dtype,
+    shape: Vec<usize>,
+    data: &'a [u8], // Exactly the elements of `shape`
+}
+
+impl<'a> Array<'a> {
+    fn parse(bytes: &'a [u8]) -> Result<Self, String> {
+        if bytes.len() < 10 || !bytes.starts_with(MAGIC) {
+            return Err("Not a .npy array (bad magic)".to_string());
+        }
+        let (header_start, header_len) = match bytes[6] {
+            1 => (10, read_u16(bytes, 8) as usize),
+            2 | 3 if bytes.len() >= 12 => (12, read_u32(bytes, 8) as usize),
+            version => return Err(format!("Unsupported .npy version {}", version)),
+        };
+        let header_end = header_start + header_len;
+        let header = bytes.get(header_start..header_end).ok_or("Truncated .npy header")?;
+        let header = std::str::from_utf8(header).map_err(|_| ".npy header is not UTF-8")?;
+
+        let descr = header_field(header, "descr")
+            .and_then(|value| {
+                let quote = value.chars().next().filter(|&c| c == '\'' || c == '"')?;
+                value[1..].split(quote).next()
+            })
+            .ok_or(".npy header has no descr")?;
+        let dtype = Dtype::parse(descr)?;
+        match header_field(header, "fortran_order") {
+            Some(value) if value.starts_with("False") => {}
+            Some(value) if value.starts_with("True") => {
+                return Err("Fortran-ordered .npy arrays are not supported".to_string())
+            }
+            _ => return Err(".npy header has no fortran_order".to_string()),
+        }
+        let shape = header_field(header, "shape")
+            .and_then(|value| value.strip_prefix('(')?.split(')').next())
+            .and_then(|dims| {
+                dims.split(',')
+                    .map(str::trim)
+                    .filter(|d| !d.is_empty())
+                    .map(|d| d.parse().ok())
+                    .collect::<Option<Vec<usize>>>()
+            })
+            .ok_or(".npy header has no valid shape")?;
+
+        let data_len = shape
+            .iter()
+            .try_fold(dtype.element_bytes(), |n, &d| n.checked_mul(d))
+            .ok_or(".npy shape is too large")?;
+        let data = header_end
+            .checked_add(data_len)
+            .and_then(|end| bytes.get(header_end..end))
+            .ok_or("Truncated .npy data")?;
+        Ok(Array { dtype, shape, data })
+    }
+
+    fn documents(&self, doc_tokens: &[usize]) -> Result<PreloadedDocuments, String> {
+        let element_bytes = self.dtype.element_bytes();
+        match self.dtype {
+            Dtype::F16 => documents_from_tensor(self.data, &self.shape, element_bytes, |raw, out| {
+                out.extend(raw.chunks_exact(2).map(|b| f16_to_f32(u16::from_le_bytes([b[0], b[1]]))))
+            }, doc_tokens),
+            Dtype::F32 => documents_from_tensor(self.data, &self.shape, element_bytes, |raw, out| {
+                out.extend(raw.chunks_exact(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])))
+            }, doc_tokens),
+            Dtype::F64 => documents_from_tensor(self.data, &self.shape, element_bytes, |raw, out| {
+                out.extend(raw.chunks_exact(8).map(|b| f64::from_le_bytes(b.try_into().unwrap()) as f32))
+            }, doc_tokens),
+            Dtype::Int { .. } => Err("Expected floating-point embeddings, found an integer array".to_string()),
+        }
+    }
+
+    /// 1-D integer array as usize (negative values are rejected)
+    fn to_usize(&self) -> Result<Vec<usize>, String> {
+        let Dtype::Int { bytes, signed } = self.dtype else {
+            return Err("Expected an integer array of document lengths".to_string());
+        };
+        if self.shape.len() != 1 {
+            return Err(format!("Expected a 1-D array of document lengths, found shape {:?}", self.shape));
+        }
+        self.data
+            .chunks_exact(bytes)
+            .map(|b| {
+                // Sign-extend into 8 little-endian bytes
+                let fill = if signed && b[bytes - 1] & 0x80 != 0 { 0xff } else { 0 };
+                let mut wide = [fill; 8];
+                wide[..bytes].copy_from_slice(b);
+                let value = i64::from_le_bytes(wide);
+                usize::try_from(value).map_err(|_| format!("Invalid document length {}", value))
+            })
+            .collect()
+    }
+}
+
+// Text following `'key':` in a header dict literal
+fn header_field<'h>(header: &'h str, key: &str) -> Option<&'h str> {
+    let start = header.find(&format!("'{}'", key))? + key.len() + 2;
+    Some(header[start..].trim_start().strip_prefix(':')?.trim_start())
+}
+
+/// Read a `.npy` embeddings array as documents of `doc_tokens` tokens
+pub(crate) fn read_documents(bytes: &[u8], doc_tokens: &[usize]) -> Result<PreloadedDocuments, String> {
+    Array::parse(bytes)?.documents(doc_tokens)
+}
+
+/// Read a 1-D `.npy` integer array of document lengths (for the CLI)
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn read_lengths(bytes: &[u8]) -> Result<Vec<usize>, String> {
+    Array::parse(bytes)?.to_usize()
+}
+
+/// Read arrays `embeddings` and `doclens` (names without `.npy`) of a `.npz` archive
+pub(crate) fn read_npz_documents(bytes: &[u8], embeddings: &str, doclens: &str) -> Result<PreloadedDocuments, String> {
+    let entries = zip::entries(bytes)?;
+    let array = |name: &str| {
+        let file = format!("{}.npy", name);
+        entries
+            .iter()
+            .find(|&&(entry, _)| entry == file)
+            .ok_or_else(|| format!("Array {:?} not found in .npz archive", name))
+            .and_then(|&(_, data)| Array::parse(data))
+    };
+
+    let doc_tokens = array(doclens)?.to_usize()?;
+    if doc_tokens.is_empty() {
+        return Err("No documents to load".to_string());
+    }
+    array(embeddings)?.documents(&doc_tokens)
+}
+
+/// Serialize an array as `.npy` (version 1, header padded to 64 bytes)
+#[cfg(test)]
+pub(crate) fn write_npy(descr: &str, shape: &[usize], data: &[u8]) -> Vec<u8> {
+    let dims: String = shape.iter().map(|d| format!("{}, ", d)).collect();
+    let mut header = format!("{{'descr': '{}', 'fortran_order': False, 'shape': ({}), }}", descr, dims.trim_end());
+    while !(10 + header.len() + 1).is_multiple_of(64) {
+        header.push(' ');
+    }
+    header.push('\n');
+
+    let mut out = MAGIC.to_vec();
+    out.extend_from_slice(&[1, 0]);
+    out.extend_from_slice(&(header.len() as u16).to_le_bytes());
+    out.extend_from_slice(header.as_bytes());
+    out.extend_from_slice(data);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reads_npy_and_npz_documents() {
+        let floats: Vec<u8> = [1.0f32, 0.5, -2.0, 1.0, 0.0, 0.25].iter().flat_map(|v| v.to_le_bytes()).collect();
+        let npy = write_npy("<f4", &[3, 2], &floats);
+        let docs = read_documents(&npy, &[2, 1]).unwrap();
+        assert_eq!(docs.num_docs(), 2);
+        assert_eq!(docs.document(0).0, &[1.0, 0.5, -2.0, 1.0]);
+        assert_eq!(docs.document(1).0, &[0.0, 0.25]);
+        assert!(read_documents(&npy, &[2, 2]).is_err());
+
+        // Padded [2, 2, 2] f16 embeddings with int64 doclens, as written by numpy.savez
+        let halves: Vec<u8> = [0x3c00u16, 0, 0, 0, 0xc000, 0x3800, 0, 0]
+            .iter()
+            .flat_map(|h| h.to_le_bytes())
+            .collect();
+        let doclens: Vec<u8> = [1i64, 1].iter().flat_map(|v| v.to_le_bytes()).collect();
+        let npz = zip::write_stored(&[
+            ("embeddings.npy".to_string(), write_npy("<f2", &[2, 2, 2], &halves)),
+            ("doclens.npy".to_string(), write_npy("<i8", &[2], &doclens)),
+        ]);
+        let docs = read_npz_documents(&npz, "embeddings", "doclens").unwrap();
+        assert_eq!(docs.document(0).0, &[1.0, 0.0]);
+        assert_eq!(docs.document(1).0, &[-2.0, 0.5]);
+        assert!(read_npz_documents(&npz, "embeddings", "missing").is_err());
+
+        assert!(read_documents(&write_npy(">f4", &[3, 2], &floats), &[2, 1]).is_err());
+        assert!(read_documents(&npy[..npy.len() - 1], &[2, 1]).is_err());
+    }
+}
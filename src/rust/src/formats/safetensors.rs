@@ -0,0 +1,105 @@
+/*!
+ * safetensors embedding files
+ *
+ * Layout: an 8-byte little-endian header length N, an N-byte JSON header mapping
+ * tensor names to `{dtype, shape, data_offsets}` (offsets relative to the end of
+ * the header), then the raw little-endian tensor data. Document embeddings are read
+ * straight from the buffer into paged storage from a flat `[total_tokens, dim]` or
+ * padded `[num_docs, max_tokens, dim]` tensor (see `documents_from_tensor`).
+ */
+
+use super::json::Json;
+use super::{bf16_to_f32, documents_from_tensor, f16_to_f32, read_u64};
+use crate::store::PreloadedDocuments;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Dtype {
+    F32,
+    F16,
+    BF16,
+}
+
+impl Dtype {
+    fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "F32" => Ok(Dtype::F32),
+            "F16" => Ok(Dtype::F16),
+            "BF16" => Ok(Dtype::BF16),
+            other => Err(format!("Unsupported safetensors dtype {} (expected F32, F16 or BF16)", other)),
+        }
+    }
+
+    fn element_bytes(self) -> usize {
+        match self {
+            Dtype::F32 => 4,
+            Dtype::F16 | Dtype::BF16 => 2,
+        }
+    }
+
+    // Append little-endian elements as f32
+    fn decode_into(self, data: &[u8], out: &mut Vec<f32>) {
+        match self {
+            Dtype::F32 => out.extend(data.chunks_exact(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))),
+            Dtype::F16 => out.extend(data.chunks_exact(2).map(|b| f16_to_f32(u16::from_le_bytes([b[0], b[1]])))),
+            Dtype::BF16 => out.extend(data.chunks_exact(2).map(|b| bf16_to_f32(u16::from_le_bytes([b[0], b[1]])))),
+        }
+    }
+}
+
+/// Read tensor `name` of a safetensors buffer as documents of `doc_tokens` tokens
+pub(crate) fn read_documents(bytes: &[u8], name: &str, doc_tokens: &[usize]) -> Result<PreloadedDocuments, String> {
+    if bytes.len() < 8 {
+        return Err("safetensors buffer too short for header".to_string());
+    }
+    let header_len = read_u64(bytes, 0) as usize;
+    let header = bytes
+        .get(8..8usize.saturating_add(header_len))
+        .ok_or("safetensors header length exceeds the buffer")?;
+    let header = Json::parse(std::str::from_utf8(header).map_err(|_| "safetensors header is not UTF-8")?)?;
+
+    let tensor = header.get(name).ok_or_else(|| format!("Tensor {:?} not found", name))?;
+    let dtype = Dtype::parse(tensor.get("dtype").and_then(Json::as_str).ok_or("Tensor has no dtype")?)?;
+    let shape = tensor.get("shape").and_then(Json::as_usize_array).ok_or("Tensor has no valid shape")?;
+    let offsets = tensor.get("data_offsets").and_then(Json::as_usize_array).ok_or("Tensor has no data_offsets")?;
+    let base = 8 + header_len;
+    let data = match offsets[..] {
+        [begin, end] if begin <= end => base.checked_add(end).and_then(|end| bytes.get(base + begin..end)),
+        _ => None,
+    }
+    .ok_or("Tensor data_offsets are out of bounds")?;
+    documents_from_tensor(data, &shape, dtype.element_bytes(), |raw, out| dtype.decode_into(raw, out), doc_tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn safetensors(header: &str, data: &[u8]) -> Vec<u8> {
+        let mut out = (header.len() as u64).to_le_bytes().to_vec();
+        out.extend_from_slice(header.as_bytes());
+        out.extend_from_slice(data);
+        out
+    }
+
+    #[test]
+    fn test_reads_flat_and_padded_tensors() {
+        // f16: 1.0 = 0x3c00, 0.5 = 0x3800, -2.0 = 0xc000, 0 = padding
+        let halves: Vec<u8> = [0x3c00u16, 0x3800, 0xc000, 0x3c00, 0, 0]
+            .iter()
+            .flat_map(|h| h.to_le_bytes())
+            .collect();
+
+        let flat = safetensors(r#"{"emb":{"dtype":"F16","shape":[3,2],"data_offsets":[0,12]}}"#, &halves);
+        let docs = read_documents(&flat, "emb", &[2, 1]).unwrap();
+        assert_eq!(docs.num_docs(), 2);
+        assert_eq!(docs.document(0).0, &[1.0, 0.5, -2.0, 1.0]);
+        assert_eq!(docs.document(1).0, &[0.0, 0.0]);
+
+        let padded = safetensors(r#"{"emb":{"dtype":"F16","shape":[2,1,3],"data_offsets":[0,12]}}"#, &halves);
+        let docs = read_documents(&padded, "emb", &[1, 1]).unwrap();
+        assert_eq!(docs.document(1).0, &[1.0, 0.0, 0.0]);
+
+        assert!(read_documents(&flat, "emb", &[2, 2]).is_err());
+        assert!(read_documents(&flat, "missing", &[2, 1]).is_err());
+    }
+}
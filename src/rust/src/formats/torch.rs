@@ -0,0 +1,167 @@
+/*!
+ * Minimal reader for PyTorch `torch.save` archives
+ *
+ * A `.pt` file is an uncompressed ZIP archive holding the pickled object
+ * (`<name>/data.pkl`) and one raw little-endian entry per tensor storage
+ * (`<name>/data/0`, `<name>/data/1`, ...). Only what the importers need is decoded:
+ * the storages, in key order, and their element type, read from the storage class
+ * named in the pickle (one type per archive). Tensors are assumed to span their
+ * whole storage, which holds for tensors saved by the ColBERT toolchain.
+ */
+
+use super::{bf16_to_f32, f16_to_f32, zip};
+
+/// Element type of the storages of an archive
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum StorageType {
+    F16,
+    BF16,
+    F32,
+    I32,
+    I64,
+    U8,
+}
+
+impl StorageType {
+    // Class names as they appear in the pickle (`torch.HalfStorage`, ...)
+    const CLASSES: [(&'static [u8], StorageType); 6] = [
+        (b"HalfStorage", StorageType::F16),
+        (b"BFloat16Storage", StorageType::BF16),
+        (b"FloatStorage", StorageType::F32),
+        (b"IntStorage", StorageType::I32),
+        (b"LongStorage", StorageType::I64),
+        (b"ByteStorage", StorageType::U8),
+    ];
+
+    fn element_bytes(self) -> usize {
+        match self {
+            StorageType::F16 | StorageType::BF16 => 2,
+            StorageType::F32 | StorageType::I32 => 4,
+            StorageType::I64 => 8,
+            StorageType::U8 => 1,
+        }
+    }
+}
+
+/// Tensor storages of one `torch.save` archive, borrowed from its bytes
+pub(crate) struct TorchArchive<'a> {
+    pub(crate) storage_type: StorageType,
+    storages: Vec<&'a [u8]>, // Indexed by storage key
+}
+
+impl<'a> TorchArchive<'a> {
+    pub(crate) fn parse(bytes: &'a [u8]) -> Result<Self, String> {
+        let entries = zip::entries(bytes)?;
+
+        let pickle = entries
+            .iter()
+            .find(|(name, _)| name.ends_with("/data.pkl"))
+            .map(|&(_, data)| data)
+            .ok_or("Not a torch.save archive (no data.pkl)")?;
+        let mut types = StorageType::CLASSES
+            .iter()
+            .filter(|(class, _)| pickle.windows(class.len()).any(|w| w == *class))
+            .map(|&(_, storage_type)| storage_type);
+        let storage_type = types.next().ok_or("No supported tensor storage in archive")?;
+        if types.next().is_some() {
+            return Err("Archives mixing storage types are not supported".to_string());
+        }
+
+        // Storage keys are 0..n, assigned in pickling order
+        let mut keyed: Vec<(usize, &[u8])> = entries
+            .iter()
+            .filter_map(|&(name, data)| {
+                let (prefix, key) = name.rsplit_once('/')?;
+                prefix.ends_with("/data").then(|| key.parse().ok().map(|key| (key, data)))?
+            })
+            .collect();
+        keyed.sort_by_key(|&(key, _)| key);
+        if keyed.iter().enumerate().any(|(i, &(key, _))| i != key) {
+            return Err("Archive storage keys are not contiguous".to_string());
+        }
+
+        let storages: Vec<&[u8]> = keyed.into_iter().map(|(_, data)| data).collect();
+        if storages.iter().any(|s| s.len() % storage_type.element_bytes() != 0) {
+            return Err("Storage size is not a multiple of its element size".to_string());
+        }
+        Ok(TorchArchive { storage_type, storages })
+    }
+
+    pub(crate) fn num_storages(&self) -> usize {
+        self.storages.len()
+    }
+
+    /// Number of elements of storage `key`
+    pub(crate) fn len(&self, key: usize) -> usize {
+        self.storages[key].len() / self.storage_type.element_bytes()
+    }
+
+    /// Floating-point storage `key` as f32
+    pub(crate) fn to_f32(&self, key: usize) -> Result<Vec<f32>, String> {
+        let data = self.storages[key];
+        match self.storage_type {
+            StorageType::F32 => Ok(data.chunks_exact(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect()),
+            StorageType::F16 => Ok(data.chunks_exact(2).map(|b| f16_to_f32(u16::from_le_bytes([b[0], b[1]]))).collect()),
+            StorageType::BF16 => Ok(data.chunks_exact(2).map(|b| bf16_to_f32(u16::from_le_bytes([b[0], b[1]]))).collect()),
+            other => Err(format!("Expected a floating-point tensor, found {:?}", other)),
+        }
+    }
+
+    /// Integer storage `key` as u32 (negative or too large values are rejected)
+    pub(crate) fn to_u32(&self, key: usize) -> Result<Vec<u32>, String> {
+        let data = self.storages[key];
+        let out_of_range = || "Integer tensor value out of u32 range".to_string();
+        match self.storage_type {
+            StorageType::I32 => data
+                .chunks_exact(4)
+                .map(|b| u32::try_from(i32::from_le_bytes([b[0], b[1], b[2], b[3]])).map_err(|_| out_of_range()))
+                .collect(),
+            StorageType::I64 => data
+                .chunks_exact(8)
+                .map(|b| u32::try_from(i64::from_le_bytes(b.try_into().unwrap())).map_err(|_| out_of_range()))
+                .collect(),
+            StorageType::U8 => Ok(data.iter().map(|&b| b as u32).collect()),
+            other => Err(format!("Expected an integer tensor, found {:?}", other)),
+        }
+    }
+
+    /// Byte storage `key`
+    pub(crate) fn bytes(&self, key: usize) -> Result<&'a [u8], String> {
+        match self.storage_type {
+            StorageType::U8 => Ok(self.storages[key]),
+            other => Err(format!("Expected a uint8 tensor, found {:?}", other)),
+        }
+    }
+}
+
+/// Build a stored (uncompressed) `torch.save`-like archive
+#[cfg(test)]
+pub(crate) fn write_archive(storage_class: &str, storages: &[Vec<u8>]) -> Vec<u8> {
+    let pickle = format!("\u{80}\u{2}ctorch\n{}\nq\u{0}.", storage_class).into_bytes();
+    let mut files = vec![("archive/data.pkl".to_string(), pickle)];
+    for (key, data) in storages.iter().enumerate() {
+        files.push((format!("archive/data/{}", key), data.clone()));
+    }
+    zip::write_stored(&files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reads_storages_in_key_order() {
+        let halves: Vec<u8> = [0x3c00u16, 0xc000, 0x3800].iter().flat_map(|h| h.to_le_bytes()).collect();
+        let bytes = write_archive("HalfStorage", &[halves, vec![0, 0x3c]]);
+        let archive = TorchArchive::parse(&bytes).unwrap();
+
+        assert_eq!(archive.storage_type, StorageType::F16);
+        assert_eq!(archive.num_storages(), 2);
+        assert_eq!(archive.len(0), 3);
+        assert_eq!(archive.to_f32(0).unwrap(), vec![1.0, -2.0, 0.5]);
+        assert_eq!(archive.to_f32(1).unwrap(), vec![1.0]);
+        assert!(archive.to_u32(0).is_err());
+
+        assert!(TorchArchive::parse(&bytes[..bytes.len() - 1]).is_err());
+    }
+}
@@ -0,0 +1,162 @@
+/*!
+ * Minimal reader for stored (uncompressed) ZIP archives
+ *
+ * Both `torch.save` archives and `numpy.savez` files are ZIP archives whose entries
+ * are stored without compression, so entries are borrowed straight from the buffer.
+ * ZIP64 archives (entries or offsets past 4 GiB) are supported; compressed entries
+ * (e.g. `numpy.savez_compressed`) are rejected.
+ */
+
+use super::{read_u16, read_u32, read_u64};
+
+const LOCAL_HEADER: u32 = 0x0403_4b50;
+const CENTRAL_HEADER: u32 = 0x0201_4b50;
+const END_OF_CENTRAL_DIR: u32 = 0x0605_4b50;
+const ZIP64_END_LOCATOR: u32 = 0x0706_4b50;
+const ZIP64_END_OF_CENTRAL_DIR: u32 = 0x0606_4b50;
+
+/// (name, data) of every stored (uncompressed) entry, via the central directory
+///
+/// Offsets and sizes come from the file, so every region is bounds-checked before it
+/// is read: a corrupt or crafted archive is an error, never a panic.
+pub(crate) fn entries(bytes: &[u8]) -> Result<Vec<(&str, &[u8])>, String> {
+    let eocd = (0..bytes.len().saturating_sub(21))
+        .rev()
+        .take(0xffff + 22)
+        .find(|&i| read_u32(bytes, i) == END_OF_CENTRAL_DIR)
+        .ok_or("Not a ZIP archive (no end of central directory)")?;
+
+    let (mut num_entries, mut dir_offset) = (read_u16(bytes, eocd + 10) as u64, read_u32(bytes, eocd + 16) as u64);
+    if num_entries == 0xffff || dir_offset == 0xffff_ffff {
+        // ZIP64: the locator right before the record points to the 64-bit record
+        let locator = eocd.checked_sub(20).filter(|&l| read_u32(bytes, l) == ZIP64_END_LOCATOR)
+            .ok_or("Missing ZIP64 end of central directory locator")?;
+        let record = region(bytes, read_u64(bytes, locator + 8), 56)
+            .filter(|record| read_u32(record, 0) == ZIP64_END_OF_CENTRAL_DIR)
+            .ok_or("Corrupt ZIP64 end of central directory")?;
+        num_entries = read_u64(record, 32);
+        dir_offset = read_u64(record, 48);
+    }
+
+    let truncated = || "Truncated ZIP archive".to_string();
+    let mut entries = Vec::new();
+    let mut pos = dir_offset;
+    for _ in 0..num_entries {
+        let header = region(bytes, pos, 46)
+            .filter(|header| read_u32(header, 0) == CENTRAL_HEADER)
+            .ok_or("Corrupt ZIP central directory")?;
+        let compression = read_u16(header, 10);
+        let mut size = read_u32(header, 24) as u64;
+        let name_len = read_u16(header, 28) as u64;
+        let extra_len = read_u16(header, 30) as u64;
+        let comment_len = read_u16(header, 32) as u64;
+        let mut local = read_u32(header, 42) as u64;
+        // The header is in the buffer, so these offsets stay far from overflowing
+        let name_start = pos + 46;
+        let name = region(bytes, name_start, name_len).ok_or_else(truncated)?;
+        let name = std::str::from_utf8(name).map_err(|_| "Invalid ZIP entry name")?;
+        let extra = region(bytes, name_start + name_len, extra_len).ok_or_else(truncated)?;
+
+        // ZIP64 extended information: present fields follow the order
+        // (uncompressed size, compressed size, local header offset)
+        let mut field = 0;
+        while field + 4 <= extra.len() {
+            let (id, len) = (read_u16(extra, field), read_u16(extra, field + 2) as usize);
+            let body = extra.get(field + 4..field + 4 + len).ok_or_else(truncated)?;
+            if id == 0x0001 {
+                let mut values = body.chunks_exact(8).map(|v| read_u64(v, 0));
+                if size == 0xffff_ffff {
+                    size = values.next().ok_or_else(truncated)?;
+                    values.next(); // Compressed size (same: entries are stored)
+                }
+                if local == 0xffff_ffff {
+                    local = values.next().ok_or_else(truncated)?;
+                }
+            }
+            field += 4 + len;
+        }
+
+        if compression != 0 {
+            return Err(format!("Compressed ZIP entry {} is not supported", name));
+        }
+        let local_header = region(bytes, local, 30)
+            .filter(|header| read_u32(header, 0) == LOCAL_HEADER)
+            .ok_or("Corrupt ZIP local header")?;
+        let data_start = local + 30 + read_u16(local_header, 26) as u64 + read_u16(local_header, 28) as u64;
+        entries.push((name, region(bytes, data_start, size).ok_or_else(truncated)?));
+
+        pos = name_start + name_len + extra_len + comment_len;
+    }
+    Ok(entries)
+}
+
+// `len` bytes at `start`, when the whole region is inside the buffer
+fn region(bytes: &[u8], start: u64, len: u64) -> Option<&[u8]> {
+    let start = usize::try_from(start).ok()?;
+    bytes.get(start..start.checked_add(usize::try_from(len).ok()?)?)
+}
+
+/// Build a stored archive from (name, data) entries
+#[cfg(test)]
+pub(crate) fn write_stored(files: &[(String, Vec<u8>)]) -> Vec<u8> {
+    let (mut out, mut directory) = (Vec::new(), Vec::new());
+    for (name, data) in files {
+        let offset = out.len() as u32;
+        out.extend_from_slice(&LOCAL_HEADER.to_le_bytes());
+        out.extend_from_slice(&[0; 14]); // Versions, flags, compression, time, date, crc
+        out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        out.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        out.extend_from_slice(&0u16.to_le_bytes());
+        out.extend_from_slice(name.as_bytes());
+        out.extend_from_slice(data);
+
+        directory.extend_from_slice(&CENTRAL_HEADER.to_le_bytes());
+        directory.extend_from_slice(&[0; 16]); // Versions, flags, compression, time, date, crc
+        directory.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        directory.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        directory.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        directory.extend_from_slice(&[0; 12]); // Extra, comment, disk, attributes
+        directory.extend_from_slice(&offset.to_le_bytes());
+        directory.extend_from_slice(name.as_bytes());
+    }
+
+    let dir_offset = out.len() as u32;
+    out.extend_from_slice(&directory);
+    out.extend_from_slice(&END_OF_CENTRAL_DIR.to_le_bytes());
+    out.extend_from_slice(&[0; 4]); // Disk numbers
+    out.extend_from_slice(&(files.len() as u16).to_le_bytes());
+    out.extend_from_slice(&(files.len() as u16).to_le_bytes());
+    out.extend_from_slice(&(directory.len() as u32).to_le_bytes());
+    out.extend_from_slice(&dir_offset.to_le_bytes());
+    out.extend_from_slice(&0u16.to_le_bytes());
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_out_of_range_offsets_are_errors() {
+        let archive = write_stored(&[("a.npy".to_string(), vec![1, 2, 3])]);
+        assert_eq!(entries(&archive).unwrap(), [("a.npy", &[1u8, 2, 3][..])]);
+        let eocd = archive.len() - 22;
+
+        // Directory, local header and ZIP64 record offsets near the end of the address space
+        let mut far_directory = archive.clone();
+        far_directory[eocd + 16..eocd + 20].copy_from_slice(&0xffff_fffeu32.to_le_bytes());
+        assert!(entries(&far_directory).is_err());
+        let mut far_local = archive.clone();
+        far_local[eocd - 5 - 4..eocd - 5].copy_from_slice(&0xffff_fffeu32.to_le_bytes());
+        assert!(entries(&far_local).is_err());
+
+        let mut zip64 = ZIP64_END_LOCATOR.to_le_bytes().to_vec();
+        zip64.extend_from_slice(&[0; 4]);
+        zip64.extend_from_slice(&(u64::MAX - 20).to_le_bytes());
+        zip64.extend_from_slice(&[0; 4]);
+        zip64.extend_from_slice(&END_OF_CENTRAL_DIR.to_le_bytes());
+        zip64.extend_from_slice(&[0, 0, 0, 0, 0xff, 0xff, 0xff, 0xff, 0, 0, 0, 0, 0xff, 0xff, 0xff, 0xff, 0, 0]);
+        assert!(entries(&zip64).is_err());
+    }
+}
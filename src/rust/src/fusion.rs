@@ -0,0 +1,105 @@
+/*!
+ * Hybrid score fusion (MaxSim + external scores, e.g. BM25)
+ *
+ * Both inputs are per-document arrays in document order; non-finite external
+ * entries mean "not retrieved by the external system" and contribute nothing.
+ * - WeightedSum:    (1 - w) · minmax(maxsim) + w · minmax(external), in [0, 1]
+ * - ReciprocalRank: (1 - w) / (k + rank_maxsim) + w / (k + rank_external), with
+ *   1-based ranks and k = 60 (w = 0.5 is standard RRF, halved)
+ */
+
+use wasm_bindgen::prelude::*;
+
+use crate::results::rank_cmp;
+
+/// Rank-fusion constant of Reciprocal Rank Fusion (Cormack et al.)
+pub(crate) const RRF_K: f32 = 60.0;
+
+/// How MaxSim and external scores are combined
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FusionMethod {
+    #[default]
+    WeightedSum = 0,
+    ReciprocalRank = 1,
+}
+
+/// Fused score of every document; `weight` is the share of the external scores in [0, 1]
+pub(crate) fn fuse(maxsim: &[f32], external: &[f32], method: FusionMethod, weight: f32) -> Result<Vec<f32>, String> {
+    if maxsim.len() != external.len() {
+        return Err(format!("{} MaxSim scores but {} external scores", maxsim.len(), external.len()));
+    }
+    if !(0.0..=1.0).contains(&weight) {
+        return Err(format!("weight must be in [0, 1], got {}", weight));
+    }
+
+    let fused = match method {
+        FusionMethod::WeightedSum => {
+            let maxsim_range = min_max(maxsim.iter().copied());
+            let external_range = min_max(external.iter().copied().filter(|s| s.is_finite()));
+            maxsim
+                .iter()
+                .zip(external)
+                .map(|(&m, &e)| {
+                    let e = if e.is_finite() { unit(e, external_range) } else { 0.0 };
+                    (1.0 - weight) * unit(m, maxsim_range) + weight * e
+                })
+                .collect()
+        }
+        FusionMethod::ReciprocalRank => {
+            let (maxsim_ranks, external_ranks) = (ranks(maxsim), ranks(external));
+            maxsim_ranks
+                .iter()
+                .zip(&external_ranks)
+                .map(|(&m, &e)| {
+                    let e = e.map_or(0.0, |rank| weight / (RRF_K + rank as f32));
+                    m.map_or(0.0, |rank| (1.0 - weight) / (RRF_K + rank as f32)) + e
+                })
+                .collect()
+        }
+    };
+    Ok(fused)
+}
+
+/// 1-based rank of every finite score, best first (ties: lower index first)
+pub(crate) fn ranks(scores: &[f32]) -> Vec<Option<usize>> {
+    let mut order: Vec<usize> = (0..scores.len()).filter(|&i| scores[i].is_finite()).collect();
+    order.sort_by(|&a, &b| rank_cmp((a, scores[a]), (b, scores[b])));
+    let mut ranks = vec![None; scores.len()];
+    for (rank, &doc) in order.iter().enumerate() {
+        ranks[doc] = Some(rank + 1);
+    }
+    ranks
+}
+
+/// (min, max) of `values` ((∞, -∞) when empty)
+pub(crate) fn min_max(values: impl Iterator<Item = f32>) -> (f32, f32) {
+    values.fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), v| (lo.min(v), hi.max(v)))
+}
+
+/// Position of `value` in `[lo, hi]`; a degenerate range maps everything to 1
+pub(crate) fn unit(value: f32, (lo, hi): (f32, f32)) -> f32 {
+    if hi > lo { (value - lo) / (hi - lo) } else { 1.0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_weighted_sum_and_rrf() {
+        let maxsim = [10.0, 20.0, 30.0];
+        let bm25 = [4.0, f32::NAN, 2.0]; // Document 1 was not retrieved lexically
+
+        let fused = fuse(&maxsim, &bm25, FusionMethod::WeightedSum, 0.5).unwrap();
+        assert_eq!(fused, vec![0.5, 0.25, 0.5]);
+
+        let rrf = fuse(&maxsim, &bm25, FusionMethod::ReciprocalRank, 0.5).unwrap();
+        assert_eq!(rrf[0], 0.5 / 63.0 + 0.5 / 61.0);
+        assert_eq!(rrf[1], 0.5 / 62.0);
+        assert!(rrf[2] > rrf[0]); // Ranks 1 and 2 beat ranks 3 and 1
+
+        assert!(fuse(&maxsim, &bm25[..2], FusionMethod::WeightedSum, 0.5).is_err());
+        assert!(fuse(&maxsim, &bm25, FusionMethod::ReciprocalRank, -0.1).is_err());
+    }
+}
@@ -0,0 +1,135 @@
+/*!
+ * Grouped search results
+ *
+ * Passage-chunked corpora (a file or page split into many documents) rank several
+ * chunks of the same source at the top. With a group per document (loaded with
+ * `MaxSimWasm.load_documents_grouped()`), `search_grouped()` ranks groups instead:
+ * each group keeps its best `k_per_group` passages, scored by one `GroupScore`:
+ * - Max: the best passage's score (one strong passage is enough)
+ * - Sum: the sum of the kept passages' scores (rewards several good passages,
+ *   at most `k_per_group` so large groups aren't favored for their size)
+ *
+ * Groups and their passages follow `rank_cmp` (equal group scores by lower group id).
+ */
+
+use std::collections::BTreeMap;
+
+use wasm_bindgen::prelude::*;
+
+use crate::results::rank_cmp;
+
+// (document, score) pairs of one group
+type Passages = Vec<(usize, f32)>;
+
+/// How a group's passage scores become its score
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GroupScore {
+    #[default]
+    Max = 0,
+    Sum = 1,
+}
+
+/// The best groups with their best passages, best first
+///
+/// Passages of group `i` are `indices[group_offsets[i]..group_offsets[i + 1]]`.
+#[wasm_bindgen]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GroupedHits {
+    groups: Vec<u32>,
+    group_scores: Vec<f32>,
+    group_offsets: Vec<u32>,
+    indices: Vec<u32>,
+    scores: Vec<f32>,
+}
+
+#[wasm_bindgen]
+impl GroupedHits {
+    /// Group ids, best first (Uint32Array)
+    #[wasm_bindgen(getter)]
+    pub fn groups(&self) -> Vec<u32> {
+        self.groups.clone()
+    }
+
+    /// Score of each group, aligned with `groups`
+    #[wasm_bindgen(getter)]
+    pub fn group_scores(&self) -> Vec<f32> {
+        self.group_scores.clone()
+    }
+
+    /// Start of each group's passages in `indices`, plus the total (groups + 1 entries)
+    #[wasm_bindgen(getter)]
+    pub fn group_offsets(&self) -> Vec<u32> {
+        self.group_offsets.clone()
+    }
+
+    /// Document indices of the kept passages, group by group, best first within a group
+    #[wasm_bindgen(getter)]
+    pub fn indices(&self) -> Vec<u32> {
+        self.indices.clone()
+    }
+
+    /// Passage scores aligned with `indices`
+    #[wasm_bindgen(getter)]
+    pub fn scores(&self) -> Vec<f32> {
+        self.scores.clone()
+    }
+}
+
+impl GroupedHits {
+    /// Rank the groups of `doc_groups` by the scores of their documents
+    pub(crate) fn rank(scores: &[f32], doc_groups: &[u32], k_groups: usize, k_per_group: usize, group_score: GroupScore) -> Self {
+        let mut members: BTreeMap<u32, Passages> = BTreeMap::new();
+        for (doc, (&score, &group)) in scores.iter().zip(doc_groups).enumerate() {
+            members.entry(group).or_default().push((doc, score));
+        }
+
+        let mut ranked: Vec<(usize, f32, Passages)> = members
+            .into_iter()
+            .map(|(group, mut passages)| {
+                passages.sort_by(|&a, &b| rank_cmp(a, b));
+                passages.truncate(k_per_group);
+                let score = match group_score {
+                    GroupScore::Max => passages[0].1,
+                    GroupScore::Sum => passages.iter().map(|&(_, score)| score).sum(),
+                };
+                (group as usize, score, passages)
+            })
+            .collect();
+        ranked.sort_by(|a, b| rank_cmp((a.0, a.1), (b.0, b.1)));
+        ranked.truncate(k_groups);
+
+        let mut hits = GroupedHits { group_offsets: vec![0], ..Default::default() };
+        for (group, score, passages) in ranked {
+            hits.groups.push(group as u32);
+            hits.group_scores.push(score);
+            for (doc, score) in passages {
+                hits.indices.push(doc as u32);
+                hits.scores.push(score);
+            }
+            hits.group_offsets.push(hits.indices.len() as u32);
+        }
+        hits
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_groups_rank_by_best_or_summed_passages() {
+        // Group 7: 0.9, 0.1, 0.1; group 3: 0.6, 0.5; group 5: 0.2
+        let scores = [0.9, 0.6, 0.1, 0.5, 0.2, 0.1];
+        let groups = [7, 3, 7, 3, 5, 7];
+
+        let by_max = GroupedHits::rank(&scores, &groups, 2, 2, GroupScore::Max);
+        assert_eq!((by_max.groups(), by_max.group_scores()), (vec![7, 3], vec![0.9, 0.6]));
+        assert_eq!((by_max.group_offsets(), by_max.indices()), (vec![0, 2, 4], vec![0, 2, 1, 3]));
+        assert_eq!(by_max.scores(), [0.9, 0.1, 0.6, 0.5]);
+
+        let by_sum = GroupedHits::rank(&scores, &groups, 3, 2, GroupScore::Sum);
+        assert_eq!(by_sum.groups(), [3, 7, 5]);
+        assert!((by_sum.group_scores()[0] - 1.1).abs() < 1e-6);
+    }
+}
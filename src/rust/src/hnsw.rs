@@ -0,0 +1,300 @@
+/*!
+ * HNSW graph over the pooled document vectors
+ *
+ * A hierarchical navigable small-world graph (Malkov & Yashunin) finds the pooled
+ * vectors (see pooled.rs) nearest to a pooled query in roughly logarithmic time, so
+ * candidate generation stays cheap where a linear scan over 500k+ documents does
+ * not. `MaxSimWasm.search_preloaded_hnsw()` takes the graph's candidates and scores
+ * them with full MaxSim.
+ *
+ * Every node is inserted at a random top layer (geometric with ratio 1/m) and linked
+ * on each of its layers to its `m` most similar nodes found by a beam search of width
+ * `ef_construction` (`2m` links are kept per node on layer 0). Similarity is the dot
+ * product of the L2-normalized pooled vectors. Levels are drawn from a seeded stream,
+ * so a graph is reproducible for a given corpus, seed and parameters.
+ *
+ * Building costs about `num_docs × ef_construction` dot products; large corpora
+ * should build the graph offline (`export_hnsw_graph()`, e.g. under Node) and ship
+ * it beside the index. Graph blob layout (little-endian; counts and links use the
+ * snapshot section encoding):
+ *
+ * ```text
+ * magic          4 bytes   "MXSH"
+ * version        u32       GRAPH_VERSION
+ * fingerprint    u32       of the index the graph was built for (0: unknown)
+ * pooling        u32       Pooling of the vectors
+ * m              u64
+ * entry          u64       entry node (u64::MAX for an empty graph)
+ * levels         u64 count + u64 × num_nodes   top layer of every node
+ * links          per node and layer: u64 count + u32 × count
+ * ```
+ *
+ * A blob that can't be read fails with an `IndexError`: `NotAnIndex` (bad magic),
+ * `UnsupportedVersion` or `CorruptHeader` (truncated, or links out of range).
+ */
+
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashSet};
+
+use crate::dot_product;
+use crate::formats::index::IndexError;
+use crate::pooled::Pooling;
+use crate::rng::SeededRng;
+use crate::snapshot::{SectionReader, SectionWriter};
+
+const GRAPH_MAGIC: &[u8; 4] = b"MXSH";
+pub(crate) const GRAPH_VERSION: u32 = 1;
+
+/// Highest layer a node can be drawn at
+const MAX_LEVEL: usize = 16;
+
+/// Default beam width while building
+pub(crate) const DEFAULT_EF_CONSTRUCTION: usize = 100;
+
+/// Navigable graph over one vector per document, in original document order
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct HnswGraph {
+    m: usize,
+    entry: Option<u32>,
+    links: Vec<Vec<Vec<u32>>>, // Node → layer (0..=its level) → neighbors
+}
+
+/// A node (or document) with its similarity to the query; ordered by similarity,
+/// ties by lower id
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Scored(pub(crate) f32, pub(crate) u32);
+
+impl Eq for Scored {}
+
+impl Ord for Scored {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0).then(other.1.cmp(&self.1))
+    }
+}
+
+impl PartialOrd for Scored {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl HnswGraph {
+    /// Insert every vector (`num_docs × dim`, flat) in document order
+    pub(crate) fn build(vectors: &[f32], dim: usize, m: usize, ef_construction: usize, rng: &mut SeededRng) -> Self {
+        let m = m.max(2);
+        let level_scale = 1.0 / (m as f64).ln();
+        let mut graph = HnswGraph { m, entry: None, links: Vec::with_capacity(vectors.len() / dim) };
+        for node in 0..vectors.len() / dim {
+            // Uniform in (0, 1], so the logarithm is finite
+            let uniform = ((rng.next_u64() >> 11) + 1) as f64 / (1u64 << 53) as f64;
+            let level = ((-uniform.ln() * level_scale) as usize).min(MAX_LEVEL);
+            graph.insert(node as u32, level, vectors, dim, ef_construction.max(m));
+        }
+        graph
+    }
+
+    pub(crate) fn num_nodes(&self) -> usize {
+        self.links.len()
+    }
+
+    /// Bytes held by the links
+    pub(crate) fn memory_bytes(&self) -> usize {
+        self.links.iter().flatten().map(|neighbors| neighbors.len() * std::mem::size_of::<u32>()).sum()
+    }
+
+    /// The (approximately) `k` most similar nodes to `query`, best first, searching
+    /// layer 0 with a beam of `max(ef, k)`
+    pub(crate) fn search(&self, query: &[f32], k: usize, ef: usize, vectors: &[f32], dim: usize) -> Vec<(usize, f32)> {
+        let Some(entry) = self.entry else {
+            return Vec::new();
+        };
+        let mut entry_points = vec![entry];
+        for layer in (1..self.links[entry as usize].len()).rev() {
+            entry_points = vec![self.search_layer(query, &entry_points, 1, layer, vectors, dim)[0].1];
+        }
+        let mut found = self.search_layer(query, &entry_points, ef.max(k), 0, vectors, dim);
+        found.truncate(k);
+        found.into_iter().map(|Scored(similarity, node)| (node as usize, similarity)).collect()
+    }
+
+    fn insert(&mut self, node: u32, level: usize, vectors: &[f32], dim: usize, ef_construction: usize) {
+        self.links.push(vec![Vec::new(); level + 1]);
+        let Some(entry) = self.entry else {
+            self.entry = Some(node);
+            return;
+        };
+        let query = vector(vectors, dim, node);
+        let top = self.links[entry as usize].len() - 1;
+
+        let mut entry_points = vec![entry];
+        for layer in (level + 1..=top).rev() {
+            entry_points = vec![self.search_layer(query, &entry_points, 1, layer, vectors, dim)[0].1];
+        }
+        for layer in (0..=level.min(top)).rev() {
+            let found = self.search_layer(query, &entry_points, ef_construction, layer, vectors, dim);
+            let neighbors: Vec<u32> = found.iter().take(self.m).map(|&Scored(_, id)| id).collect();
+            let max_links = if layer == 0 { 2 * self.m } else { self.m };
+            for &neighbor in &neighbors {
+                let mut links = std::mem::take(&mut self.links[neighbor as usize][layer]);
+                links.push(node);
+                if links.len() > max_links {
+                    // Keep the neighbor's most similar links
+                    let center = vector(vectors, dim, neighbor);
+                    let mut scored: Vec<Scored> = links.iter().map(|&id| Scored(dot_product(center, vector(vectors, dim, id)), id)).collect();
+                    scored.sort_unstable_by(|a, b| b.cmp(a));
+                    links = scored[..max_links].iter().map(|&Scored(_, id)| id).collect();
+                }
+                self.links[neighbor as usize][layer] = links;
+            }
+            self.links[node as usize][layer] = neighbors;
+            entry_points = found.into_iter().map(|Scored(_, id)| id).collect();
+        }
+        if level > top {
+            self.entry = Some(node);
+        }
+    }
+
+    // Beam search of width `ef` on one layer; the found nodes, best first
+    fn search_layer(&self, query: &[f32], entry_points: &[u32], ef: usize, layer: usize, vectors: &[f32], dim: usize) -> Vec<Scored> {
+        let similarity = |id: u32| Scored(dot_product(query, vector(vectors, dim, id)), id);
+        let mut visited: HashSet<u32> = entry_points.iter().copied().collect();
+        let mut candidates: BinaryHeap<Scored> = entry_points.iter().map(|&id| similarity(id)).collect();
+        let mut found: BinaryHeap<Reverse<Scored>> = candidates.iter().map(|&scored| Reverse(scored)).collect();
+
+        while let Some(candidate) = candidates.pop() {
+            let worst = found.peek().map_or(f32::NEG_INFINITY, |Reverse(scored)| scored.0);
+            if candidate.0 < worst && found.len() >= ef {
+                break;
+            }
+            for &neighbor in &self.links[candidate.1 as usize][layer] {
+                if !visited.insert(neighbor) {
+                    continue;
+                }
+                let scored = similarity(neighbor);
+                if found.len() < ef || scored > found.peek().unwrap().0 {
+                    candidates.push(scored);
+                    found.push(Reverse(scored));
+                    if found.len() > ef {
+                        found.pop();
+                    }
+                }
+            }
+        }
+        let mut found: Vec<Scored> = found.into_iter().map(|Reverse(scored)| scored).collect();
+        found.sort_unstable_by(|a, b| b.cmp(a));
+        found
+    }
+
+    /// Graph blob for the index with `fingerprint` (0 if unknown), vectors pooled by
+    /// `pooling`
+    pub(crate) fn to_bytes(&self, fingerprint: u32, pooling: Pooling) -> Vec<u8> {
+        let mut out = GRAPH_MAGIC.to_vec();
+        out.extend_from_slice(&GRAPH_VERSION.to_le_bytes());
+        out.extend_from_slice(&fingerprint.to_le_bytes());
+        out.extend_from_slice(&(pooling as u32).to_le_bytes());
+        let mut payload = SectionWriter::default();
+        payload.u64(self.m);
+        payload.u64(self.entry.map_or(usize::MAX, |entry| entry as usize));
+        payload.usizes(&self.links.iter().map(|layers| layers.len() - 1).collect::<Vec<_>>());
+        for neighbors in self.links.iter().flatten() {
+            payload.u32s(neighbors);
+        }
+        out.extend_from_slice(&payload.into_bytes());
+        out
+    }
+
+    /// Parse a graph blob: the graph, with the index fingerprint and pooling it was
+    /// built for
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Result<(Self, u32, u32), IndexError> {
+        if bytes.len() < 4 || &bytes[..4] != GRAPH_MAGIC {
+            return Err(IndexError::NotAnIndex);
+        }
+        if bytes.len() < 16 {
+            return Err(IndexError::CorruptHeader("graph too short for header".to_string()));
+        }
+        let version = crate::formats::read_u32(bytes, 4);
+        if version != GRAPH_VERSION {
+            return Err(IndexError::UnsupportedVersion { found: version });
+        }
+        let (fingerprint, pooling) = (crate::formats::read_u32(bytes, 8), crate::formats::read_u32(bytes, 12));
+        let graph = Self::read_links(&mut SectionReader::new(&bytes[16..])).map_err(IndexError::CorruptHeader)?;
+        Ok((graph, fingerprint, pooling))
+    }
+
+    fn read_links(input: &mut SectionReader) -> Result<Self, String> {
+        let m = input.u64()?;
+        let entry = input.u64()?;
+        let levels = input.usizes()?;
+        let num_nodes = levels.len();
+        let mut links = Vec::with_capacity(num_nodes);
+        for &level in &levels {
+            if level > MAX_LEVEL {
+                return Err("level out of range".to_string());
+            }
+            links.push((0..=level).map(|_| input.u32s()).collect::<Result<Vec<_>, _>>()?);
+        }
+        // Every neighbor on layer L must itself reach layer L, and the entry point the top layer
+        let in_range = links.iter().all(|layers| {
+            layers.iter().enumerate().all(|(layer, neighbors)| {
+                neighbors.iter().all(|&id| levels.get(id as usize).is_some_and(|&level| level >= layer))
+            })
+        });
+        let entry = if entry == usize::MAX { None } else { Some(entry) };
+        let top_level = levels.iter().copied().max();
+        if m < 2 || !in_range || entry.map(|entry| levels.get(entry).copied()) != top_level.map(Some) {
+            return Err("links out of range".to_string());
+        }
+        Ok(HnswGraph { m, entry: entry.map(|entry| entry as u32), links })
+    }
+}
+
+fn vector(vectors: &[f32], dim: usize, id: u32) -> &[f32] {
+    &vectors[id as usize * dim..(id as usize + 1) * dim]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::normalize_tokens;
+
+    #[test]
+    fn test_graph_finds_the_exact_neighbors_and_round_trips() {
+        let (dim, n) = (8, 500);
+        let mut vectors = crate::tuning::synthetic(n * dim, 2086);
+        normalize_tokens(&mut vectors, dim);
+        let graph = HnswGraph::build(&vectors, dim, 8, 64, &mut SeededRng::new(0, 3));
+        assert_eq!(graph.num_nodes(), n);
+
+        // Recall of the top 10 over a few queries
+        let mut hits = 0;
+        for query_id in 0..20u32 {
+            let query = vector(&vectors, dim, query_id * 7);
+            let mut exact: Vec<(usize, f32)> = (0..n as u32).map(|id| (id as usize, dot_product(query, vector(&vectors, dim, id)))).collect();
+            exact.sort_by(|a, b| b.1.total_cmp(&a.1));
+            let found = graph.search(query, 10, 64, &vectors, dim);
+            assert_eq!(found[0].0, query_id as usize * 7);
+            hits += found.iter().filter(|(id, _)| exact[..10].iter().any(|(e, _)| e == id)).count();
+        }
+        assert!(hits >= 190, "recall {} / 200", hits);
+
+        let bytes = graph.to_bytes(42, Pooling::Mean);
+        assert_eq!(HnswGraph::from_bytes(&bytes).unwrap(), (graph.clone(), 42, Pooling::Mean as u32));
+        let error = |bytes: &[u8]| HnswGraph::from_bytes(bytes).err().map(|e| e.code());
+        assert_eq!(error(&bytes[..bytes.len() - 1]), Some("CorruptHeader"));
+        assert_eq!(error(&bytes[..12]), Some("CorruptHeader"));
+        assert_eq!(error(b"MXSS"), Some("NotAnIndex"));
+        let mut newer = bytes.clone();
+        newer[4] = 2;
+        assert_eq!(error(&newer), Some("UnsupportedVersion"));
+
+        // A link to a node that doesn't reach the layer, or an entry point below the top
+        let top = graph.links.iter().map(Vec::len).max().unwrap() - 1;
+        let low = graph.links.iter().position(|layers| layers.len() == 1).unwrap() as u32;
+        let mut corrupt = graph.clone();
+        corrupt.links[corrupt.entry.unwrap() as usize][top].push(low);
+        assert_eq!(error(&corrupt.to_bytes(42, Pooling::Mean)), Some("CorruptHeader"));
+        let mut corrupt = graph.clone();
+        corrupt.entry = Some(low);
+        assert_eq!(error(&corrupt.to_bytes(42, Pooling::Mean)), Some("CorruptHeader"));
+    }
+}
@@ -0,0 +1,262 @@
+/*!
+ * IndexedDB-backed sharded index (feature `idb`)
+ *
+ * Stores a corpus in IndexedDB as fixed-size shards (one page each, see shards.rs)
+ * and loads shards on demand during search, keeping only a memory-budgeted working
+ * set resident in WASM linear memory. Corpora larger than linear memory can be
+ * built incrementally: load a chunk into a `MaxSimWasm`, `append()` it, repeat.
+ *
+ * ```js
+ * const index = await IdbIndex.open('my-corpus');
+ * for (const chunk of chunks) {
+ *   maxsim.load_documents(chunk.embeddings, chunk.docTokens, dim);
+ *   await index.append(maxsim, false);
+ * }
+ * index.set_memory_budget(64 * 1024 * 1024);
+ * const scores = await index.search(query, queryTokens, Metric.DotProduct, false);
+ * ```
+ */
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use js_sys::{Float32Array, Promise, Reflect, Uint8Array};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::{future_to_promise, JsFuture};
+use web_sys::{Event, IdbDatabase, IdbFactory, IdbRequest, IdbTransaction, IdbTransactionMode};
+
+use crate::formats::index::{self, Encoding};
+use crate::shards::{ShardCache, ShardManifest, DEFAULT_BUDGET_BYTES, MANIFEST_VERSION};
+use crate::{Metric, MaxSimWasm};
+
+const DB_VERSION: u32 = 1;
+const STORE_NAME: &str = "shards";
+const MANIFEST_KEY: &str = "manifest";
+
+struct IdbState {
+    manifest: Option<ShardManifest>,
+    cache: ShardCache,
+}
+
+/// Sharded index persisted in IndexedDB with lazy shard loading
+#[wasm_bindgen]
+pub struct IdbIndex {
+    db: IdbDatabase,
+    state: Rc<RefCell<IdbState>>,
+    // Scoring engine for resident shards (default settings)
+    engine: Rc<MaxSimWasm>,
+}
+
+#[wasm_bindgen]
+impl IdbIndex {
+    /// Open (or create) the IndexedDB database `db_name` and read its shard manifest
+    /// Works in windows and workers
+    #[wasm_bindgen]
+    pub async fn open(db_name: String) -> Result<IdbIndex, JsValue> {
+        let factory: IdbFactory = Reflect::get(&js_sys::global(), &JsValue::from_str("indexedDB"))?
+            .dyn_into()
+            .map_err(|_| JsValue::from_str("IndexedDB is not available in this context"))?;
+
+        let open_request = factory.open_with_u32(&db_name, DB_VERSION)?;
+        let upgrade_request = open_request.clone();
+        let on_upgrade = Closure::once_into_js(move |_: Event| {
+            if let Ok(db) = upgrade_request.result().and_then(|r| r.dyn_into::<IdbDatabase>()) {
+                let _ = db.create_object_store(STORE_NAME);
+            }
+        });
+        open_request.set_onupgradeneeded(Some(on_upgrade.unchecked_ref()));
+
+        let db: IdbDatabase = request_future(&open_request)
+            .await?
+            .dyn_into()
+            .map_err(|_| JsValue::from_str("Failed to open IndexedDB database"))?;
+
+        let manifest = match get_bytes(&db, &JsValue::from_str(MANIFEST_KEY)).await? {
+            Some(bytes) => Some(ShardManifest::from_bytes(&bytes).map_err(|e| index::artifact_js_error("shard manifest", MANIFEST_VERSION, e))?),
+            None => None,
+        };
+
+        Ok(IdbIndex {
+            db,
+            state: Rc::new(RefCell::new(IdbState {
+                manifest,
+                cache: ShardCache::new(DEFAULT_BUDGET_BYTES),
+            })),
+            engine: Rc::new(MaxSimWasm::new()),
+        })
+    }
+
+    /// Append the documents preloaded in `maxsim` as new shards (one per page)
+    ///
+    /// Shards are serialized and handed to IndexedDB one at a time, so the corpus never
+    /// needs a second full copy in linear memory. `quantized` stores int8 embeddings.
+    /// Resolves once the write transaction commits; await it before the next append.
+    #[wasm_bindgen]
+    pub fn append(&self, maxsim: &MaxSimWasm, quantized: bool) -> Result<Promise, JsValue> {
+        let docs_ref = maxsim.documents.borrow();
+        let docs = docs_ref.as_ref()
+            .ok_or_else(|| JsValue::from_str("No documents loaded. Call load_documents() first."))?;
+
+        let mut manifest = self.state.borrow().manifest.clone().unwrap_or(ShardManifest {
+            embedding_dim: docs.embedding_dim,
+            shard_docs: Vec::new(),
+        });
+        if manifest.embedding_dim != docs.embedding_dim {
+            return Err(JsValue::from_str(&format!(
+                "Embedding dimension {} does not match stored shards ({})",
+                docs.embedding_dim, manifest.embedding_dim
+            )));
+        }
+
+        let encoding = if quantized { Encoding::Int8 } else { Encoding::F32 };
+        let tx = self.db.transaction_with_str_and_mode(STORE_NAME, IdbTransactionMode::Readwrite)?;
+        let store = tx.object_store(STORE_NAME)?;
+        for page in docs.pages() {
+            let shard = index::serialize_pages(std::slice::from_ref(page), docs.embedding_dim, encoding);
+            store.put_with_key(&Uint8Array::from(&shard[..]), &shard_key(manifest.shard_docs.len()))?;
+            manifest.shard_docs.push(page.doc_tokens.len());
+        }
+        store.put_with_key(&Uint8Array::from(&manifest.to_bytes()[..]), &JsValue::from_str(MANIFEST_KEY))?;
+
+        let committed = transaction_future(&tx);
+        let state = Rc::clone(&self.state);
+        Ok(future_to_promise(async move {
+            committed.await?;
+            state.borrow_mut().manifest = Some(manifest);
+            Ok(JsValue::UNDEFINED)
+        }))
+    }
+
+    /// Delete every stored shard and drop the resident working set
+    #[wasm_bindgen]
+    pub fn clear(&self) -> Result<Promise, JsValue> {
+        let tx = self.db.transaction_with_str_and_mode(STORE_NAME, IdbTransactionMode::Readwrite)?;
+        tx.object_store(STORE_NAME)?.clear()?;
+
+        let committed = transaction_future(&tx);
+        let state = Rc::clone(&self.state);
+        Ok(future_to_promise(async move {
+            committed.await?;
+            let mut state = state.borrow_mut();
+            state.manifest = None;
+            state.cache.clear();
+            Ok(JsValue::UNDEFINED)
+        }))
+    }
+
+    /// Score every stored document, loading non-resident shards from IndexedDB
+    ///
+    /// Resolves to a Float32Array of MaxSim scores in document order. Shards beyond the
+    /// memory budget are evicted least-recently-used first.
+    #[wasm_bindgen]
+    pub fn search(&self, query_flat: Vec<f32>, query_tokens: usize, metric: Metric, normalized: bool) -> Promise {
+        let db = self.db.clone();
+        let state = Rc::clone(&self.state);
+        let engine = Rc::clone(&self.engine);
+
+        future_to_promise(async move {
+            let manifest = state.borrow().manifest.clone()
+                .ok_or_else(|| JsValue::from_str("No shards stored. Call append() first."))?;
+
+            let mut scores = vec![0.0f32; manifest.num_docs()];
+            for (shard, first_doc) in manifest.shard_offsets().into_iter().enumerate() {
+                let resident = state.borrow_mut().cache.get(shard);
+                let docs = match resident {
+                    Some(docs) => docs,
+                    None => {
+                        let bytes = get_bytes(&db, &shard_key(shard))
+                            .await?
+                            .ok_or_else(|| JsValue::from_str(&format!("Shard {} is missing", shard)))?;
+                        let docs = index::deserialize(&bytes).map_err(|e| index::shard_js_error(shard, e))?;
+                        state.borrow_mut().cache.insert(shard, docs)
+                    }
+                };
+
+                let shard_scores = engine
+                    .search_store(&docs, &query_flat, query_tokens, &[], normalized, metric, None)
+                    .map_err(|e| JsValue::from_str(&e))?;
+                scores[first_doc..first_doc + shard_scores.len()].copy_from_slice(&shard_scores);
+            }
+
+            Ok(Float32Array::from(&scores[..]).into())
+        })
+    }
+
+    /// Cap the bytes of shards kept resident between searches (default 32 MB)
+    #[wasm_bindgen]
+    pub fn set_memory_budget(&self, bytes: usize) {
+        self.state.borrow_mut().cache.set_budget(bytes);
+    }
+
+    /// Bytes of shards currently resident in linear memory
+    #[wasm_bindgen]
+    pub fn resident_bytes(&self) -> usize {
+        self.state.borrow().cache.resident_bytes()
+    }
+
+    /// Number of shards currently resident in linear memory
+    #[wasm_bindgen]
+    pub fn resident_shards(&self) -> usize {
+        self.state.borrow().cache.resident_shards()
+    }
+
+    /// Number of documents stored across all shards
+    #[wasm_bindgen]
+    pub fn num_documents(&self) -> usize {
+        self.state.borrow().manifest.as_ref().map_or(0, |m| m.num_docs())
+    }
+
+    /// Number of stored shards
+    #[wasm_bindgen]
+    pub fn num_shards(&self) -> usize {
+        self.state.borrow().manifest.as_ref().map_or(0, |m| m.shard_docs.len())
+    }
+}
+
+fn shard_key(shard: usize) -> JsValue {
+    JsValue::from_str(&format!("shard:{}", shard))
+}
+
+// Read one stored blob, `None` when the key is absent
+async fn get_bytes(db: &IdbDatabase, key: &JsValue) -> Result<Option<Vec<u8>>, JsValue> {
+    let tx = db.transaction_with_str(STORE_NAME)?;
+    let value = request_future(&tx.object_store(STORE_NAME)?.get(key)?).await?;
+    if value.is_undefined() {
+        return Ok(None);
+    }
+    Ok(Some(Uint8Array::new(&value).to_vec()))
+}
+
+// Resolve with the request's result once it succeeds
+fn request_future(request: &IdbRequest) -> JsFuture {
+    let promise = Promise::new(&mut |resolve, reject| {
+        let succeeded = request.clone();
+        let on_success = Closure::once_into_js(move |_: Event| {
+            let _ = resolve.call1(&JsValue::UNDEFINED, &succeeded.result().unwrap_or(JsValue::UNDEFINED));
+        });
+        let failed = request.clone();
+        let on_error = Closure::once_into_js(move |_: Event| {
+            let error = failed.error().ok().flatten().map_or(JsValue::from_str("IndexedDB request failed"), JsValue::from);
+            let _ = reject.call1(&JsValue::UNDEFINED, &error);
+        });
+        request.set_onsuccess(Some(on_success.unchecked_ref()));
+        request.set_onerror(Some(on_error.unchecked_ref()));
+    });
+    JsFuture::from(promise)
+}
+
+// Resolve once the transaction commits
+fn transaction_future(tx: &IdbTransaction) -> JsFuture {
+    let promise = Promise::new(&mut |resolve, reject| {
+        let on_complete = Closure::once_into_js(move |_: Event| {
+            let _ = resolve.call0(&JsValue::UNDEFINED);
+        });
+        let on_error = Closure::once_into_js(move |_: Event| {
+            let _ = reject.call1(&JsValue::UNDEFINED, &JsValue::from_str("IndexedDB transaction failed"));
+        });
+        tx.set_oncomplete(Some(on_complete.unchecked_ref()));
+        tx.set_onerror(Some(on_error.unchecked_ref()));
+    });
+    JsFuture::from(promise)
+}
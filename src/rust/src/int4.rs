@@ -0,0 +1,150 @@
+/*!
+ * Resident 4-bit corpus
+ *
+ * Documents loaded with `MaxSimWasm.load_documents_int4()` stay in the grouped
+ * 4-bit encoding of `maxsim_core::int4` (about 1/6 of f32 at dim 768 with groups of
+ * 64) and are scored with its fused dot product: tokens are never dequantized.
+ * Squared norms of the quantized tokens are kept for the Cosine and NegativeL2
+ * metrics (4 bytes per token).
+ */
+
+use maxsim_core::int4::{code_bytes, dot_int4, group_sums, quantize_token};
+
+use crate::dot_product;
+use crate::snapshot::{SectionReader, SectionWriter};
+
+/// 4-bit corpus, in original document order
+pub(crate) struct Int4Documents {
+    pub(crate) embedding_dim: usize,
+    group_size: usize,
+    codes: Vec<u8>,         // code_bytes(embedding_dim) per token
+    scales: Vec<f32>,       // One per group of every token
+    zeros: Vec<f32>,        // One per group of every token
+    token_norms: Vec<f32>,  // Squared norm of every quantized token
+    doc_tokens: Vec<usize>,
+}
+
+impl Int4Documents {
+    /// Quantize documents stored back to back
+    pub(crate) fn quantize(flat: &[f32], doc_tokens: &[usize], embedding_dim: usize, group_size: usize) -> Result<Self, String> {
+        if group_size == 0 || !group_size.is_multiple_of(2) {
+            return Err("group_size must be even".to_string());
+        }
+
+        let mut docs = Int4Documents {
+            embedding_dim,
+            group_size,
+            codes: Vec::new(),
+            scales: Vec::new(),
+            zeros: Vec::new(),
+            token_norms: Vec::new(),
+            doc_tokens: doc_tokens.to_vec(),
+        };
+        let mut restored = Vec::with_capacity(embedding_dim);
+        for token in flat.chunks_exact(embedding_dim) {
+            quantize_token(token, group_size, &mut docs.codes, &mut docs.scales, &mut docs.zeros);
+            restored.clear();
+            docs.dequantize(docs.token_norms.len(), &mut restored);
+            docs.token_norms.push(dot_product(&restored, &restored));
+        }
+        Ok(docs)
+    }
+
+    pub(crate) fn num_docs(&self) -> usize {
+        self.doc_tokens.len()
+    }
+
+    pub(crate) fn group_size(&self) -> usize {
+        self.group_size
+    }
+
+    /// Write the quantized tokens to a snapshot section
+    pub(crate) fn write_state(&self, out: &mut SectionWriter) {
+        out.u64(self.embedding_dim);
+        out.u64(self.group_size);
+        out.bytes(&self.codes);
+        out.f32s(&self.scales);
+        out.f32s(&self.zeros);
+        out.f32s(&self.token_norms);
+        out.usizes(&self.doc_tokens);
+    }
+
+    /// Read a corpus written by `write_state()`
+    pub(crate) fn read_state(input: &mut SectionReader) -> Result<Self, String> {
+        let (embedding_dim, group_size) = (input.u64()?, input.u64()?);
+        if embedding_dim == 0 || group_size == 0 || !group_size.is_multiple_of(2) {
+            return Err("group_size must be even".to_string());
+        }
+        let docs = Int4Documents {
+            embedding_dim,
+            group_size,
+            codes: input.bytes()?.to_vec(),
+            scales: input.f32s()?,
+            zeros: input.f32s()?,
+            token_norms: input.f32s()?,
+            doc_tokens: input.usizes()?,
+        };
+        let total_tokens = docs.doc_tokens.iter().try_fold(0usize, |sum, &tokens| sum.checked_add(tokens));
+        if total_tokens != Some(docs.token_norms.len())
+            || docs.codes.len() != docs.token_norms.len() * code_bytes(embedding_dim)
+            || docs.scales.len() != docs.token_norms.len() * docs.groups()
+            || docs.zeros.len() != docs.scales.len()
+        {
+            return Err("4-bit corpus size mismatch".to_string());
+        }
+        Ok(docs)
+    }
+
+    /// Bytes held by the quantized corpus
+    pub(crate) fn memory_bytes(&self) -> usize {
+        self.codes.len() + (self.scales.len() + self.zeros.len() + self.token_norms.len()) * std::mem::size_of::<f32>()
+    }
+
+    // Groups per token, the last one shorter when `group_size` doesn't divide the dim
+    fn groups(&self) -> usize {
+        self.embedding_dim.div_ceil(self.group_size)
+    }
+
+    fn dequantize(&self, token: usize, out: &mut Vec<f32>) {
+        let (bytes, groups) = (code_bytes(self.embedding_dim), self.groups());
+        maxsim_core::int4::dequantize_token(
+            &self.codes[token * bytes..(token + 1) * bytes],
+            &self.scales[token * groups..(token + 1) * groups],
+            &self.zeros[token * groups..(token + 1) * groups],
+            self.embedding_dim,
+            self.group_size,
+            out,
+        );
+    }
+
+    /// Visit every document as its raw dot-product rows with `query_flat`
+    /// (query_tokens × doc_tokens) and the squared norms of its tokens
+    pub(crate) fn for_each_document(&self, query_flat: &[f32], mut score: impl FnMut(&mut [f32], &[f32], usize)) {
+        let (dim, bytes, groups) = (self.embedding_dim, code_bytes(self.embedding_dim), self.groups());
+        let mut query_sums = Vec::new();
+        for query_token in query_flat.chunks_exact(dim) {
+            group_sums(query_token, self.group_size, &mut query_sums);
+        }
+
+        let mut similarities = Vec::new();
+        let mut first = 0;
+        for &tokens in &self.doc_tokens {
+            similarities.clear();
+            for (q_idx, query_token) in query_flat.chunks_exact(dim).enumerate() {
+                let sums = &query_sums[q_idx * groups..(q_idx + 1) * groups];
+                similarities.extend((first..first + tokens).map(|token| {
+                    dot_int4(
+                        query_token,
+                        sums,
+                        &self.codes[token * bytes..(token + 1) * bytes],
+                        &self.scales[token * groups..(token + 1) * groups],
+                        &self.zeros[token * groups..(token + 1) * groups],
+                        self.group_size,
+                    )
+                }));
+            }
+            score(&mut similarities, &self.token_norms[first..first + tokens], tokens);
+            first += tokens;
+        }
+    }
+}
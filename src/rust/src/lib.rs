@@ -4,6 +4,7 @@
  * IMPORTANT: This implementation expects L2-normalized embeddings as input.
  * Modern embedding models (ColBERT, BGE, E5, etc.) output normalized embeddings by default.
  * For normalized embeddings, dot product equals cosine similarity.
+ * Models that don't output unit vectors can enable set_auto_normalize(true).
  *
  * MaxSim Algorithm:
  * - For each query token, find the maximum dot product with all document tokens
@@ -15,46 +16,384 @@
  */
 
 use wasm_bindgen::prelude::*;
+use std::borrow::Cow;
 use std::cell::RefCell;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap};
+use std::rc::Rc;
+
+mod aggregation;
+mod ann;
+mod benchmark;
+mod bounds;
+mod buffers;
+mod calibration;
+mod cancel;
+mod centroids;
+mod chunks;
+#[cfg(not(target_arch = "wasm32"))]
+#[doc(hidden)]
+pub mod cli;
+mod clock;
+mod compat;
+mod config;
+mod conformance;
+mod cooperative;
+mod dedup;
+mod degradation;
+mod experiment;
+mod formats;
+mod fusion;
+mod groups;
+mod hnsw;
+#[cfg(feature = "idb")]
+mod idb;
+mod int4;
+mod metric;
+mod migration;
+mod pool;
+mod pooled;
+mod postings;
+mod pq;
+mod profile;
+mod query;
+mod residual;
+mod results;
+mod rng;
+mod score_norm;
+mod scoring;
+mod scratch;
+mod shared;
+mod snapshot;
+#[cfg(any(feature = "idb", test))]
+#[cfg_attr(not(feature = "idb"), allow(dead_code))]
+mod shards;
+mod stats;
+mod store;
+mod trace;
+mod tuning;
+mod validation;
+mod variants;
+mod watchdog;
+mod windows;
+
+#[cfg(feature = "idb")]
+pub use idb::IdbIndex;
+pub use aggregation::Aggregation;
+pub use benchmark::BenchmarkReport;
+pub use cancel::AbortFlag;
+pub use chunks::ChunkMerge;
+pub use compat::{maxsim_scores, maxsim_scores_variable};
+pub use conformance::{verify, ConformanceReport};
+pub use config::MaxSimConfig;
+pub use degradation::{QueryDegradation, QueryPruning};
+pub use experiment::{ExperimentReport, ExperimentRunner, QuantizationReport};
+pub use fusion::FusionMethod;
+pub use groups::{GroupScore, GroupedHits};
+pub use metric::Metric;
+#[cfg(feature = "profiling")]
+pub use profile::SearchProfile;
+pub use migration::{convert_threshold, DualScores, ScoreMode};
+pub use pool::{handle_pool_message, MaxSimPool};
+pub use pooled::Pooling;
+pub use query::QueryHandle;
+pub use results::{softmax, FusedScores, SearchHits, TokenMaxima};
+pub use score_norm::ScoreNorm;
+pub use stats::IndexStats;
+pub use variants::VariantAggregation;
+pub use windows::WindowScores;
+use ann::AnnIndex;
+use bounds::DocBounds;
+use buffers::BufferRegistry;
+use centroids::TokenCentroids;
+use maxsim_core::kernels::{
+    dot_kernel_name, dot_product, matrix_multiply, matrix_multiply_f64, prefix_similarities, simd_clamp_unit, simd_max,
+};
+use maxsim_core::delta::Delta;
+use maxsim_core::index::IndexError;
+use maxsim_core::{batch, packed};
+use metric::token_norms_sq;
+use int4::Int4Documents;
+use pooled::PooledIndex;
+use calibration::Calibration;
+use hnsw::{HnswGraph, Scored};
+use pq::PqDocuments;
+use profile::Stage;
+use results::rank_cmp;
+use residual::ResidualDocuments;
+use scoring::{DocIds, ScoreContext};
+use scratch::ScratchPool;
+use shared::SharedDocuments;
+use store::PreloadedDocuments;
+use trace::SearchTrace;
+use tuning::Tuning;
+use watchdog::Watchdog;
+
+// Default reserved capacity of the reusable scoring buffers (floats)
+const DEFAULT_SIMILARITY_FLOATS: usize = 1024 * 128;
+const DEFAULT_BATCH_FLOATS: usize = 1024 * 1024;
+// Idle scratch memory kept between calls (floats, 64 MB); larger idle buffers are released
+const MAX_IDLE_SCRATCH_FLOATS: usize = 16 * 1024 * 1024;
+
+/// "Ignorable" token embeddings (punctuation, [MASK], etc.) used to drop query tokens
+/// during query preparation
+#[derive(Clone)]
+struct StopMask {
+    embeddings: Vec<f32>, // num_tokens × embedding_dim
+    embedding_dim: usize,
+    threshold: f32,       // Query tokens with similarity >= threshold to any entry are masked
+}
+
+/// Query set describing what a selective local index wants to keep (e.g. a user's
+/// interests); candidate documents are scored against it before indexing
+struct AdmissionProfile {
+    queries: Vec<f32>,        // Flat embeddings of all profile queries, back to back
+    query_tokens: Vec<usize>, // Token count of each profile query
+    embedding_dim: usize,
+}
+
+/// Documents received so far by an in-progress streaming load
+struct StreamingLoad {
+    documents: PreloadedDocuments,
+    bytes_loaded: usize,
+    on_progress: Option<js_sys::Function>, // Called as (bytes_loaded, docs_loaded) after each chunk
+}
 
-#[cfg(target_arch = "wasm32")]
-use std::arch::wasm32::*;
+/// Query assembled token by token from a streaming encoder
+///
+/// The buffer keeps its capacity across queries, so steady-state streaming does not
+/// allocate. The running token sum is the pooled query of the first-stage ANN scan.
+#[derive(Default)]
+struct StreamingQuery {
+    tokens: Vec<f32>,    // num_tokens × embedding_dim
+    token_sum: Vec<f32>, // Sum of the tokens pushed so far
+    max_tokens: usize,
+    embedding_dim: usize,
+    active: bool,
+}
+
+/// Query prepared once for scoring documents streamed in chunks
+///
+/// Stopmask, query limit and auto-normalization are applied by `begin_stream_search`;
+/// every `score_chunk` reuses the prepared tokens.
+struct StreamSearch {
+    query: Vec<f32>, // Prepared query tokens, query_tokens × embedding_dim
+    query_tokens: usize,
+    embedding_dim: usize,
+    normalized: bool,
+    metric: Metric,
+    docs_scored: usize,
+}
 
-/// Preloaded documents stored in flat, contiguous memory for zero-copy access
-/// Stored in original order for simplicity - sorting happens on-the-fly in batch_impl (negligible cost)
-struct PreloadedDocuments {
-    embeddings_flat: Vec<f32>,  // All document embeddings in one contiguous array (original order)
-    doc_tokens: Vec<usize>,     // Token count for each document (original order)
-    embedding_dim: usize,       // Embedding dimension
+/// Full ranking of the latest paged search, reused while its inputs are unchanged
+struct PagedResults {
+    query: Vec<f32>, // Query as passed by the caller (before preparation)
+    query_tokens: usize,
+    normalized: bool,
+    generation: u32,     // Corpus generation the ranking was computed for
+    query_settings: u32, // Query preparation settings epoch
+    config: MaxSimConfig,
+    ranking: SearchHits, // Every document, best first
 }
 
 #[wasm_bindgen]
 pub struct MaxSimWasm {
-    // Reusable buffers to avoid repeated allocations
-    // These are hidden from JavaScript using #[wasm_bindgen(skip)]
-    #[wasm_bindgen(skip)]
-    similarity_buffer: RefCell<Vec<f32>>,
-    #[wasm_bindgen(skip)]
-    batch_buffer: RefCell<Vec<f32>>,
+    // Reusable size-classed buffers (similarities, document batches, per-call documents
+    // after masking and/or auto-normalization), checked out per operation (see scratch.rs)
+    // Private fields are never exposed to JavaScript
+    scratch: ScratchPool,
+    buffer_floats: (usize, usize), // Reserved (similarity, batch) capacity, see set_buffer_capacity()
+    tuning: Tuning,                // Cache blocking parameters, see calibrate()
     // Document preloading support (NEW in v0.5.0)
-    // Stores documents as flat arrays for zero-copy access
-    #[wasm_bindgen(skip)]
-    documents: RefCell<Option<PreloadedDocuments>>,
+    // Stores documents in fixed-size pages of flat arrays (see store.rs); shared
+    // copy-on-write with in-flight async searches, which score a consistent snapshot
+    documents: RefCell<Option<Rc<PreloadedDocuments>>>,
+    // Staging area written directly by JS (alloc_document_buffer / commit_documents)
+    document_buffer: Vec<f32>,
+    // Scoring buffers written directly by JS (alloc_buffer / maxsim_batch_buffers)
+    buffers: BufferRegistry,
+    // Per-document tag bitmask (original order); empty when loaded without metadata
+    doc_tags: Vec<u32>,
+    // Group of every preloaded document for search_grouped() (empty: none loaded)
+    doc_groups: Vec<u32>,
+    // Chunks per long document for search_chunked(); the stored documents are the chunks
+    doc_chunks: Vec<usize>,
+    // Fingerprint of the index the preloaded documents came from (load_index or a
+    // delta applied to one); None after any other load
+    index_fingerprint: Option<u32>,
+    // Named corpora beside the preloaded one (None until loaded); they share all buffers
+    collections: BTreeMap<String, Option<PreloadedDocuments>>,
+    // Streaming load in progress (begin_load → load_chunk → finish_load)
+    streaming_load: Option<StreamingLoad>,
+    // Query being assembled (begin_streaming_query → push_query_token → finish_query)
+    streaming_query: StreamingQuery,
+    // Chunked scoring in progress (begin_stream_search → score_chunk → end_stream_search)
+    stream_search: Option<StreamSearch>,
+    // Ranking cached by search_preloaded_paged() for "load more" requests
+    paged_results: RefCell<Option<PagedResults>>,
+    // Stopmask vocabulary applied to every query at preparation time
+    stopmask: Option<StopMask>,
+    // Cap on active query tokens (max_tokens, reduction); None = unlimited
+    query_limit: Option<(usize, QueryPruning)>,
+    // Matryoshka search: (leading dimensions scored, top-k candidates reranked at full dimension)
+    search_dim: Option<(usize, usize)>,
+    // Cosine above which a query token duplicating an earlier one is dropped; None = keep all
+    query_dedup: Option<f32>,
+    // Reduction applied by the latest query preparation
+    last_degradation: RefCell<Option<QueryDegradation>>,
+    // Epoch of the query preparation settings, bumped on every change (invalidates QueryHandles)
+    query_settings: u32,
+    // Profile query set for admission_score()
+    admission_profile: Option<AdmissionProfile>,
+    // L2-normalize documents at load time and queries per search
+    auto_normalize: bool,
+    // Scan inputs for NaN/Inf on every API with an error channel (see validation.rs)
+    validation: bool,
+    // Keep packed (block-transposed) copies of stored documents for the packed kernel
+    packed_layout: bool,
+    // Internal path trace of the last search (only recorded when enabled)
+    trace: RefCell<SearchTrace>,
+    // Stage timings of the last search (empty without the `profiling` feature)
+    profile: RefCell<profile::SearchProfile>,
+    // Default metric, aggregation and normalization for calls that don't specify them
+    config: MaxSimConfig,
+    // Attach the best-matching document tokens to top-k hits (see set_match_spans())
+    match_spans: bool,
+    // Minimum interesting score for the thresholded search APIs
+    score_threshold: Option<f32>,
+    // Score gap around the k-th hit of quantized top-k searches rescored in f32
+    escalation_epsilon: Option<f32>,
+    // Range of calibrate_scores(), set by the app or learned at every load (see calibration.rs)
+    calibration: Option<Calibration>,
+    auto_calibration: bool,
+    // IVF-style cluster index over the preloaded documents (see ann.rs)
+    ann: Option<AnnIndex>,
+    ann_clusters: usize, // 0 = disabled
+    // Preview corpus: the first `preview_tokens` tokens of every preloaded document
+    preview: Option<PreloadedDocuments>,
+    preview_tokens: usize, // 0 = disabled
+    // One pooled vector per preloaded document for `search_pooled()`
+    pooled: Option<PooledIndex>,
+    pooling: Pooling,
+    // Navigable graph over the pooled vectors for search_preloaded_hnsw() (see hnsw.rs)
+    hnsw: Option<HnswGraph>,
+    hnsw_params: (usize, usize), // (m, ef_construction); m = 0: built at load = disabled
+    // Per-document bounding boxes for search_preloaded_top_k() pruning (see bounds.rs)
+    bounds: Option<DocBounds>,
+    score_bounds: bool,
+    // Per-document token centroids for search_preloaded_centroid() (see centroids.rs)
+    token_centroids: Option<TokenCentroids>,
+    centroids_per_doc: usize, // 0 = disabled
+    // Cancellation flag of the cancellable call currently running, if any
+    active_abort: RefCell<Option<AbortFlag>>,
+    // ColBERTv2 residual-compressed corpus, searched independently of `documents`
+    residual_documents: Option<ResidualDocuments>,
+    // Product-quantized corpus, searched independently of `documents` (see pq.rs)
+    pq_documents: Option<PqDocuments>,
+    // 4-bit corpus, searched independently of `documents` (see int4.rs)
+    int4_documents: Option<Int4Documents>,
+    // Corpus read from a SharedArrayBuffer shared with other workers (see shared.rs)
+    shared_documents: Option<SharedDocuments>,
+    // ColBERT index import in progress (begin_colbert_import → add_colbert_chunk → finish)
+    colbert_import: Option<ResidualDocuments>,
+    // Telemetry for pathological batching cases (see watchdog.rs)
+    watchdog: Watchdog,
+    // Corpus generation, bumped by every mutation of the stored documents
+    generation: u32,
+    on_change: Option<js_sys::Function>, // Called as (generation, change) after each bump
+}
+
+impl Default for MaxSimWasm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The module's linear memory, for views on buffers such as `alloc_document_buffer()`
+#[wasm_bindgen]
+pub fn wasm_memory() -> JsValue {
+    wasm_bindgen::memory()
 }
 
 #[wasm_bindgen]
 impl MaxSimWasm {
     #[wasm_bindgen(constructor)]
     pub fn new() -> MaxSimWasm {
+        Self::with_config(&MaxSimConfig::new())
+    }
+
+    /// Create an instance with fixed default metric, aggregation and normalization
+    ///
+    /// Calls without explicit options use these defaults; `*_normalized` and
+    /// `*_with_metric` variants still override them per call.
+    #[wasm_bindgen]
+    pub fn with_config(config: &MaxSimConfig) -> MaxSimWasm {
+        // Pre-allocate for common sizes
+        Self::with_buffers(config, (DEFAULT_SIMILARITY_FLOATS, DEFAULT_BATCH_FLOATS))
+    }
+
+    fn with_buffers(config: &MaxSimConfig, buffer_floats: (usize, usize)) -> MaxSimWasm {
         MaxSimWasm {
-            similarity_buffer: RefCell::new(Vec::with_capacity(1024 * 128)), // Pre-allocate for common sizes
-            batch_buffer: RefCell::new(Vec::with_capacity(1024 * 1024)),
+            scratch: ScratchPool::new(&[buffer_floats.0, buffer_floats.1], MAX_IDLE_SCRATCH_FLOATS),
+            buffer_floats,
+            tuning: Tuning::default(),
             documents: RefCell::new(None), // No documents preloaded initially
+            document_buffer: Vec::new(),
+            buffers: BufferRegistry::default(),
+            doc_tags: Vec::new(),
+            doc_groups: Vec::new(),
+            doc_chunks: Vec::new(),
+            index_fingerprint: None,
+            collections: BTreeMap::new(),
+            streaming_load: None,
+            streaming_query: StreamingQuery::default(),
+            stream_search: None,
+            paged_results: RefCell::new(None),
+            stopmask: None,
+            query_limit: None,
+            query_dedup: None,
+            search_dim: None,
+            last_degradation: RefCell::new(None),
+            query_settings: 0,
+            admission_profile: None,
+            auto_normalize: false,
+            validation: false,
+            packed_layout: false,
+            trace: RefCell::new(SearchTrace::default()),
+            profile: RefCell::new(profile::SearchProfile::default()),
+            config: *config,
+            match_spans: false,
+            score_threshold: None,
+            escalation_epsilon: None,
+            calibration: None,
+            auto_calibration: false,
+            ann: None,
+            ann_clusters: 0,
+            preview: None,
+            preview_tokens: 0,
+            pooled: None,
+            pooling: Pooling::None,
+            hnsw: None,
+            hnsw_params: (0, 0),
+            bounds: None,
+            score_bounds: false,
+            token_centroids: None,
+            centroids_per_doc: 0,
+            active_abort: RefCell::new(None),
+            residual_documents: None,
+            pq_documents: None,
+            int4_documents: None,
+            shared_documents: None,
+            colbert_import: None,
+            watchdog: Watchdog::default(),
+            generation: 0,
+            on_change: None,
         }
     }
 
     /// Official MaxSim: raw sum with dot product
     /// Expects L2-normalized embeddings. Matches ColBERT, pylate-rs, mixedbread-ai implementations
+    /// whatever the instance config (see config.rs)
     #[wasm_bindgen]
     pub fn maxsim_single(
         &self,
@@ -63,8 +402,10 @@ impl MaxSimWasm {
         doc_flat: &[f32],
         doc_tokens: usize,
         embedding_dim: usize,
-    ) -> f32 {
-        self.maxsim_single_impl(query_flat, query_tokens, doc_flat, doc_tokens, embedding_dim, false)
+    ) -> Result<f32, JsValue> {
+        self.check_batch_input(query_flat, query_tokens, doc_flat, &[doc_tokens], embedding_dim)
+            .map_err(|e| JsValue::from_str(&e))?;
+        Ok(self.maxsim_single_impl(query_flat, query_tokens, doc_flat, doc_tokens, embedding_dim, false, Metric::DotProduct, true))
     }
 
     /// Normalized MaxSim: averaged score for cross-query comparison
@@ -77,11 +418,34 @@ impl MaxSimWasm {
         doc_flat: &[f32],
         doc_tokens: usize,
         embedding_dim: usize,
-    ) -> f32 {
-        self.maxsim_single_impl(query_flat, query_tokens, doc_flat, doc_tokens, embedding_dim, true)
+    ) -> Result<f32, JsValue> {
+        self.check_batch_input(query_flat, query_tokens, doc_flat, &[doc_tokens], embedding_dim)
+            .map_err(|e| JsValue::from_str(&e))?;
+        Ok(self.maxsim_single_impl(query_flat, query_tokens, doc_flat, doc_tokens, embedding_dim, true, Metric::DotProduct, true))
+    }
+
+    /// MaxSim for a single document with an explicit similarity metric
+    /// Overrides the instance metric set with `set_metric()` for this call only
+    #[wasm_bindgen]
+    #[allow(clippy::too_many_arguments)]
+    pub fn maxsim_single_with_metric(
+        &self,
+        query_flat: &[f32],
+        query_tokens: usize,
+        doc_flat: &[f32],
+        doc_tokens: usize,
+        embedding_dim: usize,
+        metric: Metric,
+        normalized: bool,
+    ) -> Result<f32, JsValue> {
+        self.check_batch_input(query_flat, query_tokens, doc_flat, &[doc_tokens], embedding_dim)
+            .map_err(|e| JsValue::from_str(&e))?;
+        Ok(self.maxsim_single_impl(query_flat, query_tokens, doc_flat, doc_tokens, embedding_dim, normalized, metric, false))
     }
 
-    // Internal implementation shared by both methods
+    // Internal implementation shared by all single-document methods; the official
+    // entry points score in `official_context()`, the others with the instance config
+    #[allow(clippy::too_many_arguments)]
     fn maxsim_single_impl(
         &self,
         query_flat: &[f32],
@@ -90,16 +454,36 @@ impl MaxSimWasm {
         doc_tokens: usize,
         embedding_dim: usize,
         normalized: bool,
+        metric: Metric,
+        official: bool,
     ) -> f32 {
-        // Use the optimized compute_maxsim_score which reuses buffers
-        self.compute_maxsim_score(
-            query_flat,
-            query_tokens,
-            doc_flat,
-            doc_tokens,
-            embedding_dim,
-            normalized,
-        )
+        self.begin_search(|| format!(
+            "op=single query_tokens={} doc_tokens={} dim={} kernel={} metric={} normalized={}",
+            query_tokens, doc_tokens, embedding_dim, dot_kernel_name(embedding_dim), metric.name(), normalized
+        ));
+        let (query_data, query_tokens) = self.prepare_query_unmasked(query_flat, query_tokens, embedding_dim);
+
+        self.with_prepared_documents(doc_flat, &[doc_tokens], &[], embedding_dim, |doc_data, doc_counts| {
+            let doc_norms = call_doc_norms(metric, doc_data, embedding_dim);
+            let ctx = if official {
+                self.official_context(normalized, &query_data, embedding_dim)
+            } else {
+                self.score_context(normalized, metric, &query_data, Some(&doc_norms), embedding_dim)
+            };
+
+            // Use the optimized compute_maxsim_score which reuses buffers
+            let score = self.compute_maxsim_score(
+                &query_data,
+                query_tokens,
+                doc_data,
+                doc_counts[0],
+                embedding_dim,
+                &ctx,
+                &doc_norms,
+            );
+            self.finish_search(&ctx);
+            score
+        })
     }
 
     /// Official MaxSim batch: raw sum with dot product
@@ -111,8 +495,12 @@ impl MaxSimWasm {
         doc_flat: &[f32],
         doc_tokens: &[usize],
         embedding_dim: usize,
-    ) -> Vec<f32> {
-        self.maxsim_batch_impl(query_flat, query_tokens, doc_flat, doc_tokens, embedding_dim, false, false)
+    ) -> Result<Vec<f32>, JsValue> {
+        self.check_batch_input(query_flat, query_tokens, doc_flat, doc_tokens, embedding_dim)
+            .map_err(|e| JsValue::from_str(&e))?;
+        self.begin_batch_search(doc_tokens.len(), query_tokens, embedding_dim, Metric::DotProduct, false);
+        let (query_data, query_tokens) = self.prepare_query_unmasked(query_flat, query_tokens, embedding_dim);
+        Ok(self.score_batch_official(&query_data, query_tokens, doc_flat, doc_tokens, embedding_dim, false))
     }
 
     /// Normalized MaxSim batch: averaged with dot product
@@ -124,996 +512,7255 @@ impl MaxSimWasm {
         doc_flat: &[f32],
         doc_tokens: &[usize],
         embedding_dim: usize,
-    ) -> Vec<f32> {
-        self.maxsim_batch_impl(query_flat, query_tokens, doc_flat, doc_tokens, embedding_dim, true, false)
+    ) -> Result<Vec<f32>, JsValue> {
+        self.check_batch_input(query_flat, query_tokens, doc_flat, doc_tokens, embedding_dim)
+            .map_err(|e| JsValue::from_str(&e))?;
+        self.begin_batch_search(doc_tokens.len(), query_tokens, embedding_dim, Metric::DotProduct, true);
+        let (query_data, query_tokens) = self.prepare_query_unmasked(query_flat, query_tokens, embedding_dim);
+        Ok(self.score_batch_official(&query_data, query_tokens, doc_flat, doc_tokens, embedding_dim, true))
     }
 
-    // Internal batch implementation with adaptive optimization strategy
-    //
-    // OPTIMIZATION STRATEGY:
-    // 1. Sort documents by length for better cache locality
-    // 2. Detect uniform-length docs (≤20% variance) → fast path (no padding)
-    // 3. Variable-length docs → adaptive length-based grouping with tolerance
-    //    - Groups docs within 20-40% of each other (adaptive based on variance)
-    //    - Processes in sub-batches of 16 docs for cache efficiency
-    //    - Uses cache-blocked matrix multiply (adaptive 16/8/4 blocking)
-    //
-    // KEY INSIGHT: All paths use the same optimized compute_maxsim_score with
-    // cache-blocked matrix_multiply for consistent performance
-    fn maxsim_batch_impl(
+    /// MaxSim batch with an explicit similarity metric
+    /// Overrides the instance metric set with `set_metric()` for this call only
+    #[wasm_bindgen]
+    #[allow(clippy::too_many_arguments)]
+    pub fn maxsim_batch_with_metric(
         &self,
         query_flat: &[f32],
         query_tokens: usize,
         doc_flat: &[f32],
         doc_tokens: &[usize],
         embedding_dim: usize,
+        metric: Metric,
         normalized: bool,
-        is_sorted: bool,  // NEW: documents already sorted by length?
-    ) -> Vec<f32> {
-        let num_docs = doc_tokens.len();
-
-        if num_docs == 0 || query_tokens == 0 {
-            return vec![0.0; num_docs];
-        }
+    ) -> Result<Vec<f32>, JsValue> {
+        self.check_batch_input(query_flat, query_tokens, doc_flat, doc_tokens, embedding_dim)
+            .map_err(|e| JsValue::from_str(&e))?;
+        self.begin_batch_search(doc_tokens.len(), query_tokens, embedding_dim, metric, normalized);
+        let (query_data, query_tokens) = self.prepare_query_unmasked(query_flat, query_tokens, embedding_dim);
+        Ok(self.score_batch_prepared(&query_data, query_tokens, doc_flat, doc_tokens, &[], embedding_dim, normalized, metric, None))
+    }
 
-        let mut scores = vec![0.0; num_docs];
+    /// Symmetric (Chamfer) MaxSim of two token sets: raw sum in both directions
+    ///
+    /// Σ over `a` tokens of the max over `b` plus Σ over `b` tokens of the max over `a`.
+    /// Unlike MaxSim the score doesn't depend on which side is the query, so it suits
+    /// document-to-document similarity (dedup, clustering) where one-sided MaxSim
+    /// under-ranks long documents. Uses the instance metric and aggregation; the
+    /// query stopmask, query limit and length penalty don't apply.
+    #[wasm_bindgen]
+    pub fn maxsim_symmetric(
+        &self,
+        a_flat: &[f32],
+        a_tokens: usize,
+        b_flat: &[f32],
+        b_tokens: usize,
+        embedding_dim: usize,
+    ) -> Result<f32, JsValue> {
+        let scores = self.maxsim_symmetric_impl(a_flat, a_tokens, b_flat, &[b_tokens], embedding_dim, false)
+            .map_err(|e| JsValue::from_str(&e))?;
+        Ok(scores[0])
+    }
 
-        // Build document info: (original_index, length, offset)
-        let mut doc_infos: Vec<(usize, usize, usize)> = Vec::with_capacity(num_docs);
-        let mut offset = 0;
-        for (idx, &len) in doc_tokens.iter().enumerate() {
-            doc_infos.push((idx, len, offset));
-            offset += len * embedding_dim;
-        }
+    /// Normalized symmetric MaxSim: the mean of both directions' averaged scores
+    #[wasm_bindgen]
+    pub fn maxsim_symmetric_normalized(
+        &self,
+        a_flat: &[f32],
+        a_tokens: usize,
+        b_flat: &[f32],
+        b_tokens: usize,
+        embedding_dim: usize,
+    ) -> Result<f32, JsValue> {
+        let scores = self.maxsim_symmetric_impl(a_flat, a_tokens, b_flat, &[b_tokens], embedding_dim, true)
+            .map_err(|e| JsValue::from_str(&e))?;
+        Ok(scores[0])
+    }
 
-        // Sort by document length for better batching (skip if already sorted!)
-        let sorted_indices: Vec<usize> = if is_sorted {
-            // Documents already sorted - use sequential indices (FAST!)
-            (0..num_docs).collect()
-        } else {
-            // Need to sort - create sorted index array (slower)
-            let mut indices: Vec<usize> = (0..num_docs).collect();
-            indices.sort_by_key(|&i| doc_infos[i].1);
-            indices
-        };
+    /// Symmetric MaxSim of one token set against a batch of documents (raw sum)
+    #[wasm_bindgen]
+    pub fn maxsim_symmetric_batch(
+        &self,
+        query_flat: &[f32],
+        query_tokens: usize,
+        doc_flat: &[f32],
+        doc_tokens: &[usize],
+        embedding_dim: usize,
+    ) -> Result<Vec<f32>, JsValue> {
+        self.maxsim_symmetric_impl(query_flat, query_tokens, doc_flat, doc_tokens, embedding_dim, false)
+            .map_err(|e| JsValue::from_str(&e))
+    }
 
-        // Check if all documents have similar lengths (within 20% variance)
-        let min_len = doc_infos[sorted_indices[0]].1;
-        let max_len = doc_infos[sorted_indices[num_docs - 1]].1;
-        let length_variance = if min_len > 0 {
-            max_len as f32 / min_len as f32
-        } else {
-            f32::MAX
-        };
+    /// Normalized symmetric MaxSim of one token set against a batch of documents
+    #[wasm_bindgen]
+    pub fn maxsim_symmetric_batch_normalized(
+        &self,
+        query_flat: &[f32],
+        query_tokens: usize,
+        doc_flat: &[f32],
+        doc_tokens: &[usize],
+        embedding_dim: usize,
+    ) -> Result<Vec<f32>, JsValue> {
+        self.maxsim_symmetric_impl(query_flat, query_tokens, doc_flat, doc_tokens, embedding_dim, true)
+            .map_err(|e| JsValue::from_str(&e))
+    }
 
-        // Fast path: uniform-length documents (≤20% variance and ≥50 docs)
-        if length_variance <= 1.2 && num_docs >= 50 {
-            return self.maxsim_batch_uniform_length(
-                query_flat,
-                query_tokens,
-                doc_flat,
-                &doc_infos,
-                &sorted_indices,
-                embedding_dim,
-                normalized,
-            );
+    // Internal implementation: both directions through the single-document scorer
+    fn maxsim_symmetric_impl(
+        &self,
+        query_flat: &[f32],
+        query_tokens: usize,
+        doc_flat: &[f32],
+        doc_tokens: &[usize],
+        embedding_dim: usize,
+        normalized: bool,
+    ) -> Result<Vec<f32>, String> {
+        self.check_batch_input(query_flat, query_tokens, doc_flat, doc_tokens, embedding_dim)?;
+        let metric = self.config.metric();
+        self.begin_search(|| format!(
+            "op=symmetric docs={} query_tokens={} dim={} kernel={} metric={} normalized={}",
+            doc_tokens.len(), query_tokens, embedding_dim, dot_kernel_name(embedding_dim), metric.name(), normalized
+        ));
+
+        let mut query_data = Cow::Borrowed(query_flat);
+        if self.auto_normalize {
+            normalize_tokens(query_data.to_mut(), embedding_dim);
         }
-
-        // Adaptive batching with length-based grouping (matches official maxsim-cpu)
-        const TARGET_BATCH_SIZE: usize = 128;
-        const LENGTH_TOLERANCE: f32 = 1.2;  // Fixed 20% tolerance (like official)
-
-        let mut i = 0;
-        while i < num_docs {
-            let base_len = doc_infos[sorted_indices[i]].1;
-            if base_len == 0 {
-                i += 1;
-                continue;
-            }
-
-            let max_allowed_len = (base_len as f32 * LENGTH_TOLERANCE) as usize;
-
-            // Find batch end: docs within 20% tolerance of base length (matches official)
-            let mut batch_end = i + 1;
-            while batch_end < num_docs && batch_end < i + TARGET_BATCH_SIZE {
-                let doc_len = doc_infos[sorted_indices[batch_end]].1;
-                if doc_len > max_allowed_len {
-                    break;
-                }
-                batch_end += 1;
-            }
-
-            let batch_size = batch_end - i;
-
-            // Find actual max length in this homogeneous batch
-            let batch_max_len = sorted_indices[i..batch_end]
+        let query_norms = call_doc_norms(metric, &query_data, embedding_dim);
+
+        Ok(self.with_prepared_documents(doc_flat, doc_tokens, &[], embedding_dim, |doc_data, doc_counts| {
+            let doc_norms = call_doc_norms(metric, doc_data, embedding_dim);
+            let forward_ctx = self.score_context(false, metric, &query_data, Some(&doc_norms), embedding_dim)
+                .with_score_norm(ScoreNorm::None);
+            let mut offset = 0;
+            let scores = doc_counts
                 .iter()
-                .map(|&idx| doc_infos[idx].1)
-                .max()
-                .unwrap_or(base_len);
-
-            // Process batch
-            if batch_size < 4 {
-                // Too small for batching - process individually
-                for &sorted_idx in &sorted_indices[i..batch_end] {
-                    let (orig_idx, doc_len, doc_offset) = doc_infos[sorted_idx];
-                    let doc_slice = &doc_flat[doc_offset..doc_offset + doc_len * embedding_dim];
-                    scores[orig_idx] = self.compute_maxsim_score(
-                        query_flat,
-                        query_tokens,
-                        doc_slice,
-                        doc_len,
-                        embedding_dim,
-                        normalized,
+                .map(|&tokens| {
+                    let doc = &doc_data[offset..offset + tokens * embedding_dim];
+                    let forward = self.compute_maxsim_score(
+                        &query_data, query_tokens, doc, tokens, embedding_dim, &forward_ctx,
+                        forward_ctx.doc_norms(offset, tokens),
                     );
-                }
-            } else {
-                // Batch process with minimal padding
-                self.process_variable_batch(
-                    query_flat,
-                    query_tokens,
-                    doc_flat,
-                    &doc_infos,
-                    &sorted_indices[i..batch_end],
-                    batch_max_len,
-                    embedding_dim,
-                    normalized,
-                    &mut scores,
-                );
-            }
-
-            i = batch_end;
-        }
+                    let backward_ctx = self.score_context(false, metric, doc, Some(&query_norms), embedding_dim)
+                        .with_score_norm(ScoreNorm::None);
+                    let backward = self.compute_maxsim_score(
+                        doc, tokens, &query_data, query_tokens, embedding_dim, &backward_ctx,
+                        backward_ctx.doc_norms(0, query_tokens),
+                    );
+                    offset += tokens * embedding_dim;
 
-        scores
+                    if normalized {
+                        (mean(forward, query_tokens) + mean(backward, tokens)) / 2.0
+                    } else {
+                        forward + backward
+                    }
+                })
+                .collect();
+            self.finish_search(&forward_ctx);
+            scores
+        }))
     }
 
-    // Fast path for uniform-length documents
-    fn maxsim_batch_uniform_length(
+    /// MaxSim for a single document, rejecting NaN/Inf and shape errors
+    ///
+    /// Validates like `set_validation(true)` whatever the instance setting; the
+    /// unchecked variants return garbage scores for such inputs instead.
+    #[wasm_bindgen]
+    pub fn maxsim_single_checked(
         &self,
         query_flat: &[f32],
         query_tokens: usize,
         doc_flat: &[f32],
-        doc_infos: &[(usize, usize, usize)],
-        sorted_indices: &[usize],
+        doc_tokens: usize,
         embedding_dim: usize,
         normalized: bool,
-    ) -> Vec<f32> {
-        let num_docs = sorted_indices.len();
-        let mut scores = vec![0.0; doc_infos.len()];
-        let doc_len = doc_infos[sorted_indices[0]].1;
-
-        // Process all documents together without padding
-        let batch_size = 32;
-        for batch_start in (0..num_docs).step_by(batch_size) {
-            let batch_end = (batch_start + batch_size).min(num_docs);
-            let actual_batch_size = batch_end - batch_start;
-
-            self.batch_buffer.borrow_mut().resize(actual_batch_size * doc_len * embedding_dim, 0.0);
-
-            // Copy documents into batch buffer
-            {
-                let mut buffer = self.batch_buffer.borrow_mut();
-                for (batch_idx, &sorted_idx) in sorted_indices[batch_start..batch_end].iter().enumerate() {
-                    let (_, _, doc_offset) = doc_infos[sorted_idx];
-                    let src = &doc_flat[doc_offset..doc_offset + doc_len * embedding_dim];
-                    let dst_offset = batch_idx * doc_len * embedding_dim;
-                    buffer[dst_offset..dst_offset + src.len()].copy_from_slice(src);
-                }
-            }
-
-            // Process batch
-            let buffer = self.batch_buffer.borrow();
-            for (batch_idx, &sorted_idx) in sorted_indices[batch_start..batch_end].iter().enumerate() {
-                let (orig_idx, _, _) = doc_infos[sorted_idx];
-                let doc_start = batch_idx * doc_len * embedding_dim;
-                let doc_slice = &buffer[doc_start..doc_start + doc_len * embedding_dim];
-
-                scores[orig_idx] = self.compute_maxsim_score(
-                    query_flat,
-                    query_tokens,
-                    doc_slice,
-                    doc_len,
-                    embedding_dim,
-                    normalized,
-                );
-            }
-        }
-
-        scores
+    ) -> Result<f32, JsValue> {
+        validate_scoring_inputs(query_flat, query_tokens, doc_flat, &[doc_tokens], embedding_dim)
+            .map_err(|e| JsValue::from_str(&e))?;
+        Ok(self.maxsim_single_impl(query_flat, query_tokens, doc_flat, doc_tokens, embedding_dim, normalized, self.config.metric(), false))
     }
 
-    // Process a batch of variable-length documents with TRUE parallel batching
-    //
-    // WASM-OPTIMIZED STRATEGY:
-    // Unlike official maxsim-cpu (which uses BLAS GEMM for 128 docs at once),
-    // we use cache-aware sub-batching because:
-    // 1. WASM has no BLAS - manual loops benefit from smaller working sets
-    // 2. L2 cache is limited (256KB-1MB) - sub-batches fit better
-    // 3. Single-threaded - no benefit from massive batches
-    //
-    // Sub-batch size tuned for cache locality:
-    // 16 docs × 256 tokens × 13 query × 4 bytes = 213 KB (fits in L2 ✓)
-    fn process_variable_batch(
+    /// MaxSim batch, rejecting NaN/Inf and shape errors (see `maxsim_single_checked()`)
+    #[wasm_bindgen]
+    pub fn maxsim_batch_checked(
         &self,
         query_flat: &[f32],
         query_tokens: usize,
         doc_flat: &[f32],
-        doc_infos: &[(usize, usize, usize)],
-        batch_indices: &[usize],
-        max_len: usize,
+        doc_tokens: &[usize],
         embedding_dim: usize,
         normalized: bool,
-        scores: &mut [f32],
-    ) {
-        let batch_size = batch_indices.len();
+    ) -> Result<Vec<f32>, JsValue> {
+        validate_scoring_inputs(query_flat, query_tokens, doc_flat, doc_tokens, embedding_dim)
+            .map_err(|e| JsValue::from_str(&e))?;
+        self.begin_batch_search(doc_tokens.len(), query_tokens, embedding_dim, self.config.metric(), normalized);
+        let (query_data, query_tokens) = self.prepare_query_unmasked(query_flat, query_tokens, embedding_dim);
+        Ok(self.score_batch_prepared(&query_data, query_tokens, doc_flat, doc_tokens, &[], embedding_dim, normalized, self.config.metric(), None))
+    }
 
-        // Cache-optimized sub-batch size for WASM (empirically tested optimal)
-        // 16 docs: 165ms ✓ BEST
-        // 32 docs: 198ms (cache thrashing)
-        // Conclusion: 16 is the sweet spot for L2 cache
-        const SUB_BATCH_SIZE: usize = 16;
+    /// MaxSim batch using the instance defaults for metric, aggregation and normalization
+    #[wasm_bindgen]
+    pub fn maxsim_batch_default(
+        &self,
+        query_flat: &[f32],
+        query_tokens: usize,
+        doc_flat: &[f32],
+        doc_tokens: &[usize],
+        embedding_dim: usize,
+    ) -> Result<Vec<f32>, JsValue> {
+        self.check_batch_input(query_flat, query_tokens, doc_flat, doc_tokens, embedding_dim)
+            .map_err(|e| JsValue::from_str(&e))?;
+        self.begin_batch_search(doc_tokens.len(), query_tokens, embedding_dim, self.config.metric(), self.config.normalized());
+        let (query_data, query_tokens) = self.prepare_query_unmasked(query_flat, query_tokens, embedding_dim);
+        Ok(self.score_batch_prepared(
+            &query_data,
+            query_tokens,
+            doc_flat,
+            doc_tokens,
+            &[],
+            embedding_dim,
+            self.config.normalized(),
+            self.config.metric(),
+            None,
+        ))
+    }
 
-        // Process in cache-friendly sub-batches
-        let mut i = 0;
-        while i < batch_size {
-            let current_batch_size = (batch_size - i).min(SUB_BATCH_SIZE);
-            let batch_slice = &batch_indices[i..i + current_batch_size];
-
-            // Allocate buffer for this sub-batch
-            let required_size = current_batch_size * max_len * embedding_dim;
-            self.batch_buffer.borrow_mut().resize(required_size, 0.0);
-
-            {
-                let mut buffer = self.batch_buffer.borrow_mut();
-
-                // Selective padding: only clear padding areas (optimization from official)
-                for (batch_idx, &sorted_idx) in batch_slice.iter().enumerate() {
-                    let (_, doc_len, doc_offset) = doc_infos[sorted_idx];
-                    let src_size = doc_len * embedding_dim;
-                    let dst_offset = batch_idx * max_len * embedding_dim;
-
-                    // Copy actual document data
-                    buffer[dst_offset..dst_offset + src_size]
-                        .copy_from_slice(&doc_flat[doc_offset..doc_offset + src_size]);
-
-                    // Clear only the padding area (if needed) - saves memory writes
-                    if doc_len < max_len {
-                        let padding_start = dst_offset + src_size;
-                        let padding_end = dst_offset + max_len * embedding_dim;
-                        buffer[padding_start..padding_end].fill(0.0);
-                    }
-                }
-            }
-
-            // Compute sub-batch
-            let batch_scores = self.compute_maxsim_batch(
-                query_flat,
-                query_tokens,
-                current_batch_size,
-                max_len,
-                embedding_dim,
-                normalized,
-                doc_infos,
-                batch_slice,
-            );
-
-            // Store results
-            for (batch_idx, &sorted_idx) in batch_slice.iter().enumerate() {
-                let (orig_idx, _, _) = doc_infos[sorted_idx];
-                scores[orig_idx] = batch_scores[batch_idx];
-            }
-
-            i += current_batch_size;
-        }
+    /// MaxSim batch that stops early once `abort` is raised
+    ///
+    /// The flag is checked between sub-batches; an aborted search returns an error
+    /// instead of partial scores.
+    #[wasm_bindgen]
+    pub fn maxsim_batch_cancellable(
+        &self,
+        query_flat: &[f32],
+        query_tokens: usize,
+        doc_flat: &[f32],
+        doc_tokens: &[usize],
+        embedding_dim: usize,
+        abort: &AbortFlag,
+    ) -> Result<Vec<f32>, JsValue> {
+        self.run_abortable(abort, || self.maxsim_batch(query_flat, query_tokens, doc_flat, doc_tokens, embedding_dim))
+            .map_err(|e| JsValue::from_str(&e))?
     }
 
-    // Compute MaxSim for multiple documents in a batch with TRUE batched processing
-    // Processes ALL documents TOGETHER in a single pass (not sequentially!)
-    // This allows SIMD vectorization across documents
-    fn compute_maxsim_batch(
+    /// MaxSim batch returning both raw and normalized scores from a single pass
+    ///
+    /// Replaces calling `maxsim_batch` and `maxsim_batch_normalized` separately when
+    /// one is needed for ranking and the other for display.
+    #[wasm_bindgen]
+    pub fn maxsim_batch_fused(
         &self,
         query_flat: &[f32],
         query_tokens: usize,
-        batch_size: usize,
-        max_doc_tokens: usize,
+        doc_flat: &[f32],
+        doc_tokens: &[usize],
         embedding_dim: usize,
-        normalized: bool,
-        doc_infos: &[(usize, usize, usize)],
-        batch_indices: &[usize],
-    ) -> Vec<f32> {
-        let batch_buffer = self.batch_buffer.borrow();
-
-        // Allocate ONE large similarity buffer for ALL documents together
-        // Layout: query_tokens × (batch_size × max_doc_tokens)
-        let sim_size = query_tokens * batch_size * max_doc_tokens;
-        self.similarity_buffer.borrow_mut().resize(sim_size, 0.0);
-
-        // Compute similarities for ALL documents in ONE pass
-        // OPTIMIZATION: Manual unrolling to improve instruction-level parallelism
-        {
-            let mut similarities = self.similarity_buffer.borrow_mut();
-
-            // Outer loop: query tokens (for cache locality)
-            for q_idx in 0..query_tokens {
-                let query_token = &query_flat[q_idx * embedding_dim..(q_idx + 1) * embedding_dim];
-
-                // Process documents in groups of 4 for better ILP
-                let num_full_groups = batch_size / 4;
-
-                // Process 4 documents at a time (unrolled for ILP)
-                for group_idx in 0..num_full_groups {
-                    let base_doc_idx = group_idx * 4;
-
-                    // Get document info for all 4 docs
-                    let (_, len0, _) = doc_infos[batch_indices[base_doc_idx]];
-                    let (_, len1, _) = doc_infos[batch_indices[base_doc_idx + 1]];
-                    let (_, len2, _) = doc_infos[batch_indices[base_doc_idx + 2]];
-                    let (_, len3, _) = doc_infos[batch_indices[base_doc_idx + 3]];
-
-                    let start0 = base_doc_idx * max_doc_tokens * embedding_dim;
-                    let start1 = (base_doc_idx + 1) * max_doc_tokens * embedding_dim;
-                    let start2 = (base_doc_idx + 2) * max_doc_tokens * embedding_dim;
-                    let start3 = (base_doc_idx + 3) * max_doc_tokens * embedding_dim;
-
-                    let min_len = len0.min(len1).min(len2).min(len3);
-
-                    // Process common tokens for all 4 docs together (better ILP)
-                    for doc_tok_idx in 0..min_len {
-                        let tok_offset = doc_tok_idx * embedding_dim;
-
-                        // Compute 4 similarities - CPU can pipeline these!
-                        let sim0 = dot_product(query_token, &batch_buffer[start0 + tok_offset..start0 + tok_offset + embedding_dim]);
-                        let sim1 = dot_product(query_token, &batch_buffer[start1 + tok_offset..start1 + tok_offset + embedding_dim]);
-                        let sim2 = dot_product(query_token, &batch_buffer[start2 + tok_offset..start2 + tok_offset + embedding_dim]);
-                        let sim3 = dot_product(query_token, &batch_buffer[start3 + tok_offset..start3 + tok_offset + embedding_dim]);
-
-                        // Store all 4 results
-                        let base_sim_idx = q_idx * (batch_size * max_doc_tokens) + doc_tok_idx;
-                        similarities[base_sim_idx + base_doc_idx * max_doc_tokens] = sim0;
-                        similarities[base_sim_idx + (base_doc_idx + 1) * max_doc_tokens] = sim1;
-                        similarities[base_sim_idx + (base_doc_idx + 2) * max_doc_tokens] = sim2;
-                        similarities[base_sim_idx + (base_doc_idx + 3) * max_doc_tokens] = sim3;
-                    }
-
-                    // Handle remaining tokens for each doc individually
-                    for (offset, &(len, doc_idx)) in [(len0, base_doc_idx), (len1, base_doc_idx + 1),
-                                                        (len2, base_doc_idx + 2), (len3, base_doc_idx + 3)].iter().enumerate() {
-                        let start = (base_doc_idx + offset) * max_doc_tokens * embedding_dim;
-                        for doc_tok_idx in min_len..len {
-                            let tok_offset = doc_tok_idx * embedding_dim;
-                            let similarity = dot_product(query_token, &batch_buffer[start + tok_offset..start + tok_offset + embedding_dim]);
-
-                            let sim_idx = q_idx * (batch_size * max_doc_tokens) +
-                                         doc_idx * max_doc_tokens +
-                                         doc_tok_idx;
-                            similarities[sim_idx] = similarity;
-                        }
-                    }
-                }
-
-                // Handle remainder documents (< 4)
-                for doc_idx in (num_full_groups * 4)..batch_size {
-                    let (_, actual_doc_len, _) = doc_infos[batch_indices[doc_idx]];
-                    let doc_start = doc_idx * max_doc_tokens * embedding_dim;
-
-                    for doc_tok_idx in 0..actual_doc_len {
-                        let doc_token_start = doc_start + doc_tok_idx * embedding_dim;
-                        let doc_token = &batch_buffer[doc_token_start..doc_token_start + embedding_dim];
-
-                        let similarity = dot_product(query_token, doc_token);
-
-                        let sim_idx = q_idx * (batch_size * max_doc_tokens) +
-                                     doc_idx * max_doc_tokens +
-                                     doc_tok_idx;
-                        similarities[sim_idx] = similarity;
-                    }
-                }
-            }
-        }
-
-        // Compute MaxSim scores for each document
-        let similarities = self.similarity_buffer.borrow();
-        let mut batch_scores = vec![0.0; batch_size];
-
-        for doc_idx in 0..batch_size {
-            let (_, actual_doc_len, _) = doc_infos[batch_indices[doc_idx]];
-            let mut sum_max_sim = 0.0;
+    ) -> Result<FusedScores, JsValue> {
+        self.check_batch_input(query_flat, query_tokens, doc_flat, doc_tokens, embedding_dim)
+            .map_err(|e| JsValue::from_str(&e))?;
+        self.begin_batch_search(doc_tokens.len(), query_tokens, embedding_dim, self.config.metric(), false);
+        let (query_data, query_tokens) = self.prepare_query_unmasked(query_flat, query_tokens, embedding_dim);
+        let raw = self.score_batch_prepared(
+            &query_data,
+            query_tokens,
+            doc_flat,
+            doc_tokens,
+            &[],
+            embedding_dim,
+            false,
+            self.config.metric(),
+            None,
+        );
+        Ok(FusedScores::from_raw(raw, query_tokens))
+    }
 
-            // For each query token, find max similarity across this document's tokens
-            for q_idx in 0..query_tokens {
-                let row_start = q_idx * (batch_size * max_doc_tokens) + doc_idx * max_doc_tokens;
-                let row_end = row_start + actual_doc_len;
+    /// MaxSim batch in two score modes at once, for migrating between them
+    ///
+    /// Both score arrays come from one scoring pass; the second mode costs one division
+    /// per document. Pair with `convert_threshold()` to migrate stored thresholds.
+    #[wasm_bindgen]
+    #[allow(clippy::too_many_arguments)]
+    pub fn maxsim_batch_dual(
+        &self,
+        query_flat: &[f32],
+        query_tokens: usize,
+        doc_flat: &[f32],
+        doc_tokens: &[usize],
+        embedding_dim: usize,
+        previous: ScoreMode,
+        current: ScoreMode,
+    ) -> Result<DualScores, JsValue> {
+        self.check_batch_input(query_flat, query_tokens, doc_flat, doc_tokens, embedding_dim)
+            .map_err(|e| JsValue::from_str(&e))?;
+        self.begin_batch_search(doc_tokens.len(), query_tokens, embedding_dim, self.config.metric(), false);
+        let (query_data, query_tokens) = self.prepare_query_unmasked(query_flat, query_tokens, embedding_dim);
+        let raw = self.score_batch_prepared(
+            &query_data,
+            query_tokens,
+            doc_flat,
+            doc_tokens,
+            &[],
+            embedding_dim,
+            false,
+            self.config.metric(),
+            None,
+        );
+        Ok(DualScores::from_raw(&raw, previous, current, query_tokens))
+    }
 
-                sum_max_sim += simd_max(&similarities[row_start..row_end]);
-            }
+    /// MaxSim batch emitting only documents at or above the score threshold
+    ///
+    /// Uses the instance threshold from `set_score_threshold()` (all documents are
+    /// returned when none is set). Documents that provably cannot reach it skip the
+    /// remaining per-query-token reductions.
+    #[wasm_bindgen]
+    pub fn maxsim_batch_thresholded(
+        &self,
+        query_flat: &[f32],
+        query_tokens: usize,
+        doc_flat: &[f32],
+        doc_tokens: &[usize],
+        embedding_dim: usize,
+    ) -> Result<SearchHits, JsValue> {
+        self.check_batch_input(query_flat, query_tokens, doc_flat, doc_tokens, embedding_dim)
+            .map_err(|e| JsValue::from_str(&e))?;
+        self.begin_batch_search(doc_tokens.len(), query_tokens, embedding_dim, self.config.metric(), false);
+        let (query_data, query_tokens) = self.prepare_query_unmasked(query_flat, query_tokens, embedding_dim);
+        let scores = self.score_batch_prepared(
+            &query_data,
+            query_tokens,
+            doc_flat,
+            doc_tokens,
+            &[],
+            embedding_dim,
+            false,
+            self.config.metric(),
+            self.score_threshold,
+        );
+        Ok(self.threshold_hits(&scores))
+    }
 
-            batch_scores[doc_idx] = if normalized {
-                sum_max_sim / query_tokens as f32
-            } else {
-                sum_max_sim
-            };
-        }
+    /// Score candidates supplied by another retrieval stage, without preloading them;
+    /// returns all candidates best first, with `indices` holding their `candidate_ids`
+    ///
+    /// # Arguments
+    /// * `query_flat` - Flat query embedding (query_tokens × embedding_dim)
+    /// * `query_tokens` - Number of query tokens (the embedding dimension follows)
+    /// * `candidates_flat` - Flat embeddings of the candidates, back to back
+    /// * `candidate_tokens` - Token count of each candidate
+    /// * `candidate_ids` - The caller's ID of each candidate (ties rank by lower ID)
+    ///
+    /// Takes the adaptive batch path of `maxsim_batch()`.
+    #[wasm_bindgen]
+    pub fn rerank_external(
+        &self,
+        query_flat: &[f32],
+        query_tokens: usize,
+        candidates_flat: &[f32],
+        candidate_tokens: &[usize],
+        candidate_ids: &[u32],
+    ) -> Result<SearchHits, JsValue> {
+        self.rerank_external_impl(query_flat, query_tokens, candidates_flat, candidate_tokens, candidate_ids, false)
+            .map_err(|e| JsValue::from_str(&e))
+    }
 
-        batch_scores
+    /// Normalized variant of `rerank_external`
+    #[wasm_bindgen]
+    pub fn rerank_external_normalized(
+        &self,
+        query_flat: &[f32],
+        query_tokens: usize,
+        candidates_flat: &[f32],
+        candidate_tokens: &[usize],
+        candidate_ids: &[u32],
+    ) -> Result<SearchHits, JsValue> {
+        self.rerank_external_impl(query_flat, query_tokens, candidates_flat, candidate_tokens, candidate_ids, true)
+            .map_err(|e| JsValue::from_str(&e))
     }
 
-    // Optimized score computation with buffer reuse
-    fn compute_maxsim_score(
+    fn rerank_external_impl(
         &self,
         query_flat: &[f32],
         query_tokens: usize,
-        doc_slice: &[f32],
-        doc_tokens: usize,
-        embedding_dim: usize,
+        candidates_flat: &[f32],
+        candidate_tokens: &[usize],
+        candidate_ids: &[u32],
         normalized: bool,
-    ) -> f32 {
-        if query_tokens == 0 || doc_tokens == 0 {
-            return 0.0;
-        }
-
-        let sim_size = query_tokens * doc_tokens;
-        self.similarity_buffer.borrow_mut().resize(sim_size, 0.0);
-
-        // Compute similarities using shared buffer
-        {
-            let mut similarities = self.similarity_buffer.borrow_mut();
-            matrix_multiply(
-                query_flat,
-                doc_slice,
-                &mut similarities,
-                query_tokens,
-                doc_tokens,
-                embedding_dim,
-                normalized,
-            );
+    ) -> Result<SearchHits, String> {
+        if query_tokens == 0 || !query_flat.len().is_multiple_of(query_tokens) {
+            return Err(format!("Query length {} is not a multiple of {} tokens", query_flat.len(), query_tokens));
         }
-
-        // Compute max-sim score
-        let similarities = self.similarity_buffer.borrow();
-        let mut sum_max_sim = 0.0;
-        for q_idx in 0..query_tokens {
-            let row_start = q_idx * doc_tokens;
-            let row_end = row_start + doc_tokens;
-            sum_max_sim += simd_max(&similarities[row_start..row_end]);
+        if candidate_ids.len() != candidate_tokens.len() {
+            return Err(format!("Expected {} candidate IDs, got {}", candidate_tokens.len(), candidate_ids.len()));
         }
+        let embedding_dim = query_flat.len() / query_tokens;
+        self.check_batch_input(query_flat, query_tokens, candidates_flat, candidate_tokens, embedding_dim)?;
+        self.begin_batch_search(candidate_tokens.len(), query_tokens, embedding_dim, self.config.metric(), normalized);
+        let (query_data, query_tokens) = self.prepare_query_unmasked(query_flat, query_tokens, embedding_dim);
+        let scores = self.score_batch_prepared(
+            &query_data,
+            query_tokens,
+            candidates_flat,
+            candidate_tokens,
+            &[],
+            embedding_dim,
+            normalized,
+            self.config.metric(),
+            None,
+        );
+        let ids: Vec<usize> = candidate_ids.iter().map(|&id| id as usize).collect();
+        Ok(SearchHits::top_k(&ids, &scores, ids.len()))
+    }
 
-        if normalized {
-            sum_max_sim / query_tokens as f32
-        } else {
-            sum_max_sim
-        }
+    // Start the trace of an adaptive batch search; called before the query is prepared
+    // so the preparation events (query limit) land in this search's trace
+    fn begin_batch_search(&self, num_docs: usize, query_tokens: usize, embedding_dim: usize, metric: Metric, normalized: bool) {
+        self.begin_search(|| format!(
+            "op=batch docs={} query_tokens={} dim={} kernel={} metric={} normalized={}",
+            num_docs, query_tokens, embedding_dim, dot_kernel_name(embedding_dim), metric.name(), normalized
+        ));
     }
 
-    /// Official MaxSim batch uniform: raw sum with dot product
-    #[wasm_bindgen]
-    pub fn maxsim_batch_uniform(
+    // Shared per-call batch path: document preparation (mask, auto-normalize), metric norms,
+    // then the adaptive batch implementation. The query must already be prepared and the
+    // trace started with `begin_batch_search()`.
+    #[allow(clippy::too_many_arguments)]
+    fn score_batch_prepared(
         &self,
-        query_flat: &[f32],
+        query_data: &[f32],
         query_tokens: usize,
         doc_flat: &[f32],
-        num_docs: usize,
-        doc_tokens: usize,
+        doc_tokens: &[usize],
+        doc_mask: &[u8],
         embedding_dim: usize,
+        normalized: bool,
+        metric: Metric,
+        min_score: Option<f32>,
     ) -> Vec<f32> {
-        self.maxsim_batch_uniform_impl(query_flat, query_tokens, doc_flat, num_docs, doc_tokens, embedding_dim, false)
+        self.with_prepared_documents(doc_flat, doc_tokens, doc_mask, embedding_dim, |doc_data, doc_counts| {
+            let doc_norms = call_doc_norms(metric, doc_data, embedding_dim);
+            let ctx = self.score_context(normalized, metric, query_data, Some(&doc_norms), embedding_dim)
+                .with_min_score(min_score, self.auto_normalize);
+            let scores = self.maxsim_batch_impl(query_data, query_tokens, doc_data, doc_counts, embedding_dim, &ctx, None);
+            self.finish_search(&ctx);
+            scores
+        })
     }
 
-    /// Normalized MaxSim batch uniform: averaged with dot product
-    #[wasm_bindgen]
-    pub fn maxsim_batch_uniform_normalized(
+    // Batch path of the official entry points, scored in `official_context()` so
+    // `maxsim_batch` keeps matching the reference implementations
+    fn score_batch_official(
         &self,
-        query_flat: &[f32],
+        query_data: &[f32],
         query_tokens: usize,
         doc_flat: &[f32],
-        num_docs: usize,
-        doc_tokens: usize,
+        doc_tokens: &[usize],
         embedding_dim: usize,
+        normalized: bool,
     ) -> Vec<f32> {
-        self.maxsim_batch_uniform_impl(query_flat, query_tokens, doc_flat, num_docs, doc_tokens, embedding_dim, true)
+        self.with_prepared_documents(doc_flat, doc_tokens, &[], embedding_dim, |doc_data, doc_counts| {
+            let ctx = self.official_context(normalized, query_data, embedding_dim);
+            let scores = self.maxsim_batch_impl(query_data, query_tokens, doc_data, doc_counts, embedding_dim, &ctx, None);
+            self.finish_search(&ctx);
+            scores
+        })
     }
 
-    // Internal implementation
-    fn maxsim_batch_uniform_impl(
+    // Internal batch implementation with adaptive optimization strategy
+    //
+    // OPTIMIZATION STRATEGY:
+    // 1. Sort documents by length for better cache locality
+    // 2. Detect uniform-length docs (≤20% variance) → fast path (no padding)
+    // 3. Variable-length docs → adaptive length-based grouping with tolerance
+    //    - Groups docs within 20-40% of each other (adaptive based on variance)
+    //    - Processes in sub-batches of 16 docs for cache efficiency
+    //    - Uses cache-blocked matrix multiply (adaptive 16/8/4 blocking)
+    //
+    // KEY INSIGHT: All paths use the same optimized compute_maxsim_score with
+    // cache-blocked matrix_multiply for consistent performance
+    #[allow(clippy::too_many_arguments)]
+    fn maxsim_batch_impl(
         &self,
         query_flat: &[f32],
         query_tokens: usize,
         doc_flat: &[f32],
-        num_docs: usize,
-        doc_tokens: usize,
+        doc_tokens: &[usize],
         embedding_dim: usize,
-        normalized: bool,
+        ctx: &ScoreContext,
+        length_order: Option<&[usize]>,  // Precomputed indices sorted by length (skips the sort)
     ) -> Vec<f32> {
-        if num_docs == 0 || query_tokens == 0 || doc_tokens == 0 {
+        let num_docs = doc_tokens.len();
+
+        self.trace.borrow_mut().record(|| format!("batch docs={} presorted={}", num_docs, length_order.is_some()));
+
+        if num_docs == 0 || query_tokens == 0 {
+            self.trace.borrow_mut().record(|| "path=empty".to_string());
             return vec![0.0; num_docs];
         }
 
         let mut scores = vec![0.0; num_docs];
 
-        // Process each document with cache-blocked matrix multiply (same as other optimized paths)
-        for doc_idx in 0..num_docs {
-            let doc_start = doc_idx * doc_tokens * embedding_dim;
-            let doc_end = doc_start + doc_tokens * embedding_dim;
-            let doc_slice = &doc_flat[doc_start..doc_end];
+        // Accuracy mode scores every document on its own with the f64 kernel
+        if ctx.precise() {
+            self.trace.borrow_mut().record(|| "path=precise".to_string());
+            let mut offset = 0;
+            for (score, &len) in scores.iter_mut().zip(doc_tokens) {
+                let doc_slice = &doc_flat[offset..offset + len * embedding_dim];
+                *score = self.compute_maxsim_score(query_flat, query_tokens, doc_slice, len, embedding_dim, ctx, ctx.doc_norms(offset, len));
+                offset += len * embedding_dim;
+            }
+            return scores;
+        }
+
+        // Build document info: (original_index, length, offset)
+        let mut doc_infos: Vec<(usize, usize, usize)> = Vec::with_capacity(num_docs);
+        let mut offset = 0;
+        for (idx, &len) in doc_tokens.iter().enumerate() {
+            doc_infos.push((idx, len, offset));
+            offset += len * embedding_dim;
+        }
+
+        // Sort by document length for better batching (skip if already sorted!)
+        let sorted_indices: Cow<[usize]> = match length_order {
+            // Order precomputed by background maintenance (FAST!)
+            Some(order) => Cow::Borrowed(order),
+            None => {
+                // Need to sort - create sorted index array (slower)
+                let start = profile::stamp();
+                let indices = batch::length_order(doc_tokens);
+                self.profile.borrow_mut().add(Stage::Sort, start);
+                Cow::Owned(indices)
+            }
+        };
+        let sorted_lens: Vec<usize> = sorted_indices.iter().map(|&idx| doc_tokens[idx]).collect();
 
-            scores[doc_idx] = self.compute_maxsim_score(
+        // Fast path: uniform-length documents (≤20% variance and ≥50 docs)
+        let min_len = sorted_lens[0];
+        let max_len = sorted_lens[num_docs - 1];
+        if batch::is_uniform(min_len, max_len, num_docs) {
+            self.trace.borrow_mut().record(|| format!("path=uniform min_len={} max_len={}", min_len, max_len));
+            return self.maxsim_batch_uniform_length(
                 query_flat,
                 query_tokens,
-                doc_slice,
-                doc_tokens,
+                doc_flat,
+                &doc_infos,
+                &sorted_indices,
                 embedding_dim,
-                normalized,
+                ctx,
             );
         }
 
-        scores
-    }
+        // Adaptive batching with length-based grouping (matches official maxsim-cpu:
+        // fixed 20% tolerance, groups of at most 128)
+        self.trace.borrow_mut().record(|| format!("path=variable min_len={} max_len={}", min_len, max_len));
 
-    /// Official MaxSim batch zero-copy: raw sum with dot product
-    #[wasm_bindgen]
-    pub fn maxsim_batch_zero_copy(
-        &mut self,
-        query_ptr: *const f32,
-        query_tokens: usize,
-        doc_ptr: *const f32,
-        doc_tokens_ptr: *const usize,
-        num_docs: usize,
-        embedding_dim: usize,
-    ) -> Vec<f32> {
-        self.maxsim_batch_zero_copy_impl(query_ptr, query_tokens, doc_ptr, doc_tokens_ptr, num_docs, embedding_dim, false)
-    }
+        let mut i = 0;
+        while i < num_docs {
+            if ctx.is_aborted() {
+                self.trace.borrow_mut().record(|| format!("aborted at={}", i));
+                break;
+            }
+            if sorted_lens[i] == 0 {
+                i += 1;
+                continue;
+            }
 
-    /// Normalized MaxSim batch zero-copy: averaged with dot product
-    #[wasm_bindgen]
-    pub fn maxsim_batch_zero_copy_normalized(
-        &mut self,
-        query_ptr: *const f32,
-        query_tokens: usize,
-        doc_ptr: *const f32,
-        doc_tokens_ptr: *const usize,
-        num_docs: usize,
-        embedding_dim: usize,
-    ) -> Vec<f32> {
-        self.maxsim_batch_zero_copy_impl(query_ptr, query_tokens, doc_ptr, doc_tokens_ptr, num_docs, embedding_dim, true)
+            // Docs within 20% tolerance of the group's shortest length (matches official)
+            let batch_end = batch::group_end(&sorted_lens, i);
+            let batch_size = batch_end - i;
+            let batch_max_len = sorted_lens[batch_end - 1];
+            let padded = batch_size >= batch::MIN_PADDED_GROUP;
+
+            self.trace.borrow_mut().record(|| format!(
+                "group start={} end={} max_len={} mode={}",
+                i,
+                batch_end,
+                batch_max_len,
+                if padded { "batched" } else { "individual" }
+            ));
+            ctx.watch_group(|| {
+                let group = &sorted_indices[i..batch_end];
+                watchdog::Group {
+                    doc_ids: group.iter().map(|&idx| ctx.doc_id(idx)).collect(),
+                    lengths: group.iter().map(|&idx| doc_infos[idx].1).collect(),
+                    padded,
+                }
+            });
+
+            // Process batch
+            if !padded {
+                // Too small for batching - process individually
+                for &sorted_idx in &sorted_indices[i..batch_end] {
+                    let (orig_idx, doc_len, doc_offset) = doc_infos[sorted_idx];
+                    let doc_slice = &doc_flat[doc_offset..doc_offset + doc_len * embedding_dim];
+                    scores[orig_idx] = self.compute_maxsim_score(
+                        query_flat,
+                        query_tokens,
+                        doc_slice,
+                        doc_len,
+                        embedding_dim,
+                        ctx,
+                        ctx.doc_norms(doc_offset, doc_len),
+                    );
+                }
+            } else {
+                // Batch process with minimal padding
+                self.process_variable_batch(
+                    query_flat,
+                    query_tokens,
+                    doc_flat,
+                    &doc_infos,
+                    &sorted_indices[i..batch_end],
+                    batch_max_len,
+                    embedding_dim,
+                    ctx,
+                    &mut scores,
+                );
+            }
+
+            i = batch_end;
+        }
+
+        scores
+    }
+
+    // Fast path for uniform-length documents
+    #[allow(clippy::too_many_arguments)]
+    fn maxsim_batch_uniform_length(
+        &self,
+        query_flat: &[f32],
+        query_tokens: usize,
+        doc_flat: &[f32],
+        doc_infos: &[(usize, usize, usize)],
+        sorted_indices: &[usize],
+        embedding_dim: usize,
+        ctx: &ScoreContext,
+    ) -> Vec<f32> {
+        let num_docs = sorted_indices.len();
+        let mut scores = vec![0.0; doc_infos.len()];
+        // Lengths differ by up to 20%: slots are sized for the longest document
+        // (sorted ascending), every document is scored over its own tokens
+        let doc_len = doc_infos[sorted_indices[num_docs - 1]].1;
+
+        // Process all documents together without padding
+        let batch_size = 32;
+        let mut buffer = self.scratch.lend(batch_size.min(num_docs) * doc_len * embedding_dim);
+        for batch_start in (0..num_docs).step_by(batch_size) {
+            if ctx.is_aborted() {
+                self.trace.borrow_mut().record(|| format!("aborted at={}", batch_start));
+                break;
+            }
+            let batch_end = (batch_start + batch_size).min(num_docs);
+            let actual_batch_size = batch_end - batch_start;
+
+            let start = profile::stamp();
+            buffer.resize(actual_batch_size * doc_len * embedding_dim, 0.0);
+
+            // Copy documents into batch buffer
+            for (batch_idx, &sorted_idx) in sorted_indices[batch_start..batch_end].iter().enumerate() {
+                let (_, len, doc_offset) = doc_infos[sorted_idx];
+                let src = &doc_flat[doc_offset..doc_offset + len * embedding_dim];
+                let dst_offset = batch_idx * doc_len * embedding_dim;
+                buffer[dst_offset..dst_offset + src.len()].copy_from_slice(src);
+            }
+            self.profile.borrow_mut().add(Stage::Pack, start);
+
+            // Process batch
+            for (batch_idx, &sorted_idx) in sorted_indices[batch_start..batch_end].iter().enumerate() {
+                let (orig_idx, len, doc_offset) = doc_infos[sorted_idx];
+                let doc_start = batch_idx * doc_len * embedding_dim;
+                let doc_slice = &buffer[doc_start..doc_start + len * embedding_dim];
+
+                scores[orig_idx] = self.compute_maxsim_score(
+                    query_flat,
+                    query_tokens,
+                    doc_slice,
+                    len,
+                    embedding_dim,
+                    ctx,
+                    ctx.doc_norms(doc_offset, len),
+                );
+            }
+        }
+
+        scores
+    }
+
+    // Process a batch of variable-length documents with TRUE parallel batching
+    //
+    // WASM-OPTIMIZED STRATEGY:
+    // Unlike official maxsim-cpu (which uses BLAS GEMM for 128 docs at once),
+    // we use cache-aware sub-batching because:
+    // 1. WASM has no BLAS - manual loops benefit from smaller working sets
+    // 2. L2 cache is limited (256KB-1MB) - sub-batches fit better
+    // 3. Single-threaded - no benefit from massive batches
+    //
+    // Sub-batch size tuned for cache locality (default 16, see tuning.rs):
+    // 16 docs × 256 tokens × 13 query × 4 bytes = 213 KB (fits in L2 ✓)
+    #[allow(clippy::too_many_arguments)]
+    fn process_variable_batch(
+        &self,
+        query_flat: &[f32],
+        query_tokens: usize,
+        doc_flat: &[f32],
+        doc_infos: &[(usize, usize, usize)],
+        batch_indices: &[usize],
+        max_len: usize,
+        embedding_dim: usize,
+        ctx: &ScoreContext,
+        scores: &mut [f32],
+    ) {
+        let batch_size = batch_indices.len();
+
+        // Process in cache-friendly sub-batches
+        let mut buffer = self.scratch.lend(batch_size.min(self.tuning.sub_batch_size) * max_len * embedding_dim);
+        let mut i = 0;
+        while i < batch_size {
+            let current_batch_size = (batch_size - i).min(self.tuning.sub_batch_size);
+            let batch_slice = &batch_indices[i..i + current_batch_size];
+
+            // Allocate buffer for this sub-batch
+            let start = profile::stamp();
+            let required_size = current_batch_size * max_len * embedding_dim;
+            buffer.resize(required_size, 0.0);
+
+            // Selective padding: only clear padding areas (optimization from official)
+            let padding = batch::pad_documents(
+                batch_slice.iter().map(|&sorted_idx| {
+                    let (_, doc_len, doc_offset) = doc_infos[sorted_idx];
+                    &doc_flat[doc_offset..doc_offset + doc_len * embedding_dim]
+                }),
+                max_len,
+                embedding_dim,
+                &mut buffer,
+            );
+            self.profile.borrow_mut().add_padding(padding);
+            self.profile.borrow_mut().add(Stage::Pack, start);
+
+            // Compute sub-batch
+            let batch_scores = self.compute_maxsim_batch(
+                query_flat,
+                query_tokens,
+                &buffer,
+                current_batch_size,
+                max_len,
+                embedding_dim,
+                ctx,
+                doc_infos,
+                batch_slice,
+            );
+
+            // Store results
+            for (batch_idx, &sorted_idx) in batch_slice.iter().enumerate() {
+                let (orig_idx, _, _) = doc_infos[sorted_idx];
+                scores[orig_idx] = batch_scores[batch_idx];
+            }
+
+            i += current_batch_size;
+        }
+    }
+
+    // Compute MaxSim for multiple documents in a batch with TRUE batched processing
+    // Processes ALL documents TOGETHER in a single pass (not sequentially!)
+    // This allows SIMD vectorization across documents
+    #[allow(clippy::too_many_arguments)]
+    fn compute_maxsim_batch(
+        &self,
+        query_flat: &[f32],
+        query_tokens: usize,
+        batch_buffer: &[f32], // Padded documents, batch_size × max_doc_tokens tokens
+        batch_size: usize,
+        max_doc_tokens: usize,
+        embedding_dim: usize,
+        ctx: &ScoreContext,
+        doc_infos: &[(usize, usize, usize)],
+        batch_indices: &[usize],
+    ) -> Vec<f32> {
+        // Allocate ONE large similarity buffer for ALL documents together
+        // Layout: query_tokens × (batch_size × max_doc_tokens)
+        let sim_size = query_tokens * batch_size * max_doc_tokens;
+        let mut similarities = self.scratch.lend(sim_size);
+        similarities.resize(sim_size, 0.0);
+
+        // Compute similarities for ALL documents in ONE pass (four documents at a time)
+        let start = profile::stamp();
+        let lens: Vec<usize> = batch_indices.iter().map(|&idx| doc_infos[idx].1).collect();
+        batch::batch_similarities(
+            query_flat,
+            query_tokens,
+            batch_buffer,
+            &lens,
+            max_doc_tokens,
+            embedding_dim,
+            &mut similarities,
+        );
+        self.profile.borrow_mut().add(Stage::Similarity, start);
+
+        // Compute MaxSim scores for each document
+        let start = profile::stamp();
+        let mut batch_scores = vec![0.0; batch_size];
+
+        for (doc_idx, batch_score) in batch_scores.iter_mut().enumerate() {
+            let (_, actual_doc_len, doc_offset) = doc_infos[batch_indices[doc_idx]];
+
+            // For each query token, find max similarity across this document's tokens
+            *batch_score = ctx.score_document(
+                &mut similarities,
+                |q_idx| q_idx * (batch_size * max_doc_tokens) + doc_idx * max_doc_tokens,
+                actual_doc_len,
+                query_tokens,
+                ctx.doc_norms(doc_offset, actual_doc_len),
+            );
+        }
+        self.profile.borrow_mut().add(Stage::Reduction, start);
+
+        batch_scores
+    }
+
+    // Optimized score computation with buffer reuse
+    #[allow(clippy::too_many_arguments)]
+    fn compute_maxsim_score(
+        &self,
+        query_flat: &[f32],
+        query_tokens: usize,
+        doc_slice: &[f32],
+        doc_tokens: usize,
+        embedding_dim: usize,
+        ctx: &ScoreContext,
+        doc_norms: &[f32],
+    ) -> f32 {
+        if query_tokens == 0 || doc_tokens == 0 {
+            return 0.0;
+        }
+
+        let sim_size = query_tokens * doc_tokens;
+        let mut similarities = self.scratch.lend(sim_size);
+        similarities.resize(sim_size, 0.0);
+
+        // Compute similarities using shared buffer
+        let start = profile::stamp();
+        self.document_similarities(ctx, query_flat, doc_slice, &mut similarities, query_tokens, doc_tokens, embedding_dim);
+        self.profile.borrow_mut().add(Stage::Similarity, start);
+
+        // Compute max-sim score
+        let start = profile::stamp();
+        let score = ctx.score_document(&mut similarities, |q_idx| q_idx * doc_tokens, doc_tokens, query_tokens, doc_norms);
+        self.profile.borrow_mut().add(Stage::Reduction, start);
+        score
+    }
+
+    /// Official MaxSim batch uniform: raw sum with dot product
+    #[wasm_bindgen]
+    pub fn maxsim_batch_uniform(
+        &self,
+        query_flat: &[f32],
+        query_tokens: usize,
+        doc_flat: &[f32],
+        num_docs: usize,
+        doc_tokens: usize,
+        embedding_dim: usize,
+    ) -> Result<Vec<f32>, JsValue> {
+        self.maxsim_batch_uniform_impl(query_flat, query_tokens, doc_flat, num_docs, doc_tokens, embedding_dim, false)
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    /// Normalized MaxSim batch uniform: averaged with dot product
+    #[wasm_bindgen]
+    pub fn maxsim_batch_uniform_normalized(
+        &self,
+        query_flat: &[f32],
+        query_tokens: usize,
+        doc_flat: &[f32],
+        num_docs: usize,
+        doc_tokens: usize,
+        embedding_dim: usize,
+    ) -> Result<Vec<f32>, JsValue> {
+        self.maxsim_batch_uniform_impl(query_flat, query_tokens, doc_flat, num_docs, doc_tokens, embedding_dim, true)
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    // Internal implementation
+    #[allow(clippy::too_many_arguments)]
+    fn maxsim_batch_uniform_impl(
+        &self,
+        query_flat: &[f32],
+        query_tokens: usize,
+        doc_flat: &[f32],
+        num_docs: usize,
+        doc_tokens: usize,
+        embedding_dim: usize,
+        normalized: bool,
+    ) -> Result<Vec<f32>, String> {
+        // Checked before building the per-document counts, so a bogus num_docs cannot allocate
+        if embedding_dim == 0 {
+            return Err("Embedding dimension must be > 0".to_string());
+        }
+        let doc_floats = num_docs.checked_mul(doc_tokens).and_then(|n| n.checked_mul(embedding_dim));
+        if doc_floats != Some(doc_flat.len()) {
+            return Err("Embeddings data size mismatch".to_string());
+        }
+        let doc_counts = vec![doc_tokens; num_docs];
+        self.check_batch_input(query_flat, query_tokens, doc_flat, &doc_counts, embedding_dim)?;
+        if num_docs == 0 || query_tokens == 0 || doc_tokens == 0 {
+            return Ok(vec![0.0; num_docs]);
+        }
+
+        let metric = self.config.metric();
+        self.begin_search(|| format!(
+            "op=batch_uniform docs={} query_tokens={} doc_tokens={} dim={} kernel={} metric={} normalized={}",
+            num_docs, query_tokens, doc_tokens, embedding_dim, dot_kernel_name(embedding_dim), metric.name(), normalized
+        ));
+        let (query_data, query_tokens) = self.prepare_query_unmasked(query_flat, query_tokens, embedding_dim);
+        let mut scores = vec![0.0; num_docs];
+
+        self.with_prepared_documents(doc_flat, &doc_counts, &[], embedding_dim, |doc_data, _| {
+            let doc_norms = call_doc_norms(metric, doc_data, embedding_dim);
+            let ctx = self.score_context(normalized, metric, &query_data, Some(&doc_norms), embedding_dim);
+
+            // Process each document with cache-blocked matrix multiply (same as other optimized paths)
+            for (doc_idx, score) in scores.iter_mut().enumerate() {
+                let doc_start = doc_idx * doc_tokens * embedding_dim;
+                let doc_end = doc_start + doc_tokens * embedding_dim;
+                let doc_slice = &doc_data[doc_start..doc_end];
+
+                *score = self.compute_maxsim_score(
+                    &query_data,
+                    query_tokens,
+                    doc_slice,
+                    doc_tokens,
+                    embedding_dim,
+                    &ctx,
+                    ctx.doc_norms(doc_start, doc_tokens),
+                );
+            }
+            self.finish_search(&ctx);
+        });
+
+        Ok(scores)
+    }
+
+    /// Allocate a zeroed scoring buffer of `floats` floats inside WASM memory
+    ///
+    /// Returns a handle for `write_buffer()`, `buffer_ptr()` and the `*_buffers`
+    /// scoring APIs. Buffers are reusable across calls until `free_buffer()`, so a
+    /// persistent query/document buffer pair costs no allocation per search.
+    #[wasm_bindgen]
+    pub fn alloc_buffer(&mut self, floats: usize) -> u32 {
+        self.buffers.alloc(floats)
+    }
+
+    /// Copy `data` into a buffer starting at float `offset`
+    #[wasm_bindgen]
+    pub fn write_buffer(&mut self, handle: u32, offset: usize, data: &[f32]) -> Result<(), JsValue> {
+        self.buffers.write(handle, offset, data).map_err(|e| JsValue::from_str(&e))
+    }
+
+    /// Address of a buffer in WASM linear memory, for writing through a view
+    ///
+    /// `new Float32Array(wasm_memory().buffer, ptr, floats)` fills the buffer without
+    /// the copy `write_buffer()` makes. Create the view right after this call: any
+    /// later call may grow the memory and detach it. The address is never read back;
+    /// scoring only trusts the handle.
+    #[wasm_bindgen]
+    pub fn buffer_ptr(&mut self, handle: u32) -> Result<*mut f32, JsValue> {
+        let buffer = self.buffers.get_mut(handle).map_err(|e| JsValue::from_str(&e))?;
+        Ok(buffer.as_mut_ptr())
+    }
+
+    /// Release a buffer; false if the handle was unknown
+    #[wasm_bindgen]
+    pub fn free_buffer(&mut self, handle: u32) -> bool {
+        self.buffers.free(handle)
+    }
+
+    /// Official MaxSim batch over WASM-owned buffers: raw sum with dot product
+    ///
+    /// Scores the first `query_tokens × embedding_dim` floats of the query buffer
+    /// against the documents at the start of the document buffer, without copying
+    /// either across the JS boundary. Buffers may be larger than needed.
+    #[wasm_bindgen]
+    pub fn maxsim_batch_buffers(
+        &self,
+        query_buffer: u32,
+        query_tokens: usize,
+        doc_buffer: u32,
+        doc_tokens: &[usize],
+        embedding_dim: usize,
+    ) -> Result<Vec<f32>, JsValue> {
+        self.maxsim_batch_buffers_impl(query_buffer, query_tokens, doc_buffer, doc_tokens, embedding_dim, false)
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    /// Normalized MaxSim batch over WASM-owned buffers: averaged with dot product
+    #[wasm_bindgen]
+    pub fn maxsim_batch_buffers_normalized(
+        &self,
+        query_buffer: u32,
+        query_tokens: usize,
+        doc_buffer: u32,
+        doc_tokens: &[usize],
+        embedding_dim: usize,
+    ) -> Result<Vec<f32>, JsValue> {
+        self.maxsim_batch_buffers_impl(query_buffer, query_tokens, doc_buffer, doc_tokens, embedding_dim, true)
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    // Internal implementation: resolve the handles, then the adaptive batch path
+    fn maxsim_batch_buffers_impl(
+        &self,
+        query_buffer: u32,
+        query_tokens: usize,
+        doc_buffer: u32,
+        doc_tokens: &[usize],
+        embedding_dim: usize,
+        normalized: bool,
+    ) -> Result<Vec<f32>, String> {
+        let query_floats = query_tokens.checked_mul(embedding_dim);
+        let doc_floats = doc_tokens
+            .iter()
+            .try_fold(0usize, |size, &count| count.checked_mul(embedding_dim).and_then(|n| size.checked_add(n)));
+        let query_flat = buffer_prefix(self.buffers.get(query_buffer)?, query_floats, "Query")?;
+        let doc_flat = buffer_prefix(self.buffers.get(doc_buffer)?, doc_floats, "Document")?;
+        self.check_batch_input(query_flat, query_tokens, doc_flat, doc_tokens, embedding_dim)?;
+
+        self.begin_batch_search(doc_tokens.len(), query_tokens, embedding_dim, self.config.metric(), normalized);
+        let (query_data, query_tokens) = self.prepare_query_unmasked(query_flat, query_tokens, embedding_dim);
+        Ok(self.score_batch_prepared(
+            &query_data,
+            query_tokens,
+            doc_flat,
+            doc_tokens,
+            &[],
+            embedding_dim,
+            normalized,
+            self.config.metric(),
+            None,
+        ))
+    }
+
+    /// Official MaxSim batch with attention masks: raw sum with dot product
+    ///
+    /// Masks use one byte per token (non-zero = real token, 0 = padding).
+    /// Padded query tokens are excluded from the sum and padded document tokens
+    /// from the max, so fixed-length model output can be passed in untrimmed.
+    /// Pass an empty mask to treat every token as real.
+    ///
+    /// # Arguments
+    /// * `query_mask` - One entry per query token (or empty)
+    /// * `doc_mask` - One entry per document token across all documents (or empty)
+    #[wasm_bindgen]
+    #[allow(clippy::too_many_arguments)]
+    pub fn maxsim_batch_masked(
+        &self,
+        query_flat: &[f32],
+        query_tokens: usize,
+        query_mask: &[u8],
+        doc_flat: &[f32],
+        doc_tokens: &[usize],
+        doc_mask: &[u8],
+        embedding_dim: usize,
+    ) -> Result<Vec<f32>, JsValue> {
+        self.maxsim_batch_masked_impl(query_flat, query_tokens, query_mask, doc_flat, doc_tokens, doc_mask, embedding_dim, false)
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    /// Normalized MaxSim batch with attention masks: averaged over unmasked query tokens
+    #[wasm_bindgen]
+    #[allow(clippy::too_many_arguments)]
+    pub fn maxsim_batch_masked_normalized(
+        &self,
+        query_flat: &[f32],
+        query_tokens: usize,
+        query_mask: &[u8],
+        doc_flat: &[f32],
+        doc_tokens: &[usize],
+        doc_mask: &[u8],
+        embedding_dim: usize,
+    ) -> Result<Vec<f32>, JsValue> {
+        self.maxsim_batch_masked_impl(query_flat, query_tokens, query_mask, doc_flat, doc_tokens, doc_mask, embedding_dim, true)
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    // Internal implementation: compact away masked tokens, then reuse the adaptive batch path
+    // Compaction happens once per call inside WASM memory and also tightens the length
+    // grouping, since documents are grouped by their real (unpadded) token counts
+    #[allow(clippy::too_many_arguments)]
+    fn maxsim_batch_masked_impl(
+        &self,
+        query_flat: &[f32],
+        query_tokens: usize,
+        query_mask: &[u8],
+        doc_flat: &[f32],
+        doc_tokens: &[usize],
+        doc_mask: &[u8],
+        embedding_dim: usize,
+        normalized: bool,
+    ) -> Result<Vec<f32>, String> {
+        if query_flat.len() != query_tokens * embedding_dim {
+            return Err("Query size mismatch".to_string());
+        }
+
+        let total_doc_tokens: usize = doc_tokens.iter().sum();
+        if doc_flat.len() != total_doc_tokens * embedding_dim {
+            return Err("Document data size mismatch".to_string());
+        }
+
+        if !doc_mask.is_empty() && doc_mask.len() != total_doc_tokens {
+            return Err(format!(
+                "Document mask length {} does not match total document tokens {}",
+                doc_mask.len(),
+                total_doc_tokens
+            ));
+        }
+
+        self.begin_batch_search(doc_tokens.len(), query_tokens, embedding_dim, self.config.metric(), normalized);
+        let (query_data, active_query_tokens) =
+            self.prepare_query_tokens(query_flat, query_tokens, query_mask, embedding_dim)?;
+
+        Ok(self.score_batch_prepared(
+            &query_data,
+            active_query_tokens,
+            doc_flat,
+            doc_tokens,
+            doc_mask,
+            embedding_dim,
+            normalized,
+            self.config.metric(),
+            None,
+        ))
+    }
+
+    #[wasm_bindgen]
+    pub fn get_info(&self) -> String {
+        format!(
+            "MaxSim WASM v0.5.0 (SIMD: {}, adaptive_batching: true, buffer_reuse: true, methods: maxsim + maxsim_normalized + preloading)",
+            cfg!(target_feature = "simd128")
+        )
+    }
+
+    /// Load and store document embeddings in WASM memory
+    /// This eliminates per-search conversion overhead (following FastPlaid's pattern)
+    ///
+    /// # Arguments
+    /// * `embeddings_data` - Flat array of all document embeddings concatenated
+    /// * `doc_tokens` - Array of token counts for each document
+    /// * `embedding_dim` - Embedding dimension
+    ///
+    /// # Example
+    /// For 3 documents with [128, 256, 192] tokens each at dim=48:
+    /// - embeddings_data.len() = (128 + 256 + 192) * 48 = 27,648
+    /// - doc_tokens = [128, 256, 192]
+    #[wasm_bindgen]
+    pub fn load_documents(
+        &mut self,
+        embeddings_data: &[f32],
+        doc_tokens: &[usize],
+        embedding_dim: usize,
+    ) -> Result<(), JsValue> {
+        self.check_documents_input(embeddings_data, doc_tokens, embedding_dim).map_err(|e| JsValue::from_str(&e))?;
+
+        // Store documents in original order, copied page by page (no giant allocation)
+        // Searches sort each page by length until run_maintenance() has stored its order
+        let preloaded = PreloadedDocuments::from_flat(embeddings_data, doc_tokens, embedding_dim);
+        self.install_documents(preloaded);
+        Ok(())
+    }
+
+    /// Load documents, taking ownership of the embeddings array
+    ///
+    /// Same as `load_documents()`, but the array JS passes in is moved into the paged
+    /// store and released page by page rather than copied, so a large corpus is never
+    /// held twice in WASM memory.
+    #[wasm_bindgen]
+    pub fn load_documents_owned(
+        &mut self,
+        embeddings_data: Vec<f32>,
+        doc_tokens: Vec<usize>,
+        embedding_dim: usize,
+    ) -> Result<(), JsValue> {
+        self.check_documents_input(&embeddings_data, &doc_tokens, embedding_dim).map_err(|e| JsValue::from_str(&e))?;
+        self.install_documents(PreloadedDocuments::from_vec(embeddings_data, &doc_tokens, embedding_dim));
+        Ok(())
+    }
+
+    /// Load documents, detecting exact and near-duplicate documents (see dedup.rs)
+    ///
+    /// Returns, for every input document, the input index of the first document it
+    /// duplicates (its own index when it is the first of its kind). With
+    /// `skip_duplicates` only those first documents are stored, in input order, so
+    /// stored document `k` is the `k`-th input mapped to itself; otherwise every
+    /// document is stored and the mapping lets the app collapse duplicate hits.
+    ///
+    /// # Arguments
+    /// * `embeddings_data` - Flat array of all document embeddings
+    /// * `doc_tokens` - Token count for each document
+    /// * `embedding_dim` - Embedding dimension
+    /// * `threshold` - Symmetric cosine MaxSim (in (0, 1]) from which two documents are
+    ///   near-duplicates; 1 detects exact copies only
+    /// * `skip_duplicates` - Store only the first document of every kind
+    #[wasm_bindgen]
+    pub fn load_documents_dedup(
+        &mut self,
+        embeddings_data: &[f32],
+        doc_tokens: &[usize],
+        embedding_dim: usize,
+        threshold: f32,
+        skip_duplicates: bool,
+    ) -> Result<Vec<u32>, JsValue> {
+        self.load_documents_dedup_impl(embeddings_data, doc_tokens, embedding_dim, threshold, skip_duplicates)
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    fn load_documents_dedup_impl(
+        &mut self,
+        embeddings_data: &[f32],
+        doc_tokens: &[usize],
+        embedding_dim: usize,
+        threshold: f32,
+        skip_duplicates: bool,
+    ) -> Result<Vec<u32>, String> {
+        self.check_documents_input(embeddings_data, doc_tokens, embedding_dim)?;
+        if !(threshold > 0.0 && threshold <= 1.0) {
+            return Err("threshold must be in (0, 1]".to_string());
+        }
+
+        let mut rng = self.config.rng(rng::STREAM_DEDUP);
+        let canonical = dedup::find_duplicates(embeddings_data, doc_tokens, embedding_dim, threshold, &mut rng);
+        let preloaded = if skip_duplicates {
+            let mut preloaded = PreloadedDocuments::new(embedding_dim);
+            let mut offset = 0;
+            for (doc_idx, &tokens) in doc_tokens.iter().enumerate() {
+                if canonical[doc_idx] == doc_idx {
+                    preloaded.push_document(&embeddings_data[offset..offset + tokens * embedding_dim]);
+                }
+                offset += tokens * embedding_dim;
+            }
+            preloaded.finish();
+            preloaded
+        } else {
+            PreloadedDocuments::from_flat(embeddings_data, doc_tokens, embedding_dim)
+        };
+        self.check_store_input(&preloaded)?;
+        self.install_documents(preloaded);
+        Ok(canonical.into_iter().map(|doc_idx| doc_idx as u32).collect())
+    }
+
+    /// Load documents with a metadata tag per document, for filtered search
+    ///
+    /// Tags are u32 bitmasks (e.g. one bit per category or access group) matched by
+    /// `search_preloaded_filtered`. Any later load without metadata clears them.
+    ///
+    /// # Arguments
+    /// * `embeddings_data` - Flat array of all document embeddings
+    /// * `doc_tokens` - Array of token counts for each document
+    /// * `doc_tags` - Tag bitmask of each document
+    /// * `embedding_dim` - Embedding dimension
+    #[wasm_bindgen]
+    pub fn load_documents_with_metadata(
+        &mut self,
+        embeddings_data: &[f32],
+        doc_tokens: &[usize],
+        doc_tags: &[u32],
+        embedding_dim: usize,
+    ) -> Result<(), JsValue> {
+        if doc_tags.len() != doc_tokens.len() {
+            return Err(JsValue::from_str("Document tags length mismatch"));
+        }
+        self.load_documents(embeddings_data, doc_tokens, embedding_dim)?;
+        self.doc_tags = doc_tags.to_vec();
+        Ok(())
+    }
+
+    /// Load documents with a group per document (e.g. the file a passage was chunked
+    /// from), for `search_grouped()`
+    ///
+    /// Any later load without groups clears them.
+    ///
+    /// # Arguments
+    /// * `embeddings_data` - Flat array of all document embeddings
+    /// * `doc_tokens` - Array of token counts for each document
+    /// * `doc_groups` - Group id of each document (any u32, not necessarily dense)
+    /// * `embedding_dim` - Embedding dimension
+    #[wasm_bindgen]
+    pub fn load_documents_grouped(
+        &mut self,
+        embeddings_data: &[f32],
+        doc_tokens: &[usize],
+        doc_groups: &[u32],
+        embedding_dim: usize,
+    ) -> Result<(), JsValue> {
+        if doc_groups.len() != doc_tokens.len() {
+            return Err(JsValue::from_str("Document groups length mismatch"));
+        }
+        self.load_documents(embeddings_data, doc_tokens, embedding_dim)?;
+        self.doc_groups = doc_groups.to_vec();
+        Ok(())
+    }
+
+    /// Load long documents split into chunks, for `search_chunked()`
+    ///
+    /// The chunks are stored (and searchable) as documents of their own, in order;
+    /// `doc_chunks` says how many consecutive chunks make up each long document. Any
+    /// later load without chunks clears the grouping.
+    ///
+    /// # Arguments
+    /// * `embeddings_data` - Flat array of all chunk embeddings
+    /// * `chunk_tokens` - Token count of each chunk
+    /// * `doc_chunks` - Chunk count of each long document (summing to the chunk count)
+    /// * `embedding_dim` - Embedding dimension
+    #[wasm_bindgen]
+    pub fn load_documents_chunked(
+        &mut self,
+        embeddings_data: &[f32],
+        chunk_tokens: &[usize],
+        doc_chunks: &[usize],
+        embedding_dim: usize,
+    ) -> Result<(), JsValue> {
+        let total_chunks = doc_chunks.iter().try_fold(0usize, |total, &chunks| total.checked_add(chunks));
+        if total_chunks != Some(chunk_tokens.len()) {
+            return Err(JsValue::from_str("Document chunk counts don't add up to the number of chunks"));
+        }
+        self.load_documents(embeddings_data, chunk_tokens, embedding_dim)?;
+        self.doc_chunks = doc_chunks.to_vec();
+        Ok(())
+    }
+
+    /// Allocate a staging buffer of `total_floats` floats for `commit_documents()`
+    ///
+    /// Returns its address in WASM linear memory, so JS can write the embeddings in
+    /// place (e.g. `new Float32Array(wasm_memory().buffer, ptr, total_floats).set(...)`
+    /// straight from a fetched `ArrayBuffer`) instead of passing a copy to
+    /// `load_documents()`. Create the view right after this call: any later call may
+    /// grow the memory and detach it. Replaces an uncommitted earlier buffer.
+    #[wasm_bindgen]
+    pub fn alloc_document_buffer(&mut self, total_floats: usize) -> *mut f32 {
+        self.document_buffer = vec![0.0; total_floats];
+        self.document_buffer.as_mut_ptr()
+    }
+
+    /// Load the documents written to the `alloc_document_buffer()` buffer
+    ///
+    /// The buffer is moved into the paged store and released as it goes, so the
+    /// corpus is never held twice in WASM memory.
+    ///
+    /// # Arguments
+    /// * `doc_tokens` - Array of token counts for each document
+    /// * `embedding_dim` - Embedding dimension
+    #[wasm_bindgen]
+    pub fn commit_documents(&mut self, doc_tokens: &[usize], embedding_dim: usize) -> Result<(), JsValue> {
+        self.check_documents_input(&self.document_buffer, doc_tokens, embedding_dim).map_err(|e| JsValue::from_str(&e))?;
+        let embeddings = std::mem::take(&mut self.document_buffer);
+        self.install_documents(PreloadedDocuments::from_vec(embeddings, doc_tokens, embedding_dim));
+        Ok(())
+    }
+
+    /// Load documents together with a per-token attention mask
+    ///
+    /// Padded tokens (mask = 0) are dropped once at load time, so they never
+    /// take part in any later search and cost no memory.
+    ///
+    /// # Arguments
+    /// * `embeddings_data` - Flat array of all (padded) document embeddings
+    /// * `doc_tokens` - Token count for each document, including padding
+    /// * `token_mask` - One entry per token across all documents (non-zero = keep)
+    /// * `embedding_dim` - Embedding dimension
+    #[wasm_bindgen]
+    pub fn load_documents_masked(
+        &mut self,
+        embeddings_data: &[f32],
+        doc_tokens: &[usize],
+        token_mask: &[u8],
+        embedding_dim: usize,
+    ) -> Result<(), JsValue> {
+        self.load_documents_masked_impl(embeddings_data, doc_tokens, token_mask, embedding_dim)
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    /// Load documents keeping only the `max_tokens` most informative tokens of each
+    ///
+    /// Tokens are ranked by `token_weights` (one per token across all documents, e.g.
+    /// IDF of the token ids) or, when it is empty, by embedding norm, which only
+    /// carries salience for models that don't L2-normalize their output. Kept tokens
+    /// stay in document order; shorter documents are stored whole. Pruning long
+    /// documents to a few dozen tokens cuts memory and scan time several-fold, usually
+    /// at a small quality cost (check with `ExperimentRunner`).
+    ///
+    /// # Arguments
+    /// * `embeddings_data` - Flat array of all document embeddings
+    /// * `doc_tokens` - Token count for each document
+    /// * `embedding_dim` - Embedding dimension
+    /// * `max_tokens` - Tokens kept per document (> 0)
+    /// * `token_weights` - Importance of every token, or empty to rank by norm
+    #[wasm_bindgen]
+    pub fn load_documents_pruned(
+        &mut self,
+        embeddings_data: &[f32],
+        doc_tokens: &[usize],
+        embedding_dim: usize,
+        max_tokens: usize,
+        token_weights: &[f32],
+    ) -> Result<(), JsValue> {
+        self.check_documents_input(embeddings_data, doc_tokens, embedding_dim).map_err(|e| JsValue::from_str(&e))?;
+        let mask = prune_mask(embeddings_data, doc_tokens, embedding_dim, max_tokens, token_weights)
+            .map_err(|e| JsValue::from_str(&e))?;
+        self.load_documents_masked_impl(embeddings_data, doc_tokens, &mask, embedding_dim)
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    fn load_documents_masked_impl(
+        &mut self,
+        embeddings_data: &[f32],
+        doc_tokens: &[usize],
+        token_mask: &[u8],
+        embedding_dim: usize,
+    ) -> Result<(), String> {
+        check_documents(embeddings_data, doc_tokens, embedding_dim)?;
+
+        let total_tokens: usize = doc_tokens.iter().sum();
+        if token_mask.len() != total_tokens {
+            return Err("Token mask length mismatch".to_string());
+        }
+
+        // Compact one document at a time straight into the paged store
+        let mut preloaded = PreloadedDocuments::new(embedding_dim);
+        let mut compacted = Vec::new();
+        let mut token_offset = 0;
+        for &tokens in doc_tokens {
+            let doc_range = token_offset * embedding_dim..(token_offset + tokens) * embedding_dim;
+            let mask_range = token_offset..token_offset + tokens;
+            compact_tokens(&embeddings_data[doc_range], &[tokens], &token_mask[mask_range], embedding_dim, &mut compacted);
+            preloaded.push_document(&compacted);
+            token_offset += tokens;
+        }
+        preloaded.finish();
+        self.check_store_input(&preloaded)?;
+        self.install_documents(preloaded);
+        Ok(())
+    }
+
+    /// Load documents from a safetensors buffer, without a JS-side decode
+    ///
+    /// The tensor is read straight into the paged store (F32, F16 or BF16). It is
+    /// either flat, `[total_tokens, dim]` with documents back to back, or padded,
+    /// `[num_docs, max_tokens, dim]`, in which case only the first `doc_tokens[i]`
+    /// rows of each document are kept.
+    ///
+    /// # Arguments
+    /// * `bytes` - The whole `.safetensors` file
+    /// * `tensor_name` - Name of the embeddings tensor in the file header
+    /// * `doc_tokens` - Token count for each document
+    #[wasm_bindgen]
+    pub fn load_documents_safetensors(
+        &mut self,
+        bytes: &[u8],
+        tensor_name: &str,
+        doc_tokens: &[usize],
+    ) -> Result<(), JsValue> {
+        if doc_tokens.is_empty() {
+            return Err(JsValue::from_str("No documents to load"));
+        }
+
+        let preloaded = formats::safetensors::read_documents(bytes, tensor_name, doc_tokens)
+            .map_err(|e| JsValue::from_str(&e))?;
+        self.check_store_input(&preloaded).map_err(|e| JsValue::from_str(&e))?;
+        self.install_documents(preloaded);
+        Ok(())
+    }
+
+    /// Load documents from a NumPy `.npy` buffer (`numpy.save`)
+    ///
+    /// The array is f16, f32 or f64, either flat `[total_tokens, dim]` or padded
+    /// `[num_docs, max_tokens, dim]`, as in `load_documents_safetensors()`.
+    ///
+    /// # Arguments
+    /// * `bytes` - The whole `.npy` file
+    /// * `doc_tokens` - Token count for each document
+    #[wasm_bindgen]
+    pub fn load_documents_npy(&mut self, bytes: &[u8], doc_tokens: &[usize]) -> Result<(), JsValue> {
+        if doc_tokens.is_empty() {
+            return Err(JsValue::from_str("No documents to load"));
+        }
+
+        let preloaded = formats::numpy::read_documents(bytes, doc_tokens)
+            .map_err(|e| JsValue::from_str(&e))?;
+        self.check_store_input(&preloaded).map_err(|e| JsValue::from_str(&e))?;
+        self.install_documents(preloaded);
+        Ok(())
+    }
+
+    /// Load documents from a NumPy `.npz` archive (`numpy.savez`, uncompressed)
+    ///
+    /// # Arguments
+    /// * `bytes` - The whole `.npz` file
+    /// * `embeddings_name` - Array holding the embeddings (as in `load_documents_npy()`)
+    /// * `doclens_name` - 1-D integer array holding the token count of each document
+    #[wasm_bindgen]
+    pub fn load_documents_npz(&mut self, bytes: &[u8], embeddings_name: &str, doclens_name: &str) -> Result<(), JsValue> {
+        let preloaded = formats::numpy::read_npz_documents(bytes, embeddings_name, doclens_name)
+            .map_err(|e| JsValue::from_str(&e))?;
+        self.check_store_input(&preloaded).map_err(|e| JsValue::from_str(&e))?;
+        self.install_documents(preloaded);
+        Ok(())
+    }
+
+    /// Start streaming a corpus in chunks (e.g. straight from a fetch() body reader)
+    ///
+    /// Chunks are appended to the paged store as they arrive, so the whole corpus never
+    /// has to exist as one Float32Array in JS. Previously loaded documents stay
+    /// searchable until `finish_load()` swaps the new corpus in.
+    ///
+    /// # Arguments
+    /// * `embedding_dim` - Embedding dimension
+    /// * `on_progress` - Optional callback invoked as `(bytes_loaded, docs_loaded)` after each chunk
+    #[wasm_bindgen]
+    pub fn begin_load(
+        &mut self,
+        embedding_dim: usize,
+        on_progress: Option<js_sys::Function>,
+    ) -> Result<(), JsValue> {
+        if embedding_dim == 0 {
+            return Err(JsValue::from_str("Embedding dimension must be > 0"));
+        }
+
+        self.streaming_load = Some(StreamingLoad {
+            documents: PreloadedDocuments::new(embedding_dim),
+            bytes_loaded: 0,
+            on_progress,
+        });
+        Ok(())
+    }
+
+    /// Append one chunk of whole documents to the streaming load
+    ///
+    /// # Arguments
+    /// * `embeddings_data` - Flat embeddings of the documents in this chunk
+    /// * `doc_tokens` - Token count for each document in this chunk
+    #[wasm_bindgen]
+    pub fn load_chunk(&mut self, embeddings_data: &[f32], doc_tokens: &[usize]) -> Result<(), JsValue> {
+        let load = self.streaming_load.as_mut()
+            .ok_or_else(|| JsValue::from_str("No load in progress. Call begin_load() first."))?;
+        let embedding_dim = load.documents.embedding_dim;
+
+        let expected_size: usize = doc_tokens.iter().map(|&count| count * embedding_dim).sum();
+        if embeddings_data.len() != expected_size {
+            return Err(JsValue::from_str("Embeddings data size mismatch"));
+        }
+
+        let mut offset = 0;
+        for &tokens in doc_tokens {
+            let len = tokens * embedding_dim;
+            load.documents.push_document(&embeddings_data[offset..offset + len]);
+            offset += len;
+        }
+        load.bytes_loaded += std::mem::size_of_val(embeddings_data);
+
+        if let Some(callback) = &load.on_progress {
+            callback.call2(
+                &JsValue::NULL,
+                &JsValue::from_f64(load.bytes_loaded as f64),
+                &JsValue::from_f64(load.documents.num_docs() as f64),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Finish the streaming load and replace the preloaded documents with it
+    /// Returns the number of documents loaded
+    #[wasm_bindgen]
+    pub fn finish_load(&mut self) -> Result<usize, JsValue> {
+        let load = self.streaming_load.take()
+            .ok_or_else(|| JsValue::from_str("No load in progress. Call begin_load() first."))?;
+        if load.documents.num_docs() == 0 {
+            return Err(JsValue::from_str("No documents to load"));
+        }
+
+        let mut preloaded = load.documents;
+        preloaded.finish();
+        self.check_store_input(&preloaded).map_err(|e| JsValue::from_str(&e))?;
+        let num_docs = preloaded.num_docs();
+        self.install_documents(preloaded);
+        Ok(num_docs)
+    }
+
+    /// Start assembling a query from a streaming encoder, one token at a time
+    ///
+    /// Reuses the query buffer of earlier streaming queries. While tokens arrive,
+    /// `streaming_query_candidates()` already ranks ANN clusters against the tokens so
+    /// far; `finish_query()` scores the assembled query.
+    ///
+    /// # Arguments
+    /// * `max_tokens` - Maximum number of query tokens
+    /// * `embedding_dim` - Embedding dimension (must match the preloaded documents)
+    #[wasm_bindgen]
+    pub fn begin_streaming_query(&mut self, max_tokens: usize, embedding_dim: usize) -> Result<(), JsValue> {
+        if max_tokens == 0 || embedding_dim == 0 {
+            return Err(JsValue::from_str("max_tokens and embedding_dim must be > 0"));
+        }
+
+        let query = &mut self.streaming_query;
+        query.tokens.clear();
+        query.tokens.reserve(max_tokens * embedding_dim);
+        query.token_sum.clear();
+        query.token_sum.resize(embedding_dim, 0.0);
+        query.max_tokens = max_tokens;
+        query.embedding_dim = embedding_dim;
+        query.active = true;
+        Ok(())
+    }
+
+    /// Append one query token (embedding_dim floats); returns the number of tokens so far
+    #[wasm_bindgen]
+    pub fn push_query_token(&mut self, token: &[f32]) -> Result<usize, JsValue> {
+        let query = &mut self.streaming_query;
+        if !query.active {
+            return Err(JsValue::from_str("No query in progress. Call begin_streaming_query() first."));
+        }
+        if token.len() != query.embedding_dim {
+            return Err(JsValue::from_str(&format!(
+                "Query token has {} values, expected {}", token.len(), query.embedding_dim
+            )));
+        }
+        let start = query.tokens.len();
+        if start / query.embedding_dim >= query.max_tokens {
+            return Err(JsValue::from_str(&format!("Query already has max_tokens={} tokens", query.max_tokens)));
+        }
+
+        query.tokens.extend_from_slice(token);
+        if self.auto_normalize {
+            normalize_tokens(&mut query.tokens[start..], query.embedding_dim);
+        }
+        for (sum, &value) in query.token_sum.iter_mut().zip(&query.tokens[start..]) {
+            *sum += value;
+        }
+        Ok(query.tokens.len() / query.embedding_dim)
+    }
+
+    /// First-stage scan over the tokens pushed so far: documents of the `nprobe` ANN
+    /// clusters nearest to the pooled partial query (ascending), e.g. to prefetch them
+    #[wasm_bindgen]
+    pub fn streaming_query_candidates(&self, nprobe: usize) -> Result<Vec<u32>, JsValue> {
+        let query = &self.streaming_query;
+        if !query.active || query.tokens.is_empty() {
+            return Err(JsValue::from_str("No query tokens yet. Call push_query_token() first."));
+        }
+        let docs_ref = self.documents.borrow();
+        let docs = docs_ref.as_ref()
+            .ok_or_else(|| JsValue::from_str("No documents loaded. Call load_documents() first."))?;
+        if docs.embedding_dim != query.embedding_dim {
+            return Err(JsValue::from_str("Query dimension mismatch"));
+        }
+        let ann = self.ann.as_ref()
+            .ok_or_else(|| JsValue::from_str("No ANN index. Call set_ann_clusters() first."))?;
+
+        Ok(ann.candidates_pooled(&query.token_sum, nprobe).into_iter().map(|doc| doc as u32).collect())
+    }
+
+    /// Finish the streaming query and score it against the preloaded documents
+    /// (same output as `search_preloaded()`)
+    #[wasm_bindgen]
+    pub fn finish_query(&mut self) -> Result<Vec<f32>, JsValue> {
+        if !std::mem::take(&mut self.streaming_query.active) {
+            return Err(JsValue::from_str("No query in progress. Call begin_streaming_query() first."));
+        }
+
+        let query = &self.streaming_query;
+        let query_tokens = query.tokens.len() / query.embedding_dim;
+        self.search_preloaded_impl(&query.tokens, query_tokens, &[], self.config.normalized(), self.config.metric(), None)
+    }
+
+    /// Start scoring documents streamed in chunks against `query_flat`, without preloading
+    ///
+    /// The query is prepared once (stopmask, query limit, auto-normalization) and
+    /// reused by every `score_chunk()`, so documents can be scored as they arrive from
+    /// the network or IndexedDB and dropped afterwards. Metric and normalization are
+    /// the instance defaults at this call. Replaces any stream search in progress.
+    ///
+    /// # Arguments
+    /// * `query_flat` - Flat query embedding (query_tokens × embedding_dim)
+    /// * `query_tokens` - Number of query tokens
+    #[wasm_bindgen]
+    pub fn begin_stream_search(&mut self, query_flat: &[f32], query_tokens: usize) -> Result<(), JsValue> {
+        self.begin_stream_search_impl(query_flat, query_tokens).map_err(|e| JsValue::from_str(&e))
+    }
+
+    fn begin_stream_search_impl(&mut self, query_flat: &[f32], query_tokens: usize) -> Result<(), String> {
+        if query_tokens == 0 || query_flat.is_empty() || !query_flat.len().is_multiple_of(query_tokens) {
+            return Err("Query cannot be empty and must hold query_tokens × embedding_dim values".to_string());
+        }
+        let embedding_dim = query_flat.len() / query_tokens;
+        self.check_query_input(query_flat, query_tokens, embedding_dim)?;
+        let (query, query_tokens) = self.prepare_query_tokens(query_flat, query_tokens, &[], embedding_dim)?;
+        self.stream_search = Some(StreamSearch {
+            query: query.into_owned(),
+            query_tokens,
+            embedding_dim,
+            normalized: self.config.normalized(),
+            metric: self.config.metric(),
+            docs_scored: 0,
+        });
+        Ok(())
+    }
+
+    /// Score one chunk of documents against the stream query (one score per document,
+    /// chunk order; same scores as `maxsim_batch_default()` over the chunk)
+    ///
+    /// # Arguments
+    /// * `doc_flat` - Flat embeddings of the chunk's documents, back to back
+    /// * `doc_tokens` - Token count of each document in the chunk
+    #[wasm_bindgen]
+    pub fn score_chunk(&mut self, doc_flat: &[f32], doc_tokens: &[usize]) -> Result<Vec<f32>, JsValue> {
+        self.score_chunk_impl(doc_flat, doc_tokens).map_err(|e| JsValue::from_str(&e))
+    }
+
+    fn score_chunk_impl(&mut self, doc_flat: &[f32], doc_tokens: &[usize]) -> Result<Vec<f32>, String> {
+        let stream = self.stream_search.as_ref()
+            .ok_or("No stream search in progress. Call begin_stream_search() first.")?;
+        self.check_documents_input(doc_flat, doc_tokens, stream.embedding_dim)?;
+        self.begin_batch_search(doc_tokens.len(), stream.query_tokens, stream.embedding_dim, stream.metric, stream.normalized);
+        let scores = self.score_batch_prepared(
+            &stream.query,
+            stream.query_tokens,
+            doc_flat,
+            doc_tokens,
+            &[],
+            stream.embedding_dim,
+            stream.normalized,
+            stream.metric,
+            None,
+        );
+        if let Some(stream) = self.stream_search.as_mut() {
+            stream.docs_scored += doc_tokens.len();
+        }
+        Ok(scores)
+    }
+
+    /// Finish the stream search and release its query; returns the number of documents scored
+    #[wasm_bindgen]
+    pub fn end_stream_search(&mut self) -> Result<usize, JsValue> {
+        self.stream_search.take()
+            .map(|stream| stream.docs_scored)
+            .ok_or_else(|| JsValue::from_str("No stream search in progress. Call begin_stream_search() first."))
+    }
+
+    /// Serialize the preloaded documents into a compact, versioned binary index
+    ///
+    /// The blob (header + doc_tokens + flat f32 embeddings) can be cached in IndexedDB
+    /// or served as a static file and restored with `load_index()`.
+    #[wasm_bindgen]
+    pub fn serialize_index(&self) -> Result<Vec<u8>, JsValue> {
+        self.serialize_index_impl(formats::index::Encoding::F32)
+    }
+
+    /// Serialize the preloaded documents with int8 embeddings (per-token scale)
+    /// About 4× smaller than `serialize_index()`; embeddings are dequantized on load
+    #[wasm_bindgen]
+    pub fn serialize_index_quantized(&self) -> Result<Vec<u8>, JsValue> {
+        self.serialize_index_impl(formats::index::Encoding::Int8)
+    }
+
+    fn serialize_index_impl(&self, encoding: formats::index::Encoding) -> Result<Vec<u8>, JsValue> {
+        let docs_ref = self.documents.borrow();
+        let docs = docs_ref.as_ref()
+            .ok_or_else(|| JsValue::from_str("No documents loaded. Call load_documents() first."))?;
+        Ok(formats::index::serialize(docs, encoding))
+    }
+
+    /// Replace the preloaded documents with a serialized index from `serialize_index()`
+    ///
+    /// A blob that can't be read throws an `Error` named after the failure:
+    /// `NotAnIndex`, `UnsupportedVersion` (written by a newer release), `UnsupportedEncoding`,
+    /// `CorruptHeader` (including a truncated blob) or `ChecksumMismatch` (damaged bytes;
+    /// the message names the block). Nothing is replaced in that case.
+    #[wasm_bindgen]
+    pub fn load_index(&mut self, bytes: &[u8]) -> Result<(), JsValue> {
+        self.load_index_impl(bytes, None)
+    }
+
+    /// `load_index()` for an index that must have `embedding_dim` dimensions (the query
+    /// encoder's); any other dimension throws a `DimMismatch` error
+    #[wasm_bindgen]
+    pub fn load_index_checked(&mut self, bytes: &[u8], embedding_dim: usize) -> Result<(), JsValue> {
+        self.load_index_impl(bytes, Some(embedding_dim))
+    }
+
+    fn load_index_impl(&mut self, bytes: &[u8], embedding_dim: Option<usize>) -> Result<(), JsValue> {
+        let (preloaded, fingerprint) = formats::index::read_index(bytes, embedding_dim).map_err(formats::index::js_error)?;
+        self.check_store_input(&preloaded).map_err(|e| JsValue::from_str(&e))?;
+        self.install_documents(preloaded);
+        self.index_fingerprint = Some(fingerprint);
+        Ok(())
+    }
+
+    /// Fingerprint of the loaded index (see `maxsim_core::delta`), or undefined when the
+    /// documents weren't loaded with `load_index()`
+    ///
+    /// Send it with update requests so the server can answer with the matching delta.
+    #[wasm_bindgen(getter)]
+    pub fn index_fingerprint(&self) -> Option<u32> {
+        self.index_fingerprint
+    }
+
+    /// Update the index loaded with `load_index()` by a delta from `maxsim-cli delta`
+    /// (or `maxsim_web.index_delta` in Python): the documents it removes are dropped
+    /// and the ones it adds appended, as in the updated index it was made from
+    ///
+    /// A delta made for another base index throws a `BaseMismatch` error; other
+    /// failures are named like `load_index()`'s, and worded for the delta when its own
+    /// header is at fault. Document tags are cleared, as with any load. Counts as one
+    /// `"delta"` mutation for `generation()`.
+    #[wasm_bindgen]
+    pub fn apply_index_delta(&mut self, bytes: &[u8]) -> Result<(), JsValue> {
+        let delta = Delta::parse(bytes)
+            .map_err(|e| formats::index::artifact_js_error("index delta", maxsim_core::delta::DELTA_VERSION, e))?;
+        let (updated, fingerprint) = self.apply_index_delta_impl(&delta).map_err(formats::index::js_error)?;
+        self.check_store_input(&updated).map_err(|e| JsValue::from_str(&e))?;
+        self.place_documents(Some(updated));
+        self.index_fingerprint = Some(fingerprint);
+        self.bump_generation("delta");
+        Ok(())
+    }
+
+    fn apply_index_delta_impl(&self, delta: &Delta) -> Result<(PreloadedDocuments, u32), IndexError> {
+        let docs_ref = self.documents.borrow();
+        let docs = match (self.index_fingerprint, docs_ref.as_deref()) {
+            (Some(loaded), Some(docs)) if loaded == delta.base => docs,
+            (loaded, _) => return Err(IndexError::BaseMismatch { base: delta.base, loaded }),
+        };
+        let (added, _) = formats::index::read_index(delta.added, Some(docs.embedding_dim))?;
+        if delta.removed.last().is_some_and(|&doc_idx| doc_idx >= docs.num_docs()) {
+            return Err(IndexError::CorruptHeader("delta removes documents past the end of the index".to_string()));
+        }
+        Ok((docs.spliced(&delta.removed, &added), delta.result))
+    }
+
+    /// Snapshot of the engine state: preloaded documents, their tags, groups and
+    /// chunking, the calibrated blocking parameters and the residual, PQ and 4-bit
+    /// corpora (see snapshot.rs)
+    ///
+    /// Transfer the returned buffer to a new worker (or cache it) and restore it with
+    /// `import_state()` instead of reloading and re-encoding the corpus.
+    #[wasm_bindgen]
+    pub fn export_state(&self) -> Vec<u8> {
+        let mut state = snapshot::StateWriter::new();
+        if let Some(docs) = self.documents.borrow().as_deref() {
+            state.section(snapshot::DOCUMENTS, |out| out.bytes(&formats::index::serialize(docs, formats::index::Encoding::F32)));
+        }
+        if !self.doc_tags.is_empty() {
+            state.section(snapshot::TAGS, |out| out.u32s(&self.doc_tags));
+        }
+        if !self.doc_groups.is_empty() {
+            state.section(snapshot::GROUPS, |out| out.u32s(&self.doc_groups));
+        }
+        if !self.doc_chunks.is_empty() {
+            state.section(snapshot::CHUNKS, |out| out.usizes(&self.doc_chunks));
+        }
+        if let Some(fingerprint) = self.index_fingerprint {
+            state.section(snapshot::FINGERPRINT, |out| out.u64(fingerprint as usize));
+        }
+        state.section(snapshot::TUNING, |out| self.tuning.write_state(out));
+        if let Some(residual) = &self.residual_documents {
+            state.section(snapshot::RESIDUAL, |out| residual.write_state(out));
+        }
+        if let Some(pq) = &self.pq_documents {
+            state.section(snapshot::PQ, |out| pq.write_state(out));
+        }
+        if let Some(int4) = &self.int4_documents {
+            state.section(snapshot::INT4, |out| int4.write_state(out));
+        }
+        state.finish()
+    }
+
+    /// Replace the stored corpora and blocking parameters with a snapshot from
+    /// `export_state()`; parts absent from the snapshot are cleared
+    ///
+    /// The snapshot is fully parsed and checked first: on error the instance is left
+    /// unchanged. A snapshot that can't be read throws an `Error` named like
+    /// `load_index()`'s (`NotAnIndex`, `UnsupportedVersion`, `CorruptHeader`). Counts as
+    /// one `"restore"` mutation for `generation()`.
+    #[wasm_bindgen]
+    pub fn import_state(&mut self, bytes: &[u8]) -> Result<(), JsValue> {
+        let state = snapshot::EngineState::read(bytes)
+            .map_err(|e| formats::index::artifact_js_error("engine state snapshot", snapshot::VERSION, e))?;
+        self.import_state_impl(state).map_err(|e| JsValue::from_str(&e))
+    }
+
+    fn import_state_impl(&mut self, state: snapshot::EngineState) -> Result<(), String> {
+        if let Some(docs) = &state.documents {
+            self.check_store_input(docs)?;
+        }
+        self.place_documents(state.documents);
+        self.index_fingerprint = state.fingerprint;
+        self.doc_tags = state.doc_tags;
+        self.doc_groups = state.doc_groups;
+        self.doc_chunks = state.doc_chunks;
+        self.tuning = state.tuning;
+        self.residual_documents = state.residual;
+        self.pq_documents = state.pq;
+        self.int4_documents = state.int4;
+        self.bump_generation("restore");
+        Ok(())
+    }
+
+    /// Search preloaded documents with a query
+    /// Returns MaxSim scores for all documents
+    ///
+    /// # Arguments
+    /// * `query_flat` - Flat query embedding (query_tokens × embedding_dim)
+    /// * `query_tokens` - Number of query tokens
+    ///
+    /// # Returns
+    /// Float32Array of MaxSim scores (one per document)
+    #[wasm_bindgen]
+    pub fn search_preloaded(
+        &self,
+        query_flat: &[f32],
+        query_tokens: usize,
+    ) -> Result<Vec<f32>, JsValue> {
+        self.search_preloaded_impl(query_flat, query_tokens, &[], false, self.config.metric(), None)
+    }
+
+    /// Search preloaded documents with normalized MaxSim scores
+    #[wasm_bindgen]
+    pub fn search_preloaded_normalized(
+        &self,
+        query_flat: &[f32],
+        query_tokens: usize,
+    ) -> Result<Vec<f32>, JsValue> {
+        self.search_preloaded_impl(query_flat, query_tokens, &[], true, self.config.metric(), None)
+    }
+
+    /// One page of the ranking of the preloaded documents, best first
+    ///
+    /// The full ranking of the latest query is cached, so "load more" requests
+    /// (same query, next offset) only slice it instead of rescanning the corpus.
+    /// The cache is dropped when the query, the corpus (see `generation()`), the
+    /// query preparation settings or the instance config change.
+    ///
+    /// # Arguments
+    /// * `query_flat` - Flat query embedding (query_tokens × embedding_dim)
+    /// * `query_tokens` - Number of query tokens
+    /// * `offset` - Rank of the first hit (0-based)
+    /// * `limit` - Maximum number of hits (0 = all remaining)
+    #[wasm_bindgen]
+    pub fn search_preloaded_paged(
+        &self,
+        query_flat: &[f32],
+        query_tokens: usize,
+        offset: usize,
+        limit: usize,
+    ) -> Result<SearchHits, JsValue> {
+        self.search_paged_impl(query_flat, query_tokens, offset, limit, false)
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    /// Normalized variant of `search_preloaded_paged`
+    #[wasm_bindgen]
+    pub fn search_preloaded_paged_normalized(
+        &self,
+        query_flat: &[f32],
+        query_tokens: usize,
+        offset: usize,
+        limit: usize,
+    ) -> Result<SearchHits, JsValue> {
+        self.search_paged_impl(query_flat, query_tokens, offset, limit, true)
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    fn search_paged_impl(
+        &self,
+        query_flat: &[f32],
+        query_tokens: usize,
+        offset: usize,
+        limit: usize,
+        normalized: bool,
+    ) -> Result<SearchHits, String> {
+        let mut cache = self.paged_results.borrow_mut();
+        let cached = cache.as_ref().is_some_and(|paged| {
+            paged.query == query_flat
+                && paged.query_tokens == query_tokens
+                && paged.normalized == normalized
+                && paged.generation == self.generation
+                && paged.query_settings == self.query_settings
+                && paged.config == self.config
+        });
+
+        if cached {
+            let num_docs = cache.as_ref().map_or(0, |paged| paged.ranking.length());
+            self.begin_search(|| format!("op=paged docs={} normalized={}", num_docs, normalized));
+            self.trace.borrow_mut().record(|| format!("paged cache=hit offset={} limit={}", offset, limit));
+        } else {
+            let scores = {
+                let docs_ref = self.documents.borrow();
+                let docs = docs_ref.as_ref()
+                    .ok_or_else(|| "No documents loaded. Call load_documents() first.".to_string())?;
+                self.search_store(docs, query_flat, query_tokens, &[], normalized, self.config.metric(), None)?
+            };
+            let all: Vec<usize> = (0..scores.len()).collect();
+            *cache = Some(PagedResults {
+                query: query_flat.to_vec(),
+                query_tokens,
+                normalized,
+                generation: self.generation,
+                query_settings: self.query_settings,
+                config: self.config,
+                ranking: SearchHits::top_k(&all, &scores, scores.len()).with_degradation(self.last_query_degradation()),
+            });
+            self.trace.borrow_mut().record(|| format!("paged cache=miss offset={} limit={}", offset, limit));
+        }
+
+        Ok(cache.as_ref().map(|paged| paged.ranking.page(offset, limit)).unwrap_or_default())
+    }
+
+    /// Search preloaded documents, returning the top `k` as ranked result objects
+    ///
+    /// Array of `{id, index, score, rank}` objects sorted by descending score (see
+    /// `SearchHits.to_objects()`), replacing the zip-sort-slice step in JS.
+    ///
+    /// # Arguments
+    /// * `query_flat` - Flat query embedding (query_tokens × embedding_dim)
+    /// * `query_tokens` - Number of query tokens
+    /// * `k` - Number of results (0 = all documents)
+    /// * `ids` - Optional caller ids per document, reported as `id` (default: the index)
+    #[wasm_bindgen]
+    pub fn search_preloaded_ranked(
+        &self,
+        query_flat: &[f32],
+        query_tokens: usize,
+        k: usize,
+        ids: Option<js_sys::Array>,
+    ) -> Result<js_sys::Array, JsValue> {
+        let scores = self.search_preloaded_impl(query_flat, query_tokens, &[], false, self.config.metric(), None)?;
+        ranked_objects(&scores, k, ids)
+    }
+
+    /// Normalized variant of `search_preloaded_ranked`
+    #[wasm_bindgen]
+    pub fn search_preloaded_ranked_normalized(
+        &self,
+        query_flat: &[f32],
+        query_tokens: usize,
+        k: usize,
+        ids: Option<js_sys::Array>,
+    ) -> Result<js_sys::Array, JsValue> {
+        let scores = self.search_preloaded_impl(query_flat, query_tokens, &[], true, self.config.metric(), None)?;
+        ranked_objects(&scores, k, ids)
+    }
+
+    /// Search preloaded documents with a padded query and its attention mask
+    /// Masked query tokens (mask = 0) are excluded from the score
+    #[wasm_bindgen]
+    pub fn search_preloaded_masked(
+        &self,
+        query_flat: &[f32],
+        query_tokens: usize,
+        query_mask: &[u8],
+    ) -> Result<Vec<f32>, JsValue> {
+        self.search_preloaded_impl(query_flat, query_tokens, query_mask, false, self.config.metric(), None)
+    }
+
+    /// Search preloaded documents with a padded query, normalized over unmasked query tokens
+    #[wasm_bindgen]
+    pub fn search_preloaded_masked_normalized(
+        &self,
+        query_flat: &[f32],
+        query_tokens: usize,
+        query_mask: &[u8],
+    ) -> Result<Vec<f32>, JsValue> {
+        self.search_preloaded_impl(query_flat, query_tokens, query_mask, true, self.config.metric(), None)
+    }
+
+    /// Search preloaded documents with an explicit similarity metric
+    /// Overrides the instance metric set with `set_metric()` for this call only
+    #[wasm_bindgen]
+    pub fn search_preloaded_with_metric(
+        &self,
+        query_flat: &[f32],
+        query_tokens: usize,
+        metric: Metric,
+        normalized: bool,
+    ) -> Result<Vec<f32>, JsValue> {
+        self.search_preloaded_impl(query_flat, query_tokens, &[], normalized, metric, None)
+    }
+
+    /// Search preloaded documents without blocking the UI thread
+    ///
+    /// Returns a Promise resolving to the Float32Array of scores. At most `chunk_docs`
+    /// documents are scored per step and the search yields to the event loop between
+    /// steps. It scores a snapshot of the corpus taken at call time, so documents
+    /// reloaded while it runs don't affect it.
+    #[wasm_bindgen]
+    pub fn search_preloaded_async(
+        &self,
+        query_flat: Vec<f32>,
+        query_tokens: usize,
+        chunk_docs: usize,
+    ) -> Result<js_sys::Promise, JsValue> {
+        self.search_preloaded_async_impl(query_flat, query_tokens, chunk_docs, None)
+    }
+
+    /// Cancellable variant of `search_preloaded_async`
+    /// The Promise rejects with "Search aborted" once `abort.abort()` is called
+    #[wasm_bindgen]
+    pub fn search_preloaded_async_cancellable(
+        &self,
+        query_flat: Vec<f32>,
+        query_tokens: usize,
+        chunk_docs: usize,
+        abort: &AbortFlag,
+    ) -> Result<js_sys::Promise, JsValue> {
+        self.search_preloaded_async_impl(query_flat, query_tokens, chunk_docs, Some(abort.clone()))
+    }
+
+    fn search_preloaded_async_impl(
+        &self,
+        query_flat: Vec<f32>,
+        query_tokens: usize,
+        chunk_docs: usize,
+        abort: Option<AbortFlag>,
+    ) -> Result<js_sys::Promise, JsValue> {
+        let docs = self.documents.borrow().clone()
+            .ok_or_else(|| JsValue::from_str("No documents loaded. Call load_documents() first."))?;
+
+        let engine = self.scoring_snapshot();
+        *engine.active_abort.borrow_mut() = abort.clone();
+        let mut search = cooperative::ChunkedSearch::new(
+            engine,
+            docs,
+            &query_flat,
+            query_tokens,
+            false,
+            self.config.metric(),
+            chunk_docs,
+        )
+        .map_err(|e| JsValue::from_str(&e))?;
+
+        let aborted = move || abort.as_ref().is_some_and(|flag| flag.aborted());
+        Ok(wasm_bindgen_futures::future_to_promise(async move {
+            while !aborted() && search.step() {
+                cooperative::yield_to_event_loop().await?;
+            }
+            if aborted() {
+                return Err(JsValue::from_str("Search aborted"));
+            }
+            Ok(js_sys::Float32Array::from(&search.into_scores()[..]).into())
+        }))
+    }
+
+    /// Search preloaded documents, stopping early once `abort` is raised
+    /// (checked between pages and sub-batches)
+    #[wasm_bindgen]
+    pub fn search_preloaded_cancellable(
+        &self,
+        query_flat: &[f32],
+        query_tokens: usize,
+        abort: &AbortFlag,
+    ) -> Result<Vec<f32>, JsValue> {
+        self.run_abortable(abort, || self.search_preloaded(query_flat, query_tokens))
+            .map_err(|e| JsValue::from_str(&e))?
+    }
+
+    /// Search preloaded documents returning both raw and normalized scores from one pass
+    #[wasm_bindgen]
+    pub fn search_preloaded_fused(
+        &self,
+        query_flat: &[f32],
+        query_tokens: usize,
+    ) -> Result<FusedScores, JsValue> {
+        let (raw, active_tokens) = self.search_preloaded_raw(query_flat, query_tokens).map_err(|e| JsValue::from_str(&e))?;
+        Ok(FusedScores::from_raw(raw, active_tokens))
+    }
+
+    /// Per-query-token maxima of every preloaded document, u8-quantized
+    ///
+    /// Each value is one (active) query token's contribution to a document score, i.e.
+    /// what MaxSim sums. One byte per value in a k-major layout (see `TokenMaxima`)
+    /// keeps the payload small enough to transfer for thousands of documents.
+    #[wasm_bindgen]
+    pub fn search_preloaded_token_maxima(
+        &self,
+        query_flat: &[f32],
+        query_tokens: usize,
+    ) -> Result<TokenMaxima, JsValue> {
+        let docs_ref = self.documents.borrow();
+        let docs = docs_ref.as_ref()
+            .ok_or_else(|| JsValue::from_str("No documents loaded. Call load_documents() first."))?;
+
+        self.token_maxima_impl(docs, query_flat, query_tokens)
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    fn token_maxima_impl(
+        &self,
+        docs: &PreloadedDocuments,
+        query_flat: &[f32],
+        query_tokens: usize,
+    ) -> Result<TokenMaxima, String> {
+        let (maxima, active_query_tokens) = self.token_maxima_raw(docs, query_flat, query_tokens, "token_maxima")?;
+        Ok(TokenMaxima::quantize(&maxima, active_query_tokens, docs.num_docs()))
+    }
+
+    // Per-query-token maxima of every preloaded document, k-major, with the active
+    // query token count; `op` names the search in the trace
+    fn token_maxima_raw(
+        &self,
+        docs: &PreloadedDocuments,
+        query_flat: &[f32],
+        query_tokens: usize,
+        op: &str,
+    ) -> Result<(Vec<f32>, usize), String> {
+        let dim = docs.embedding_dim;
+        self.check_query_input(query_flat, query_tokens, dim)?;
+
+        self.begin_search(|| format!(
+            "op={} docs={} query_tokens={} dim={} kernel={} metric={}",
+            op, docs.num_docs(), query_tokens, dim, dot_kernel_name(dim), self.config.metric().name()
+        ));
+        let (query_data, active_query_tokens) = self.prepare_query_tokens(query_flat, query_tokens, &[], dim)?;
+        let maxima = self.token_maxima_prepared(docs, &query_data, active_query_tokens);
+        Ok((maxima, active_query_tokens))
+    }
+
+    // Per-query-token maxima of every preloaded document for an already prepared query
+    fn token_maxima_prepared(&self, docs: &PreloadedDocuments, query_data: &[f32], active_query_tokens: usize) -> Vec<f32> {
+        let dim = docs.embedding_dim;
+        let metric = self.config.metric();
+        let ctx = self.score_context(false, metric, query_data, None, dim);
+
+        // k-major: maxima[k * num_docs + doc]; empty documents contribute 0
+        let num_docs = docs.num_docs();
+        let mut maxima = vec![0.0; active_query_tokens * num_docs];
+        let mut similarities = self.scratch.lend(active_query_tokens * docs.max_doc_tokens());
+        for doc_idx in 0..num_docs {
+            let (embeddings, token_norms) = docs.document(doc_idx);
+            let doc_tokens = token_norms.len();
+            if doc_tokens == 0 {
+                continue;
+            }
+
+            similarities.resize(active_query_tokens * doc_tokens, 0.0);
+            self.document_similarities(&ctx, query_data, embeddings, &mut similarities, active_query_tokens, doc_tokens, dim);
+            for (q_idx, row) in similarities.chunks_exact_mut(doc_tokens).enumerate() {
+                maxima[q_idx * num_docs + doc_idx] = ctx.row_score(row, q_idx, token_norms);
+            }
+        }
+        self.finish_search(&ctx);
+        maxima
+    }
+
+    /// Score of every chunked document (see `load_documents_chunked()`), merging the
+    /// scores of its chunks by `merge` (see chunks.rs)
+    ///
+    /// # Arguments
+    /// * `query_flat` - Flat query embedding (query_tokens × embedding_dim)
+    /// * `query_tokens` - Number of query tokens
+    /// * `merge` - Best chunk, best window of chunks, or the whole document
+    /// * `window` - Consecutive chunks per window (`BestWindow` only)
+    ///
+    /// Every chunk is scored once, whatever the policy.
+    #[wasm_bindgen]
+    pub fn search_chunked(&self, query_flat: &[f32], query_tokens: usize, merge: ChunkMerge, window: usize) -> Result<Vec<f32>, JsValue> {
+        self.search_chunked_impl(query_flat, query_tokens, merge, window, false)
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    /// Normalized variant of `search_chunked`
+    #[wasm_bindgen]
+    pub fn search_chunked_normalized(
+        &self,
+        query_flat: &[f32],
+        query_tokens: usize,
+        merge: ChunkMerge,
+        window: usize,
+    ) -> Result<Vec<f32>, JsValue> {
+        self.search_chunked_impl(query_flat, query_tokens, merge, window, true)
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    fn search_chunked_impl(
+        &self,
+        query_flat: &[f32],
+        query_tokens: usize,
+        merge: ChunkMerge,
+        window: usize,
+        normalized: bool,
+    ) -> Result<Vec<f32>, String> {
+        if self.doc_chunks.is_empty() {
+            return Err("No chunked documents loaded. Call load_documents_chunked() first.".to_string());
+        }
+        if merge == ChunkMerge::BestWindow && window == 0 {
+            return Err("window must be at least 1".to_string());
+        }
+        let docs_ref = self.documents.borrow();
+        let docs = docs_ref.as_ref().ok_or("No documents loaded. Call load_documents() first.")?;
+        let (maxima, active_query_tokens) = self.token_maxima_raw(docs, query_flat, query_tokens, "chunked")?;
+        let mut scores = chunks::merge_chunks(&maxima, active_query_tokens, docs.doc_tokens(), &self.doc_chunks, merge, window);
+        if normalized {
+            scores.iter_mut().for_each(|score| *score = mean(*score, active_query_tokens));
+        }
+        Ok(scores)
+    }
+
+    /// Score of every preloaded document against several variants of one query (e.g.
+    /// paraphrases or a hypothetical answer), merged by `aggregation` (see variants.rs)
+    ///
+    /// # Arguments
+    /// * `variants_flat` - The variants' embeddings back to back (Σ tokens × embedding_dim)
+    /// * `variant_token_counts` - Number of tokens of every variant
+    /// * `aggregation` - Best variant, mean over variants, or reciprocal rank fusion
+    ///
+    /// The corpus is scanned once for all variants; every variant's score is finished
+    /// (normalization, `set_score_norm()`) like a `search_preloaded()` score before merging.
+    #[wasm_bindgen]
+    pub fn search_multi_variant(
+        &self,
+        variants_flat: &[f32],
+        variant_token_counts: &[usize],
+        aggregation: VariantAggregation,
+    ) -> Result<Vec<f32>, JsValue> {
+        self.search_multi_variant_impl(variants_flat, variant_token_counts, aggregation, false)
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    /// Normalized variant of `search_multi_variant` (every variant's score is averaged
+    /// over its own tokens before merging)
+    #[wasm_bindgen]
+    pub fn search_multi_variant_normalized(
+        &self,
+        variants_flat: &[f32],
+        variant_token_counts: &[usize],
+        aggregation: VariantAggregation,
+    ) -> Result<Vec<f32>, JsValue> {
+        self.search_multi_variant_impl(variants_flat, variant_token_counts, aggregation, true)
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    fn search_multi_variant_impl(
+        &self,
+        variants_flat: &[f32],
+        variant_token_counts: &[usize],
+        aggregation: VariantAggregation,
+        normalized: bool,
+    ) -> Result<Vec<f32>, String> {
+        let docs_ref = self.documents.borrow();
+        let docs = docs_ref.as_ref().ok_or("No documents loaded. Call load_documents() first.")?;
+        let dim = docs.embedding_dim;
+        if variant_token_counts.contains(&0) {
+            return Err("Every query variant needs at least one token".to_string());
+        }
+        self.check_query_input(variants_flat, variant_token_counts.iter().sum(), dim)?;
+
+        let metric = self.config.metric();
+        let num_docs = docs.num_docs();
+        self.begin_search(|| format!(
+            "op=multi_variant docs={} variants={} query_tokens={} dim={} kernel={} metric={} normalized={}",
+            num_docs, variant_token_counts.len(), variant_token_counts.iter().sum::<usize>(), dim,
+            dot_kernel_name(dim), metric.name(), normalized
+        ));
+
+        // Prepare every variant on its own
+        let mut variants = Vec::with_capacity(variant_token_counts.len());
+        let mut offset = 0;
+        for &tokens in variant_token_counts {
+            variants.push(self.prepare_query_tokens(&variants_flat[offset * dim..(offset + tokens) * dim], tokens, &[], dim)?);
+            offset += tokens;
+        }
+        let contexts: Vec<ScoreContext> = variants
+            .iter()
+            .map(|(query_data, _)| self.score_context(normalized, metric, query_data, None, dim))
+            .collect();
+
+        // One pass over the corpus, every document scored against each variant in turn;
+        // scores are variant-major, empty documents score 0
+        let max_tokens = variants.iter().map(|&(_, tokens)| tokens).max().unwrap_or(0);
+        let mut scores = vec![0.0; variants.len() * num_docs];
+        let mut similarities = self.scratch.lend(max_tokens * docs.max_doc_tokens());
+        for doc_idx in 0..num_docs {
+            let (embeddings, token_norms) = docs.document(doc_idx);
+            let doc_tokens = token_norms.len();
+            if doc_tokens == 0 {
+                continue;
+            }
+            for (variant, ((query_data, tokens), ctx)) in variants.iter().zip(&contexts).enumerate() {
+                if *tokens == 0 {
+                    continue;
+                }
+                similarities.resize(tokens * doc_tokens, 0.0);
+                self.document_similarities(ctx, query_data, embeddings, &mut similarities, *tokens, doc_tokens, dim);
+                scores[variant * num_docs + doc_idx] =
+                    ctx.score_document(&mut similarities, |q_idx| q_idx * doc_tokens, doc_tokens, *tokens, token_norms);
+            }
+        }
+        contexts.iter().for_each(|ctx| self.finish_search(ctx));
+        Ok(variants::aggregate_variants(&scores, num_docs, aggregation))
+    }
+
+    /// MaxSim between every pair of the given preloaded documents, as a flat matrix
+    ///
+    /// Entry `i * n + j` (n = `indices.len()`) scores document `indices[i]` as the
+    /// query against document `indices[j]`; with `symmetric` it is the symmetric
+    /// score of `maxsim_symmetric()`, so the matrix is symmetric too. Reuses the
+    /// search embeddings for clustering and near-duplicate detection. Uses the
+    /// instance metric and aggregation; the query stopmask, query limit and length
+    /// penalty don't apply. Costs n² document-pair scorings.
+    #[wasm_bindgen]
+    pub fn doc_pairwise_maxsim(&self, indices: &[usize], symmetric: bool) -> Result<Vec<f32>, JsValue> {
+        self.doc_pairwise_impl(indices, symmetric, false).map_err(|e| JsValue::from_str(&e))
+    }
+
+    /// Normalized variant of `doc_pairwise_maxsim` (averaged over query tokens; the
+    /// symmetric score averages both directions)
+    #[wasm_bindgen]
+    pub fn doc_pairwise_maxsim_normalized(&self, indices: &[usize], symmetric: bool) -> Result<Vec<f32>, JsValue> {
+        self.doc_pairwise_impl(indices, symmetric, true).map_err(|e| JsValue::from_str(&e))
+    }
+
+    fn doc_pairwise_impl(&self, indices: &[usize], symmetric: bool, normalized: bool) -> Result<Vec<f32>, String> {
+        let docs_ref = self.documents.borrow();
+        let docs = docs_ref.as_ref()
+            .ok_or_else(|| "No documents loaded. Call load_documents() first.".to_string())?;
+        if let Some(&doc) = indices.iter().find(|&&doc| doc >= docs.num_docs()) {
+            return Err(format!("Document index {} out of range ({} documents)", doc, docs.num_docs()));
+        }
+
+        let dim = docs.embedding_dim;
+        let metric = self.config.metric();
+        self.begin_search(|| format!(
+            "op=pairwise docs={} dim={} kernel={} metric={} symmetric={} normalized={}",
+            indices.len(), dim, dot_kernel_name(dim), metric.name(), symmetric, normalized
+        ));
+
+        // Raw one-directional scores first: raw[i * n + j] = MaxSim(indices[i] → indices[j])
+        let n = indices.len();
+        let mut raw = vec![0.0; n * n];
+        for (i, &query_doc) in indices.iter().enumerate() {
+            let (query, _) = docs.document(query_doc);
+            let query_tokens = docs.doc_tokens()[query_doc];
+            let ctx = self.score_context(false, metric, query, None, dim).with_score_norm(ScoreNorm::None);
+            for (j, &doc) in indices.iter().enumerate() {
+                let (embeddings, token_norms) = docs.document(doc);
+                raw[i * n + j] = self.compute_maxsim_score(query, query_tokens, embeddings, token_norms.len(), dim, &ctx, token_norms);
+            }
+            self.finish_search(&ctx);
+        }
+
+        let tokens: Vec<usize> = indices.iter().map(|&doc| docs.doc_tokens()[doc]).collect();
+        let mut matrix = vec![0.0; n * n];
+        for i in 0..n {
+            for j in 0..n {
+                let (forward, backward) = (raw[i * n + j], raw[j * n + i]);
+                matrix[i * n + j] = match (symmetric, normalized) {
+                    (false, false) => forward,
+                    (false, true) => mean(forward, tokens[i]),
+                    (true, false) => forward + backward,
+                    (true, true) => (mean(forward, tokens[i]) + mean(backward, tokens[j])) / 2.0,
+                };
+            }
+        }
+        Ok(matrix)
+    }
+
+    /// Score every window of `window_tokens` tokens of preloaded document `doc_index`,
+    /// one every `stride` tokens, with the best window's offset (see `WindowScores`)
+    ///
+    /// Each window scores like a document of its own (`search_preloaded` with the
+    /// instance metric and aggregation, no length penalty); see windows.rs.
+    #[wasm_bindgen]
+    pub fn maxsim_windows(
+        &self,
+        query_flat: &[f32],
+        query_tokens: usize,
+        doc_index: usize,
+        window_tokens: usize,
+        stride: usize,
+    ) -> Result<WindowScores, JsValue> {
+        self.maxsim_windows_impl(query_flat, query_tokens, doc_index, window_tokens, stride, false)
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    /// Normalized variant of `maxsim_windows`
+    #[wasm_bindgen]
+    pub fn maxsim_windows_normalized(
+        &self,
+        query_flat: &[f32],
+        query_tokens: usize,
+        doc_index: usize,
+        window_tokens: usize,
+        stride: usize,
+    ) -> Result<WindowScores, JsValue> {
+        self.maxsim_windows_impl(query_flat, query_tokens, doc_index, window_tokens, stride, true)
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    fn maxsim_windows_impl(
+        &self,
+        query_flat: &[f32],
+        query_tokens: usize,
+        doc_index: usize,
+        window_tokens: usize,
+        stride: usize,
+        normalized: bool,
+    ) -> Result<WindowScores, String> {
+        let docs_ref = self.documents.borrow();
+        let docs = docs_ref.as_ref().ok_or("No documents loaded. Call load_documents() first.")?;
+        if doc_index >= docs.num_docs() {
+            return Err(format!("Document index {} out of range ({} documents)", doc_index, docs.num_docs()));
+        }
+        if window_tokens == 0 || stride == 0 {
+            return Err("window_tokens and stride must be at least 1".to_string());
+        }
+        let dim = docs.embedding_dim;
+        self.check_query_input(query_flat, query_tokens, dim)?;
+
+        let metric = self.config.metric();
+        let (embeddings, token_norms) = docs.document(doc_index);
+        let doc_tokens = token_norms.len();
+        self.begin_search(|| format!(
+            "op=windows doc={} doc_tokens={} window={} stride={} query_tokens={} dim={} kernel={} metric={} normalized={}",
+            doc_index, doc_tokens, window_tokens, stride, query_tokens, dim, dot_kernel_name(dim), metric.name(), normalized
+        ));
+        let (query_data, active_query_tokens) = self.prepare_query_tokens(query_flat, query_tokens, &[], dim)?;
+        let ctx = self.score_context(false, metric, &query_data, None, dim);
+
+        let mut similarities = self.scratch.lend(active_query_tokens * doc_tokens);
+        similarities.resize(active_query_tokens * doc_tokens, 0.0);
+        self.document_similarities(&ctx, &query_data, embeddings, &mut similarities, active_query_tokens, doc_tokens, dim);
+        // Rows are reduced in place, so every window reduces a copy of its columns
+        let mut row = Vec::with_capacity(window_tokens.min(doc_tokens));
+        let windows = WindowScores::scan(doc_tokens, window_tokens, stride, |start, len| {
+            let score: f32 = similarities
+                .chunks_exact(doc_tokens)
+                .enumerate()
+                .map(|(q_idx, similarity_row)| {
+                    row.clear();
+                    row.extend_from_slice(&similarity_row[start..start + len]);
+                    ctx.row_score(&mut row, q_idx, &token_norms[start..start + len])
+                })
+                .sum();
+            if normalized { mean(score, active_query_tokens) } else { score }
+        });
+        self.finish_search(&ctx);
+        Ok(windows)
+    }
+
+    /// Search preloaded documents in two score modes at once (see `maxsim_batch_dual`)
+    #[wasm_bindgen]
+    pub fn search_preloaded_dual(
+        &self,
+        query_flat: &[f32],
+        query_tokens: usize,
+        previous: ScoreMode,
+        current: ScoreMode,
+    ) -> Result<DualScores, JsValue> {
+        let (raw, active_tokens) = self.search_preloaded_raw(query_flat, query_tokens).map_err(|e| JsValue::from_str(&e))?;
+        Ok(DualScores::from_raw(&raw, previous, current, active_tokens))
+    }
+
+    // Raw scores of every preloaded document with the active query token count, from
+    // one query preparation (shared by the fused and dual outputs)
+    fn search_preloaded_raw(&self, query_flat: &[f32], query_tokens: usize) -> Result<(Vec<f32>, usize), String> {
+        let docs_ref = self.documents.borrow();
+        let docs = docs_ref.as_ref().ok_or("No documents loaded. Call load_documents() first.")?;
+        self.check_query_input(query_flat, query_tokens, docs.embedding_dim)?;
+
+        let metric = self.config.metric();
+        self.begin_store_search(docs, query_tokens, false, metric);
+        let (query_data, active_query_tokens) =
+            self.prepare_query_tokens(query_flat, query_tokens, &[], docs.embedding_dim)?;
+        Ok((self.search_store_prepared(docs, &query_data, active_query_tokens, false, metric, None), active_query_tokens))
+    }
+
+    /// Search preloaded documents using the instance defaults for metric, aggregation
+    /// and normalization
+    #[wasm_bindgen]
+    pub fn search_preloaded_default(
+        &self,
+        query_flat: &[f32],
+        query_tokens: usize,
+    ) -> Result<Vec<f32>, JsValue> {
+        self.search_preloaded_impl(query_flat, query_tokens, &[], self.config.normalized(), self.config.metric(), None)
+    }
+
+    /// Search preloaded documents, emitting only documents at or above the score threshold
+    ///
+    /// Uses the instance threshold from `set_score_threshold()` (all documents are
+    /// returned when none is set), so broad queries transfer only the interesting hits.
+    #[wasm_bindgen]
+    pub fn search_preloaded_thresholded(
+        &self,
+        query_flat: &[f32],
+        query_tokens: usize,
+    ) -> Result<SearchHits, JsValue> {
+        let scores = self.search_preloaded_impl(query_flat, query_tokens, &[], false, self.config.metric(), self.score_threshold)?;
+        Ok(self.threshold_hits(&scores))
+    }
+
+    /// Normalized variant of `search_preloaded_thresholded` (threshold on the averaged score)
+    #[wasm_bindgen]
+    pub fn search_preloaded_thresholded_normalized(
+        &self,
+        query_flat: &[f32],
+        query_tokens: usize,
+    ) -> Result<SearchHits, JsValue> {
+        let scores = self.search_preloaded_impl(query_flat, query_tokens, &[], true, self.config.metric(), self.score_threshold)?;
+        Ok(self.threshold_hits(&scores))
+    }
+
+    // Internal implementation shared by all preloaded search variants
+    fn search_preloaded_impl(
+        &self,
+        query_flat: &[f32],
+        query_tokens: usize,
+        query_mask: &[u8],
+        normalized: bool,
+        metric: Metric,
+        min_score: Option<f32>,
+    ) -> Result<Vec<f32>, JsValue> {
+        // Get reference to preloaded documents
+        let docs_ref = self.documents.borrow();
+        let docs = docs_ref.as_ref()
+            .ok_or_else(|| JsValue::from_str("No documents loaded. Call load_documents() first."))?;
+
+        self.search_store(docs, query_flat, query_tokens, query_mask, normalized, metric, min_score)
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    // Score every document of a paged store (the preloaded corpus or one resident shard)
+    #[allow(clippy::too_many_arguments)]
+    fn search_store(
+        &self,
+        docs: &PreloadedDocuments,
+        query_flat: &[f32],
+        query_tokens: usize,
+        query_mask: &[u8],
+        normalized: bool,
+        metric: Metric,
+        min_score: Option<f32>,
+    ) -> Result<Vec<f32>, String> {
+        self.check_query_input(query_flat, query_tokens, docs.embedding_dim)?;
+
+        self.begin_store_search(docs, query_tokens, normalized, metric);
+        let (query_data, active_query_tokens) =
+            self.prepare_query_tokens(query_flat, query_tokens, query_mask, docs.embedding_dim)?;
+        Ok(self.search_store_prepared(docs, &query_data, active_query_tokens, normalized, metric, min_score))
+    }
+
+    // Start the trace of a paged store search before its query is prepared
+    fn begin_store_search(&self, docs: &PreloadedDocuments, query_tokens: usize, normalized: bool, metric: Metric) {
+        let dim = docs.embedding_dim;
+        match self.truncation(dim) {
+            Some((search_dim, _)) => self.begin_search(|| format!(
+                "op=preloaded docs={} pages={} query_tokens={} dim={} search_dim={} kernel={} metric={} normalized={}",
+                docs.num_docs(), docs.pages().len(), query_tokens, dim, search_dim,
+                dot_kernel_name(search_dim), metric.name(), normalized
+            )),
+            None => self.begin_search(|| format!(
+                "op=preloaded docs={} pages={} query_tokens={} dim={} kernel={} metric={} normalized={}",
+                docs.num_docs(), docs.pages().len(), query_tokens, dim, dot_kernel_name(dim), metric.name(), normalized
+            )),
+        }
+    }
+
+    // Score every document of a paged store with an already prepared query; the trace
+    // must already be started with `begin_store_search()`
+    fn search_store_prepared(
+        &self,
+        docs: &PreloadedDocuments,
+        query_data: &[f32],
+        active_query_tokens: usize,
+        normalized: bool,
+        metric: Metric,
+        min_score: Option<f32>,
+    ) -> Vec<f32> {
+        if let Some((search_dim, _)) = self.truncation(docs.embedding_dim) {
+            return self.search_store_truncated(docs, query_data, active_query_tokens, normalized, metric, min_score, search_dim);
+        }
+        let ctx = self.score_context(normalized, metric, query_data, None, docs.embedding_dim)
+            .with_min_score(min_score, self.auto_normalize);
+
+        // ZERO-COPY SEARCH! 🚀
+        // Every page is already a flat batch of whole documents - direct batch processing with
+        // full optimizations. Pages sorted by run_maintenance() skip the per-search sort,
+        // scores returned in original order
+        let mut scores = vec![0.0; docs.num_docs()];
+        for (page_idx, page) in docs.pages().iter().enumerate() {
+            if ctx.is_aborted() {
+                break;
+            }
+            self.trace.borrow_mut().record(|| format!("page index={} docs={:?}", page_idx, page.doc_range()));
+
+            // Token norms were computed once at load time
+            let page_ctx = ctx.with_doc_norms(&page.token_norms).with_doc_ids(DocIds::From(page.first_doc));
+            let page_scores = match &page.packed {
+                Some(packed) if !ctx.precise() => self.score_packed_page(
+                    query_data,
+                    active_query_tokens,
+                    packed,
+                    &page.doc_tokens,
+                    docs.embedding_dim,
+                    &page_ctx,
+                ),
+                _ => self.maxsim_batch_impl(
+                    query_data,
+                    active_query_tokens,
+                    &page.embeddings,  // Already flat and contiguous!
+                    &page.doc_tokens,  // Already computed!
+                    docs.embedding_dim,
+                    &page_ctx,
+                    page.length_order.as_deref()  // Sorted by run_maintenance(), otherwise on-the-fly
+                ),
+            };
+            scores[page.doc_range()].copy_from_slice(&page_scores);
+        }
+        self.finish_search(&ctx);
+
+        scores
+    }
+
+    // Active Matryoshka truncation for documents of `embedding_dim` dimensions
+    fn truncation(&self, embedding_dim: usize) -> Option<(usize, usize)> {
+        self.search_dim.filter(|&(search_dim, _)| search_dim < embedding_dim)
+    }
+
+    // Score every document of a paged store over the first `search_dim` dimensions
+    #[allow(clippy::too_many_arguments)]
+    fn search_store_truncated(
+        &self,
+        docs: &PreloadedDocuments,
+        query_data: &[f32],
+        query_tokens: usize,
+        normalized: bool,
+        metric: Metric,
+        min_score: Option<f32>,
+        search_dim: usize,
+    ) -> Vec<f32> {
+        let dim = docs.embedding_dim;
+        let query_prefix: Vec<f32> =
+            query_data.chunks_exact(dim).flat_map(|token| token[..search_dim].iter().copied()).collect();
+        let ctx = self.score_context(normalized, metric, &query_prefix, None, search_dim)
+            .with_min_score(min_score, false);
+
+        let mut similarities = self.scratch.lend(query_tokens * docs.max_doc_tokens());
+        let mut doc_norms = Vec::new();
+        let mut scores = Vec::with_capacity(docs.num_docs());
+        for page in docs.pages() {
+            if ctx.is_aborted() {
+                break;
+            }
+            let mut offset = 0;
+            for &tokens in &page.doc_tokens {
+                let doc_flat = &page.embeddings[offset..offset + tokens * dim];
+                offset += tokens * dim;
+                if tokens == 0 || query_tokens == 0 {
+                    scores.push(0.0);
+                    continue;
+                }
+                similarities.resize(query_tokens * tokens, 0.0);
+                prefix_similarities(&query_prefix, doc_flat, &mut similarities, query_tokens, tokens, dim, search_dim);
+                doc_norms.clear();
+                if metric.needs_norms() {
+                    doc_norms.extend(doc_flat.chunks_exact(dim).map(|token| dot_product(&token[..search_dim], &token[..search_dim])));
+                }
+                scores.push(ctx.score_document(&mut similarities, |q_idx| q_idx * tokens, tokens, query_tokens, &doc_norms));
+            }
+        }
+        scores.resize(docs.num_docs(), 0.0);
+        self.finish_search(&ctx);
+        scores
+    }
+
+    // Top k of a Matryoshka search with a prepared query, its best `rerank` candidates
+    // rescored at full dimension
+    fn truncated_top_k(
+        &self,
+        docs: &PreloadedDocuments,
+        query_data: &[f32],
+        active_query_tokens: usize,
+        k: usize,
+        normalized: bool,
+        rerank: usize,
+    ) -> SearchHits {
+        let metric = self.config.metric();
+        let scores = self.search_store_prepared(docs, query_data, active_query_tokens, normalized, metric, None);
+        let all: Vec<usize> = (0..scores.len()).collect();
+        let k = if k == 0 { scores.len() } else { k };
+        if rerank == 0 {
+            return SearchHits::top_k(&all, &scores, k);
+        }
+
+        let candidates: Vec<usize> =
+            SearchHits::top_k(&all, &scores, rerank.max(k)).indices().into_iter().map(|idx| idx as usize).collect();
+        let ctx = self.score_context(normalized, metric, query_data, None, docs.embedding_dim);
+        self.trace.borrow_mut().record(|| format!("rerank candidates={} dim={}", candidates.len(), docs.embedding_dim));
+        let full_scores = self.score_candidates(docs, query_data, active_query_tokens, &candidates, &ctx);
+        self.finish_search(&ctx);
+
+        SearchHits::top_k(&candidates, &full_scores, k)
+    }
+
+    // Score the documents of one page from its packed copy (no batching or padding needed:
+    // the packed kernel is vectorized across document tokens)
+    fn score_packed_page(
+        &self,
+        query_flat: &[f32],
+        query_tokens: usize,
+        packed_docs: &[f32],
+        doc_tokens: &[usize],
+        embedding_dim: usize,
+        ctx: &ScoreContext,
+    ) -> Vec<f32> {
+        self.trace.borrow_mut().record(|| format!("batch docs={} path=packed", doc_tokens.len()));
+        let mut similarities = self.scratch.lend(query_tokens * doc_tokens.iter().copied().max().unwrap_or(0));
+        let (mut offset, mut packed_offset) = (0, 0);
+        doc_tokens
+            .iter()
+            .map(|&tokens| {
+                let packed_doc = &packed_docs[packed_offset..packed_offset + packed::packed_len(tokens, embedding_dim)];
+                let doc_norms = ctx.doc_norms(offset, tokens);
+                offset += tokens * embedding_dim;
+                packed_offset += packed_doc.len();
+                if tokens == 0 || query_tokens == 0 {
+                    return 0.0;
+                }
+
+                let start = profile::stamp();
+                similarities.resize(query_tokens * tokens, 0.0);
+                packed::packed_similarities(query_flat, packed_doc, &mut similarities, query_tokens, tokens, embedding_dim);
+                self.profile.borrow_mut().add(Stage::Similarity, start);
+
+                let start = profile::stamp();
+                let score = ctx.score_document(&mut similarities, |q_idx| q_idx * tokens, tokens, query_tokens, doc_norms);
+                self.profile.borrow_mut().add(Stage::Reduction, start);
+                score
+            })
+            .collect()
+    }
+
+    /// Perform background maintenance for at most about `budget_ms` milliseconds
+    ///
+    /// Designed to be driven from `requestIdleCallback` (pass `deadline.timeRemaining()`):
+    /// work is split into small steps (one page each), at least one step runs per call,
+    /// and the crate never schedules anything itself. Steps compute the per-page length
+    /// order of pages that lack one (every page after a load), so preloaded searches
+    /// skip their per-search sort. That is the only deferred work: the ANN clusters,
+    /// pooled vectors, HNSW graph, token centroids and score bounds are built during the
+    /// load (searches rely on them as soon as it returns), and compressed corpora are
+    /// encoded by their own load calls.
+    /// Returns the number of steps still pending (0 when fully maintained).
+    #[wasm_bindgen]
+    pub fn run_maintenance(&self, budget_ms: f64) -> usize {
+        let mut docs_ref = self.documents.borrow_mut();
+        let Some(shared) = docs_ref.as_mut() else {
+            return 0;
+        };
+        // Never copy the corpus for maintenance; retry once async searches have finished
+        let Some(docs) = Rc::get_mut(shared) else {
+            return shared.pending_maintenance();
+        };
+
+        let deadline = clock::now_ms() + budget_ms;
+        while docs.maintenance_step() {
+            if clock::now_ms() >= deadline {
+                break;
+            }
+        }
+        docs.pending_maintenance()
+    }
+
+    /// Number of background maintenance steps still pending
+    #[wasm_bindgen]
+    pub fn maintenance_pending(&self) -> usize {
+        self.documents.borrow().as_ref().map_or(0, |d| d.pending_maintenance())
+    }
+
+    /// Get number of loaded documents
+    #[wasm_bindgen]
+    pub fn num_documents_loaded(&self) -> usize {
+        self.documents.borrow()
+            .as_ref()
+            .map(|d| d.num_docs())
+            .unwrap_or(0)
+    }
+
+    /// Reserve the reusable scoring buffers with the given sizes in bytes
+    ///
+    /// The defaults (512 KB of similarities, 4 MB of document batches) suit desktop
+    /// browsers; memory-constrained webviews can lower them, down to 0. The pool is
+    /// rebuilt now, releasing its previous memory. Buffers of other sizes are still
+    /// pooled on demand for larger calls (up to 64 MB idle); `shrink_buffers()`
+    /// trims the pool back to the reserved total.
+    #[wasm_bindgen]
+    pub fn set_buffer_capacity(&mut self, similarity_bytes: usize, batch_bytes: usize) {
+        let float_bytes = std::mem::size_of::<f32>();
+        self.buffer_floats = (similarity_bytes / float_bytes, batch_bytes / float_bytes);
+        self.scratch = ScratchPool::new(&[self.buffer_floats.0, self.buffer_floats.1], MAX_IDLE_SCRATCH_FLOATS);
+    }
+
+    /// Release idle buffer memory grown by large calls, back to the reserved capacity
+    /// (e.g. on a memory pressure signal)
+    #[wasm_bindgen]
+    pub fn shrink_buffers(&mut self) {
+        self.scratch.trim(self.buffer_floats.0 + self.buffer_floats.1);
+    }
+
+    /// Corpus shape and memory use of this instance (see `IndexStats`)
+    #[wasm_bindgen]
+    pub fn index_stats(&self) -> IndexStats {
+        let mut stats = IndexStats::of_store(self.documents.borrow().as_deref());
+        stats.buffer_bytes = self.scratch.idle_floats() * std::mem::size_of::<f32>();
+        stats.preview_bytes = self.preview.as_ref().map_or(0, |preview| preview.memory_bytes());
+        stats.pooled_bytes = self.pooled.as_ref().map_or(0, |pooled| pooled.memory_bytes());
+        stats.hnsw_bytes = self.hnsw.as_ref().map_or(0, |graph| graph.memory_bytes());
+        stats.collection_bytes = self.collections.values().flatten().map(|docs| docs.memory_bytes()).sum();
+        stats.residual_bytes = self.residual_documents.as_ref().map_or(0, |residual| residual.memory_bytes());
+
+        stats.pq_bytes = self.pq_documents.as_ref().map_or(0, |pq| pq.memory_bytes());
+        stats.int4_bytes = self.int4_documents.as_ref().map_or(0, |int4| int4.memory_bytes());
+
+        let storage: Vec<String> = [
+            (stats.num_docs() > 0).then(|| "f32".to_string()),
+            self.residual_documents.as_ref().map(|residual| format!("residual-{}bit", residual.nbits())),
+            self.pq_documents.as_ref().map(|pq| format!("pq-{}x8", pq.num_subspaces())),
+            self.int4_documents.as_ref().map(|int4| format!("int4-g{}", int4.group_size())),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        stats.quantization = if storage.is_empty() { "none".to_string() } else { storage.join("+") };
+        stats
+    }
+
+    /// Create an empty named collection
+    ///
+    /// Collections are independent corpora (e.g. "notes", "emails") held by this
+    /// instance beside the preloaded documents. They share its scoring buffers and
+    /// settings (metric, stopmask, auto-normalization), so several corpora don't need
+    /// several instances.
+    #[wasm_bindgen]
+    pub fn create_collection(&mut self, name: &str) -> Result<(), JsValue> {
+        if self.collections.contains_key(name) {
+            return Err(JsValue::from_str(&format!("Collection {:?} already exists", name)));
+        }
+        self.collections.insert(name.to_string(), None);
+        self.bump_generation("collection");
+        Ok(())
+    }
+
+    /// Remove a collection and free its documents; returns whether it existed
+    #[wasm_bindgen]
+    pub fn drop_collection(&mut self, name: &str) -> bool {
+        let existed = self.collections.remove(name).is_some();
+        if existed {
+            self.bump_generation("collection");
+        }
+        existed
+    }
+
+    /// Load documents into a collection, replacing its previous documents
+    ///
+    /// Same layout as `load_documents()`.
+    #[wasm_bindgen]
+    pub fn load_documents_into(
+        &mut self,
+        name: &str,
+        embeddings_data: &[f32],
+        doc_tokens: &[usize],
+        embedding_dim: usize,
+    ) -> Result<(), JsValue> {
+        self.check_documents_input(embeddings_data, doc_tokens, embedding_dim).map_err(|e| JsValue::from_str(&e))?;
+        let Some(slot) = self.collections.get_mut(name) else {
+            return Err(JsValue::from_str(&format!("Unknown collection {:?}. Call create_collection() first.", name)));
+        };
+
+        let mut preloaded = PreloadedDocuments::from_flat(embeddings_data, doc_tokens, embedding_dim);
+        if self.auto_normalize {
+            preloaded.normalize();
+        }
+        preloaded.set_packed(self.packed_layout);
+        *slot = Some(preloaded);
+        self.bump_generation("collection");
+        Ok(())
+    }
+
+    /// Search every document of a collection (one score per document, in load order)
+    #[wasm_bindgen]
+    pub fn search_collection(&self, name: &str, query_flat: &[f32], query_tokens: usize) -> Result<Vec<f32>, JsValue> {
+        self.search_collection_impl(name, query_flat, query_tokens, false)
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    /// Search a collection with normalized MaxSim scores
+    #[wasm_bindgen]
+    pub fn search_collection_normalized(
+        &self,
+        name: &str,
+        query_flat: &[f32],
+        query_tokens: usize,
+    ) -> Result<Vec<f32>, JsValue> {
+        self.search_collection_impl(name, query_flat, query_tokens, true)
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    fn search_collection_impl(
+        &self,
+        name: &str,
+        query_flat: &[f32],
+        query_tokens: usize,
+        normalized: bool,
+    ) -> Result<Vec<f32>, String> {
+        let docs = match self.collections.get(name) {
+            Some(Some(docs)) => docs,
+            Some(None) => return Err(format!("Collection {:?} is empty. Call load_documents_into() first.", name)),
+            None => return Err(format!("Unknown collection {:?}", name)),
+        };
+        self.search_store(docs, query_flat, query_tokens, &[], normalized, self.config.metric(), None)
+    }
+
+    /// Load a stopmask vocabulary of "ignorable" token embeddings
+    ///
+    /// Every query is checked against this set when it is prepared: query tokens whose
+    /// similarity to any stopmask entry is >= `threshold` are masked out, exactly as if
+    /// the caller had passed a query mask. Typical entries are the model's embeddings
+    /// for punctuation, [MASK] query padding, or special tokens.
+    ///
+    /// # Arguments
+    /// * `embeddings` - Flat array of stopmask token embeddings (num_tokens × embedding_dim)
+    /// * `embedding_dim` - Embedding dimension (must match the queries it applies to)
+    /// * `threshold` - Similarity threshold, e.g. 0.95 for normalized embeddings (with
+    ///   auto-normalization, query and stopmask tokens are compared normalized)
+    #[wasm_bindgen]
+    pub fn load_stopmask(
+        &mut self,
+        embeddings: &[f32],
+        embedding_dim: usize,
+        threshold: f32,
+    ) -> Result<(), JsValue> {
+        if embedding_dim == 0 {
+            return Err(JsValue::from_str("Embedding dimension must be > 0"));
+        }
+
+        if embeddings.is_empty() || !embeddings.len().is_multiple_of(embedding_dim) {
+            return Err(JsValue::from_str("Stopmask size must be a non-zero multiple of embedding_dim"));
+        }
+
+        if let Some(docs) = self.documents.borrow().as_ref() {
+            if docs.embedding_dim != embedding_dim {
+                return Err(JsValue::from_str("Stopmask dimension does not match loaded documents"));
+            }
+        }
+
+        self.stopmask = Some(StopMask {
+            embeddings: embeddings.to_vec(),
+            embedding_dim,
+            threshold,
+        });
+        self.query_settings += 1;
+        Ok(())
+    }
+
+    /// Remove the stopmask vocabulary
+    #[wasm_bindgen]
+    pub fn clear_stopmask(&mut self) {
+        self.stopmask = None;
+        self.query_settings += 1;
+    }
+
+    /// Number of token embeddings in the stopmask vocabulary
+    #[wasm_bindgen]
+    pub fn stopmask_size(&self) -> usize {
+        self.stopmask
+            .as_ref()
+            .map(|m| m.embeddings.len() / m.embedding_dim)
+            .unwrap_or(0)
+    }
+
+    /// Cap the number of query tokens scored per search (0 removes the limit)
+    ///
+    /// Longer queries (counted after masks and the stopmask) are reduced with
+    /// `strategy` instead of growing scratch memory; the reduction is reported on
+    /// `SearchHits.degradation` and by `last_query_degradation()`.
+    #[wasm_bindgen]
+    pub fn set_query_limit(&mut self, max_tokens: usize, strategy: QueryPruning) {
+        self.query_limit = (max_tokens > 0).then_some((max_tokens, strategy));
+        self.query_settings += 1;
+    }
+
+    /// Score paged corpora with only the first `search_dim` dimensions (0 = all)
+    ///
+    /// For Matryoshka (MRL) models, whose leading dimensions form a usable embedding
+    /// on their own: `search_preloaded*`, collection and preview searches read just
+    /// the prefix of every stored token (in place, no copy), roughly
+    /// `embedding_dim / search_dim` times less work. `search_preloaded_top_k()` then
+    /// rescores its best `rerank` candidates (at least k; 0 = no rerank) with every
+    /// dimension and returns exact full-dimension scores for them. Truncated scores
+    /// are smaller in scale than full ones; don't mix the two in one threshold.
+    #[wasm_bindgen]
+    pub fn set_search_dim(&mut self, search_dim: usize, rerank: usize) {
+        self.search_dim = (search_dim > 0).then_some((search_dim, rerank));
+        *self.paged_results.get_mut() = None;
+    }
+
+    /// Drop query tokens that near-duplicate an earlier query token (0 disables)
+    ///
+    /// A query token whose cosine similarity to an earlier kept token exceeds
+    /// `threshold` (e.g. 0.98) is masked out at preparation time, like a stopmask
+    /// match. Padded ColBERT queries repeat near-identical [MASK] embeddings, and each
+    /// copy costs a full pass over the documents while adding almost nothing to the
+    /// ranking. Scores change: normalized scores divide by the remaining tokens, and
+    /// raw sums lose the duplicates' contribution. Combine with `load_stopmask()` to
+    /// also drop punctuation and padding tokens outright.
+    #[wasm_bindgen]
+    pub fn set_query_dedup(&mut self, threshold: f32) {
+        self.query_dedup = (threshold > 0.0).then_some(threshold);
+        self.query_settings += 1;
+    }
+
+    /// Reduction applied to the query of the latest search (undefined when none)
+    #[wasm_bindgen]
+    pub fn last_query_degradation(&self) -> Option<QueryDegradation> {
+        self.last_degradation.borrow().clone()
+    }
+
+    /// Store the profile query set that `admission_score()` scores candidates against
+    ///
+    /// # Arguments
+    /// * `queries_flat` - Flat embeddings of the profile queries, back to back
+    /// * `query_tokens` - Token count of each profile query
+    /// * `embedding_dim` - Embedding dimension
+    #[wasm_bindgen]
+    pub fn set_admission_profile(
+        &mut self,
+        queries_flat: &[f32],
+        query_tokens: &[usize],
+        embedding_dim: usize,
+    ) -> Result<(), JsValue> {
+        if embedding_dim == 0 {
+            return Err(JsValue::from_str("Embedding dimension must be > 0"));
+        }
+        if query_tokens.is_empty() || query_tokens.contains(&0) {
+            return Err(JsValue::from_str("Profile needs at least one query, none of them empty"));
+        }
+        if query_tokens.iter().sum::<usize>() * embedding_dim != queries_flat.len() {
+            return Err(JsValue::from_str("Profile queries size mismatch"));
+        }
+
+        self.admission_profile = Some(AdmissionProfile {
+            queries: queries_flat.to_vec(),
+            query_tokens: query_tokens.to_vec(),
+            embedding_dim,
+        });
+        Ok(())
+    }
+
+    /// Remove the admission profile
+    #[wasm_bindgen]
+    pub fn clear_admission_profile(&mut self) {
+        self.admission_profile = None;
+    }
+
+    /// Score a candidate document against the admission profile before indexing it
+    ///
+    /// Returns the document's best MaxSim over the profile queries (raw sum), using the
+    /// same kernels, metric, stopmask and auto-normalization as searches.
+    ///
+    /// # Arguments
+    /// * `candidate_embeddings` - Flat embeddings of the candidate (tokens × embedding_dim)
+    /// * `tokens` - Number of candidate tokens
+    #[wasm_bindgen]
+    pub fn admission_score(&self, candidate_embeddings: &[f32], tokens: usize) -> Result<f32, JsValue> {
+        self.admission_score_impl(candidate_embeddings, tokens, false)
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    /// Normalized variant of `admission_score` (comparable across profile query lengths)
+    #[wasm_bindgen]
+    pub fn admission_score_normalized(&self, candidate_embeddings: &[f32], tokens: usize) -> Result<f32, JsValue> {
+        self.admission_score_impl(candidate_embeddings, tokens, true)
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    fn admission_score_impl(&self, candidate: &[f32], tokens: usize, normalized: bool) -> Result<f32, String> {
+        let profile = self.admission_profile.as_ref()
+            .ok_or("No admission profile. Call set_admission_profile() first.")?;
+        let dim = profile.embedding_dim;
+        if tokens == 0 || candidate.len() != tokens * dim {
+            return Err("Candidate embeddings size mismatch".to_string());
+        }
+
+        let metric = self.config.metric();
+        self.begin_search(|| format!(
+            "op=admission profile_queries={} doc_tokens={} dim={} kernel={} metric={} normalized={}",
+            profile.query_tokens.len(), tokens, dim, dot_kernel_name(dim), metric.name(), normalized
+        ));
+
+        // The candidate is prepared (and its norms computed) once for all profile queries
+        Ok(self.with_prepared_documents(candidate, &[tokens], &[], dim, |doc_data, doc_counts| {
+            let doc_norms = call_doc_norms(metric, doc_data, dim);
+            let mut best = f32::NEG_INFINITY;
+            let mut offset = 0;
+            for &query_tokens in &profile.query_tokens {
+                let query_flat = &profile.queries[offset..offset + query_tokens * dim];
+                offset += query_tokens * dim;
+
+                let (query_data, active_tokens) = self.prepare_query_unmasked(query_flat, query_tokens, dim);
+                let ctx = self.score_context(normalized, metric, &query_data, Some(&doc_norms), dim);
+                let score = self.compute_maxsim_score(&query_data, active_tokens, doc_data, doc_counts[0], dim, &ctx, &doc_norms);
+                self.finish_search(&ctx);
+                best = best.max(score);
+            }
+            best
+        }))
+    }
+
+    /// Number of query tokens that take part in scoring after the stopmask
+    /// (the token count that normalized scores and `convert_threshold()` divide by)
+    #[wasm_bindgen]
+    pub fn active_query_tokens(&self, query_flat: &[f32], query_tokens: usize, embedding_dim: usize) -> usize {
+        self.prepare_query_unmasked(query_flat, query_tokens, embedding_dim).1
+    }
+
+    /// Compute the effective query mask (caller mask combined with the stopmask)
+    /// Returns one byte per query token: 1 = scored, 0 = ignored
+    #[wasm_bindgen]
+    pub fn query_stopmask(
+        &self,
+        query_flat: &[f32],
+        query_tokens: usize,
+        embedding_dim: usize,
+    ) -> Result<Vec<u8>, JsValue> {
+        if query_flat.len() != query_tokens * embedding_dim {
+            return Err(JsValue::from_str("Query size mismatch"));
+        }
+
+        Ok(self.query_keep_mask(query_flat, query_tokens, &[], embedding_dim))
+    }
+
+    /// Enable or disable auto-normalization for models that don't output unit vectors
+    ///
+    /// When enabled, preloaded documents and the documents of every collection are
+    /// L2-normalized once (immediately for documents already loaded, at load time
+    /// afterwards) and every query and per-call document batch is normalized before
+    /// scoring, so dot product equals cosine. Disabling does not restore the original
+    /// norms of already-loaded documents. Quantized corpora (PQ, 4-bit) are
+    /// normalized when they are loaded: load them again after enabling it.
+    #[wasm_bindgen]
+    pub fn set_auto_normalize(&mut self, enabled: bool) {
+        if enabled && !self.auto_normalize {
+            let mut normalized = false;
+            if let Some(docs) = self.documents.get_mut().as_mut() {
+                Rc::make_mut(docs).normalize();
+                normalized = true;
+            }
+            for docs in self.collections.values_mut().flatten() {
+                docs.normalize();
+                normalized = true;
+            }
+            if normalized {
+                self.bump_generation("normalize");
+            }
+            self.rebuild_ann();
+            self.rebuild_preview();
+            self.rebuild_bounds();
+            self.rebuild_centroids();
+            self.rebuild_pooled();
+            self.rebuild_calibration();
+        }
+        if enabled != self.auto_normalize {
+            self.query_settings += 1;
+        }
+        self.auto_normalize = enabled;
+    }
+
+    /// Enable or disable input validation (off by default)
+    ///
+    /// When enabled, queries, loaded documents and per-call document batches are
+    /// scanned for NaN/Inf before use, and APIs with an error channel fail with the
+    /// first offending token and dimension instead of ranking garbage scores.
+    #[wasm_bindgen]
+    pub fn set_validation(&mut self, enabled: bool) {
+        self.validation = enabled;
+    }
+
+    /// Tune the cache blocking parameters to this device with a short microbenchmark
+    ///
+    /// Runs for about 0.2 s, so call it once at startup (e.g. when idle); the tuned
+    /// values apply to every later call of this instance and never change a score.
+    /// Returns a summary of the chosen parameters.
+    #[wasm_bindgen]
+    pub fn calibrate(&mut self) -> String {
+        self.tuning = tuning::calibrate(self);
+        self.trace.borrow_mut().begin(|| format!("op=calibrate {}", self.tuning.describe()));
+        self.tuning.describe()
+    }
+
+    /// Benchmark this device on a synthetic corpus of `num_docs` documents of
+    /// `doc_tokens` tokens each
+    ///
+    /// Data is generated inside WASM and searched by a scoring snapshot of this
+    /// instance, so the loaded documents are untouched. Reports per-stage timings
+    /// (generate, load, ANN build, exact and approximate query) and throughput, e.g.
+    /// to show device capability or to pick between exact and approximate search.
+    ///
+    /// # Arguments
+    /// * `num_docs` - Documents in the synthetic corpus
+    /// * `doc_tokens` - Tokens per document
+    /// * `embedding_dim` - Embedding dimension
+    /// * `iterations` - Timed queries per search path (32 tokens each)
+    #[wasm_bindgen]
+    pub fn benchmark(
+        &self,
+        num_docs: usize,
+        doc_tokens: usize,
+        embedding_dim: usize,
+        iterations: usize,
+    ) -> Result<BenchmarkReport, JsValue> {
+        let report = benchmark::run(self, num_docs, doc_tokens, embedding_dim, iterations)
+            .map_err(|e| JsValue::from_str(&e))?;
+        self.trace.borrow_mut().begin(|| format!("op=benchmark {}", report.describe()));
+        Ok(report)
+    }
+
+    /// Keep a packed (block-transposed) copy of stored documents for faster scoring
+    ///
+    /// Built now for the preloaded documents and collections and at every later load.
+    /// Full-corpus searches (`search_preloaded*`, `search_collection*`, previews) then
+    /// compute four document tokens per SIMD operation instead of one dot product per
+    /// token pair. Costs a second copy of the embeddings; disabling frees it. The sums
+    /// run in another order, so scores can differ from the unpacked ones in their last
+    /// bits.
+    #[wasm_bindgen]
+    pub fn set_packed_layout(&mut self, enabled: bool) {
+        self.packed_layout = enabled;
+        if let Some(docs) = self.documents.get_mut().as_mut() {
+            if docs.is_packed() != enabled {
+                Rc::make_mut(docs).set_packed(enabled);
+            }
+        }
+        for docs in self.collections.values_mut().flatten() {
+            docs.set_packed(enabled);
+        }
+        self.rebuild_preview();
+    }
+
+    /// Enable IVF-style candidate pruning with `num_clusters` clusters (0 disables)
+    ///
+    /// Spherical k-means over mean-pooled document vectors runs now (if documents are
+    /// loaded) and on every later document load. Roughly √num_docs clusters is a good
+    /// start; `search_preloaded_approx()` then scores only the nearest clusters.
+    #[wasm_bindgen]
+    pub fn set_ann_clusters(&mut self, num_clusters: usize) {
+        self.ann_clusters = num_clusters;
+        self.rebuild_ann();
+    }
+
+    /// Keep per-document score bounds so `search_preloaded_top_k()` can skip documents
+    ///
+    /// Bounds (the bounding box of each document's tokens, `2 × embedding_dim` floats
+    /// per document) are built now (if documents are loaded) and on every later
+    /// document load. Without them top-k searches are still exact, just exhaustive.
+    #[wasm_bindgen]
+    pub fn set_score_bounds(&mut self, enabled: bool) {
+        self.score_bounds = enabled;
+        self.rebuild_bounds();
+    }
+
+    /// Exact top `k` hits (best first) of `search_preloaded`, skipping documents whose
+    /// score bound can't reach the current k-th best score
+    ///
+    /// Pruning needs `set_score_bounds(true)` and the DotProduct metric; otherwise (or
+    /// with `k` = 0) every document is scored. The hits are identical either way.
+    #[wasm_bindgen]
+    pub fn search_preloaded_top_k(&self, query_flat: &[f32], query_tokens: usize, k: usize) -> Result<SearchHits, JsValue> {
+        self.search_top_k_impl(query_flat, query_tokens, k, false)
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    /// Normalized variant of `search_preloaded_top_k`
+    #[wasm_bindgen]
+    pub fn search_preloaded_top_k_normalized(
+        &self,
+        query_flat: &[f32],
+        query_tokens: usize,
+        k: usize,
+    ) -> Result<SearchHits, JsValue> {
+        self.search_top_k_impl(query_flat, query_tokens, k, true)
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    /// Also return, with every hit of `search_preloaded_top_k()`, the document tokens
+    /// that were a query token's best match and how many query tokens picked each
+    /// (`SearchHits.matched_tokens`), e.g. to highlight snippets
+    ///
+    /// Costs one more similarity matrix per hit (not per document). Best matches
+    /// follow the instance metric, whatever the aggregation.
+    #[wasm_bindgen]
+    pub fn set_match_spans(&mut self, enabled: bool) {
+        self.match_spans = enabled;
+    }
+
+    fn search_top_k_impl(&self, query_flat: &[f32], query_tokens: usize, k: usize, normalized: bool) -> Result<SearchHits, String> {
+        let docs_ref = self.documents.borrow();
+        let docs = docs_ref.as_ref().ok_or("No documents loaded. Call load_documents() first.")?;
+        self.check_query_input(query_flat, query_tokens, docs.embedding_dim)?;
+
+        // Bound pruning needs full-dimension dot products and a real cutoff
+        let metric = self.config.metric();
+        let bounds = self.bounds.as_ref().filter(|_| {
+            self.truncation(docs.embedding_dim).is_none() && metric == Metric::DotProduct && k > 0 && k < docs.num_docs()
+        });
+        match bounds {
+            Some(_) => self.begin_search(|| format!(
+                "op=top_k docs={} k={} query_tokens={} dim={} kernel={} normalized={}",
+                docs.num_docs(), k, query_tokens, docs.embedding_dim, dot_kernel_name(docs.embedding_dim), normalized
+            )),
+            None => self.begin_store_search(docs, query_tokens, normalized, metric),
+        }
+        let (query_data, active_query_tokens) =
+            self.prepare_query_tokens(query_flat, query_tokens, &[], docs.embedding_dim)?;
+        let hits = self.top_k_prepared(docs, &query_data, active_query_tokens, k, normalized, bounds)
+            .with_degradation(self.last_query_degradation());
+        if !self.match_spans {
+            return Ok(hits);
+        }
+        let matches = hits
+            .indices()
+            .iter()
+            .map(|&doc| self.token_matches(docs, &query_data, active_query_tokens, doc as usize))
+            .collect();
+        Ok(hits.with_matches(matches))
+    }
+
+    // Document tokens that are some query token's best match, with how many query tokens
+    // picked each, ascending by token
+    fn token_matches(&self, docs: &PreloadedDocuments, query_data: &[f32], query_tokens: usize, doc_idx: usize) -> Vec<(u32, u32)> {
+        let (embeddings, token_norms) = docs.document(doc_idx);
+        let doc_tokens = token_norms.len();
+        if doc_tokens == 0 {
+            return Vec::new();
+        }
+        let dim = docs.embedding_dim;
+        let ctx = self.score_context(false, self.config.metric(), query_data, None, dim);
+        let mut similarities = self.scratch.lend(query_tokens * doc_tokens);
+        similarities.resize(query_tokens * doc_tokens, 0.0);
+        self.document_similarities(&ctx, query_data, embeddings, &mut similarities, query_tokens, doc_tokens, dim);
+
+        let mut counts = vec![0u32; doc_tokens];
+        for (q_idx, row) in similarities.chunks_exact_mut(doc_tokens).enumerate() {
+            counts[ctx.row_argmax(row, q_idx, token_norms)] += 1;
+        }
+        counts.into_iter().enumerate().filter(|&(_, count)| count > 0).map(|(token, count)| (token as u32, count)).collect()
+    }
+
+    // Top k hits for a prepared query (the trace already started), pruned by `bounds`
+    fn top_k_prepared(
+        &self,
+        docs: &PreloadedDocuments,
+        query_data: &[f32],
+        active_query_tokens: usize,
+        k: usize,
+        normalized: bool,
+        bounds: Option<&DocBounds>,
+    ) -> SearchHits {
+        if let Some((_, rerank)) = self.truncation(docs.embedding_dim) {
+            return self.truncated_top_k(docs, query_data, active_query_tokens, k, normalized, rerank);
+        }
+        let metric = self.config.metric();
+        let Some(bounds) = bounds else {
+            let scores = self.search_store_prepared(docs, query_data, active_query_tokens, normalized, metric, None);
+            let all: Vec<usize> = (0..scores.len()).collect();
+            let k = if k == 0 { scores.len() } else { k };
+            return SearchHits::top_k(&all, &scores, k);
+        };
+
+        let ctx = self.score_context(normalized, metric, query_data, None, docs.embedding_dim);
+
+        // Visit documents by decreasing bound
+        let mut order: Vec<(usize, f32)> = (0..docs.num_docs())
+            .map(|doc| {
+                let doc_tokens = docs.doc_tokens()[doc];
+                (doc, ctx.score_bound(bounds.token_bounds(doc, query_data), active_query_tokens, doc_tokens))
+            })
+            .collect();
+        order.sort_by(|&a, &b| rank_cmp(a, b));
+
+        let (scored, scores) = self.score_by_estimate(docs, query_data, active_query_tokens, &order, k, 0.0, &ctx);
+        self.finish_search(&ctx);
+
+        SearchHits::top_k(&scored, &scores, k)
+    }
+
+    // Score documents exactly in `order` (sorted by decreasing estimate), k at a time,
+    // until the next estimate falls more than `margin` below the k-th best exact score
+    // Returns the scored documents and their scores
+    #[allow(clippy::too_many_arguments)]
+    fn score_by_estimate(
+        &self,
+        docs: &PreloadedDocuments,
+        query_data: &[f32],
+        query_tokens: usize,
+        order: &[(usize, f32)],
+        k: usize,
+        margin: f32,
+        ctx: &ScoreContext,
+    ) -> (Vec<usize>, Vec<f32>) {
+        let (mut scored, mut scores) = (Vec::new(), Vec::new());
+        // The k best exact scores so far, worst on top
+        let mut best: BinaryHeap<Reverse<Scored>> = BinaryHeap::with_capacity(k + 1);
+        let mut kth_best = f32::NEG_INFINITY;
+        for chunk in order.chunks(k) {
+            let candidates: Vec<usize> =
+                chunk.iter().take_while(|&&(_, estimate)| estimate + margin >= kth_best).map(|&(doc, _)| doc).collect();
+            if candidates.is_empty() {
+                break;
+            }
+            let chunk_scores = self.score_candidates(docs, query_data, query_tokens, &candidates, ctx);
+            for (&doc, &score) in candidates.iter().zip(&chunk_scores) {
+                best.push(Reverse(Scored(score, doc as u32)));
+                if best.len() > k {
+                    best.pop();
+                }
+            }
+            if best.len() == k {
+                kth_best = best.peek().map_or(f32::NEG_INFINITY, |Reverse(worst)| worst.0);
+            }
+            scores.extend(chunk_scores);
+            scored.extend_from_slice(&candidates);
+            if candidates.len() < chunk.len() {
+                break;
+            }
+        }
+        self.trace.borrow_mut().record(|| format!("rescore scored={} skipped={}", scored.len(), docs.num_docs() - scored.len()));
+        (scored, scores)
+    }
+
+    /// Enable approximate search over `per_doc` token centroids per document (0 disables)
+    ///
+    /// Each document's tokens are clustered into at most `per_doc` centroids now (if
+    /// documents are loaded) and on every later document load; shorter documents keep
+    /// their tokens. 4-8 centroids per document is a good start.
+    #[wasm_bindgen]
+    pub fn set_token_centroids(&mut self, per_doc: usize) {
+        self.centroids_per_doc = per_doc;
+        self.rebuild_centroids();
+    }
+
+    /// Approximate top `k` hits (best first) from token-centroid estimates
+    ///
+    /// Documents are ranked by their centroid estimate and rescored exactly, best
+    /// estimate first, until the next estimate falls more than `margin` below the
+    /// k-th best exact score. Returned scores are always exact; `margin` trades speed
+    /// (0: fewest rescored documents) for recall (Infinity: exact top k). Estimates
+    /// are in score units, so margins for normalized scores are per query token.
+    /// Needs `set_token_centroids()` and the DotProduct metric; otherwise every
+    /// document is scored.
+    #[wasm_bindgen]
+    pub fn search_preloaded_centroid(
+        &self,
+        query_flat: &[f32],
+        query_tokens: usize,
+        k: usize,
+        margin: f32,
+    ) -> Result<SearchHits, JsValue> {
+        self.search_centroid_impl(query_flat, query_tokens, k, margin, false)
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    /// Normalized variant of `search_preloaded_centroid`
+    #[wasm_bindgen]
+    pub fn search_preloaded_centroid_normalized(
+        &self,
+        query_flat: &[f32],
+        query_tokens: usize,
+        k: usize,
+        margin: f32,
+    ) -> Result<SearchHits, JsValue> {
+        self.search_centroid_impl(query_flat, query_tokens, k, margin, true)
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    fn search_centroid_impl(
+        &self,
+        query_flat: &[f32],
+        query_tokens: usize,
+        k: usize,
+        margin: f32,
+        normalized: bool,
+    ) -> Result<SearchHits, String> {
+        let centroids = self.token_centroids.as_ref().filter(|_| self.config.metric() == Metric::DotProduct);
+        let Some(centroids) = centroids.filter(|_| k > 0) else {
+            return self.search_top_k_impl(query_flat, query_tokens, k, normalized);
+        };
+        let docs_ref = self.documents.borrow();
+        let docs = docs_ref.as_ref().ok_or("No documents loaded. Call load_documents() first.")?;
+
+        self.check_query_input(query_flat, query_tokens, docs.embedding_dim)?;
+        self.begin_search(|| format!(
+            "op=centroid docs={} k={} margin={} centroids_per_doc={:.1} query_tokens={} dim={} normalized={}",
+            docs.num_docs(), k, margin, centroids.mean_per_doc(), query_tokens, docs.embedding_dim, normalized
+        ));
+        let (query_data, active_query_tokens) =
+            self.prepare_query_tokens(query_flat, query_tokens, &[], docs.embedding_dim)?;
+        let ctx = self.score_context(normalized, Metric::DotProduct, &query_data, None, docs.embedding_dim);
+
+        let mut order: Vec<(usize, f32)> = (0..docs.num_docs())
+            .map(|doc| {
+                let estimate: f32 = centroids.token_estimates(doc, &query_data).sum();
+                (doc, ctx.finish(estimate, active_query_tokens, docs.doc_tokens()[doc]))
+            })
+            .collect();
+        order.sort_by(|&a, &b| rank_cmp(a, b));
+
+        let (scored, scores) = self.score_by_estimate(docs, &query_data, active_query_tokens, &order, k, margin.max(0.0), &ctx);
+        self.finish_search(&ctx);
+
+        Ok(SearchHits::top_k(&scored, &scores, k).with_degradation(self.last_query_degradation()))
+    }
+
+    /// Keep a preview corpus of the first `max_tokens` tokens of every document (0 disables)
+    ///
+    /// The preview is built now (if documents are loaded) and on every later document
+    /// load. `search_preview()` scores it at a fraction of the full cost, so the UI can
+    /// show provisional results while the exact search (e.g. `search_preloaded_async`)
+    /// completes. Costs up to `max_tokens` extra tokens of memory per document.
+    #[wasm_bindgen]
+    pub fn set_preview_tokens(&mut self, max_tokens: usize) {
+        self.preview_tokens = max_tokens;
+        self.rebuild_preview();
+    }
+
+    /// Keep one pooled vector per document for `search_pooled()` (`Pooling.None` disables)
+    ///
+    /// Vectors (the mean of each document's tokens, or its first token) are built now
+    /// (if documents are loaded) and on every later document load, at
+    /// `4 × embedding_dim` bytes per document.
+    #[wasm_bindgen]
+    pub fn set_pooling(&mut self, pooling: Pooling) {
+        self.pooling = pooling;
+        self.rebuild_pooled();
+    }
+
+    /// Top `k` documents (best first, 0 = all) by cosine of their pooled vector with
+    /// `query_pooled`, a single query vector (e.g. the encoder's pooled or [CLS] output)
+    ///
+    /// A coarse first pass: pass the hits' `indices` to `rerank()` to score them with
+    /// full MaxSim. Scores are cosine times the norm of `query_pooled`. Needs
+    /// `set_pooling()`.
+    #[wasm_bindgen]
+    pub fn search_pooled(&self, query_pooled: &[f32], k: usize) -> Result<SearchHits, JsValue> {
+        self.search_pooled_impl(query_pooled, k).map_err(|e| JsValue::from_str(&e))
+    }
+
+    fn search_pooled_impl(&self, query_pooled: &[f32], k: usize) -> Result<SearchHits, String> {
+        let pooled = self.pooled.as_ref().ok_or("No pooled vectors. Call set_pooling() and load documents first.")?;
+        if query_pooled.len() != pooled.embedding_dim() {
+            return Err(format!("Pooled query must have {} values", pooled.embedding_dim()));
+        }
+        let scores = pooled.scores(query_pooled);
+        self.begin_search(|| format!("op=pooled docs={} k={} dim={}", scores.len(), k, pooled.embedding_dim()));
+        let all: Vec<usize> = (0..scores.len()).collect();
+        let k = if k == 0 { scores.len() } else { k };
+        Ok(SearchHits::top_k(&all, &scores, k))
+    }
+
+    /// Build an HNSW graph over the pooled vectors at every document load (`m` = 0
+    /// disables), for `search_preloaded_hnsw()`
+    ///
+    /// # Arguments
+    /// * `m` - Links per document and layer (2m on the bottom layer; 16 is a good start)
+    /// * `ef_construction` - Beam width while inserting (0 = 100); wider is slower to
+    ///   build and gives a better graph
+    ///
+    /// The graph is built now (if documents are loaded and `set_pooling()` is on) at
+    /// roughly `num_docs × ef_construction` dot products, which is long for large
+    /// corpora in a browser: build it offline with `export_hnsw_graph()` and ship it
+    /// with `load_hnsw_graph()` instead.
+    #[wasm_bindgen]
+    pub fn set_hnsw(&mut self, m: usize, ef_construction: usize) {
+        self.hnsw_params = match m {
+            0 => (0, 0),
+            m => (m, if ef_construction == 0 { hnsw::DEFAULT_EF_CONSTRUCTION } else { ef_construction }),
+        };
+        self.rebuild_pooled();
+    }
+
+    /// Serialized HNSW graph of the preloaded documents, for `load_hnsw_graph()`
+    ///
+    /// The blob records the pooling and the fingerprint of the loaded index, so it is
+    /// only accepted next to the same documents.
+    #[wasm_bindgen]
+    pub fn export_hnsw_graph(&self) -> Result<Vec<u8>, JsValue> {
+        let (Some(pooled), Some(graph)) = (&self.pooled, &self.hnsw) else {
+            return Err(JsValue::from_str("No HNSW graph. Call set_pooling() and set_hnsw() first."));
+        };
+        Ok(graph.to_bytes(self.index_fingerprint.unwrap_or(0), pooled.pooling()))
+    }
+
+    /// Use a graph written by `export_hnsw_graph()` for the loaded documents instead of
+    /// building one (leave `set_hnsw()` off, or the graph is also built at load)
+    ///
+    /// Needs `set_pooling()` with the pooling the graph was built with. A graph for
+    /// another index (fingerprints differ, when both are known) or another number of
+    /// documents is rejected. A blob that can't be read throws an `Error` named like
+    /// `load_index()`'s (`NotAnIndex`, `UnsupportedVersion`, `CorruptHeader`). The
+    /// graph is dropped at the next document load.
+    #[wasm_bindgen]
+    pub fn load_hnsw_graph(&mut self, bytes: &[u8]) -> Result<(), JsValue> {
+        let parsed = HnswGraph::from_bytes(bytes)
+            .map_err(|e| formats::index::artifact_js_error("HNSW graph", hnsw::GRAPH_VERSION, e))?;
+        self.load_hnsw_graph_impl(parsed).map_err(|e| JsValue::from_str(&e))
+    }
+
+    fn load_hnsw_graph_impl(&mut self, (graph, fingerprint, pooling): (HnswGraph, u32, u32)) -> Result<(), String> {
+        let pooled = self.pooled.as_ref().ok_or("No pooled vectors. Call set_pooling() and load documents first.")?;
+        if pooling != pooled.pooling() as u32 {
+            return Err(format!("HNSW graph was built with pooling {} (instance uses {:?})", pooling, pooled.pooling()));
+        }
+        if let Some(loaded) = self.index_fingerprint.filter(|&loaded| fingerprint != 0 && loaded != fingerprint) {
+            return Err(format!("HNSW graph is for index {:08x}, loaded index is {:08x}", fingerprint, loaded));
+        }
+        let num_docs = pooled.vectors().len() / pooled.embedding_dim();
+        if graph.num_nodes() != num_docs {
+            return Err(format!("HNSW graph has {} documents, {} are loaded", graph.num_nodes(), num_docs));
+        }
+        self.hnsw = Some(graph);
+        Ok(())
+    }
+
+    /// Top `k` hits (best first) of `search_preloaded` among the documents whose pooled
+    /// vectors are nearest to the pooled query in the HNSW graph
+    ///
+    /// The query is pooled like the documents (`set_pooling()`); the graph search keeps
+    /// a beam of `ef` documents (at least `k`), all of which are scored with full
+    /// MaxSim. Approximate: a document the graph doesn't reach is missed, less often
+    /// with a wider `ef`. Needs `set_hnsw()` or `load_hnsw_graph()`.
+    #[wasm_bindgen]
+    pub fn search_preloaded_hnsw(&self, query_flat: &[f32], query_tokens: usize, k: usize, ef: usize) -> Result<SearchHits, JsValue> {
+        self.search_hnsw_impl(query_flat, query_tokens, k, ef, false)
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    /// Normalized variant of `search_preloaded_hnsw`
+    #[wasm_bindgen]
+    pub fn search_preloaded_hnsw_normalized(
+        &self,
+        query_flat: &[f32],
+        query_tokens: usize,
+        k: usize,
+        ef: usize,
+    ) -> Result<SearchHits, JsValue> {
+        self.search_hnsw_impl(query_flat, query_tokens, k, ef, true)
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    fn search_hnsw_impl(&self, query_flat: &[f32], query_tokens: usize, k: usize, ef: usize, normalized: bool) -> Result<SearchHits, String> {
+        let (Some(pooled), Some(graph)) = (&self.pooled, &self.hnsw) else {
+            return Err("No HNSW graph. Call set_pooling() and set_hnsw() (or load_hnsw_graph()) first.".to_string());
+        };
+        if k == 0 {
+            return Err("k must be at least 1".to_string());
+        }
+        let docs_ref = self.documents.borrow();
+        let docs = docs_ref.as_ref().ok_or("No documents loaded. Call load_documents() first.")?;
+        self.check_query_input(query_flat, query_tokens, docs.embedding_dim)?;
+
+        let query_pooled = pooled.pool_query(query_flat);
+        let ef = ef.max(k);
+        let candidates: Vec<usize> = graph
+            .search(&query_pooled, ef, ef, pooled.vectors(), pooled.embedding_dim())
+            .into_iter()
+            .map(|(doc, _)| doc)
+            .collect();
+        self.begin_rerank_search(docs, &candidates, query_tokens, normalized, "hnsw");
+        let (query_data, active_query_tokens) =
+            self.prepare_query_tokens(query_flat, query_tokens, &[], docs.embedding_dim)?;
+        Ok(self.rerank_prepared(docs, &query_data, active_query_tokens, &candidates, normalized).page(0, k))
+    }
+
+    /// Provisional scores from the preview corpus (one per document, original order)
+    ///
+    /// A lower bound of the exact `search_preloaded` score: only preview tokens can
+    /// match each query token.
+    #[wasm_bindgen]
+    pub fn search_preview(&self, query_flat: &[f32], query_tokens: usize) -> Result<Vec<f32>, JsValue> {
+        self.search_preview_impl(query_flat, query_tokens, false)
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    /// Normalized variant of `search_preview`
+    #[wasm_bindgen]
+    pub fn search_preview_normalized(&self, query_flat: &[f32], query_tokens: usize) -> Result<Vec<f32>, JsValue> {
+        self.search_preview_impl(query_flat, query_tokens, true)
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    fn search_preview_impl(&self, query_flat: &[f32], query_tokens: usize, normalized: bool) -> Result<Vec<f32>, String> {
+        let preview = self.preview.as_ref()
+            .ok_or("No preview corpus. Call set_preview_tokens() and load documents first.")?;
+        self.search_store(preview, query_flat, query_tokens, &[], normalized, self.config.metric(), None)
+    }
+
+    /// Score a sample of queries with the preloaded documents at f32, f16, int8 and
+    /// binary precision, reporting each level's rank correlation and top-k overlap
+    /// with f32 (plus its storage size), to pick the cheapest precision that meets a
+    /// quality bar on your own data
+    ///
+    /// # Arguments
+    /// * `sample_queries` - Flat embeddings of the sample queries, back to back
+    /// * `query_tokens` - Token count of each sample query
+    /// * `k` - Cutoff of the top-k overlap
+    #[wasm_bindgen]
+    pub fn quantization_impact_report(
+        &self,
+        sample_queries: &[f32],
+        query_tokens: &[usize],
+        k: usize,
+    ) -> Result<QuantizationReport, JsValue> {
+        experiment::quantization_impact(self, sample_queries, query_tokens, k.max(1))
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    /// Deterministic random sample of `k` preloaded document indices (ascending)
+    ///
+    /// Drawn from the instance seed (`MaxSimConfig.seed`): the same seed, corpus size
+    /// and `k` give the same sample on every run and build, e.g. for evaluation subsets.
+    #[wasm_bindgen]
+    pub fn sample_documents(&self, k: usize) -> Result<Vec<u32>, JsValue> {
+        let num_docs = self.documents.borrow().as_ref()
+            .ok_or_else(|| JsValue::from_str("No documents loaded. Call load_documents() first."))?
+            .num_docs();
+        let mut rng = self.config.rng(rng::STREAM_DOCUMENT_SAMPLE);
+        Ok(rng.sample(num_docs, k).into_iter().map(|doc| doc as u32).collect())
+    }
+
+    /// Number of clusters of the current ANN index (0 when disabled)
+    #[wasm_bindgen]
+    pub fn ann_clusters(&self) -> usize {
+        self.ann.as_ref().map_or(0, |ann| ann.num_clusters())
+    }
+
+    /// Approximate search: exact MaxSim over the documents of the `nprobe` clusters
+    /// nearest to the query, returning the top `k` hits (best first)
+    #[wasm_bindgen]
+    pub fn search_preloaded_approx(
+        &self,
+        query_flat: &[f32],
+        query_tokens: usize,
+        nprobe: usize,
+        k: usize,
+    ) -> Result<SearchHits, JsValue> {
+        let docs_ref = self.documents.borrow();
+        let docs = docs_ref.as_ref()
+            .ok_or_else(|| JsValue::from_str("No documents loaded. Call load_documents() first."))?;
+        let ann = self.ann.as_ref()
+            .ok_or_else(|| JsValue::from_str("No ANN index. Call set_ann_clusters() first."))?;
+
+        self.approx_hits(docs, ann, query_flat, query_tokens, nprobe, k)
+            .map(|hits| hits.with_degradation(self.last_query_degradation()))
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    // Approximate top-k over any store clustered by `ann` (the preloaded corpus or a copy of it)
+    fn approx_hits(
+        &self,
+        docs: &PreloadedDocuments,
+        ann: &AnnIndex,
+        query_flat: &[f32],
+        query_tokens: usize,
+        nprobe: usize,
+        k: usize,
+    ) -> Result<SearchHits, String> {
+        self.check_query_input(query_flat, query_tokens, docs.embedding_dim)?;
+
+        let metric = self.config.metric();
+        self.begin_search(|| format!(
+            "op=approx docs={} clusters={} nprobe={} query_tokens={} dim={} kernel={} metric={}",
+            docs.num_docs(), ann.num_clusters(), nprobe, query_tokens,
+            docs.embedding_dim, dot_kernel_name(docs.embedding_dim), metric.name()
+        ));
+        let (query_data, active_query_tokens) =
+            self.prepare_query_tokens(query_flat, query_tokens, &[], docs.embedding_dim)?;
+        let candidates = ann.candidates(&query_data, nprobe);
+        self.trace.borrow_mut().record(|| format!("approx candidates={}", candidates.len()));
+
+        let ctx = self.score_context(false, metric, &query_data, None, docs.embedding_dim);
+        let scores = self.score_candidates(docs, &query_data, active_query_tokens, &candidates, &ctx);
+        self.finish_search(&ctx);
+        Ok(SearchHits::top_k(&candidates, &scores, k))
+    }
+
+    /// Fuse MaxSim scores with external scores (e.g. BM25), one per document
+    ///
+    /// # Arguments
+    /// * `maxsim_scores` - MaxSim score of every document
+    /// * `external_scores` - External score of every document (NaN: not retrieved)
+    /// * `method` - WeightedSum (min-max normalized) or ReciprocalRank (k = 60)
+    /// * `weight` - Share of the external scores in [0, 1]
+    ///
+    /// # Returns
+    /// Float32Array of fused scores (higher is better), in document order
+    #[wasm_bindgen]
+    pub fn fuse_scores(
+        &self,
+        maxsim_scores: &[f32],
+        external_scores: &[f32],
+        method: FusionMethod,
+        weight: f32,
+    ) -> Result<Vec<f32>, JsValue> {
+        fusion::fuse(maxsim_scores, external_scores, method, weight)
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    /// Search the preloaded documents and fuse the scores with `external_scores`,
+    /// returning the top `k` fused hits (0 = all), best first
+    ///
+    /// The inputs of each hit stay available as `maxsim_scores` and `external_scores`.
+    #[wasm_bindgen]
+    pub fn search_and_fuse(
+        &self,
+        query_flat: &[f32],
+        query_tokens: usize,
+        external_scores: &[f32],
+        method: FusionMethod,
+        weight: f32,
+        k: usize,
+    ) -> Result<SearchHits, JsValue> {
+        let maxsim = self.search_preloaded_impl(query_flat, query_tokens, &[], self.config.normalized(), self.config.metric(), None)?;
+        let fused = fusion::fuse(&maxsim, external_scores, method, weight)
+            .map_err(|e| JsValue::from_str(&e))?;
+        let k = if k == 0 { fused.len() } else { k };
+        Ok(SearchHits::fused(&maxsim, external_scores, &fused, k).with_degradation(self.last_query_degradation()))
+    }
+
+    /// Rerank an explicit subset of the preloaded documents (e.g. BM25 candidates)
+    ///
+    /// Only the named documents are scored, straight from the preloaded store (no
+    /// re-upload of their embeddings). Returns every candidate, best first.
+    ///
+    /// # Arguments
+    /// * `query_flat` - Flat query embedding (query_tokens × embedding_dim)
+    /// * `query_tokens` - Number of query tokens
+    /// * `candidate_indices` - Indices of the preloaded documents to score
+    #[wasm_bindgen]
+    pub fn rerank(
+        &self,
+        query_flat: &[f32],
+        query_tokens: usize,
+        candidate_indices: &[usize],
+    ) -> Result<SearchHits, JsValue> {
+        self.rerank_impl(query_flat, query_tokens, candidate_indices, false, "rerank")
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    /// Normalized variant of `rerank`
+    #[wasm_bindgen]
+    pub fn rerank_normalized(
+        &self,
+        query_flat: &[f32],
+        query_tokens: usize,
+        candidate_indices: &[usize],
+    ) -> Result<SearchHits, JsValue> {
+        self.rerank_impl(query_flat, query_tokens, candidate_indices, true, "rerank")
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    /// Search only the preloaded documents whose tags match `tag_mask`
+    ///
+    /// A document matches when `doc_tag & tag_mask != 0`; the others are skipped
+    /// before scoring, so a selective filter saves the corresponding compute.
+    /// Returns the matching documents, best first. Requires documents loaded with
+    /// `load_documents_with_metadata()`.
+    ///
+    /// # Arguments
+    /// * `query_flat` - Flat query embedding (query_tokens × embedding_dim)
+    /// * `query_tokens` - Number of query tokens
+    /// * `tag_mask` - Tag bits to accept
+    #[wasm_bindgen]
+    pub fn search_preloaded_filtered(
+        &self,
+        query_flat: &[f32],
+        query_tokens: usize,
+        tag_mask: u32,
+    ) -> Result<SearchHits, JsValue> {
+        let candidates = self.matching_documents(tag_mask).map_err(|e| JsValue::from_str(&e))?;
+        self.rerank_impl(query_flat, query_tokens, &candidates, false, "filtered")
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    /// Normalized variant of `search_preloaded_filtered`
+    #[wasm_bindgen]
+    pub fn search_preloaded_filtered_normalized(
+        &self,
+        query_flat: &[f32],
+        query_tokens: usize,
+        tag_mask: u32,
+    ) -> Result<SearchHits, JsValue> {
+        let candidates = self.matching_documents(tag_mask).map_err(|e| JsValue::from_str(&e))?;
+        self.rerank_impl(query_flat, query_tokens, &candidates, true, "filtered")
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    /// Top `k_groups` groups (0 = all) by the scores of their best `k_per_group`
+    /// documents, each with those documents (see `GroupedHits`)
+    ///
+    /// Every document is scored as in `search_preloaded`; `group_score` picks how a
+    /// group's kept scores combine (see groups.rs). Requires documents loaded with
+    /// `load_documents_grouped()`.
+    #[wasm_bindgen]
+    pub fn search_grouped(
+        &self,
+        query_flat: &[f32],
+        query_tokens: usize,
+        k_groups: usize,
+        k_per_group: usize,
+        group_score: GroupScore,
+    ) -> Result<GroupedHits, JsValue> {
+        self.search_grouped_impl(query_flat, query_tokens, k_groups, k_per_group, group_score, false)
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    /// Normalized variant of `search_grouped`
+    #[wasm_bindgen]
+    pub fn search_grouped_normalized(
+        &self,
+        query_flat: &[f32],
+        query_tokens: usize,
+        k_groups: usize,
+        k_per_group: usize,
+        group_score: GroupScore,
+    ) -> Result<GroupedHits, JsValue> {
+        self.search_grouped_impl(query_flat, query_tokens, k_groups, k_per_group, group_score, true)
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    fn search_grouped_impl(
+        &self,
+        query_flat: &[f32],
+        query_tokens: usize,
+        k_groups: usize,
+        k_per_group: usize,
+        group_score: GroupScore,
+        normalized: bool,
+    ) -> Result<GroupedHits, String> {
+        if self.doc_groups.is_empty() {
+            return Err("No document groups loaded. Call load_documents_grouped() first.".to_string());
+        }
+        if k_per_group == 0 {
+            return Err("k_per_group must be at least 1".to_string());
+        }
+        let docs_ref = self.documents.borrow();
+        let docs = docs_ref.as_ref().ok_or("No documents loaded. Call load_documents() first.")?;
+        let scores = self.search_store(docs, query_flat, query_tokens, &[], normalized, self.config.metric(), None)?;
+        let k_groups = if k_groups == 0 { usize::MAX } else { k_groups };
+        Ok(GroupedHits::rank(&scores, &self.doc_groups, k_groups, k_per_group, group_score))
+    }
+
+    /// Prepare a query once for repeated searches (pagination, filter changes,
+    /// several collections)
+    ///
+    /// Applies the stopmask, the query limit and auto-normalization now; the
+    /// `*_prepared` searches reuse the result. Changing any of those settings makes
+    /// the handle stale (searches with it then fail).
+    ///
+    /// # Arguments
+    /// * `query_flat` - Flat query embedding (query_tokens × embedding_dim)
+    /// * `query_tokens` - Number of query tokens
+    #[wasm_bindgen]
+    pub fn prepare_query(&self, query_flat: &[f32], query_tokens: usize) -> Result<QueryHandle, JsValue> {
+        self.prepare_query_impl(query_flat, query_tokens).map_err(|e| JsValue::from_str(&e))
+    }
+
+    fn prepare_query_impl(&self, query_flat: &[f32], query_tokens: usize) -> Result<QueryHandle, String> {
+        if query_tokens == 0 || query_flat.is_empty() || !query_flat.len().is_multiple_of(query_tokens) {
+            return Err("Query cannot be empty and must hold query_tokens × embedding_dim values".to_string());
+        }
+        let embedding_dim = query_flat.len() / query_tokens;
+        self.check_query_input(query_flat, query_tokens, embedding_dim)?;
+        let (query, active_tokens) = self.prepare_query_tokens(query_flat, query_tokens, &[], embedding_dim)?;
+        Ok(QueryHandle::new(
+            query.into_owned(),
+            active_tokens,
+            query_tokens,
+            embedding_dim,
+            self.last_query_degradation(),
+            self.query_settings,
+        ))
+    }
+
+    /// `search_preloaded()` with a prepared query
+    #[wasm_bindgen]
+    pub fn search_preloaded_prepared(&self, query: &QueryHandle) -> Result<Vec<f32>, JsValue> {
+        self.search_prepared_impl(None, query, false).map_err(|e| JsValue::from_str(&e))
+    }
+
+    /// `search_preloaded_normalized()` with a prepared query
+    #[wasm_bindgen]
+    pub fn search_preloaded_prepared_normalized(&self, query: &QueryHandle) -> Result<Vec<f32>, JsValue> {
+        self.search_prepared_impl(None, query, true).map_err(|e| JsValue::from_str(&e))
+    }
+
+    /// `search_collection()` with a prepared query
+    #[wasm_bindgen]
+    pub fn search_collection_prepared(&self, name: &str, query: &QueryHandle) -> Result<Vec<f32>, JsValue> {
+        self.search_prepared_impl(Some(name), query, false).map_err(|e| JsValue::from_str(&e))
+    }
+
+    /// `search_collection_normalized()` with a prepared query
+    #[wasm_bindgen]
+    pub fn search_collection_prepared_normalized(&self, name: &str, query: &QueryHandle) -> Result<Vec<f32>, JsValue> {
+        self.search_prepared_impl(Some(name), query, true).map_err(|e| JsValue::from_str(&e))
+    }
+
+    /// `search_preloaded_filtered()` with a prepared query
+    #[wasm_bindgen]
+    pub fn search_preloaded_filtered_prepared(&self, query: &QueryHandle, tag_mask: u32) -> Result<SearchHits, JsValue> {
+        self.search_filtered_prepared_impl(query, tag_mask, false).map_err(|e| JsValue::from_str(&e))
+    }
+
+    /// `search_preloaded_filtered_normalized()` with a prepared query
+    #[wasm_bindgen]
+    pub fn search_preloaded_filtered_prepared_normalized(
+        &self,
+        query: &QueryHandle,
+        tag_mask: u32,
+    ) -> Result<SearchHits, JsValue> {
+        self.search_filtered_prepared_impl(query, tag_mask, true).map_err(|e| JsValue::from_str(&e))
+    }
+
+    // Search the preloaded documents (`collection` None) or a named collection
+    fn search_prepared_impl(&self, collection: Option<&str>, query: &QueryHandle, normalized: bool) -> Result<Vec<f32>, String> {
+        let docs_ref = self.documents.borrow();
+        let docs: &PreloadedDocuments = match collection {
+            None => docs_ref.as_ref().ok_or("No documents loaded. Call load_documents() first.")?,
+            Some(name) => match self.collections.get(name) {
+                Some(Some(docs)) => docs,
+                Some(None) => return Err(format!("Collection {:?} is empty. Call load_documents_into() first.", name)),
+                None => return Err(format!("Unknown collection {:?}", name)),
+            },
+        };
+        query.check(self.query_settings, docs.embedding_dim)?;
+        *self.last_degradation.borrow_mut() = query.degradation.clone();
+        self.begin_store_search(docs, query.active_tokens, normalized, self.config.metric());
+        Ok(self.search_store_prepared(docs, &query.tokens, query.active_tokens, normalized, self.config.metric(), None))
+    }
+
+    fn search_filtered_prepared_impl(&self, query: &QueryHandle, tag_mask: u32, normalized: bool) -> Result<SearchHits, String> {
+        let candidates = self.matching_documents(tag_mask)?;
+        let docs_ref = self.documents.borrow();
+        let docs = docs_ref.as_ref().ok_or("No documents loaded. Call load_documents() first.")?;
+        query.check(self.query_settings, docs.embedding_dim)?;
+        *self.last_degradation.borrow_mut() = query.degradation.clone();
+        self.begin_rerank_search(docs, &candidates, query.active_tokens, normalized, "filtered");
+        Ok(self.rerank_prepared(docs, &query.tokens, query.active_tokens, &candidates, normalized))
+    }
+
+    // Indices of the preloaded documents with a tag bit in `tag_mask`
+    fn matching_documents(&self, tag_mask: u32) -> Result<Vec<usize>, String> {
+        if self.doc_tags.is_empty() {
+            return Err("No document metadata loaded. Call load_documents_with_metadata() first.".to_string());
+        }
+        Ok((0..self.doc_tags.len()).filter(|&doc| self.doc_tags[doc] & tag_mask != 0).collect())
+    }
+
+    // Score `candidates` of the preloaded documents; `op` names the search in the trace
+    fn rerank_impl(
+        &self,
+        query_flat: &[f32],
+        query_tokens: usize,
+        candidates: &[usize],
+        normalized: bool,
+        op: &str,
+    ) -> Result<SearchHits, String> {
+        let docs_ref = self.documents.borrow();
+        let docs = docs_ref.as_ref()
+            .ok_or_else(|| "No documents loaded. Call load_documents() first.".to_string())?;
+        self.check_query_input(query_flat, query_tokens, docs.embedding_dim)?;
+        if let Some(&doc) = candidates.iter().find(|&&doc| doc >= docs.num_docs()) {
+            return Err(format!("Candidate {} out of range ({} documents)", doc, docs.num_docs()));
+        }
+
+        self.begin_rerank_search(docs, candidates, query_tokens, normalized, op);
+        let (query_data, active_query_tokens) =
+            self.prepare_query_tokens(query_flat, query_tokens, &[], docs.embedding_dim)?;
+        Ok(self.rerank_prepared(docs, &query_data, active_query_tokens, candidates, normalized))
+    }
+
+    // Start the trace of a candidate rescoring search before its query is prepared
+    fn begin_rerank_search(&self, docs: &PreloadedDocuments, candidates: &[usize], query_tokens: usize, normalized: bool, op: &str) {
+        self.begin_search(|| format!(
+            "op={} docs={} candidates={} query_tokens={} dim={} kernel={} metric={} normalized={}",
+            op, docs.num_docs(), candidates.len(), query_tokens,
+            docs.embedding_dim, dot_kernel_name(docs.embedding_dim), self.config.metric().name(), normalized
+        ));
+    }
+
+    // Score `candidates` (checked to be in range) with an already prepared query; the
+    // trace must already be started with `begin_rerank_search()`
+    fn rerank_prepared(
+        &self,
+        docs: &PreloadedDocuments,
+        query_data: &[f32],
+        active_query_tokens: usize,
+        candidates: &[usize],
+        normalized: bool,
+    ) -> SearchHits {
+        let metric = self.config.metric();
+        let ctx = self.score_context(normalized, metric, query_data, None, docs.embedding_dim);
+        let scores = self.score_candidates(docs, query_data, active_query_tokens, candidates, &ctx);
+        self.finish_search(&ctx);
+        SearchHits::top_k(candidates, &scores, candidates.len()).with_degradation(self.last_query_degradation())
+    }
+
+    /// Final step of a hybrid cascade: blend external scores (e.g. a cross-encoder run
+    /// over the top hits) into `hits` and return the final ranking, best first
+    ///
+    /// # Arguments
+    /// * `hits` - MaxSim hits from any search returning `SearchHits`
+    /// * `indices` - Document indices (from `hits.indices`) that were scored externally
+    /// * `scores` - External score of each document in `indices`
+    /// * `blend` - Weight of the external score in [0, 1] (0: MaxSim only, 1: external only)
+    ///
+    /// Both score sets are min-max normalized over the externally scored hits before
+    /// blending; hits without an external score follow with a score of -Infinity. The
+    /// original MaxSim and external scores stay available as `maxsim_scores` and
+    /// `external_scores`.
+    #[wasm_bindgen]
+    pub fn finalize_with_external_scores(
+        &self,
+        hits: &SearchHits,
+        indices: &[u32],
+        scores: &[f32],
+        blend: f32,
+    ) -> Result<SearchHits, JsValue> {
+        hits.blend_external(indices, scores, blend)
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    /// Load a ColBERTv2 residual-compressed index
+    ///
+    /// Arrays as exported from the official ColBERT toolchain (`centroids.pt`,
+    /// `buckets.pt` bucket weights, `codes.pt`, `residuals.pt`, `doclens.*.json`).
+    /// Tokens stay compressed in memory (~8-16× smaller than f32) and are decompressed
+    /// batch by batch during `search_residual()`; see residual.rs for the format.
+    ///
+    /// # Arguments
+    /// * `centroids` - Flat codebook (num_centroids × embedding_dim)
+    /// * `bucket_weights` - The 2^nbits residual values
+    /// * `codes` - Centroid index of every token, across all documents
+    /// * `residuals` - Packed residual bucket codes (embedding_dim · nbits / 8 bytes per token)
+    /// * `doc_tokens` - Token count for each document
+    /// * `embedding_dim` - Embedding dimension
+    /// * `nbits` - Bits per residual dimension (1, 2, 4 or 8)
+    #[wasm_bindgen]
+    #[allow(clippy::too_many_arguments)]
+    pub fn load_residual_index(
+        &mut self,
+        centroids: &[f32],
+        bucket_weights: &[f32],
+        codes: &[u32],
+        residuals: &[u8],
+        doc_tokens: &[usize],
+        embedding_dim: usize,
+        nbits: usize,
+    ) -> Result<(), JsValue> {
+        if doc_tokens.is_empty() {
+            return Err(JsValue::from_str("No documents to load"));
+        }
+        let mut compressed = ResidualDocuments::new(centroids, bucket_weights, embedding_dim, nbits)
+            .map_err(|e| JsValue::from_str(&e))?;
+        compressed.append(codes, residuals, doc_tokens).map_err(|e| JsValue::from_str(&e))?;
+        self.residual_documents = Some(compressed);
+        self.bump_generation("residual");
+        Ok(())
+    }
+
+    /// Start importing an index built offline by the `colbert-ai` Python package
+    ///
+    /// Pass the raw file contents (ArrayBuffer → Uint8Array) of the index directory:
+    /// `centroids.pt` and `buckets.pt` here, then every chunk with
+    /// `add_colbert_chunk()`, then `finish_colbert_import()`. nbits is read from the
+    /// bucket weights; `embedding_dim` is the `dim` of the index `metadata.json`.
+    /// The imported index is searched with `search_residual()`.
+    #[wasm_bindgen]
+    pub fn begin_colbert_import(
+        &mut self,
+        centroids_pt: &[u8],
+        buckets_pt: &[u8],
+        embedding_dim: usize,
+    ) -> Result<(), JsValue> {
+        let centroids = formats::colbert::read_centroids(centroids_pt).map_err(|e| JsValue::from_str(&e))?;
+        let (bucket_weights, nbits) =
+            formats::colbert::read_bucket_weights(buckets_pt).map_err(|e| JsValue::from_str(&e))?;
+        let import = ResidualDocuments::new(&centroids, &bucket_weights, embedding_dim, nbits)
+            .map_err(|e| JsValue::from_str(&e))?;
+        self.colbert_import = Some(import);
+        Ok(())
+    }
+
+    /// Append one chunk (`{chunk}.codes.pt`, `{chunk}.residuals.pt`, `doclens.{chunk}.json`)
+    /// Chunks must be added in chunk order; returns the number of documents imported so far
+    #[wasm_bindgen]
+    pub fn add_colbert_chunk(&mut self, codes_pt: &[u8], residuals_pt: &[u8], doclens_json: &str) -> Result<usize, JsValue> {
+        let import = self.colbert_import.as_mut()
+            .ok_or_else(|| JsValue::from_str("No import in progress. Call begin_colbert_import() first."))?;
+        let mut append = || -> Result<(), String> {
+            let codes = formats::colbert::read_codes(codes_pt)?;
+            let residuals = formats::colbert::read_residuals(residuals_pt)?;
+            let doc_tokens = formats::colbert::read_doclens(doclens_json)?;
+            import.append(&codes, residuals, &doc_tokens)
+        };
+        append().map_err(|e| JsValue::from_str(&e))?;
+        Ok(import.num_docs())
+    }
+
+    /// Finish the import and make it the residual index; returns the number of documents
+    #[wasm_bindgen]
+    pub fn finish_colbert_import(&mut self) -> Result<usize, JsValue> {
+        let import = self.colbert_import.take()
+            .ok_or_else(|| JsValue::from_str("No import in progress. Call begin_colbert_import() first."))?;
+        if import.num_docs() == 0 {
+            return Err(JsValue::from_str("No documents to load"));
+        }
+        let num_docs = import.num_docs();
+        self.residual_documents = Some(import);
+        self.bump_generation("residual");
+        Ok(num_docs)
+    }
+
+    /// Search the residual-compressed index (official MaxSim: raw sum)
+    #[wasm_bindgen]
+    pub fn search_residual(&self, query_flat: &[f32], query_tokens: usize) -> Result<Vec<f32>, JsValue> {
+        self.search_residual_impl(query_flat, query_tokens, false).map_err(|e| JsValue::from_str(&e))
+    }
+
+    /// Search the residual-compressed index with normalized MaxSim scores
+    #[wasm_bindgen]
+    pub fn search_residual_normalized(&self, query_flat: &[f32], query_tokens: usize) -> Result<Vec<f32>, JsValue> {
+        self.search_residual_impl(query_flat, query_tokens, true).map_err(|e| JsValue::from_str(&e))
+    }
+
+    /// Memory held by the residual-compressed index in bytes (0 when none is loaded)
+    #[wasm_bindgen]
+    pub fn residual_index_bytes(&self) -> usize {
+        self.residual_documents.as_ref().map_or(0, |docs| docs.memory_bytes())
+    }
+
+    fn search_residual_impl(&self, query_flat: &[f32], query_tokens: usize, normalized: bool) -> Result<Vec<f32>, String> {
+        let docs = self.residual_documents.as_ref()
+            .ok_or_else(|| "No residual index loaded. Call load_residual_index() first.".to_string())?;
+        let dim = docs.embedding_dim;
+        self.check_query_input(query_flat, query_tokens, dim)?;
+
+        let metric = self.config.metric();
+        self.begin_search(|| format!(
+            "op=residual docs={} nbits={} query_tokens={} dim={} kernel={} metric={} normalized={}",
+            docs.num_docs(), docs.nbits(), query_tokens, dim, dot_kernel_name(dim), metric.name(), normalized
+        ));
+        let (query_data, active_query_tokens) = self.prepare_query_tokens(query_flat, query_tokens, &[], dim)?;
+        let ctx = self.score_context(normalized, metric, &query_data, None, dim);
+
+        let mut scores = Vec::with_capacity(docs.num_docs());
+        docs.for_each_batch(|flat, doc_tokens| {
+            let doc_norms = call_doc_norms(metric, flat, dim);
+            let batch_ctx = ctx.with_doc_norms(&doc_norms).with_doc_ids(DocIds::From(scores.len()));
+            scores.extend(self.maxsim_batch_impl(&query_data, active_query_tokens, flat, doc_tokens, dim, &batch_ctx, None));
+        });
+        self.finish_search(&ctx);
+
+        Ok(scores)
+    }
+
+    /// Top `k` hits (best first) of `search_residual` among the candidates found
+    /// through the residual index's centroids, decompressing only those
+    ///
+    /// # Arguments
+    /// * `query_flat` - Flat query embeddings
+    /// * `query_tokens` - Number of query tokens
+    /// * `k` - Hits to return
+    /// * `ncells` - Centroids probed per query token (recall: more reaches more documents)
+    /// * `max_candidates` - Documents scored exactly, by best centroid interaction
+    ///   (0 = every document reached)
+    ///
+    /// Approximate: a document none of whose tokens sits in a probed centroid is never
+    /// scored. The postings (see postings.rs) are built on the first call. Near ties at
+    /// the cutoff are rescored in f32 when boundary escalation is enabled (see
+    /// `set_boundary_escalation()`).
+    #[wasm_bindgen]
+    pub fn search_residual_top_k(
+        &self,
+        query_flat: &[f32],
+        query_tokens: usize,
+        k: usize,
+        ncells: usize,
+        max_candidates: usize,
+    ) -> Result<SearchHits, JsValue> {
+        self.search_residual_top_k_impl(query_flat, query_tokens, k, ncells, max_candidates, false)
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    /// Normalized variant of `search_residual_top_k`
+    #[wasm_bindgen]
+    pub fn search_residual_top_k_normalized(
+        &self,
+        query_flat: &[f32],
+        query_tokens: usize,
+        k: usize,
+        ncells: usize,
+        max_candidates: usize,
+    ) -> Result<SearchHits, JsValue> {
+        self.search_residual_top_k_impl(query_flat, query_tokens, k, ncells, max_candidates, true)
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    fn search_residual_top_k_impl(
+        &self,
+        query_flat: &[f32],
+        query_tokens: usize,
+        k: usize,
+        ncells: usize,
+        max_candidates: usize,
+        normalized: bool,
+    ) -> Result<SearchHits, String> {
+        let docs = self.residual_documents.as_ref()
+            .ok_or_else(|| "No residual index loaded. Call load_residual_index() first.".to_string())?;
+        if k == 0 || ncells == 0 {
+            return Err("k and ncells must be at least 1".to_string());
+        }
+        let dim = docs.embedding_dim;
+        self.check_query_input(query_flat, query_tokens, dim)?;
+
+        let metric = self.config.metric();
+        self.begin_search(|| format!(
+            "op=residual_top_k docs={} ncells={} nbits={} query_tokens={} dim={} kernel={} metric={} normalized={}",
+            docs.num_docs(), ncells, docs.nbits(), query_tokens, dim, dot_kernel_name(dim), metric.name(), normalized
+        ));
+        let (query_data, active_query_tokens) = self.prepare_query_tokens(query_flat, query_tokens, &[], dim)?;
+        let candidates = docs.postings().candidates(&query_data, docs.centroids(), dim, ncells, max_candidates);
+        self.trace.borrow_mut().record(|| format!("residual candidates={}", candidates.len()));
+        let ctx = self.score_context(normalized, metric, &query_data, None, dim);
+
+        let mut scores = Vec::with_capacity(candidates.len());
+        docs.for_each_batch_of(&candidates, |flat, doc_tokens| {
+            let doc_norms = call_doc_norms(metric, flat, dim);
+            let batch = &candidates[scores.len()..scores.len() + doc_tokens.len()];
+            let batch_ctx = ctx.with_doc_norms(&doc_norms).with_doc_ids(DocIds::List(batch));
+            scores.extend(self.maxsim_batch_impl(&query_data, active_query_tokens, flat, doc_tokens, dim, &batch_ctx, None));
+        });
+        self.finish_search(&ctx);
+
+        let escalated = self.escalate_boundary(&candidates, &mut scores, k, &query_data, active_query_tokens, normalized, dim)?;
+        Ok(SearchHits::top_k(&candidates, &scores, k).with_degradation(self.last_query_degradation()).with_escalated(escalated))
+    }
+
+    /// Load documents product-quantized to `num_subspaces` bytes per token
+    ///
+    /// Codebooks (256 sub-centroids per subspace) are trained on the embeddings now,
+    /// or taken from `codebooks` when it is non-empty (`num_subspaces × 256 × sub_dim`
+    /// floats, e.g. exported by `pq_codebooks()` from an earlier load). Only the codes
+    /// are kept: searched with `search_pq()` or `search_pq_top_k()`, independently of
+    /// `load_documents()`; see pq.rs. Replaces a previously loaded PQ corpus.
+    ///
+    /// # Arguments
+    /// * `embeddings_data` - Flat array of all document embeddings
+    /// * `doc_tokens` - Token count for each document
+    /// * `embedding_dim` - Embedding dimension (a multiple of `num_subspaces`)
+    /// * `num_subspaces` - Sub-vectors per token, i.e. bytes per token (e.g. 16 at dim 128)
+    /// * `codebooks` - Pretrained codebooks, or empty to train them
+    #[wasm_bindgen]
+    pub fn load_documents_pq(
+        &mut self,
+        embeddings_data: &[f32],
+        doc_tokens: &[usize],
+        embedding_dim: usize,
+        num_subspaces: usize,
+        codebooks: &[f32],
+    ) -> Result<(), JsValue> {
+        self.load_documents_pq_impl(embeddings_data, doc_tokens, embedding_dim, num_subspaces, codebooks)
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    fn load_documents_pq_impl(
+        &mut self,
+        embeddings_data: &[f32],
+        doc_tokens: &[usize],
+        embedding_dim: usize,
+        num_subspaces: usize,
+        codebooks: &[f32],
+    ) -> Result<(), String> {
+        self.check_documents_input(embeddings_data, doc_tokens, embedding_dim)?;
+        let mut flat = Cow::Borrowed(embeddings_data);
+        if self.auto_normalize {
+            normalize_tokens(flat.to_mut(), embedding_dim);
+        }
+
+        let encoded = if codebooks.is_empty() {
+            PqDocuments::train(&flat, doc_tokens, embedding_dim, num_subspaces)?
+        } else {
+            let mut encoded = PqDocuments::new(codebooks, embedding_dim, num_subspaces)?;
+            encoded.encode(&flat, doc_tokens);
+            encoded
+        };
+        self.pq_documents = Some(encoded);
+        self.bump_generation("pq");
+        Ok(())
+    }
+
+    /// Load documents encoded offline with the given PQ codebooks
+    ///
+    /// The codebooks and codes are plain arrays, not a blob with a header, so shape
+    /// errors throw plain `Error`s; a PQ corpus saved by `export_state()` fails to
+    /// restore like the rest of the snapshot.
+    ///
+    /// # Arguments
+    /// * `codebooks` - Codebooks (`num_subspaces × 256 × sub_dim` floats)
+    /// * `codes` - `num_subspaces` sub-centroid indices per token, across all documents
+    /// * `doc_tokens` - Token count for each document
+    /// * `embedding_dim` - Embedding dimension
+    /// * `num_subspaces` - Sub-vectors per token
+    #[wasm_bindgen]
+    pub fn load_pq_index(
+        &mut self,
+        codebooks: &[f32],
+        codes: &[u8],
+        doc_tokens: &[usize],
+        embedding_dim: usize,
+        num_subspaces: usize,
+    ) -> Result<(), JsValue> {
+        if doc_tokens.is_empty() {
+            return Err(JsValue::from_str("No documents to load"));
+        }
+        let mut encoded = PqDocuments::new(codebooks, embedding_dim, num_subspaces)
+            .map_err(|e| JsValue::from_str(&e))?;
+        encoded.append_codes(codes, doc_tokens).map_err(|e| JsValue::from_str(&e))?;
+        self.pq_documents = Some(encoded);
+        self.bump_generation("pq");
+        Ok(())
+    }
+
+    /// Codebooks of the PQ corpus (empty when none is loaded), for reuse in later loads
+    #[wasm_bindgen]
+    pub fn pq_codebooks(&self) -> Vec<f32> {
+        self.pq_documents.as_ref().map_or_else(Vec::new, |pq| pq.codebooks().to_vec())
+    }
+
+    /// Search the product-quantized corpus
+    #[wasm_bindgen]
+    pub fn search_pq(&self, query_flat: &[f32], query_tokens: usize) -> Result<Vec<f32>, JsValue> {
+        self.search_pq_impl(query_flat, query_tokens, false).map_err(|e| JsValue::from_str(&e))
+    }
+
+    /// Search the product-quantized corpus with normalized MaxSim scores
+    #[wasm_bindgen]
+    pub fn search_pq_normalized(&self, query_flat: &[f32], query_tokens: usize) -> Result<Vec<f32>, JsValue> {
+        self.search_pq_impl(query_flat, query_tokens, true).map_err(|e| JsValue::from_str(&e))
+    }
+
+    fn search_pq_impl(&self, query_flat: &[f32], query_tokens: usize, normalized: bool) -> Result<Vec<f32>, String> {
+        let docs = self.pq_documents.as_ref()
+            .ok_or_else(|| "No PQ corpus loaded. Call load_documents_pq() first.".to_string())?;
+        let dim = docs.embedding_dim;
+        self.check_query_input(query_flat, query_tokens, dim)?;
+
+        self.begin_pq_search(docs, query_tokens, normalized);
+        let (query_data, active_query_tokens) = self.prepare_query_tokens(query_flat, query_tokens, &[], dim)?;
+        Ok(self.pq_scores(docs, &query_data, active_query_tokens, normalized))
+    }
+
+    /// Top `k` hits (best first) of `search_pq`, with near ties at the cutoff rescored
+    /// in f32 when boundary escalation is enabled (see `set_boundary_escalation()`)
+    #[wasm_bindgen]
+    pub fn search_pq_top_k(&self, query_flat: &[f32], query_tokens: usize, k: usize) -> Result<SearchHits, JsValue> {
+        self.search_pq_top_k_impl(query_flat, query_tokens, k, false).map_err(|e| JsValue::from_str(&e))
+    }
+
+    /// Normalized variant of `search_pq_top_k`
+    #[wasm_bindgen]
+    pub fn search_pq_top_k_normalized(&self, query_flat: &[f32], query_tokens: usize, k: usize) -> Result<SearchHits, JsValue> {
+        self.search_pq_top_k_impl(query_flat, query_tokens, k, true).map_err(|e| JsValue::from_str(&e))
+    }
+
+    fn search_pq_top_k_impl(&self, query_flat: &[f32], query_tokens: usize, k: usize, normalized: bool) -> Result<SearchHits, String> {
+        let docs = self.pq_documents.as_ref()
+            .ok_or_else(|| "No PQ corpus loaded. Call load_documents_pq() first.".to_string())?;
+        let dim = docs.embedding_dim;
+        self.check_query_input(query_flat, query_tokens, dim)?;
+
+        self.begin_pq_search(docs, query_tokens, normalized);
+        let (query_data, active_query_tokens) = self.prepare_query_tokens(query_flat, query_tokens, &[], dim)?;
+        let mut scores = self.pq_scores(docs, &query_data, active_query_tokens, normalized);
+        let all: Vec<usize> = (0..docs.num_docs()).collect();
+        let escalated = self.escalate_boundary(&all, &mut scores, k, &query_data, active_query_tokens, normalized, dim)?;
+        Ok(SearchHits::top_k(&all, &scores, k).with_degradation(self.last_query_degradation()).with_escalated(escalated))
+    }
+
+    // Start the trace of a PQ search before its query is prepared
+    fn begin_pq_search(&self, docs: &PqDocuments, query_tokens: usize, normalized: bool) {
+        self.begin_search(|| format!(
+            "op=pq docs={} subspaces={} query_tokens={} dim={} metric={} normalized={}",
+            docs.num_docs(), docs.num_subspaces(), query_tokens, docs.embedding_dim, self.config.metric().name(), normalized
+        ));
+    }
+
+    // Scores of every PQ document for a prepared query
+    fn pq_scores(&self, docs: &PqDocuments, query_data: &[f32], active_query_tokens: usize, normalized: bool) -> Vec<f32> {
+        let dim = docs.embedding_dim;
+        let metric = self.config.metric();
+        let ctx = self.score_context(normalized, metric, query_data, None, dim);
+
+        let tables = docs.lookup_tables(query_data);
+        let mut scores = Vec::with_capacity(docs.num_docs());
+        docs.for_each_document(&tables, |similarities, doc_norms, tokens| {
+            scores.push(if tokens == 0 || active_query_tokens == 0 {
+                0.0
+            } else {
+                ctx.score_document(similarities, |q_idx| q_idx * tokens, tokens, active_query_tokens, doc_norms)
+            });
+        });
+        self.finish_search(&ctx);
+
+        scores
+    }
+
+    /// Load documents quantized to 4 bits per dimension with per-group scales
+    ///
+    /// Every `group_size` consecutive dimensions of a token share a scale and zero
+    /// point; smaller groups are more accurate and cost 8 more bytes per group. Only
+    /// the quantized corpus is kept: searched with `search_int4()` or
+    /// `search_int4_top_k()`, independently of `load_documents()`; see int4.rs.
+    /// Replaces a previously loaded 4-bit corpus. `quantization_impact_report()`
+    /// estimates the ranking drift on your data.
+    ///
+    /// # Arguments
+    /// * `embeddings_data` - Flat array of all document embeddings
+    /// * `doc_tokens` - Token count for each document
+    /// * `embedding_dim` - Embedding dimension
+    /// * `group_size` - Dimensions per scale (even; the last group is shorter when it
+    ///   doesn't divide embedding_dim; 0 = the largest of 64, 32, ..., 2 dividing
+    ///   embedding_dim, else 64)
+    #[wasm_bindgen]
+    pub fn load_documents_int4(
+        &mut self,
+        embeddings_data: &[f32],
+        doc_tokens: &[usize],
+        embedding_dim: usize,
+        group_size: usize,
+    ) -> Result<(), JsValue> {
+        self.load_documents_int4_impl(embeddings_data, doc_tokens, embedding_dim, group_size)
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    fn load_documents_int4_impl(
+        &mut self,
+        embeddings_data: &[f32],
+        doc_tokens: &[usize],
+        embedding_dim: usize,
+        group_size: usize,
+    ) -> Result<(), String> {
+        self.check_documents_input(embeddings_data, doc_tokens, embedding_dim)?;
+        let mut flat = Cow::Borrowed(embeddings_data);
+        if self.auto_normalize {
+            normalize_tokens(flat.to_mut(), embedding_dim);
+        }
+        let group_size = if group_size == 0 { experiment::int4_group_size(embedding_dim) } else { group_size };
+        self.int4_documents = Some(Int4Documents::quantize(&flat, doc_tokens, embedding_dim, group_size)?);
+        self.bump_generation("int4");
+        Ok(())
+    }
+
+    /// Search the 4-bit corpus
+    #[wasm_bindgen]
+    pub fn search_int4(&self, query_flat: &[f32], query_tokens: usize) -> Result<Vec<f32>, JsValue> {
+        self.search_int4_impl(query_flat, query_tokens, false).map_err(|e| JsValue::from_str(&e))
+    }
+
+    /// Search the 4-bit corpus with normalized MaxSim scores
+    #[wasm_bindgen]
+    pub fn search_int4_normalized(&self, query_flat: &[f32], query_tokens: usize) -> Result<Vec<f32>, JsValue> {
+        self.search_int4_impl(query_flat, query_tokens, true).map_err(|e| JsValue::from_str(&e))
+    }
+
+    fn search_int4_impl(&self, query_flat: &[f32], query_tokens: usize, normalized: bool) -> Result<Vec<f32>, String> {
+        let docs = self.int4_documents.as_ref()
+            .ok_or_else(|| "No 4-bit corpus loaded. Call load_documents_int4() first.".to_string())?;
+        let dim = docs.embedding_dim;
+        self.check_query_input(query_flat, query_tokens, dim)?;
+
+        self.begin_int4_search(docs, query_tokens, normalized);
+        let (query_data, active_query_tokens) = self.prepare_query_tokens(query_flat, query_tokens, &[], dim)?;
+        Ok(self.int4_scores(docs, &query_data, active_query_tokens, normalized))
+    }
+
+    /// Top `k` hits (best first) of `search_int4`, with near ties at the cutoff
+    /// rescored in f32 when boundary escalation is enabled (see
+    /// `set_boundary_escalation()`)
+    #[wasm_bindgen]
+    pub fn search_int4_top_k(&self, query_flat: &[f32], query_tokens: usize, k: usize) -> Result<SearchHits, JsValue> {
+        self.search_int4_top_k_impl(query_flat, query_tokens, k, false).map_err(|e| JsValue::from_str(&e))
+    }
+
+    /// Normalized variant of `search_int4_top_k`
+    #[wasm_bindgen]
+    pub fn search_int4_top_k_normalized(&self, query_flat: &[f32], query_tokens: usize, k: usize) -> Result<SearchHits, JsValue> {
+        self.search_int4_top_k_impl(query_flat, query_tokens, k, true).map_err(|e| JsValue::from_str(&e))
+    }
+
+    fn search_int4_top_k_impl(&self, query_flat: &[f32], query_tokens: usize, k: usize, normalized: bool) -> Result<SearchHits, String> {
+        let docs = self.int4_documents.as_ref()
+            .ok_or_else(|| "No 4-bit corpus loaded. Call load_documents_int4() first.".to_string())?;
+        let dim = docs.embedding_dim;
+        self.check_query_input(query_flat, query_tokens, dim)?;
+
+        self.begin_int4_search(docs, query_tokens, normalized);
+        let (query_data, active_query_tokens) = self.prepare_query_tokens(query_flat, query_tokens, &[], dim)?;
+        let mut scores = self.int4_scores(docs, &query_data, active_query_tokens, normalized);
+        let all: Vec<usize> = (0..docs.num_docs()).collect();
+        let escalated = self.escalate_boundary(&all, &mut scores, k, &query_data, active_query_tokens, normalized, dim)?;
+        Ok(SearchHits::top_k(&all, &scores, k).with_degradation(self.last_query_degradation()).with_escalated(escalated))
+    }
+
+    // Start the trace of a 4-bit search before its query is prepared
+    fn begin_int4_search(&self, docs: &Int4Documents, query_tokens: usize, normalized: bool) {
+        self.begin_search(|| format!(
+            "op=int4 docs={} group_size={} query_tokens={} dim={} metric={} normalized={}",
+            docs.num_docs(), docs.group_size(), query_tokens, docs.embedding_dim, self.config.metric().name(), normalized
+        ));
+    }
+
+    // Scores of every 4-bit document for a prepared query
+    fn int4_scores(&self, docs: &Int4Documents, query_data: &[f32], active_query_tokens: usize, normalized: bool) -> Vec<f32> {
+        let dim = docs.embedding_dim;
+        let metric = self.config.metric();
+        let ctx = self.score_context(normalized, metric, query_data, None, dim);
+
+        let mut scores = Vec::with_capacity(docs.num_docs());
+        docs.for_each_document(query_data, |similarities, doc_norms, tokens| {
+            scores.push(if tokens == 0 || active_query_tokens == 0 {
+                0.0
+            } else {
+                ctx.score_document(similarities, |q_idx| q_idx * tokens, tokens, active_query_tokens, doc_norms)
+            });
+        });
+        self.finish_search(&ctx);
+
+        scores
+    }
+
+    /// Attach documents stored in a SharedArrayBuffer, searched with `search_shared()`
+    ///
+    /// The embeddings are not copied into this instance, so several workers can search
+    /// one corpus with a single copy in memory; see shared.rs for the cross-origin
+    /// isolation (COOP/COEP) headers `SharedArrayBuffer` requires. The buffer must
+    /// hold exactly `sum(doc_tokens) × embedding_dim` f32 values, and must not be
+    /// written while searches run. Replaces a previously attached buffer.
+    ///
+    /// # Arguments
+    /// * `buffer` - SharedArrayBuffer with the flat document embeddings
+    /// * `doc_tokens` - Token count of each document
+    /// * `embedding_dim` - Embedding dimension
+    #[wasm_bindgen]
+    pub fn attach_shared_documents(
+        &mut self,
+        buffer: &js_sys::SharedArrayBuffer,
+        doc_tokens: &[usize],
+        embedding_dim: usize,
+    ) -> Result<(), JsValue> {
+        if doc_tokens.is_empty() {
+            return Err(JsValue::from_str("No documents to load"));
+        }
+        if embedding_dim == 0 {
+            return Err(JsValue::from_str("Embedding dimension must be > 0"));
+        }
+        // Token counts come from the caller: an overflowing size must not wrap into a match
+        let byte_size = doc_tokens
+            .iter()
+            .try_fold(0usize, |total, &count| total.checked_add(count))
+            .and_then(|tokens| tokens.checked_mul(embedding_dim))
+            .and_then(|floats| floats.checked_mul(std::mem::size_of::<f32>()));
+        if byte_size != Some(buffer.byte_length() as usize) {
+            return Err(JsValue::from_str("Embeddings data size mismatch"));
+        }
+
+        let docs = SharedDocuments::new(js_sys::Float32Array::new(buffer), doc_tokens, embedding_dim);
+        if self.validation {
+            let mut first_doc = 0;
+            let mut invalid = Ok(());
+            docs.for_each_batch(false, |flat, doc_tokens| {
+                if invalid.is_ok() {
+                    invalid = validation::check_documents_from(flat, doc_tokens, embedding_dim, first_doc);
+                }
+                first_doc += doc_tokens.len();
+            });
+            invalid.map_err(|e| JsValue::from_str(&e))?;
+        }
+        self.shared_documents = Some(docs);
+        self.bump_generation("shared");
+        Ok(())
+    }
+
+    /// Drop the attached SharedArrayBuffer corpus (the buffer itself stays alive
+    /// for other workers)
+    #[wasm_bindgen]
+    pub fn detach_shared_documents(&mut self) {
+        if self.shared_documents.take().is_some() {
+            self.bump_generation("shared");
+        }
+    }
+
+    /// Search the SharedArrayBuffer corpus (official MaxSim: raw sum)
+    #[wasm_bindgen]
+    pub fn search_shared(&self, query_flat: &[f32], query_tokens: usize) -> Result<Vec<f32>, JsValue> {
+        self.search_shared_impl(query_flat, query_tokens, false).map_err(|e| JsValue::from_str(&e))
+    }
+
+    /// Search the SharedArrayBuffer corpus with normalized MaxSim scores
+    #[wasm_bindgen]
+    pub fn search_shared_normalized(&self, query_flat: &[f32], query_tokens: usize) -> Result<Vec<f32>, JsValue> {
+        self.search_shared_impl(query_flat, query_tokens, true).map_err(|e| JsValue::from_str(&e))
+    }
+
+    fn search_shared_impl(&self, query_flat: &[f32], query_tokens: usize, normalized: bool) -> Result<Vec<f32>, String> {
+        let docs = self.shared_documents.as_ref()
+            .ok_or_else(|| "No shared documents attached. Call attach_shared_documents() first.".to_string())?;
+        let dim = docs.embedding_dim;
+        self.check_query_input(query_flat, query_tokens, dim)?;
+
+        let metric = self.config.metric();
+        self.begin_search(|| format!(
+            "op=shared docs={} query_tokens={} dim={} kernel={} metric={} normalized={}",
+            docs.num_docs(), query_tokens, dim, dot_kernel_name(dim), metric.name(), normalized
+        ));
+        let (query_data, active_query_tokens) = self.prepare_query_tokens(query_flat, query_tokens, &[], dim)?;
+        let ctx = self.score_context(normalized, metric, &query_data, None, dim);
+
+        // The shared buffer is read-only here, so auto-normalization applies per batch
+        let mut scores = Vec::with_capacity(docs.num_docs());
+        docs.for_each_batch(self.auto_normalize, |flat, doc_tokens| {
+            let doc_norms = call_doc_norms(metric, flat, dim);
+            let batch_ctx = ctx.with_doc_norms(&doc_norms).with_doc_ids(DocIds::From(scores.len()));
+            scores.extend(self.maxsim_batch_impl(&query_data, active_query_tokens, flat, doc_tokens, dim, &batch_ctx, None));
+        });
+        self.finish_search(&ctx);
+
+        Ok(scores)
+    }
+
+    /// Corpus generation: starts at 0 and increases by one with every mutation of the
+    /// stored documents, so caches and snapshots can key their invalidation on it
+    ///
+    /// Mutations are document loads of any kind (`"load"`), normalization of loaded
+    /// documents by `set_auto_normalize` (`"normalize"`), collection changes
+    /// (`"collection"`), residual index loads (`"residual"`), PQ corpus loads
+    /// (`"pq"`), 4-bit corpus loads (`"int4"`), index deltas (`"delta"`), state
+    /// snapshot imports (`"restore"`) and shared buffers being attached or detached
+    /// (`"shared"`). Searches, settings and background maintenance (which doesn't
+    /// change scores) leave it unchanged.
+    #[wasm_bindgen]
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+
+    /// Call `callback` as `(generation, change)` after every generation bump, with
+    /// `change` the mutation kind listed in `generation()` (`undefined` stops it)
+    #[wasm_bindgen]
+    pub fn set_on_change(&mut self, callback: Option<js_sys::Function>) {
+        self.on_change = callback;
+    }
+
+    /// Report pathological batching cases to `callback`, or stop reporting (`undefined`)
+    ///
+    /// Called as `(kind, doc_indices, value)` with `kind` one of `"padding_waste"`,
+    /// `"dominant_document"` or `"collapsed_groups"` (see `set_watchdog_thresholds`),
+    /// the offending document indices (Uint32Array) and the measured value. Findings
+    /// are also recorded in the score trace.
+    #[wasm_bindgen]
+    pub fn set_watchdog(&mut self, callback: Option<js_sys::Function>) {
+        self.watchdog.callback = callback;
+    }
+
+    /// Watchdog thresholds
+    ///
+    /// # Arguments
+    /// * `max_padding_waste` - Fraction of a padded group's buffer that may be padding (default 0.1)
+    /// * `max_length_ratio` - Longest allowed document relative to the median length (default 8)
+    /// * `max_collapsed_fraction` - Fraction of documents that may be scored in groups of one (default 0.5)
+    #[wasm_bindgen]
+    pub fn set_watchdog_thresholds(&mut self, max_padding_waste: f32, max_length_ratio: f32, max_collapsed_fraction: f32) {
+        self.watchdog.max_padding_waste = max_padding_waste;
+        self.watchdog.max_length_ratio = max_length_ratio;
+        self.watchdog.max_collapsed_fraction = max_collapsed_fraction;
+    }
+
+    /// Whether auto-normalization is enabled
+    #[wasm_bindgen]
+    pub fn auto_normalize(&self) -> bool {
+        self.auto_normalize
+    }
+
+    /// Whether input validation is enabled
+    #[wasm_bindgen]
+    pub fn validation(&self) -> bool {
+        self.validation
+    }
+
+    /// Set the default similarity metric (DotProduct, Cosine or NegativeL2)
+    ///
+    /// Applies to every call that doesn't take an explicit metric. DotProduct is the
+    /// official MaxSim for L2-normalized embeddings; Cosine handles non-normalized
+    /// inputs and NegativeL2 matches checkpoints trained with squared-L2 late interaction.
+    #[wasm_bindgen]
+    pub fn set_metric(&mut self, metric: Metric) {
+        self.config.set_metric(metric);
+    }
+
+    /// Default similarity metric of this instance
+    #[wasm_bindgen]
+    pub fn metric(&self) -> Metric {
+        self.config.metric()
+    }
+
+    /// Set the default aggregation (Max, Softmax, TopK, LogSumExp or TopP) of this instance
+    ///
+    /// Takes effect on the next search; loaded documents are kept. Fails like
+    /// `set_config_checked()` when the aggregation doesn't fit the loaded corpus.
+    #[wasm_bindgen]
+    pub fn set_aggregation(&mut self, aggregation: Aggregation) -> Result<(), JsValue> {
+        let mut config = self.config;
+        config.set_aggregation(aggregation);
+        self.set_config_checked(&config)
+    }
+
+    /// Set the length penalty (None, Log or Sqrt) applied to every document score
+    ///
+    /// Takes effect on the next preloaded, `*_with_metric` or `*_default` batch
+    /// search; the official entry points ignore it. See `ScoreNorm`.
+    #[wasm_bindgen]
+    pub fn set_score_norm(&mut self, score_norm: ScoreNorm) {
+        self.config.set_score_norm(score_norm);
+    }
+
+    /// Replace the instance defaults (metric, aggregation, normalization)
+    ///
+    /// Takes effect on the next search without reloading documents; see
+    /// `set_config_checked()` to validate the configuration first.
+    #[wasm_bindgen]
+    pub fn set_config(&mut self, config: &MaxSimConfig) {
+        self.config = *config;
+    }
+
+    /// Replace the instance defaults, rejecting a configuration that doesn't fit the
+    /// loaded corpus
+    ///
+    /// TopK with a `top_k` larger than the longest document would silently average
+    /// whole documents, so it is rejected and the previous configuration is kept.
+    #[wasm_bindgen]
+    pub fn set_config_checked(&mut self, config: &MaxSimConfig) -> Result<(), JsValue> {
+        self.check_config(config).map_err(|e| JsValue::from_str(&e))?;
+        self.set_config(config);
+        Ok(())
+    }
+
+    // Validate a configuration against the loaded documents
+    fn check_config(&self, config: &MaxSimConfig) -> Result<(), String> {
+        let longest = self.documents.borrow().as_ref().map_or(0, |docs| docs.max_doc_tokens());
+        if config.aggregation() == Aggregation::TopK && longest > 0 && config.top_k() > longest {
+            return Err(format!(
+                "top_k {} exceeds the longest loaded document ({} tokens)",
+                config.top_k(), longest
+            ));
+        }
+        Ok(())
+    }
+
+    /// Copy of the instance defaults
+    #[wasm_bindgen]
+    pub fn config(&self) -> MaxSimConfig {
+        self.config
+    }
+
+    /// Set the persistent minimum interesting score used by the `*_thresholded` APIs
+    ///
+    /// Typically learned from past sessions or chosen by the app. Documents below it
+    /// are not emitted, and when per-token contributions are bounded (Cosine,
+    /// NegativeL2, or DotProduct with auto-normalization) documents that can no longer
+    /// reach it stop aggregating early.
+    #[wasm_bindgen]
+    pub fn set_score_threshold(&mut self, min_score: f32) {
+        self.score_threshold = Some(min_score);
+    }
+
+    /// Remove the score threshold (thresholded APIs then emit every document)
+    #[wasm_bindgen]
+    pub fn clear_score_threshold(&mut self) {
+        self.score_threshold = None;
+    }
+
+    /// Rescore near ties at the cutoff of quantized top-k searches in f32
+    ///
+    /// When at least two documents score within `epsilon` of the k-th best score of
+    /// `search_residual_top_k()`, `search_pq_top_k()` or `search_int4_top_k()`, all of
+    /// them are rescored exactly against the preloaded f32 documents (which must be the
+    /// same corpus, loaded with `load_documents()`) before the cutoff is drawn, so
+    /// quantization noise can't decide which of them make it. `SearchHits.escalated`
+    /// reports how many were rescored.
+    #[wasm_bindgen]
+    pub fn set_boundary_escalation(&mut self, epsilon: f32) -> Result<(), JsValue> {
+        if !(epsilon.is_finite() && epsilon >= 0.0) {
+            return Err(JsValue::from_str(&format!("epsilon must be finite and non-negative, got {}", epsilon)));
+        }
+        self.escalation_epsilon = Some(epsilon);
+        Ok(())
+    }
+
+    /// Stop rescoring near ties of quantized top-k searches
+    #[wasm_bindgen]
+    pub fn clear_boundary_escalation(&mut self) {
+        self.escalation_epsilon = None;
+    }
+
+    /// Map per-query-token scores in `[min, max]` onto 0–100 in `calibrate_scores()`
+    ///
+    /// `min` and `max` are normalized MaxSim scores (mean similarity per query token),
+    /// e.g. the scores of an unrelated and of a clearly relevant document for labelled
+    /// queries. Turns auto-calibration off.
+    #[wasm_bindgen]
+    pub fn set_calibration(&mut self, min: f32, max: f32) -> Result<(), JsValue> {
+        self.calibration = Some(Calibration::new(min, max).map_err(|e| JsValue::from_str(&e))?);
+        self.auto_calibration = false;
+        Ok(())
+    }
+
+    /// Learn the calibration range from the preloaded documents now (if loaded) and at
+    /// every later document load (see calibration.rs); disabling keeps the last range
+    #[wasm_bindgen]
+    pub fn set_auto_calibration(&mut self, enabled: bool) {
+        self.auto_calibration = enabled;
+        self.rebuild_calibration();
+    }
+
+    /// Remove the calibration range (and turn auto-calibration off)
+    #[wasm_bindgen]
+    pub fn clear_calibration(&mut self) {
+        self.calibration = None;
+        self.auto_calibration = false;
+    }
+
+    /// Current calibration range as `[min, max]` (empty when none), e.g. to persist a
+    /// learned range and restore it with `set_calibration()`
+    #[wasm_bindgen]
+    pub fn calibration_range(&self) -> Vec<f32> {
+        self.calibration.map_or(Vec::new(), |calibration| vec![calibration.min, calibration.max])
+    }
+
+    /// Scores of one query on the 0–100 calibrated relevance scale, for display
+    ///
+    /// # Arguments
+    /// * `scores` - Scores of any search with this query (e.g. `hits.scores`)
+    /// * `query_tokens` - Token count of the query (raw scores are divided by it)
+    /// * `normalized` - Whether `scores` are already normalized (a `*_normalized` search)
+    #[wasm_bindgen]
+    pub fn calibrate_scores(&self, scores: &[f32], query_tokens: usize, normalized: bool) -> Result<Vec<f32>, JsValue> {
+        self.calibrate_scores_impl(scores, query_tokens, normalized).map_err(|e| JsValue::from_str(&e))
+    }
+
+    fn calibrate_scores_impl(&self, scores: &[f32], query_tokens: usize, normalized: bool) -> Result<Vec<f32>, String> {
+        let calibration = self.calibration
+            .ok_or("No calibration range. Call set_calibration() or set_auto_calibration(true) first.")?;
+        if query_tokens == 0 {
+            return Err("query_tokens must be at least 1".to_string());
+        }
+        let per_token = if normalized { 1.0 } else { 1.0 / query_tokens as f32 };
+        Ok(scores.iter().map(|&score| calibration.scale(score * per_token)).collect())
+    }
+
+    /// Current score threshold, if any
+    #[wasm_bindgen]
+    pub fn score_threshold(&self) -> Option<f32> {
+        self.score_threshold
+    }
+
+    /// Enable or disable score tracing
+    ///
+    /// While enabled, every search records which internal path it took (uniform /
+    /// variable / individual batching), its length-group boundaries and the dot-product
+    /// kernel variant. Retrieve it with `last_trace()` and diff traces across versions
+    /// to pinpoint which internal path changed scores or latency.
+    #[wasm_bindgen]
+    pub fn set_trace_enabled(&mut self, enabled: bool) {
+        self.trace.get_mut().set_enabled(enabled);
+    }
+
+    /// Whether score tracing is enabled
+    #[wasm_bindgen]
+    pub fn trace_enabled(&self) -> bool {
+        self.trace.borrow().is_enabled()
+    }
+
+    /// Trace of the most recent search (one event per line, empty when tracing is off)
+    #[wasm_bindgen]
+    pub fn last_trace(&self) -> String {
+        self.trace.borrow().render()
+    }
+
+    /// Time spent in each stage (sort, pack, similarity, reduction) by the most recent
+    /// search (`profiling` builds only)
+    #[cfg(feature = "profiling")]
+    #[wasm_bindgen]
+    pub fn last_search_profile(&self) -> SearchProfile {
+        self.profile.borrow().clone()
+    }
+}
+
+// Query/document preparation and result emission shared by every search path
+impl MaxSimWasm {
+    // Make a freshly loaded corpus the preloaded documents (auto-normalize, ANN index)
+    fn install_documents(&mut self, preloaded: PreloadedDocuments) {
+        self.place_documents(Some(preloaded));
+        self.bump_generation("load");
+    }
+
+    // Replace (or remove) the preloaded documents and rebuild their derived indexes
+    fn place_documents(&mut self, preloaded: Option<PreloadedDocuments>) {
+        self.doc_tags.clear();
+        self.doc_groups.clear();
+        self.doc_chunks.clear();
+        self.index_fingerprint = None;
+        *self.documents.get_mut() = preloaded.map(|mut preloaded| {
+            if self.auto_normalize {
+                preloaded.normalize();
+            }
+            preloaded.set_packed(self.packed_layout);
+            Rc::new(preloaded)
+        });
+        self.rebuild_ann();
+        self.rebuild_preview();
+        self.rebuild_bounds();
+        self.rebuild_centroids();
+        self.rebuild_pooled();
+        self.rebuild_calibration();
+    }
+
+    // Start the trace and profile of a new search
+    fn begin_search(&self, header: impl FnOnce() -> String) {
+        self.trace.borrow_mut().begin(header);
+        self.profile.borrow_mut().reset();
+    }
+
+    // Advance the corpus generation and notify the change callback
+    fn bump_generation(&mut self, change: &str) {
+        self.generation += 1;
+        if let Some(callback) = &self.on_change {
+            // The mutation already happened; a throwing listener can't undo it
+            let _ = callback.call2(&JsValue::NULL, &JsValue::from(self.generation), &JsValue::from_str(change));
+        }
+    }
+
+    // Recluster the preloaded documents when ANN pruning is enabled
+    fn rebuild_ann(&mut self) {
+        self.ann = match (self.ann_clusters, self.documents.get_mut().as_ref()) {
+            (0, _) | (_, None) => None,
+            (num_clusters, Some(docs)) => Some(AnnIndex::build(docs, num_clusters, ann::DEFAULT_ITERATIONS)),
+        };
+    }
+
+    // Rebuild the document bounding boxes when enabled
+    fn rebuild_bounds(&mut self) {
+        self.bounds = match (self.score_bounds, self.documents.get_mut().as_ref()) {
+            (false, _) | (_, None) => None,
+            (true, Some(docs)) => Some(DocBounds::build(docs)),
+        };
+    }
+
+    // Recluster the token centroids of every document when enabled
+    fn rebuild_centroids(&mut self) {
+        self.token_centroids = match (self.centroids_per_doc, self.documents.get_mut().as_ref()) {
+            (0, _) | (_, None) => None,
+            (per_doc, Some(docs)) => Some(TokenCentroids::build(docs, per_doc)),
+        };
+    }
+
+    // Rebuild the pooled vectors when pooling is enabled, and their graph when it is
+    // built at load (an imported graph belongs to the previous documents and is dropped)
+    fn rebuild_pooled(&mut self) {
+        self.pooled = self.documents.get_mut().as_ref().and_then(|docs| PooledIndex::build(docs, self.pooling));
+        self.hnsw = match (&self.pooled, self.hnsw_params) {
+            (Some(pooled), (m, ef_construction)) if m > 0 => {
+                let mut rng = self.config.rng(rng::STREAM_HNSW);
+                Some(HnswGraph::build(pooled.vectors(), pooled.embedding_dim(), m, ef_construction, &mut rng))
+            }
+            _ => None,
+        };
+    }
+
+    // Learn the calibration range of the preloaded documents when auto-calibrating
+    fn rebuild_calibration(&mut self) {
+        if self.auto_calibration {
+            let mut rng = self.config.rng(rng::STREAM_CALIBRATION);
+            self.calibration = self.documents.get_mut().as_ref().and_then(|docs| Calibration::learn(docs, &mut rng));
+        }
+    }
+
+    // Rebuild the preview corpus when previews are enabled
+    fn rebuild_preview(&mut self) {
+        self.preview = match (self.preview_tokens, self.documents.get_mut().as_ref()) {
+            (0, _) | (_, None) => None,
+            (max_tokens, Some(docs)) => {
+                let mut preview = docs.truncated(max_tokens);
+                preview.set_packed(self.packed_layout);
+                Some(preview)
+            }
+        };
+    }
+
+    // Exact MaxSim over a subset of the preloaded documents (original indices)
+    // Candidates are gathered into page-sized flat batches for the adaptive batch path
+    fn score_candidates(
+        &self,
+        docs: &PreloadedDocuments,
+        query_data: &[f32],
+        query_tokens: usize,
+        candidates: &[usize],
+        ctx: &ScoreContext,
+    ) -> Vec<f32> {
+        let flush_floats = store::DEFAULT_PAGE_BYTES / std::mem::size_of::<f32>();
+        let mut scores = Vec::with_capacity(candidates.len());
+        let (mut flat, mut norms, mut tokens) = (Vec::new(), Vec::new(), Vec::new());
+
+        for (i, &doc_idx) in candidates.iter().enumerate() {
+            let (embeddings, token_norms) = docs.document(doc_idx);
+            flat.extend_from_slice(embeddings);
+            norms.extend_from_slice(token_norms);
+            tokens.push(token_norms.len());
+
+            if flat.len() >= flush_floats || i + 1 == candidates.len() {
+                let batch_ctx = ctx.with_doc_norms(&norms).with_doc_ids(DocIds::List(&candidates[scores.len()..=i]));
+                scores.extend(self.maxsim_batch_impl(
+                    query_data,
+                    query_tokens,
+                    &flat,
+                    &tokens,
+                    docs.embedding_dim,
+                    &batch_ctx,
+                    None,
+                ));
+                flat.clear();
+                norms.clear();
+                tokens.clear();
+            }
+        }
+
+        scores
+    }
+
+    // Rescore in f32 the candidates scoring within the escalation epsilon of the k-th
+    // best quantized score, when at least two do; returns how many were rescored
+    #[allow(clippy::too_many_arguments)]
+    fn escalate_boundary(
+        &self,
+        candidates: &[usize],
+        scores: &mut [f32],
+        k: usize,
+        query_data: &[f32],
+        query_tokens: usize,
+        normalized: bool,
+        embedding_dim: usize,
+    ) -> Result<usize, String> {
+        let Some(epsilon) = self.escalation_epsilon else {
+            return Ok(0);
+        };
+        if k == 0 || scores.len() <= k {
+            return Ok(0);
+        }
+        let mut order: Vec<usize> = (0..scores.len()).collect();
+        order.select_nth_unstable_by(k - 1, |&a, &b| rank_cmp((a, scores[a]), (b, scores[b])));
+        let kth = scores[order[k - 1]];
+        let boundary: Vec<usize> = (0..scores.len()).filter(|&pos| (scores[pos] - kth).abs() <= epsilon).collect();
+        if boundary.len() < 2 {
+            return Ok(0);
+        }
+
+        let docs_ref = self.documents.borrow();
+        let docs = docs_ref
+            .as_ref()
+            .filter(|docs| docs.embedding_dim == embedding_dim && candidates.iter().all(|&doc| doc < docs.num_docs()))
+            .ok_or("Boundary escalation needs the same documents in f32. Call load_documents() first.")?;
+        let ctx = self.score_context(normalized, self.config.metric(), query_data, None, embedding_dim);
+        let boundary_docs: Vec<usize> = boundary.iter().map(|&pos| candidates[pos]).collect();
+        let exact = self.score_candidates(docs, query_data, query_tokens, &boundary_docs, &ctx);
+        for (&pos, score) in boundary.iter().zip(exact) {
+            scores[pos] = score;
+        }
+        self.trace.borrow_mut().record(|| format!("escalation epsilon={} boundary={}", epsilon, boundary.len()));
+        Ok(boundary.len())
+    }
+
+    // Independent instance with the same scoring settings (for searches that outlive a call)
+    fn scoring_snapshot(&self) -> MaxSimWasm {
+        let mut engine = MaxSimWasm::with_buffers(&self.config, self.buffer_floats);
+        engine.tuning = self.tuning;
+        engine.stopmask = self.stopmask.clone();
+        engine.query_limit = self.query_limit;
+        engine.query_dedup = self.query_dedup;
+        engine.search_dim = self.search_dim;
+        engine.auto_normalize = self.auto_normalize;
+        engine.validation = self.validation;
+        engine.packed_layout = self.packed_layout;
+        engine.match_spans = self.match_spans;
+        engine.score_threshold = self.score_threshold;
+        engine.watchdog = self.watchdog.clone();
+        engine
+    }
+
+    // Scoring context for one call, using the instance aggregation
+    fn score_context<'a>(
+        &self,
+        normalized: bool,
+        metric: Metric,
+        query_flat: &[f32],
+        doc_norms: Option<&'a [f32]>,
+        embedding_dim: usize,
+    ) -> ScoreContext<'a> {
+        ScoreContext::new(normalized, metric, query_flat, doc_norms, embedding_dim)
+            .with_reduction(self.config.reduction())
+            .with_score_norm(self.config.score_norm())
+            .with_abort(self.active_abort.borrow().clone())
+            .with_clamp(self.config.clamp_similarities())
+            .with_precise(self.config.precise_accumulation())
+            // Length groups are only collected for the watchdog when someone is listening
+            .with_watchdog(self.watchdog.callback.is_some() || self.trace.borrow().is_enabled())
+    }
+
+    // Context of the official entry points (`maxsim_single`, `maxsim_batch` and their
+    // normalized variants): dot product, plain Max, no length norm, clamp or f64
+    // accumulation, whatever the instance config; cancellation and the watchdog still apply
+    fn official_context<'a>(&self, normalized: bool, query_flat: &[f32], embedding_dim: usize) -> ScoreContext<'a> {
+        ScoreContext::new(normalized, Metric::DotProduct, query_flat, None, embedding_dim)
+            .with_abort(self.active_abort.borrow().clone())
+            .with_watchdog(self.watchdog.callback.is_some() || self.trace.borrow().is_enabled())
+    }
+
+    // Similarity matrix of one document, in f64 accumulation in accuracy mode
+    #[allow(clippy::too_many_arguments)]
+    fn document_similarities(
+        &self,
+        ctx: &ScoreContext,
+        query_flat: &[f32],
+        doc_flat: &[f32],
+        similarities: &mut [f32],
+        query_tokens: usize,
+        doc_tokens: usize,
+        embedding_dim: usize,
+    ) {
+        if ctx.precise() {
+            matrix_multiply_f64(query_flat, doc_flat, similarities, query_tokens, doc_tokens, embedding_dim);
+        } else {
+            let d_block = self.tuning.d_block(doc_tokens);
+            matrix_multiply(query_flat, doc_flat, similarities, query_tokens, doc_tokens, embedding_dim, d_block);
+        }
+    }
+
+    // End-of-search reporting of what the context gathered over all its pages: how many
+    // similarities the robustness clamp changed, and the watchdog's findings
+    fn finish_search(&self, ctx: &ScoreContext) {
+        if let Some(count) = ctx.clamped_count() {
+            self.trace.borrow_mut().record(|| format!("clamped values={}", count));
+        }
+        if let Some(groups) = ctx.take_watched() {
+            self.report_watchdog(&groups);
+        }
+    }
+
+    // Inspect the length groups of one whole search and report the pathological ones
+    pub(crate) fn report_watchdog(&self, groups: &[watchdog::Group]) {
+        let findings = self.watchdog.inspect(groups);
+        for finding in &findings {
+            self.trace.borrow_mut().record(|| format!(
+                "watchdog kind={} docs={:?} value={:.3}", finding.kind, finding.docs, finding.value
+            ));
+        }
+        // Telemetry must never fail a search: callback exceptions are dropped
+        let _ = self.watchdog.report(&findings);
+    }
+
+    // Run `search` with `abort` visible to every scoring context it creates
+    fn run_abortable<R>(&self, abort: &AbortFlag, search: impl FnOnce() -> R) -> Result<R, String> {
+        if abort.aborted() {
+            return Err("Search aborted".to_string());
+        }
+        *self.active_abort.borrow_mut() = Some(abort.clone());
+        let result = search();
+        *self.active_abort.borrow_mut() = None;
+
+        if abort.aborted() {
+            return Err("Search aborted".to_string());
+        }
+        Ok(result)
+    }
+
+    // Emit the documents at or above the instance threshold
+    fn threshold_hits(&self, scores: &[f32]) -> SearchHits {
+        let hits = SearchHits::above(scores, self.score_threshold.unwrap_or(f32::NEG_INFINITY));
+        self.trace.borrow_mut().record(|| format!("threshold emitted={} of={}", hits.length(), scores.len()));
+        hits.with_degradation(self.last_query_degradation())
+    }
+
+    // Combine the caller's query mask with the stopmask vocabulary and deduplication
+    fn query_keep_mask(
+        &self,
+        query_flat: &[f32],
+        query_tokens: usize,
+        query_mask: &[u8],
+        embedding_dim: usize,
+    ) -> Vec<u8> {
+        let mut keep: Vec<u8> = if query_mask.is_empty() {
+            vec![1; query_tokens]
+        } else {
+            query_mask.iter().map(|&m| (m != 0) as u8).collect()
+        };
+
+        if let Some(stopmask) = self.stopmask.as_ref().filter(|m| m.embedding_dim == embedding_dim) {
+            // Under auto-normalization the threshold applies to the normalized tokens,
+            // i.e. to their cosine similarity, as scoring will see them
+            let (mut query, mut stop_tokens) = (Cow::Borrowed(query_flat), Cow::Borrowed(&stopmask.embeddings[..]));
+            if self.auto_normalize {
+                normalize_tokens(query.to_mut(), embedding_dim);
+                normalize_tokens(stop_tokens.to_mut(), embedding_dim);
+            }
+            for (q_idx, keep_token) in keep.iter_mut().enumerate() {
+                if *keep_token == 0 {
+                    continue;
+                }
+                let query_token = &query[q_idx * embedding_dim..(q_idx + 1) * embedding_dim];
+                let is_stop = stop_tokens
+                    .chunks_exact(embedding_dim)
+                    .any(|stop_token| dot_product(query_token, stop_token) >= stopmask.threshold);
+                if is_stop {
+                    *keep_token = 0;
+                }
+            }
+        }
+
+        if let Some(threshold) = self.query_dedup {
+            dedup_query_tokens(query_flat, embedding_dim, threshold, &mut keep);
+        }
+
+        keep
+    }
+
+    // Apply the query mask and stopmask, returning the (possibly compacted) query
+    // Borrows the input unchanged when nothing is masked
+    fn prepare_query_tokens<'a>(
+        &self,
+        query_flat: &'a [f32],
+        query_tokens: usize,
+        query_mask: &[u8],
+        embedding_dim: usize,
+    ) -> Result<(Cow<'a, [f32]>, usize), String> {
+        if !query_mask.is_empty() && query_mask.len() != query_tokens {
+            return Err(format!(
+                "Query mask length {} does not match query tokens {}",
+                query_mask.len(),
+                query_tokens
+            ));
+        }
+
+        let mut query: Cow<'a, [f32]> = Cow::Borrowed(query_flat);
+        let mut active_tokens = query_tokens;
+
+        if !query_mask.is_empty() || self.stopmask.is_some() || self.query_dedup.is_some() {
+            let keep = self.query_keep_mask(query_flat, query_tokens, query_mask, embedding_dim);
+            if keep.contains(&0) {
+                let mut compacted = Vec::new();
+                active_tokens = compact_tokens(query_flat, &[query_tokens], &keep, embedding_dim, &mut compacted)[0];
+                query = Cow::Owned(compacted);
+            }
+        }
+
+        let mut degradation = None;
+        if let Some((max_tokens, strategy)) = self.query_limit.filter(|&(max_tokens, _)| active_tokens > max_tokens) {
+            let keep = strategy.keep_mask(&query, active_tokens, max_tokens, embedding_dim);
+            let mut compacted = Vec::new();
+            compact_tokens(&query, &[active_tokens], &keep, embedding_dim, &mut compacted);
+            self.trace.borrow_mut().record(|| format!(
+                "query_limit strategy={} kept={} of={}", strategy.name(), max_tokens, active_tokens
+            ));
+            degradation = Some(QueryDegradation::new(active_tokens, max_tokens, strategy));
+            query = Cow::Owned(compacted);
+            active_tokens = max_tokens;
+        }
+        *self.last_degradation.borrow_mut() = degradation;
+
+        if self.auto_normalize {
+            normalize_tokens(query.to_mut(), embedding_dim);
+        }
+
+        Ok((query, active_tokens))
+    }
+
+    // Run `score` over the documents after applying the token mask and auto-normalization
+    // Documents are passed through untouched (no copy) when neither applies
+    fn with_prepared_documents<R>(
+        &self,
+        doc_flat: &[f32],
+        doc_tokens: &[usize],
+        doc_mask: &[u8],
+        embedding_dim: usize,
+        score: impl FnOnce(&[f32], &[usize]) -> R,
+    ) -> R {
+        if doc_mask.is_empty() && !self.auto_normalize {
+            return score(doc_flat, doc_tokens);
+        }
+
+        let mut prepared = self.scratch.lend(doc_flat.len());
+        let prepared_tokens = if doc_mask.is_empty() {
+            prepared.clear();
+            prepared.extend_from_slice(doc_flat);
+            doc_tokens.to_vec()
+        } else {
+            compact_tokens(doc_flat, doc_tokens, doc_mask, embedding_dim, &mut prepared)
+        };
+
+        if self.auto_normalize {
+            normalize_tokens(&mut prepared, embedding_dim);
+        }
+
+        score(&prepared, &prepared_tokens)
+    }
+
+    // Query shape checks, plus a NaN/Inf scan in validation mode
+    fn check_query_input(&self, query_flat: &[f32], query_tokens: usize, embedding_dim: usize) -> Result<(), String> {
+        check_query(query_flat, query_tokens, embedding_dim)?;
+        if self.validation {
+            validation::check_query_values(query_flat, embedding_dim)?;
+        }
+        Ok(())
+    }
+
+    // Per-call scoring input checks, plus a NaN/Inf scan in validation mode
+    fn check_batch_input(
+        &self,
+        query_flat: &[f32],
+        query_tokens: usize,
+        doc_flat: &[f32],
+        doc_tokens: &[usize],
+        embedding_dim: usize,
+    ) -> Result<(), String> {
+        check_batch(query_flat, query_tokens, doc_flat, doc_tokens, embedding_dim)?;
+        if self.validation {
+            validation::check_query_values(query_flat, embedding_dim)?;
+            validation::check_document_values(doc_flat, doc_tokens, embedding_dim)?;
+        }
+        Ok(())
+    }
+
+    // Document shape checks, plus a NaN/Inf scan in validation mode
+    fn check_documents_input(&self, embeddings_data: &[f32], doc_tokens: &[usize], embedding_dim: usize) -> Result<(), String> {
+        check_documents(embeddings_data, doc_tokens, embedding_dim)?;
+        if self.validation {
+            validation::check_document_values(embeddings_data, doc_tokens, embedding_dim)?;
+        }
+        Ok(())
+    }
+
+    // NaN/Inf scan of a parsed corpus in validation mode
+    fn check_store_input(&self, docs: &PreloadedDocuments) -> Result<(), String> {
+        if self.validation {
+            validation::check_store(docs)?;
+        }
+        Ok(())
+    }
+
+    // Query preparation for APIs without a caller-supplied mask
+    fn prepare_query_unmasked<'a>(
+        &self,
+        query_flat: &'a [f32],
+        query_tokens: usize,
+        embedding_dim: usize,
+    ) -> (Cow<'a, [f32]>, usize) {
+        self.prepare_query_tokens(query_flat, query_tokens, &[], embedding_dim)
+            .unwrap_or((Cow::Borrowed(query_flat), query_tokens))
+    }
+}
+
+// Top `k` of all document scores (0 = all) as ranked result objects
+fn ranked_objects(scores: &[f32], k: usize, ids: Option<js_sys::Array>) -> Result<js_sys::Array, JsValue> {
+    let all: Vec<usize> = (0..scores.len()).collect();
+    let k = if k == 0 { scores.len() } else { k };
+    SearchHits::top_k(&all, scores, k).to_objects(ids)
+}
+
+// Validate flat document embeddings against their token counts
+fn check_documents(embeddings_data: &[f32], doc_tokens: &[usize], embedding_dim: usize) -> Result<(), String> {
+    if doc_tokens.is_empty() {
+        return Err("No documents to load".to_string());
+    }
+
+    if embedding_dim == 0 {
+        return Err("Embedding dimension must be > 0".to_string());
+    }
+
+    let expected_size: usize = doc_tokens.iter().map(|&count| count * embedding_dim).sum();
+    if embeddings_data.len() != expected_size {
+        return Err("Embeddings data size mismatch".to_string());
+    }
+
+    Ok(())
+}
+
+// Validate a query against the corpus embedding dimension
+fn check_query(query_flat: &[f32], query_tokens: usize, embedding_dim: usize) -> Result<(), String> {
+    if query_tokens == 0 {
+        return Err("Query cannot be empty".to_string());
+    }
+
+    if query_flat.len() != query_tokens * embedding_dim {
+        return Err("Query size mismatch".to_string());
+    }
+
+    Ok(())
+}
+
+// The first `floats` floats of a scoring buffer
+fn buffer_prefix<'a>(buffer: &'a [f32], floats: Option<usize>, what: &str) -> Result<&'a [f32], String> {
+    match floats {
+        Some(floats) if floats <= buffer.len() => Ok(&buffer[..floats]),
+        _ => Err(format!("{} buffer too small ({} floats)", what, buffer.len())),
+    }
+}
+
+// Validate per-call scoring inputs (an empty query or document list is allowed)
+//
+// Scoring slices documents by their token counts, so without this a short `doc_flat`
+// panics and aborts the instance, losing the preloaded corpus with it
+fn check_batch(
+    query_flat: &[f32],
+    query_tokens: usize,
+    doc_flat: &[f32],
+    doc_tokens: &[usize],
+    embedding_dim: usize,
+) -> Result<(), String> {
+    if embedding_dim == 0 {
+        return Err("Embedding dimension must be > 0".to_string());
+    }
+
+    if query_tokens.checked_mul(embedding_dim) != Some(query_flat.len()) {
+        return Err("Query size mismatch".to_string());
+    }
+
+    let expected_size = doc_tokens
+        .iter()
+        .try_fold(0usize, |size, &count| count.checked_mul(embedding_dim).and_then(|n| size.checked_add(n)));
+    if expected_size != Some(doc_flat.len()) {
+        return Err("Embeddings data size mismatch".to_string());
+    }
+
+    Ok(())
+}
+
+// Shape and NaN/Inf checks behind the `*_checked` scoring APIs
+fn validate_scoring_inputs(
+    query_flat: &[f32],
+    query_tokens: usize,
+    doc_flat: &[f32],
+    doc_tokens: &[usize],
+    embedding_dim: usize,
+) -> Result<(), String> {
+    check_query(query_flat, query_tokens, embedding_dim)?;
+    check_documents(doc_flat, doc_tokens, embedding_dim)?;
+    validation::check_query_values(query_flat, embedding_dim)?;
+    validation::check_document_values(doc_flat, doc_tokens, embedding_dim)
+}
+
+// ============================================================================
+// TOKEN MASKING
+// ============================================================================
+
+// Copy the tokens whose mask entry is non-zero into `out` (cleared first)
+// and return the surviving token count of each document
+fn compact_tokens(
+    flat: &[f32],
+    token_counts: &[usize],
+    mask: &[u8],
+    embedding_dim: usize,
+    out: &mut Vec<f32>,
+) -> Vec<usize> {
+    out.clear();
+    let mut kept_counts = Vec::with_capacity(token_counts.len());
+    let mut token_idx = 0;
+
+    for &count in token_counts {
+        let mut kept = 0;
+        for _ in 0..count {
+            if mask[token_idx] != 0 {
+                let start = token_idx * embedding_dim;
+                out.extend_from_slice(&flat[start..start + embedding_dim]);
+                kept += 1;
+            }
+            token_idx += 1;
+        }
+        kept_counts.push(kept);
+    }
+
+    kept_counts
+}
+
+// Clear `keep` for tokens whose cosine to an earlier kept token exceeds `threshold`
+fn dedup_query_tokens(query_flat: &[f32], embedding_dim: usize, threshold: f32, keep: &mut [u8]) {
+    let norms: Vec<f32> = token_norms_sq(query_flat, embedding_dim).iter().map(|n| n.sqrt()).collect();
+    let token = |idx: usize| &query_flat[idx * embedding_dim..(idx + 1) * embedding_dim];
+    let mut kept: Vec<usize> = Vec::with_capacity(keep.len());
+    for idx in 0..keep.len() {
+        if keep[idx] == 0 {
+            continue;
+        }
+        let duplicate = kept.iter().any(|&prev| {
+            let denom = norms[idx] * norms[prev];
+            denom > 0.0 && dot_product(token(idx), token(prev)) / denom > threshold
+        });
+        if duplicate {
+            keep[idx] = 0;
+        } else {
+            kept.push(idx);
+        }
+    }
+}
+
+// Keep mask of the `max_tokens` highest-weighted tokens of every document (token
+// norm when `weights` is empty); ties keep the earlier token
+fn prune_mask(
+    flat: &[f32],
+    token_counts: &[usize],
+    embedding_dim: usize,
+    max_tokens: usize,
+    weights: &[f32],
+) -> Result<Vec<u8>, String> {
+    if max_tokens == 0 {
+        return Err("max_tokens must be > 0".to_string());
+    }
+    let total_tokens: usize = token_counts.iter().sum();
+    if !weights.is_empty() && weights.len() != total_tokens {
+        return Err("Token weights length mismatch".to_string());
+    }
+
+    let weights = if weights.is_empty() { Cow::Owned(token_norms_sq(flat, embedding_dim)) } else { Cow::Borrowed(weights) };
+    let mut mask = vec![1u8; total_tokens];
+    let mut first = 0;
+    for &count in token_counts {
+        if count > max_tokens {
+            let mut ranked: Vec<usize> = (first..first + count).collect();
+            ranked.sort_by(|&a, &b| weights[b].total_cmp(&weights[a]).then(a.cmp(&b)));
+            for &token in &ranked[max_tokens..] {
+                mask[token] = 0;
+            }
+        }
+        first += count;
+    }
+    Ok(mask)
+}
+
+// Squared token norms for per-call documents, only computed when the metric needs them
+fn call_doc_norms(metric: Metric, doc_flat: &[f32], embedding_dim: usize) -> Vec<f32> {
+    if metric.needs_norms() {
+        token_norms_sq(doc_flat, embedding_dim)
+    } else {
+        Vec::new()
+    }
+}
+
+// Per-token average of a summed score (0 for no tokens)
+fn mean(sum: f32, tokens: usize) -> f32 {
+    if tokens == 0 { 0.0 } else { sum / tokens as f32 }
+}
+
+// L2-normalize every token vector in place (norms via the SIMD dot product)
+// Zero vectors are left untouched
+fn normalize_tokens(flat: &mut [f32], embedding_dim: usize) {
+    for token in flat.chunks_exact_mut(embedding_dim) {
+        let norm = dot_product(token, token).sqrt();
+        if norm > 0.0 {
+            let inv_norm = 1.0 / norm;
+            token.iter_mut().for_each(|x| *x *= inv_norm);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Four one-token documents scoring 0.0, 1.0, 0.8 and 0.6 against `UNIT_QUERY`
+    const UNIT_DOCS: [f32; 8] = [1.0, 0.0, 0.0, 1.0, 0.6, 0.8, 0.8, 0.6];
+    const UNIT_QUERY: [f32; 2] = [0.0, 1.0];
+
+    // `hits` must be exactly `expected`, best first, with their full-search scores
+    fn assert_subset_hits(maxsim: &MaxSimWasm, hits: &SearchHits, expected: &[u32]) {
+        let full = maxsim.search_preloaded(&UNIT_QUERY, 1).unwrap();
+        assert_eq!(hits.indices(), expected);
+        assert_eq!(hits.scores(), expected.iter().map(|&doc| full[doc as usize]).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_bindings_match_the_core_pipeline() {
+        let maxsim = MaxSimWasm::new();
+        let dim = 8;
+        let value = |i: usize| ((i * 29 % 31) as f32 - 15.0) / 16.0;
+        let query: Vec<f32> = (0..3 * dim).map(value).collect();
+        let doc_tokens: Vec<usize> = (0..30).map(|i| 2 + i * 7 % 19).collect();
+        let docs: Vec<f32> = (0..doc_tokens.iter().sum::<usize>() * dim).map(|i| value(i + 3)).collect();
+        assert_eq!(
+            maxsim.maxsim_batch(&query, 3, &docs, &doc_tokens, dim).unwrap(),
+            maxsim_core::maxsim_batch(&query, 3, &docs, &doc_tokens, dim)
+        );
+    }
+
+    #[test]
+    fn test_near_uniform_batch_scores_every_token() {
+        // 50 documents of 5 or 6 tokens take the uniform path; the 6-token documents
+        // only match the query on their last token, which must not be cut off
+        let mut maxsim = MaxSimWasm::new();
+        let doc_tokens: Vec<usize> = (0..50).map(|i| 5 + i % 2).collect();
+        let docs: Vec<f32> = doc_tokens
+            .iter()
+            .flat_map(|&len| (0..len).flat_map(move |t| if t == 5 { [1.0, 0.0] } else { [0.0, 1.0] }))
+            .collect();
+        maxsim.set_trace_enabled(true);
+        let scores = maxsim.maxsim_batch(&[1.0, 0.0], 1, &docs, &doc_tokens, 2).unwrap();
+        assert!(maxsim.last_trace().contains("path=uniform min_len=5 max_len=6"));
+        let expected: Vec<f32> = doc_tokens.iter().map(|&len| if len == 6 { 1.0 } else { 0.0 }).collect();
+        assert_eq!(scores, expected);
+    }
+
+    #[test]
+    fn test_batch_matches_single_on_random_shapes() {
+        // Property check over seeded random batches (fuzz/fuzz_targets/batch.rs explores
+        // the same property with coverage guidance): every adaptive batching path must
+        // score each document like maxsim_single
+        let maxsim = MaxSimWasm::new();
+        let mut rng = rng::SeededRng::new(2074, 0);
+        for case in 0..200 {
+            let mut draw = |n: usize| (rng.next_u64() % n as u64) as usize;
+            let dim = [1, 3, 4, 5, 7, 13, 16, 33, 127][draw(9)];
+            let query_tokens = 1 + draw(12);
+            // Lengths near one value (uniform path), spread out (length groups) or
+            // anywhere up to 200, each with occasional empty documents
+            let (num_docs, spread) = (draw(120), draw(3));
+            let base = 1 + draw(60);
+            let doc_tokens: Vec<usize> = (0..num_docs)
+                .map(|_| match (draw(10), spread) {
+                    (0, _) => 0,
+                    (_, 0) => base + draw(base / 5 + 1),
+                    (_, 1) => base * (1 + draw(4)),
+                    _ => draw(201),
+                })
+                .collect();
+            // Tiny (subnormal products), unit and huge magnitudes
+            let scale = [1e-20f32, 1.0, 1e15][draw(3)];
+            let seed = rng.next_u64();
+            let scaled = |len: usize, seed: u64| -> Vec<f32> { tuning::synthetic(len, seed).iter().map(|v| v * scale).collect() };
+            let query = scaled(query_tokens * dim, seed);
+            let docs = scaled(doc_tokens.iter().sum::<usize>() * dim, seed ^ 1);
+
+            let scores = maxsim.maxsim_batch(&query, query_tokens, &docs, &doc_tokens, dim).unwrap();
+            let tolerance = 1e-5 * scale * scale * (dim * query_tokens) as f32;
+            let mut offset = 0;
+            for (doc_idx, (&score, &len)) in scores.iter().zip(&doc_tokens).enumerate() {
+                let doc = &docs[offset * dim..(offset + len) * dim];
+                let single = maxsim.maxsim_single(&query, query_tokens, doc, len, dim).unwrap();
+                assert!(
+                    (score - single).abs() <= tolerance,
+                    "case {} doc {} (len {}, dim {}): batch {} vs single {}", case, doc_idx, len, dim, score, single
+                );
+                offset += len;
+            }
+        }
+    }
+
+    #[test]
+    fn test_nested_search_gets_its_own_buffers() {
+        // A search started while another holds every scratch buffer (as a callback
+        // invoked mid-search would) scores normally instead of panicking
+        let mut maxsim = MaxSimWasm::new();
+        maxsim.set_auto_normalize(true);
+        let docs = [2.0, 0.0, 0.0, 3.0, 0.6, 0.8];
+        let expected = maxsim.maxsim_batch(&[1.0, 0.0], 1, &docs, &[2, 1], 2).unwrap();
+
+        let held: Vec<_> = (0..4).map(|_| maxsim.scratch.lend(0)).collect();
+        assert_eq!(maxsim.scratch.idle_floats(), 0);
+        assert_eq!(maxsim.maxsim_batch(&[1.0, 0.0], 1, &docs, &[2, 1], 2).unwrap(), expected);
+        assert_eq!(expected, vec![1.0, 0.6]);
+        drop(held);
+    }
+
+    #[test]
+    fn test_maxsim_single_official() {
+        let maxsim = MaxSimWasm::new();
+        let query = vec![1.0, 0.0, 0.0, 0.0, 1.0, 0.0];
+        let doc = vec![1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0];
+        let score = maxsim.maxsim_single(&query, 2, &doc, 3, 3).unwrap();
+        // Official MaxSim: raw sum, should be >= 0
+        assert!(score >= 0.0);
+    }
+
+    #[test]
+    fn test_maxsim_single_normalized() {
+        let maxsim = MaxSimWasm::new();
+        let query = vec![1.0, 0.0, 0.0, 0.0, 1.0, 0.0];
+        let doc = vec![1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0];
+        let score = maxsim.maxsim_single_normalized(&query, 2, &doc, 3, 3).unwrap();
+        // Normalized MaxSim: averaged, should be between -1 and 1
+        assert!((-1.0..=1.0).contains(&score));
+    }
+
+    #[test]
+    fn test_compact_tokens_drops_masked() {
+        let flat = vec![1.0, 1.0, 2.0, 2.0, 3.0, 3.0, 4.0, 4.0, 5.0, 5.0];
+        let mut out = Vec::new();
+        let counts = compact_tokens(&flat, &[3, 2], &[1, 0, 1, 0, 1], 2, &mut out);
+        assert_eq!(counts, vec![2, 1]);
+        assert_eq!(out, vec![1.0, 1.0, 3.0, 3.0, 5.0, 5.0]);
+    }
+
+    #[test]
+    fn test_masked_batch_matches_trimmed() {
+        let maxsim = MaxSimWasm::new();
+        // Query: 2 real tokens + 1 padding token (dim = 2)
+        let query = vec![1.0, 0.0, 0.0, 1.0, 0.0, -1.0];
+        // Doc 0: 2 real + 1 padding token, doc 1: 1 real token
+        let docs = vec![0.6, 0.8, 0.8, 0.6, 1.0, 0.0, 0.0, 1.0];
+        let masked = maxsim
+            .maxsim_batch_masked_impl(&query, 3, &[1, 1, 0], &docs, &[3, 1], &[1, 1, 0, 1], 2, false)
+            .unwrap();
+
+        let trimmed = maxsim.maxsim_batch(&query[..4], 2, &[0.6, 0.8, 0.8, 0.6, 0.0, 1.0], &[2, 1], 2).unwrap();
+        assert_eq!(masked, trimmed);
+        assert!((masked[0] - 1.6).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_prune_mask_keeps_top_tokens_in_order() {
+        // Doc 0: norms 1, 3, 2 -> keep tokens 1 and 2; doc 1 fits whole
+        let flat = vec![1.0, 0.0, 3.0, 0.0, 0.0, 2.0, 5.0, 0.0];
+        assert_eq!(prune_mask(&flat, &[3, 1], 2, 2, &[]).unwrap(), vec![0, 1, 1, 1]);
+        // Weights override norms
+        assert_eq!(prune_mask(&flat, &[3, 1], 2, 1, &[0.9, 0.1, 0.5, 0.0]).unwrap(), vec![1, 0, 0, 1]);
+        assert!(prune_mask(&flat, &[3, 1], 2, 0, &[]).is_err());
+        assert!(prune_mask(&flat, &[3, 1], 2, 1, &[1.0]).is_err());
+    }
+
+    #[test]
+    fn test_query_dedup_drops_near_duplicate_tokens() {
+        let mut maxsim = MaxSimWasm::new();
+        // Tokens 1 and 2 repeat token 0's direction (at different norms)
+        let query = vec![1.0, 0.0, 2.0, 0.01, 0.5, 0.0, 0.0, 1.0];
+        assert_eq!(maxsim.active_query_tokens(&query, 4, 2), 4);
+
+        maxsim.set_query_dedup(0.98);
+        assert_eq!(maxsim.query_keep_mask(&query, 4, &[], 2), vec![1, 0, 0, 1]);
+        let doc = vec![1.0, 0.0, 0.0, 1.0];
+        assert_eq!(maxsim.maxsim_single_normalized(&query, 4, &doc, 2, 2).unwrap(), 1.0);
+
+        maxsim.set_query_dedup(0.0);
+        assert_eq!(maxsim.active_query_tokens(&query, 4, 2), 4);
+    }
+
+    #[test]
+    fn test_stopmask_masks_matching_query_tokens() {
+        let mut maxsim = MaxSimWasm::new();
+        // Stop token points along y; query token 1 matches it and is dropped
+        maxsim.load_stopmask(&[0.0, 1.0], 2, 0.95).unwrap();
+        let query = vec![1.0, 0.0, 0.0, 1.0];
+        assert_eq!(maxsim.query_keep_mask(&query, 2, &[], 2), vec![1, 0]);
+
+        let doc = vec![0.0, 1.0];
+        let score = maxsim.maxsim_single(&query, 2, &doc, 1, 2).unwrap();
+        assert_eq!(score, 0.0);
+
+        maxsim.clear_stopmask();
+        assert_eq!(maxsim.maxsim_single(&query, 2, &doc, 1, 2).unwrap(), 1.0);
+
+        // Auto-normalization compares the normalized tokens (a short stop token matches)
+        maxsim.load_stopmask(&[0.0, 0.5], 2, 0.95).unwrap();
+        assert_eq!(maxsim.query_keep_mask(&query, 2, &[], 2), vec![1, 1]);
+        maxsim.set_auto_normalize(true);
+        assert_eq!(maxsim.query_keep_mask(&query, 2, &[], 2), vec![1, 0]);
+    }
+
+    #[test]
+    fn test_auto_normalize_scores_cosine() {
+        let mut maxsim = MaxSimWasm::new();
+        let query = vec![3.0, 0.0, 0.0, 2.0];
+        let doc = vec![0.0, 5.0, 4.0, 0.0];
+        assert_eq!(maxsim.maxsim_batch(&query, 2, &doc, &[2], 2).unwrap(), vec![22.0]);
+
+        maxsim.set_auto_normalize(true);
+        let scores = maxsim.maxsim_batch(&query, 2, &doc, &[2], 2).unwrap();
+        assert!((scores[0] - 2.0).abs() < 1e-6);
+
+        maxsim.load_documents(&doc, &[2], 2).unwrap();
+        let preloaded = maxsim.search_preloaded(&query, 2).unwrap();
+        assert!((preloaded[0] - 2.0).abs() < 1e-6);
+
+        // Collections loaded before enabling it are normalized too
+        let mut maxsim = MaxSimWasm::new();
+        maxsim.create_collection("docs").unwrap();
+        maxsim.load_documents_into("docs", &doc, &[2], 2).unwrap();
+        maxsim.set_auto_normalize(true);
+        assert!((maxsim.search_collection("docs", &query, 2).unwrap()[0] - 2.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_trace_records_batch_path() {
+        let mut maxsim = MaxSimWasm::new();
+        let query = vec![1.0, 0.0];
+        let docs = vec![1.0, 0.0, 0.0, 1.0, 1.0, 0.0];
+        maxsim.maxsim_batch(&query, 1, &docs, &[1, 2], 2).unwrap();
+        assert_eq!(maxsim.last_trace(), "");
+
+        maxsim.set_trace_enabled(true);
+        maxsim.maxsim_batch(&query, 1, &docs, &[1, 2], 2).unwrap();
+        let trace = maxsim.last_trace();
+        assert!(trace.starts_with("trace v1 op=batch docs=2"));
+        assert!(trace.contains("path=variable"));
+        assert!(trace.contains("group start=0 end=1 max_len=1 mode=individual"));
+    }
+
+    #[test]
+    fn test_metrics_across_batch_paths() {
+        let mut maxsim = MaxSimWasm::new();
+        // Non-normalized 2-dim tokens; 60 equal-length docs exercise the uniform path
+        let query = vec![2.0, 0.0];
+        let doc = [3.0, 4.0, 0.0, 1.0];
+        let docs: Vec<f32> = doc.iter().copied().cycle().take(doc.len() * 60).collect();
+        let lens = vec![2; 60];
+
+        let cosine = maxsim.maxsim_batch_with_metric(&query, 1, &docs, &lens, 2, Metric::Cosine, false).unwrap();
+        assert!(cosine.iter().all(|s| (s - 0.6).abs() < 1e-6));
+
+        // -|q - d|²: (2,0)-(3,4) → 17, (2,0)-(0,1) → 5
+        let neg_l2 = maxsim.maxsim_batch_with_metric(&query, 1, &docs[..4], &[2], 2, Metric::NegativeL2, false).unwrap();
+        assert_eq!(neg_l2, vec![-5.0]);
+
+        maxsim.set_metric(Metric::NegativeL2);
+        maxsim.load_documents(&docs, &lens, 2).unwrap();
+        let preloaded = maxsim.search_preloaded(&query, 1).unwrap();
+        assert!(preloaded.iter().all(|&s| s == -5.0));
+    }
+
+    #[test]
+    fn test_score_threshold_emits_only_interesting_docs() {
+        let mut maxsim = MaxSimWasm::new();
+        maxsim.set_metric(Metric::Cosine);
+        let query = vec![1.0, 0.0, 0.0, 1.0];
+        let docs = vec![1.0, 0.0, 0.0, 1.0, 0.0, 1.0, -1.0, 0.0];
+        maxsim.load_documents(&docs, &[2, 1, 1], 2).unwrap();
+
+        let all = maxsim.search_preloaded_thresholded(&query, 2).unwrap();
+        assert_eq!(all.length(), 3);
+
+        maxsim.set_score_threshold(1.5);
+        let hits = maxsim.search_preloaded_thresholded(&query, 2).unwrap();
+        assert_eq!(hits.indices(), vec![0]);
+        assert_eq!(hits.scores(), vec![2.0]);
+    }
+
+    #[test]
+    fn test_paged_search_matches_flat_batch() {
+        let maxsim = MaxSimWasm::new();
+        let query = vec![1.0, 0.0, 0.6, 0.8];
+        let docs = vec![0.0, 1.0, 1.0, 0.0, 0.8, 0.6, 0.6, 0.8, 0.0, -1.0];
+        let lens = [1, 1, 2, 1];
+
+        // 4 floats per page forces documents across three pages
+        let mut store = PreloadedDocuments::with_page_floats(2, 4);
+        let mut offset = 0;
+        for &len in &lens {
+            store.push_document(&docs[offset..offset + len * 2]);
+            offset += len * 2;
+        }
+        assert_eq!(store.pages().len(), 3);
+        *maxsim.documents.borrow_mut() = Some(Rc::new(store));
+
+        let paged = maxsim.search_preloaded(&query, 2).unwrap();
+        assert_eq!(paged, maxsim.maxsim_batch(&query, 2, &docs, &lens, 2).unwrap());
+    }
+
+    #[test]
+    fn test_index_round_trip_preserves_scores() {
+        let mut maxsim = MaxSimWasm::new();
+        let query = vec![1.0, 0.0, 0.6, 0.8];
+        maxsim.load_documents(&[0.0, 1.0, 1.0, 0.0, 0.8, 0.6], &[2, 1], 2).unwrap();
+        let expected = maxsim.search_preloaded(&query, 2).unwrap();
+
+        let bytes = maxsim.serialize_index().unwrap();
+        let mut restored = MaxSimWasm::new();
+        restored.load_index(&bytes).unwrap();
+        assert_eq!(restored.search_preloaded(&query, 2).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_top_k_hits_carry_matched_tokens() {
+        let mut maxsim = MaxSimWasm::new();
+        // Document 0: tokens (1, 0), (0, 1), (0.6, 0.8); document 1: (0.8, 0.6)
+        maxsim.load_documents(&[1.0, 0.0, 0.0, 1.0, 0.6, 0.8, 0.8, 0.6], &[3, 1], 2).unwrap();
+        let query = [1.0, 0.0, 0.9, 0.1, 0.0, 1.0];
+        assert!(maxsim.search_top_k_impl(&query, 3, 2, false).unwrap().match_offsets().is_empty());
+
+        maxsim.set_match_spans(true);
+        let hits = maxsim.search_top_k_impl(&query, 3, 2, false).unwrap();
+        assert_eq!(hits.indices(), [0, 1]);
+        assert_eq!(hits.match_offsets(), [0, 2, 3]);
+        assert_eq!((hits.matched_tokens(), hits.match_counts()), (vec![0, 1, 0], vec![2, 1, 3]));
+        // Paging keeps each hit's matches
+        let second = hits.page(1, 1);
+        assert_eq!((second.match_offsets(), second.matched_tokens(), second.match_counts()), (vec![0, 1], vec![0], vec![3]));
+    }
+
+    #[test]
+    fn test_windows_score_like_documents_of_their_own() {
+        let flat = tuning::synthetic(10 * 4, 2093);
+        let query = &flat[20..28];
+        let mut maxsim = MaxSimWasm::new();
+        maxsim.load_documents(&flat, &[3, 7], 4).unwrap();
+
+        let windows = maxsim.maxsim_windows_impl(query, 2, 1, 3, 2, false).unwrap();
+        assert_eq!(windows.offsets(), [0, 2, 4]);
+        // The query is tokens 2..4 of document 1, so the window starting there wins
+        assert_eq!(windows.best_offset(), 2);
+        for (&offset, score) in windows.offsets().iter().zip(windows.scores()) {
+            let start = (3 + offset as usize) * 4;
+            let expected = maxsim.maxsim_single(query, 2, &flat[start..start + 12], 3, 4).unwrap();
+            assert!((score - expected).abs() < 1e-6);
+        }
+        assert!(maxsim.maxsim_windows_impl(query, 2, 2, 3, 2, false).is_err());
+    }
+
+    #[test]
+    fn test_multi_variant_search_matches_separate_searches() {
+        let flat = tuning::synthetic(9 * 4, 2095);
+        let mut maxsim = MaxSimWasm::new();
+        maxsim.load_documents(&flat, &[2, 3, 4], 4).unwrap();
+
+        // Variants of 2 and 3 tokens
+        let variants = &flat[4..24];
+        let first = maxsim.search_preloaded_normalized(&variants[..8], 2).unwrap();
+        let second = maxsim.search_preloaded_normalized(&variants[8..], 3).unwrap();
+        let best = maxsim.search_multi_variant_impl(variants, &[2, 3], VariantAggregation::Max, true).unwrap();
+        for (doc, score) in best.iter().enumerate() {
+            assert!((score - first[doc].max(second[doc])).abs() < 1e-6);
+        }
+        let raw = maxsim.search_multi_variant_impl(variants, &[2, 3], VariantAggregation::Mean, false).unwrap();
+        let expected = [maxsim.search_preloaded(&variants[..8], 2).unwrap(), maxsim.search_preloaded(&variants[8..], 3).unwrap()];
+        assert!(raw.iter().enumerate().all(|(doc, score)| (score - (expected[0][doc] + expected[1][doc]) / 2.0).abs() < 1e-6));
+
+        // The length normalization applies to every variant's score
+        maxsim.set_score_norm(ScoreNorm::Sqrt);
+        let penalized = maxsim.search_multi_variant_impl(variants, &[2, 3], VariantAggregation::Max, false).unwrap();
+        let expected = [maxsim.search_preloaded(&variants[..8], 2).unwrap(), maxsim.search_preloaded(&variants[8..], 3).unwrap()];
+        assert!(penalized.iter().enumerate().all(|(doc, score)| (score - expected[0][doc].max(expected[1][doc])).abs() < 1e-6));
+
+        assert!(maxsim.search_multi_variant_impl(variants, &[2, 0, 3], VariantAggregation::Max, false).is_err());
+        assert!(maxsim.search_multi_variant_impl(variants, &[2, 2], VariantAggregation::Max, false).is_err());
     }
 
-    // Internal implementation
-    fn maxsim_batch_zero_copy_impl(
-        &mut self,
-        query_ptr: *const f32,
-        query_tokens: usize,
-        doc_ptr: *const f32,
-        doc_tokens_ptr: *const usize,
-        num_docs: usize,
-        embedding_dim: usize,
-        normalized: bool,
-    ) -> Vec<f32> {
-        if num_docs == 0 || query_tokens == 0 {
-            return vec![0.0; num_docs];
-        }
+    #[test]
+    fn test_chunked_full_merge_matches_concatenated_document() {
+        let flat = tuning::synthetic(9 * 4, 2092);
+        let query = &flat[4..12];
+        let mut maxsim = MaxSimWasm::new();
+        assert!(maxsim.search_chunked_impl(query, 2, ChunkMerge::Full, 0, false).is_err());
+
+        // Long documents of 3 chunks (2 + 2 + 1 tokens) and 1 chunk (4 tokens)
+        maxsim.load_documents_chunked(&flat, &[2, 2, 1, 4], &[3, 1], 4).unwrap();
+        let full = maxsim.search_chunked_impl(query, 2, ChunkMerge::Full, 0, false).unwrap();
+        let chunks = maxsim.search_preloaded(query, 2).unwrap();
+        let best_chunk = maxsim.search_chunked_impl(query, 2, ChunkMerge::MaxChunk, 0, false).unwrap();
+        assert_eq!(best_chunk, [chunks[0].max(chunks[1]).max(chunks[2]), chunks[3]]);
+        let normalized = maxsim.search_chunked_impl(query, 2, ChunkMerge::BestWindow, 3, true).unwrap();
+        assert_eq!(normalized, [full[0] / 2.0, full[1] / 2.0]);
+
+        let mut whole = MaxSimWasm::new();
+        whole.load_documents(&flat, &[5, 4], 4).unwrap();
+        let expected = whole.search_preloaded(query, 2).unwrap();
+        assert!(full.iter().zip(&expected).all(|(a, b)| (a - b).abs() < 1e-6));
+        let mut restored = MaxSimWasm::new();
+        restored.import_state(&maxsim.export_state()).unwrap();
+        assert_eq!(restored.search_chunked_impl(query, 2, ChunkMerge::Full, 0, false).unwrap(), full);
+    }
 
-        // Convert pointers to slices
-        let query_slice = unsafe {
-            std::slice::from_raw_parts(query_ptr, query_tokens * embedding_dim)
-        };
-        let doc_tokens_slice = unsafe {
-            std::slice::from_raw_parts(doc_tokens_ptr, num_docs)
-        };
+    #[test]
+    fn test_grouped_search_diversifies_by_group() {
+        let mut maxsim = MaxSimWasm::new();
+        let flat = [1.0, 0.0, 0.9, 0.1, 0.8, 0.2, 0.0, 1.0, 0.5, 0.5];
+        assert!(maxsim.search_grouped_impl(&[1.0, 0.0], 1, 2, 1, GroupScore::Max, false).is_err());
+        maxsim.load_documents_grouped(&flat, &[1; 5], &[4, 4, 4, 9, 2], 2).unwrap();
+
+        let hits = maxsim.search_grouped_impl(&[1.0, 0.0], 1, 2, 1, GroupScore::Max, false).unwrap();
+        assert_eq!((hits.groups(), hits.indices()), (vec![4, 2], vec![0, 4]));
+        assert_eq!(maxsim.search_grouped_impl(&[1.0, 0.0], 1, 0, 3, GroupScore::Sum, false).unwrap().groups(), [4, 2, 9]);
+
+        // Groups survive a snapshot and are cleared by a plain load
+        let mut restored = MaxSimWasm::new();
+        restored.import_state(&maxsim.export_state()).unwrap();
+        assert_eq!(restored.doc_groups, [4, 4, 4, 9, 2]);
+        maxsim.load_documents(&flat, &[1; 5], 2).unwrap();
+        assert!(maxsim.doc_groups.is_empty());
+    }
 
-        // Calculate total document floats to create flat doc slice
-        let total_doc_floats: usize = doc_tokens_slice.iter()
-            .map(|&count| count * embedding_dim)
-            .sum();
+    #[test]
+    fn test_calibrated_scores_ignore_query_length() {
+        let mut maxsim = MaxSimWasm::new();
+        assert!(maxsim.calibrate_scores_impl(&[1.0], 1, false).is_err());
+        maxsim.set_calibration(0.2, 0.8).unwrap();
+        // 1.0 over 2 tokens and 0.5 normalized are the same relevance
+        assert_eq!(maxsim.calibrate_scores_impl(&[1.0, 2.0, 0.0], 2, false).unwrap(), [50.0, 100.0, 0.0]);
+        assert_eq!(maxsim.calibrate_scores_impl(&[0.5], 7, true).unwrap(), [50.0]);
+
+        // Learned at load, kept when disabled, replaced by the app's range
+        maxsim.set_auto_calibration(true);
+        maxsim.load_documents(&[1.0, 0.0, 0.0, 1.0, 0.9, 0.1, 0.1, 0.9], &[1, 1, 1, 1], 2).unwrap();
+        let learned = maxsim.calibration_range();
+        assert_eq!(learned.len(), 2);
+        assert!(learned[0] < learned[1]);
+        maxsim.set_auto_calibration(false);
+        maxsim.load_documents(&[1.0, 0.0], &[1], 2).unwrap();
+        assert_eq!(maxsim.calibration_range(), learned);
+        maxsim.clear_calibration();
+        assert!(maxsim.calibration_range().is_empty());
+    }
 
-        let doc_slice = unsafe {
-            std::slice::from_raw_parts(doc_ptr, total_doc_floats)
-        };
+    #[test]
+    fn test_rerank_external_ranks_caller_ids() {
+        let maxsim = MaxSimWasm::new();
+        let candidates = [0.0, 1.0, 1.0, 0.0, 0.6, 0.8, 0.0, 1.0];
+        let hits = maxsim.rerank_external_impl(&[1.0, 0.0], 1, &candidates, &[2, 1, 1], &[70, 40, 9], false).unwrap();
+        assert_eq!((hits.indices(), hits.scores()), (vec![70, 40, 9], vec![1.0, 0.6, 0.0]));
+        assert_eq!(hits.scores(), maxsim.maxsim_batch(&[1.0, 0.0], 1, &candidates, &[2, 1, 1], 2).unwrap());
+        assert!(maxsim.rerank_external_impl(&[1.0, 0.0], 1, &candidates, &[2, 1, 1], &[70, 40], false).is_err());
+        assert!(maxsim.rerank_external_impl(&[1.0, 0.0, 0.5], 2, &candidates, &[2, 1, 1], &[70, 40, 9], false).is_err());
+    }
 
-        // USE BATCH OPTIMIZATION! 🚀
-        // This gives us sorting, grouping, cache blocking - same optimizations as preloaded!
-        self.maxsim_batch_impl(
-            query_slice,
-            query_tokens,
-            doc_slice,
-            doc_tokens_slice,
-            embedding_dim,
-            normalized,
-            false  // Data not pre-sorted
-        )
+    #[test]
+    fn test_pooled_first_pass_feeds_rerank() {
+        let mut maxsim = MaxSimWasm::new();
+        maxsim.load_documents(&[0.0, 1.0, 1.0, 0.0, 0.0, 1.0, 0.6, 0.8, 0.8, 0.6], &[2, 1, 2], 2).unwrap();
+        assert!(maxsim.search_pooled_impl(&[1.0, 0.0], 2).is_err());
+
+        maxsim.set_pooling(Pooling::First);
+        let stats = maxsim.index_stats();
+        assert_eq!((stats.pooled_bytes(), stats.preview_bytes(), stats.hnsw_bytes()), (3 * 2 * 4, 0, 0));
+        let coarse = maxsim.search_pooled_impl(&[1.0, 0.0], 2).unwrap();
+        assert_eq!(coarse.indices(), [2, 0]);
+        let candidates: Vec<usize> = coarse.indices().iter().map(|&doc| doc as usize).collect();
+        let hits = maxsim.rerank_impl(&[1.0, 0.0], 1, &candidates, false, "rerank").unwrap();
+        assert_eq!((hits.indices(), hits.scores()), (vec![0, 2], vec![1.0, 0.8]));
+
+        // Rebuilt on load, dropped when disabled
+        maxsim.load_documents(&[1.0, 0.0], &[1], 2).unwrap();
+        assert_eq!(maxsim.search_pooled_impl(&[1.0, 0.0], 0).unwrap().scores(), [1.0]);
+        maxsim.set_pooling(Pooling::None);
+        assert!(maxsim.search_pooled_impl(&[1.0, 0.0], 0).is_err());
     }
 
-    #[wasm_bindgen]
-    pub fn get_info(&self) -> String {
-        format!(
-            "MaxSim WASM v0.5.0 (SIMD: {}, adaptive_batching: true, buffer_reuse: true, methods: maxsim + maxsim_normalized + preloading)",
-            cfg!(target_feature = "simd128")
-        )
+    #[test]
+    fn test_hnsw_candidates_rerank_and_graph_import() {
+        let flat = tuning::synthetic(200 * 3 * 8, 2086);
+        let mut maxsim = MaxSimWasm::new();
+        maxsim.set_pooling(Pooling::Mean);
+        maxsim.load_documents(&flat, &[3; 200], 8).unwrap();
+        assert!(maxsim.search_hnsw_impl(&flat[..24], 3, 5, 50, false).is_err());
+
+        // A document's own tokens as the query: it is found, with its exact score
+        maxsim.set_hnsw(8, 64);
+        let query = &flat[17 * 24..18 * 24];
+        let hits = maxsim.search_hnsw_impl(query, 3, 5, 50, false).unwrap();
+        let exact = maxsim.search_top_k_impl(query, 3, 5, false).unwrap();
+        assert_eq!(hits.indices()[0], 17);
+        assert_eq!(hits.scores()[0], exact.scores()[0]);
+        let stats = maxsim.index_stats();
+        assert_eq!((stats.pooled_bytes(), stats.preview_bytes()), (200 * 8 * 4, 0));
+        assert!(stats.hnsw_bytes() >= 200 * 8 * 4);
+
+        // A prebuilt graph imports into an instance that doesn't build one
+        let graph = || HnswGraph::from_bytes(&maxsim.export_hnsw_graph().unwrap()).unwrap();
+        let mut other = MaxSimWasm::new();
+        other.set_pooling(Pooling::Mean);
+        other.load_documents(&flat, &[3; 200], 8).unwrap();
+        other.load_hnsw_graph_impl(graph()).unwrap();
+        assert_eq!(other.search_hnsw_impl(query, 3, 5, 50, false).unwrap().indices(), hits.indices());
+        other.set_pooling(Pooling::First);
+        assert!(other.load_hnsw_graph_impl(graph()).is_err());
+        other.set_pooling(Pooling::Mean);
+        other.load_documents(&flat[..24], &[3], 8).unwrap();
+        assert!(other.load_hnsw_graph_impl(graph()).is_err());
     }
 
-    /// Load and store document embeddings in WASM memory
-    /// This eliminates per-search conversion overhead (following FastPlaid's pattern)
-    ///
-    /// # Arguments
-    /// * `embeddings_data` - Flat array of all document embeddings concatenated
-    /// * `doc_tokens` - Array of token counts for each document
-    /// * `embedding_dim` - Embedding dimension
-    ///
-    /// # Example
-    /// For 3 documents with [128, 256, 192] tokens each at dim=48:
-    /// - embeddings_data.len() = (128 + 256 + 192) * 48 = 27,648
-    /// - doc_tokens = [128, 256, 192]
-    #[wasm_bindgen]
-    pub fn load_documents(
-        &mut self,
-        embeddings_data: &[f32],
-        doc_tokens: &[usize],
-        embedding_dim: usize,
-    ) -> Result<(), JsValue> {
-        if doc_tokens.is_empty() {
-            return Err(JsValue::from_str("No documents to load"));
-        }
+    #[test]
+    fn test_dedup_load_skips_or_maps_duplicates() {
+        let flat = [1.0, 0.0, 0.0, 1.0, 1.0, 0.0, 0.6, 0.8];
+        let mut maxsim = MaxSimWasm::new();
+        assert_eq!(maxsim.load_documents_dedup_impl(&flat, &[1, 1, 1, 1], 2, 1.0, false).unwrap(), [0, 1, 0, 3]);
+        assert_eq!(maxsim.num_documents_loaded(), 4);
+        assert_eq!(maxsim.load_documents_dedup_impl(&flat, &[1, 1, 1, 1], 2, 1.0, true).unwrap(), [0, 1, 0, 3]);
+        assert_eq!(maxsim.search_preloaded(&[1.0, 0.0], 1).unwrap(), [1.0, 0.0, 0.6]);
+        assert!(maxsim.load_documents_dedup_impl(&flat, &[1, 1, 1, 1], 2, 0.0, true).is_err());
+    }
 
-        if embedding_dim == 0 {
-            return Err(JsValue::from_str("Embedding dimension must be > 0"));
-        }
+    #[test]
+    fn test_index_delta_updates_the_loaded_index() {
+        let query = vec![1.0, 0.0, 0.6, 0.8];
+        let (base_docs, updated_docs) = ([0.0, 1.0, 1.0, 0.0, 0.8, 0.6, 0.6, 0.8], [0.0, 1.0, 1.0, 0.0, 0.6, 0.8, 0.0, -1.0]);
+        let base = maxsim_core::index::serialize(&base_docs, &[2, 1, 1], 2, formats::index::Encoding::F32);
+        let updated = maxsim_core::index::serialize(&updated_docs, &[2, 1, 1], 2, formats::index::Encoding::F32);
+        let delta = maxsim_core::delta::create(&base, &updated).unwrap();
+        let parsed = Delta::parse(&delta).unwrap();
+
+        let mut maxsim = MaxSimWasm::new();
+        assert_eq!(maxsim.apply_index_delta_impl(&parsed).err().map(|e| e.code()), Some("BaseMismatch"));
+        maxsim.load_index(&base).unwrap();
+        maxsim.apply_index_delta(&delta).unwrap();
+        let mut expected = MaxSimWasm::new();
+        expected.load_index(&updated).unwrap();
+        assert_eq!(maxsim.search_preloaded(&query, 2).unwrap(), expected.search_preloaded(&query, 2).unwrap());
+        assert_eq!((maxsim.index_fingerprint(), maxsim.generation()), (expected.index_fingerprint(), 2));
+
+        // Applied once only; the fingerprint survives a snapshot
+        assert_eq!(maxsim.apply_index_delta_impl(&parsed).err().map(|e| e.code()), Some("BaseMismatch"));
+        let mut restored = MaxSimWasm::new();
+        restored.import_state(&maxsim.export_state()).unwrap();
+        assert_eq!(restored.index_fingerprint(), expected.index_fingerprint());
+    }
 
-        // Validate data size
-        let expected_size: usize = doc_tokens.iter().map(|&count| count * embedding_dim).sum();
-        if embeddings_data.len() != expected_size {
-            return Err(JsValue::from_str("Embeddings data size mismatch"));
-        }
+    #[test]
+    fn test_config_defaults_apply_to_every_path() {
+        let mut config = MaxSimConfig::new();
+        config.set_aggregation(Aggregation::TopK);
+        config.set_top_k(2);
+        config.set_normalized(true);
+        let maxsim = MaxSimWasm::with_config(&config);
+
+        // Query tokens (1, 0) and (0, 1); one document with three tokens
+        let query = vec![1.0, 0.0, 0.0, 1.0];
+        let doc = vec![1.0, 0.0, 0.6, 0.8, 0.0, 1.0];
+
+        // Top-2 means: (1.0 + 0.6) / 2 and (1.0 + 0.8) / 2, averaged over 2 query tokens
+        let expected = (0.8 + 0.9) / 2.0;
+        let scores = maxsim.maxsim_batch_default(&query, 2, &doc, &[3], 2).unwrap();
+        assert!((scores[0] - expected).abs() < 1e-6);
+
+        // Per-call override of normalization keeps the configured aggregation
+        let raw = maxsim.maxsim_batch_with_metric(&query, 2, &doc, &[3], 2, Metric::DotProduct, false).unwrap();
+        assert!((raw[0] - 1.7).abs() < 1e-6);
+
+        // The official entry points stay on plain Max
+        assert_eq!(maxsim.maxsim_batch(&query, 2, &doc, &[3], 2).unwrap(), vec![2.0]);
+        assert_eq!(maxsim.maxsim_batch_normalized(&query, 2, &doc, &[3], 2).unwrap(), vec![1.0]);
+        assert_eq!(maxsim.maxsim_single(&query, 2, &doc, 3, 2).unwrap(), 2.0);
+        assert_eq!(maxsim.maxsim_single_normalized(&query, 2, &doc, 3, 2).unwrap(), 1.0);
+    }
 
-        // Store documents EXACTLY as received - zero restructuring overhead!
-        // Sorting happens on-the-fly in maxsim_batch_impl (negligible cost: ~0.05ms for 1000 docs)
-        // This is simpler and faster than pre-sorting + reordering scores
-        let preloaded = PreloadedDocuments {
-            embeddings_flat: embeddings_data.to_vec(),
-            doc_tokens: doc_tokens.to_vec(),
-            embedding_dim,
+    #[test]
+    fn test_official_entry_points_ignore_the_config() {
+        let query = vec![1.0, 0.0, 0.0, 1.0];
+        let docs = vec![1.0, 0.0, 0.6, 0.8, 0.0, 1.0, 1.5, 0.0];
+        let official = |maxsim: &MaxSimWasm| {
+            (
+                maxsim.maxsim_batch(&query, 2, &docs, &[3, 1], 2).unwrap(),
+                maxsim.maxsim_batch_normalized(&query, 2, &docs, &[3, 1], 2).unwrap(),
+                maxsim.maxsim_single(&query, 2, &docs[..6], 3, 2).unwrap(),
+                maxsim.maxsim_single_normalized(&query, 2, &docs[6..], 1, 2).unwrap(),
+            )
         };
+        let expected = official(&MaxSimWasm::new());
+        assert_eq!(expected, (vec![2.0, 1.5], vec![1.0, 0.75], 2.0, 0.75));
+
+        let configs: [fn(&mut MaxSimConfig); 5] = [
+            |config| config.set_metric(Metric::NegativeL2),
+            |config| config.set_metric(Metric::Cosine),
+            |config| config.set_score_norm(ScoreNorm::Log),
+            |config| config.set_clamp_similarities(true),
+            |config| config.set_precise_accumulation(true),
+        ];
+        for set in configs {
+            let mut config = MaxSimConfig::new();
+            set(&mut config);
+            assert_eq!(official(&MaxSimWasm::with_config(&config)), expected, "{:?}", config);
+        }
+    }
 
-        *self.documents.borrow_mut() = Some(preloaded);
-        Ok(())
+    #[test]
+    fn test_symmetric_maxsim_scores_both_directions() {
+        let maxsim = MaxSimWasm::new();
+        let a = [1.0, 0.0];
+        let b = [1.0, 0.0, 0.0, 1.0];
+        // a → b: 1; b → a: 1 + 0
+        assert_eq!(maxsim.maxsim_symmetric(&a, 1, &b, 2, 2).unwrap(), 2.0);
+        assert_eq!(maxsim.maxsim_symmetric(&b, 2, &a, 1, 2).unwrap(), 2.0);
+        assert_eq!(maxsim.maxsim_symmetric_normalized(&a, 1, &b, 2, 2).unwrap(), 0.75);
+        assert_eq!(maxsim.maxsim_symmetric_normalized(&b, 2, &a, 1, 2).unwrap(), 0.75);
+
+        let docs = [0.0, 1.0, 1.0, 0.0, 0.0, 1.0];
+        assert_eq!(maxsim.maxsim_symmetric_batch(&a, 1, &docs, &[1, 2], 2).unwrap(), vec![0.0, 2.0]);
     }
 
-    /// Search preloaded documents with a query
-    /// Returns MaxSim scores for all documents
-    ///
-    /// # Arguments
-    /// * `query_flat` - Flat query embedding (query_tokens × embedding_dim)
-    /// * `query_tokens` - Number of query tokens
-    ///
-    /// # Returns
-    /// Float32Array of MaxSim scores (one per document)
-    #[wasm_bindgen]
-    pub fn search_preloaded(
-        &self,
-        query_flat: &[f32],
-        query_tokens: usize,
-    ) -> Result<Vec<f32>, JsValue> {
-        // Get reference to preloaded documents
-        let docs_ref = self.documents.borrow();
-        let docs = docs_ref.as_ref()
-            .ok_or_else(|| JsValue::from_str("No documents loaded. Call load_documents() first."))?;
+    #[test]
+    fn test_pairwise_matrix_matches_per_pair_scores() {
+        let mut maxsim = MaxSimWasm::new();
+        let docs = [1.0, 0.0, 1.0, 0.0, 0.0, 1.0, 0.6, 0.8];
+        maxsim.load_documents(&docs, &[1, 2, 1], 2).unwrap();
+
+        let matrix = maxsim.doc_pairwise_impl(&[0, 1, 2], false, false).unwrap();
+        assert_eq!(matrix[1], maxsim.maxsim_single(&docs[..2], 1, &docs[2..6], 2, 2).unwrap());
+        assert_eq!(matrix[3], maxsim.maxsim_single(&docs[2..6], 2, &docs[..2], 1, 2).unwrap());
+
+        let symmetric = maxsim.doc_pairwise_impl(&[2, 1], true, true).unwrap();
+        let expected = maxsim.maxsim_symmetric_normalized(&docs[6..], 1, &docs[2..6], 2, 2).unwrap();
+        assert_eq!(symmetric, vec![1.0, expected, expected, 1.0]);
+        assert!(maxsim.doc_pairwise_impl(&[3], false, false).unwrap_err().contains("out of range"));
+    }
 
-        if query_tokens == 0 {
-            return Err(JsValue::from_str("Query cannot be empty"));
-        }
+    #[test]
+    fn test_score_norm_penalizes_long_documents() {
+        // Both documents contain the query token; the second has 3 more tokens
+        let docs = [1.0, 0.0, 1.0, 0.0, 0.0, 1.0, 0.6, 0.8, 0.8, 0.6];
+        let query = [1.0, 0.0];
+        let mut maxsim = MaxSimWasm::new();
+        maxsim.load_documents(&docs, &[1, 4], 2).unwrap();
+        assert_eq!(maxsim.search_preloaded(&query, 1).unwrap(), vec![1.0, 1.0]);
+
+        maxsim.set_score_norm(ScoreNorm::Sqrt);
+        assert_eq!(maxsim.search_preloaded(&query, 1).unwrap(), vec![1.0, 0.5]);
+        assert_eq!(maxsim.maxsim_batch_with_metric(&query, 1, &docs, &[1, 4], 2, Metric::DotProduct, false).unwrap(), vec![1.0, 0.5]);
+        assert_eq!(maxsim.maxsim_batch(&query, 1, &docs, &[1, 4], 2).unwrap(), vec![1.0, 1.0]);
+
+        maxsim.set_score_norm(ScoreNorm::Log);
+        let scores = maxsim.search_preloaded(&query, 1).unwrap();
+        assert!((scores[0] - 1.0 / 2f32.ln()).abs() < 1e-6 && (scores[1] - 1.0 / 5f32.ln()).abs() < 1e-6);
+    }
 
-        if query_flat.len() != query_tokens * docs.embedding_dim {
-            return Err(JsValue::from_str("Query size mismatch"));
-        }
+    #[test]
+    fn test_config_hot_swap_keeps_documents() {
+        let mut maxsim = MaxSimWasm::new();
+        maxsim.load_documents(&[1.0, 0.0, 0.6, 0.8, 0.0, 1.0], &[3], 2).unwrap();
+        let query = [1.0, 0.0];
+        assert_eq!(maxsim.search_preloaded(&query, 1).unwrap(), vec![1.0]);
+
+        let mut config = maxsim.config();
+        config.set_aggregation(Aggregation::TopK);
+        config.set_top_k(2);
+        maxsim.set_config_checked(&config).unwrap();
+        assert!((maxsim.search_preloaded(&query, 1).unwrap()[0] - 0.8).abs() < 1e-6);
+
+        // top_k beyond the longest document is rejected
+        config.set_top_k(4);
+        assert!(maxsim.check_config(&config).is_err());
+        config.set_top_k(3);
+        assert!(maxsim.check_config(&config).is_ok());
+
+        // TopP keeps ⌈0.5 · 3⌉ = 2 of the document's tokens
+        config.set_aggregation(Aggregation::TopP);
+        config.set_top_p(0.5);
+        maxsim.set_config(&config);
+        assert!((maxsim.search_preloaded(&query, 1).unwrap()[0] - 0.8).abs() < 1e-6);
+    }
 
-        // ZERO-COPY SEARCH! 🚀
-        // Documents already stored as flat arrays - direct batch processing with full optimizations
-        // Sorting happens on-the-fly (negligible cost), scores returned in original order
-        let scores = self.maxsim_batch_impl(
-            query_flat,
-            query_tokens,
-            &docs.embeddings_flat,  // Already flat and contiguous!
-            &docs.doc_tokens,        // Already computed!
-            docs.embedding_dim,
-            false,         // not normalized
-            false          // Sort on-the-fly (cheap)
+    #[test]
+    fn test_maintenance_keeps_scores_identical() {
+        let mut maxsim = MaxSimWasm::new();
+        let docs = vec![0.6, 0.8, 1.0, 0.0, 0.0, 1.0, 0.8, 0.6, 1.0, 0.0, 0.6, 0.8];
+        maxsim.load_documents(&docs, &[3, 1, 2], 2).unwrap();
+        let query = vec![1.0, 0.0, 0.0, 1.0];
+        let before = maxsim.maxsim_batch(&query, 2, &docs, &[3, 1, 2], 2).unwrap(); // Sorted per call
+
+        // The load leaves the page sort to maintenance; at least one step always runs
+        maxsim.set_trace_enabled(true);
+        assert_eq!(maxsim.search_preloaded(&query, 2).unwrap(), before);
+        assert!(maxsim.last_trace().contains("presorted=false"));
+        assert_eq!(maxsim.maintenance_pending(), 1);
+        assert_eq!(maxsim.run_maintenance(0.0), 0);
+        assert_eq!(maxsim.search_preloaded(&query, 2).unwrap(), before);
+        assert!(maxsim.last_trace().contains("presorted=true"));
+    }
+
+    #[test]
+    fn test_streaming_load_matches_load_documents() {
+        let docs = vec![0.6, 0.8, 1.0, 0.0, 0.0, 1.0, 0.8, 0.6, 1.0, 0.0, 0.6, 0.8];
+        let query = vec![1.0, 0.0, 0.0, 1.0];
+
+        let mut bulk = MaxSimWasm::new();
+        bulk.load_documents(&docs, &[3, 1, 2], 2).unwrap();
+
+        let mut streamed = MaxSimWasm::new();
+        streamed.begin_load(2, None).unwrap();
+        streamed.load_chunk(&docs[..8], &[3, 1]).unwrap();
+        streamed.load_chunk(&docs[8..], &[2]).unwrap();
+        assert_eq!(streamed.num_documents_loaded(), 0); // not swapped in until finished
+        assert_eq!(streamed.finish_load().unwrap(), 3);
+
+        assert_eq!(
+            streamed.search_preloaded(&query, 2).unwrap(),
+            bulk.search_preloaded(&query, 2).unwrap()
         );
+    }
 
-        Ok(scores)
+    #[test]
+    fn test_dual_scores_match_separate_searches() {
+        let mut maxsim = MaxSimWasm::new();
+        let docs = vec![0.6, 0.8, 1.0, 0.0, 0.0, 1.0, 0.8, 0.6];
+        maxsim.load_documents(&docs, &[3, 1], 2).unwrap();
+        let query = vec![1.0, 0.0, 0.0, 1.0];
+
+        let dual = maxsim.search_preloaded_dual(&query, 2, ScoreMode::Raw, ScoreMode::Normalized).unwrap();
+        assert_eq!(dual.previous(), maxsim.search_preloaded(&query, 2).unwrap());
+        assert_eq!(dual.current(), maxsim.search_preloaded_normalized(&query, 2).unwrap());
+
+        let batch = maxsim.maxsim_batch_dual(&query, 2, &docs, &[3, 1], 2, ScoreMode::Normalized, ScoreMode::Raw).unwrap();
+        assert_eq!(batch.previous(), maxsim.maxsim_batch_normalized(&query, 2, &docs, &[3, 1], 2).unwrap());
     }
 
-    /// Search preloaded documents with normalized MaxSim scores
-    #[wasm_bindgen]
-    pub fn search_preloaded_normalized(
-        &self,
-        query_flat: &[f32],
-        query_tokens: usize,
-    ) -> Result<Vec<f32>, JsValue> {
-        // Get reference to preloaded documents
-        let docs_ref = self.documents.borrow();
-        let docs = docs_ref.as_ref()
-            .ok_or_else(|| JsValue::from_str("No documents loaded. Call load_documents() first."))?;
+    #[test]
+    fn test_aborted_flag_stops_search() {
+        let maxsim = MaxSimWasm::new();
+        let query = vec![1.0, 0.0];
+        let docs = vec![1.0, 0.0, 0.0, 1.0];
+
+        let abort = AbortFlag::new();
+        assert_eq!(maxsim.run_abortable(&abort, || 1), Ok(1));
+        abort.abort();
+        assert!(maxsim.run_abortable(&abort, || 1).is_err());
+
+        // A raised flag stops the batch before any sub-batch is scored
+        *maxsim.active_abort.borrow_mut() = Some(abort.clone());
+        maxsim.trace.borrow_mut().set_enabled(true);
+        assert_eq!(maxsim.maxsim_batch(&query, 1, &docs, &[1, 1], 2).unwrap(), vec![0.0, 0.0]);
+        assert!(maxsim.last_trace().contains("aborted at=0"));
+    }
 
-        if query_tokens == 0 {
-            return Err(JsValue::from_str("Query cannot be empty"));
-        }
+    #[test]
+    fn test_fused_scores_match_both_variants() {
+        let maxsim = MaxSimWasm::new();
+        let query = vec![1.0, 0.0, 0.0, 1.0, 0.6, 0.8];
+        let docs = vec![0.6, 0.8, 1.0, 0.0, 0.0, 1.0, 0.8, 0.6];
 
-        if query_flat.len() != query_tokens * docs.embedding_dim {
-            return Err(JsValue::from_str("Query size mismatch"));
-        }
+        let fused = maxsim.maxsim_batch_fused(&query, 3, &docs, &[3, 1], 2).unwrap();
+        assert_eq!(fused.raw(), maxsim.maxsim_batch(&query, 3, &docs, &[3, 1], 2).unwrap());
+        assert_eq!(fused.normalized(), maxsim.maxsim_batch_normalized(&query, 3, &docs, &[3, 1], 2).unwrap());
+    }
 
-        // ZERO-COPY SEARCH! 🚀
-        // Documents already stored as flat arrays - direct batch processing with full optimizations
-        // Sorting happens on-the-fly (negligible cost), scores returned in original order
-        let scores = self.maxsim_batch_impl(
-            query_flat,
-            query_tokens,
-            &docs.embeddings_flat,  // Already flat and contiguous!
-            &docs.doc_tokens,        // Already computed!
-            docs.embedding_dim,
-            true,          // normalized
-            false          // Sort on-the-fly (cheap)
-        );
+    #[test]
+    fn test_approx_search_scores_probed_clusters_exactly() {
+        let mut maxsim = MaxSimWasm::new();
+        let docs = vec![1.0, 0.1, 0.9, 0.0, 0.0, 1.0, 0.1, 0.9, 1.0, 0.0, 0.2, 1.0];
+        maxsim.load_documents(&docs, &[2, 2, 1, 1], 2).unwrap();
+        maxsim.set_ann_clusters(2);
+        assert_eq!(maxsim.ann_clusters(), 2);
+
+        let query = vec![1.0, 0.0];
+        let exact = maxsim.search_preloaded(&query, 1).unwrap();
+        let hits = maxsim.search_preloaded_approx(&query, 1, 1, 10).unwrap();
+        assert_eq!(hits.indices(), vec![0, 2]); // only the (1, 0) cluster, ties by index
+        assert_eq!(hits.scores(), vec![exact[0], exact[2]]);
+
+        let all = maxsim.search_preloaded_approx(&query, 1, 2, 2).unwrap();
+        assert_eq!(all.length(), 2);
+    }
 
-        Ok(scores)
+    #[test]
+    fn test_top_k_with_bounds_and_centroids_matches_full_scan() {
+        let mut maxsim = MaxSimWasm::new();
+        // 40 documents of 1-4 tokens pointing in scattered directions (dim = 4)
+        let doc_tokens: Vec<usize> = (0..40).map(|doc| 1 + doc % 4).collect();
+        let total: usize = doc_tokens.iter().sum();
+        let docs: Vec<f32> = (0..total * 4).map(|i| ((i * 7919) % 97) as f32 / 48.0 - 1.0).collect();
+        maxsim.load_documents(&docs, &doc_tokens, 4).unwrap();
+        let query = vec![0.9, -0.3, 0.1, 0.4, -0.2, 0.8, 0.5, 0.0];
+
+        let exhaustive = maxsim.search_preloaded_top_k(&query, 2, 5).unwrap();
+        maxsim.set_score_bounds(true);
+        maxsim.set_trace_enabled(true);
+        let pruned = maxsim.search_preloaded_top_k(&query, 2, 5).unwrap();
+        assert_eq!(pruned.indices(), exhaustive.indices());
+        assert_eq!(pruned.scores(), exhaustive.scores());
+        assert!(!maxsim.last_trace().contains("skipped=0"));
+
+        // Centroid estimates with an unbounded margin rescore everything: exact top k
+        maxsim.set_token_centroids(2);
+        let approx = maxsim.search_preloaded_centroid(&query, 2, 5, f32::INFINITY).unwrap();
+        assert_eq!(approx.indices(), exhaustive.indices());
+        let fast = maxsim.search_preloaded_centroid(&query, 2, 5, 0.0).unwrap();
+        assert_eq!(fast.length(), 5);
+
+        let scores = maxsim.search_preloaded_normalized(&query, 2).unwrap();
+        let best = maxsim.search_preloaded_top_k_normalized(&query, 2, 1).unwrap();
+        assert_eq!(best.scores()[0], scores.iter().copied().fold(f32::NEG_INFINITY, f32::max));
     }
 
-    /// Get number of loaded documents
-    #[wasm_bindgen]
-    pub fn num_documents_loaded(&self) -> usize {
-        self.documents.borrow()
-            .as_ref()
-            .map(|d| d.doc_tokens.len())
-            .unwrap_or(0)
+    #[test]
+    fn test_pq_search_matches_exact_scores_on_few_distinct_tokens() {
+        let mut maxsim = MaxSimWasm::new();
+        // Three distinct tokens: each gets its own sub-centroids, so PQ is lossless
+        let docs = vec![1.0, 0.0, 0.0, 1.0, 0.6, 0.8, -0.8, 0.6, 0.0, 1.0, 1.0, 0.0];
+        maxsim.load_documents(&docs, &[2, 1], 4).unwrap();
+        maxsim.load_documents_pq(&docs, &[2, 1], 4, 2, &[]).unwrap();
+        assert_eq!(maxsim.index_stats().quantization(), "f32+pq-2x8");
+
+        let query = vec![0.5, 0.5, 1.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+        let exact = maxsim.search_preloaded_normalized(&query, 2).unwrap();
+        let pq = maxsim.search_pq_normalized(&query, 2).unwrap();
+        assert!(exact.iter().zip(&pq).all(|(a, b)| (a - b).abs() < 1e-6));
+
+        // Reusing the exported codebooks encodes identically
+        let codebooks = maxsim.pq_codebooks();
+        maxsim.load_documents_pq(&docs, &[2, 1], 4, 2, &codebooks).unwrap();
+        assert_eq!(maxsim.search_pq_normalized(&query, 2).unwrap(), pq);
+        assert!(maxsim.load_documents_pq_impl(&docs, &[2, 1], 4, 3, &[]).is_err());
     }
-}
 
-// ============================================================================
-// SIMD DOT PRODUCT - Macro-generated specialized versions
-// ============================================================================
+    #[test]
+    fn test_int4_search_tracks_f32_scores() {
+        let mut maxsim = MaxSimWasm::new();
+        let doc_tokens = [3, 2, 4];
+        let docs: Vec<f32> = (0..9 * 32).map(|i| ((i * 31) % 17) as f32 / 8.0 - 1.0).collect();
+        maxsim.load_documents(&docs, &doc_tokens, 32).unwrap();
+        maxsim.load_documents_int4(&docs, &doc_tokens, 32, 0).unwrap();
+        assert_eq!(maxsim.index_stats().quantization(), "f32+int4-g32");
+        assert_eq!(maxsim.index_stats().int4_bytes(), 9 * (16 + 3 * 4));
+
+        let query: Vec<f32> = (0..2 * 32).map(|i| ((i * 7) % 5) as f32 / 4.0 - 0.5).collect();
+        let exact = maxsim.search_preloaded(&query, 2).unwrap();
+        let int4 = maxsim.search_int4(&query, 2).unwrap();
+        // Within a few half-steps (2/15 / 2 per dimension) and in the same order
+        assert!(exact.iter().zip(&int4).all(|(a, b)| (a - b).abs() < 0.15));
+        assert!(int4[0] > int4[1] && int4[1] > int4[2]);
+        assert!(maxsim.load_documents_int4_impl(&docs, &doc_tokens, 32, 3).is_err());
+
+        // Odd dims get an even default group size and a shorter last group
+        let odd: Vec<f32> = (0..9 * 33).map(|i| ((i * 31) % 17) as f32 / 8.0 - 1.0).collect();
+        let odd_query: Vec<f32> = (0..2 * 33).map(|i| ((i * 7) % 5) as f32 / 4.0 - 0.5).collect();
+        maxsim.load_documents(&odd, &doc_tokens, 33).unwrap();
+        maxsim.load_documents_int4(&odd, &doc_tokens, 33, 0).unwrap();
+        assert_eq!(maxsim.index_stats().quantization(), "f32+int4-g64");
+        let exact = maxsim.search_preloaded(&odd_query, 2).unwrap();
+        let int4 = maxsim.search_int4(&odd_query, 2).unwrap();
+        assert!(exact.iter().zip(&int4).all(|(a, b)| (a - b).abs() < 0.15));
+        maxsim.load_documents_int4(&odd, &doc_tokens, 33, 16).unwrap();
+        let int4 = maxsim.search_int4(&odd_query, 2).unwrap();
+        assert!(exact.iter().zip(&int4).all(|(a, b)| (a - b).abs() < 0.15));
+    }
 
-macro_rules! generate_simd_dot {
-    ($name:ident, $dim:expr) => {
-        #[cfg(target_arch = "wasm32")]
-        #[inline]
-        fn $name(a: &[f32], b: &[f32]) -> f32 {
-            unsafe {
-                let mut sum = f32x4_splat(0.0);
-                for i in (0..$dim).step_by(4) {
-                    let va = v128_load(a.as_ptr().add(i) as *const v128);
-                    let vb = v128_load(b.as_ptr().add(i) as *const v128);
-                    sum = f32x4_add(sum, f32x4_mul(va, vb));
-                }
-                f32x4_extract_lane::<0>(sum) + f32x4_extract_lane::<1>(sum) + 
-                f32x4_extract_lane::<2>(sum) + f32x4_extract_lane::<3>(sum)
-            }
-        }
-    };
-}
+    #[test]
+    fn test_boundary_escalation_rescores_near_ties_in_f32() {
+        let mut maxsim = MaxSimWasm::new();
+        let doc_tokens = [3; 40];
+        let docs = tuning::synthetic(120 * 32, 2026);
+        let query = tuning::synthetic(4 * 32, 1);
+        maxsim.load_documents_int4(&docs, &doc_tokens, 32, 8).unwrap();
+        assert_eq!(maxsim.search_int4_top_k_impl(&query, 4, 5, false).unwrap().escalated(), 0);
+        maxsim.set_boundary_escalation(f32::MAX).unwrap();
+        assert!(maxsim.search_int4_top_k_impl(&query, 4, 5, false).is_err());
+
+        // Every score is within epsilon of the cutoff, so the ranking is exact
+        maxsim.load_documents(&docs, &doc_tokens, 32).unwrap();
+        let exact = maxsim.search_preloaded_top_k(&query, 4, 5).unwrap();
+        let hits = maxsim.search_int4_top_k_impl(&query, 4, 5, false).unwrap();
+        assert_eq!((hits.indices(), hits.escalated()), (exact.indices(), 40));
+        assert!(hits.scores().iter().zip(exact.scores()).all(|(a, b)| (a - b).abs() < 1e-5));
+        maxsim.set_boundary_escalation(0.0).unwrap();
+        assert_eq!(maxsim.search_int4_top_k_impl(&query, 4, 5, false).unwrap().escalated(), 0);
+    }
+
+    #[test]
+    fn test_search_dim_scores_leading_dimensions_and_reranks() {
+        let mut maxsim = MaxSimWasm::new();
+        // Doc 0 wins on the first two dimensions, doc 1 on all four
+        let docs = vec![1.0, 0.0, 0.0, 0.0, 0.6, 0.0, 0.8, 0.0, 0.0, 0.0, 0.0, 1.0];
+        maxsim.load_documents(&docs, &[1, 1, 1], 4).unwrap();
+        let query = vec![0.8, 0.0, 0.6, 0.0];
+
+        maxsim.set_search_dim(2, 0);
+        let truncated = maxsim.search_preloaded(&query, 1).unwrap();
+        assert!(truncated.iter().zip([0.8, 0.48, 0.0]).all(|(a, b)| (a - b).abs() < 1e-6));
+        assert_eq!(maxsim.search_preloaded_top_k(&query, 1, 1).unwrap().indices(), vec![0]);
+
+        maxsim.set_search_dim(2, 2);
+        let hits = maxsim.search_preloaded_top_k(&query, 1, 1).unwrap();
+        assert_eq!(hits.indices(), vec![1]);
+        assert!((hits.scores()[0] - 0.96).abs() < 1e-6);
+
+        maxsim.set_search_dim(0, 0);
+        assert_eq!(maxsim.search_preloaded(&query, 1).unwrap()[1], hits.scores()[0]);
+    }
 
-generate_simd_dot!(simd_dot_128, 128);
-generate_simd_dot!(simd_dot_256, 256);
-generate_simd_dot!(simd_dot_384, 384);
-generate_simd_dot!(simd_dot_512, 512);
-generate_simd_dot!(simd_dot_768, 768);
-generate_simd_dot!(simd_dot_1024, 1024);
-
-#[cfg(target_arch = "wasm32")]
-#[inline]
-fn simd_dot_generic(a: &[f32], b: &[f32]) -> f32 {
-    let len = a.len();
-    let simd_len = len - (len % 16);
-
-    unsafe {
-        let mut sum0 = f32x4_splat(0.0);
-        let mut sum1 = f32x4_splat(0.0);
-        let mut sum2 = f32x4_splat(0.0);
-        let mut sum3 = f32x4_splat(0.0);
+    #[test]
+    fn test_precise_accumulation_scores_in_f64() {
+        let mut config = MaxSimConfig::new();
+        config.set_precise_accumulation(true);
+        let precise = MaxSimWasm::with_config(&config);
+        // The f32 sum of 1 + 1e8 - 1e8 (+ padding dimensions) loses the 1
+        let query = vec![1.0, 1.0, 1.0, 0.0];
+        let docs = vec![1.0, 1e8, -1e8, 0.0, 0.5, 0.0, 0.0, 0.0];
+        assert_eq!(precise.maxsim_batch_with_metric(&query, 1, &docs, &[1, 1], 4, Metric::DotProduct, false).unwrap(), vec![1.0, 0.5]);
+
+        let mut maxsim = MaxSimWasm::with_config(&config);
+        maxsim.load_documents(&docs, &[1, 1], 4).unwrap();
+        maxsim.set_trace_enabled(true);
+        assert_eq!(maxsim.search_preloaded(&query, 1).unwrap(), vec![1.0, 0.5]);
+        assert!(maxsim.last_trace().contains("path=precise"));
+    }
 
-        let mut i = 0;
-        while i < simd_len {
-            let va0 = v128_load(a.as_ptr().add(i) as *const v128);
-            let vb0 = v128_load(b.as_ptr().add(i) as *const v128);
-            sum0 = f32x4_add(sum0, f32x4_mul(va0, vb0));
+    #[test]
+    fn test_clamp_limits_denormalized_similarities() {
+        let mut config = MaxSimConfig::new();
+        config.set_clamp_similarities(true);
+        let mut maxsim = MaxSimWasm::with_config(&config);
+        maxsim.set_trace_enabled(true);
+
+        // Slightly de-normalized document tokens: similarities 1.02 and -1.01 fall outside [-1, 1]
+        let query = vec![1.0, 0.0];
+        let doc = vec![1.02, 0.0, -1.01, 0.0, 0.5, 0.5];
+        let scores = maxsim.maxsim_batch_with_metric(&query, 1, &doc, &[3], 2, Metric::DotProduct, false).unwrap();
+        assert_eq!(scores, vec![1.0]);
+        assert!(maxsim.last_trace().contains("clamped values=2"));
+        assert_eq!(maxsim.maxsim_batch(&query, 1, &doc, &[3], 2).unwrap(), vec![1.02]);
+
+        let unclamped = MaxSimWasm::new().maxsim_batch(&query, 1, &doc, &[3], 2).unwrap();
+        assert_eq!(unclamped, vec![1.02]);
+    }
+
+    #[test]
+    fn test_residual_search_matches_decompressed_documents() {
+        // dim 4 at 2 bits, two centroids; buckets (1, 1, 1, 1), (3, 1, 1, 1) and
+        // (1, 0, 1, 3), in colbert-ai's bit order (see residual.rs)
+        let centroids = vec![1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+        let weights = vec![-0.1, 0.0, 0.05, 0.1];
+        let codes = vec![0, 1, 1];
+        let residuals = vec![0b1010_1010, 0b1110_1010, 0b1000_1011];
+        let mut maxsim = MaxSimWasm::new();
+        maxsim.load_residual_index(&centroids, &weights, &codes, &residuals, &[2, 1], 4, 2).unwrap();
+        assert_eq!(maxsim.residual_index_bytes(), (8 + 4 + 3) * 4 + 3);
+
+        // Reference: the same tokens decompressed by hand, searched as f32 documents
+        let mut flat = vec![
+            1.0, 0.0, 0.0, 0.0,
+            0.1, 1.0, 0.0, 0.0,
+            0.0, 0.9, 0.0, 0.1,
+        ];
+        normalize_tokens(&mut flat, 4);
+        let query = vec![1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+        let expected = maxsim.maxsim_batch(&query, 2, &flat, &[2, 1], 4).unwrap();
+
+        let scores = maxsim.search_residual(&query, 2).unwrap();
+        for (score, want) in scores.iter().zip(&expected) {
+            assert!((score - want).abs() < 1e-6);
+        }
+    }
 
-            let va1 = v128_load(a.as_ptr().add(i + 4) as *const v128);
-            let vb1 = v128_load(b.as_ptr().add(i + 4) as *const v128);
-            sum1 = f32x4_add(sum1, f32x4_mul(va1, vb1));
+    #[test]
+    fn test_residual_top_k_scores_probed_candidates_exactly() {
+        // Two centroids; documents 0 and 2 have tokens near centroid 0, document 1 only near centroid 1
+        let (centroids, weights) = ([1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0], [-0.1, -0.05, 0.05, 0.1]);
+        let mut maxsim = MaxSimWasm::new();
+        maxsim.load_residual_index(&centroids, &weights, &[0, 1, 1, 0], &[0b1110_0101, 0b0110_1001, 0b0101_1010, 0b1001_0110], &[2, 1, 1], 4, 2)
+            .unwrap();
+        let query = [1.0, 0.0, 0.0, 0.0];
+        let all = maxsim.search_residual(&query, 1).unwrap();
+
+        let hits = maxsim.search_residual_top_k_impl(&query, 1, 3, 1, 0, false).unwrap();
+        assert_eq!(hits.indices().len(), 2);
+        assert!(hits.indices().iter().all(|&doc| doc != 1));
+        assert!(hits.indices().iter().zip(hits.scores()).all(|(&doc, score)| score == all[doc as usize]));
+        // Probing both centroids reaches every document; the cap limits exact scoring
+        assert_eq!(maxsim.search_residual_top_k_impl(&query, 1, 3, 2, 0, false).unwrap().indices().len(), 3);
+        assert_eq!(maxsim.search_residual_top_k_impl(&query, 1, 3, 2, 1, false).unwrap().indices().len(), 1);
+        assert!(maxsim.search_residual_top_k_impl(&query, 1, 3, 0, 0, false).is_err());
+    }
 
-            let va2 = v128_load(a.as_ptr().add(i + 8) as *const v128);
-            let vb2 = v128_load(b.as_ptr().add(i + 8) as *const v128);
-            sum2 = f32x4_add(sum2, f32x4_mul(va2, vb2));
+    #[test]
+    fn test_colbert_import_matches_residual_load() {
+        let bytes = |values: &[u32]| values.iter().flat_map(|v| v.to_le_bytes()).collect::<Vec<u8>>();
+        let floats = |values: &[f32]| values.iter().flat_map(|v| v.to_le_bytes()).collect::<Vec<u8>>();
+        let centroids = vec![1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+        let weights = vec![-0.1, 0.0, 0.05, 0.1];
+        let residuals = vec![0b1010_1010, 0b1110_1010, 0b1000_1011];
+
+        let mut imported = MaxSimWasm::new();
+        imported.begin_colbert_import(
+            &formats::torch::write_archive("FloatStorage", &[floats(&centroids)]),
+            &formats::torch::write_archive("FloatStorage", &[floats(&[-0.05, 0.0, 0.05]), floats(&weights)]),
+            4,
+        ).unwrap();
+        let codes_pt = |codes: &[u32]| formats::torch::write_archive("IntStorage", &[bytes(codes)]);
+        let residuals_pt = |packed: &[u8]| formats::torch::write_archive("ByteStorage", &[packed.to_vec()]);
+        assert_eq!(imported.add_colbert_chunk(&codes_pt(&[0, 1]), &residuals_pt(&residuals[..2]), "[2]").unwrap(), 1);
+        assert_eq!(imported.add_colbert_chunk(&codes_pt(&[1]), &residuals_pt(&residuals[2..]), "[1]").unwrap(), 2);
+        assert_eq!(imported.finish_colbert_import().unwrap(), 2);
+
+        let mut direct = MaxSimWasm::new();
+        direct.load_residual_index(&centroids, &weights, &[0, 1, 1], &residuals, &[2, 1], 4, 2).unwrap();
+        let query = vec![1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+        assert_eq!(imported.search_residual(&query, 2).unwrap(), direct.search_residual(&query, 2).unwrap());
+    }
 
-            let va3 = v128_load(a.as_ptr().add(i + 12) as *const v128);
-            let vb3 = v128_load(b.as_ptr().add(i + 12) as *const v128);
-            sum3 = f32x4_add(sum3, f32x4_mul(va3, vb3));
+    #[test]
+    fn test_token_maxima_sum_to_scores() {
+        let mut maxsim = MaxSimWasm::new();
+        let docs = vec![0.6, 0.8, 1.0, 0.0, 0.0, 1.0, 0.8, 0.6, 1.0, 0.0, 0.6, 0.8];
+        maxsim.load_documents(&docs, &[3, 1, 2], 2).unwrap();
+        let query = vec![1.0, 0.0, 0.0, 1.0];
+
+        let scores = maxsim.search_preloaded(&query, 2).unwrap();
+        let maxima = maxsim.search_preloaded_token_maxima(&query, 2).unwrap();
+        assert_eq!((maxima.query_tokens(), maxima.num_docs()), (2, 3));
+        for (doc, score) in scores.iter().enumerate() {
+            let sum = maxima.get(0, doc).unwrap() + maxima.get(1, doc).unwrap();
+            assert!((sum - score).abs() <= maxima.scale() + 1e-6);
+        }
+    }
 
-            i += 16;
+    #[test]
+    fn test_watchdog_reports_global_document_indices() {
+        // Two pages; the second holds one very long document among short ones
+        let mut docs = PreloadedDocuments::with_page_floats(2, 100);
+        let lengths = [20, 20, 2, 2, 40, 2, 2];
+        for &tokens in &lengths {
+            docs.push_document(&vec![0.6; tokens * 2]);
         }
+        let mut maxsim = MaxSimWasm::new();
+        maxsim.install_documents(docs);
+        maxsim.set_trace_enabled(true);
 
-        let sum_ab = f32x4_add(f32x4_add(sum0, sum1), f32x4_add(sum2, sum3));
-        let mut result = f32x4_extract_lane::<0>(sum_ab)
-            + f32x4_extract_lane::<1>(sum_ab)
-            + f32x4_extract_lane::<2>(sum_ab)
-            + f32x4_extract_lane::<3>(sum_ab);
+        maxsim.search_preloaded(&[1.0, 0.0], 1).unwrap();
+        let trace = maxsim.last_trace();
+        assert!(trace.contains("watchdog kind=dominant_document docs=[4] value=20.000"), "{}", trace);
+    }
 
-        for j in simd_len..len {
-            result += a[j] * b[j];
+    #[test]
+    fn test_watchdog_inspects_the_whole_search() {
+        // A page of short documents and a page holding only a long one: neither page is
+        // pathological on its own
+        let mut docs = PreloadedDocuments::with_page_floats(2, 100);
+        for tokens in [2; 25].into_iter().chain([40]) {
+            docs.push_document(&vec![0.6; tokens * 2]);
         }
+        assert_eq!(docs.pages().len(), 2);
+        let mut maxsim = MaxSimWasm::new();
+        maxsim.install_documents(docs);
+        maxsim.set_trace_enabled(true);
+
+        maxsim.search_preloaded(&[1.0, 0.0], 1).unwrap();
+        let trace = maxsim.last_trace();
+        assert_eq!(trace.matches("watchdog kind=dominant_document").count(), 1, "{}", trace);
+        assert!(trace.contains("watchdog kind=dominant_document docs=[25] value=20.000"), "{}", trace);
+    }
 
-        result
+    #[test]
+    fn test_streaming_query_matches_search_preloaded() {
+        let docs: Vec<f32> = vec![1.0, 0.0, 0.9, 0.1, 0.0, 1.0, 0.1, 0.9, 0.7, 0.7];
+        let query = [0.95, 0.05, 0.2, 0.8];
+        let mut maxsim = MaxSimWasm::new();
+        maxsim.load_documents(&docs, &[2, 2, 1], 2).unwrap();
+        maxsim.set_ann_clusters(2);
+
+        maxsim.begin_streaming_query(2, 2).unwrap();
+        assert_eq!(maxsim.push_query_token(&query[..2]).unwrap(), 1);
+        let partial = maxsim.streaming_query_candidates(1).unwrap();
+        let expected: Vec<u32> = maxsim.ann.as_ref().unwrap().candidates(&query[..2], 1)
+            .into_iter().map(|doc| doc as u32).collect();
+        assert_eq!(partial, expected);
+        assert_eq!(maxsim.push_query_token(&query[2..]).unwrap(), 2);
+
+        assert_eq!(maxsim.finish_query().unwrap(), maxsim.search_preloaded(&query, 2).unwrap());
     }
-}
 
-#[inline]
-fn dot_product(a: &[f32], b: &[f32]) -> f32 {
-    #[cfg(target_arch = "wasm32")]
-    {
-        match a.len() {
-            128 => simd_dot_128(a, b),
-            256 => simd_dot_256(a, b),
-            384 => simd_dot_384(a, b),
-            512 => simd_dot_512(a, b),
-            768 => simd_dot_768(a, b),
-            1024 => simd_dot_1024(a, b),
-            _ => simd_dot_generic(a, b),
-        }
-    }
-    
-    #[cfg(not(target_arch = "wasm32"))]
-    {
-        a.iter().zip(b.iter()).map(|(x, y)| x * y).sum()
+    #[test]
+    fn test_stream_search_matches_one_batch() {
+        let docs: Vec<f32> = vec![1.0, 0.0, 0.9, 0.1, 0.0, 1.0, 0.1, 0.9, 0.7, 0.7, 0.6, 0.8];
+        let doc_tokens = [2, 2, 1, 1];
+        let query = [0.95, 0.05, 0.2, 0.8];
+        let mut maxsim = MaxSimWasm::new();
+        let expected = maxsim.maxsim_batch(&query, 2, &docs, &doc_tokens, 2).unwrap();
+
+        assert!(maxsim.score_chunk_impl(&docs, &doc_tokens).is_err());
+        maxsim.begin_stream_search(&query, 2).unwrap();
+        let mut scores = maxsim.score_chunk(&docs[..8], &doc_tokens[..2]).unwrap();
+        scores.extend(maxsim.score_chunk(&docs[8..], &doc_tokens[2..]).unwrap());
+        assert_eq!(scores, expected);
+        assert!(maxsim.score_chunk_impl(&docs[..5], &doc_tokens[..2]).is_err());
+        assert_eq!(maxsim.end_stream_search().unwrap(), 4);
+        assert!(maxsim.stream_search.is_none());
     }
-}
 
-// ============================================================================
-// MATRIX MULTIPLICATION with Adaptive Cache Blocking
-// ============================================================================
+    #[test]
+    fn test_admission_score_is_best_profile_match() {
+        let mut maxsim = MaxSimWasm::new();
+        let profile = [1.0, 0.0, 0.0, 1.0, 0.6, 0.8];
+        maxsim.set_admission_profile(&profile, &[1, 2], 2).unwrap();
+
+        let candidate = [0.8, 0.6, 0.0, 1.0];
+        let expected = maxsim.maxsim_single(&profile[2..], 2, &candidate, 2, 2).unwrap();
+        assert!(expected > maxsim.maxsim_single(&profile[..2], 1, &candidate, 2, 2).unwrap());
+        assert_eq!(maxsim.admission_score(&candidate, 2).unwrap(), expected);
+        assert_eq!(maxsim.admission_score_normalized(&candidate, 2).unwrap(), expected / 2.0);
+    }
 
-#[inline]
-fn matrix_multiply(
-    query_flat: &[f32],
-    doc_flat: &[f32],
-    similarities: &mut [f32],
-    query_tokens: usize,
-    doc_tokens: usize,
-    embedding_dim: usize,
-    normalized: bool,
-) {
-    // Adaptive cache blocking based on document length
-    let d_block_size = match doc_tokens {
-        0..=64 => 16,
-        65..=128 => 16,
-        129..=256 => 12,
-        257..=512 => 8,
-        513..=1024 => 6,
-        1025..=2048 => 4,
-        _ => 4,
-    };
-    
-    let q_block_size = 8;
-
-    for q_block in (0..query_tokens).step_by(q_block_size) {
-        let q_end = (q_block + q_block_size).min(query_tokens);
-        
-        for d_block in (0..doc_tokens).step_by(d_block_size) {
-            let d_end = (d_block + d_block_size).min(doc_tokens);
-            
-            for q_idx in q_block..q_end {
-                let query_start = q_idx * embedding_dim;
-                let query_token = &query_flat[query_start..query_start + embedding_dim];
-                
-                for d_idx in d_block..d_end {
-                    let doc_start = d_idx * embedding_dim;
-                    let doc_token = &doc_flat[doc_start..doc_start + embedding_dim];
-                    
-                    let similarity = dot_product(query_token, doc_token);
-                    
-                    similarities[q_idx * doc_tokens + d_idx] = similarity;
-                }
-            }
-        }
+    #[test]
+    fn test_rerank_scores_only_candidates() {
+        let mut maxsim = MaxSimWasm::new();
+        maxsim.load_documents(&UNIT_DOCS, &[1, 1, 1, 1], 2).unwrap();
+        let hits = maxsim.rerank(&UNIT_QUERY, 1, &[3, 0, 2]).unwrap();
+        assert_subset_hits(&maxsim, &hits, &[2, 3, 0]);
     }
-}
 
-// ============================================================================
-// SIMD MAX FINDING
-// ============================================================================
+    #[test]
+    fn test_preview_scores_leading_tokens_only() {
+        let mut maxsim = MaxSimWasm::new();
+        maxsim.set_preview_tokens(1);
+        // Document 0's best match for the query is its second token
+        maxsim.load_documents(&[1.0, 0.0, 0.0, 1.0, 0.6, 0.8], &[2, 1], 2).unwrap();
+        let query = [0.0, 1.0];
+
+        assert_eq!(maxsim.search_preview(&query, 1).unwrap(), vec![0.0, 0.8]);
+        assert_eq!(maxsim.search_preloaded(&query, 1).unwrap(), vec![1.0, 0.8]);
+
+        maxsim.set_preview_tokens(2);
+        assert_eq!(maxsim.search_preview(&query, 1).unwrap(), vec![1.0, 0.8]);
+        maxsim.set_preview_tokens(0);
+        assert!(maxsim.preview.is_none());
+    }
 
-#[cfg(target_arch = "wasm32")]
-#[inline]
-fn simd_max(slice: &[f32]) -> f32 {
-    let len = slice.len();
-    
-    if len < 32 {
-        return slice.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    #[test]
+    fn test_index_stats_report_corpus_and_memory() {
+        let mut maxsim = MaxSimWasm::new();
+        assert_eq!(maxsim.index_stats().quantization(), "none");
+
+        maxsim.load_documents(&[0.5; 12], &[1, 2, 3], 2).unwrap();
+        let stats = maxsim.index_stats();
+        assert_eq!((stats.num_docs(), stats.total_tokens(), stats.embedding_dim()), (3, 6, 2));
+        assert_eq!((stats.min_doc_tokens(), stats.max_doc_tokens(), stats.avg_doc_tokens()), (1, 3, 2.0));
+        assert_eq!(stats.embedding_bytes(), (12 + 6) * 4);
+        assert_eq!(stats.quantization(), "f32");
+        assert!(stats.buffer_bytes() > 0);
+        assert_eq!(stats.total_bytes(), stats.embedding_bytes() + stats.buffer_bytes());
     }
 
-    let simd_len = len - (len % 32);
+    #[test]
+    fn test_state_snapshot_restores_every_corpus() {
+        let docs = [1.0, 0.0, 0.0, 0.0, 0.6, 0.8, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0];
+        let mut maxsim = MaxSimWasm::new();
+        maxsim.load_documents_with_metadata(&docs, &[2, 1], &[1, 2], 4).unwrap();
+        maxsim.load_documents_pq(&docs, &[2, 1], 4, 2, &[]).unwrap();
+        maxsim.load_documents_int4(&docs, &[2, 1], 4, 2).unwrap();
+        maxsim.load_residual_index(&[1.0, 0.0, 0.0, 0.0], &[-0.5, -0.25, 0.25, 0.5], &[0, 0], &[0b1100_1001, 0], &[1, 1], 4, 2)
+            .unwrap();
+        let state = maxsim.export_state();
+
+        let mut restored = MaxSimWasm::new();
+        restored.import_state(&state).unwrap();
+        let query = [0.0, 1.0, 0.0, 0.0];
+        assert_eq!(restored.search_preloaded(&query, 1).unwrap(), maxsim.search_preloaded(&query, 1).unwrap());
+        assert_eq!(restored.search_preloaded_filtered(&query, 1, 2).unwrap().indices(), vec![1]);
+        assert_eq!(restored.search_pq(&query, 1).unwrap(), maxsim.search_pq(&query, 1).unwrap());
+        assert_eq!(restored.search_int4(&query, 1).unwrap(), maxsim.search_int4(&query, 1).unwrap());
+        assert_eq!(restored.search_residual(&query, 1).unwrap(), maxsim.search_residual(&query, 1).unwrap());
+        assert_eq!(restored.index_stats().quantization(), maxsim.index_stats().quantization());
+        assert_eq!(restored.export_state(), state);
+
+        // A damaged snapshot leaves the instance as it was; an empty one clears it
+        let error = |bytes: &[u8]| snapshot::EngineState::read(bytes).err().map(|e| e.code());
+        assert_eq!(error(&state[..state.len() - 3]), Some("CorruptHeader"));
+        assert_eq!(error(&state[4..]), Some("NotAnIndex"));
+        assert_eq!(restored.num_documents_loaded(), 2);
+        restored.import_state(&MaxSimWasm::new().export_state()).unwrap();
+        assert_eq!(restored.index_stats().quantization(), "none");
+    }
 
-    unsafe {
-        let mut max0 = f32x4_splat(f32::NEG_INFINITY);
-        let mut max1 = f32x4_splat(f32::NEG_INFINITY);
-        let mut max2 = f32x4_splat(f32::NEG_INFINITY);
-        let mut max3 = f32x4_splat(f32::NEG_INFINITY);
-        let mut max4 = f32x4_splat(f32::NEG_INFINITY);
-        let mut max5 = f32x4_splat(f32::NEG_INFINITY);
-        let mut max6 = f32x4_splat(f32::NEG_INFINITY);
-        let mut max7 = f32x4_splat(f32::NEG_INFINITY);
+    #[test]
+    fn test_buffers_can_be_released_and_regrow() {
+        let mut maxsim = MaxSimWasm::new();
+        maxsim.load_documents(&[1.0, 0.0, 0.6, 0.8], &[2], 2).unwrap();
+        maxsim.set_buffer_capacity(0, 0);
+        assert_eq!(maxsim.index_stats().buffer_bytes(), 0);
+
+        // Buffers grow on demand and shrink back to the reserved capacity
+        let docs = vec![0.5; 300 * 2];
+        assert_eq!(maxsim.maxsim_batch(&[1.0, 0.0], 1, &docs, &[3; 100], 2).unwrap(), vec![0.5; 100]);
+        assert_eq!(maxsim.search_preloaded(&[0.0, 1.0], 1).unwrap(), vec![0.8]);
+        assert!(maxsim.index_stats().buffer_bytes() > 0);
+        maxsim.shrink_buffers();
+        assert_eq!(maxsim.index_stats().buffer_bytes(), 0);
+    }
 
-        let mut i = 0;
-        while i < simd_len {
-            let data0 = v128_load(slice.as_ptr().add(i) as *const v128);
-            let data1 = v128_load(slice.as_ptr().add(i + 4) as *const v128);
-            let data2 = v128_load(slice.as_ptr().add(i + 8) as *const v128);
-            let data3 = v128_load(slice.as_ptr().add(i + 12) as *const v128);
-            let data4 = v128_load(slice.as_ptr().add(i + 16) as *const v128);
-            let data5 = v128_load(slice.as_ptr().add(i + 20) as *const v128);
-            let data6 = v128_load(slice.as_ptr().add(i + 24) as *const v128);
-            let data7 = v128_load(slice.as_ptr().add(i + 28) as *const v128);
+    #[test]
+    fn test_query_limit_reports_degradation() {
+        let mut maxsim = MaxSimWasm::new();
+        maxsim.load_documents(&[1.0, 0.0, 0.0, 1.0], &[1, 1], 2).unwrap();
+        // Three query tokens; the weakest (norm 0.5) is the only match for document 1
+        let query = [2.0, 0.0, 1.0, 0.0, 0.0, 0.5];
+
+        maxsim.set_query_limit(2, QueryPruning::PruneByNorm);
+        assert_eq!(maxsim.search_preloaded(&query, 3).unwrap(), vec![3.0, 0.0]);
+        let degradation = maxsim.last_query_degradation().unwrap();
+        assert_eq!((degradation.original_tokens(), degradation.tokens_dropped()), (3, 1));
+
+        let hits = maxsim.rerank(&query, 3, &[0, 1]).unwrap();
+        assert_eq!(hits.degradation().unwrap().strategy(), QueryPruning::PruneByNorm);
+
+        maxsim.set_query_limit(0, QueryPruning::Truncate);
+        assert_eq!(maxsim.search_preloaded(&query, 3).unwrap(), vec![3.0, 0.5]);
+        assert!(maxsim.last_query_degradation().is_none());
+    }
 
-            max0 = f32x4_pmax(max0, data0);
-            max1 = f32x4_pmax(max1, data1);
-            max2 = f32x4_pmax(max2, data2);
-            max3 = f32x4_pmax(max3, data3);
-            max4 = f32x4_pmax(max4, data4);
-            max5 = f32x4_pmax(max5, data5);
-            max6 = f32x4_pmax(max6, data6);
-            max7 = f32x4_pmax(max7, data7);
+    #[test]
+    fn test_query_limit_is_traced() {
+        let mut maxsim = MaxSimWasm::new();
+        maxsim.load_documents(&[1.0, 0.0, 0.0, 1.0], &[1, 1], 2).unwrap();
+        maxsim.set_trace_enabled(true);
+        maxsim.set_query_limit(2, QueryPruning::Truncate);
+        let query = [2.0, 0.0, 1.0, 0.0, 0.0, 0.5];
+        let limited = "query_limit strategy=truncate kept=2 of=3";
+
+        maxsim.search_preloaded(&query, 3).unwrap();
+        assert!(maxsim.last_trace().starts_with("trace v1 op=preloaded docs=2 pages=1 query_tokens=3"));
+        assert!(maxsim.last_trace().contains(limited));
+        maxsim.rerank(&query, 3, &[0, 1]).unwrap();
+        assert!(maxsim.last_trace().contains(limited));
+        maxsim.maxsim_batch(&query, 3, &[1.0, 0.0], &[1], 2).unwrap();
+        assert!(maxsim.last_trace().contains(limited));
+
+        // Searches deriving several outputs prepare the query once
+        maxsim.set_match_spans(true);
+        let hits = maxsim.search_top_k_impl(&query, 3, 1, false).unwrap();
+        assert_eq!(hits.match_offsets().len(), 2);
+        assert_eq!(maxsim.last_trace().matches("query_limit").count(), 1);
+        let fused = maxsim.search_preloaded_fused(&query, 3).unwrap();
+        assert_eq!(fused.normalized(), vec![1.5, 0.0]);
+        assert_eq!(maxsim.last_trace().matches("query_limit").count(), 1);
+        maxsim.search_preloaded_dual(&query, 3, ScoreMode::Raw, ScoreMode::Normalized).unwrap();
+        assert_eq!(maxsim.last_trace().matches("query_limit").count(), 1);
+        assert!(maxsim.last_query_degradation().is_some());
+    }
 
-            i += 32;
-        }
+    #[test]
+    fn test_generation_counts_corpus_mutations() {
+        let mut maxsim = MaxSimWasm::new();
+        assert_eq!(maxsim.generation(), 0);
+        maxsim.load_documents(&[1.0, 0.0], &[1], 2).unwrap();
+        maxsim.search_preloaded(&[1.0, 0.0], 1).unwrap();
+        maxsim.set_metric(Metric::Cosine);
+        assert_eq!(maxsim.generation(), 1);
+
+        maxsim.set_auto_normalize(true);
+        maxsim.create_collection("notes").unwrap();
+        maxsim.load_documents_into("notes", &[0.0, 1.0], &[1], 2).unwrap();
+        assert!(!maxsim.drop_collection("emails"));
+        assert_eq!(maxsim.generation(), 4);
+    }
 
-        let max_ab = f32x4_pmax(f32x4_pmax(max0, max1), f32x4_pmax(max2, max3));
-        let max_cd = f32x4_pmax(f32x4_pmax(max4, max5), f32x4_pmax(max6, max7));
-        let final_max = f32x4_pmax(max_ab, max_cd);
+    #[test]
+    fn test_committed_buffer_matches_load_documents() {
+        let docs = [1.0, 0.0, 0.0, 1.0, 0.6, 0.8];
+        let mut copied = MaxSimWasm::new();
+        copied.load_documents(&docs, &[2, 1], 2).unwrap();
+
+        let mut maxsim = MaxSimWasm::new();
+        let ptr = maxsim.alloc_document_buffer(docs.len());
+        unsafe { std::slice::from_raw_parts_mut(ptr, docs.len()) }.copy_from_slice(&docs);
+        maxsim.commit_documents(&[2, 1], 2).unwrap();
+
+        let query = [0.6, 0.8];
+        assert_eq!(maxsim.search_preloaded(&query, 1).unwrap(), copied.search_preloaded(&query, 1).unwrap());
+        assert!(maxsim.document_buffer.is_empty());
+
+        let mut owned = MaxSimWasm::new();
+        owned.load_documents_owned(docs.to_vec(), vec![2, 1], 2).unwrap();
+        assert_eq!(owned.search_preloaded(&query, 1).unwrap(), copied.search_preloaded(&query, 1).unwrap());
+    }
 
-        let mut result = f32x4_extract_lane::<0>(final_max)
-            .max(f32x4_extract_lane::<1>(final_max))
-            .max(f32x4_extract_lane::<2>(final_max))
-            .max(f32x4_extract_lane::<3>(final_max));
+    #[test]
+    fn test_buffer_handles_score_like_maxsim_batch() {
+        let mut maxsim = MaxSimWasm::new();
+        let docs = [1.0, 0.0, 0.0, 1.0, 0.6, 0.8];
+        let query = maxsim.alloc_buffer(4);
+        let doc_buffer = maxsim.alloc_buffer(8); // Larger than needed
+        maxsim.write_buffer(query, 0, &[0.6, 0.8]).unwrap();
+        maxsim.write_buffer(doc_buffer, 0, &docs).unwrap();
+
+        let scores = maxsim.maxsim_batch_buffers_impl(query, 1, doc_buffer, &[2, 1], 2, false).unwrap();
+        assert_eq!(scores, maxsim.maxsim_batch(&[0.6, 0.8], 1, &docs, &[2, 1], 2).unwrap());
+        let too_many = maxsim.maxsim_batch_buffers_impl(query, 1, doc_buffer, &[2, 3], 2, false);
+        assert_eq!(too_many.unwrap_err(), "Document buffer too small (8 floats)");
+
+        assert!(maxsim.free_buffer(doc_buffer));
+        assert!(maxsim.maxsim_batch_buffers_impl(query, 1, doc_buffer, &[2, 1], 2, false).unwrap_err().contains("Unknown"));
+    }
 
-        for j in simd_len..len {
-            result = result.max(slice[j]);
+    #[test]
+    fn test_packed_layout_matches_row_major_scores() {
+        let dim = 5;
+        let doc_tokens = [1, 4, 7, 3];
+        let docs: Vec<f32> = (0..15 * dim).map(|v| ((v * 37 % 23) as f32 - 11.0) / 10.0).collect();
+        let query: Vec<f32> = (0..3 * dim).map(|v| ((v * 11 % 7) as f32 - 3.0) / 4.0).collect();
+
+        let mut maxsim = MaxSimWasm::new();
+        maxsim.load_documents(&docs, &doc_tokens, dim).unwrap();
+        let row_major = maxsim.search_preloaded(&query, 3).unwrap();
+
+        maxsim.set_packed_layout(true);
+        maxsim.set_metric(Metric::Cosine);
+        let cosine = maxsim.search_preloaded(&query, 3).unwrap();
+        maxsim.set_metric(Metric::DotProduct);
+        maxsim.set_trace_enabled(true);
+        let packed = maxsim.search_preloaded(&query, 3).unwrap();
+        assert!(maxsim.last_trace().contains("path=packed"));
+        for (a, b) in packed.iter().zip(&row_major) {
+            assert!((a - b).abs() < 1e-5, "{} vs {}", a, b);
         }
+        assert!(cosine.iter().all(|&score| score.abs() <= 3.0 + 1e-5));
 
-        result
+        maxsim.set_packed_layout(false);
+        assert_eq!(maxsim.search_preloaded(&query, 3).unwrap(), row_major);
     }
-}
 
-#[cfg(not(target_arch = "wasm32"))]
-#[inline]
-fn simd_max(slice: &[f32]) -> f32 {
-    slice.iter().copied().fold(f32::NEG_INFINITY, f32::max)
-}
+    #[test]
+    fn test_collections_are_independent_corpora() {
+        let mut maxsim = MaxSimWasm::new();
+        maxsim.load_documents(&[1.0, 0.0], &[1], 2).unwrap();
+        maxsim.create_collection("notes").unwrap();
+        maxsim.create_collection("emails").unwrap();
+        maxsim.load_documents_into("notes", &[0.0, 1.0, 0.6, 0.8], &[1, 1], 2).unwrap();
+        maxsim.load_documents_into("emails", &[0.8, 0.6, 0.0, 2.0, 1.0, 0.0], &[2, 1], 2).unwrap();
+
+        let query = [0.0, 1.0];
+        assert_eq!(maxsim.search_collection("notes", &query, 1).unwrap(), vec![1.0, 0.8]);
+        assert_eq!(maxsim.search_collection("emails", &query, 1).unwrap(), vec![2.0, 0.0]);
+        assert_eq!(maxsim.search_preloaded(&query, 1).unwrap(), vec![0.0]);
+
+        assert!(maxsim.drop_collection("notes"));
+        assert!(!maxsim.drop_collection("notes"));
+        assert_eq!(maxsim.search_collection_impl("notes", &query, 1, false), Err("Unknown collection \"notes\"".to_string()));
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn test_prepared_query_matches_per_call_preparation() {
+        let mut maxsim = MaxSimWasm::new();
+        maxsim.load_documents_with_metadata(&UNIT_DOCS, &[1, 2, 1], &[0b01, 0b10, 0b11], 2).unwrap();
+        maxsim.create_collection("other").unwrap();
+        maxsim.load_documents_into("other", &UNIT_DOCS[2..], &[3], 2).unwrap();
+        maxsim.set_auto_normalize(true);
+        maxsim.set_query_limit(2, QueryPruning::PruneByNorm);
+        let query = [0.0, 2.0, 0.1, 0.1, 3.0, 1.0];
+
+        let handle = maxsim.prepare_query(&query, 3).unwrap();
+        assert_eq!((handle.active_tokens(), handle.original_tokens()), (2, 3));
+        assert_eq!(maxsim.search_preloaded_prepared(&handle).unwrap(), maxsim.search_preloaded(&query, 3).unwrap());
+        assert_eq!(
+            maxsim.search_collection_prepared_normalized("other", &handle).unwrap(),
+            maxsim.search_collection_normalized("other", &query, 3).unwrap()
+        );
+        let hits = maxsim.search_preloaded_filtered_prepared(&handle, 0b10).unwrap();
+        assert_eq!(hits.scores(), maxsim.search_preloaded_filtered(&query, 3, 0b10).unwrap().scores());
+        assert!(hits.degradation().is_some());
+
+        maxsim.set_query_limit(0, QueryPruning::Truncate);
+        assert!(maxsim.search_prepared_impl(None, &handle, false).unwrap_err().contains("stale"));
+    }
 
     #[test]
-    fn test_dot_product() {
-        let a = vec![1.0, 2.0, 3.0, 4.0];
-        let b = vec![2.0, 3.0, 4.0, 5.0];
-        let result = dot_product(&a, &b);
-        assert_eq!(result, 40.0);
+    fn test_paged_search_reuses_the_cached_ranking() {
+        let mut maxsim = MaxSimWasm::new();
+        let docs = [1.0, 0.0, 0.0, 1.0, 0.6, 0.8, 0.8, 0.6, 0.3, 0.9];
+        maxsim.load_documents(&docs, &[1, 1, 1, 1, 1], 2).unwrap();
+        maxsim.set_trace_enabled(true);
+        let query = [0.0, 1.0];
+
+        let first = maxsim.search_preloaded_paged(&query, 1, 0, 2).unwrap();
+        assert_eq!(first.indices(), vec![1, 4]);
+        assert!(maxsim.last_trace().contains("paged cache=miss"));
+        let next = maxsim.search_preloaded_paged(&query, 1, 2, 2).unwrap();
+        assert_eq!(next.indices(), vec![2, 3]);
+        assert!(maxsim.last_trace().contains("paged cache=hit"));
+        assert_eq!(maxsim.search_preloaded_paged(&query, 1, 4, 0).unwrap().indices(), vec![0]);
+        assert_eq!(maxsim.search_preloaded_paged(&query, 1, 9, 2).unwrap().length(), 0);
+
+        // A corpus change invalidates the cache
+        maxsim.load_documents(&docs[..4], &[1, 1], 2).unwrap();
+        assert_eq!(maxsim.search_preloaded_paged(&query, 1, 0, 0).unwrap().indices(), vec![1, 0]);
+        assert!(maxsim.last_trace().contains("paged cache=miss"));
     }
 
     #[test]
-    fn test_maxsim_single_official() {
+    fn test_malformed_batch_inputs_are_errors() {
+        let docs = [1.0, 0.0, 0.0, 1.0, 0.6];
+        assert_eq!(check_batch(&[1.0, 0.0], 1, &docs, &[1, 2], 2).unwrap_err(), "Embeddings data size mismatch");
+        assert_eq!(check_batch(&[1.0, 0.0, 0.5], 2, &docs[..4], &[2], 2).unwrap_err(), "Query size mismatch");
+        assert!(check_batch(&[1.0], 1, &[], &[], 0).is_err());
+        assert!(check_batch(&[1.0, 0.0], 1, &docs[..4], &[usize::MAX, 2], 2).is_err());
+        assert!(check_batch(&[], 0, &[], &[], 2).is_ok());
+
         let maxsim = MaxSimWasm::new();
-        let query = vec![1.0, 0.0, 0.0, 0.0, 1.0, 0.0];
-        let doc = vec![1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0];
-        let score = maxsim.maxsim_single(&query, 2, &doc, 3, 3);
-        // Official MaxSim: raw sum, should be >= 0
-        assert!(score >= 0.0);
+        assert!(maxsim.maxsim_batch_uniform_impl(&[1.0, 0.0], 1, &docs[..4], usize::MAX, 2, 2, false).is_err());
+        assert_eq!(maxsim.maxsim_batch_uniform_impl(&[1.0, 0.0], 1, &docs[..4], 2, 1, 2, false).unwrap(), vec![1.0, 0.0]);
     }
 
     #[test]
-    fn test_maxsim_single_normalized() {
+    fn test_validation_mode_rejects_non_finite_inputs() {
+        let mut maxsim = MaxSimWasm::new();
+        let docs = [1.0, 0.0, 0.0, f32::NAN];
+        assert!(maxsim.check_documents_input(&docs, &[1, 1], 2).is_ok());
+
+        maxsim.set_validation(true);
+        assert_eq!(maxsim.check_documents_input(&docs, &[1, 1], 2).unwrap_err(), "Document 1 token 0 has NaN at dimension 1");
+        assert!(maxsim.check_query_input(&[f32::INFINITY, 0.0], 1, 2).is_err());
+        assert_eq!(maxsim.check_query_input(&[1.0, 0.0], 2, 2).unwrap_err(), "Query size mismatch");
+        assert!(maxsim.prepare_query_impl(&[f32::NAN, 0.0], 1).is_err());
+        assert!(maxsim.begin_stream_search_impl(&[f32::NAN, 0.0], 1).is_err());
+        assert!(maxsim.scoring_snapshot().validation);
+
+        // The checked APIs validate whatever the mode, and score like the unchecked ones
+        assert!(validate_scoring_inputs(&[1.0, 0.0], 1, &docs, &[1, 1], 2).unwrap_err().contains("NaN"));
+        maxsim.set_validation(false);
+        let scores = maxsim.maxsim_batch_checked(&[0.6, 0.8], 1, &docs[..2], &[1], 2, false).unwrap();
+        assert_eq!(scores, maxsim.maxsim_batch(&[0.6, 0.8], 1, &docs[..2], &[1], 2).unwrap());
+    }
+
+    #[test]
+    fn test_filtered_search_scores_matching_tags_only() {
+        let mut maxsim = MaxSimWasm::new();
+        maxsim.load_documents_with_metadata(&UNIT_DOCS, &[1, 1, 1, 1], &[0b01, 0b10, 0b11, 0b01], 2).unwrap();
+        let hits = maxsim.search_preloaded_filtered(&UNIT_QUERY, 1, 0b01).unwrap();
+        assert_subset_hits(&maxsim, &hits, &[2, 3, 0]);
+        assert_subset_hits(&maxsim, &maxsim.search_preloaded_filtered(&UNIT_QUERY, 1, 0b100).unwrap(), &[]);
+
+        // Reloading without metadata drops the tags
+        maxsim.load_documents(&UNIT_DOCS, &[1, 1, 1, 1], 2).unwrap();
+        assert!(maxsim.doc_tags.is_empty());
+    }
+
+    #[test]
+    fn test_search_and_fuse_keeps_both_inputs() {
+        let mut maxsim = MaxSimWasm::new();
+        maxsim.load_documents(&[1.0, 0.0, 0.0, 1.0, 0.6, 0.8], &[1, 1, 1], 2).unwrap();
+        let query = [0.0, 1.0];
+        let bm25 = [9.0, 1.0, f32::NAN];
+
+        let hits = maxsim.search_and_fuse(&query, 1, &bm25, FusionMethod::WeightedSum, 0.5, 2).unwrap();
+        assert_eq!(hits.indices(), vec![0, 1]); // 0.5 + 0 vs 0 + 0.5, ties keep document order
+        assert_eq!(hits.maxsim_scores(), vec![0.0, 1.0]);
+        assert_eq!(hits.external_scores(), vec![9.0, 1.0]);
+    }
+
+    #[cfg(feature = "profiling")]
+    #[test]
+    fn test_search_profile_covers_the_batch_stages() {
         let maxsim = MaxSimWasm::new();
-        let query = vec![1.0, 0.0, 0.0, 0.0, 1.0, 0.0];
-        let doc = vec![1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0];
-        let score = maxsim.maxsim_single_normalized(&query, 2, &doc, 3, 3);
-        // Normalized MaxSim: averaged, should be between -1 and 1
-        assert!(score >= -1.0 && score <= 1.0);
+        let dim = 4;
+        // One padded group of lengths 10-12, scored after an unsorted input
+        let doc_tokens = [12, 10, 11, 10, 12];
+        let docs: Vec<f32> = (0..doc_tokens.iter().sum::<usize>() * dim).map(|v| (v % 7) as f32 * 0.1).collect();
+        maxsim.maxsim_batch(&[1.0, 0.5, 0.0, -0.5], 1, &docs, &doc_tokens, dim).unwrap();
+
+        let profile = maxsim.last_search_profile();
+        assert_eq!(profile.padding_floats(), (2 + 1 + 2) * dim);
+        assert!(profile.similarity_ms() > 0.0 && profile.reduction_ms() > 0.0);
+        assert!(profile.total_ms() >= profile.sort_ms() + profile.pack_ms());
+
+        maxsim.maxsim_batch(&[1.0, 0.5, 0.0, -0.5], 1, &[], &[], dim).unwrap();
+        assert_eq!(maxsim.last_search_profile().total_ms(), 0.0); // Reset per search
     }
 }
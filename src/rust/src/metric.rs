@@ -0,0 +1,88 @@
+/*!
+ * Similarity metrics
+ *
+ * All metrics are computed from the same SIMD dot products; Cosine and NegativeL2
+ * additionally use per-token squared norms, so no normalized copy of the documents
+ * is ever materialized:
+ * - DotProduct: q · d (cosine for L2-normalized embeddings - the default)
+ * - Cosine:     q · d / (|q| |d|), for embeddings that are not unit vectors
+ * - NegativeL2: -|q - d|² = 2 q · d - |q|² - |d|², for checkpoints trained with squared L2
+ */
+
+use wasm_bindgen::prelude::*;
+
+use crate::dot_product;
+
+/// Token-level similarity metric used inside MaxSim
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Metric {
+    #[default]
+    DotProduct = 0,
+    Cosine = 1,
+    NegativeL2 = 2,
+}
+
+impl Metric {
+    /// Whether the metric needs per-token squared norms
+    pub(crate) fn needs_norms(self) -> bool {
+        self != Metric::DotProduct
+    }
+
+    /// Convert raw dot products of one query token against a run of document tokens
+    /// into metric similarities, in place
+    #[inline]
+    pub(crate) fn apply(self, row: &mut [f32], query_norm_sq: f32, doc_norms_sq: &[f32]) {
+        match self {
+            Metric::DotProduct => {}
+            Metric::Cosine => {
+                let query_norm = query_norm_sq.sqrt();
+                for (sim, &doc_norm_sq) in row.iter_mut().zip(doc_norms_sq) {
+                    let denom = query_norm * doc_norm_sq.sqrt();
+                    *sim = if denom > 0.0 { *sim / denom } else { 0.0 };
+                }
+            }
+            Metric::NegativeL2 => {
+                for (sim, &doc_norm_sq) in row.iter_mut().zip(doc_norms_sq) {
+                    *sim = 2.0 * *sim - query_norm_sq - doc_norm_sq;
+                }
+            }
+        }
+    }
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Metric::DotProduct => "dot",
+            Metric::Cosine => "cosine",
+            Metric::NegativeL2 => "neg_l2",
+        }
+    }
+}
+
+/// Squared L2 norm of every token vector in a flat array
+pub(crate) fn token_norms_sq(flat: &[f32], embedding_dim: usize) -> Vec<f32> {
+    flat.chunks_exact(embedding_dim)
+        .map(|token| dot_product(token, token))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cosine_and_negative_l2() {
+        // q = (3, 4), d0 = (3, 4), d1 = (0, 2)
+        let norms = token_norms_sq(&[3.0, 4.0, 0.0, 2.0], 2);
+        assert_eq!(norms, vec![25.0, 4.0]);
+
+        let mut cosine = vec![25.0, 8.0];
+        Metric::Cosine.apply(&mut cosine, 25.0, &norms);
+        assert!((cosine[0] - 1.0).abs() < 1e-6);
+        assert!((cosine[1] - 0.8).abs() < 1e-6);
+
+        let mut neg_l2 = vec![25.0, 8.0];
+        Metric::NegativeL2.apply(&mut neg_l2, 25.0, &norms);
+        assert_eq!(neg_l2, vec![0.0, -13.0]);
+    }
+}
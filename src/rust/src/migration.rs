@@ -0,0 +1,96 @@
+/*!
+ * Score-compatible migration between score modes
+ *
+ * Switching a deployed app from raw-sum to normalized scoring (or back) breaks every
+ * stored threshold. Both modes derive from the same per-query-token sum, so:
+ * - thresholds convert exactly given the number of active query tokens, and
+ * - during a migration window both scores come out of a single pass (one division).
+ */
+
+use wasm_bindgen::prelude::*;
+
+/// How per-query-token similarities are combined into a document score
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ScoreMode {
+    /// Official MaxSim: raw sum over query tokens
+    #[default]
+    Raw = 0,
+    /// Averaged over query tokens, comparable across queries
+    Normalized = 1,
+}
+
+impl ScoreMode {
+    /// Score in this mode from the raw sum over `query_tokens` active query tokens
+    #[inline]
+    pub(crate) fn convert_raw(self, raw: f32, query_tokens: usize) -> f32 {
+        match self {
+            ScoreMode::Raw => raw,
+            ScoreMode::Normalized => raw / query_tokens.max(1) as f32,
+        }
+    }
+
+    #[inline]
+    fn to_raw(self, score: f32, query_tokens: usize) -> f32 {
+        match self {
+            ScoreMode::Raw => score,
+            ScoreMode::Normalized => score * query_tokens.max(1) as f32,
+        }
+    }
+}
+
+/// Convert a score threshold between modes for a query with `query_tokens` active tokens
+///
+/// Use `MaxSimWasm.active_query_tokens()` when masks or a stopmask drop query tokens.
+#[wasm_bindgen]
+pub fn convert_threshold(threshold: f32, from: ScoreMode, to: ScoreMode, query_tokens: usize) -> f32 {
+    to.convert_raw(from.to_raw(threshold, query_tokens), query_tokens)
+}
+
+/// Scores of the same documents in the previous and the current score mode
+#[wasm_bindgen]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DualScores {
+    previous: Vec<f32>,
+    current: Vec<f32>,
+}
+
+#[wasm_bindgen]
+impl DualScores {
+    /// Scores in the mode being migrated from (Float32Array)
+    #[wasm_bindgen(getter)]
+    pub fn previous(&self) -> Vec<f32> {
+        self.previous.clone()
+    }
+
+    /// Scores in the mode being migrated to (Float32Array)
+    #[wasm_bindgen(getter)]
+    pub fn current(&self) -> Vec<f32> {
+        self.current.clone()
+    }
+}
+
+impl DualScores {
+    pub(crate) fn from_raw(raw: &[f32], previous: ScoreMode, current: ScoreMode, query_tokens: usize) -> Self {
+        DualScores {
+            previous: raw.iter().map(|&s| previous.convert_raw(s, query_tokens)).collect(),
+            current: raw.iter().map(|&s| current.convert_raw(s, query_tokens)).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_threshold_conversion_round_trips() {
+        assert_eq!(convert_threshold(12.0, ScoreMode::Raw, ScoreMode::Normalized, 16), 0.75);
+        assert_eq!(convert_threshold(0.75, ScoreMode::Normalized, ScoreMode::Raw, 16), 12.0);
+        assert_eq!(convert_threshold(0.5, ScoreMode::Normalized, ScoreMode::Normalized, 16), 0.5);
+
+        let dual = DualScores::from_raw(&[8.0, 4.0], ScoreMode::Raw, ScoreMode::Normalized, 8);
+        assert_eq!(dual.previous(), vec![8.0, 4.0]);
+        assert_eq!(dual.current(), vec![1.0, 0.5]);
+    }
+}
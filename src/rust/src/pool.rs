@@ -0,0 +1,308 @@
+/*!
+ * Worker pool orchestration
+ *
+ * `MaxSimPool` splits a corpus into contiguous, token-balanced shards, one per Web
+ * Worker, broadcasts each query to every worker holding a shard and merges the
+ * per-shard top-k into one global ranking (see `rank_cmp`). Each worker runs its own
+ * `MaxSimWasm` and answers the pool's messages with `handle_pool_message()`:
+ *
+ * ```js
+ * // maxsim-pool-worker.js
+ * import init, { MaxSimWasm, handle_pool_message } from './maxsim_web_wasm.js';
+ * await init();
+ * const engine = new MaxSimWasm();
+ * self.onmessage = (event) => self.postMessage(handle_pool_message(engine, event.data));
+ *
+ * // main thread
+ * const workers = Array.from({ length: 4 }, () => new Worker('maxsim-pool-worker.js', { type: 'module' }));
+ * const pool = new MaxSimPool(workers);
+ * await pool.load_documents(embeddings, docTokens, dim);
+ * const hits = await pool.search(query, queryTokens, 10, false); // SearchHits, global indices
+ * ```
+ *
+ * Messages are plain objects (`{id, type: "load" | "search", ...}`); shard embeddings
+ * are transferred, not copied, to their worker. An `{id, error}` reply rejects its
+ * call; an `error` or `messageerror` event on a worker rejects all of its pending calls.
+ */
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::rc::Rc;
+
+use js_sys::{Array, Float32Array, Function, Object, Promise, Reflect, Uint32Array};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::{future_to_promise, JsFuture};
+
+use crate::results::SearchHits;
+use crate::MaxSimWasm;
+
+// (resolve, reject) of every request awaiting a reply, by (request id, worker)
+type Pending = Rc<RefCell<BTreeMap<(u32, usize), (Function, Function)>>>;
+
+/// Corpus sharded across Web Workers, searched as one
+#[wasm_bindgen]
+pub struct MaxSimPool {
+    workers: Vec<JsValue>,
+    shards: Rc<RefCell<Vec<(usize, usize)>>>, // (worker, first document) of every loaded shard
+    pending: Pending,
+    next_id: u32,
+    _listeners: Vec<Closure<dyn FnMut(JsValue)>>, // Kept alive for the pool's lifetime
+}
+
+#[wasm_bindgen]
+impl MaxSimPool {
+    /// Create a pool over `workers` (Worker objects answering with `handle_pool_message()`)
+    #[wasm_bindgen(constructor)]
+    pub fn new(workers: Array) -> Result<MaxSimPool, JsValue> {
+        if workers.length() == 0 {
+            return Err(JsValue::from_str("A pool needs at least one worker"));
+        }
+
+        let pending: Pending = Rc::new(RefCell::new(BTreeMap::new()));
+        let mut listeners = Vec::new();
+        for (index, worker) in workers.iter().enumerate() {
+            let requests = Rc::clone(&pending);
+            let listener = Closure::<dyn FnMut(JsValue)>::new(move |event: JsValue| {
+                let Ok(reply) = Reflect::get(&event, &"data".into()) else { return };
+                let id = get_number(&reply, "id").unwrap_or(-1.0) as u32;
+                let Some((resolve, reject)) = requests.borrow_mut().remove(&(id, index)) else { return };
+                match Reflect::get(&reply, &"error".into()) {
+                    Ok(error) if !error.is_undefined() => reject.call1(&JsValue::NULL, &error),
+                    _ => resolve.call1(&JsValue::NULL, &reply),
+                }
+                .ok();
+            });
+            let add_listener: Function = Reflect::get(&worker, &"addEventListener".into())?.dyn_into()?;
+            add_listener.call2(&worker, &"message".into(), listener.as_ref())?;
+            listeners.push(listener);
+
+            // A crashed worker, or a reply that can't be deserialized, never answers:
+            // reject everything still waiting on it
+            for kind in ["error", "messageerror"] {
+                let requests = Rc::clone(&pending);
+                let listener = Closure::<dyn FnMut(JsValue)>::new(move |event: JsValue| {
+                    let error = match Reflect::get(&event, &"message".into()) {
+                        Ok(message) if message.is_string() => message,
+                        _ => JsValue::from_str(&format!("Pool worker {} failed ({})", index, kind)),
+                    };
+                    let failed: Vec<(u32, usize)> =
+                        requests.borrow().keys().filter(|&&(_, worker)| worker == index).copied().collect();
+                    for key in failed {
+                        if let Some((_, reject)) = requests.borrow_mut().remove(&key) {
+                            reject.call1(&JsValue::NULL, &error).ok();
+                        }
+                    }
+                });
+                add_listener.call2(&worker, &kind.into(), listener.as_ref())?;
+                listeners.push(listener);
+            }
+        }
+
+        Ok(MaxSimPool {
+            workers: workers.iter().collect(),
+            shards: Rc::new(RefCell::new(Vec::new())),
+            pending,
+            next_id: 0,
+            _listeners: listeners,
+        })
+    }
+
+    /// Number of workers in the pool
+    #[wasm_bindgen(getter)]
+    pub fn size(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Shard documents across the workers (replacing their corpora)
+    ///
+    /// Shards are contiguous and balanced by token count. Resolves once every worker
+    /// has loaded its shard; searches are refused until then (and after a failed load).
+    #[wasm_bindgen]
+    pub fn load_documents(&mut self, embeddings_data: &[f32], doc_tokens: &[usize], embedding_dim: usize) -> Result<Promise, JsValue> {
+        crate::check_documents(embeddings_data, doc_tokens, embedding_dim).map_err(|e| JsValue::from_str(&e))?;
+
+        self.shards.borrow_mut().clear();
+        let replies = Array::new();
+        let mut shards = Vec::new();
+        let mut first_token = 0;
+        for (worker, docs) in shard_ranges(doc_tokens, self.workers.len()).into_iter().enumerate() {
+            let tokens: usize = doc_tokens[docs.clone()].iter().sum();
+            let embeddings = Float32Array::from(&embeddings_data[first_token * embedding_dim..(first_token + tokens) * embedding_dim]);
+            let counts: Vec<u32> = doc_tokens[docs.clone()].iter().map(|&count| count as u32).collect();
+
+            let message = Object::new();
+            Reflect::set(&message, &"type".into(), &"load".into())?;
+            Reflect::set(&message, &"embeddings".into(), &embeddings)?;
+            Reflect::set(&message, &"docTokens".into(), &Uint32Array::from(&counts[..]))?;
+            Reflect::set(&message, &"dim".into(), &(embedding_dim as u32).into())?;
+            let reply = self.request(worker, &message, &Array::of1(&embeddings.buffer()))?;
+            replies.push(&reply);
+
+            shards.push((worker, docs.start));
+            first_token += tokens;
+        }
+
+        let loaded = Rc::clone(&self.shards);
+        Ok(future_to_promise(async move {
+            let replies = JsFuture::from(Promise::all(&replies)).await?;
+            *loaded.borrow_mut() = shards;
+            Ok(replies)
+        }))
+    }
+
+    /// Search every shard and merge the per-shard top `k` (0 = all documents)
+    ///
+    /// Resolves to `SearchHits` with global document indices, best first.
+    #[wasm_bindgen]
+    pub fn search(&mut self, query_flat: &[f32], query_tokens: usize, k: usize, normalized: bool) -> Result<Promise, JsValue> {
+        let shards = self.shards.borrow().clone();
+        if shards.is_empty() {
+            return Err(JsValue::from_str("No documents loaded. Call load_documents() first."));
+        }
+
+        let replies = Array::new();
+        for &(worker, _) in &shards {
+            let message = Object::new();
+            Reflect::set(&message, &"type".into(), &"search".into())?;
+            Reflect::set(&message, &"query".into(), &Float32Array::from(query_flat))?;
+            Reflect::set(&message, &"queryTokens".into(), &(query_tokens as u32).into())?;
+            Reflect::set(&message, &"k".into(), &(k as u32).into())?;
+            Reflect::set(&message, &"normalized".into(), &normalized.into())?;
+            let reply = self.request(worker, &message, &Array::new())?;
+            replies.push(&reply);
+        }
+
+        let first_docs: Vec<usize> = shards.iter().map(|&(_, first_doc)| first_doc).collect();
+        Ok(future_to_promise(async move {
+            let replies: Array = JsFuture::from(Promise::all(&replies)).await?.dyn_into()?;
+            let mut shard_hits = Vec::with_capacity(first_docs.len());
+            for (reply, first_doc) in replies.iter().zip(first_docs) {
+                let indices: Uint32Array = Reflect::get(&reply, &"indices".into())?.dyn_into()?;
+                let scores: Float32Array = Reflect::get(&reply, &"scores".into())?.dyn_into()?;
+                shard_hits.push((first_doc, indices.to_vec(), scores.to_vec()));
+            }
+            Ok(merge_shard_hits(&shard_hits, k).into())
+        }))
+    }
+
+    // Post `message` to one worker under a fresh id; resolves with its reply
+    fn request(&mut self, worker: usize, message: &Object, transfer: &Array) -> Result<Promise, JsValue> {
+        self.next_id = self.next_id.wrapping_add(1);
+        let id = self.next_id;
+        Reflect::set(message, &"id".into(), &id.into())?;
+
+        let pending = Rc::clone(&self.pending);
+        let reply = Promise::new(&mut |resolve, reject| {
+            pending.borrow_mut().insert((id, worker), (resolve, reject));
+        });
+        let post: Function = Reflect::get(&self.workers[worker], &"postMessage".into())?.dyn_into()?;
+        if let Err(error) = post.call2(&self.workers[worker], message, transfer) {
+            self.pending.borrow_mut().remove(&(id, worker));
+            return Err(error);
+        }
+        Ok(reply)
+    }
+}
+
+/// Answer one `MaxSimPool` message inside a worker (see the module docs)
+///
+/// Returns the reply to post back: `{id}` after a load, `{id, indices, scores}` (shard
+/// top-k, shard-local indices) after a search, `{id, error}` on failure.
+#[wasm_bindgen]
+pub fn handle_pool_message(engine: &mut MaxSimWasm, message: &JsValue) -> JsValue {
+    let reply = Object::new();
+    let id = Reflect::get(message, &"id".into()).unwrap_or(JsValue::UNDEFINED);
+    let _ = Reflect::set(&reply, &"id".into(), &id);
+    if let Err(error) = answer(engine, message, &reply) {
+        let _ = Reflect::set(&reply, &"error".into(), &error);
+    }
+    reply.into()
+}
+
+fn answer(engine: &mut MaxSimWasm, message: &JsValue, reply: &Object) -> Result<(), JsValue> {
+    let kind = Reflect::get(message, &"type".into())?.as_string().unwrap_or_default();
+    match kind.as_str() {
+        "load" => {
+            let embeddings: Float32Array = Reflect::get(message, &"embeddings".into())?.dyn_into()?;
+            let doc_tokens: Uint32Array = Reflect::get(message, &"docTokens".into())?.dyn_into()?;
+            let doc_tokens = doc_tokens.to_vec().into_iter().map(|count| count as usize).collect();
+            engine.load_documents_owned(embeddings.to_vec(), doc_tokens, get_number(message, "dim")? as usize)
+        }
+        "search" => {
+            let query: Float32Array = Reflect::get(message, &"query".into())?.dyn_into()?;
+            let query_tokens = get_number(message, "queryTokens")? as usize;
+            let scores = if Reflect::get(message, &"normalized".into())?.is_truthy() {
+                engine.search_preloaded_normalized(&query.to_vec(), query_tokens)?
+            } else {
+                engine.search_preloaded(&query.to_vec(), query_tokens)?
+            };
+            let k = match get_number(message, "k")? as usize {
+                0 => scores.len(),
+                k => k,
+            };
+            let all: Vec<usize> = (0..scores.len()).collect();
+            let hits = SearchHits::top_k(&all, &scores, k);
+            Reflect::set(reply, &"indices".into(), &Uint32Array::from(&hits.indices()[..]))?;
+            Reflect::set(reply, &"scores".into(), &Float32Array::from(&hits.scores()[..]))?;
+            Ok(())
+        }
+        _ => Err(JsValue::from_str(&format!("Unknown pool message type {:?}", kind))),
+    }
+}
+
+fn get_number(object: &JsValue, key: &str) -> Result<f64, JsValue> {
+    Reflect::get(object, &key.into())?
+        .as_f64()
+        .ok_or_else(|| JsValue::from_str(&format!("Pool message field {:?} must be a number", key)))
+}
+
+// Contiguous document ranges, at most `workers` of them, with about equal token counts
+fn shard_ranges(doc_tokens: &[usize], workers: usize) -> Vec<Range<usize>> {
+    let total_tokens: usize = doc_tokens.iter().sum();
+    let mut ranges = Vec::with_capacity(workers);
+    let (mut start, mut seen) = (0, 0);
+    for (doc, &tokens) in doc_tokens.iter().enumerate() {
+        seen += tokens;
+        let shard = ranges.len();
+        if shard + 1 < workers && seen * workers >= total_tokens * (shard + 1) {
+            ranges.push(start..doc + 1);
+            start = doc + 1;
+        }
+    }
+    if start < doc_tokens.len() {
+        ranges.push(start..doc_tokens.len());
+    }
+    ranges
+}
+
+// Global top `k` (0 = all) of per-shard hits given as (first document, local indices, scores)
+fn merge_shard_hits(shards: &[(usize, Vec<u32>, Vec<f32>)], k: usize) -> SearchHits {
+    let mut indices = Vec::new();
+    let mut scores = Vec::new();
+    for (first_doc, shard_indices, shard_scores) in shards {
+        indices.extend(shard_indices.iter().map(|&index| first_doc + index as usize));
+        scores.extend_from_slice(shard_scores);
+    }
+    let k = if k == 0 { indices.len() } else { k };
+    SearchHits::top_k(&indices, &scores, k)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shards_balance_tokens_and_merge_globally() {
+        assert_eq!(shard_ranges(&[4, 1, 1, 1, 1], 2), vec![0..1, 1..5]);
+        assert_eq!(shard_ranges(&[2, 2, 2, 2], 3), vec![0..2, 2..3, 3..4]);
+        assert_eq!(shard_ranges(&[3, 3], 4), vec![0..1, 1..2]);
+
+        let shards = [(0, vec![1, 0], vec![0.9, 0.5]), (2, vec![0, 1], vec![0.9, 0.7])];
+        let merged = merge_shard_hits(&shards, 3);
+        assert_eq!(merged.indices(), vec![1, 2, 3]); // Equal scores by lower global index
+        assert_eq!(merged.scores(), vec![0.9, 0.9, 0.7]);
+        assert_eq!(merge_shard_hits(&shards, 0).length(), 4);
+    }
+}
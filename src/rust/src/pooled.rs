@@ -0,0 +1,110 @@
+/*!
+ * Single-vector index of pooled documents
+ *
+ * With `MaxSimWasm.set_pooling()` every preloaded document is also summarized by
+ * one L2-normalized vector: the mean of its tokens, or its first token (the [CLS]
+ * position of encoders that emit one). `search_pooled()` ranks the corpus by cosine
+ * with a pooled query vector, one dot product per document, as a cheap first pass
+ * whose hits `rerank()` then scores with full MaxSim.
+ */
+
+use wasm_bindgen::prelude::*;
+
+use crate::store::PreloadedDocuments;
+use crate::{dot_product, normalize_tokens};
+
+/// How a document is summarized by one vector
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Pooling {
+    /// No pooled index
+    #[default]
+    None = 0,
+    /// Mean of the document's tokens
+    Mean = 1,
+    /// The document's first token ([CLS])
+    First = 2,
+}
+
+/// One pooled, L2-normalized vector per preloaded document, in original order
+pub(crate) struct PooledIndex {
+    embedding_dim: usize,
+    pooling: Pooling,
+    vectors: Vec<f32>, // num_docs × embedding_dim (zeros for empty documents)
+}
+
+impl PooledIndex {
+    /// Pool every document (None for `Pooling::None`)
+    pub(crate) fn build(docs: &PreloadedDocuments, pooling: Pooling) -> Option<Self> {
+        let dim = docs.embedding_dim;
+        let vectors = match pooling {
+            Pooling::None => return None,
+            Pooling::Mean => crate::ann::pooled_vectors(docs),
+            Pooling::First => {
+                let mut vectors = Vec::with_capacity(docs.num_docs() * dim);
+                for doc_idx in 0..docs.num_docs() {
+                    let embeddings = docs.document(doc_idx).0;
+                    let start = vectors.len();
+                    vectors.extend(embeddings.get(..dim).unwrap_or(&vec![0.0; dim]));
+                    normalize_tokens(&mut vectors[start..], dim);
+                }
+                vectors
+            }
+        };
+        Some(PooledIndex { embedding_dim: dim, pooling, vectors })
+    }
+
+    pub(crate) fn embedding_dim(&self) -> usize {
+        self.embedding_dim
+    }
+
+    pub(crate) fn pooling(&self) -> Pooling {
+        self.pooling
+    }
+
+    /// The pooled vectors, flat (num_docs × embedding_dim)
+    pub(crate) fn vectors(&self) -> &[f32] {
+        &self.vectors
+    }
+
+    /// Pool a multi-vector query the way the documents were pooled, L2-normalized
+    pub(crate) fn pool_query(&self, query_flat: &[f32]) -> Vec<f32> {
+        let dim = self.embedding_dim;
+        match self.pooling {
+            Pooling::First => {
+                let mut pooled = query_flat.get(..dim).map_or(vec![0.0; dim], <[f32]>::to_vec);
+                normalize_tokens(&mut pooled, dim);
+                pooled
+            }
+            _ => crate::ann::mean_pool(query_flat, dim),
+        }
+    }
+
+    /// Cosine of every document with the query, times the query's norm
+    pub(crate) fn scores(&self, query_pooled: &[f32]) -> Vec<f32> {
+        self.vectors.chunks_exact(self.embedding_dim).map(|vector| dot_product(query_pooled, vector)).collect()
+    }
+
+    /// Bytes held by the pooled vectors
+    pub(crate) fn memory_bytes(&self) -> usize {
+        self.vectors.len() * std::mem::size_of::<f32>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mean_and_first_token_pooling() {
+        let docs = PreloadedDocuments::from_flat(&[3.0, 0.0, 0.0, 1.0, 0.0, 2.0], &[2, 1], 2);
+        let mean = PooledIndex::build(&docs, Pooling::Mean).unwrap();
+        let first = PooledIndex::build(&docs, Pooling::First).unwrap();
+        assert!(PooledIndex::build(&docs, Pooling::None).is_none());
+
+        assert_eq!(first.scores(&[1.0, 0.0]), [1.0, 0.0]);
+        let mean_scores = mean.scores(&[1.0, 0.0]);
+        assert!((mean_scores[0] - 3.0 / 10f32.sqrt()).abs() < 1e-6 && mean_scores[1] == 0.0);
+        assert_eq!(mean.memory_bytes(), 16);
+    }
+}
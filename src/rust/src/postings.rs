@@ -0,0 +1,130 @@
+/*!
+ * Centroid postings of the residual corpus
+ *
+ * Every token of a residual-compressed corpus (see residual.rs) already carries the
+ * index of its nearest centroid, so an inverted index comes for free: for every
+ * centroid, the documents with at least one token assigned to it. Candidate
+ * generation follows PLAID / EMVB:
+ *
+ * 1. score every query token against every centroid (one small matrix product);
+ * 2. probe each query token's `ncells` best centroids and collect their documents;
+ * 3. rank the collected documents by their centroid interaction, the MaxSim of the
+ *    query over the probed centroids each document has
+ *    (`Σ_q max_{c ∈ probed(q) ∩ doc} q · c`), and keep the best `max_candidates`.
+ *
+ * Only the kept candidates are decompressed and scored exactly, so the cost of a
+ * search follows `ncells` and `max_candidates` rather than the corpus size. More
+ * cells raise recall (documents reached by fewer probed centroids are found) and a
+ * larger cap lets more of them through to exact scoring.
+ *
+ * Postings are built on the first pruned search (4 bytes per document and distinct
+ * centroid, plus the first token of every document) and kept until the corpus
+ * changes.
+ */
+
+use crate::dot_product;
+
+/// Documents per centroid, in compressed sparse row form
+pub(crate) struct CentroidPostings {
+    offsets: Vec<usize>,      // num_centroids + 1; documents of c are docs[offsets[c]..offsets[c + 1]]
+    docs: Vec<u32>,           // Ascending within every centroid
+    token_starts: Vec<usize>, // First token of every document
+}
+
+impl CentroidPostings {
+    /// Postings of the documents (`doc_tokens` tokens each) whose tokens have `codes`
+    pub(crate) fn build(codes: &[u32], doc_tokens: &[usize], num_centroids: usize) -> Self {
+        let mut pairs: Vec<(u32, u32)> = Vec::with_capacity(codes.len());
+        let mut token_starts = Vec::with_capacity(doc_tokens.len());
+        let mut first = 0;
+        for (doc, &tokens) in doc_tokens.iter().enumerate() {
+            token_starts.push(first);
+            pairs.extend(codes[first..first + tokens].iter().map(|&code| (code, doc as u32)));
+            first += tokens;
+        }
+        pairs.sort_unstable();
+        pairs.dedup();
+
+        let mut offsets = vec![0; num_centroids + 1];
+        for &(code, _) in &pairs {
+            offsets[code as usize + 1] += 1;
+        }
+        for c in 0..num_centroids {
+            offsets[c + 1] += offsets[c];
+        }
+        CentroidPostings { offsets, docs: pairs.into_iter().map(|(_, doc)| doc).collect(), token_starts }
+    }
+
+    /// First token of every document
+    pub(crate) fn token_starts(&self) -> &[usize] {
+        &self.token_starts
+    }
+
+    /// Bytes held by the postings
+    pub(crate) fn memory_bytes(&self) -> usize {
+        (self.offsets.len() + self.token_starts.len()) * std::mem::size_of::<usize>()
+            + self.docs.len() * std::mem::size_of::<u32>()
+    }
+
+    /// Documents reached by the `ncells` best centroids of every query token, best
+    /// centroid interaction first, at most `max_candidates` of them (0 = all)
+    pub(crate) fn candidates(&self, query_flat: &[f32], centroids: &[f32], dim: usize, ncells: usize, max_candidates: usize) -> Vec<usize> {
+        let num_docs = self.token_starts.len();
+        let mut interaction = vec![0.0f32; num_docs];
+        let mut last_token = vec![usize::MAX; num_docs];
+        let mut reached = Vec::new();
+
+        for (q_idx, query_token) in query_flat.chunks_exact(dim).enumerate() {
+            let mut cells: Vec<(usize, f32)> =
+                centroids.chunks_exact(dim).map(|centroid| dot_product(query_token, centroid)).enumerate().collect();
+            let ncells = ncells.min(cells.len());
+            if ncells < cells.len() {
+                cells.select_nth_unstable_by(ncells, |a, b| b.1.total_cmp(&a.1));
+                cells.truncate(ncells);
+            }
+            cells.sort_unstable_by(|a, b| b.1.total_cmp(&a.1));
+
+            // Cells by decreasing similarity: a document's first hit is its maximum
+            for (cell, similarity) in cells {
+                for &doc in &self.docs[self.offsets[cell]..self.offsets[cell + 1]] {
+                    let doc = doc as usize;
+                    if last_token[doc] == q_idx {
+                        continue;
+                    }
+                    if last_token[doc] == usize::MAX {
+                        reached.push(doc);
+                    }
+                    last_token[doc] = q_idx;
+                    interaction[doc] += similarity;
+                }
+            }
+        }
+
+        reached.sort_unstable_by(|&a, &b| interaction[b].total_cmp(&interaction[a]).then(a.cmp(&b)));
+        if max_candidates > 0 {
+            reached.truncate(max_candidates);
+        }
+        reached
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_probed_cells_rank_documents_by_centroid_interaction() {
+        // Centroids along the axes; documents 0: {0, 1}, 1: {1}, 2: {2} (twice)
+        let centroids = [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0];
+        let postings = CentroidPostings::build(&[0, 1, 1, 2, 2], &[2, 1, 2], 3);
+        assert_eq!(postings.token_starts(), [0, 2, 3]);
+        assert_eq!((postings.offsets.clone(), postings.docs.clone()), (vec![0, 1, 3, 4], vec![0, 0, 1, 2]));
+
+        // Query tokens near centroids 0 and 1
+        let query = [0.9, 0.1, 0.0, 0.2, 0.8, 0.0];
+        assert_eq!(postings.candidates(&query, &centroids, 3, 1, 0), [0, 1]);
+        // Two cells per token reach no new document; a cap keeps the best
+        assert_eq!(postings.candidates(&query, &centroids, 3, 2, 1), [0]);
+        assert_eq!(postings.candidates(&query, &centroids, 3, 3, 0), [0, 1, 2]);
+    }
+}
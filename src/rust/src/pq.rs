@@ -0,0 +1,271 @@
+/*!
+ * Product quantization (PQ) of token embeddings
+ *
+ * The embedding is split into `num_subspaces` equal sub-vectors; each subspace has
+ * a codebook of 256 sub-centroids and every token stores one byte per subspace,
+ * the index of its nearest sub-centroid. At dim 128 with 16 subspaces a token
+ * costs 16 bytes instead of 512 (32× less memory).
+ *
+ * Scoring never decodes tokens: for each query token a lookup table holds its dot
+ * product with every sub-centroid (`num_subspaces × 256` floats), and the dot
+ * product with a token is the sum of one table entry per subspace. Squared token
+ * norms (for Cosine and NegativeL2) come from the same codes, since the subspaces
+ * are orthogonal.
+ *
+ * Codebooks are trained at load by k-means in every subspace over an evenly
+ * spaced sample of the tokens, or imported (e.g. from faiss `ProductQuantizer`
+ * centroids, same layout) together with precomputed codes.
+ */
+
+use crate::dot_product;
+use crate::snapshot::{SectionReader, SectionWriter};
+
+/// Sub-centroids per subspace (one byte per code)
+pub(crate) const CODEBOOK_SIZE: usize = 256;
+
+/// k-means rounds when training codebooks
+const TRAIN_ITERATIONS: usize = 8;
+
+/// Tokens sampled for training (64 per sub-centroid)
+const TRAIN_SAMPLE: usize = 64 * CODEBOOK_SIZE;
+
+/// PQ-encoded corpus, in original document order
+pub(crate) struct PqDocuments {
+    pub(crate) embedding_dim: usize,
+    num_subspaces: usize,
+    codebooks: Vec<f32>,      // num_subspaces × 256 × sub_dim
+    centroid_norms: Vec<f32>, // Squared norm of every sub-centroid (num_subspaces × 256)
+    codes: Vec<u8>,           // num_subspaces bytes per token
+    doc_tokens: Vec<usize>,
+}
+
+impl PqDocuments {
+    /// Empty corpus with the given codebooks (`num_subspaces × 256 × sub_dim` floats)
+    pub(crate) fn new(codebooks: &[f32], embedding_dim: usize, num_subspaces: usize) -> Result<Self, String> {
+        if embedding_dim == 0 {
+            return Err("Embedding dimension must be > 0".to_string());
+        }
+        if num_subspaces == 0 || !embedding_dim.is_multiple_of(num_subspaces) {
+            return Err("num_subspaces must be > 0 and divide embedding_dim".to_string());
+        }
+        if codebooks.len() != CODEBOOK_SIZE * embedding_dim {
+            return Err(format!("Expected {} codebook floats (num_subspaces × 256 × sub_dim)", CODEBOOK_SIZE * embedding_dim));
+        }
+
+        let sub_dim = embedding_dim / num_subspaces;
+        Ok(PqDocuments {
+            embedding_dim,
+            num_subspaces,
+            codebooks: codebooks.to_vec(),
+            centroid_norms: codebooks.chunks_exact(sub_dim).map(|c| dot_product(c, c)).collect(),
+            codes: Vec::new(),
+            doc_tokens: Vec::new(),
+        })
+    }
+
+    /// Train codebooks on (a sample of) `flat` and encode it
+    pub(crate) fn train(flat: &[f32], doc_tokens: &[usize], embedding_dim: usize, num_subspaces: usize) -> Result<Self, String> {
+        if embedding_dim == 0 || num_subspaces == 0 || !embedding_dim.is_multiple_of(num_subspaces) {
+            return Err("num_subspaces must be > 0 and divide embedding_dim".to_string());
+        }
+        let mut docs = PqDocuments::new(&train_codebooks(flat, embedding_dim, num_subspaces), embedding_dim, num_subspaces)?;
+        docs.encode(flat, doc_tokens);
+        Ok(docs)
+    }
+
+    /// Encode and append documents with the current codebooks
+    pub(crate) fn encode(&mut self, flat: &[f32], doc_tokens: &[usize]) {
+        let sub_dim = self.sub_dim();
+        for token in flat.chunks_exact(self.embedding_dim) {
+            for (subspace, sub_vector) in token.chunks_exact(sub_dim).enumerate() {
+                let codebook = self.codebook(subspace);
+                self.codes.push(nearest_l2(codebook, sub_vector, sub_dim) as u8);
+            }
+        }
+        self.doc_tokens.extend_from_slice(doc_tokens);
+    }
+
+    /// Append documents encoded offline (`num_subspaces` codes per token)
+    pub(crate) fn append_codes(&mut self, codes: &[u8], doc_tokens: &[usize]) -> Result<(), String> {
+        let total_tokens: usize = doc_tokens.iter().sum();
+        if codes.len() != total_tokens * self.num_subspaces {
+            return Err("Codes size mismatch".to_string());
+        }
+        self.codes.extend_from_slice(codes);
+        self.doc_tokens.extend_from_slice(doc_tokens);
+        Ok(())
+    }
+
+    pub(crate) fn num_docs(&self) -> usize {
+        self.doc_tokens.len()
+    }
+
+    pub(crate) fn num_subspaces(&self) -> usize {
+        self.num_subspaces
+    }
+
+    pub(crate) fn codebooks(&self) -> &[f32] {
+        &self.codebooks
+    }
+
+    /// Write the codebooks and codes to a snapshot section
+    pub(crate) fn write_state(&self, out: &mut SectionWriter) {
+        out.u64(self.embedding_dim);
+        out.u64(self.num_subspaces);
+        out.f32s(&self.codebooks);
+        out.bytes(&self.codes);
+        out.usizes(&self.doc_tokens);
+    }
+
+    /// Read a corpus written by `write_state()`, with the checks of `new()` and `append_codes()`
+    pub(crate) fn read_state(input: &mut SectionReader) -> Result<Self, String> {
+        let (embedding_dim, num_subspaces) = (input.u64()?, input.u64()?);
+        let mut docs = PqDocuments::new(&input.f32s()?, embedding_dim, num_subspaces)?;
+        let (codes, doc_tokens) = (input.bytes()?, input.usizes()?);
+        docs.append_codes(codes, &doc_tokens)?;
+        Ok(docs)
+    }
+
+    /// Bytes held by the encoded corpus (codebooks included)
+    pub(crate) fn memory_bytes(&self) -> usize {
+        (self.codebooks.len() + self.centroid_norms.len()) * std::mem::size_of::<f32>() + self.codes.len()
+    }
+
+    fn sub_dim(&self) -> usize {
+        self.embedding_dim / self.num_subspaces
+    }
+
+    fn codebook(&self, subspace: usize) -> &[f32] {
+        let len = CODEBOOK_SIZE * self.sub_dim();
+        &self.codebooks[subspace * len..(subspace + 1) * len]
+    }
+
+    /// Dot product of every query token with every sub-centroid
+    /// (query_tokens × num_subspaces × 256)
+    pub(crate) fn lookup_tables(&self, query_flat: &[f32]) -> Vec<f32> {
+        let sub_dim = self.sub_dim();
+        let mut tables = Vec::with_capacity(query_flat.len() / sub_dim * CODEBOOK_SIZE);
+        for query_token in query_flat.chunks_exact(self.embedding_dim) {
+            for (subspace, sub_query) in query_token.chunks_exact(sub_dim).enumerate() {
+                tables.extend(self.codebook(subspace).chunks_exact(sub_dim).map(|c| dot_product(sub_query, c)));
+            }
+        }
+        tables
+    }
+
+    /// Visit every document as its raw dot-product rows (query_tokens × doc_tokens, via
+    /// `tables` from `lookup_tables()`) and the squared norms of its decoded tokens
+    pub(crate) fn for_each_document(&self, tables: &[f32], mut score: impl FnMut(&mut [f32], &[f32], usize)) {
+        let m = self.num_subspaces;
+        let table_len = m * CODEBOOK_SIZE;
+        let query_tokens = tables.len() / table_len;
+        let (mut similarities, mut norms) = (Vec::new(), Vec::new());
+        let mut token = 0;
+
+        for &tokens in &self.doc_tokens {
+            let codes = &self.codes[token * m..(token + tokens) * m];
+            similarities.clear();
+            for table in tables.chunks_exact(table_len) {
+                similarities.extend(codes.chunks_exact(m).map(|token_codes| lookup(table, token_codes)));
+            }
+            norms.clear();
+            norms.extend(codes.chunks_exact(m).map(|token_codes| lookup(&self.centroid_norms, token_codes)));
+            debug_assert_eq!(similarities.len(), query_tokens * tokens);
+            score(&mut similarities, &norms, tokens);
+            token += tokens;
+        }
+    }
+}
+
+// Sum of one table entry per subspace
+#[inline]
+fn lookup(table: &[f32], token_codes: &[u8]) -> f32 {
+    token_codes.iter().enumerate().map(|(subspace, &code)| table[subspace * CODEBOOK_SIZE + code as usize]).sum()
+}
+
+// Index of the sub-centroid closest to `vector` in L2 distance
+fn nearest_l2(codebook: &[f32], vector: &[f32], sub_dim: usize) -> usize {
+    codebook
+        .chunks_exact(sub_dim)
+        .map(|centroid| centroid.iter().zip(vector).map(|(c, v)| (c - v) * (c - v)).sum::<f32>())
+        .enumerate()
+        .fold((0, f32::INFINITY), |best, (idx, dist)| if dist < best.1 { (idx, dist) } else { best })
+        .0
+}
+
+// k-means codebooks of every subspace over an evenly spaced sample of the tokens
+fn train_codebooks(flat: &[f32], embedding_dim: usize, num_subspaces: usize) -> Vec<f32> {
+    let sub_dim = embedding_dim / num_subspaces;
+    let num_tokens = flat.len() / embedding_dim;
+    let sample_size = num_tokens.min(TRAIN_SAMPLE);
+    let sample: Vec<&[f32]> = (0..sample_size)
+        .map(|i| {
+            let token = i * num_tokens / sample_size;
+            &flat[token * embedding_dim..(token + 1) * embedding_dim]
+        })
+        .collect();
+
+    let mut codebooks = Vec::with_capacity(CODEBOOK_SIZE * embedding_dim);
+    for subspace in 0..num_subspaces {
+        if sample.is_empty() {
+            codebooks.resize(codebooks.len() + CODEBOOK_SIZE * sub_dim, 0.0);
+            continue;
+        }
+        // Deterministic initialization: evenly spaced sample tokens (repeating when the
+        // sample is smaller than the codebook)
+        let mut centroids: Vec<f32> = (0..CODEBOOK_SIZE)
+            .flat_map(|c| &sample[c * sample_size / CODEBOOK_SIZE][subspace * sub_dim..(subspace + 1) * sub_dim])
+            .copied()
+            .collect();
+
+        for _ in 0..TRAIN_ITERATIONS {
+            let mut sums = vec![0.0f32; CODEBOOK_SIZE * sub_dim];
+            let mut counts = vec![0usize; CODEBOOK_SIZE];
+            for token in &sample {
+                let sub_vector = &token[subspace * sub_dim..(subspace + 1) * sub_dim];
+                let code = nearest_l2(&centroids, sub_vector, sub_dim);
+                counts[code] += 1;
+                for (sum, &value) in sums[code * sub_dim..(code + 1) * sub_dim].iter_mut().zip(sub_vector) {
+                    *sum += value;
+                }
+            }
+            // Empty clusters keep their previous centroid
+            for code in (0..CODEBOOK_SIZE).filter(|&c| counts[c] > 0) {
+                for (centroid, &sum) in centroids[code * sub_dim..(code + 1) * sub_dim].iter_mut().zip(&sums[code * sub_dim..]) {
+                    *centroid = sum / counts[code] as f32;
+                }
+            }
+        }
+        codebooks.extend(centroids);
+    }
+    codebooks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup_scores_match_decoded_tokens() {
+        // Few distinct tokens: every one gets its own sub-centroid, so PQ is lossless
+        let flat = vec![1.0, 0.0, 0.0, 1.0, 0.6, 0.8, -0.8, 0.6, 0.0, 1.0, 1.0, 0.0];
+        let docs = PqDocuments::train(&flat, &[2, 1], 4, 2).unwrap();
+        assert_eq!(docs.num_docs(), 2);
+        assert_eq!(docs.memory_bytes(), (256 * 4 + 2 * 256) * 4 + 3 * 2);
+
+        let query = vec![0.5, 0.5, 1.0, 0.0];
+        let tables = docs.lookup_tables(&query);
+        let mut rows = Vec::new();
+        docs.for_each_document(&tables, |similarities, norms, tokens| {
+            rows.push((similarities.to_vec(), norms.to_vec(), tokens));
+        });
+        let expected_doc0 = [dot_product(&query, &flat[..4]), dot_product(&query, &flat[4..8])];
+        assert!(rows[0].0.iter().zip(expected_doc0).all(|(a, b)| (a - b).abs() < 1e-6));
+        assert!(rows[0].1.iter().all(|&norm| (norm - 2.0).abs() < 1e-6));
+        assert_eq!(rows[1].2, 1);
+
+        assert!(PqDocuments::new(&[0.0; 10], 4, 3).is_err());
+        let mut imported = PqDocuments::new(docs.codebooks(), 4, 2).unwrap();
+        assert!(imported.append_codes(&[0, 1, 2], &[1]).is_err());
+    }
+}
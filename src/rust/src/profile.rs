@@ -0,0 +1,174 @@
+/*!
+ * Per-stage search profiling (`profiling` feature)
+ *
+ * With the feature enabled, every search accumulates the time spent in each stage
+ * of the batch path, retrievable via `MaxSimWasm.last_search_profile()`:
+ * - sort:       ordering documents by length (skipped for presorted pages)
+ * - pack:       copying documents into the padded batch buffer and zeroing padding
+ * - similarity: query × document dot products
+ * - reduction:  per-query-token max and aggregation into a score
+ *
+ * Stages are timed with `performance.now()` around short intervals (often one
+ * document), so the sums carry timer coarsening and a small per-interval overhead;
+ * compare stages against each other rather than against wall time.
+ *
+ * Without the feature `SearchProfile` is zero-sized and every call is a no-op.
+ */
+
+/// Timed stage of a search
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Stage {
+    Sort = 0,
+    Pack = 1,
+    Similarity = 2,
+    Reduction = 3,
+}
+
+#[cfg(feature = "profiling")]
+pub use enabled::*;
+#[cfg(not(feature = "profiling"))]
+pub(crate) use disabled::*;
+
+#[cfg(feature = "profiling")]
+mod enabled {
+    use wasm_bindgen::prelude::*;
+
+    use super::Stage;
+
+    #[cfg(target_arch = "wasm32")]
+    #[wasm_bindgen]
+    extern "C" {
+        // Sub-millisecond clock (Date.now() only resolves whole milliseconds)
+        #[wasm_bindgen(js_namespace = performance, js_name = now)]
+        fn performance_now() -> f64;
+    }
+
+    /// Start of a timed interval
+    #[derive(Clone, Copy)]
+    pub(crate) struct Stamp(f64);
+
+    #[inline]
+    pub(crate) fn stamp() -> Stamp {
+        #[cfg(target_arch = "wasm32")]
+        return Stamp(performance_now());
+        #[cfg(not(target_arch = "wasm32"))]
+        return Stamp(crate::clock::now_ms());
+    }
+
+    /// Stage timings of the latest search
+    #[wasm_bindgen]
+    #[derive(Clone, Debug, Default, PartialEq)]
+    pub struct SearchProfile {
+        stage_ms: [f64; 4],
+        padding_floats: usize,
+    }
+
+    impl SearchProfile {
+        pub(crate) fn reset(&mut self) {
+            *self = SearchProfile::default();
+        }
+
+        /// Add the time since `start` to `stage`
+        #[inline]
+        pub(crate) fn add(&mut self, stage: Stage, start: Stamp) {
+            self.stage_ms[stage as usize] += stamp().0 - start.0;
+        }
+
+        #[inline]
+        pub(crate) fn add_padding(&mut self, floats: usize) {
+            self.padding_floats += floats;
+        }
+    }
+
+    #[wasm_bindgen]
+    impl SearchProfile {
+        #[wasm_bindgen(getter)]
+        pub fn sort_ms(&self) -> f64 {
+            self.stage_ms[Stage::Sort as usize]
+        }
+
+        #[wasm_bindgen(getter)]
+        pub fn pack_ms(&self) -> f64 {
+            self.stage_ms[Stage::Pack as usize]
+        }
+
+        #[wasm_bindgen(getter)]
+        pub fn similarity_ms(&self) -> f64 {
+            self.stage_ms[Stage::Similarity as usize]
+        }
+
+        #[wasm_bindgen(getter)]
+        pub fn reduction_ms(&self) -> f64 {
+            self.stage_ms[Stage::Reduction as usize]
+        }
+
+        /// Sum of the stage timings
+        #[wasm_bindgen(getter)]
+        pub fn total_ms(&self) -> f64 {
+            self.stage_ms.iter().sum()
+        }
+
+        /// Zero floats written as padding into the batch buffer
+        #[wasm_bindgen(getter)]
+        pub fn padding_floats(&self) -> usize {
+            self.padding_floats
+        }
+
+        /// Human-readable summary, e.g. "sort=0.012ms pack=0.340ms similarity=4.100ms reduction=0.520ms padding=2048"
+        #[wasm_bindgen]
+        pub fn describe(&self) -> String {
+            format!(
+                "sort={:.3}ms pack={:.3}ms similarity={:.3}ms reduction={:.3}ms padding={}",
+                self.sort_ms(), self.pack_ms(), self.similarity_ms(), self.reduction_ms(), self.padding_floats
+            )
+        }
+    }
+}
+
+#[cfg(not(feature = "profiling"))]
+mod disabled {
+    use super::Stage;
+
+    #[derive(Clone, Copy)]
+    pub(crate) struct Stamp;
+
+    #[inline(always)]
+    pub(crate) fn stamp() -> Stamp {
+        Stamp
+    }
+
+    #[derive(Default)]
+    pub(crate) struct SearchProfile {}
+
+    impl SearchProfile {
+        #[inline(always)]
+        pub(crate) fn reset(&mut self) {}
+
+        #[inline(always)]
+        pub(crate) fn add(&mut self, _stage: Stage, _start: Stamp) {}
+
+        #[inline(always)]
+        pub(crate) fn add_padding(&mut self, _floats: usize) {}
+    }
+}
+
+#[cfg(all(test, feature = "profiling"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stages_accumulate_until_reset() {
+        let mut profile = SearchProfile::default();
+        let start = stamp();
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        profile.add(Stage::Similarity, start);
+        profile.add(Stage::Similarity, stamp());
+        profile.add_padding(16);
+        assert!(profile.similarity_ms() >= 2.0 && profile.sort_ms() == 0.0);
+        assert_eq!(profile.total_ms(), profile.similarity_ms());
+        assert_eq!(profile.padding_floats(), 16);
+
+        profile.reset();
+        assert_eq!(profile, SearchProfile::default());
+    }
+}
@@ -0,0 +1,73 @@
+/*!
+ * Prepared queries
+ *
+ * `MaxSimWasm.prepare_query()` runs query preparation once (stopmask and query-limit
+ * pruning, auto-normalization) and returns a `QueryHandle` that the `*_prepared`
+ * searches reuse, so pagination, filter changes and searches over several
+ * collections skip the per-call work.
+ *
+ * A handle is bound to the preparation settings it was built with: changing the
+ * stopmask, the query limit or auto-normalization makes it stale, and searches
+ * reject stale handles instead of silently scoring with outdated preparation.
+ */
+
+use wasm_bindgen::prelude::*;
+
+use crate::degradation::QueryDegradation;
+
+/// A query prepared once for repeated searches
+#[wasm_bindgen]
+pub struct QueryHandle {
+    pub(crate) tokens: Vec<f32>, // Prepared tokens, active_tokens × embedding_dim
+    pub(crate) active_tokens: usize,
+    original_tokens: usize,
+    pub(crate) embedding_dim: usize,
+    pub(crate) degradation: Option<QueryDegradation>,
+    pub(crate) settings: u32, // Preparation settings epoch it was built under
+}
+
+impl QueryHandle {
+    pub(crate) fn new(
+        tokens: Vec<f32>,
+        active_tokens: usize,
+        original_tokens: usize,
+        embedding_dim: usize,
+        degradation: Option<QueryDegradation>,
+        settings: u32,
+    ) -> Self {
+        QueryHandle { tokens, active_tokens, original_tokens, embedding_dim, degradation, settings }
+    }
+
+    /// Check the handle against the current settings epoch and the searched corpus
+    pub(crate) fn check(&self, settings: u32, embedding_dim: usize) -> Result<(), String> {
+        if self.settings != settings {
+            return Err("Query handle is stale (query preparation settings changed). Call prepare_query() again.".to_string());
+        }
+        if self.embedding_dim != embedding_dim {
+            return Err(format!(
+                "Query handle has dimension {}, documents have {}", self.embedding_dim, embedding_dim
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[wasm_bindgen]
+impl QueryHandle {
+    /// Query tokens scored after masking and pruning
+    #[wasm_bindgen(getter)]
+    pub fn active_tokens(&self) -> usize {
+        self.active_tokens
+    }
+
+    /// Query tokens passed to `prepare_query()`
+    #[wasm_bindgen(getter)]
+    pub fn original_tokens(&self) -> usize {
+        self.original_tokens
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn embedding_dim(&self) -> usize {
+        self.embedding_dim
+    }
+}
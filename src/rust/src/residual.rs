@@ -0,0 +1,239 @@
+/*!
+ * ColBERTv2-style residual compression of token embeddings
+ *
+ * Every token is stored as the index of its nearest centroid plus an `nbits` bucket
+ * code per dimension (the quantized residual). Decompression follows ColBERTv2:
+ *
+ *   token[d] = centroid[code][d] + bucket_weights[bucket[d]], then L2-normalize
+ *
+ * At dim 128 a token costs 4 + 128 · nbits / 8 bytes (36 bytes at 2 bits, 68 at 4)
+ * instead of 512, i.e. ~8-14× less memory. Residual bytes use the packed layout of
+ * the official toolchain (`residuals.pt`): every code is written least significant
+ * bit first and the bit stream is packed into bytes most significant bit first
+ * (`np.packbits`), so a byte holds `8 / nbits` codes, first code in the high bits,
+ * each with its bits reversed (colbert-ai undoes this with `reversed_bit_map`).
+ * Searches decompress one page-sized batch of documents at
+ * a time into a scratch buffer, so the corpus is never materialized in f32.
+ * Pruned searches decompress only the candidates found through the centroid
+ * postings (see postings.rs).
+ */
+
+use std::cell::OnceCell;
+
+use crate::normalize_tokens;
+use crate::postings::CentroidPostings;
+use crate::snapshot::{SectionReader, SectionWriter};
+use crate::store::DEFAULT_PAGE_BYTES;
+
+/// Residually compressed corpus, in original document order
+pub(crate) struct ResidualDocuments {
+    pub(crate) embedding_dim: usize,
+    nbits: usize,
+    centroids: Vec<f32>,      // num_centroids × embedding_dim
+    bucket_weights: Vec<f32>, // 2^nbits residual values
+    codes: Vec<u32>,          // Centroid index of every token
+    residuals: Vec<u8>,       // Packed bucket codes, embedding_dim · nbits / 8 bytes per token
+    doc_tokens: Vec<usize>,
+    postings: OnceCell<CentroidPostings>, // Built on first use, reset by append()
+}
+
+impl ResidualDocuments {
+    /// Empty corpus with the given codec
+    pub(crate) fn new(centroids: &[f32], bucket_weights: &[f32], embedding_dim: usize, nbits: usize) -> Result<Self, String> {
+        if embedding_dim == 0 {
+            return Err("Embedding dimension must be > 0".to_string());
+        }
+        if ![1, 2, 4, 8].contains(&nbits) || !(embedding_dim * nbits).is_multiple_of(8) {
+            return Err("nbits must be 1, 2, 4 or 8 and embedding_dim · nbits a multiple of 8".to_string());
+        }
+        if centroids.is_empty() || !centroids.len().is_multiple_of(embedding_dim) {
+            return Err("Centroids size mismatch".to_string());
+        }
+        if bucket_weights.len() != 1 << nbits {
+            return Err(format!("Expected {} bucket weights for nbits={}", 1 << nbits, nbits));
+        }
+
+        Ok(ResidualDocuments {
+            embedding_dim,
+            nbits,
+            centroids: centroids.to_vec(),
+            bucket_weights: bucket_weights.to_vec(),
+            codes: Vec::new(),
+            residuals: Vec::new(),
+            doc_tokens: Vec::new(),
+            postings: OnceCell::new(),
+        })
+    }
+
+    /// Append compressed documents (e.g. one chunk of a ColBERT index)
+    pub(crate) fn append(&mut self, codes: &[u32], residuals: &[u8], doc_tokens: &[usize]) -> Result<(), String> {
+        let total_tokens: usize = doc_tokens.iter().sum();
+        if codes.len() != total_tokens {
+            return Err("Codes size mismatch".to_string());
+        }
+        if residuals.len() != total_tokens * self.packed_bytes_per_token() {
+            return Err("Residuals size mismatch".to_string());
+        }
+        let num_centroids = self.centroids.len() / self.embedding_dim;
+        if codes.iter().any(|&code| code as usize >= num_centroids) {
+            return Err("Centroid code out of range".to_string());
+        }
+
+        self.codes.extend_from_slice(codes);
+        self.residuals.extend_from_slice(residuals);
+        self.doc_tokens.extend_from_slice(doc_tokens);
+        self.postings = OnceCell::new();
+        Ok(())
+    }
+
+    pub(crate) fn num_docs(&self) -> usize {
+        self.doc_tokens.len()
+    }
+
+    pub(crate) fn nbits(&self) -> usize {
+        self.nbits
+    }
+
+    pub(crate) fn centroids(&self) -> &[f32] {
+        &self.centroids
+    }
+
+    /// Documents per centroid (built now if this is the first call since a change)
+    pub(crate) fn postings(&self) -> &CentroidPostings {
+        self.postings.get_or_init(|| {
+            CentroidPostings::build(&self.codes, &self.doc_tokens, self.centroids.len() / self.embedding_dim)
+        })
+    }
+
+    /// Write the codec and every compressed token to a snapshot section
+    pub(crate) fn write_state(&self, out: &mut SectionWriter) {
+        out.u64(self.embedding_dim);
+        out.u64(self.nbits);
+        out.f32s(&self.centroids);
+        out.f32s(&self.bucket_weights);
+        out.u32s(&self.codes);
+        out.bytes(&self.residuals);
+        out.usizes(&self.doc_tokens);
+    }
+
+    /// Read a corpus written by `write_state()`, with the checks of `new()` and `append()`
+    pub(crate) fn read_state(input: &mut SectionReader) -> Result<Self, String> {
+        let (embedding_dim, nbits) = (input.u64()?, input.u64()?);
+        let (centroids, bucket_weights) = (input.f32s()?, input.f32s()?);
+        let mut docs = ResidualDocuments::new(&centroids, &bucket_weights, embedding_dim, nbits)?;
+        let (codes, residuals, doc_tokens) = (input.u32s()?, input.bytes()?, input.usizes()?);
+        docs.append(&codes, residuals, &doc_tokens)?;
+        Ok(docs)
+    }
+
+    /// Bytes held by the compressed corpus (codebook and postings, once built, included)
+    pub(crate) fn memory_bytes(&self) -> usize {
+        (self.centroids.len() + self.bucket_weights.len() + self.codes.len()) * std::mem::size_of::<f32>()
+            + self.residuals.len()
+            + self.postings.get().map_or(0, |postings| postings.memory_bytes())
+    }
+
+    fn packed_bytes_per_token(&self) -> usize {
+        self.embedding_dim * self.nbits / 8
+    }
+
+    // Decompress one token into `out` (embedding_dim floats, not yet normalized)
+    fn decompress_token(&self, token: usize, out: &mut [f32]) {
+        let dim = self.embedding_dim;
+        let code = self.codes[token] as usize;
+        let centroid = &self.centroids[code * dim..(code + 1) * dim];
+        let per_byte = 8 / self.nbits;
+        let start = token * self.packed_bytes_per_token();
+        let packed = &self.residuals[start..start + self.packed_bytes_per_token()];
+
+        for (d, (value, &center)) in out.iter_mut().zip(centroid).enumerate() {
+            let shift = 8 - self.nbits * (d % per_byte + 1);
+            let bucket = (packed[d / per_byte] >> shift).reverse_bits() >> (8 - self.nbits);
+            *value = center + self.bucket_weights[bucket as usize];
+        }
+    }
+
+    /// Decompress the corpus in document-aligned, page-sized batches
+    ///
+    /// `score` receives the flat (normalized) embeddings and token counts of each batch,
+    /// in original order; the scratch buffer is reused across batches.
+    pub(crate) fn for_each_batch(&self, score: impl FnMut(&[f32], &[usize])) {
+        let runs = self.doc_tokens.iter().scan(0, |first, &tokens| {
+            *first += tokens;
+            Some((*first - tokens, tokens))
+        });
+        self.decompress_batches(runs, score);
+    }
+
+    /// `for_each_batch()` over the documents `docs` only, in that order
+    pub(crate) fn for_each_batch_of(&self, docs: &[usize], score: impl FnMut(&[f32], &[usize])) {
+        let token_starts = self.postings().token_starts();
+        self.decompress_batches(docs.iter().map(|&doc| (token_starts[doc], self.doc_tokens[doc])), score);
+    }
+
+    // Decompress runs of (first token, tokens), one document each, in page-sized batches
+    fn decompress_batches(&self, runs: impl Iterator<Item = (usize, usize)>, mut score: impl FnMut(&[f32], &[usize])) {
+        let dim = self.embedding_dim;
+        let batch_floats = DEFAULT_PAGE_BYTES / std::mem::size_of::<f32>();
+        let (mut flat, mut batch_tokens): (Vec<f32>, Vec<usize>) = (Vec::new(), Vec::new());
+
+        for (first, tokens) in runs {
+            let offset = flat.len();
+            flat.resize(offset + tokens * dim, 0.0);
+            for (i, out) in flat[offset..].chunks_exact_mut(dim).enumerate() {
+                self.decompress_token(first + i, out);
+            }
+            batch_tokens.push(tokens);
+
+            if flat.len() >= batch_floats {
+                normalize_tokens(&mut flat, dim);
+                score(&flat, &batch_tokens);
+                flat.clear();
+                batch_tokens.clear();
+            }
+        }
+        if !batch_tokens.is_empty() {
+            normalize_tokens(&mut flat, dim);
+            score(&flat, &batch_tokens);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decompression_unpacks_colbert_bit_order() {
+        // dim 4 at 2 bits: one byte per token. colbert-ai's ResidualCodec.binarize()
+        // writes buckets (3, 0, 1, 2) as the bits 11 00 10 01 (each code LSB first),
+        // packed MSB first into 0b1100_1001
+        let centroids = [1.0, 0.0, 0.0, 0.0];
+        let weights = [-0.5, -0.25, 0.25, 0.5];
+        let mut docs = ResidualDocuments::new(&centroids, &weights, 4, 2).unwrap();
+        docs.append(&[0, 0], &[0b1100_1001, 0], &[1, 1]).unwrap();
+
+        let mut batches = Vec::new();
+        docs.for_each_batch(|flat, tokens| batches.push((flat.to_vec(), tokens.to_vec())));
+        assert_eq!(batches.len(), 1);
+
+        let (flat, tokens) = &batches[0];
+        assert_eq!(tokens, &vec![1, 1]);
+        // (1.5, -0.5, -0.25, 0.25) normalized: norm² = 2.25 + 0.25 + 0.0625 + 0.0625 = 2.625
+        let norm = 2.625f32.sqrt();
+        let expected = [1.5 / norm, -0.5 / norm, -0.25 / norm, 0.25 / norm];
+        for (a, b) in flat[..4].iter().zip(expected) {
+            assert!((a - b).abs() < 1e-6);
+        }
+
+        // At 4 bits, buckets (1, 12) are written 1000 0011
+        let mut wide = ResidualDocuments::new(&[0.0, 0.0], &(0..16).map(|b| b as f32).collect::<Vec<_>>(), 2, 4).unwrap();
+        wide.append(&[0], &[0b1000_0011], &[1]).unwrap();
+        let mut token = [0.0; 2];
+        wide.decompress_token(0, &mut token);
+        assert_eq!(token, [1.0, 12.0]);
+
+        assert!(docs.append(&[1], &[0], &[1]).is_err()); // Only centroid 0 exists
+        assert!(ResidualDocuments::new(&centroids, &weights, 4, 3).is_err());
+    }
+}
@@ -0,0 +1,511 @@
+/*!
+ * Search result containers
+ *
+ * APIs that emit only a subset of documents (score thresholds, top-k, reranking)
+ * return a `SearchHits`: parallel arrays of original document indices and scores,
+ * with slots for an external (e.g. cross-encoder) score blended in afterwards, for
+ * the reduction applied to an oversized query (see degradation.rs) and for the
+ * best-matching document tokens of every hit (snippet highlighting).
+ * APIs that emit several scores per document from one pass return `FusedScores`.
+ * Per-query-token maxima for visual analytics are exported as u8 `TokenMaxima`.
+ * `softmax()` (and `SearchHits.probabilities()`) turn scores into a probability
+ * distribution for downstream models.
+ *
+ * Every ranking uses `rank_cmp`: higher score first, equal scores by lower document
+ * index, NaN last. For a given corpus, layout and build the scores, and so the
+ * rankings, repeat exactly from run to run. They are not bit-identical across
+ * layouts: the packed kernel (`set_packed_layout()`) and the batch paths sum the dot products
+ * in different orders, so a score can move in its last bits and near-ties can swap.
+ */
+
+use std::cmp::Ordering;
+
+use js_sys::{Array, Object, Reflect};
+use wasm_bindgen::prelude::*;
+
+use crate::degradation::QueryDegradation;
+use crate::fusion::{min_max, unit};
+use crate::migration::ScoreMode;
+
+/// Ranking order of two (document index, score) pairs: higher score first, equal
+/// scores (0.0 and -0.0 included) by lower document index, NaN scores last
+pub(crate) fn rank_cmp(a: (usize, f32), b: (usize, f32)) -> Ordering {
+    match (a.1.is_nan(), b.1.is_nan()) {
+        (false, false) => b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal),
+        (a_nan, b_nan) => a_nan.cmp(&b_nan),
+    }
+    .then(a.0.cmp(&b.0))
+}
+
+/// Document indices with their scores (parallel arrays)
+#[wasm_bindgen]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SearchHits {
+    indices: Vec<u32>,
+    scores: Vec<f32>,
+    maxsim_scores: Option<Vec<f32>>, // MaxSim before blending (None: `scores` are MaxSim)
+    external_scores: Vec<f32>,       // Blended-in score per hit, NaN where none (empty: unblended)
+    degradation: Option<QueryDegradation>,
+    escalated: usize,                // Boundary documents rescored in f32 (quantized searches)
+    match_offsets: Vec<u32>,         // Start of each hit's matches, plus the total (empty: not requested)
+    matched_tokens: Vec<u32>,
+    match_counts: Vec<u32>,
+}
+
+#[wasm_bindgen]
+impl SearchHits {
+    /// Original document indices (Uint32Array)
+    #[wasm_bindgen(getter)]
+    pub fn indices(&self) -> Vec<u32> {
+        self.indices.clone()
+    }
+
+    /// Scores aligned with `indices` (Float32Array)
+    #[wasm_bindgen(getter)]
+    pub fn scores(&self) -> Vec<f32> {
+        self.scores.clone()
+    }
+
+    /// Number of hits
+    #[wasm_bindgen(getter)]
+    pub fn length(&self) -> usize {
+        self.indices.len()
+    }
+
+    /// MaxSim score of each hit, aligned with `indices` (equals `scores` until blended)
+    #[wasm_bindgen(getter)]
+    pub fn maxsim_scores(&self) -> Vec<f32> {
+        self.maxsim_scores.as_ref().unwrap_or(&self.scores).clone()
+    }
+
+    /// Hits as an Array of `{id, index, score, rank}` objects, in hit order
+    ///
+    /// `index` is the document index, `id` is `ids[index]` when `ids` is given (e.g.
+    /// the caller's document keys) and the index otherwise, and `rank` counts from 1.
+    #[wasm_bindgen]
+    pub fn to_objects(&self, ids: Option<Array>) -> Result<Array, JsValue> {
+        let keys = [JsValue::from_str("id"), JsValue::from_str("index"), JsValue::from_str("score"), JsValue::from_str("rank")];
+        let objects = Array::new_with_length(self.indices.len() as u32);
+        for (index, score, rank) in self.object_rows() {
+            let id = ids.as_ref().map_or_else(|| JsValue::from(index), |ids| ids.get(index));
+            let object = Object::new();
+            for (key, value) in keys.iter().zip([id, index.into(), score.into(), rank.into()]) {
+                Reflect::set(&object, key, &value)?;
+            }
+            objects.set(rank - 1, object.into());
+        }
+        Ok(objects)
+    }
+
+    /// External score of each hit, aligned with `indices` (NaN where none was given;
+    /// empty until blended)
+    #[wasm_bindgen(getter)]
+    pub fn external_scores(&self) -> Vec<f32> {
+        self.external_scores.clone()
+    }
+
+    /// How the query was reduced to the instance query limit (undefined when it wasn't)
+    #[wasm_bindgen(getter)]
+    pub fn degradation(&self) -> Option<QueryDegradation> {
+        self.degradation.clone()
+    }
+
+    /// Documents around the top-k boundary of a quantized search that were rescored
+    /// against their f32 embeddings (see `MaxSimWasm.set_boundary_escalation()`)
+    #[wasm_bindgen(getter)]
+    pub fn escalated(&self) -> usize {
+        self.escalated
+    }
+
+    /// Document token indices that were the best match of at least one query token,
+    /// hit by hit in ascending order (empty unless requested, see
+    /// `MaxSimWasm.set_match_spans()`)
+    #[wasm_bindgen(getter)]
+    pub fn matched_tokens(&self) -> Vec<u32> {
+        self.matched_tokens.clone()
+    }
+
+    /// Number of query tokens that matched each entry of `matched_tokens` best
+    #[wasm_bindgen(getter)]
+    pub fn match_counts(&self) -> Vec<u32> {
+        self.match_counts.clone()
+    }
+
+    /// Start of each hit's entries in `matched_tokens`, plus their total (hits + 1
+    /// entries; empty when matches weren't requested)
+    #[wasm_bindgen(getter)]
+    pub fn match_offsets(&self) -> Vec<u32> {
+        self.match_offsets.clone()
+    }
+
+    /// Softmax of `scores` over these hits at `temperature`, aligned with `indices`
+    /// (see `softmax()`)
+    #[wasm_bindgen]
+    pub fn probabilities(&self, temperature: f32) -> Result<Vec<f32>, JsValue> {
+        softmax_impl(&self.scores, temperature).map_err(|e| JsValue::from_str(&e))
+    }
+}
+
+/// Softmax of `scores / temperature`: probabilities summing to 1, in input order
+///
+/// Lower temperatures sharpen the distribution towards the best score, higher ones
+/// flatten it. Computed stably (the maximum is subtracted before exponentiation,
+/// sums accumulate in f64); non-finite scores get probability 0.
+#[wasm_bindgen]
+pub fn softmax(scores: &[f32], temperature: f32) -> Result<Vec<f32>, JsValue> {
+    softmax_impl(scores, temperature).map_err(|e| JsValue::from_str(&e))
+}
+
+pub(crate) fn softmax_impl(scores: &[f32], temperature: f32) -> Result<Vec<f32>, String> {
+    if !(temperature.is_finite() && temperature > 0.0) {
+        return Err(format!("temperature must be positive, got {}", temperature));
+    }
+    let max = scores.iter().copied().filter(|s| s.is_finite()).fold(f32::NEG_INFINITY, f32::max);
+    if max == f32::NEG_INFINITY {
+        return Ok(vec![0.0; scores.len()]);
+    }
+    let weights: Vec<f64> = scores
+        .iter()
+        .map(|&s| if s.is_finite() { ((s as f64 - max as f64) / temperature as f64).exp() } else { 0.0 })
+        .collect();
+    let total: f64 = weights.iter().sum();
+    Ok(weights.into_iter().map(|weight| (weight / total) as f32).collect())
+}
+
+impl SearchHits {
+    pub(crate) fn with_degradation(mut self, degradation: Option<QueryDegradation>) -> Self {
+        self.degradation = degradation;
+        self
+    }
+
+    pub(crate) fn with_escalated(mut self, escalated: usize) -> Self {
+        self.escalated = escalated;
+        self
+    }
+
+    /// Attach the matched tokens of every hit as (document token, query token count)
+    /// pairs, aligned with `indices`
+    pub(crate) fn with_matches(mut self, matches: Vec<Vec<(u32, u32)>>) -> Self {
+        self.match_offsets = vec![0];
+        for hit in matches {
+            for (token, count) in hit {
+                self.matched_tokens.push(token);
+                self.match_counts.push(count);
+            }
+            self.match_offsets.push(self.matched_tokens.len() as u32);
+        }
+        self
+    }
+
+    /// Keep documents scoring at or above `min_score`, in document order
+    pub(crate) fn above(scores: &[f32], min_score: f32) -> Self {
+        let (indices, scores) = scores
+            .iter()
+            .enumerate()
+            .filter(|&(_, &score)| score >= min_score)
+            .map(|(idx, &score)| (idx as u32, score))
+            .unzip();
+        SearchHits { indices, scores, ..Default::default() }
+    }
+
+    /// `(index, score, rank)` of every hit in hit order, ranks from 1 (the fields of
+    /// `to_objects()` besides the caller's id)
+    pub(crate) fn object_rows(&self) -> impl Iterator<Item = (u32, f32, u32)> + '_ {
+        self.indices.iter().zip(&self.scores).enumerate().map(|(rank, (&index, &score))| (index, score, rank as u32 + 1))
+    }
+
+    /// The `k` best-scoring documents, best first (see `rank_cmp`)
+    pub(crate) fn top_k(doc_indices: &[usize], scores: &[f32], k: usize) -> Self {
+        let mut ranked: Vec<(usize, f32)> = doc_indices.iter().copied().zip(scores.iter().copied()).collect();
+        ranked.sort_by(|&a, &b| rank_cmp(a, b));
+        ranked.truncate(k);
+
+        let (indices, scores) = ranked.into_iter().map(|(idx, score)| (idx as u32, score)).unzip();
+        SearchHits { indices, scores, ..Default::default() }
+    }
+
+    /// Hits `offset..offset + limit` of this ranking (limit 0 = to the end)
+    pub(crate) fn page(&self, offset: usize, limit: usize) -> Self {
+        let start = offset.min(self.indices.len());
+        let end = if limit == 0 { self.indices.len() } else { start.saturating_add(limit).min(self.indices.len()) };
+        let slice = |values: &[f32]| if values.is_empty() { Vec::new() } else { values[start..end].to_vec() };
+        SearchHits {
+            indices: self.indices[start..end].to_vec(),
+            scores: slice(&self.scores),
+            maxsim_scores: self.maxsim_scores.as_deref().map(slice),
+            external_scores: slice(&self.external_scores),
+            degradation: self.degradation.clone(),
+            ..Default::default()
+        }
+        .with_matches_of(self, start..end)
+    }
+
+    // Matches of the given hits of `ranking` (in that order), if it has any
+    fn with_matches_of(self, ranking: &SearchHits, hits: impl Iterator<Item = usize>) -> Self {
+        if ranking.match_offsets.is_empty() {
+            return self;
+        }
+        let matches = hits
+            .map(|hit| {
+                let range = ranking.match_offsets[hit] as usize..ranking.match_offsets[hit + 1] as usize;
+                ranking.matched_tokens[range.clone()].iter().copied().zip(ranking.match_counts[range].iter().copied()).collect()
+            })
+            .collect();
+        self.with_matches(matches)
+    }
+
+    /// The `k` best documents by fused score, keeping both inputs in the score slots
+    pub(crate) fn fused(maxsim: &[f32], external: &[f32], fused: &[f32], k: usize) -> Self {
+        let all: Vec<usize> = (0..fused.len()).collect();
+        let mut hits = SearchHits::top_k(&all, fused, k);
+        hits.maxsim_scores = Some(hits.indices.iter().map(|&doc| maxsim[doc as usize]).collect());
+        hits.external_scores = hits.indices.iter().map(|&doc| external[doc as usize]).collect();
+        hits
+    }
+
+    /// Blend external scores of a subset of the hits into a final ranking
+    ///
+    /// MaxSim and external scores live on different scales, so both are min-max
+    /// normalized over the externally scored hits before mixing:
+    /// `(1 - blend) · maxsim + blend · external`, in [0, 1]. Those hits come first,
+    /// best first; the remaining hits follow in MaxSim order with a score of -∞.
+    /// Blending again starts over from the original MaxSim scores.
+    pub(crate) fn blend_external(&self, indices: &[u32], scores: &[f32], blend: f32) -> Result<Self, String> {
+        if indices.len() != scores.len() {
+            return Err(format!("{} indices but {} external scores", indices.len(), scores.len()));
+        }
+        if !(0.0..=1.0).contains(&blend) {
+            return Err(format!("blend must be in [0, 1], got {}", blend));
+        }
+        if scores.iter().any(|s| !s.is_finite()) {
+            return Err("External scores must be finite".to_string());
+        }
+
+        let maxsim = self.maxsim_scores.as_ref().unwrap_or(&self.scores);
+        let mut external = vec![f32::NAN; self.indices.len()];
+        for (&doc, &score) in indices.iter().zip(scores) {
+            let pos = self.indices.iter().position(|&hit| hit == doc)
+                .ok_or_else(|| format!("Document {} is not among the hits", doc))?;
+            if !external[pos].is_nan() {
+                return Err(format!("Document {} has more than one external score", doc));
+            }
+            external[pos] = score;
+        }
+
+        let rescored: Vec<usize> = (0..external.len()).filter(|&pos| !external[pos].is_nan()).collect();
+        let maxsim_range = min_max(rescored.iter().map(|&pos| maxsim[pos]));
+        let external_range = min_max(rescored.iter().map(|&pos| external[pos]));
+        let blended: Vec<f32> = (0..external.len())
+            .map(|pos| {
+                if external[pos].is_nan() {
+                    f32::NEG_INFINITY
+                } else {
+                    (1.0 - blend) * unit(maxsim[pos], maxsim_range) + blend * unit(external[pos], external_range)
+                }
+            })
+            .collect();
+
+        // Ties (and the unscored tail) fall back to the MaxSim ranking
+        let mut order: Vec<usize> = (0..blended.len()).collect();
+        let doc = |pos: usize| self.indices[pos] as usize;
+        order.sort_by(|&a, &b| {
+            rank_cmp((0, blended[a]), (0, blended[b])).then_with(|| rank_cmp((doc(a), maxsim[a]), (doc(b), maxsim[b])))
+        });
+        Ok(SearchHits {
+            indices: order.iter().map(|&pos| self.indices[pos]).collect(),
+            scores: order.iter().map(|&pos| blended[pos]).collect(),
+            maxsim_scores: Some(order.iter().map(|&pos| maxsim[pos]).collect()),
+            external_scores: order.iter().map(|&pos| external[pos]).collect(),
+            degradation: self.degradation.clone(),
+            ..Default::default()
+        }
+        .with_matches_of(self, order.iter().copied()))
+    }
+}
+
+/// Raw and normalized MaxSim of every document, computed in a single pass
+#[wasm_bindgen]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FusedScores {
+    raw: Vec<f32>,
+    normalized: Vec<f32>,
+}
+
+#[wasm_bindgen]
+impl FusedScores {
+    /// Official MaxSim (raw sum), e.g. for ranking (Float32Array)
+    #[wasm_bindgen(getter)]
+    pub fn raw(&self) -> Vec<f32> {
+        self.raw.clone()
+    }
+
+    /// Normalized MaxSim (averaged over query tokens), e.g. for display (Float32Array)
+    #[wasm_bindgen(getter)]
+    pub fn normalized(&self) -> Vec<f32> {
+        self.normalized.clone()
+    }
+}
+
+impl FusedScores {
+    /// Derive both outputs from raw sums over `query_tokens` active query tokens
+    pub(crate) fn from_raw(raw: Vec<f32>, query_tokens: usize) -> Self {
+        let normalized = raw.iter().map(|&s| ScoreMode::Normalized.convert_raw(s, query_tokens)).collect();
+        FusedScores { raw, normalized }
+    }
+}
+
+/// Per-query-token maxima of many documents, u8-quantized with one shared affine scale
+///
+/// k-major layout: the value of query token `k` for document `d` is at
+/// `values[k * num_docs + d]` and decodes to `offset + value * scale`.
+#[wasm_bindgen]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TokenMaxima {
+    values: Vec<u8>,
+    query_tokens: usize,
+    num_docs: usize,
+    scale: f32,
+    offset: f32,
+}
+
+#[wasm_bindgen]
+impl TokenMaxima {
+    /// Quantized maxima, query token major (Uint8Array)
+    #[wasm_bindgen(getter)]
+    pub fn values(&self) -> Vec<u8> {
+        self.values.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn query_tokens(&self) -> usize {
+        self.query_tokens
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn num_docs(&self) -> usize {
+        self.num_docs
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn offset(&self) -> f32 {
+        self.offset
+    }
+
+    /// Decoded maximum of query token `query_token` for document `doc` (undefined when
+    /// either is out of range)
+    #[wasm_bindgen]
+    pub fn get(&self, query_token: usize, doc: usize) -> Option<f32> {
+        if query_token >= self.query_tokens || doc >= self.num_docs {
+            return None;
+        }
+        Some(self.offset + self.values[query_token * self.num_docs + doc] as f32 * self.scale)
+    }
+}
+
+impl TokenMaxima {
+    /// Quantize k-major maxima to the [min, max] range of their finite values
+    pub(crate) fn quantize(maxima: &[f32], query_tokens: usize, num_docs: usize) -> Self {
+        let (min, max) = maxima
+            .iter()
+            .filter(|v| v.is_finite())
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), &v| (lo.min(v), hi.max(v)));
+        // All finite values equal (or none): every finite value encodes as 0 at offset `min`
+        let (offset, scale) = match (min < max, min.is_finite()) {
+            (true, _) => (min, (max - min) / 255.0),
+            (false, true) => (min, 1.0),
+            (false, false) => (0.0, 1.0),
+        };
+
+        let values = maxima
+            .iter()
+            .map(|&v| ((v - offset) / scale).round().clamp(0.0, 255.0) as u8)
+            .collect();
+        TokenMaxima { values, query_tokens, num_docs, scale, offset }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_above_filters_skipped_documents() {
+        let hits = SearchHits::above(&[0.2, f32::NEG_INFINITY, 0.9, 0.5], 0.5);
+        assert_eq!(hits.indices(), vec![2, 3]);
+        assert_eq!(hits.scores(), vec![0.9, 0.5]);
+    }
+
+    #[test]
+    fn test_softmax_is_stable_and_sharpens_with_temperature() {
+        let probabilities = softmax_impl(&[1000.0, 1000.0, f32::NAN], 1.0).unwrap();
+        assert_eq!(probabilities, [0.5, 0.5, 0.0]);
+
+        let (sharp, flat) = (softmax_impl(&[2.0, 1.0], 0.1).unwrap(), softmax_impl(&[2.0, 1.0], 10.0).unwrap());
+        assert!(sharp[0] > 0.99 && flat[0] < 0.53 && flat[0] > 0.5);
+        assert!(((1.0 / (1.0 + (-1.0f64).exp())) as f32 - softmax_impl(&[2.0, 1.0], 1.0).unwrap()[0]).abs() < 1e-7);
+        assert!(softmax_impl(&[1.0], 0.0).is_err());
+    }
+
+    #[test]
+    fn test_top_k_sorts_descending_with_stable_ties() {
+        let hits = SearchHits::top_k(&[0, 1, 2, 3], &[0.5, 0.9, 0.5, 0.1], 3);
+        assert_eq!(hits.indices(), vec![1, 0, 2]);
+        assert_eq!(hits.scores(), vec![0.9, 0.5, 0.5]);
+
+        // Ties go to the lower index whatever the input order; -0.0 ties 0.0; NaN last
+        let hits = SearchHits::top_k(&[7, 5, 3, 1, 0], &[0.0, f32::NAN, -0.0, 0.0, -1.0], 5);
+        assert_eq!(hits.indices(), vec![1, 3, 7, 0, 5]);
+    }
+
+    #[test]
+    fn test_object_rows_follow_hit_order() {
+        let hits = SearchHits::top_k(&[4, 9, 2], &[0.5, 0.9, 0.7], 2);
+        let rows: Vec<_> = hits.object_rows().collect();
+        assert_eq!(rows, vec![(9, 0.9, 1), (2, 0.7, 2)]);
+        assert_eq!(SearchHits::top_k(&[], &[], 3).object_rows().count(), 0);
+    }
+
+    #[test]
+    fn test_blend_external_reranks_scored_hits_first() {
+        let hits = SearchHits::top_k(&[0, 1, 2, 3], &[0.9, 0.8, 0.7, 0.1], 4);
+        // The cross-encoder prefers document 2 and never saw document 3
+        let blended = hits.blend_external(&[0, 1, 2], &[1.0, 3.0, 5.0], 0.75).unwrap();
+        assert_eq!(blended.indices(), vec![2, 1, 0, 3]);
+        assert_eq!(blended.scores()[0], 0.75); // maxsim 0.0 · 0.25 + external 1.0 · 0.75
+        assert_eq!(blended.scores()[3], f32::NEG_INFINITY);
+        assert_eq!(blended.maxsim_scores(), vec![0.7, 0.8, 0.9, 0.1]);
+        assert!(blended.external_scores()[3].is_nan());
+
+        // Blending again starts from MaxSim; blend 0 restores the MaxSim order
+        let maxsim_only = blended.blend_external(&[0, 1, 2], &[1.0, 3.0, 5.0], 0.0).unwrap();
+        assert_eq!(maxsim_only.indices(), vec![0, 1, 2, 3]);
+
+        assert!(hits.blend_external(&[7], &[1.0], 0.5).is_err());
+        assert!(hits.blend_external(&[0], &[1.0], 1.5).is_err());
+    }
+
+    #[test]
+    fn test_token_maxima_round_trip_within_half_a_step() {
+        let maxima = [0.9, 0.1, -0.3, 0.5, 0.7, 0.2];
+        let quantized = TokenMaxima::quantize(&maxima, 2, 3);
+        assert_eq!(quantized.values().len(), 6);
+        assert_eq!(quantized.values()[2], 0);
+        assert_eq!(quantized.values()[0], 255);
+        for (i, &value) in maxima.iter().enumerate() {
+            assert!((quantized.get(i / 3, i % 3).unwrap() - value).abs() <= quantized.scale() / 2.0 + 1e-6);
+        }
+        assert_eq!(quantized.get(2, 0), None);
+        assert_eq!(quantized.get(0, 3), None);
+
+        // Constant maxima decode exactly, whatever their value
+        for constant in [5.3, 300.0, -2.0] {
+            let quantized = TokenMaxima::quantize(&[constant; 4], 2, 2);
+            assert_eq!(quantized.get(1, 1), Some(constant));
+        }
+    }
+}
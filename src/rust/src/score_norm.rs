@@ -0,0 +1,57 @@
+/*!
+ * Per-document length normalization of MaxSim scores
+ *
+ * Every query token keeps its best match among the document tokens, so a longer
+ * document has more chances at a high maximum and its raw sum grows with length
+ * even when it is no more relevant. A `ScoreNorm` divides each document's final
+ * score by a slowly growing function of its token count:
+ * - None: no length penalty (official MaxSim - the default)
+ * - Log:  score / ln(1 + doc_tokens)
+ * - Sqrt: score / √doc_tokens
+ *
+ * The penalty is an instance setting (`MaxSimConfig.score_norm`) and only scopes
+ * the configured searches: preloaded and collection searches and the
+ * `*_with_metric` and `*_default` batch calls. The official entry points (`maxsim_single`,
+ * `maxsim_batch`) always score without it. It applies after query-token averaging
+ * (`*_normalized`), so both score modes can be combined with it. Empty documents
+ * are left unchanged.
+ */
+
+use wasm_bindgen::prelude::*;
+
+/// Length penalty applied to each document's MaxSim score
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ScoreNorm {
+    #[default]
+    None = 0,
+    Log = 1,
+    Sqrt = 2,
+}
+
+impl ScoreNorm {
+    /// Divisor of the score of a document with `doc_tokens` tokens (always > 0)
+    #[inline]
+    pub(crate) fn divisor(self, doc_tokens: usize) -> f32 {
+        match self {
+            _ if doc_tokens == 0 => 1.0,
+            ScoreNorm::None => 1.0,
+            ScoreNorm::Log => (1.0 + doc_tokens as f32).ln(),
+            ScoreNorm::Sqrt => (doc_tokens as f32).sqrt(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_divisors_grow_with_length() {
+        assert_eq!(ScoreNorm::None.divisor(100), 1.0);
+        assert_eq!(ScoreNorm::Sqrt.divisor(16), 4.0);
+        assert!((ScoreNorm::Log.divisor(1) - std::f32::consts::LN_2).abs() < 1e-6);
+        assert!(ScoreNorm::Log.divisor(100) > ScoreNorm::Log.divisor(10));
+        assert_eq!(ScoreNorm::Sqrt.divisor(0), 1.0);
+    }
+}
@@ -0,0 +1,296 @@
+/*!
+ * Per-call scoring context threaded through the batch pipeline
+ *
+ * Every optimized path (uniform, variable-length sub-batches, individual documents)
+ * produces raw dot-product rows; the context turns each row into the per-query-token
+ * contribution and the final document score, so all paths share one reduction.
+ */
+
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use crate::aggregation::RowReduction;
+use crate::cancel::AbortFlag;
+use crate::metric::{token_norms_sq, Metric};
+use crate::score_norm::ScoreNorm;
+use crate::simd_clamp_unit;
+use crate::watchdog::Group;
+
+/// Caller-visible indices of the documents of one batch
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum DocIds<'a> {
+    From(usize),        // Contiguous run starting at this index (e.g. a page)
+    List(&'a [usize]),  // Arbitrary subset (e.g. ANN candidates)
+}
+
+pub(crate) struct ScoreContext<'a> {
+    pub(crate) normalized: bool,
+    pub(crate) metric: Metric,
+    query_norms: Vec<f32>,        // Squared norm per query token (empty for DotProduct)
+    doc_norms: Option<&'a [f32]>, // Squared norm per document token, aligned with doc_flat
+    embedding_dim: usize,
+    min_score: Option<f32>,       // Documents provably below this score skip aggregation
+    token_bound: Option<f32>,     // Upper bound of a single query token's contribution
+    reduction: RowReduction,      // Per-query-token aggregation (Max by default)
+    score_norm: ScoreNorm,        // Per-document length penalty (none by default)
+    abort: Option<AbortFlag>,     // Checked between sub-batches
+    clamp: bool,                  // Clamp similarities to [-1, 1] before aggregation
+    precise: bool,                // Accumulate in f64 (accuracy mode)
+    clamped: Rc<Cell<usize>>,     // Similarities changed by the clamp (shared across pages)
+    doc_ids: DocIds<'a>,          // Indices reported for batch-local documents (watchdog)
+    watched: Option<Rc<RefCell<Vec<Group>>>>, // Length groups for the watchdog (shared across pages)
+}
+
+impl<'a> ScoreContext<'a> {
+    pub(crate) fn new(
+        normalized: bool,
+        metric: Metric,
+        query_flat: &[f32],
+        doc_norms: Option<&'a [f32]>,
+        embedding_dim: usize,
+    ) -> Self {
+        let query_norms = if metric.needs_norms() {
+            token_norms_sq(query_flat, embedding_dim)
+        } else {
+            Vec::new()
+        };
+
+        ScoreContext {
+            normalized,
+            metric,
+            query_norms,
+            doc_norms,
+            embedding_dim,
+            min_score: None,
+            token_bound: None,
+            reduction: RowReduction::default(),
+            score_norm: ScoreNorm::None,
+            abort: None,
+            clamp: false,
+            precise: false,
+            clamped: Rc::new(Cell::new(0)),
+            doc_ids: DocIds::From(0),
+            watched: None,
+        }
+    }
+
+    /// Same scoring parameters over a different run of documents (e.g. another page)
+    pub(crate) fn with_doc_norms<'b>(&self, doc_norms: &'b [f32]) -> ScoreContext<'b> {
+        ScoreContext {
+            normalized: self.normalized,
+            metric: self.metric,
+            query_norms: self.query_norms.clone(),
+            doc_norms: Some(doc_norms),
+            embedding_dim: self.embedding_dim,
+            min_score: self.min_score,
+            token_bound: self.token_bound,
+            reduction: self.reduction,
+            score_norm: self.score_norm,
+            abort: self.abort.clone(),
+            clamp: self.clamp,
+            precise: self.precise,
+            clamped: Rc::clone(&self.clamped),
+            doc_ids: DocIds::From(0),
+            watched: self.watched.clone(),
+        }
+    }
+
+    /// Report batch-local document `i` as `ids[i]` (a run starting at 0 by default)
+    pub(crate) fn with_doc_ids(mut self, doc_ids: DocIds<'a>) -> Self {
+        self.doc_ids = doc_ids;
+        self
+    }
+
+    /// Caller-visible index of batch-local document `local`
+    pub(crate) fn doc_id(&self, local: usize) -> usize {
+        match self.doc_ids {
+            DocIds::From(first) => first + local,
+            DocIds::List(ids) => ids[local],
+        }
+    }
+
+    /// Collect the length groups of every batch scored with this context (or its
+    /// per-page copies), so the watchdog inspects the whole search at once
+    pub(crate) fn with_watchdog(mut self, watching: bool) -> Self {
+        self.watched = watching.then(|| Rc::new(RefCell::new(Vec::new())));
+        self
+    }
+
+    /// Record one length group when the watchdog is collecting (`group` only runs then)
+    pub(crate) fn watch_group(&self, group: impl FnOnce() -> Group) {
+        if let Some(watched) = &self.watched {
+            watched.borrow_mut().push(group());
+        }
+    }
+
+    /// Length groups collected so far (None when the watchdog isn't collecting)
+    pub(crate) fn take_watched(&self) -> Option<Vec<Group>> {
+        self.watched.as_ref().map(|watched| watched.take())
+    }
+
+    /// Aggregate each query token's similarities with `reduction` instead of Max
+    pub(crate) fn with_reduction(mut self, reduction: RowReduction) -> Self {
+        self.reduction = reduction;
+        self
+    }
+
+    /// Divide every document score by the `score_norm` length penalty
+    pub(crate) fn with_score_norm(mut self, score_norm: ScoreNorm) -> Self {
+        self.score_norm = score_norm;
+        self
+    }
+
+    /// Enable document-level early termination against `min_score`
+    ///
+    /// `unit_vectors` states that all embeddings are L2-normalized, which bounds every
+    /// dot-product contribution by 1. Without a bound (raw dot products of arbitrary
+    /// vectors) documents are still filtered at emission, just never skipped early.
+    pub(crate) fn with_min_score(mut self, min_score: Option<f32>, unit_vectors: bool) -> Self {
+        self.min_score = min_score;
+        self.token_bound = match self.metric {
+            Metric::DotProduct if unit_vectors => Some(1.0),
+            Metric::DotProduct => None,
+            Metric::Cosine => Some(1.0),
+            Metric::NegativeL2 => Some(0.0),
+        };
+        self
+    }
+
+    /// Stop scoring once `abort` is raised (remaining documents keep a score of 0)
+    pub(crate) fn with_abort(mut self, abort: Option<AbortFlag>) -> Self {
+        self.abort = abort;
+        self
+    }
+
+    /// Clamp similarities to [-1, 1] before aggregation (robustness mode)
+    ///
+    /// Only meaningful for DotProduct and Cosine; NegativeL2 similarities are never clamped.
+    pub(crate) fn with_clamp(mut self, clamp: bool) -> Self {
+        self.clamp = clamp && self.metric != Metric::NegativeL2;
+        self
+    }
+
+    /// Accumulate dot products and document sums in f64 (see `MaxSimConfig.precise_accumulation`)
+    pub(crate) fn with_precise(mut self, precise: bool) -> Self {
+        self.precise = precise;
+        self
+    }
+
+    pub(crate) fn precise(&self) -> bool {
+        self.precise
+    }
+
+    /// Number of similarities clamped so far, `None` when clamping is off
+    pub(crate) fn clamped_count(&self) -> Option<usize> {
+        self.clamp.then(|| self.clamped.get())
+    }
+
+    /// Whether the caller cancelled this search
+    #[inline]
+    pub(crate) fn is_aborted(&self) -> bool {
+        self.abort.as_ref().is_some_and(|flag| flag.aborted())
+    }
+
+    /// Squared norms of the document tokens starting at float offset `doc_offset`
+    #[inline]
+    pub(crate) fn doc_norms(&self, doc_offset: usize, doc_tokens: usize) -> &[f32] {
+        match self.doc_norms {
+            Some(norms) if self.metric.needs_norms() => {
+                let start = doc_offset / self.embedding_dim;
+                &norms[start..start + doc_tokens]
+            }
+            _ => &[],
+        }
+    }
+
+    /// Aggregated similarity of query token `q_idx` over one document's raw dot-product row
+    #[inline]
+    pub(crate) fn row_score(&self, row: &mut [f32], q_idx: usize, doc_norms: &[f32]) -> f32 {
+        if self.metric.needs_norms() {
+            self.metric.apply(row, self.query_norms[q_idx], doc_norms);
+        }
+        if self.clamp {
+            self.clamped.set(self.clamped.get() + simd_clamp_unit(row));
+        }
+        self.reduction.reduce(row)
+    }
+
+    /// Document token most similar to query token `q_idx` under the metric (the first
+    /// on ties), from one raw dot-product row
+    pub(crate) fn row_argmax(&self, row: &mut [f32], q_idx: usize, doc_norms: &[f32]) -> usize {
+        if self.metric.needs_norms() {
+            self.metric.apply(row, self.query_norms[q_idx], doc_norms);
+        }
+        (0..row.len()).reduce(|best, j| if row[j] > row[best] { j } else { best }).unwrap_or(0)
+    }
+
+    /// Reduce one document's raw similarity rows to its score
+    ///
+    /// `row_start(q_idx)` is the offset of query token `q_idx`'s row in `similarities`.
+    /// Returns `f32::NEG_INFINITY` when the document cannot reach the minimum score,
+    /// in which case the remaining query tokens are never reduced.
+    #[inline]
+    pub(crate) fn score_document(
+        &self,
+        similarities: &mut [f32],
+        row_start: impl Fn(usize) -> usize,
+        doc_tokens: usize,
+        query_tokens: usize,
+        doc_norms: &[f32],
+    ) -> f32 {
+        if self.precise {
+            let sum: f64 = (0..query_tokens)
+                .map(|q_idx| {
+                    let start = row_start(q_idx);
+                    self.row_score(&mut similarities[start..start + doc_tokens], q_idx, doc_norms) as f64
+                })
+                .sum();
+            return self.finish(sum as f32, query_tokens, doc_tokens);
+        }
+
+        let mut sum_max_sim = 0.0;
+        for q_idx in 0..query_tokens {
+            if self.cannot_reach_min(sum_max_sim, query_tokens - q_idx, query_tokens, doc_tokens) {
+                return f32::NEG_INFINITY;
+            }
+            let start = row_start(q_idx);
+            sum_max_sim += self.row_score(&mut similarities[start..start + doc_tokens], q_idx, doc_norms);
+        }
+        self.finish(sum_max_sim, query_tokens, doc_tokens)
+    }
+
+    /// Upper bound of a document score from upper bounds of each query token's raw
+    /// dot products with it (DotProduct only: every row reduction is at most the row
+    /// maximum, and the clamp and length penalty are monotonic)
+    pub(crate) fn score_bound(&self, token_bounds: impl Iterator<Item = f32>, query_tokens: usize, doc_tokens: usize) -> f32 {
+        let sum = token_bounds.map(|bound| if self.clamp { bound.clamp(-1.0, 1.0) } else { bound }).sum();
+        self.finish(sum, query_tokens, doc_tokens)
+    }
+
+    #[inline]
+    fn cannot_reach_min(&self, partial_sum: f32, remaining_tokens: usize, query_tokens: usize, doc_tokens: usize) -> bool {
+        match (self.min_score, self.token_bound) {
+            (Some(min_score), Some(bound)) => {
+                self.finish(partial_sum + remaining_tokens as f32 * bound, query_tokens, doc_tokens) < min_score
+            }
+            _ => false,
+        }
+    }
+
+    /// Final document score from the summed per-query-token maxima
+    ///
+    /// The length penalty divides by a positive factor, so the early-termination
+    /// bound above stays exact.
+    #[inline]
+    pub(crate) fn finish(&self, sum_max_sim: f32, query_tokens: usize, doc_tokens: usize) -> f32 {
+        let score = if self.normalized {
+            sum_max_sim / query_tokens as f32
+        } else {
+            sum_max_sim
+        };
+        match self.score_norm {
+            ScoreNorm::None => score,
+            norm => score / norm.divisor(doc_tokens),
+        }
+    }
+}
@@ -0,0 +1,162 @@
+/*!
+ * Pool of reentrant scratch buffers
+ *
+ * Scoring reuses large f32 buffers (similarity matrices, padded document batches,
+ * prepared per-call documents) instead of allocating per call. Each operation
+ * checks one out of a `ScratchPool` with `lend(floats)` and the returned guard
+ * checks it back in when dropped. The pool is only borrowed for the checkout and
+ * the check-in themselves, so a search started while another is still running on
+ * the same instance (e.g. from a callback invoked mid-search) simply gets another
+ * buffer instead of panicking on a double borrow.
+ *
+ * Idle buffers are keyed by size class (the power of two of floats they hold, at
+ * least `MIN_CLASS`); a checkout takes the smallest idle buffer large enough, or
+ * allocates one of the request's class. Memory is bounded on check-in: at most
+ * `MAX_IDLE_PER_CLASS` buffers are kept per class, and while the idle buffers
+ * exceed the pool's limit the largest is released, so a one-off huge call no
+ * longer pins its buffers for the lifetime of the instance. `trim()` releases idle
+ * buffers down to a smaller budget on demand.
+ */
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::ops::{Deref, DerefMut};
+
+/// Smallest size class in floats (16 KB)
+const MIN_CLASS: usize = 4096;
+
+/// Idle buffers kept per size class (one per nesting level of concurrent searches)
+const MAX_IDLE_PER_CLASS: usize = 2;
+
+/// Size-classed f32 buffers lent to one operation at a time
+pub(crate) struct ScratchPool {
+    idle: RefCell<BTreeMap<usize, Vec<Vec<f32>>>>, // Size class → idle buffers of that class
+    limit: usize,                                   // Idle floats kept on check-in
+}
+
+impl ScratchPool {
+    /// Pool holding one idle buffer per entry of `reserved` (in floats), keeping at most
+    /// `limit` idle floats
+    pub(crate) fn new(reserved: &[usize], limit: usize) -> Self {
+        let mut idle: BTreeMap<usize, Vec<Vec<f32>>> = BTreeMap::new();
+        for &floats in reserved.iter().filter(|&&floats| floats > 0) {
+            idle.entry(size_class(floats)).or_default().push(Vec::with_capacity(size_class(floats)));
+        }
+        ScratchPool { idle: RefCell::new(idle), limit }
+    }
+
+    /// Check out an empty buffer with capacity for at least `floats` floats
+    pub(crate) fn lend(&self, floats: usize) -> Lent<'_> {
+        let class = size_class(floats);
+        let mut idle = self.idle.borrow_mut();
+        let buffer = idle
+            .range_mut(class..)
+            .find_map(|(_, buffers)| buffers.pop())
+            .map(|mut buffer| {
+                buffer.clear();
+                buffer
+            })
+            .unwrap_or_else(|| Vec::with_capacity(class));
+        Lent { pool: self, buffer }
+    }
+
+    /// Floats held by the idle buffers
+    pub(crate) fn idle_floats(&self) -> usize {
+        self.idle.borrow().values().flatten().map(Vec::capacity).sum()
+    }
+
+    /// Release the largest idle buffers until at most `floats` floats stay idle
+    pub(crate) fn trim(&self, floats: usize) {
+        let mut idle = self.idle.borrow_mut();
+        let mut total: usize = idle.values().flatten().map(Vec::capacity).sum();
+        while total > floats {
+            let Some(mut largest) = idle.last_entry() else {
+                break;
+            };
+            let released = largest.get_mut().pop().map_or(0, |buffer| buffer.capacity());
+            if largest.get().is_empty() {
+                largest.remove();
+            }
+            total -= released;
+        }
+    }
+
+    fn check_in(&self, buffer: Vec<f32>) {
+        if buffer.capacity() < MIN_CLASS {
+            return;
+        }
+        {
+            // Buffers that grew while lent are filed under the class they now fill
+            let class = 1 << (usize::BITS - 1 - buffer.capacity().leading_zeros());
+            let mut idle = self.idle.borrow_mut();
+            let buffers = idle.entry(class).or_default();
+            if buffers.len() >= MAX_IDLE_PER_CLASS {
+                return;
+            }
+            buffers.push(buffer);
+        }
+        self.trim(self.limit);
+    }
+}
+
+// Power of two of floats holding `floats`, at least MIN_CLASS
+fn size_class(floats: usize) -> usize {
+    floats.max(MIN_CLASS).next_power_of_two()
+}
+
+/// A buffer checked out of a `ScratchPool`, checked back in on drop
+pub(crate) struct Lent<'a> {
+    pool: &'a ScratchPool,
+    buffer: Vec<f32>,
+}
+
+impl Deref for Lent<'_> {
+    type Target = Vec<f32>;
+
+    fn deref(&self) -> &Vec<f32> {
+        &self.buffer
+    }
+}
+
+impl DerefMut for Lent<'_> {
+    fn deref_mut(&mut self) -> &mut Vec<f32> {
+        &mut self.buffer
+    }
+}
+
+impl Drop for Lent<'_> {
+    fn drop(&mut self) {
+        self.pool.check_in(std::mem::take(&mut self.buffer));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lending_reuses_size_classes_within_the_limit() {
+        let pool = ScratchPool::new(&[10_000], 1 << 20);
+        assert_eq!(pool.idle_floats(), 16_384);
+        {
+            let mut outer = pool.lend(5_000);
+            assert_eq!((outer.capacity(), pool.idle_floats()), (16_384, 0));
+            outer.resize(4, 1.0);
+            {
+                // A nested operation gets its own buffer, of its own class
+                let inner = pool.lend(100);
+                assert_eq!(inner.capacity(), MIN_CLASS);
+            }
+            assert_eq!(pool.idle_floats(), MIN_CLASS);
+            assert_eq!(outer[..], [1.0; 4]);
+        }
+        assert_eq!(pool.idle_floats(), 16_384 + MIN_CLASS);
+        assert_eq!(pool.lend(9_000).capacity(), 16_384);
+
+        // Beyond the limit, the largest idle buffers are released on check-in
+        drop(pool.lend(1 << 21));
+        assert_eq!(pool.idle_floats(), 16_384 + MIN_CLASS);
+        pool.trim(MIN_CLASS);
+        assert_eq!(pool.idle_floats(), MIN_CLASS);
+    }
+}
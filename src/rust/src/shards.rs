@@ -0,0 +1,183 @@
+/*!
+ * Sharded corpora with a memory-budgeted working set
+ *
+ * A corpus too large for WASM linear memory is stored externally (IndexedDB, see
+ * idb.rs) as one standalone serialized index per page ("shard", formats/index.rs)
+ * plus a small manifest with each shard's document count. During search, shards are
+ * loaded on demand and kept in an LRU working set bounded by a byte budget, so only
+ * a few pages are ever resident at once.
+ *
+ * Manifest layout (little-endian):
+ *
+ * ```text
+ * magic          4 bytes   "MXSM"
+ * version        u32       MANIFEST_VERSION
+ * embedding_dim  u32
+ * num_shards     u32
+ * shard_docs     u32 × num_shards
+ * ```
+ */
+
+use std::rc::Rc;
+
+use crate::formats::index::IndexError;
+use crate::store::PreloadedDocuments;
+
+const MANIFEST_MAGIC: &[u8; 4] = b"MXSM";
+pub(crate) const MANIFEST_VERSION: u32 = 1;
+
+/// Default byte budget of resident shards (4 pages of the default page size)
+pub(crate) const DEFAULT_BUDGET_BYTES: usize = 4 * crate::store::DEFAULT_PAGE_BYTES;
+
+/// Shape of a sharded corpus: embedding dimension and document count per shard
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct ShardManifest {
+    pub(crate) embedding_dim: usize,
+    pub(crate) shard_docs: Vec<usize>,
+}
+
+impl ShardManifest {
+    pub(crate) fn num_docs(&self) -> usize {
+        self.shard_docs.iter().sum()
+    }
+
+    /// Global index of the first document of every shard
+    pub(crate) fn shard_offsets(&self) -> Vec<usize> {
+        self.shard_docs
+            .iter()
+            .scan(0, |offset, &docs| {
+                let start = *offset;
+                *offset += docs;
+                Some(start)
+            })
+            .collect()
+    }
+
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(16 + self.shard_docs.len() * 4);
+        out.extend_from_slice(MANIFEST_MAGIC);
+        out.extend_from_slice(&MANIFEST_VERSION.to_le_bytes());
+        out.extend_from_slice(&(self.embedding_dim as u32).to_le_bytes());
+        out.extend_from_slice(&(self.shard_docs.len() as u32).to_le_bytes());
+        for &docs in &self.shard_docs {
+            out.extend_from_slice(&(docs as u32).to_le_bytes());
+        }
+        out
+    }
+
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Result<Self, IndexError> {
+        if bytes.len() < 4 || &bytes[0..4] != MANIFEST_MAGIC {
+            return Err(IndexError::NotAnIndex);
+        }
+        if bytes.len() < 16 {
+            return Err(IndexError::CorruptHeader("manifest too short for header".to_string()));
+        }
+        let read_u32 = |offset: usize| u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+
+        let version = read_u32(4);
+        if version != MANIFEST_VERSION {
+            return Err(IndexError::UnsupportedVersion { found: version });
+        }
+
+        let num_shards = read_u32(12) as usize;
+        if bytes.len() != 16 + num_shards * 4 {
+            return Err(IndexError::CorruptHeader("manifest size does not match its shard count".to_string()));
+        }
+
+        Ok(ShardManifest {
+            embedding_dim: read_u32(8) as usize,
+            shard_docs: (0..num_shards).map(|i| read_u32(16 + i * 4) as usize).collect(),
+        })
+    }
+}
+
+/// LRU working set of deserialized shards bounded by a byte budget
+pub(crate) struct ShardCache {
+    budget_bytes: usize,
+    resident: Vec<(usize, Rc<PreloadedDocuments>)>, // Least recently used first
+}
+
+impl ShardCache {
+    pub(crate) fn new(budget_bytes: usize) -> Self {
+        ShardCache { budget_bytes, resident: Vec::new() }
+    }
+
+    pub(crate) fn set_budget(&mut self, budget_bytes: usize) {
+        self.budget_bytes = budget_bytes;
+        self.evict(None);
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.resident.clear();
+    }
+
+    /// Resident shard, marked as most recently used
+    pub(crate) fn get(&mut self, shard: usize) -> Option<Rc<PreloadedDocuments>> {
+        let pos = self.resident.iter().position(|(idx, _)| *idx == shard)?;
+        let entry = self.resident.remove(pos);
+        let docs = Rc::clone(&entry.1);
+        self.resident.push(entry);
+        Some(docs)
+    }
+
+    /// Make a freshly loaded shard resident, evicting least recently used shards
+    /// until the working set fits the budget (the new shard itself always stays)
+    pub(crate) fn insert(&mut self, shard: usize, docs: PreloadedDocuments) -> Rc<PreloadedDocuments> {
+        let docs = Rc::new(docs);
+        self.resident.retain(|(idx, _)| *idx != shard);
+        self.resident.push((shard, Rc::clone(&docs)));
+        self.evict(Some(shard));
+        docs
+    }
+
+    pub(crate) fn resident_bytes(&self) -> usize {
+        self.resident.iter().map(|(_, docs)| docs.memory_bytes()).sum()
+    }
+
+    pub(crate) fn resident_shards(&self) -> usize {
+        self.resident.len()
+    }
+
+    fn evict(&mut self, keep: Option<usize>) {
+        while self.resident_bytes() > self.budget_bytes {
+            match self.resident.iter().position(|(idx, _)| Some(*idx) != keep) {
+                Some(pos) => {
+                    self.resident.remove(pos);
+                }
+                None => break,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manifest_round_trip() {
+        let manifest = ShardManifest { embedding_dim: 128, shard_docs: vec![3, 1, 2] };
+        assert_eq!(manifest.num_docs(), 6);
+        assert_eq!(manifest.shard_offsets(), vec![0, 3, 4]);
+        assert_eq!(ShardManifest::from_bytes(&manifest.to_bytes()).unwrap(), manifest);
+        assert!(matches!(ShardManifest::from_bytes(&manifest.to_bytes()[..12]), Err(IndexError::CorruptHeader(_))));
+        assert_eq!(ShardManifest::from_bytes(b"MXSI"), Err(IndexError::NotAnIndex));
+    }
+
+    #[test]
+    fn test_cache_evicts_least_recently_used() {
+        let shard = || PreloadedDocuments::from_flat(&[1.0, 0.0], &[1], 2); // 12 bytes resident
+        let mut cache = ShardCache::new(24);
+        cache.insert(0, shard());
+        cache.insert(1, shard());
+        assert!(cache.get(0).is_some()); // 1 is now least recently used
+        cache.insert(2, shard());
+
+        assert_eq!(cache.resident_shards(), 2);
+        assert!(cache.get(1).is_none());
+        assert!(cache.get(0).is_some() && cache.get(2).is_some());
+
+        cache.set_budget(0);
+        assert_eq!(cache.resident_shards(), 0);
+    }
+}
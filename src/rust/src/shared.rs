@@ -0,0 +1,96 @@
+/*!
+ * Document store over a SharedArrayBuffer
+ *
+ * Every Web Worker runs its own WASM instance with its own linear memory, so
+ * loading the corpus in N workers normally holds N copies of the embeddings. With
+ * `attach_shared_documents()` the embeddings stay in one `SharedArrayBuffer`
+ * (created and filled once, then posted to every worker) and each instance only
+ * keeps the token counts. Searches copy one page-sized, document-aligned batch at
+ * a time into a scratch buffer and score it there, so per-worker memory is one
+ * page (8 MB) regardless of corpus size, at the cost of streaming the corpus
+ * through that page on every search.
+ *
+ * `SharedArrayBuffer` is only available to cross-origin isolated pages: serve the
+ * page with
+ *
+ * ```text
+ * Cross-Origin-Opener-Policy: same-origin
+ * Cross-Origin-Embedder-Policy: require-corp
+ * ```
+ *
+ * (check `self.crossOriginIsolated` in JS). Writers must finish filling the buffer
+ * before workers search it; searches read it without synchronization.
+ */
+
+use std::ops::Range;
+
+use crate::normalize_tokens;
+use crate::store::DEFAULT_PAGE_BYTES;
+
+/// Corpus whose embeddings live in a SharedArrayBuffer, in original document order
+pub(crate) struct SharedDocuments {
+    pub(crate) embedding_dim: usize,
+    view: js_sys::Float32Array, // The whole SharedArrayBuffer as f32
+    doc_tokens: Vec<usize>,
+}
+
+impl SharedDocuments {
+    pub(crate) fn new(view: js_sys::Float32Array, doc_tokens: &[usize], embedding_dim: usize) -> Self {
+        SharedDocuments { embedding_dim, view, doc_tokens: doc_tokens.to_vec() }
+    }
+
+    pub(crate) fn num_docs(&self) -> usize {
+        self.doc_tokens.len()
+    }
+
+    /// Copy the corpus out in document-aligned, page-sized batches
+    ///
+    /// `score` receives the flat embeddings (L2-normalized when `normalize` is set)
+    /// and token counts of each batch, in original order; the scratch buffer is reused
+    /// across batches.
+    pub(crate) fn for_each_batch(&self, normalize: bool, mut score: impl FnMut(&[f32], &[usize])) {
+        let dim = self.embedding_dim;
+        let mut flat: Vec<f32> = Vec::new();
+        let mut token = 0;
+
+        for docs in batch_ranges(&self.doc_tokens, dim, DEFAULT_PAGE_BYTES / std::mem::size_of::<f32>()) {
+            let doc_tokens = &self.doc_tokens[docs];
+            let tokens: usize = doc_tokens.iter().sum();
+            flat.resize(tokens * dim, 0.0);
+            self.view.subarray((token * dim) as u32, ((token + tokens) * dim) as u32).copy_to(&mut flat);
+            if normalize {
+                normalize_tokens(&mut flat, dim);
+            }
+            score(&flat, doc_tokens);
+            token += tokens;
+        }
+    }
+}
+
+// Consecutive document ranges of about `batch_floats` floats each (a larger
+// document gets a batch of its own)
+fn batch_ranges(doc_tokens: &[usize], embedding_dim: usize, batch_floats: usize) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    let (mut start, mut floats) = (0, 0);
+    for (doc, &tokens) in doc_tokens.iter().enumerate() {
+        floats += tokens * embedding_dim;
+        if floats >= batch_floats || doc + 1 == doc_tokens.len() {
+            ranges.push(start..doc + 1);
+            start = doc + 1;
+            floats = 0;
+        }
+    }
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_batches_are_document_aligned() {
+        assert_eq!(batch_ranges(&[2, 1, 3, 1], 2, 6), vec![0..2, 2..3, 3..4]);
+        assert_eq!(batch_ranges(&[1, 1], 2, 100), vec![0..2]);
+        assert!(batch_ranges(&[], 2, 6).is_empty());
+    }
+}
@@ -0,0 +1,275 @@
+/*!
+ * Engine state snapshots
+ *
+ * `MaxSimWasm.export_state()` writes everything a respawned worker (or reloaded
+ * page) needs to serve searches again into one buffer: the preloaded documents
+ * (in the serialized index format, f32), their tag bitmasks, groups, chunking and
+ * index fingerprint (so deltas still apply after a restore), the calibrated blocking
+ * parameters and the residual, PQ and 4-bit corpora with their codebooks.
+ * `import_state()` restores them in one call, with no re-download or re-training.
+ * Settings (config, stopmask, search options) belong to the app and are not part of
+ * the snapshot; named collections and in-progress loads aren't either.
+ *
+ * Layout: the magic `MXSS`, a u32 version, then sections of `[tag: 4 bytes]
+ * [length: u64][payload]`, all little-endian. Each part present on the instance
+ * gets one section; readers skip sections with unknown tags, so newer snapshots
+ * stay readable as long as the version is unchanged.
+ *
+ * A snapshot that can't be read fails with an `IndexError` like an index blob:
+ * `NotAnIndex` (bad magic), `UnsupportedVersion` or `CorruptHeader` (truncated or
+ * inconsistent sections, including a damaged document index).
+ */
+
+use crate::formats::index::IndexError;
+use crate::formats::{read_u32, read_u64};
+use crate::int4::Int4Documents;
+use crate::pq::PqDocuments;
+use crate::residual::ResidualDocuments;
+use crate::store::PreloadedDocuments;
+use crate::tuning::Tuning;
+
+const MAGIC: &[u8; 4] = b"MXSS";
+pub(crate) const VERSION: u32 = 1;
+
+// Section tags
+pub(crate) const DOCUMENTS: [u8; 4] = *b"DOCS";
+pub(crate) const TAGS: [u8; 4] = *b"TAGS";
+pub(crate) const TUNING: [u8; 4] = *b"TUNE";
+pub(crate) const RESIDUAL: [u8; 4] = *b"RESI";
+pub(crate) const PQ: [u8; 4] = *b"PQ08";
+pub(crate) const INT4: [u8; 4] = *b"INT4";
+pub(crate) const FINGERPRINT: [u8; 4] = *b"FING";
+pub(crate) const GROUPS: [u8; 4] = *b"GRPS";
+pub(crate) const CHUNKS: [u8; 4] = *b"CHNK";
+
+/// Snapshot being written, one section at a time
+pub(crate) struct StateWriter {
+    out: Vec<u8>,
+}
+
+impl StateWriter {
+    pub(crate) fn new() -> Self {
+        let mut out = MAGIC.to_vec();
+        out.extend_from_slice(&VERSION.to_le_bytes());
+        StateWriter { out }
+    }
+
+    /// Append a section whose payload is written by `write`
+    pub(crate) fn section(&mut self, tag: [u8; 4], write: impl FnOnce(&mut SectionWriter)) {
+        let mut payload = SectionWriter { out: Vec::new() };
+        write(&mut payload);
+        self.out.extend_from_slice(&tag);
+        self.out.extend_from_slice(&(payload.out.len() as u64).to_le_bytes());
+        self.out.extend_from_slice(&payload.out);
+    }
+
+    pub(crate) fn finish(self) -> Vec<u8> {
+        self.out
+    }
+}
+
+/// Payload of one section (also used on its own for other blobs with this encoding)
+#[derive(Default)]
+pub(crate) struct SectionWriter {
+    out: Vec<u8>,
+}
+
+impl SectionWriter {
+    pub(crate) fn into_bytes(self) -> Vec<u8> {
+        self.out
+    }
+
+    pub(crate) fn u64(&mut self, value: usize) {
+        self.out.extend_from_slice(&(value as u64).to_le_bytes());
+    }
+
+    /// Length-prefixed raw bytes
+    pub(crate) fn bytes(&mut self, bytes: &[u8]) {
+        self.u64(bytes.len());
+        self.out.extend_from_slice(bytes);
+    }
+
+    pub(crate) fn f32s(&mut self, values: &[f32]) {
+        self.u64(values.len());
+        self.out.extend(values.iter().flat_map(|value| value.to_le_bytes()));
+    }
+
+    pub(crate) fn u32s(&mut self, values: &[u32]) {
+        self.u64(values.len());
+        self.out.extend(values.iter().flat_map(|value| value.to_le_bytes()));
+    }
+
+    pub(crate) fn usizes(&mut self, values: &[usize]) {
+        self.u64(values.len());
+        self.out.extend(values.iter().flat_map(|&value| (value as u64).to_le_bytes()));
+    }
+}
+
+/// Everything `import_state()` restores, parsed and checked against itself
+#[derive(Default)]
+pub(crate) struct EngineState {
+    pub(crate) documents: Option<PreloadedDocuments>,
+    pub(crate) doc_tags: Vec<u32>,
+    pub(crate) doc_groups: Vec<u32>,
+    pub(crate) doc_chunks: Vec<usize>,
+    pub(crate) fingerprint: Option<u32>,
+    pub(crate) tuning: Tuning,
+    pub(crate) residual: Option<ResidualDocuments>,
+    pub(crate) pq: Option<PqDocuments>,
+    pub(crate) int4: Option<Int4Documents>,
+}
+
+impl EngineState {
+    pub(crate) fn read(bytes: &[u8]) -> Result<Self, IndexError> {
+        let mut state = EngineState::default();
+        for (tag, mut input) in sections(bytes)? {
+            let section = |e: String| IndexError::CorruptHeader(format!("section {}: {}", String::from_utf8_lossy(&tag), e));
+            match tag {
+                DOCUMENTS => {
+                    let index = input.bytes().map_err(section)?;
+                    state.documents = Some(crate::formats::index::deserialize(index).map_err(|e| section(e.to_string()))?);
+                }
+                TAGS => state.doc_tags = input.u32s().map_err(section)?,
+                GROUPS => state.doc_groups = input.u32s().map_err(section)?,
+                CHUNKS => state.doc_chunks = input.usizes().map_err(section)?,
+                FINGERPRINT => state.fingerprint = Some(input.u64().map_err(section)? as u32),
+                TUNING => state.tuning = Tuning::read_state(&mut input).map_err(section)?,
+                RESIDUAL => state.residual = Some(ResidualDocuments::read_state(&mut input).map_err(section)?),
+                PQ => state.pq = Some(PqDocuments::read_state(&mut input).map_err(section)?),
+                INT4 => state.int4 = Some(Int4Documents::read_state(&mut input).map_err(section)?),
+                _ => {} // Written by a newer version
+            }
+        }
+
+        let corrupt = |reason: &str| Err(IndexError::CorruptHeader(reason.to_string()));
+        let num_docs = state.documents.as_ref().map_or(0, |docs| docs.num_docs());
+        if !state.doc_tags.is_empty() && state.doc_tags.len() != num_docs {
+            return corrupt("document tags length mismatch");
+        }
+        if !state.doc_groups.is_empty() && state.doc_groups.len() != num_docs {
+            return corrupt("document groups length mismatch");
+        }
+        let chunked = state.doc_chunks.iter().try_fold(0usize, |sum, &chunks| sum.checked_add(chunks));
+        if !state.doc_chunks.is_empty() && chunked != Some(num_docs) {
+            return corrupt("document chunk counts mismatch");
+        }
+        if state.documents.is_none() {
+            state.fingerprint = None;
+        }
+        Ok(state)
+    }
+}
+
+/// Sections of a parsed snapshot, by tag (payloads borrowed from the input)
+pub(crate) fn sections(bytes: &[u8]) -> Result<Vec<([u8; 4], SectionReader<'_>)>, IndexError> {
+    if bytes.len() < 4 || &bytes[..4] != MAGIC {
+        return Err(IndexError::NotAnIndex);
+    }
+    if bytes.len() < 8 {
+        return Err(IndexError::CorruptHeader("snapshot too short for header".to_string()));
+    }
+    let version = read_u32(bytes, 4);
+    if version != VERSION {
+        return Err(IndexError::UnsupportedVersion { found: version });
+    }
+
+    let mut sections = Vec::new();
+    let mut offset = 8;
+    while offset < bytes.len() {
+        if bytes.len() - offset < 12 {
+            return Err(IndexError::CorruptHeader("truncated section header".to_string()));
+        }
+        let tag: [u8; 4] = bytes[offset..offset + 4].try_into().unwrap();
+        let len = read_u64(bytes, offset + 4);
+        let start = offset + 12;
+        if len > (bytes.len() - start) as u64 {
+            return Err(IndexError::CorruptHeader(format!("truncated section {}", String::from_utf8_lossy(&tag))));
+        }
+        let end = start + len as usize;
+        sections.push((tag, SectionReader::new(&bytes[start..end])));
+        offset = end;
+    }
+    Ok(sections)
+}
+
+/// Cursor over the payload of one section (every read is bounds-checked)
+pub(crate) struct SectionReader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> SectionReader<'a> {
+    pub(crate) fn new(bytes: &'a [u8]) -> Self {
+        SectionReader { bytes, offset: 0 }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        if len > self.bytes.len() - self.offset {
+            return Err("Truncated snapshot section".to_string());
+        }
+        let taken = &self.bytes[self.offset..self.offset + len];
+        self.offset += len;
+        Ok(taken)
+    }
+
+    pub(crate) fn u64(&mut self) -> Result<usize, String> {
+        let value = read_u64(self.take(8)?, 0);
+        usize::try_from(value).map_err(|_| "Snapshot value out of range".to_string())
+    }
+
+    // Byte length of `count` elements of `size` bytes, checked against the payload
+    fn array_bytes(&mut self, size: usize) -> Result<&'a [u8], String> {
+        let count = self.u64()?;
+        let len = count.checked_mul(size).ok_or_else(|| "Truncated snapshot section".to_string())?;
+        self.take(len)
+    }
+
+    pub(crate) fn bytes(&mut self) -> Result<&'a [u8], String> {
+        self.array_bytes(1)
+    }
+
+    pub(crate) fn f32s(&mut self) -> Result<Vec<f32>, String> {
+        Ok(self.array_bytes(4)?.chunks_exact(4).map(|b| f32::from_le_bytes(b.try_into().unwrap())).collect())
+    }
+
+    pub(crate) fn u32s(&mut self) -> Result<Vec<u32>, String> {
+        Ok(self.array_bytes(4)?.chunks_exact(4).map(|b| u32::from_le_bytes(b.try_into().unwrap())).collect())
+    }
+
+    pub(crate) fn usizes(&mut self) -> Result<Vec<usize>, String> {
+        self.array_bytes(8)?
+            .chunks_exact(8)
+            .map(|b| usize::try_from(u64::from_le_bytes(b.try_into().unwrap())).map_err(|_| "Snapshot value out of range".to_string()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sections_round_trip_and_reject_truncation() {
+        let mut writer = StateWriter::new();
+        writer.section(TAGS, |w| w.u32s(&[1, 2, 3]));
+        writer.section(*b"NEW!", |w| w.bytes(b"from a newer writer"));
+        writer.section(TUNING, |w| {
+            w.usizes(&[16, 8]);
+            w.f32s(&[0.5]);
+        });
+        let bytes = writer.finish();
+
+        let mut sections = sections(&bytes).unwrap();
+        assert_eq!(sections.iter().map(|(tag, _)| *tag).collect::<Vec<_>>(), [TAGS, *b"NEW!", TUNING]);
+        assert_eq!(sections[0].1.u32s().unwrap(), [1, 2, 3]);
+        let tuning = &mut sections[2].1;
+        assert_eq!((tuning.usizes().unwrap(), tuning.f32s().unwrap()), (vec![16, 8], vec![0.5]));
+        assert!(tuning.u64().is_err());
+
+        let error = |bytes: &[u8]| super::sections(bytes).err().map(|e| e.code());
+        assert_eq!(error(&bytes[..bytes.len() - 1]), Some("CorruptHeader"));
+        assert_eq!(error(b"MXSS\x02\0\0\0"), Some("UnsupportedVersion"));
+        assert_eq!(error(b"MXSI\x01\0\0\0"), Some("NotAnIndex"));
+        assert_eq!(error(b"MXSS\x01\0"), Some("CorruptHeader"));
+    }
+}
@@ -0,0 +1,155 @@
+/*!
+ * Index statistics and memory accounting
+ *
+ * `MaxSimWasm.index_stats()` reports the shape of the preloaded corpus and the heap
+ * bytes held by each part of the instance, so an app can enforce a memory budget
+ * (e.g. on mobile browsers) before loading more documents. Byte counts cover the
+ * float data only (small per-page bookkeeping is not included); buffer bytes are
+ * the capacity of the idle scratch buffers pooled for reuse (see scratch.rs).
+ */
+
+use wasm_bindgen::prelude::*;
+
+use crate::store::PreloadedDocuments;
+
+/// Snapshot of the corpus shape and memory use of one instance
+#[wasm_bindgen]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct IndexStats {
+    num_docs: usize,
+    total_tokens: usize,
+    min_doc_tokens: usize,
+    max_doc_tokens: usize,
+    embedding_dim: usize,
+    pub(crate) embedding_bytes: usize,
+    pub(crate) buffer_bytes: usize,
+    pub(crate) preview_bytes: usize,
+    pub(crate) pooled_bytes: usize,
+    pub(crate) hnsw_bytes: usize,
+    pub(crate) collection_bytes: usize,
+    pub(crate) residual_bytes: usize,
+    pub(crate) pq_bytes: usize,
+    pub(crate) int4_bytes: usize,
+    pub(crate) quantization: String,
+}
+
+impl IndexStats {
+    /// Corpus shape and storage of the preloaded documents (all zero when none)
+    pub(crate) fn of_store(docs: Option<&PreloadedDocuments>) -> Self {
+        let Some(docs) = docs else {
+            return IndexStats::default();
+        };
+        let doc_tokens = docs.doc_tokens();
+        IndexStats {
+            num_docs: doc_tokens.len(),
+            total_tokens: doc_tokens.iter().sum(),
+            min_doc_tokens: doc_tokens.iter().copied().min().unwrap_or(0),
+            max_doc_tokens: docs.max_doc_tokens(),
+            embedding_dim: docs.embedding_dim,
+            embedding_bytes: docs.memory_bytes(),
+            ..IndexStats::default()
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl IndexStats {
+    #[wasm_bindgen(getter)]
+    pub fn num_docs(&self) -> usize {
+        self.num_docs
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn total_tokens(&self) -> usize {
+        self.total_tokens
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn min_doc_tokens(&self) -> usize {
+        self.min_doc_tokens
+    }
+
+    /// Mean document length in tokens (0 when no documents are loaded)
+    #[wasm_bindgen(getter)]
+    pub fn avg_doc_tokens(&self) -> f32 {
+        if self.num_docs == 0 { 0.0 } else { self.total_tokens as f32 / self.num_docs as f32 }
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn max_doc_tokens(&self) -> usize {
+        self.max_doc_tokens
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn embedding_dim(&self) -> usize {
+        self.embedding_dim
+    }
+
+    /// Preloaded embeddings, their token norms and packed copies
+    #[wasm_bindgen(getter)]
+    pub fn embedding_bytes(&self) -> usize {
+        self.embedding_bytes
+    }
+
+    /// Capacity of the idle pooled scoring buffers
+    #[wasm_bindgen(getter)]
+    pub fn buffer_bytes(&self) -> usize {
+        self.buffer_bytes
+    }
+
+    /// Preview corpus (see `set_preview_tokens`)
+    #[wasm_bindgen(getter)]
+    pub fn preview_bytes(&self) -> usize {
+        self.preview_bytes
+    }
+
+    /// Pooled single-vector index (see `set_pooling`)
+    #[wasm_bindgen(getter)]
+    pub fn pooled_bytes(&self) -> usize {
+        self.pooled_bytes
+    }
+
+    /// Links of the HNSW graph over the pooled vectors (see `set_hnsw`)
+    #[wasm_bindgen(getter)]
+    pub fn hnsw_bytes(&self) -> usize {
+        self.hnsw_bytes
+    }
+
+    /// Documents of all named collections
+    #[wasm_bindgen(getter)]
+    pub fn collection_bytes(&self) -> usize {
+        self.collection_bytes
+    }
+
+    /// Residual-compressed corpus, codebook included
+    #[wasm_bindgen(getter)]
+    pub fn residual_bytes(&self) -> usize {
+        self.residual_bytes
+    }
+
+    /// Product-quantized corpus, codebooks included
+    #[wasm_bindgen(getter)]
+    pub fn pq_bytes(&self) -> usize {
+        self.pq_bytes
+    }
+
+    /// 4-bit corpus, scales and token norms included
+    #[wasm_bindgen(getter)]
+    pub fn int4_bytes(&self) -> usize {
+        self.int4_bytes
+    }
+
+    /// Storage of the loaded corpora: "f32", "residual-<n>bit", "pq-<m>x8", "int4-g<n>", the loaded
+    /// ones joined by "+", or "none"
+    #[wasm_bindgen(getter)]
+    pub fn quantization(&self) -> String {
+        self.quantization.clone()
+    }
+
+    /// Sum of all byte counts above
+    #[wasm_bindgen(getter)]
+    pub fn total_bytes(&self) -> usize {
+        self.embedding_bytes + self.buffer_bytes + self.preview_bytes + self.pooled_bytes + self.hnsw_bytes + self.collection_bytes
+            + self.residual_bytes + self.pq_bytes + self.int4_bytes
+    }
+}
@@ -0,0 +1,391 @@
+/*!
+ * Paged document storage for preloaded corpora
+ *
+ * Documents are stored in fixed-size pages (8 MB by default) instead of one giant
+ * `Vec<f32>`. A single contiguous allocation of a few hundred MB can fail in WASM
+ * even when enough total memory is available; pages never need more than one
+ * page-sized block, and appending documents only ever touches the last page, so
+ * growth never copies the whole store.
+ *
+ * A document never straddles two pages, so every page is a self-contained flat
+ * batch that the adaptive batch path can score directly. Documents larger than a
+ * page get a dedicated page of their own size.
+ *
+ * Pages can additionally keep a block-transposed copy of their documents (see
+ * packed.rs) for the packed scoring kernel, at the cost of a second copy of the
+ * embeddings.
+ */
+
+use crate::metric::token_norms_sq;
+use crate::normalize_tokens;
+use crate::packed::pack_tokens;
+
+/// Default page size in bytes
+pub(crate) const DEFAULT_PAGE_BYTES: usize = 8 * 1024 * 1024;
+
+/// One page of whole documents, stored flat and contiguous
+#[derive(Clone)]
+pub(crate) struct DocPage {
+    pub(crate) embeddings: Vec<f32>,  // Flat embeddings of the documents in this page
+    pub(crate) token_norms: Vec<f32>, // Squared norm of every token in this page
+    pub(crate) doc_tokens: Vec<usize>, // Token count of each document in this page
+    pub(crate) first_doc: usize,       // Global index of the first document in this page
+    // Page-local document indices sorted by token count, built by background maintenance
+    pub(crate) length_order: Option<Vec<usize>>,
+    // The page's documents in the packed layout, back to back (see `set_packed`)
+    pub(crate) packed: Option<Vec<f32>>,
+}
+
+impl DocPage {
+    fn new(first_doc: usize, capacity_floats: usize) -> Self {
+        DocPage {
+            embeddings: Vec::with_capacity(capacity_floats),
+            token_norms: Vec::new(),
+            doc_tokens: Vec::new(),
+            first_doc,
+            length_order: None,
+            packed: None,
+        }
+    }
+
+    /// Global document indices covered by this page
+    pub(crate) fn doc_range(&self) -> std::ops::Range<usize> {
+        self.first_doc..self.first_doc + self.doc_tokens.len()
+    }
+}
+
+/// Preloaded documents stored in pages, in original order
+#[derive(Clone)]
+pub(crate) struct PreloadedDocuments {
+    pages: Vec<DocPage>,
+    doc_tokens: Vec<usize>,             // Token count for each document (original order)
+    doc_locations: Vec<(usize, usize)>, // (page index, float offset within the page) per document
+    pub(crate) embedding_dim: usize,
+    page_floats: usize,
+    packed: bool, // Whether every page keeps a packed copy, including pages added later
+}
+
+impl PreloadedDocuments {
+    pub(crate) fn new(embedding_dim: usize) -> Self {
+        Self::with_page_floats(embedding_dim, DEFAULT_PAGE_BYTES / std::mem::size_of::<f32>())
+    }
+
+    pub(crate) fn with_page_floats(embedding_dim: usize, page_floats: usize) -> Self {
+        PreloadedDocuments {
+            pages: Vec::new(),
+            doc_tokens: Vec::new(),
+            doc_locations: Vec::new(),
+            embedding_dim,
+            page_floats: page_floats.max(embedding_dim),
+            packed: false,
+        }
+    }
+
+    /// Build a store from a flat array of concatenated documents (copied page by page)
+    pub(crate) fn from_flat(embeddings: &[f32], doc_tokens: &[usize], embedding_dim: usize) -> Self {
+        let mut store = Self::new(embedding_dim);
+        let mut offset = 0;
+        for &tokens in doc_tokens {
+            let len = tokens * embedding_dim;
+            store.push_document(&embeddings[offset..offset + len]);
+            offset += len;
+        }
+        store.finish();
+        store
+    }
+
+    /// Build a store from an owned flat array of concatenated documents
+    ///
+    /// Pages are cut from the back of the array, which is truncated and shrunk after
+    /// each one, so peak memory stays about one page above the array instead of
+    /// doubling it. The layout is the same as `from_flat`.
+    pub(crate) fn from_vec(embeddings: Vec<f32>, doc_tokens: &[usize], embedding_dim: usize) -> Self {
+        let mut store = Self::new(embedding_dim);
+        store.fill_from_vec(embeddings, doc_tokens);
+        store.finish();
+        store
+    }
+
+    fn fill_from_vec(&mut self, mut embeddings: Vec<f32>, doc_tokens: &[usize]) {
+        let dim = self.embedding_dim;
+        // Lay out pages front to back exactly like push_document: (first doc, first float)
+        let mut page_starts: Vec<(usize, usize)> = Vec::new();
+        let (mut offset, mut page_start) = (0, 0);
+        for (doc_idx, &tokens) in doc_tokens.iter().enumerate() {
+            let doc_floats = tokens * dim;
+            if page_starts.is_empty() || offset + doc_floats - page_start > self.page_floats {
+                page_starts.push((doc_idx, offset));
+                page_start = offset;
+            }
+            self.doc_locations.push((page_starts.len() - 1, offset - page_start));
+            offset += doc_floats;
+        }
+
+        let mut end_doc = doc_tokens.len();
+        for &(first_doc, start) in page_starts.iter().rev() {
+            let page_embeddings = embeddings[start..].to_vec();
+            embeddings.truncate(start);
+            embeddings.shrink_to_fit();
+            self.pages.push(DocPage {
+                token_norms: token_norms_sq(&page_embeddings, dim),
+                embeddings: page_embeddings,
+                doc_tokens: doc_tokens[first_doc..end_doc].to_vec(),
+                first_doc,
+                length_order: None,
+                packed: None,
+            });
+            end_doc = first_doc;
+        }
+        self.pages.reverse();
+        self.doc_tokens.extend_from_slice(doc_tokens);
+    }
+
+    /// Append one document (`tokens × embedding_dim` floats)
+    pub(crate) fn push_document(&mut self, embeddings: &[f32]) {
+        let dim = self.embedding_dim;
+        let doc_floats = embeddings.len();
+        let doc_idx = self.doc_tokens.len();
+
+        let fits = self
+            .pages
+            .last()
+            .is_some_and(|page| page.embeddings.len() + doc_floats <= self.page_floats);
+        if !fits {
+            // Oversized documents get a dedicated page of exactly their size
+            let mut page = DocPage::new(doc_idx, self.page_floats.max(doc_floats));
+            page.packed = self.packed.then(Vec::new);
+            self.pages.push(page);
+        }
+
+        let page_idx = self.pages.len() - 1;
+        let page = &mut self.pages[page_idx];
+        let offset = page.embeddings.len();
+        page.embeddings.extend_from_slice(embeddings);
+        page.token_norms.extend(token_norms_sq(embeddings, dim));
+        page.doc_tokens.push(doc_floats / dim);
+        page.length_order = None;
+        if let Some(packed) = &mut page.packed {
+            pack_tokens(embeddings, dim, packed);
+        }
+
+        self.doc_tokens.push(doc_floats / dim);
+        self.doc_locations.push((page_idx, offset));
+    }
+
+    /// Release the unused tail capacity of the last page (copies at most one page)
+    pub(crate) fn finish(&mut self) {
+        if let Some(page) = self.pages.last_mut() {
+            page.embeddings.shrink_to_fit();
+        }
+    }
+
+    pub(crate) fn num_docs(&self) -> usize {
+        self.doc_tokens.len()
+    }
+
+    /// Token count of every document (original order)
+    pub(crate) fn doc_tokens(&self) -> &[usize] {
+        &self.doc_tokens
+    }
+
+    /// Token count of the longest document (0 when empty)
+    pub(crate) fn max_doc_tokens(&self) -> usize {
+        self.doc_tokens.iter().copied().max().unwrap_or(0)
+    }
+
+    pub(crate) fn pages(&self) -> &[DocPage] {
+        &self.pages
+    }
+
+    /// Embeddings and squared token norms of one document (original index)
+    pub(crate) fn document(&self, doc_idx: usize) -> (&[f32], &[f32]) {
+        let (page_idx, offset) = self.doc_locations[doc_idx];
+        let page = &self.pages[page_idx];
+        let tokens = self.doc_tokens[doc_idx];
+        let token_offset = offset / self.embedding_dim;
+        (
+            &page.embeddings[offset..offset + tokens * self.embedding_dim],
+            &page.token_norms[token_offset..token_offset + tokens],
+        )
+    }
+
+    /// Resident heap size of the stored embeddings and token norms
+    pub(crate) fn memory_bytes(&self) -> usize {
+        self.pages
+            .iter()
+            .map(|p| {
+                let packed = p.packed.as_ref().map_or(0, Vec::len);
+                (p.embeddings.len() + p.token_norms.len() + packed) * std::mem::size_of::<f32>()
+            })
+            .sum()
+    }
+
+    /// Number of pending background maintenance steps (one per page without a length order)
+    pub(crate) fn pending_maintenance(&self) -> usize {
+        self.pages.iter().filter(|p| p.length_order.is_none()).count()
+    }
+
+    /// Perform one bounded maintenance step; returns false when nothing is left to do
+    ///
+    /// A step sorts one page's documents by token count, so preloaded searches over
+    /// that page skip their per-search sort.
+    pub(crate) fn maintenance_step(&mut self) -> bool {
+        let Some(page) = self.pages.iter_mut().find(|p| p.length_order.is_none()) else {
+            return false;
+        };
+        let mut order: Vec<usize> = (0..page.doc_tokens.len()).collect();
+        order.sort_by_key(|&i| page.doc_tokens[i]);
+        page.length_order = Some(order);
+        true
+    }
+
+    /// Copy of the store keeping only the first `max_tokens` tokens of every document
+    pub(crate) fn truncated(&self, max_tokens: usize) -> Self {
+        let mut store = Self::with_page_floats(self.embedding_dim, self.page_floats);
+        store.set_packed(self.packed);
+        for doc_idx in 0..self.num_docs() {
+            let embeddings = self.document(doc_idx).0;
+            store.push_document(&embeddings[..embeddings.len().min(max_tokens * self.embedding_dim)]);
+        }
+        store.finish();
+        store
+    }
+
+    /// Copy of the store without the documents in `removed` (ascending), followed by
+    /// the documents of `appended`
+    pub(crate) fn spliced(&self, removed: &[usize], appended: &PreloadedDocuments) -> Self {
+        let mut store = Self::with_page_floats(self.embedding_dim, self.page_floats);
+        store.set_packed(self.packed);
+        let mut removed = removed.iter().peekable();
+        for doc_idx in 0..self.num_docs() {
+            if removed.next_if_eq(&&doc_idx).is_none() {
+                store.push_document(self.document(doc_idx).0);
+            }
+        }
+        for doc_idx in 0..appended.num_docs() {
+            store.push_document(appended.document(doc_idx).0);
+        }
+        store.finish();
+        store
+    }
+
+    /// L2-normalize every stored token in place and refresh the token norms (and the
+    /// packed copies)
+    pub(crate) fn normalize(&mut self) {
+        for page in &mut self.pages {
+            normalize_tokens(&mut page.embeddings, self.embedding_dim);
+            page.token_norms = token_norms_sq(&page.embeddings, self.embedding_dim);
+        }
+        if self.is_packed() {
+            self.set_packed(true);
+        }
+    }
+
+    /// Build (or drop) the packed copy of every page; documents appended later are
+    /// packed as they are pushed
+    pub(crate) fn set_packed(&mut self, enabled: bool) {
+        let dim = self.embedding_dim;
+        self.packed = enabled;
+        for page in &mut self.pages {
+            page.packed = enabled.then(|| {
+                let mut packed = Vec::new();
+                let mut offset = 0;
+                for &tokens in &page.doc_tokens {
+                    pack_tokens(&page.embeddings[offset..offset + tokens * dim], dim, &mut packed);
+                    offset += tokens * dim;
+                }
+                packed
+            });
+        }
+    }
+
+    pub(crate) fn is_packed(&self) -> bool {
+        self.packed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_documents_never_straddle_pages() {
+        // 2-dim tokens, 6 floats per page: docs of 2, 1, 2 and 4 tokens
+        let mut store = PreloadedDocuments::with_page_floats(2, 6);
+        let docs: [&[f32]; 4] = [&[1.0; 4], &[2.0; 2], &[3.0; 4], &[4.0; 8]];
+        for doc in docs {
+            store.push_document(doc);
+        }
+
+        let page_ranges: Vec<_> = store.pages().iter().map(|p| p.doc_range()).collect();
+        assert_eq!(page_ranges, vec![0..2, 2..3, 3..4]);
+        assert_eq!(store.pages()[0].embeddings, vec![1.0, 1.0, 1.0, 1.0, 2.0, 2.0]);
+        assert_eq!(store.pages()[2].embeddings.len(), 8); // oversized dedicated page
+        assert_eq!(store.doc_locations, vec![(0, 0), (0, 4), (1, 0), (2, 0)]);
+        assert_eq!(store.document(1).0, &[2.0, 2.0]);
+        assert_eq!(store.document(1).1, &[8.0]);
+    }
+
+    #[test]
+    fn test_owned_array_has_the_same_layout() {
+        let doc_tokens = [2, 1, 2, 4, 1];
+        let embeddings: Vec<f32> = (0..10 * 2).map(|v| v as f32).collect();
+        let mut copied = PreloadedDocuments::with_page_floats(2, 6);
+        let mut offset = 0;
+        for &tokens in &doc_tokens {
+            copied.push_document(&embeddings[offset..offset + tokens * 2]);
+            offset += tokens * 2;
+        }
+        let mut owned = PreloadedDocuments::with_page_floats(2, 6);
+        owned.fill_from_vec(embeddings, &doc_tokens);
+
+        assert_eq!(owned.doc_locations, copied.doc_locations);
+        for (a, b) in owned.pages().iter().zip(copied.pages()) {
+            assert_eq!((&a.embeddings, &a.token_norms, &a.doc_tokens), (&b.embeddings, &b.token_norms, &b.doc_tokens));
+            assert_eq!(a.first_doc, b.first_doc);
+        }
+        assert_eq!(owned.pages().len(), copied.pages().len());
+        assert_eq!(owned.document(4).0, &[18.0, 19.0]);
+    }
+
+    #[test]
+    fn test_maintenance_sorts_one_page_per_step() {
+        let mut store = PreloadedDocuments::with_page_floats(1, 4);
+        for doc in [&[1.0, 1.0, 1.0][..], &[2.0], &[3.0, 3.0, 3.0, 3.0]] {
+            store.push_document(doc);
+        }
+        assert_eq!(store.pending_maintenance(), 2);
+
+        assert!(store.maintenance_step());
+        assert_eq!(store.pages()[0].length_order, Some(vec![1, 0]));
+        assert!(store.maintenance_step());
+        assert!(!store.maintenance_step());
+        assert_eq!(store.pending_maintenance(), 0);
+
+        // Appending to a page invalidates its order
+        store.push_document(&[4.0]);
+        assert_eq!(store.pending_maintenance(), 1);
+    }
+
+    #[test]
+    fn test_pushed_documents_are_packed() {
+        let mut store = PreloadedDocuments::with_page_floats(2, 6);
+        store.push_document(&[1.0; 4]);
+        store.set_packed(true);
+        // Joins the first page, then opens a second one
+        store.push_document(&[2.0, 3.0]);
+        store.push_document(&[4.0, 5.0, 6.0, 7.0]);
+        assert!(store.is_packed());
+
+        let mut repacked = store.clone();
+        repacked.set_packed(true);
+        assert_eq!(store.pages().len(), 2);
+        for (page, expected) in store.pages().iter().zip(repacked.pages()) {
+            assert_eq!(page.packed, expected.packed);
+        }
+
+        store.set_packed(false);
+        store.push_document(&[8.0, 9.0]);
+        assert!(!store.is_packed() && store.pages().iter().all(|page| page.packed.is_none()));
+    }
+}
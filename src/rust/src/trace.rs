@@ -0,0 +1,75 @@
+/*!
+ * Score traces for regression debugging
+ *
+ * When tracing is enabled, each search records a compact, line-oriented trace of
+ * the internal path it took: which batching strategy ran (uniform / variable /
+ * individual), the length-group boundaries, and which dot-product kernel variant
+ * was dispatched. Traces are plain text with one `key=value` event per line so
+ * two versions of the crate can be compared with an ordinary text diff.
+ *
+ * Recording is a no-op (no formatting, no allocation) while tracing is disabled.
+ */
+
+/// Trace format version, bumped whenever event names or fields change
+pub(crate) const TRACE_FORMAT_VERSION: u32 = 1;
+
+#[derive(Default)]
+pub(crate) struct SearchTrace {
+    enabled: bool,
+    events: Vec<String>,
+}
+
+impl SearchTrace {
+    pub(crate) fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.events.clear();
+        }
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Start a new trace, discarding the previous search's events
+    pub(crate) fn begin(&mut self, header: impl FnOnce() -> String) {
+        if self.enabled {
+            self.events.clear();
+            self.events.push(format!("trace v{} {}", TRACE_FORMAT_VERSION, header()));
+        }
+    }
+
+    /// Append an event; the closure only runs while tracing is enabled
+    pub(crate) fn record(&mut self, event: impl FnOnce() -> String) {
+        if self.enabled {
+            self.events.push(event());
+        }
+    }
+
+    pub(crate) fn render(&self) -> String {
+        self.events.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disabled_trace_records_nothing() {
+        let mut trace = SearchTrace::default();
+        trace.begin(|| "docs=1".to_string());
+        trace.record(|| panic!("must not format while disabled"));
+        assert_eq!(trace.render(), "");
+    }
+
+    #[test]
+    fn test_begin_resets_previous_events() {
+        let mut trace = SearchTrace::default();
+        trace.set_enabled(true);
+        trace.begin(|| "docs=1".to_string());
+        trace.record(|| "path=individual".to_string());
+        trace.begin(|| "docs=2".to_string());
+        assert_eq!(trace.render(), "trace v1 docs=2");
+    }
+}
@@ -0,0 +1,147 @@
+/*!
+ * Cache blocking parameters and their calibration
+ *
+ * `matrix_multiply` walks document tokens in blocks whose best size depends on the
+ * document length and the device's caches, and the padded batch path scores
+ * documents in sub-batches sized for L2. The defaults were tuned on a desktop
+ * browser; `MaxSimWasm.calibrate()` re-measures both on the actual device with a
+ * short synthetic microbenchmark (~0.2 s) and keeps the fastest values on the
+ * instance. Tuning only changes speed, never a score.
+ */
+
+use maxsim_core::batch::DEFAULT_SUB_BATCH_SIZE;
+use maxsim_core::kernels::{length_class, DEFAULT_D_BLOCKS, LENGTH_CLASSES};
+
+use crate::clock;
+use crate::snapshot::{SectionReader, SectionWriter};
+use crate::MaxSimWasm;
+
+/// Representative document length benchmarked for each class
+const CLASS_SAMPLE_LENGTHS: [usize; 5] = [96, 192, 384, 768, 1536];
+const BLOCK_CANDIDATES: [usize; 5] = [4, 6, 8, 12, 16];
+const SUB_BATCH_CANDIDATES: [usize; 4] = [8, 16, 32, 64];
+
+// Benchmark shape: a typical ColBERT query against 128-dim tokens
+const BENCH_DIM: usize = 128;
+const BENCH_QUERY_TOKENS: usize = 32;
+// Each candidate runs for at least this long (the browser clock has ms resolution)
+const MIN_RUN_MS: f64 = 4.0;
+
+/// Blocking parameters of one instance
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Tuning {
+    d_blocks: [usize; 5], // Document-token block size per length class
+    pub(crate) sub_batch_size: usize,
+}
+
+impl Default for Tuning {
+    fn default() -> Self {
+        // Desktop measurements: 16 docs per sub-batch, 165 ms vs 198 ms for 32 (L2 thrashing)
+        Tuning { d_blocks: DEFAULT_D_BLOCKS, sub_batch_size: DEFAULT_SUB_BATCH_SIZE }
+    }
+}
+
+impl Tuning {
+    /// Document-token block size of `matrix_multiply` for documents of `doc_tokens` tokens
+    #[inline]
+    pub(crate) fn d_block(&self, doc_tokens: usize) -> usize {
+        self.d_blocks[length_class(doc_tokens)]
+    }
+
+    pub(crate) fn write_state(&self, out: &mut SectionWriter) {
+        out.usizes(&self.d_blocks);
+        out.u64(self.sub_batch_size);
+    }
+
+    pub(crate) fn read_state(input: &mut SectionReader) -> Result<Self, String> {
+        let d_blocks: [usize; 5] = input.usizes()?.try_into().map_err(|_| "Expected 5 block sizes".to_string())?;
+        let sub_batch_size = input.u64()?;
+        if d_blocks.contains(&0) || sub_batch_size == 0 {
+            return Err("Block and sub-batch sizes must be > 0".to_string());
+        }
+        Ok(Tuning { d_blocks, sub_batch_size })
+    }
+
+    pub(crate) fn describe(&self) -> String {
+        let classes: Vec<String> = LENGTH_CLASSES
+            .iter()
+            .zip(&self.d_blocks)
+            .map(|(&max_len, &block)| match max_len {
+                usize::MAX => format!("long:{}", block),
+                _ => format!("<={}:{}", max_len, block),
+            })
+            .collect();
+        format!("d_block=[{}] sub_batch={}", classes.join(" "), self.sub_batch_size)
+    }
+}
+
+/// Measure the fastest block sizes on this device
+pub(crate) fn calibrate(engine: &MaxSimWasm) -> Tuning {
+    let mut tuning = Tuning::default();
+    let query = synthetic(BENCH_QUERY_TOKENS * BENCH_DIM, 1);
+    let mut similarities = Vec::new();
+
+    for (class, &doc_len) in CLASS_SAMPLE_LENGTHS.iter().enumerate() {
+        let doc = synthetic(doc_len * BENCH_DIM, 2);
+        similarities.resize(BENCH_QUERY_TOKENS * doc_len, 0.0);
+        tuning.d_blocks[class] = fastest(&BLOCK_CANDIDATES, |block| {
+            maxsim_core::matrix_multiply(&query, &doc, &mut similarities, BENCH_QUERY_TOKENS, doc_len, BENCH_DIM, block);
+        });
+    }
+
+    // 64 documents within the 20% length tolerance form one padded group
+    let doc_tokens: Vec<usize> = (0..64).map(|i| 100 + i % 16).collect();
+    let docs = synthetic(doc_tokens.iter().sum::<usize>() * BENCH_DIM, 3);
+    let mut bench = engine.scoring_snapshot();
+    tuning.sub_batch_size = fastest(&SUB_BATCH_CANDIDATES, |sub_batch_size| {
+        bench.tuning = Tuning { sub_batch_size, ..tuning };
+        let _ = bench.maxsim_batch(&query, BENCH_QUERY_TOKENS, &docs, &doc_tokens, BENCH_DIM);
+    });
+    tuning
+}
+
+// Candidate with the highest throughput of `run` (ties keep the earlier candidate)
+fn fastest(candidates: &[usize], mut run: impl FnMut(usize)) -> usize {
+    let mut best = (candidates[0], 0.0);
+    for &candidate in candidates {
+        run(candidate); // Warm-up
+        let start = clock::now_ms();
+        let mut iterations = 0;
+        while iterations == 0 || clock::now_ms() - start < MIN_RUN_MS {
+            run(candidate);
+            iterations += 1;
+        }
+        let throughput = iterations as f64 / (clock::now_ms() - start).max(1e-3);
+        if throughput > best.1 {
+            best = (candidate, throughput);
+        }
+    }
+    best.0
+}
+
+// Deterministic pseudo-random values in [-1, 1)
+pub(crate) fn synthetic(len: usize, seed: u64) -> Vec<f32> {
+    let mut rng = crate::rng::SeededRng::new(seed, 0);
+    (0..len).map(|_| (rng.next_u64() >> 40) as f32 / (1u32 << 23) as f32 - 1.0).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_matches_the_fixed_table() {
+        let tuning = Tuning::default();
+        let blocks: Vec<usize> = [1, 128, 129, 256, 300, 1024, 5000].iter().map(|&len| tuning.d_block(len)).collect();
+        assert_eq!(blocks, vec![16, 16, 12, 12, 8, 6, 4]);
+
+        // Slower candidates lose: 8 sleeps through its whole run
+        let pick = fastest(&[8, 16], |candidate| {
+            if candidate == 8 {
+                std::thread::sleep(std::time::Duration::from_millis(5));
+            }
+        });
+        assert_eq!(pick, 16);
+        assert_eq!(tuning.describe(), "d_block=[<=128:16 <=256:12 <=512:8 <=1024:6 long:4] sub_batch=16");
+    }
+}
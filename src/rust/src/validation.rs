@@ -0,0 +1,81 @@
+/*!
+ * Input validation mode
+ *
+ * Scoring never checks embedding values: one NaN in a document token turns every
+ * similarity with it into NaN, which `simd_max` silently skips or propagates, and
+ * the broken document just ranks wherever NaN happens to land. With
+ * `MaxSimWasm.set_validation(true)`, every API with an error channel scans its
+ * inputs (queries, loaded documents, per-call batches) and fails with the first
+ * offending value instead. The `*_checked` scoring APIs always validate.
+ *
+ * The scan is one pass over the input, cheap next to scoring a query but not free
+ * for bulk loads, hence opt-in.
+ */
+
+use crate::store::PreloadedDocuments;
+
+/// First non-finite value of `values`, as (token, dimension, value)
+fn first_non_finite(values: &[f32], embedding_dim: usize) -> Option<(usize, usize, f32)> {
+    let position = values.iter().position(|value| !value.is_finite())?;
+    Some((position / embedding_dim, position % embedding_dim, values[position]))
+}
+
+/// Reject query tokens holding NaN or ±Inf
+pub(crate) fn check_query_values(query_flat: &[f32], embedding_dim: usize) -> Result<(), String> {
+    match first_non_finite(query_flat, embedding_dim) {
+        Some((token, dim, value)) => Err(format!("Query token {} has {} at dimension {}", token, value, dim)),
+        None => Ok(()),
+    }
+}
+
+/// Reject documents (stored back to back) holding NaN or ±Inf
+pub(crate) fn check_document_values(doc_flat: &[f32], doc_tokens: &[usize], embedding_dim: usize) -> Result<(), String> {
+    check_documents_from(doc_flat, doc_tokens, embedding_dim, 0)
+}
+
+/// Reject a parsed corpus holding NaN or ±Inf
+pub(crate) fn check_store(docs: &PreloadedDocuments) -> Result<(), String> {
+    for page in docs.pages() {
+        check_documents_from(&page.embeddings, &page.doc_tokens, docs.embedding_dim, page.first_doc)?;
+    }
+    Ok(())
+}
+
+/// Documents numbered from `first_doc` in messages
+pub(crate) fn check_documents_from(doc_flat: &[f32], doc_tokens: &[usize], embedding_dim: usize, first_doc: usize) -> Result<(), String> {
+    let Some((token, dim, value)) = first_non_finite(doc_flat, embedding_dim) else {
+        return Ok(());
+    };
+    // Locate the document holding the flat token index
+    let mut first_token = 0;
+    for (doc, &tokens) in doc_tokens.iter().enumerate() {
+        if token < first_token + tokens {
+            return Err(format!(
+                "Document {} token {} has {} at dimension {}", first_doc + doc, token - first_token, value, dim
+            ));
+        }
+        first_token += tokens;
+    }
+    Err(format!("Token {} has {} at dimension {}", token, value, dim))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reports_the_first_non_finite_value() {
+        assert!(check_query_values(&[0.5, 1.0, -2.0, 0.0], 2).is_ok());
+        assert_eq!(check_query_values(&[0.5, 1.0, f32::NAN, 0.0], 2).unwrap_err(), "Query token 1 has NaN at dimension 0");
+
+        let docs = [0.0, 1.0, 1.0, 0.0, 0.5, f32::INFINITY];
+        assert_eq!(check_document_values(&docs, &[2, 1], 2).unwrap_err(), "Document 1 token 0 has inf at dimension 1");
+
+        let mut store = PreloadedDocuments::with_page_floats(2, 4);
+        for doc in docs.chunks(2) {
+            store.push_document(doc);
+        }
+        store.finish();
+        assert_eq!(check_store(&store).unwrap_err(), "Document 2 token 0 has inf at dimension 1");
+    }
+}
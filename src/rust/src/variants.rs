@@ -0,0 +1,75 @@
+/*!
+ * Query variants scored in one pass
+ *
+ * Multi-query and HyDE-style retrieval score several embeddings of the same
+ * information need (paraphrases, a hypothetical answer) and merge their rankings.
+ * `MaxSimWasm.search_multi_variant()` prepares every variant on its own (mask,
+ * deduplication and query limit apply per variant), stacks their tokens into one
+ * query and scans the corpus once: every document's similarity matrix covers all
+ * variants, and each variant's score is the sum of its own rows. The per-variant
+ * scores are then merged:
+ * - Max:            best variant score (a document only needs to match one variant)
+ * - Mean:           average variant score
+ * - ReciprocalRank: Σ_v 1 / (k + rank_v), with 1-based ranks and k = 60
+ *
+ * Max and Mean compare scores across variants, so variants of different lengths
+ * are best merged from normalized scores; ReciprocalRank only uses ranks.
+ */
+
+use wasm_bindgen::prelude::*;
+
+use crate::fusion::{ranks, RRF_K};
+
+/// How the scores of the query variants are merged into one per document
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum VariantAggregation {
+    #[default]
+    Max = 0,
+    Mean = 1,
+    ReciprocalRank = 2,
+}
+
+/// Merge variant-major scores (`num_docs` per variant) into one score per document
+pub(crate) fn aggregate_variants(scores: &[f32], num_docs: usize, aggregation: VariantAggregation) -> Vec<f32> {
+    let variants = scores.chunks_exact(num_docs.max(1));
+    let num_variants = variants.len();
+    match aggregation {
+        VariantAggregation::Max => variants.fold(vec![f32::NEG_INFINITY; num_docs], |mut merged, variant| {
+            merged.iter_mut().zip(variant).for_each(|(m, &s)| *m = m.max(s));
+            merged
+        }),
+        VariantAggregation::Mean => {
+            let mut merged = variants.fold(vec![0.0; num_docs], |mut merged, variant| {
+                merged.iter_mut().zip(variant).for_each(|(m, &s)| *m += s);
+                merged
+            });
+            merged.iter_mut().for_each(|m| *m /= num_variants as f32);
+            merged
+        }
+        VariantAggregation::ReciprocalRank => variants.fold(vec![0.0; num_docs], |mut merged, variant| {
+            for (m, rank) in merged.iter_mut().zip(ranks(variant)) {
+                *m += rank.map_or(0.0, |rank| 1.0 / (RRF_K + rank as f32));
+            }
+            merged
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_variant_aggregations() {
+        // Two variants over three documents
+        let scores = [3.0, 1.0, 2.0, 0.0, 4.0, 2.0];
+        assert_eq!(aggregate_variants(&scores, 3, VariantAggregation::Max), [3.0, 4.0, 2.0]);
+        assert_eq!(aggregate_variants(&scores, 3, VariantAggregation::Mean), [1.5, 2.5, 2.0]);
+        let rrf = aggregate_variants(&scores, 3, VariantAggregation::ReciprocalRank);
+        assert_eq!(rrf[0], 1.0 / 61.0 + 1.0 / 63.0);
+        assert_eq!(rrf[2], 2.0 / 62.0);
+        assert_eq!(rrf[1], rrf[0]);
+        assert!(rrf[0] > rrf[2]); // Ranks 1 and 3 beat ranks 2 and 2
+    }
+}
@@ -0,0 +1,137 @@
+/*!
+ * Watchdog for pathological batching cases
+ *
+ * The variable-length batch path is only fast when documents group well. Badly
+ * chunked corpora silently degrade it; the watchdog inspects the length groups of
+ * every variable-length batch of a search together (across all its pages) and
+ * reports:
+ * - `padding_waste`:     a padded group wastes more than `max_padding_waste` of its buffer
+ * - `dominant_document`: a document is more than `max_length_ratio` × the median length,
+ *   so the scratch buffers grow to (and stay at) its size
+ * - `collapsed_groups`:  more than `max_collapsed_fraction` of the documents end up in
+ *   groups of one, i.e. are scored individually
+ *
+ * Findings go to the trace (when enabled) and to the telemetry callback as
+ * `(kind, doc_indices: Uint32Array, value)`.
+ */
+
+use js_sys::{Function, Uint32Array};
+use wasm_bindgen::prelude::*;
+
+/// One pathological case, with the caller-visible indices of the documents involved
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Finding {
+    pub(crate) kind: &'static str,
+    pub(crate) docs: Vec<usize>,
+    pub(crate) value: f32, // Waste fraction, length ratio or collapsed fraction
+}
+
+/// A length group of a variable-length batch
+pub(crate) struct Group {
+    pub(crate) doc_ids: Vec<usize>,
+    pub(crate) lengths: Vec<usize>,
+    pub(crate) padded: bool, // Scored as one padded batch (false: individually)
+}
+
+#[derive(Clone)]
+pub(crate) struct Watchdog {
+    pub(crate) callback: Option<Function>,
+    pub(crate) max_padding_waste: f32,
+    pub(crate) max_length_ratio: f32,
+    pub(crate) max_collapsed_fraction: f32,
+}
+
+impl Default for Watchdog {
+    fn default() -> Self {
+        Watchdog { callback: None, max_padding_waste: 0.1, max_length_ratio: 8.0, max_collapsed_fraction: 0.5 }
+    }
+}
+
+impl Watchdog {
+    /// Inspect the groups of one search (ascending length order within each batch)
+    pub(crate) fn inspect(&self, groups: &[Group]) -> Vec<Finding> {
+        let mut findings = Vec::new();
+
+        for group in groups.iter().filter(|g| g.padded) {
+            let max_len = group.lengths.iter().copied().max().unwrap_or(0);
+            let capacity = group.lengths.len() * max_len;
+            if capacity == 0 {
+                continue;
+            }
+            let waste = 1.0 - group.lengths.iter().sum::<usize>() as f32 / capacity as f32;
+            if waste > self.max_padding_waste {
+                findings.push(Finding { kind: "padding_waste", docs: group.doc_ids.clone(), value: waste });
+            }
+        }
+
+        let mut lengths: Vec<(usize, usize)> = groups
+            .iter()
+            .flat_map(|g| g.doc_ids.iter().copied().zip(g.lengths.iter().copied()))
+            .collect();
+        lengths.sort_by_key(|&(_, len)| len);
+        let median = lengths.get(lengths.len() / 2).map_or(0, |&(_, len)| len);
+        if median > 0 {
+            for &(doc, len) in lengths.iter().rev() {
+                let ratio = len as f32 / median as f32;
+                if ratio <= self.max_length_ratio {
+                    break;
+                }
+                findings.push(Finding { kind: "dominant_document", docs: vec![doc], value: ratio });
+            }
+        }
+
+        let collapsed: Vec<usize> = groups
+            .iter()
+            .filter(|g| g.lengths.len() == 1 && g.lengths[0] > 0)
+            .map(|g| g.doc_ids[0])
+            .collect();
+        let fraction = collapsed.len() as f32 / lengths.len().max(1) as f32;
+        if collapsed.len() > 1 && fraction > self.max_collapsed_fraction {
+            findings.push(Finding { kind: "collapsed_groups", docs: collapsed, value: fraction });
+        }
+
+        findings
+    }
+
+    /// Send findings to the telemetry callback (if any)
+    pub(crate) fn report(&self, findings: &[Finding]) -> Result<(), JsValue> {
+        let Some(callback) = &self.callback else {
+            return Ok(());
+        };
+        for finding in findings {
+            let docs: Vec<u32> = finding.docs.iter().map(|&d| d as u32).collect();
+            callback.call3(
+                &JsValue::NULL,
+                &JsValue::from_str(finding.kind),
+                &Uint32Array::from(&docs[..]),
+                &JsValue::from_f64(finding.value as f64),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reports_each_pathology() {
+        let watchdog = Watchdog::default();
+        let mut groups = vec![Group { doc_ids: vec![0, 1, 2, 3], lengths: vec![8, 8, 8, 4], padded: true }];
+        for (i, len) in [20, 30, 40, 50, 200].into_iter().enumerate() {
+            groups.push(Group { doc_ids: vec![4 + i], lengths: vec![len], padded: false });
+        }
+
+        let findings = watchdog.inspect(&groups);
+        let kinds: Vec<&str> = findings.iter().map(|f| f.kind).collect();
+        assert_eq!(kinds, vec!["padding_waste", "dominant_document", "collapsed_groups"]);
+        assert!((findings[0].value - 0.125).abs() < 1e-6);
+        assert_eq!(findings[1].docs, vec![8]); // 200 tokens vs a median of 20
+        assert_eq!(findings[2].docs, vec![4, 5, 6, 7, 8]);
+
+        // Well-grouped batches report nothing
+        let uniform = Group { doc_ids: vec![0, 1, 2, 3], lengths: vec![11, 11, 12, 12], padded: true };
+        assert!(watchdog.inspect(&[uniform]).is_empty());
+    }
+}
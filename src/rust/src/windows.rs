@@ -0,0 +1,101 @@
+/*!
+ * Sliding-window scores within one document
+ *
+ * `MaxSimWasm.maxsim_windows()` scores every window of `window_tokens` consecutive
+ * tokens of a stored document, starting every `stride` tokens, against the query:
+ * the MaxSim the window would get as a document of its own. The query × document
+ * similarities are computed once and every window reduces its columns, so the cost
+ * is one document scoring plus `query_tokens × window_tokens` per window. The best
+ * window anchors a snippet on the region that matches the query best.
+ *
+ * The last window is aligned to the end of the document when the stride doesn't
+ * land there, so every token is covered; a document shorter than one window has a
+ * single window over all of it.
+ */
+
+use wasm_bindgen::prelude::*;
+
+/// Scores of a document's windows, in token order
+#[wasm_bindgen]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct WindowScores {
+    offsets: Vec<u32>,
+    scores: Vec<f32>,
+    window_tokens: usize,
+}
+
+#[wasm_bindgen]
+impl WindowScores {
+    /// First token of every window (Uint32Array)
+    #[wasm_bindgen(getter)]
+    pub fn offsets(&self) -> Vec<u32> {
+        self.offsets.clone()
+    }
+
+    /// Score of every window, aligned with `offsets`
+    #[wasm_bindgen(getter)]
+    pub fn scores(&self) -> Vec<f32> {
+        self.scores.clone()
+    }
+
+    /// Tokens per window (the document length when it is shorter)
+    #[wasm_bindgen(getter)]
+    pub fn window_tokens(&self) -> usize {
+        self.window_tokens
+    }
+
+    /// First token of the best-scoring window (the earliest on ties)
+    #[wasm_bindgen(getter)]
+    pub fn best_offset(&self) -> u32 {
+        self.best().map_or(0, |i| self.offsets[i])
+    }
+
+    /// Score of the best window (NaN for an empty document)
+    #[wasm_bindgen(getter)]
+    pub fn best_score(&self) -> f32 {
+        self.best().map_or(f32::NAN, |i| self.scores[i])
+    }
+}
+
+impl WindowScores {
+    /// Windows of a document of `doc_tokens` tokens, scored by `score(start, len)`
+    pub(crate) fn scan(doc_tokens: usize, window_tokens: usize, stride: usize, mut score: impl FnMut(usize, usize) -> f32) -> Self {
+        let window_tokens = window_tokens.min(doc_tokens);
+        let mut windows = WindowScores { window_tokens, ..Default::default() };
+        if doc_tokens == 0 {
+            return windows;
+        }
+        let last = doc_tokens - window_tokens;
+        let mut starts: Vec<usize> = (0..=last).step_by(stride).collect();
+        if starts.last() != Some(&last) {
+            starts.push(last);
+        }
+        for start in starts {
+            windows.offsets.push(start as u32);
+            windows.scores.push(score(start, window_tokens));
+        }
+        windows
+    }
+
+    fn best(&self) -> Option<usize> {
+        (0..self.scores.len()).reduce(|best, i| if self.scores[i] > self.scores[best] { i } else { best })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_windows_cover_the_document_and_pick_the_best() {
+        let windows = WindowScores::scan(10, 4, 3, |start, len| (start + len) as f32 * if start == 3 { 2.0 } else { 1.0 });
+        assert_eq!(windows.offsets(), [0, 3, 6]);
+        assert_eq!((windows.best_offset(), windows.best_score()), (3, 14.0));
+
+        // Stride not landing on the end: a last window aligned to it
+        assert_eq!(WindowScores::scan(10, 4, 4, |_, _| 0.0).offsets(), [0, 4, 6]);
+        let short = WindowScores::scan(3, 8, 2, |_, len| len as f32);
+        assert_eq!((short.offsets(), short.scores(), short.window_tokens()), (vec![0], vec![3.0], 3));
+        assert!(WindowScores::scan(0, 4, 2, |_, _| 1.0).best_score().is_nan());
+    }
+}